@@ -31,13 +31,16 @@ pub fn canonicalize_commit(s: &str) -> anyhow::Result<String> {
     Ok(str)
 }
 
+#[allow(dead_code)]
 pub const GREY: &str = "\x1b[90m";
 pub const GREEN: &str = "\x1b[32m";
 pub const BLUE: &str = "\x1b[34m";
 pub const YELLOW: &str = "\x1b[33m";
+#[allow(dead_code)]
 pub const RED: &str = "\x1b[31m";
 pub const RESET: &str = "\x1b[0m";
 pub const BOLD: &str = "\x1b[1m";
+#[allow(dead_code)]
 pub const RESET_BOLD: &str = "\x1b[22m";
 #[macro_export]
 macro_rules! info {
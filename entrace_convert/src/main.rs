@@ -2,7 +2,7 @@ use anyhow::Context;
 use clap::Parser;
 use entrace_core::{EN_DISK_VERSION, convert};
 use fs_err::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read};
+use std::io::{BufReader, BufWriter, Read, Write};
 #[derive(clap::Parser)]
 pub struct Args {
     #[command(subcommand)]
@@ -28,6 +28,11 @@ pub struct ConvertArgs {
     /// Output format
     #[arg(short = 'f', long)]
     out_format: StorageFormat,
+
+    /// Save progress to `<output>.progress`, and continue from it if it exists.
+    /// Only supported for ET -> IET.
+    #[arg(long)]
+    resume: bool,
 }
 
 #[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq)]
@@ -47,6 +52,10 @@ impl StorageFormat {
 const SUPPORTED_CONVERSIONS: &[(u8, StorageFormat, u8, StorageFormat)] = &[
     (1, StorageFormat::IET, EN_DISK_VERSION, StorageFormat::IET),
     (1, StorageFormat::ET, EN_DISK_VERSION, StorageFormat::ET),
+    (2, StorageFormat::IET, EN_DISK_VERSION, StorageFormat::IET),
+    (2, StorageFormat::ET, EN_DISK_VERSION, StorageFormat::ET),
+    (3, StorageFormat::IET, EN_DISK_VERSION, StorageFormat::IET),
+    (3, StorageFormat::ET, EN_DISK_VERSION, StorageFormat::ET),
     (EN_DISK_VERSION, StorageFormat::IET, EN_DISK_VERSION, StorageFormat::ET),
     (EN_DISK_VERSION, StorageFormat::ET, EN_DISK_VERSION, StorageFormat::IET),
 ];
@@ -65,11 +74,37 @@ fn main() -> anyhow::Result<()> {
             let mut reader = BufReader::new(input);
             let mut magic_buf = [0; 10];
             reader.read_exact(&mut magic_buf).context("failed to read magic")?;
-            let (in_version, format) =
+            let (in_version, format, _attr_encoding) =
                 entrace_core::parse_entrace_magic(&magic_buf).context("failed to parse magic")?;
             let format = StorageFormat::from_entrace(format)?;
             let out_format = convert_args.out_format;
 
+            if convert_args.resume {
+                if (in_version, format, out_format)
+                    != (EN_DISK_VERSION, StorageFormat::ET, StorageFormat::IET)
+                {
+                    return Err(anyhow::anyhow!("--resume is only supported for ET -> IET"));
+                }
+                let mut checkpoint = convert_args.output.clone().into_os_string();
+                checkpoint.push(".progress");
+                // keep what's already converted
+                let out_file = OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(&convert_args.output)?;
+                let mut out_writer = BufWriter::new(out_file);
+                convert::et_to_iet_resumable(
+                    &mut reader,
+                    &mut out_writer,
+                    checkpoint.as_ref(),
+                    1 << 16,
+                    |x| eprintln!("converted {}/{} entries", x.entries_written, x.entries_total),
+                )
+                .context("Conversion failed")?;
+                return Ok(());
+            }
+
             let out_file = File::create(&convert_args.output)?;
             let mut out_writer = BufWriter::new(out_file);
             match (in_version, format, out_format) {
@@ -90,6 +125,35 @@ fn main() -> anyhow::Result<()> {
                         .context("Conversion failed")?;
                     fs_err::remove_file(&tmp_path)?;
                 }
+                (2, format, _) if format == out_format => {
+                    let tmp_path = convert_args.output.with_extension("tmp");
+                    let mut tmp = OpenOptions::new()
+                        .create(true)
+                        .truncate(true)
+                        .write(true)
+                        .read(true)
+                        .open(&tmp_path)?;
+                    let mut tmp_writer = BufWriter::new(&mut tmp);
+                    match format {
+                        StorageFormat::IET => {
+                            convert::iet_v2_to_v3(&mut reader, &mut tmp_writer, true)
+                        }
+                        StorageFormat::ET => {
+                            convert::et_v2_to_v3(&mut reader, &mut tmp_writer, true)
+                        }
+                    }
+                    .context("Conversion failed")?;
+                    tmp_writer.flush()?;
+                    drop(tmp_writer);
+                    convert::v3_to_v4(&mut BufReader::new(&mut tmp), &mut out_writer, true)
+                        .context("Conversion failed")?;
+                    fs_err::remove_file(&tmp_path)?;
+                }
+                (3, StorageFormat::IET, StorageFormat::IET)
+                | (3, StorageFormat::ET, StorageFormat::ET) => {
+                    convert::v3_to_v4(&mut reader, &mut out_writer, true)
+                        .context("Conversion failed")?;
+                }
                 (EN_DISK_VERSION, StorageFormat::IET, StorageFormat::ET) => {
                     convert::iet_to_et(&mut reader, &mut out_writer, true, false)
                         .context("Conversion failed")?;
@@ -0,0 +1,77 @@
+//! The `bursts` workload of the example, streamed to a [RemoteLogProvider] over TCP, with the
+//! viewer calling `frame_callback` at 60 fps like the GUI does.
+//!
+//! For every burst, reports the latency from the last event being traced to it showing up in the
+//! provider, and the throughput from the first event being traced to the last one showing up.
+//! Run with `cargo bench -p entrace_core --bench bursts`.
+use std::{
+    net::TcpListener,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use entrace_core::{
+    IETPresentationConfig, LogProvider, TreeLayer,
+    remote::{IETStorage, IETStorageConfig, RemoteLogProvider},
+};
+use tracing::{info, warn};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+const BURSTS: usize = 10;
+const BURST_LEN: usize = 10_000;
+const PAUSE: Duration = Duration::from_millis(200);
+const FRAME: Duration = Duration::from_millis(16);
+
+/// When the traced process started and finished tracing a burst.
+struct BurstTimes {
+    start: Instant,
+    end: Instant,
+}
+
+fn main() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (times_tx, times) = crossbeam_channel::unbounded();
+    let traced = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let storage = Arc::new(IETStorage::init(IETStorageConfig::length_prefixed(stream)));
+        let subscriber = Registry::default().with(TreeLayer::from_storage(storage.clone()));
+        let _guard = subscriber.set_default();
+        let mut total = 0;
+        for burst_no in 0..BURSTS {
+            let start = Instant::now();
+            for local in 0..BURST_LEN {
+                info!(total, local, burst_no, "Message {total}");
+                total += 1;
+            }
+            warn!(burst_no, len = BURST_LEN, "Burst done");
+            times_tx.send(BurstTimes { start, end: Instant::now() }).unwrap();
+            std::thread::sleep(PAUSE);
+        }
+        storage.finish().unwrap();
+    });
+
+    let mut provider = RemoteLogProvider::connect(addr, IETPresentationConfig::default());
+    println!("{BURSTS} bursts of {} events, frame every {FRAME:?}", BURST_LEN + 1);
+    println!("{:>5} {:>12} {:>16}", "burst", "latency", "events/s");
+    let mut latencies = vec![];
+    for burst_no in 0..BURSTS {
+        let BurstTimes { start, end } = times.recv().unwrap();
+        // the root, and the events of the bursts so far
+        let expected = 1 + (burst_no + 1) * (BURST_LEN + 1);
+        while provider.len() < expected {
+            provider.frame_callback();
+            std::thread::sleep(FRAME);
+        }
+        let seen = Instant::now();
+        let latency = seen - end;
+        let throughput = (BURST_LEN + 1) as f64 / (seen - start).as_secs_f64();
+        println!("{burst_no:>5} {:>12} {throughput:>16.0}", format!("{latency:.1?}"));
+        latencies.push(latency);
+    }
+    traced.join().unwrap();
+    latencies.sort();
+    let median = latencies[latencies.len() / 2];
+    let max = latencies[latencies.len() - 1];
+    println!("latency median {median:.1?}, max {max:.1?}");
+}
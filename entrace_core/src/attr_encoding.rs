@@ -0,0 +1,337 @@
+use std::io::{ErrorKind, Read, Write};
+
+use bincode::{
+    de::BorrowDecode,
+    error::{DecodeError, EncodeError},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    FOLLOWS_FROM_MARKER, FrameError, IETRecord, LengthPrefixError, MetadataContainer,
+    MetadataRefContainer, MixedTraceEntry, TraceEntry, TraceEntryRef,
+    tree_layer::{EnValue, EnValueRef},
+};
+
+const CFG: bincode::config::Configuration = crate::BINCODE_CONFIG;
+
+/// How the attribute values of [TraceEntry]-es are laid out in a file.
+///
+/// The encoding is recorded in the storage format byte of the magic, see [entrace_magic_with](crate::entrace_magic_with).
+/// Everything except the attribute values is encoded identically in both encodings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AttrEncoding {
+    /// Every value is a bincode-encoded [EnValue].
+    #[default]
+    Standard,
+    /// Values are packed into a single byte string, with small integers and strings repeating the
+    /// message of the span taking a single byte.
+    ///
+    /// Each value starts with a tag byte:
+    /// - `0..=8`: `String`, `Bytes`, `false`, `true`, `Float`, `U64`, `I64`, `U128`, `I128`,
+    ///   followed by the bincode encoding of the payload (if any).
+    /// - `9`: a string equal to the message of the span, no payload.
+    /// - `10..=254`: `U64(tag - 10)`, no payload.
+    /// - `255`: [EnValue::Debug], followed by the bincode encoding of the string. Before version 4,
+    ///   this was `U64(245)`, see [crate::convert::v3_to_v4].
+    Compact,
+}
+
+const TAG_STRING: u8 = 0;
+const TAG_BYTES: u8 = 1;
+const TAG_FALSE: u8 = 2;
+const TAG_TRUE: u8 = 3;
+const TAG_FLOAT: u8 = 4;
+const TAG_U64: u8 = 5;
+const TAG_I64: u8 = 6;
+const TAG_U128: u8 = 7;
+const TAG_I128: u8 = 8;
+const TAG_MESSAGE: u8 = 9;
+const TAG_SMALL_U64: u8 = 10;
+const TAG_DEBUG: u8 = u8::MAX;
+const SMALL_U64_MAX: u64 = (TAG_DEBUG - 1 - TAG_SMALL_U64) as u64;
+
+/// The on-disk form of an entry in the [AttrEncoding::Compact] encoding.
+///
+/// The canonical order of the fields here is the same as for [TraceEntry].
+#[derive(Serialize)]
+struct CompactEntryOut<'a, M: Serialize> {
+    parent: u32,
+    message: Option<&'a str>,
+    metadata: M,
+    attr_names: &'a [String],
+    attr_values: &'a [u8],
+    follows_from: &'a [u32],
+}
+#[derive(Deserialize)]
+struct CompactEntryIn {
+    parent: u32,
+    message: Option<String>,
+    metadata: MetadataContainer,
+    attr_names: Vec<String>,
+    attr_values: Vec<u8>,
+    follows_from: Vec<u32>,
+}
+#[derive(Deserialize)]
+struct CompactEntryRef<'a> {
+    #[allow(dead_code)]
+    parent: u32,
+    message: Option<&'a str>,
+    #[serde(borrow)]
+    #[allow(dead_code)]
+    metadata: MetadataRefContainer<'a>,
+    attr_names: Vec<&'a str>,
+    attr_values: &'a [u8],
+    follows_from: Vec<u32>,
+}
+
+impl AttrEncoding {
+    /// Write `entry` in this encoding. Returns the number of bytes written.
+    pub fn encode_entry<W: Write>(
+        self, entry: &TraceEntry, writer: &mut W,
+    ) -> Result<usize, EncodeError> {
+        match self {
+            AttrEncoding::Standard => bincode::serde::encode_into_std_write(entry, writer, CFG),
+            AttrEncoding::Compact => {
+                let message = entry.message.as_deref();
+                let attr_values = encode_compact_values(&entry.attr_values, message)?;
+                let out = CompactEntryOut {
+                    parent: entry.parent,
+                    message,
+                    metadata: &entry.metadata,
+                    attr_names: &entry.attr_names,
+                    attr_values: &attr_values,
+                    follows_from: &entry.follows_from,
+                };
+                bincode::serde::encode_into_std_write(out, writer, CFG)
+            }
+        }
+    }
+
+    /// Like [AttrEncoding::encode_entry], but for a [MixedTraceEntry]. The output is the same as
+    /// for the equivalent [TraceEntry].
+    pub fn encode_mixed_entry<W: Write>(
+        self, entry: &MixedTraceEntry, writer: &mut W,
+    ) -> Result<usize, EncodeError> {
+        match self {
+            AttrEncoding::Standard => bincode::serde::encode_into_std_write(entry, writer, CFG),
+            AttrEncoding::Compact => {
+                let message = entry.message.as_deref();
+                let attr_values = encode_compact_values(&entry.attr_values, message)?;
+                let out = CompactEntryOut {
+                    parent: entry.parent,
+                    message,
+                    metadata: &entry.metadata,
+                    attr_names: &entry.attr_names,
+                    attr_values: &attr_values,
+                    follows_from: &entry.follows_from,
+                };
+                bincode::serde::encode_into_std_write(out, writer, CFG)
+            }
+        }
+    }
+
+    /// Write a record of an IET trace in this encoding, see [IETRecord]. Returns the number of
+    /// bytes written.
+    pub fn encode_record<W: Write>(
+        self, record: &IETRecord, writer: &mut W,
+    ) -> Result<usize, EncodeError> {
+        match record {
+            IETRecord::Entry(entry) => self.encode_entry(entry, writer),
+            IETRecord::FollowsFrom { id, follows } => {
+                encode_follows_from_record(*id, *follows, writer)
+            }
+        }
+    }
+
+    /// Read an entry written in this encoding.
+    pub fn decode_entry<R: Read>(self, reader: &mut R) -> Result<TraceEntry, DecodeError> {
+        match self {
+            AttrEncoding::Standard => bincode::serde::decode_from_std_read(reader, CFG),
+            AttrEncoding::Compact => decode_compact_entry(reader, true),
+        }
+    }
+    /// Read a record of an IET trace written in this encoding, see [IETRecord].
+    pub fn decode_record<R: Read>(self, reader: &mut R) -> Result<IETRecord, DecodeError> {
+        let mut first = [0];
+        if let Err(inner) = reader.read_exact(&mut first) {
+            return Err(DecodeError::Io { inner, additional: 1 });
+        }
+        if first[0] == FOLLOWS_FROM_MARKER {
+            let (id, follows) = bincode::serde::decode_from_std_read(reader, CFG)?;
+            return Ok(IETRecord::FollowsFrom { id, follows });
+        }
+        self.decode_entry(&mut first.as_slice().chain(reader)).map(IETRecord::Entry)
+    }
+    /// Decode the record in a frame of a length-prefixed trace. The record has to fill the frame
+    /// exactly, otherwise the producer most likely doesn't write the length prefixes the header
+    /// promises, and this is [LengthPrefixError::FrameMismatch] instead of a desynced stream.
+    pub fn decode_framed_record(self, frame: &[u8]) -> Result<IETRecord, FrameError> {
+        let mismatch = LengthPrefixError::FrameMismatch { len: frame.len() as u64 };
+        let mut rest = frame;
+        match self.decode_record(&mut rest) {
+            Ok(record) if rest.is_empty() => Ok(record),
+            Ok(_) => Err(mismatch.into()),
+            // the record goes on after the frame
+            Err(DecodeError::UnexpectedEnd { .. }) => Err(mismatch.into()),
+            Err(DecodeError::Io { inner, .. }) if inner.kind() == ErrorKind::UnexpectedEof => {
+                Err(mismatch.into())
+            }
+            Err(y) => Err(y.into()),
+        }
+    }
+
+    /// Decode only the attribute values of the entry at the start of `bytes`, borrowing from it
+    /// where possible.
+    pub fn borrow_decode_attr_values<'a>(
+        self, bytes: &'a [u8],
+    ) -> Result<Vec<EnValueRef<'a>>, DecodeError> {
+        match self {
+            AttrEncoding::Standard => {
+                let (entry, _): (TraceEntryRef, _) =
+                    bincode::serde::borrow_decode_from_slice(bytes, CFG)?;
+                Ok(entry.attr_values)
+            }
+            AttrEncoding::Compact => {
+                let (entry, _): (CompactEntryRef, _) =
+                    bincode::serde::borrow_decode_from_slice(bytes, CFG)?;
+                decode_compact_values(
+                    entry.attr_values,
+                    entry.attr_names.len(),
+                    entry.message,
+                    true,
+                )
+            }
+        }
+    }
+
+    /// Decode only the follows-from links of the entry at the start of `bytes`.
+    pub fn decode_follows_from(self, bytes: &[u8]) -> Result<Vec<u32>, DecodeError> {
+        match self {
+            AttrEncoding::Standard => {
+                let (entry, _): (TraceEntryRef, _) =
+                    bincode::serde::borrow_decode_from_slice(bytes, CFG)?;
+                Ok(entry.follows_from.into_owned())
+            }
+            AttrEncoding::Compact => {
+                let (entry, _): (CompactEntryRef, _) =
+                    bincode::serde::borrow_decode_from_slice(bytes, CFG)?;
+                Ok(entry.follows_from)
+            }
+        }
+    }
+}
+
+/// Write a follows-from link as its own record of an IET trace, see [IETRecord::FollowsFrom].
+/// It is the same in every attribute encoding. Returns the number of bytes written.
+pub fn encode_follows_from_record<W: Write>(
+    id: u32, follows: u32, writer: &mut W,
+) -> Result<usize, EncodeError> {
+    writer
+        .write_all(&[FOLLOWS_FROM_MARKER])
+        .map_err(|inner| EncodeError::Io { inner, index: 0 })?;
+    Ok(1 + bincode::serde::encode_into_std_write((id, follows), writer, CFG)?)
+}
+
+fn encode_compact_values(
+    values: &[EnValue], message: Option<&str>,
+) -> Result<Vec<u8>, EncodeError> {
+    let mut out = Vec::with_capacity(values.len() * 2);
+    for value in values {
+        match value {
+            EnValue::String(s) if Some(s.as_str()) == message => out.push(TAG_MESSAGE),
+            EnValue::String(s) => {
+                out.push(TAG_STRING);
+                bincode::encode_into_std_write(s.as_str(), &mut out, CFG)?;
+            }
+            EnValue::Debug(s) => {
+                out.push(TAG_DEBUG);
+                bincode::encode_into_std_write(s.as_str(), &mut out, CFG)?;
+            }
+            EnValue::Bytes(b) => {
+                out.push(TAG_BYTES);
+                bincode::encode_into_std_write(b.as_slice(), &mut out, CFG)?;
+            }
+            EnValue::Bool(false) => out.push(TAG_FALSE),
+            EnValue::Bool(true) => out.push(TAG_TRUE),
+            EnValue::Float(f) => {
+                out.push(TAG_FLOAT);
+                bincode::encode_into_std_write(f, &mut out, CFG)?;
+            }
+            EnValue::U64(x) if *x <= SMALL_U64_MAX => out.push(TAG_SMALL_U64 + *x as u8),
+            EnValue::U64(x) => {
+                out.push(TAG_U64);
+                bincode::encode_into_std_write(x, &mut out, CFG)?;
+            }
+            EnValue::I64(x) => {
+                out.push(TAG_I64);
+                bincode::encode_into_std_write(x, &mut out, CFG)?;
+            }
+            EnValue::U128(x) => {
+                out.push(TAG_U128);
+                bincode::encode_into_std_write(x, &mut out, CFG)?;
+            }
+            EnValue::I128(x) => {
+                out.push(TAG_I128);
+                bincode::encode_into_std_write(x, &mut out, CFG)?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Read an entry in the [AttrEncoding::Compact] encoding. Without `debug_tag`, [TAG_DEBUG] is a
+/// small integer, like it was before version 4.
+fn decode_compact_entry<R: Read>(
+    reader: &mut R, debug_tag: bool,
+) -> Result<TraceEntry, DecodeError> {
+    let CompactEntryIn { parent, message, metadata, attr_names, attr_values, follows_from } =
+        bincode::serde::decode_from_std_read(reader, CFG)?;
+    let values =
+        decode_compact_values(&attr_values, attr_names.len(), message.as_deref(), debug_tag)?;
+    let values = values.iter().map(|x| x.to_owned()).collect();
+    Ok(TraceEntry::from_sorted_attrs(parent, message, metadata, attr_names, values)
+        .with_follows_from(follows_from))
+}
+
+/// Read an entry of a version 3 file in the [AttrEncoding::Compact] encoding, where [TAG_DEBUG]
+/// was still a small integer, for [crate::convert::v3_to_v4].
+pub(crate) fn decode_compact_v3_entry<R: Read>(reader: &mut R) -> Result<TraceEntry, DecodeError> {
+    decode_compact_entry(reader, false)
+}
+
+fn decode_compact_values<'a>(
+    mut bytes: &'a [u8], count: usize, message: Option<&'a str>, debug_tag: bool,
+) -> Result<Vec<EnValueRef<'a>>, DecodeError> {
+    fn take<'a, T: BorrowDecode<'a, ()>>(bytes: &mut &'a [u8]) -> Result<T, DecodeError> {
+        let (value, len) = bincode::borrow_decode_from_slice(bytes, CFG)?;
+        *bytes = &bytes[len..];
+        Ok(value)
+    }
+    let mut values = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (&tag, rest) =
+            bytes.split_first().ok_or(DecodeError::UnexpectedEnd { additional: 1 })?;
+        bytes = rest;
+        let value = match tag {
+            TAG_STRING => EnValueRef::String(take(&mut bytes)?),
+            TAG_BYTES => EnValueRef::Bytes(take(&mut bytes)?),
+            TAG_FALSE => EnValueRef::Bool(false),
+            TAG_TRUE => EnValueRef::Bool(true),
+            TAG_FLOAT => EnValueRef::Float(take(&mut bytes)?),
+            TAG_U64 => EnValueRef::U64(take(&mut bytes)?),
+            TAG_I64 => EnValueRef::I64(take(&mut bytes)?),
+            TAG_U128 => EnValueRef::U128(take(&mut bytes)?),
+            TAG_I128 => EnValueRef::I128(take(&mut bytes)?),
+            TAG_MESSAGE => EnValueRef::String(message.ok_or(DecodeError::Other(
+                "Compact attribute refers to the message, but the span has none",
+            ))?),
+            TAG_DEBUG if debug_tag => EnValueRef::Debug(take(&mut bytes)?),
+            small => EnValueRef::U64((small - TAG_SMALL_U64) as u64),
+        };
+        values.push(value);
+    }
+    if !bytes.is_empty() {
+        return Err(DecodeError::Other("Trailing bytes after compact attribute values"));
+    }
+    Ok(values)
+}
@@ -1,11 +1,17 @@
-use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
 
 use bincode::config::Configuration;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    EN_DISK_VERSION, EnValue, MagicParseError, MetadataContainer, PoolEntry, StorageFormat,
-    TraceEntry, entrace_magic_for, parse_entrace_magic,
+    AttrEncoding, EN_DISK_VERSION, EnValue, FrameError, IETRecord, LengthPrefixError, LogProvider,
+    LogProviderError, MagicParseError, MetadataContainer, PoolEntry, StorageFormat, TraceEntry,
+    check_parent_order, decode_length_prefix, encode_length_prefix, entrace_magic_for,
+    entrace_magic_with, parse_entrace_magic, read_frame_into,
 };
 
 #[derive(thiserror::Error, Debug)]
@@ -30,6 +36,32 @@ pub enum ConvertError {
     InputVersionMismatch(u8, u8),
     #[error("Input file has format {0:?}, but I'm told to convert from format {1:?}")]
     InputFormatMismatch(StorageFormat, StorageFormat),
+    #[error(transparent)]
+    LengthPrefix(#[from] LengthPrefixError),
+    #[error("Failed to read or write the checkpoint file")]
+    CheckpointIoError(#[source] std::io::Error),
+    #[error("Invalid checkpoint file: {0}")]
+    BadCheckpoint(String),
+    #[error("The checkpoint file is for a different input ({0})")]
+    CheckpointMismatch(&'static str),
+    #[error("A follows-from record names entry {0}, which is not in the trace")]
+    NoSuchEntry(u32),
+    #[error("Failed to read span {id} of the trace")]
+    ReadSpan {
+        id: u32,
+        #[source]
+        inner: LogProviderError,
+    },
+}
+
+/// Read the magic at the start of `inp` and return the attribute encoding it declares.
+/// Leaves `inp` positioned right after the magic.
+fn read_attr_encoding<R: Read + Seek>(inp: &mut R) -> Result<AttrEncoding, ConvertError> {
+    let mut magic = [0; 10];
+    inp.seek(std::io::SeekFrom::Start(0)).map_err(ConvertError::ReadInputError)?;
+    inp.read_exact(&mut magic).map_err(ConvertError::ReadInputError)?;
+    let (_version, _format, attr_encoding) = parse_entrace_magic(&magic)?;
+    Ok(attr_encoding)
 }
 
 /// Convert an IET file to a ET file.
@@ -37,6 +69,9 @@ pub enum ConvertError {
 /// Somewhat slow, as this will parse the whole file, in order to find where the offsets are.
 /// It is the callers responsibility to buffer IO if desired.
 ///
+/// When `skip_magic` is set, the attribute encoding of the input is read from its magic and kept
+/// in the output. Otherwise, the input is assumed to use [AttrEncoding::Standard].
+///
 /// See also: [iet_to_et_with_table], [gather_iet_table_data]
 pub fn iet_to_et<R: Read + Seek, W: Write>(
     inp: &mut R, out: &mut W, skip_magic: bool, length_prefixed: bool,
@@ -50,73 +85,97 @@ pub fn iet_to_et<R: Read + Seek, W: Write>(
 pub fn gather_iet_table_data<R: Read + Seek>(
     inp: &mut R, skip_magic: bool, length_prefixed: bool,
 ) -> Result<IETTableData, ConvertError> {
-    if skip_magic {
-        inp.seek(std::io::SeekFrom::Start(10)).map_err(ReadInputError)?;
-    }
-    let config = bincode::config::standard();
+    let attr_encoding = if skip_magic { read_attr_encoding(inp)? } else { AttrEncoding::Standard };
     let mut pool: Vec<PoolEntry> = vec![];
     let mut offsets = vec![];
-    let mut had_root = false;
+    let mut lengths = vec![];
+    let mut follows_from: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
     let extra_offset = if skip_magic { 10 } else { 0 };
     use ConvertError::*;
+    let mut frame = vec![];
     loop {
-        if length_prefixed {
+        let (offset, record) = if length_prefixed {
             let mut cl_buf = [0; 8];
-            if let Err(y) = inp.read_exact(&mut cl_buf) {
-                match y.kind() {
-                    std::io::ErrorKind::UnexpectedEof => break,
-                    _ => return Err(ReadInputError(y)),
-                }
+            match inp.read_exact(&mut cl_buf) {
+                Ok(()) => (),
+                Err(y) if y.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(y) => return Err(ReadInputError(y)),
             }
-            let _content_len = u64::from_le_bytes(cl_buf);
-        }
-        let pl = pool.len() as u32;
-        let offset = inp
-            .stream_position()
-            .map_err(ConvertError::ReadInputError)?
-            .saturating_sub(extra_offset);
-        let decoded: Result<TraceEntry, _> = bincode::serde::decode_from_std_read(inp, config);
-        match decoded {
-            Ok(x) => {
-                offsets.push(offset);
-                pool.push(PoolEntry::new());
-                if had_root {
-                    pool[x.parent as usize].children.push(pl);
-                }
-                had_root = true;
+            let len = decode_length_prefix(cl_buf)?;
+            let offset = inp.stream_position().map_err(ReadInputError)?;
+            match read_frame_into(inp, len, &mut frame) {
+                Ok(()) => (),
+                Err(y) if y.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(y) => return Err(ReadInputError(y)),
             }
-            Err(y) => match y {
-                bincode::error::DecodeError::Io { inner, .. }
+            match attr_encoding.decode_framed_record(&frame) {
+                Ok(x) => (offset, x),
+                Err(FrameError::Decode(y)) => return Err(DecodeError(y)),
+                Err(FrameError::Prefix(y)) => return Err(y.into()),
+            }
+        } else {
+            let offset = inp.stream_position().map_err(ReadInputError)?;
+            match attr_encoding.decode_record(inp) {
+                Ok(x) => (offset, x),
+                Err(bincode::error::DecodeError::Io { inner, .. })
                     if inner.kind() == std::io::ErrorKind::UnexpectedEof =>
                 {
                     break;
                 }
-                _ => return Err(DecodeError(y)),
-            },
+                Err(y) => return Err(DecodeError(y)),
+            }
+        };
+        match record {
+            IETRecord::Entry(entry) => {
+                if !length_prefixed {
+                    check_parent_order(offsets.len() as u64, entry.parent)?;
+                }
+                let end = inp.stream_position().map_err(ReadInputError)?;
+                offsets.push(offset.saturating_sub(extra_offset));
+                lengths.push(end - offset);
+                PoolEntry::push_linked(&mut pool, entry.parent);
+            }
+            IETRecord::FollowsFrom { id, follows } => {
+                follows_from.entry(id).or_default().push(follows)
+            }
         }
     }
-    Ok(IETTableData { offsets, child_lists: pool })
+    Ok(IETTableData { offsets, lengths, child_lists: pool, follows_from })
 }
 
 /// Span location data needed for [iet_to_et_with_table].
 #[derive(Debug)]
 pub struct IETTableData {
     offsets: Vec<u64>,
+    lengths: Vec<u64>,
     child_lists: Vec<PoolEntry>,
+    follows_from: BTreeMap<u32, Vec<u32>>,
 }
 impl IETTableData {
-    pub fn to_ref(&'_ self) -> IETTableDataRef<'_, '_> {
-        IETTableDataRef { offsets: &self.offsets, child_lists: &self.child_lists }
+    pub fn to_ref(&'_ self) -> IETTableDataRef<'_> {
+        IETTableDataRef {
+            offsets: &self.offsets,
+            lengths: &self.lengths,
+            child_lists: &self.child_lists,
+            follows_from: &self.follows_from,
+        }
     }
 }
 /// A reference version of [IETTableData]
-pub struct IETTableDataRef<'a, 'b> {
+pub struct IETTableDataRef<'a> {
     offsets: &'a [u64],
-    child_lists: &'b [PoolEntry],
+    lengths: &'a [u64],
+    child_lists: &'a [PoolEntry],
+    follows_from: &'a BTreeMap<u32, Vec<u32>>,
 }
-impl<'a, 'b> IETTableDataRef<'a, 'b> {
-    pub fn new(offsets: &'a [u64], child_lists: &'b [PoolEntry]) -> Self {
-        Self { offsets, child_lists }
+impl<'a> IETTableDataRef<'a> {
+    /// `offsets` and `lengths` locate each entry in the input, relative to the end of its magic.
+    /// `follows_from` has the links which were written as their own records, see [IETRecord].
+    pub fn new(
+        offsets: &'a [u64], lengths: &'a [u64], child_lists: &'a [PoolEntry],
+        follows_from: &'a BTreeMap<u32, Vec<u32>>,
+    ) -> Self {
+        Self { offsets, lengths, child_lists, follows_from }
     }
 }
 
@@ -125,6 +184,9 @@ impl<'a, 'b> IETTableDataRef<'a, 'b> {
 /// This is a faster alternative to [iet_to_et] if you happen to know the necessary tables already,
 /// like [ETStorage](crate::mmap::ETStorage) does.
 ///
+/// Entries are copied as they are, without length prefixes, except for the ones with
+/// follows-from records, which are decoded and written again with the links added. The records
+/// themselves are left out, as ET has no place for them.
 ///
 /// It is the caller's responsibility to buffer IO, if desired.
 ///
@@ -133,17 +195,57 @@ pub fn iet_to_et_with_table<W: Write, R: Read + Seek>(
     table: &IETTableDataRef, inp: &mut R, out: &mut W, skip_magic: bool,
 ) -> Result<(), ConvertError> {
     use ConvertError::*;
-    let magic = entrace_magic_for(EN_DISK_VERSION, crate::StorageFormat::ET);
-    out.write_all(&magic).map_err(OutWriteError)?;
+    let attr_encoding = if skip_magic { read_attr_encoding(inp)? } else { AttrEncoding::Standard };
+    let data_start = inp.stream_position().map_err(ReadInputError)?;
+    let location = |id: u32| match (table.offsets.get(id as usize), table.lengths.get(id as usize))
+    {
+        (Some(&offset), Some(&len)) => Ok((offset, len)),
+        _ => Err(NoSuchEntry(id)),
+    };
 
-    let config = bincode::config::standard();
-    bincode::serde::encode_into_std_write(table.offsets, out, config)?;
-    bincode::serde::encode_into_std_write(table.child_lists, out, config)?;
-    if skip_magic {
-        inp.seek(std::io::SeekFrom::Start(10)).map_err(OutWriteError)?;
+    let mut rewritten = BTreeMap::new();
+    for (&id, links) in table.follows_from {
+        let (offset, len) = location(id)?;
+        inp.seek(SeekFrom::Start(data_start + offset)).map_err(ReadInputError)?;
+        let mut entry = attr_encoding.decode_entry(&mut inp.by_ref().take(len))?;
+        entry.follows_from.extend(links);
+        let mut bytes = vec![];
+        attr_encoding.encode_entry(&entry, &mut bytes)?;
+        rewritten.insert(id, bytes);
+    }
+    let mut new_offsets = Vec::with_capacity(table.offsets.len());
+    let mut cur_offset = 0;
+    for (id, &len) in (0..).zip(table.lengths) {
+        new_offsets.push(cur_offset);
+        cur_offset += rewritten.get(&id).map_or(len, |x: &Vec<u8>| x.len() as u64);
     }
 
-    std::io::copy(inp, out).map_err(OutWriteError)?;
+    let magic = entrace_magic_with(EN_DISK_VERSION, crate::StorageFormat::ET, attr_encoding);
+    out.write_all(&magic).map_err(OutWriteError)?;
+    let config = crate::BINCODE_CONFIG;
+    bincode::serde::encode_into_std_write(&new_offsets, out, config)?;
+    bincode::serde::encode_into_std_write(table.child_lists, out, config)?;
+
+    inp.seek(SeekFrom::Start(data_start)).map_err(ReadInputError)?;
+    let mut pos = 0;
+    for id in 0..table.offsets.len() as u32 {
+        let (offset, len) = location(id)?;
+        // skip length prefixes and follows-from records
+        let gap = offset.checked_sub(pos).ok_or(NotEnoughBytes(offset as usize))?;
+        std::io::copy(&mut inp.by_ref().take(gap), &mut std::io::sink()).map_err(ReadInputError)?;
+        let copied = match rewritten.get(&id) {
+            Some(bytes) => {
+                out.write_all(bytes).map_err(OutWriteError)?;
+                std::io::copy(&mut inp.by_ref().take(len), &mut std::io::sink())
+                    .map_err(ReadInputError)?
+            }
+            None => std::io::copy(&mut inp.by_ref().take(len), out).map_err(OutWriteError)?,
+        };
+        if copied != len {
+            return Err(NotEnoughBytes(offset as usize));
+        }
+        pos = offset + len;
+    }
 
     Ok(())
 }
@@ -151,23 +253,19 @@ pub fn iet_to_et_with_table<W: Write, R: Read + Seek>(
 /// Convert a ET file into an IET file.
 ///
 /// It is the caller's responsibility to buffer IO.
+/// The attribute encoding is kept, like in [iet_to_et].
 ///
 /// For the reverse direction, see the [iet_to_et] family of functions.
 pub fn et_to_iet<W: Write, R: Read + Seek>(
     inp: &mut R, out: &mut W, skip_magic: bool,
 ) -> Result<(), ConvertError> {
     use ConvertError::*;
-    let magic = entrace_magic_for(EN_DISK_VERSION, crate::StorageFormat::IET);
+    let attr_encoding = if skip_magic { read_attr_encoding(inp)? } else { AttrEncoding::Standard };
+    let magic = entrace_magic_with(EN_DISK_VERSION, crate::StorageFormat::IET, attr_encoding);
     out.write_all(&magic).map_err(OutWriteError)?;
-    if skip_magic {
-        inp.seek(std::io::SeekFrom::Start(10)).map_err(ReadInputError)?;
-    };
-    let config = bincode::config::standard();
-    // offset_table is a Vec<u32>.
-    // we know from the bincode spec that these are encoded by an u64 for the length and then
-    // the data. we don't want to allocate the data here, so skip it.
-    let offset_table_len: u64 = bincode::serde::decode_from_std_read(inp, config)?;
-    inp.seek_relative(offset_table_len as i64).map_err(OutWriteError)?;
+    let config = crate::BINCODE_CONFIG;
+    // the offsets are varint-encoded, so they can't be skipped by their count alone.
+    let _offset_table: Vec<u64> = bincode::serde::decode_from_std_read(inp, config)?;
 
     // we have to deserialize the whole thing here because each pool entry can have a dynamic
     // number of items
@@ -176,6 +274,61 @@ pub fn et_to_iet<W: Write, R: Read + Seek>(
     Ok(())
 }
 
+/// Read span `id` of `provider` as an entry, like it would be stored in a file.
+fn provider_entry(
+    provider: &(impl LogProvider + ?Sized), id: u32,
+) -> Result<TraceEntry, LogProviderError> {
+    let names = provider.attr_names(id)?.into_iter().map(|x| x.to_string()).collect();
+    let values = provider.attr_values(id)?.iter().map(|x| x.to_owned()).collect();
+    let meta = provider.meta(id)?.into();
+    let message = provider.message(id)?.map(|x| x.to_string());
+    let entry = TraceEntry::from_unsorted_attrs(provider.parent(id)?, message, meta, names, values);
+    Ok(entry.with_follows_from(provider.follows_from(id)?))
+}
+
+/// Write every span of `provider` as an IET file, so that an already loaded trace can be saved
+/// without reading its file again. For a [live](LogProvider::is_live) trace, pass a
+/// [snapshot](LogProvider::snapshot), so that it doesn't change while it is written.
+///
+/// The output always uses [AttrEncoding::Standard]. Spans are written one by one, so memory use
+/// doesn't grow with the trace. It is the callers responsibility to buffer IO if desired.
+pub fn provider_to_iet<W: Write>(
+    provider: &(impl LogProvider + ?Sized), out: &mut W,
+) -> Result<(), ConvertError> {
+    let magic = entrace_magic_with(EN_DISK_VERSION, StorageFormat::IET, AttrEncoding::Standard);
+    out.write_all(&magic).map_err(ConvertError::OutWriteError)?;
+    for id in 0..provider.len() as u32 {
+        let entry =
+            provider_entry(provider, id).map_err(|inner| ConvertError::ReadSpan { id, inner })?;
+        AttrEncoding::Standard.encode_entry(&entry, out)?;
+    }
+    Ok(())
+}
+
+/// Like [provider_to_iet], but writes an ET file.
+///
+/// The offset table comes before the entries in an ET file, so the encoded entries are kept in
+/// memory until all of them are written. This takes about as much memory as the IET file would.
+pub fn provider_to_et<W: Write>(
+    provider: &(impl LogProvider + ?Sized), out: &mut W,
+) -> Result<(), ConvertError> {
+    let mut entries = vec![];
+    let mut offsets = Vec::with_capacity(provider.len());
+    let mut lengths = Vec::with_capacity(provider.len());
+    let mut pool: Vec<PoolEntry> = Vec::with_capacity(provider.len());
+    for id in 0..provider.len() as u32 {
+        let entry =
+            provider_entry(provider, id).map_err(|inner| ConvertError::ReadSpan { id, inner })?;
+        offsets.push(entries.len() as u64);
+        PoolEntry::push_linked(&mut pool, entry.parent);
+        let len = AttrEncoding::Standard.encode_entry(&entry, &mut entries)?;
+        lengths.push(len as u64);
+    }
+    let no_links = BTreeMap::new();
+    let table = IETTableDataRef::new(&offsets, &lengths, &pool, &no_links);
+    iet_to_et_with_table(&table, &mut std::io::Cursor::new(entries), out, false)
+}
+
 // Old trace entry, from version 1
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct TraceEntry1 {
@@ -188,13 +341,13 @@ struct TraceEntry1 {
 impl TraceEntry1 {
     pub fn into_trace_entry_2(self) -> TraceEntry {
         let TraceEntry1 { parent, message, metadata, mut attributes } = self;
-        attributes.sort_unstable_by(|x, y| x.0.cmp(&y.0));
+        attributes.sort_by(|x, y| x.0.cmp(&y.0));
         let (attr_names, attr_values) = attributes.into_iter().unzip();
 
         TraceEntry::from_sorted_attrs(parent, message, metadata, attr_names, attr_values)
     }
 }
-/// Convert a version 1 et file to a version 2 (latest as of writing) format.
+/// Convert a version 1 et file to the latest format ([EN_DISK_VERSION]).
 /// It is the caller's responsibility to buffer IO, but temp SHOULD not be buffered
 /// (it'll be buffered internally, separately for read/write).
 ///
@@ -206,12 +359,12 @@ pub fn et_v1_to_v2<W: Write, R: Read + Seek, RW: Read + Write + Seek>(
 ) -> Result<(), ConvertError> {
     use ConvertError::*;
     use bincode::serde::{decode_from_std_read, encode_into_std_write};
-    const CFG: Configuration = bincode::config::standard();
+    const CFG: Configuration = crate::BINCODE_CONFIG;
 
     if !skip_validating_magic {
         let mut input_magic = [0; 10];
         inp.read_exact(&mut input_magic).map_err(ReadInputError)?;
-        let (version, ty) = parse_entrace_magic(&input_magic)?;
+        let (version, ty, _) = parse_entrace_magic(&input_magic)?;
         if version != 1 {
             return Err(ConvertError::InputVersionMismatch(version, 1));
         } else if ty != StorageFormat::ET {
@@ -244,18 +397,18 @@ pub fn et_v1_to_v2<W: Write, R: Read + Seek, RW: Read + Write + Seek>(
     Ok(())
 }
 
-/// Convert a version 1 iet file to a version 2 (latest as of writing) format.
+/// Convert a version 1 iet file to the latest format ([EN_DISK_VERSION]).
 /// It is the caller's responsibility to buffer IO.
 pub fn iet_v1_to_v2<W: Write, R: Read + Seek>(
     inp: &mut R, out: &mut W, skip_validating_magic: bool,
 ) -> Result<(), ConvertError> {
     use ConvertError::*;
     use bincode::serde::{decode_from_std_read, encode_into_std_write};
-    const CFG: Configuration = bincode::config::standard();
+    const CFG: Configuration = crate::BINCODE_CONFIG;
     if !skip_validating_magic {
         let mut input_magic = [0; 10];
         inp.read_exact(&mut input_magic).map_err(ReadInputError)?;
-        let (version, ty) = parse_entrace_magic(&input_magic)?;
+        let (version, ty, _) = parse_entrace_magic(&input_magic)?;
         if version != 1 {
             return Err(ConvertError::InputVersionMismatch(version, 1));
         } else if ty != StorageFormat::ET {
@@ -283,3 +436,334 @@ pub fn iet_v1_to_v2<W: Write, R: Read + Seek>(
 
     Ok(())
 }
+
+// Old trace entry, from version 2. `V` is `Vec<EnValue>` for [AttrEncoding::Standard] and the
+// packed `Vec<u8>` for [AttrEncoding::Compact].
+#[derive(Serialize, Deserialize)]
+struct TraceEntry2<V> {
+    parent: u32,
+    message: Option<String>,
+    metadata: MetadataContainer,
+    attr_names: Vec<String>,
+    attr_values: V,
+}
+
+/// Read one version 2 entry and write it as a version 3 entry, which is the same with an empty
+/// follows-from list at the end. Returns `false` at the end of the input.
+fn entry_v2_to_v3<R: Read, W: Write>(
+    inp: &mut R, out: &mut W, attr_encoding: AttrEncoding,
+) -> Result<bool, ConvertError> {
+    fn copy<V: Serialize + serde::de::DeserializeOwned, R: Read, W: Write>(
+        inp: &mut R, out: &mut W,
+    ) -> Result<bool, ConvertError> {
+        const CFG: Configuration = crate::BINCODE_CONFIG;
+        let entry: TraceEntry2<V> = match bincode::serde::decode_from_std_read(inp, CFG) {
+            Ok(x) => x,
+            Err(bincode::error::DecodeError::Io { inner, .. })
+                if inner.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                return Ok(false);
+            }
+            Err(y) => return Err(ConvertError::DecodeError(y)),
+        };
+        // a tuple is encoded as its fields one after the other
+        let no_links: &[u32] = &[];
+        bincode::serde::encode_into_std_write((entry, no_links), out, CFG)?;
+        Ok(true)
+    }
+    match attr_encoding {
+        AttrEncoding::Standard => copy::<Vec<EnValue>, _, _>(inp, out),
+        AttrEncoding::Compact => copy::<Vec<u8>, _, _>(inp, out),
+    }
+}
+
+/// Read the magic of a version 2 file from the start of `inp`, check it against `format` unless
+/// `skip_validating_magic` is set, and return its attribute encoding.
+fn read_v2_magic<R: Read + Seek>(
+    inp: &mut R, format: StorageFormat, skip_validating_magic: bool,
+) -> Result<AttrEncoding, ConvertError> {
+    let mut input_magic = [0; 10];
+    inp.seek(SeekFrom::Start(0)).map_err(ConvertError::ReadInputError)?;
+    inp.read_exact(&mut input_magic).map_err(ConvertError::ReadInputError)?;
+    let (version, ty, attr_encoding) = parse_entrace_magic(&input_magic)?;
+    if !skip_validating_magic {
+        if version != 2 {
+            return Err(ConvertError::InputVersionMismatch(version, 2));
+        } else if ty != format {
+            return Err(ConvertError::InputFormatMismatch(ty, format));
+        }
+    }
+    Ok(attr_encoding)
+}
+
+/// Convert a version 2 iet file to version 3, which added follows-from links to every entry.
+/// The attribute encoding is kept.
+///
+/// Unlike the version 1 converters, this always reads the magic from the start of `inp`, as the
+/// attribute encoding is stored there. With `skip_validating_magic`, its version and format are
+/// not checked. It is the caller's responsibility to buffer IO.
+pub fn iet_v2_to_v3<W: Write, R: Read + Seek>(
+    inp: &mut R, out: &mut W, skip_validating_magic: bool,
+) -> Result<(), ConvertError> {
+    let attr_encoding = read_v2_magic(inp, StorageFormat::IET, skip_validating_magic)?;
+    let magic = entrace_magic_with(3, StorageFormat::IET, attr_encoding);
+    out.write_all(&magic).map_err(ConvertError::OutWriteError)?;
+    while entry_v2_to_v3(inp, out, attr_encoding)? {}
+    Ok(())
+}
+
+/// Convert a version 2 et file to version 3, see [iet_v2_to_v3].
+///
+/// Every entry grows by one byte, so the offset table is rewritten, but the child lists are kept.
+/// It is the caller's responsibility to buffer IO.
+pub fn et_v2_to_v3<W: Write, R: Read + Seek>(
+    inp: &mut R, out: &mut W, skip_validating_magic: bool,
+) -> Result<(), ConvertError> {
+    use bincode::serde::{decode_from_std_read, encode_into_std_write};
+    const CFG: Configuration = crate::BINCODE_CONFIG;
+    let attr_encoding = read_v2_magic(inp, StorageFormat::ET, skip_validating_magic)?;
+    let offsets: Vec<u64> = decode_from_std_read(inp, CFG)?;
+    let child_lists: Vec<PoolEntry> = decode_from_std_read(inp, CFG)?;
+    // the empty list appended to each entry is a single byte
+    let new_offsets: Vec<u64> = offsets.iter().zip(0..).map(|(x, i)| x + i).collect();
+
+    let magic = entrace_magic_with(3, StorageFormat::ET, attr_encoding);
+    out.write_all(&magic).map_err(ConvertError::OutWriteError)?;
+    encode_into_std_write(new_offsets, out, CFG)?;
+    encode_into_std_write(child_lists, out, CFG)?;
+    for offset in offsets {
+        if !entry_v2_to_v3(inp, out, attr_encoding)? {
+            return Err(ConvertError::NotEnoughBytes(offset as usize));
+        }
+    }
+    Ok(())
+}
+
+/// Convert a version 3 iet or et file to version 4, which added follows-from records to IET, and
+/// a tag for [EnValue::Debug] values to [AttrEncoding::Compact]. The format and the attribute
+/// encoding are kept.
+///
+/// Version 3 files have no follows-from records, so [AttrEncoding::Standard] files are valid
+/// version 4 files, and only the version in the magic changes. In compact files, the new tag was
+/// the largest small integer, so their entries are decoded and written again, with such values as
+/// regular integers.
+///
+/// Unlike the version 1 converters, this always reads the magic from the start of `inp`. With
+/// `skip_validating_magic`, its version is not checked. It is the caller's responsibility to
+/// buffer IO.
+pub fn v3_to_v4<W: Write, R: Read + Seek>(
+    inp: &mut R, out: &mut W, skip_validating_magic: bool,
+) -> Result<(), ConvertError> {
+    use ConvertError::*;
+    use bincode::serde::{decode_from_std_read, encode_into_std_write};
+    const CFG: Configuration = crate::BINCODE_CONFIG;
+    let mut magic = [0; 10];
+    inp.seek(SeekFrom::Start(0)).map_err(ReadInputError)?;
+    inp.read_exact(&mut magic).map_err(ReadInputError)?;
+    let (version, format, attr_encoding) = parse_entrace_magic(&magic)?;
+    if !skip_validating_magic && version != 3 {
+        return Err(InputVersionMismatch(version, 3));
+    }
+    magic[8] = 4;
+    out.write_all(&magic).map_err(OutWriteError)?;
+    if attr_encoding == AttrEncoding::Standard {
+        std::io::copy(inp, out).map_err(OutWriteError)?;
+        return Ok(());
+    }
+    /// Read the next entry, or `None` at the end of the input.
+    fn next_entry<R: Read>(inp: &mut R) -> Result<Option<TraceEntry>, ConvertError> {
+        match crate::attr_encoding::decode_compact_v3_entry(inp) {
+            Ok(x) => Ok(Some(x)),
+            Err(bincode::error::DecodeError::Io { inner, .. })
+                if inner.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                Ok(None)
+            }
+            Err(y) => Err(DecodeError(y)),
+        }
+    }
+    let mut buffer = vec![];
+    match format {
+        StorageFormat::IET => {
+            while let Some(entry) = next_entry(inp)? {
+                AttrEncoding::Compact.encode_entry(&entry, out)?;
+            }
+        }
+        StorageFormat::IETPrefix => loop {
+            let mut cl_buf = [0; 8];
+            match inp.read_exact(&mut cl_buf) {
+                Ok(()) => (),
+                Err(y) if y.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(y) => return Err(ReadInputError(y)),
+            }
+            let len = decode_length_prefix(cl_buf)?;
+            let Some(entry) = next_entry(&mut inp.by_ref().take(len))? else { break };
+            buffer.clear();
+            AttrEncoding::Compact.encode_entry(&entry, &mut buffer)?;
+            out.write_all(&encode_length_prefix(buffer.len() as u64)).map_err(OutWriteError)?;
+            out.write_all(&buffer).map_err(OutWriteError)?;
+        },
+        StorageFormat::ET => {
+            let offsets: Vec<u64> = decode_from_std_read(inp, CFG)?;
+            let child_lists: Vec<PoolEntry> = decode_from_std_read(inp, CFG)?;
+            let data_start = inp.stream_position().map_err(ReadInputError)?;
+            // entries can grow, so they are written once to find the new offsets
+            let mut new_offsets = Vec::with_capacity(offsets.len());
+            let mut cur_offset = 0u64;
+            for &offset in &offsets {
+                let entry = next_entry(inp)?.ok_or(NotEnoughBytes(offset as usize))?;
+                buffer.clear();
+                new_offsets.push(cur_offset);
+                cur_offset += AttrEncoding::Compact.encode_entry(&entry, &mut buffer)? as u64;
+            }
+            encode_into_std_write(new_offsets, out, CFG)?;
+            encode_into_std_write(child_lists, out, CFG)?;
+            inp.seek(SeekFrom::Start(data_start)).map_err(ReadInputError)?;
+            for &offset in &offsets {
+                let entry = next_entry(inp)?.ok_or(NotEnoughBytes(offset as usize))?;
+                AttrEncoding::Compact.encode_entry(&entry, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Progress of [et_to_iet_resumable]. This is also what the checkpoint file stores.
+///
+/// The checkpoint file is a small text file of `key value` lines, in this order:
+/// ```text
+/// entrace-convert-checkpoint 1
+/// input_len 123456
+/// entries_total 1000
+/// entries_written 250
+/// output_len 30000
+/// ```
+/// `input_len` and `entries_total` identify the input, so a checkpoint is not applied to a
+/// different file by accident. `output_len` is where the output continues from, the output may
+/// have more (partially written) bytes after it, which are overwritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConvertCheckpoint {
+    pub input_len: u64,
+    pub entries_total: u64,
+    pub entries_written: u64,
+    pub output_len: u64,
+}
+impl ConvertCheckpoint {
+    const HEADER: &str = "entrace-convert-checkpoint 1";
+
+    pub fn to_text(&self) -> String {
+        let ConvertCheckpoint { input_len, entries_total, entries_written, output_len } = self;
+        format!(
+            "{}\ninput_len {input_len}\nentries_total {entries_total}\nentries_written \
+             {entries_written}\noutput_len {output_len}\n",
+            Self::HEADER
+        )
+    }
+    pub fn parse(text: &str) -> Result<Self, ConvertError> {
+        use ConvertError::BadCheckpoint;
+        let mut lines = text.lines();
+        if lines.next() != Some(Self::HEADER) {
+            return Err(BadCheckpoint("unknown header".into()));
+        }
+        let mut field = |name: &str| -> Result<u64, ConvertError> {
+            let line = lines.next().ok_or_else(|| BadCheckpoint(format!("missing {name}")))?;
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => value
+                    .parse()
+                    .map_err(|_| BadCheckpoint(format!("{name} is not a number: {value}"))),
+                _ => Err(BadCheckpoint(format!("expected {name}, got {line}"))),
+            }
+        };
+        Ok(ConvertCheckpoint {
+            input_len: field("input_len")?,
+            entries_total: field("entries_total")?,
+            entries_written: field("entries_written")?,
+            output_len: field("output_len")?,
+        })
+    }
+    /// Read the checkpoint at `path`, or `None` if there is no such file.
+    pub fn load(path: &Path) -> Result<Option<Self>, ConvertError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(Some(Self::parse(&text)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(ConvertError::CheckpointIoError(e)),
+        }
+    }
+    /// Write the checkpoint to `path`. It is written to a temporary file first and then renamed,
+    /// so an interruption leaves either the old or the new checkpoint.
+    pub fn store(&self, path: &Path) -> Result<(), ConvertError> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, self.to_text()).map_err(ConvertError::CheckpointIoError)?;
+        std::fs::rename(&tmp, path).map_err(ConvertError::CheckpointIoError)
+    }
+}
+
+/// Like [et_to_iet], but can continue where an interrupted conversion stopped.
+///
+/// Every `checkpoint_every` entries, `out` is flushed and the progress is written to the
+/// `checkpoint` file (see [ConvertCheckpoint] for its format), then `on_progress` is called.
+/// If `checkpoint` exists when this is called, the entries it records are skipped, and `out`
+/// is written from the recorded position, so `out` must be opened without truncating it.
+/// The checkpoint file is removed once the conversion is done.
+///
+/// Only [Write::flush] is called on `out` before a checkpoint, so for surviving a crash of the
+/// whole system (not just the process), `out` should sync to disk on flush.
+///
+/// The input must have a magic, unlike with [et_to_iet] it can't be skipped.
+pub fn et_to_iet_resumable<R: Read + Seek, W: Write + Seek>(
+    inp: &mut R, out: &mut W, checkpoint: &Path, checkpoint_every: u64,
+    mut on_progress: impl FnMut(&ConvertCheckpoint),
+) -> Result<(), ConvertError> {
+    use ConvertError::*;
+    let input_len = inp.seek(SeekFrom::End(0)).map_err(ReadInputError)?;
+    let attr_encoding = read_attr_encoding(inp)?;
+    let config = crate::BINCODE_CONFIG;
+    let offsets: Vec<u64> = bincode::serde::decode_from_std_read(inp, config)?;
+    let _pool: Vec<PoolEntry> = bincode::serde::decode_from_std_read(inp, config)?;
+    let entries_start = inp.stream_position().map_err(ReadInputError)?;
+    let entries_total = offsets.len() as u64;
+
+    let mut progress = match ConvertCheckpoint::load(checkpoint)? {
+        Some(x) if x.input_len != input_len => return Err(CheckpointMismatch("length differs")),
+        Some(x) if x.entries_total != entries_total => {
+            return Err(CheckpointMismatch("entry count differs"));
+        }
+        Some(x) if x.entries_written > entries_total => {
+            return Err(BadCheckpoint("more entries written than there are".into()));
+        }
+        Some(x) => {
+            out.seek(SeekFrom::Start(x.output_len)).map_err(OutWriteError)?;
+            x
+        }
+        None => {
+            let magic = entrace_magic_with(EN_DISK_VERSION, StorageFormat::IET, attr_encoding);
+            out.seek(SeekFrom::Start(0)).map_err(OutWriteError)?;
+            out.write_all(&magic).map_err(OutWriteError)?;
+            let output_len = magic.len() as u64;
+            ConvertCheckpoint { input_len, entries_total, entries_written: 0, output_len }
+        }
+    };
+    // entries are stored the same way in both formats, so they're copied in batches
+    let offset_of = |idx: u64| match offsets.get(idx as usize) {
+        Some(x) => entries_start + x,
+        None => input_len,
+    };
+    inp.seek(SeekFrom::Start(offset_of(progress.entries_written))).map_err(ReadInputError)?;
+    while progress.entries_written < entries_total {
+        let end = entries_total.min(progress.entries_written + checkpoint_every.max(1));
+        let len = offset_of(end) - offset_of(progress.entries_written);
+        let copied = std::io::copy(&mut inp.by_ref().take(len), out).map_err(OutWriteError)?;
+        if copied != len {
+            return Err(NotEnoughBytes(offset_of(progress.entries_written) as usize));
+        }
+        out.flush().map_err(OutWriteError)?;
+        progress.entries_written = end;
+        progress.output_len += len;
+        progress.store(checkpoint)?;
+        on_progress(&progress);
+    }
+    match std::fs::remove_file(checkpoint) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(CheckpointIoError(e)),
+        _ => Ok(()),
+    }
+}
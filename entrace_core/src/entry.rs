@@ -1,7 +1,9 @@
+use std::borrow::Cow;
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    MetadataContainer, MetadataRefContainer,
+    AttrTransform, MetadataContainer, MetadataRefContainer,
     tree_layer::{EnValue, EnValueRef},
 };
 
@@ -9,7 +11,8 @@ use crate::{
 /// You can't construct this on purpose. Use [TraceEntry::from_sorted_attrs] or
 /// [TraceEntry::from_unsorted_attrs].
 ///
-/// The canonical order of the fields here is `parent, message, metadata, attr_names, attr_values`
+/// The canonical order of the fields here is
+/// `parent, message, metadata, attr_names, attr_values, follows_from`
 /// Methods reading the data are implemented on [TraceEntry2Ref]. You can get one with
 /// [TraceEntry::as_ref]
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -18,11 +21,13 @@ pub struct TraceEntry {
     pub parent: u32,
     pub message: Option<String>,
     pub metadata: MetadataContainer,
-    /// This MUST be sorted
+    /// This MUST be sorted. Duplicate names are kept in the order they were recorded in.
     pub attr_names: Vec<String>,
     // TODO: to make this really fast, we'd need a way to decode just the k-th element of a bincode array
     // shouldn't be that hard tho
     pub attr_values: Vec<EnValue>,
+    /// Ids of the spans this span follows from, see [TraceEntry::with_follows_from].
+    pub follows_from: Vec<u32>,
     /// hack to disable the default TraceEntry {} constructor, so you must use a sorting constructor
     _sealed: (),
 }
@@ -34,6 +39,7 @@ impl TraceEntry {
             metadata: MetadataContainer::root(),
             attr_names: vec![],
             attr_values: vec![],
+            follows_from: vec![],
             _sealed: (),
         }
     }
@@ -41,30 +47,66 @@ impl TraceEntry {
         parent: u32, message: Option<String>, metadata: MetadataContainer, attr_names: Vec<String>,
         attr_values: Vec<EnValue>,
     ) -> Self {
-        TraceEntry { parent, message, metadata, attr_names, attr_values, _sealed: () }
+        TraceEntry {
+            parent,
+            message,
+            metadata,
+            attr_names,
+            attr_values,
+            follows_from: vec![],
+            _sealed: (),
+        }
     }
     pub fn from_unsorted_attrs(
         parent: u32, message: Option<String>, metadata: MetadataContainer,
         mut attr_names: Vec<String>, mut attr_values: Vec<EnValue>,
     ) -> Self {
-        let mut pi = permutation::sort_unstable(&attr_names);
+        let mut pi = permutation::sort(&attr_names);
         pi.apply_slice_in_place(&mut attr_names);
         pi.apply_slice_in_place(&mut attr_values);
-        TraceEntry { parent, message, metadata, attr_names, attr_values, _sealed: () }
+        TraceEntry {
+            parent,
+            message,
+            metadata,
+            attr_names,
+            attr_values,
+            follows_from: vec![],
+            _sealed: (),
+        }
+    }
+
+    /// Set the spans this span follows from, as recorded by `tracing`'s `Span::follows_from`.
+    /// These are causal links in addition to the parent, for example to the span which scheduled
+    /// an async task.
+    pub fn with_follows_from(mut self, follows_from: Vec<u32>) -> Self {
+        self.follows_from = follows_from;
+        self
+    }
+
+    /// Run `transform` on every attribute. The names stay the same, so they stay sorted.
+    pub fn transform_attrs(&mut self, transform: &dyn AttrTransform) {
+        for (name, value) in self.attr_names.iter().zip(&mut self.attr_values) {
+            transform.transform(name, value);
+        }
     }
 
     pub fn as_ref(&'_ self) -> TraceEntryRef<'_> {
-        TraceEntryRef::from_sorted_attrs(
+        let mut entry = TraceEntryRef::from_sorted_attrs(
             self.parent,
             self.message.as_deref(),
             self.metadata.as_ref(),
             self.attr_names.iter().map(|x| x.as_str()).collect(),
             self.attr_values.iter().map(|x| x.as_ref()).collect(),
-        )
+        );
+        entry.follows_from = Cow::Borrowed(&self.follows_from);
+        entry
     }
 }
 
 /// Attribute names and values are always stored in sorted order
+///
+/// `tracing` allows recording the same field name more than once. Sorting keeps such duplicates in
+/// the order they were recorded in, and lookups by name return the first one.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(clippy::manual_non_exhaustive)]
 pub struct TraceEntryRef<'a> {
@@ -75,6 +117,7 @@ pub struct TraceEntryRef<'a> {
 
     pub attr_names: Vec<&'a str>,
     pub attr_values: Vec<EnValueRef<'a>>,
+    pub follows_from: Cow<'a, [u32]>,
     _sealed: (),
 }
 impl<'a> TraceEntryRef<'a> {
@@ -82,15 +125,30 @@ impl<'a> TraceEntryRef<'a> {
         parent: u32, message: Option<&'a str>, metadata: MetadataRefContainer<'a>,
         attr_names: Vec<&'a str>, attr_values: Vec<EnValueRef<'a>>,
     ) -> Self {
-        TraceEntryRef { parent, message, metadata, attr_names, attr_values, _sealed: () }
+        TraceEntryRef {
+            parent,
+            message,
+            metadata,
+            attr_names,
+            attr_values,
+            follows_from: Cow::Borrowed(&[]),
+            _sealed: (),
+        }
     }
     pub fn get_attr(&self, name: &str) -> Option<EnValueRef<'a>> {
-        let name_idx = self.attr_names.binary_search(&name).ok()?;
+        let name_idx = find_attr(&self.attr_names, name)?;
         // this clone should be ok since EnValueRef only contains references
         Some(self.attr_values[name_idx].clone())
     }
 }
 
+/// The index of the first attribute called `name` in the sorted `attr_names`.
+/// Unlike `binary_search`, this is deterministic if the name is there more than once.
+pub(crate) fn find_attr(attr_names: &[&str], name: &str) -> Option<usize> {
+    let idx = attr_names.partition_point(|x| *x < name);
+    (attr_names.get(idx) == Some(&name)).then_some(idx)
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(clippy::manual_non_exhaustive)]
 pub struct MixedTraceEntry {
@@ -100,6 +158,7 @@ pub struct MixedTraceEntry {
     pub metadata: MetadataRefContainer<'static>,
     pub attr_names: Vec<String>,
     pub attr_values: Vec<EnValue>,
+    pub follows_from: Vec<u32>,
     /// hack to disable the default MixedTrace2Entry {} constructor, so you must use a sorting constructor
     _sealed: (),
 }
@@ -108,26 +167,57 @@ impl MixedTraceEntry {
         parent: u32, message: Option<String>, metadata: MetadataRefContainer<'static>,
         attr_names: Vec<String>, attr_values: Vec<EnValue>,
     ) -> Self {
-        MixedTraceEntry { parent, message, metadata, attr_names, attr_values, _sealed: () }
+        MixedTraceEntry {
+            parent,
+            message,
+            metadata,
+            attr_names,
+            attr_values,
+            follows_from: vec![],
+            _sealed: (),
+        }
     }
     pub fn from_unsorted_attrs(
         parent: u32, message: Option<String>, metadata: MetadataRefContainer<'static>,
         mut attr_names: Vec<String>, mut attr_values: Vec<EnValue>,
     ) -> Self {
-        let mut pi = permutation::sort_unstable(&attr_names);
+        let mut pi = permutation::sort(&attr_names);
         pi.apply_slice_in_place(&mut attr_names);
         pi.apply_slice_in_place(&mut attr_values);
-        MixedTraceEntry { parent, message, metadata, attr_names, attr_values, _sealed: () }
+        MixedTraceEntry {
+            parent,
+            message,
+            metadata,
+            attr_names,
+            attr_values,
+            follows_from: vec![],
+            _sealed: (),
+        }
     }
 
     pub fn as_ref<'a>(&'a self) -> TraceEntryRef<'a> {
-        TraceEntryRef::from_sorted_attrs(
+        let mut entry = TraceEntryRef::from_sorted_attrs(
             self.parent,
             self.message.as_deref(),
             // this clone should be okay as MetadataRefContainer only contains references
             self.metadata.clone(),
             self.attr_names.iter().map(|x| x.as_str()).collect(),
             self.attr_values.iter().map(|x| x.as_ref()).collect(),
-        )
+        );
+        entry.follows_from = Cow::Borrowed(&self.follows_from);
+        entry
     }
 }
+
+/// A record of an IET trace: an entry, or a follows-from link made after the entry of `id` was
+/// written, which readers add to [TraceEntry::follows_from] of `id`.
+///
+/// `tracing` only reports links after both spans are created, so links are written on their
+/// own whenever their entry is not pending anymore, see [crate::FOLLOWS_FROM_MARKER].
+// entries are the common case, boxing them would cost an allocation each
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum IETRecord {
+    Entry(TraceEntry),
+    FollowsFrom { id: u32, follows: u32 },
+}
@@ -0,0 +1,169 @@
+//! Exporting traces to formats understood by other tools.
+use std::io::Write;
+
+use serde::{Serialize, Serializer, ser::SerializeMap};
+
+use crate::{EnValueRef, LogProvider, LogProviderError, MetadataRefContainer};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError {
+    #[error("Failed to read span {id}")]
+    Read {
+        id: u32,
+        #[source]
+        inner: LogProviderError,
+    },
+    #[error("Failed to write the output")]
+    Write(#[source] std::io::Error),
+    #[error("Failed to write span {id} as JSON")]
+    Json {
+        id: u32,
+        #[source]
+        inner: serde_json::Error,
+    },
+}
+
+/// Keys of the fixed fields of an [export_ndjson] line. Attributes with these names are written
+/// as `attr.<name>` instead.
+pub const NDJSON_RESERVED_KEYS: [&str; 6] = ["id", "parent", "level", "target", "name", "message"];
+
+/// Write every span except the root as newline-delimited JSON: one compact object per line, with
+/// no enclosing array, as expected by `jq`, Elasticsearch, Loki and the like.
+///
+/// Each object has the keys `id`, `parent`, `level` (like `"INFO"`), `target`, `name`, and
+/// `message` (`null` if there is none), followed by the attributes of the span as top-level keys.
+/// Attributes named like one of the fixed keys are written as `attr.<name>`, and if a name is
+/// repeated, only its first value is written, like `en_attrs` does.
+///
+/// Spans are written one by one, so memory use doesn't grow with the trace. It is the callers
+/// responsibility to buffer IO if desired.
+pub fn export_ndjson<W: Write>(
+    provider: &(impl LogProvider + ?Sized), mut writer: W,
+) -> Result<(), ExportError> {
+    for id in 1..provider.len() as u32 {
+        let line =
+            NdjsonLine::read(provider, id).map_err(|inner| ExportError::Read { id, inner })?;
+        serde_json::to_writer(&mut writer, &line).map_err(|inner| match inner.io_error_kind() {
+            Some(_) => ExportError::Write(inner.into()),
+            None => ExportError::Json { id, inner },
+        })?;
+        writer.write_all(b"\n").map_err(ExportError::Write)?;
+    }
+    writer.flush().map_err(ExportError::Write)
+}
+
+/// The most spans [format_span_json] writes, so a subtree pasted into a bug report stays readable.
+pub const SPAN_JSON_MAX_SPANS: usize = 1000;
+
+/// The output of [format_span_json].
+pub struct SpanJson {
+    /// The pretty-printed JSON.
+    pub json: String,
+    /// Spans of the subtree left out, as it had more than [SPAN_JSON_MAX_SPANS].
+    pub omitted_spans: usize,
+}
+
+/// A span, and optionally its subtree, as pretty-printed JSON to paste into a bug report.
+///
+/// The output is an object with `spans`, a list of objects like the lines of [export_ndjson],
+/// and `omitted_spans`. Without `include_subtree`, `spans` only holds span `id`. With it, the
+/// descendants of `id` follow in preorder (parents before their children, so the tree can be rebuilt
+/// from the `parent` keys), up to [SPAN_JSON_MAX_SPANS] spans in total. The rest are counted in
+/// `omitted_spans`.
+pub fn format_span_json(
+    provider: &(impl LogProvider + ?Sized), id: u32, include_subtree: bool,
+) -> Result<SpanJson, ExportError> {
+    let read = |id| NdjsonLine::read(provider, id).map_err(|inner| ExportError::Read { id, inner });
+    let mut spans = vec![read(id)?];
+    let mut omitted_spans = 0;
+    if include_subtree {
+        let children = |id| provider.children(id).map_err(|inner| ExportError::Read { id, inner });
+        let mut stack: Vec<u32> = children(id)?.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            if spans.len() < SPAN_JSON_MAX_SPANS {
+                spans.push(read(id)?);
+            } else {
+                omitted_spans += 1;
+            }
+            stack.extend(children(id)?.iter().rev());
+        }
+    }
+    #[derive(Serialize)]
+    struct Output<'a> {
+        spans: Vec<NdjsonLine<'a>>,
+        omitted_spans: usize,
+    }
+    let json = serde_json::to_string_pretty(&Output { spans, omitted_spans })
+        .map_err(|inner| ExportError::Json { id, inner })?;
+    Ok(SpanJson { json, omitted_spans })
+}
+
+struct NdjsonLine<'a> {
+    id: u32,
+    parent: u32,
+    meta: MetadataRefContainer<'a>,
+    message: Option<&'a str>,
+    attr_names: Vec<&'a str>,
+    attr_values: Vec<EnValueRef<'a>>,
+}
+impl<'a> NdjsonLine<'a> {
+    fn read(provider: &'a (impl LogProvider + ?Sized), id: u32) -> Result<Self, LogProviderError> {
+        Ok(NdjsonLine {
+            id,
+            parent: provider.parent(id)?,
+            meta: provider.meta(id)?,
+            message: provider.message(id)?,
+            attr_names: provider.attr_names(id)?,
+            attr_values: provider.attr_values(id)?,
+        })
+    }
+}
+impl Serialize for NdjsonLine<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("id", &self.id)?;
+        map.serialize_entry("parent", &self.parent)?;
+        map.serialize_entry("level", self.meta.level.name())?;
+        map.serialize_entry("target", self.meta.target)?;
+        map.serialize_entry("name", self.meta.name)?;
+        map.serialize_entry("message", &self.message)?;
+        // the message is also stored as an attribute, which is already written
+        let mut skip_message = self.message.is_some();
+        for (idx, (name, value)) in self.attr_names.iter().zip(&self.attr_values).enumerate() {
+            if skip_message && *name == "message" {
+                skip_message = false;
+                continue;
+            }
+            // attr names are sorted, so duplicates are next to each other
+            if idx > 0 && self.attr_names[idx - 1] == *name {
+                continue;
+            }
+            let value = JsonValue(value);
+            if NDJSON_RESERVED_KEYS.contains(name) {
+                map.serialize_entry(&format!("attr.{name}"), &value)?;
+            } else {
+                map.serialize_entry(name, &value)?;
+            }
+        }
+        map.end()
+    }
+}
+
+struct JsonValue<'a, 'v>(&'v EnValueRef<'a>);
+impl Serialize for JsonValue<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self.0 {
+            // JSON has no way to mark formatted values
+            EnValueRef::String(x) | EnValueRef::Debug(x) => serializer.serialize_str(x),
+            // an array of numbers
+            EnValueRef::Bytes(x) => serializer.serialize_bytes(x),
+            EnValueRef::Bool(x) => serializer.serialize_bool(x),
+            // NaN and infinities become null
+            EnValueRef::Float(x) => serializer.serialize_f64(x),
+            EnValueRef::U64(x) => serializer.serialize_u64(x),
+            EnValueRef::I64(x) => serializer.serialize_i64(x),
+            EnValueRef::U128(x) => serializer.serialize_u128(x),
+            EnValueRef::I128(x) => serializer.serialize_i128(x),
+        }
+    }
+}
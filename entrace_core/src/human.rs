@@ -0,0 +1,46 @@
+//! Formatting durations and sizes for people, like `1s 20ms 5us` or `1.2 GB`.
+//!
+//! The output only depends on the input, so it can be compared exactly in tests.
+use std::{fmt::Write, time::Duration};
+
+/// Split a duration given in microseconds into seconds, milliseconds and microseconds, like
+/// `1s 20ms 5us`. Parts which are zero are left out, and zero itself is `0us`.
+pub fn us_to_human(mut us: u64) -> String {
+    if us == 0 {
+        return "0us".to_string();
+    }
+    let mut b = String::new();
+    for (name, in_us) in [("s", 1_000_000), ("ms", 1000), ("us", 1)] {
+        let in_new = us / in_us;
+        if in_new != 0 {
+            us %= in_us;
+            write!(b, "{in_new}{name} ").ok();
+        }
+    }
+    b.pop();
+    b
+}
+
+/// [us_to_human] for a [Duration]. Anything below a microsecond is dropped.
+pub fn duration_to_human(duration: Duration) -> String {
+    us_to_human(u64::try_from(duration.as_micros()).unwrap_or(u64::MAX))
+}
+
+/// Format `x` with an SI prefix and one decimal, like `1.2 GB`, or `3.4M` without a `unit`.
+/// Values below a thousand are written as they are.
+pub fn format_si(x: f64, unit: &str) -> String {
+    let sep = if unit.is_empty() { "" } else { " " };
+    for (prefix, scale) in [("T", 1e12), ("G", 1e9), ("M", 1e6), ("k", 1e3)] {
+        let scaled = format!("{:.1}", x / scale);
+        // 999_999 would be 1000.0 kB otherwise. Values below 1000 are kept exact.
+        if x >= scale || (x >= 1e3 && scaled == "1.0") {
+            return format!("{scaled}{sep}{prefix}{unit}");
+        }
+    }
+    format!("{x}{sep}{unit}")
+}
+
+/// A number of bytes with an SI prefix, like `1.2 GB`. See [format_si].
+pub fn bytes_to_human(bytes: u64) -> String {
+    format_si(bytes as f64, "B")
+}
@@ -0,0 +1,185 @@
+//! Creating traces from formats written by other tools.
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use bincode::error::EncodeError;
+use serde_json::{Map, Value};
+
+use crate::{
+    AttrEncoding, EN_DISK_VERSION, EnValue, LevelContainer, MetadataContainer, PoolRef,
+    StorageFormat, TraceEntry, entrace_magic_with, export::NDJSON_RESERVED_KEYS,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("Failed to read the input")]
+    Read(#[source] std::io::Error),
+    #[error("Line {line} is not valid JSON")]
+    Json {
+        line: usize,
+        #[source]
+        inner: serde_json::Error,
+    },
+    #[error("Line {line} is not a JSON object")]
+    NotAnObject { line: usize },
+    #[error("Line {line} has no `{key}`")]
+    MissingKey { line: usize, key: &'static str },
+    #[error("Line {line} has a bad `{key}`: {value}")]
+    BadValue { line: usize, key: &'static str, value: Value },
+    #[error("Line {line} repeats the id {id}")]
+    DuplicateId { line: usize, id: u64 },
+    #[error(
+        "Line {line} refers to the parent {parent}, which has not appeared before. Parents have to \
+         come before their children"
+    )]
+    UnknownParent { line: usize, parent: u64 },
+    #[error("Line {line} is over the limit of {} spans", PoolRef::MAX)]
+    TooManySpans { line: usize },
+    #[error("Failed to encode span {id}")]
+    Encode {
+        id: u32,
+        #[source]
+        inner: EncodeError,
+    },
+    #[error("Failed to write the output")]
+    Write(#[source] std::io::Error),
+}
+
+/// Read newline-delimited JSON, as written by [crate::export::export_ndjson], and write it as an
+/// IET file. Returns the number of spans written, not counting the root.
+///
+/// Every line is an object with an integer `id` and `parent`, where a parent of 0 is the root.
+/// The other keys are optional: `level` (like `"INFO"`, the default), `target`, `name`, and
+/// `message`. Every other key is an attribute, with `attr.<name>` read as `<name>`. Strings,
+/// numbers and booleans are kept as they are, arrays of bytes become bytes, `null` is skipped, and
+/// other values are stored as their JSON text. Empty lines are skipped.
+///
+/// The spans get new, consecutive ids in the order they are read, so the input ids only have to be
+/// unique. Parents have to come before their children, as in the output of `export_ndjson`.
+///
+/// Spans are written one by one, so memory use only grows with the ids kept for looking up
+/// parents. It is the callers responsibility to buffer IO if desired.
+pub fn from_ndjson<R: BufRead, W: Write>(reader: R, mut writer: W) -> Result<u32, ImportError> {
+    let magic = entrace_magic_with(EN_DISK_VERSION, StorageFormat::IET, AttrEncoding::Standard);
+    writer.write_all(&magic).map_err(ImportError::Write)?;
+    write_entry(&mut writer, &TraceEntry::root(), 0)?;
+    // input id -> output id
+    let mut ids = HashMap::<u64, u32>::new();
+    let mut count: u32 = 0;
+    for (idx, line) in reader.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.map_err(ImportError::Read)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: Value = serde_json::from_str(&line)
+            .map_err(|inner| ImportError::Json { line: line_no, inner })?;
+        let Value::Object(object) = object else {
+            return Err(ImportError::NotAnObject { line: line_no });
+        };
+        if count == PoolRef::MAX {
+            return Err(ImportError::TooManySpans { line: line_no });
+        }
+        let (input_id, entry) = read_line(object, line_no, &ids)?;
+        count += 1;
+        if ids.insert(input_id, count).is_some() {
+            return Err(ImportError::DuplicateId { line: line_no, id: input_id });
+        }
+        write_entry(&mut writer, &entry, count)?;
+    }
+    writer.flush().map_err(ImportError::Write)?;
+    Ok(count)
+}
+
+fn write_entry<W: Write>(writer: &mut W, entry: &TraceEntry, id: u32) -> Result<(), ImportError> {
+    match AttrEncoding::Standard.encode_entry(entry, writer) {
+        Ok(_) => Ok(()),
+        Err(EncodeError::Io { inner, .. }) => Err(ImportError::Write(inner)),
+        Err(inner) => Err(ImportError::Encode { id, inner }),
+    }
+}
+
+/// The id of a line as written in the input, and its entry with the parent resolved.
+fn read_line(
+    mut object: Map<String, Value>, line: usize, ids: &HashMap<u64, u32>,
+) -> Result<(u64, TraceEntry), ImportError> {
+    let mut take_id = |key| match object.remove(key) {
+        Some(Value::Number(x)) if x.as_u64().is_some() => Ok(x.as_u64().unwrap_or_default()),
+        Some(value) => Err(ImportError::BadValue { line, key, value }),
+        None => Err(ImportError::MissingKey { line, key }),
+    };
+    let (id, parent) = (take_id("id")?, take_id("parent")?);
+    let output_parent = match parent {
+        0 => 0,
+        parent => *ids.get(&parent).ok_or(ImportError::UnknownParent { line, parent })?,
+    };
+    let mut take_string = |key| match object.remove(key) {
+        Some(Value::String(x)) => Ok(Some(x)),
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => Err(ImportError::BadValue { line, key, value }),
+    };
+    let level = match take_string("level")? {
+        Some(level) => match parse_level(&level) {
+            Some(level) => level,
+            None => return Err(ImportError::BadValue { line, key: "level", value: level.into() }),
+        },
+        None => LevelContainer::Info,
+    };
+    let target = take_string("target")?.unwrap_or_default();
+    let name = take_string("name")?.unwrap_or_default();
+    let message = take_string("message")?;
+    let meta = MetadataContainer { name, target, level, module_path: None, file: None, line: None };
+
+    let mut attr_names = Vec::with_capacity(object.len() + 1);
+    let mut attr_values = Vec::with_capacity(object.len() + 1);
+    // like TreeLayer, which records the message as an attribute too
+    if let Some(message) = &message {
+        attr_names.push("message".to_string());
+        attr_values.push(EnValue::String(message.clone()));
+    }
+    for (key, value) in object {
+        let Some(value) = json_to_value(value) else { continue };
+        let name = match key.strip_prefix("attr.") {
+            Some(name) if NDJSON_RESERVED_KEYS.contains(&name) => name.to_string(),
+            _ => key,
+        };
+        attr_names.push(name);
+        attr_values.push(value);
+    }
+    let entry =
+        TraceEntry::from_unsorted_attrs(output_parent, message, meta, attr_names, attr_values);
+    Ok((id, entry))
+}
+
+fn parse_level(level: &str) -> Option<LevelContainer> {
+    use LevelContainer::*;
+    [Trace, Debug, Info, Warn, Error].into_iter().find(|x| x.name().eq_ignore_ascii_case(level))
+}
+
+/// `None` for `null`, which has no counterpart in [EnValue].
+fn json_to_value(value: Value) -> Option<EnValue> {
+    let value = match value {
+        Value::Null => return None,
+        Value::Bool(x) => EnValue::Bool(x),
+        Value::Number(x) => match (x.as_u64(), x.as_i64(), x.as_f64()) {
+            (Some(x), _, _) => EnValue::U64(x),
+            (_, Some(x), _) => EnValue::I64(x),
+            (_, _, Some(x)) => EnValue::Float(x),
+            _ => EnValue::String(x.to_string()),
+        },
+        Value::String(x) => EnValue::String(x),
+        Value::Array(items) => {
+            let bytes: Option<Vec<u8>> =
+                items.iter().map(|x| x.as_u64().and_then(|x| u8::try_from(x).ok())).collect();
+            match bytes {
+                // an empty array has no type, keep it as it was written
+                Some(bytes) if !bytes.is_empty() => EnValue::Bytes(bytes),
+                _ => EnValue::String(Value::Array(items).to_string()),
+            }
+        }
+        Value::Object(_) => EnValue::String(value.to_string()),
+    };
+    Some(value)
+}
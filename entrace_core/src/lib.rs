@@ -2,26 +2,52 @@
 
 use crate::remote::{FileIETLogProvider, FileWatchConfig, IETEvent, LoadIETError};
 use serde::{Deserialize, Serialize};
-use std::{fmt::Write, fs::File, io::Read, path::Path};
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::Arc,
+};
 use storage::Storage;
 use thiserror::Error;
 use tracing::Level;
 
 use crate::remote::{DummyRefresher, Refresh};
 
+mod attr_encoding;
+pub use attr_encoding::*;
 pub mod convert;
 pub mod en_formatter;
 mod entry;
 pub use entry::*;
+#[cfg(feature = "json")]
+pub mod export;
+pub mod human;
+#[cfg(feature = "json")]
+pub mod import;
 mod log_provider;
 pub use log_provider::*;
 pub mod mmap;
 pub mod remote;
 pub mod storage;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 mod tree_layer;
 pub use tree_layer::*;
 
-type PoolRef = u32;
+/// The id of a span or event in a trace, which is also its index in the pool. The root is 0.
+///
+/// This limits a trace to `PoolRef::MAX` (about 4.3 billion) spans and events besides the root.
+/// [TreeLayer] drops what comes after that, and reports it loudly, see
+/// [SpanLifecycleReport::over_id_limit].
+///
+/// Ids are `u32` in the file formats and the APIs, this alias marks the places which would need to
+/// change for `u64` ids: a new [EN_DISK_VERSION] (with converters) for the parent, children and
+/// follows-from ids in [TraceEntry] and [PoolEntry], an `AtomicU64` in [TreeLayer], and
+/// [LogProvider], whose ids are also `u32`.
+pub type PoolRef = u32;
 /// Item in the tree of spans tracked by entrace
 #[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct PoolEntry {
@@ -31,6 +57,17 @@ impl PoolEntry {
     pub fn new() -> Self {
         Default::default()
     }
+    /// Push the entry of the next id to `pool`, and list it as a child of `parent`. Returns its id.
+    ///
+    /// The first entry pushed is the root, which is its own parent, but not its own child.
+    pub fn push_linked(pool: &mut Vec<PoolEntry>, parent: PoolRef) -> PoolRef {
+        let id = pool.len() as PoolRef;
+        pool.push(PoolEntry::new());
+        if id != 0 {
+            pool[parent as usize].children.push(id);
+        }
+        id
+    }
 }
 /// A serializable representation of [tracing::Level].
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
@@ -42,6 +79,28 @@ pub enum LevelContainer {
     Warn = 3,
     Error = 4,
 }
+impl LevelContainer {
+    /// Short representation of the level, like `[I]` for [LevelContainer::Info].
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LevelContainer::Trace => "[T]",
+            LevelContainer::Debug => "[D]",
+            LevelContainer::Info => "[I]",
+            LevelContainer::Warn => "[W]",
+            LevelContainer::Error => "[E]",
+        }
+    }
+    /// The name of the level, like `"INFO"` for [LevelContainer::Info].
+    pub fn name(&self) -> &'static str {
+        match self {
+            LevelContainer::Trace => "TRACE",
+            LevelContainer::Debug => "DEBUG",
+            LevelContainer::Info => "INFO",
+            LevelContainer::Warn => "WARN",
+            LevelContainer::Error => "ERROR",
+        }
+    }
+}
 impl From<&tracing::Level> for LevelContainer {
     fn from(value: &tracing::Level) -> Self {
         match *value {
@@ -152,8 +211,21 @@ pub struct Header<'a> {
     pub line: Option<u32>,
     pub message: Option<&'a str>,
 }
+impl Header<'_> {
+    /// The text used to display the span with this header: `[I]: message` if the span has a
+    /// message, or else its name.
+    pub fn display_text(&self, id: u32) -> String {
+        if let Some(message) = self.message {
+            format!("{}: {}", self.level.symbol(), message)
+        } else if id == 0 {
+            "root".to_string()
+        } else {
+            self.name.into()
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum StorageFormat {
     ET = 0,
     IET = 1,
@@ -161,7 +233,112 @@ pub enum StorageFormat {
 }
 /// A copy of entrace supports only one file format, with conversion scripts provided in entrace_convert.
 /// So this is both the "read" and "write" version.
-pub const EN_DISK_VERSION: u8 = 2;
+///
+/// [AttrEncoding::Compact] files share this version: they are marked by [COMPACT_ATTRS_FLAG] in
+/// the storage format byte instead, which clients predating it reject as an unknown format.
+///
+/// Version 3 added [TraceEntry::follows_from]. Version 4 added follows-from links written as
+/// their own records to IET, see [IETRecord], and a tag for `Debug` values to
+/// [AttrEncoding::Compact].
+pub const EN_DISK_VERSION: u8 = 4;
+
+/// The first byte of a follows-from record in an IET trace, see [IETRecord::FollowsFrom]. It is
+/// followed by the bincode encoding of `(id, follows)`.
+///
+/// Entries start with their parent, a bincode varint, which never starts with `0xff`.
+pub const FOLLOWS_FROM_MARKER: u8 = 0xff;
+
+/// Set in the storage format byte (9) of the magic for files using [AttrEncoding::Compact].
+pub const COMPACT_ATTRS_FLAG: u8 = 0x80;
+
+/// The bincode configuration of everything entrace reads and writes.
+///
+/// entrace files and streams are little-endian, whatever machine they are written on: this
+/// config, the length prefixes of [StorageFormat::IETPrefix] (see [encode_length_prefix]), while
+/// the magic is made of single bytes.
+pub const BINCODE_CONFIG: bincode::config::Configuration =
+    bincode::config::standard().with_little_endian();
+
+/// The largest message a length prefix may announce. Entries are nowhere near this large, so
+/// a larger prefix means the stream is corrupt.
+pub const MAX_PREFIXED_LEN: u64 = 1 << 32;
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LengthPrefixError {
+    #[error(
+        "Length prefix {0:#018x} looks byte-swapped. Length prefixes must be little-endian, was \
+         this written by a big-endian writer?"
+    )]
+    ByteSwapped(u64),
+    #[error(
+        "Length prefix {0} is larger than the maximum of {MAX_PREFIXED_LEN} bytes. Does the \
+         producer write entries without length prefixes?"
+    )]
+    TooLarge(u64),
+    /// The entry in a frame of a length-prefixed trace is shorter or longer than the frame.
+    #[error(
+        "An entry doesn't fill its length-prefixed frame of {len} bytes. The producer most likely \
+         writes entries without length prefixes, though the header of the trace says it uses them"
+    )]
+    FrameMismatch { len: u64 },
+    /// An entry of a trace without length prefixes names a parent which comes after it, which is
+    /// what reading length prefixes as entries looks like.
+    #[error(
+        "Entry {id} has parent {parent}, which is not recorded before it. The producer most likely \
+         writes length prefixes, though the header of the trace says it doesn't"
+    )]
+    ParentAfterChild { id: u64, parent: u32 },
+}
+/// An error decoding the record of a length-prefixed frame, see [AttrEncoding::decode_framed_record].
+#[derive(Error, Debug)]
+pub enum FrameError {
+    #[error(transparent)]
+    Decode(#[from] bincode::error::DecodeError),
+    #[error(transparent)]
+    Prefix(#[from] LengthPrefixError),
+}
+/// Check that entry `id` of a trace without length prefixes has its parent recorded before it,
+/// like [TreeLayer] writes them. The root, entry 0, is its own parent.
+///
+/// A length prefix read as an entry gives a garbage parent, so this catches a producer which
+/// writes prefixes, though the header of the trace says it doesn't.
+pub fn check_parent_order(id: u64, parent: u32) -> Result<(), LengthPrefixError> {
+    if parent != 0 && parent as u64 >= id {
+        return Err(LengthPrefixError::ParentAfterChild { id, parent });
+    }
+    Ok(())
+}
+/// Encode the length prefix of a message in a [StorageFormat::IETPrefix] stream.
+pub fn encode_length_prefix(len: u64) -> [u8; 8] {
+    len.to_le_bytes()
+}
+/// Decode a length prefix written by [encode_length_prefix], rejecting lengths over
+/// [MAX_PREFIXED_LEN]. If the prefix would be valid with its bytes swapped, this is reported as
+/// [LengthPrefixError::ByteSwapped].
+pub fn decode_length_prefix(buf: [u8; 8]) -> Result<u64, LengthPrefixError> {
+    let len = u64::from_le_bytes(buf);
+    if len <= MAX_PREFIXED_LEN {
+        Ok(len)
+    } else if len.swap_bytes() <= MAX_PREFIXED_LEN {
+        Err(LengthPrefixError::ByteSwapped(len))
+    } else {
+        Err(LengthPrefixError::TooLarge(len))
+    }
+}
+/// Read the `len` bytes of a frame after its length prefix into `frame`.
+///
+/// `frame` only grows as the bytes arrive, so a corrupt prefix can't allocate
+/// [MAX_PREFIXED_LEN] bytes up front. A frame cut short by the end of `reader` is an
+/// [std::io::ErrorKind::UnexpectedEof] error, like with [Read::read_exact].
+pub fn read_frame_into(
+    reader: &mut impl Read, len: u64, frame: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    frame.clear();
+    let read = reader.take(len).read_to_end(frame)?;
+    if (read as u64) < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
 #[derive(Error, Debug)]
 pub enum LoadTraceError {
     #[error("Failed to parse magic number")]
@@ -184,6 +361,12 @@ pub enum LoadTraceError {
     MmapNeeded,
     #[error("Failed to create IET log provider")]
     IETError(#[from] LoadIETError),
+    #[error(
+        "Tried to load a gzip-compressed file, but you didn't enable the gzip feature for entrace_core"
+    )]
+    GzipNeeded,
+    #[error("Failed to convert the decompressed ET file to IET")]
+    ConvertError(#[from] convert::ConvertError),
 }
 #[derive(Error, Debug)]
 pub enum MagicParseError {
@@ -193,31 +376,56 @@ pub enum MagicParseError {
         "The [1,..,8) (0-indexed) bytes of the trace file should be b\"ENTRACE\" but they aren't"
     )]
     AppNameMismatch,
-    #[error("The storage format byte (9) must be 0 or 1")]
+    #[error(
+        "The storage format byte (9) must be 0, 1 or 2, optionally with the compact attribute \
+         flag (0x80) set"
+    )]
     BadStorageFormat,
     #[error("IO Error while parsing magic. Make sure the file is non-empty.")]
     IoError(#[from] std::io::Error),
+    #[error("The file is gzip-compressed. Only load_trace decompresses it before reading.")]
+    Gzipped,
 }
-pub fn parse_entrace_magic(magic: &[u8; 10]) -> Result<(u8, StorageFormat), MagicParseError> {
+/// Parse a magic, returning the version, the storage format and the attribute encoding.
+pub fn parse_entrace_magic(
+    magic: &[u8; 10],
+) -> Result<(u8, StorageFormat, AttrEncoding), MagicParseError> {
+    if magic[..2] == GZIP_MAGIC {
+        return Err(MagicParseError::Gzipped);
+    }
     if magic[0] != 0 {
         return Err(MagicParseError::FirstNonNull);
     }
     if &magic[1..8] != b"ENTRACE" {
         return Err(MagicParseError::AppNameMismatch);
     }
-    let s = match magic[9] {
+    let encoding = match magic[9] & COMPACT_ATTRS_FLAG {
+        0 => AttrEncoding::Standard,
+        _ => AttrEncoding::Compact,
+    };
+    let s = match magic[9] & !COMPACT_ATTRS_FLAG {
         0 => StorageFormat::ET,
         1 => StorageFormat::IET,
         2 => StorageFormat::IETPrefix,
         _ => return Err(MagicParseError::BadStorageFormat),
     };
-    Ok((magic[8], s))
+    Ok((magic[8], s, encoding))
 }
 
+/// The first two bytes of every gzip stream.
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
 pub fn entrace_magic_for(version: u8, format: StorageFormat) -> [u8; 10] {
+    entrace_magic_with(version, format, AttrEncoding::Standard)
+}
+/// Like [entrace_magic_for], but for a file whose entries use `encoding`.
+pub fn entrace_magic_with(version: u8, format: StorageFormat, encoding: AttrEncoding) -> [u8; 10] {
     let mut magic = [0, 69, 78, 84, 82, 65, 67, 69, 0, 0]; // b"\0ENTRACE" and two temporary 0s
     magic[8] = version;
     magic[9] = format as u8;
+    if encoding == AttrEncoding::Compact {
+        magic[9] |= COMPACT_ATTRS_FLAG;
+    }
     magic
 }
 pub struct LoadConfig<R: Refresh = DummyRefresher> {
@@ -232,10 +440,35 @@ impl Default for LoadConfig {
 pub struct IETLoadConfig<R: Refresh = DummyRefresher> {
     pub watch: FileWatchConfig,
     pub presentation: IETPresentationConfig<R>,
+    /// Runs on every attribute of every span as it is read, see [AttrTransform].
+    pub attr_transform: Option<Arc<dyn AttrTransform>>,
 }
 impl Default for IETLoadConfig {
     fn default() -> Self {
-        Self { watch: FileWatchConfig::DontWatch, presentation: IETPresentationConfig::default() }
+        Self {
+            watch: FileWatchConfig::DontWatch,
+            presentation: IETPresentationConfig::default(),
+            attr_transform: None,
+        }
+    }
+}
+
+/// Rewrites attribute values while an IET trace is loaded, for example to parse a number out of a
+/// string, or to decode a base64 blob.
+///
+/// Transforms run on each span as it is read, before it is added to the tree, so queries and the
+/// GUI only ever see the transformed values. This includes spans appended later to a watched file.
+/// ET files are memory mapped and can't be transformed.
+///
+/// The message of a span is also stored as its `message` attribute. Transforming that attribute
+/// doesn't change the message shown in the tree.
+pub trait AttrTransform: Send + Sync {
+    /// Rewrite `value`, the value of the attribute `name`.
+    fn transform(&self, name: &str, value: &mut EnValue);
+}
+impl<F: Fn(&str, &mut EnValue) + Send + Sync> AttrTransform for F {
+    fn transform(&self, name: &str, value: &mut EnValue) {
+        self(name, value)
     }
 }
 
@@ -261,23 +494,31 @@ impl<R: Refresh> IETPresentationConfig<R> {
 ///
 /// See also [FileIETLogProvider::new] and [remote::load_iht_trace] for functions that read an IET trace,
 /// in a safe way.
+///
+/// Gzip-compressed files (like `.iet.gz`) are detected by their magic and decompressed into memory
+/// (requires the `gzip` feature). A compressed ET file cannot be memory mapped, so it is converted
+/// to IET in memory instead. Compressed files are never watched for changes.
 pub unsafe fn load_trace<R: Refresh + Send + 'static>(
     file_path: &Path, config: LoadConfig<R>,
 ) -> Result<LogProviderImpl, LoadTraceError> {
     let mut file = File::open(file_path)?;
     let mut buf = [0; 10];
     file.read_exact(&mut buf).map_err(|x| LoadTraceError::BadMagic(MagicParseError::IoError(x)))?;
-    let (version, ty) = parse_entrace_magic(&buf)?;
+    if buf[..2] == GZIP_MAGIC {
+        file.seek(SeekFrom::Start(0))?;
+        return load_gzip_trace(file, config);
+    }
+    let (version, ty, encoding) = parse_entrace_magic(&buf)?;
     if version != EN_DISK_VERSION {
         Err(LoadTraceError::InvalidVersion(version))?;
     }
     match ty {
         StorageFormat::IET => {
-            let provider = FileIETLogProvider::new(file, config.iht, false)?;
+            let provider = FileIETLogProvider::new(file, config.iht, false, encoding)?;
             Ok(LogProviderImpl::FileIET(provider))
         }
         StorageFormat::IETPrefix => {
-            let provider = FileIETLogProvider::new(file, config.iht, true)?;
+            let provider = FileIETLogProvider::new(file, config.iht, true, encoding)?;
             Ok(LogProviderImpl::FileIET(provider))
         }
         StorageFormat::ET => {
@@ -295,19 +536,114 @@ pub unsafe fn load_trace<R: Refresh + Send + 'static>(
     }
 }
 
-pub fn display_error_context(mut err: &dyn std::error::Error) -> String {
-    let mut s = format!("{err}");
-    if err.source().is_none() {
+#[cfg(feature = "gzip")]
+fn load_gzip_trace<R: Refresh + Send + 'static>(
+    file: File, config: LoadConfig<R>,
+) -> Result<LogProviderImpl, LoadTraceError> {
+    let compressed_len = file.metadata()?.len();
+    let mut decompressed = vec![];
+    flate2::read::MultiGzDecoder::new(std::io::BufReader::new(file))
+        .read_to_end(&mut decompressed)?;
+    let Some((magic, rest)) = decompressed.split_first_chunk::<10>() else {
+        let eof = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+        return Err(LoadTraceError::BadMagic(MagicParseError::IoError(eof)));
+    };
+    let (version, ty, encoding) = parse_entrace_magic(magic)?;
+    if version != EN_DISK_VERSION {
+        Err(LoadTraceError::InvalidVersion(version))?;
+    }
+    let transform = config.iht.attr_transform.as_deref();
+    let initial = match ty {
+        StorageFormat::IET => remote::load_iet_trace_transformed(rest, false, encoding, transform)?,
+        StorageFormat::IETPrefix => {
+            remote::load_iet_trace_transformed(rest, true, encoding, transform)?
+        }
+        StorageFormat::ET => {
+            let mut iet = vec![];
+            convert::et_to_iet(&mut std::io::Cursor::new(&decompressed), &mut iet, true)?;
+            remote::load_iet_trace_transformed(&iet[10..], false, encoding, transform)?
+        }
+    };
+    let mut base = remote::BaseIETLogProvider::new((), config.iht.presentation, |_, _, _| ());
+    base.pool = initial.pool;
+    base.data = initial.data;
+    base.source_bytes = Some(Arc::new(std::sync::atomic::AtomicU64::new(compressed_len)));
+    Ok(LogProviderImpl::BaseIET(base))
+}
+#[cfg(not(feature = "gzip"))]
+fn load_gzip_trace<R: Refresh + Send + 'static>(
+    _file: File, _config: LoadConfig<R>,
+) -> Result<LogProviderImpl, LoadTraceError> {
+    Err(LoadTraceError::GzipNeeded)
+}
+
+/// Format an error with its chain of sources, one per line.
+pub fn display_error_context(err: &dyn std::error::Error) -> String {
+    let chain = error_chain(err);
+    let mut s = chain[0].clone();
+    if chain.len() == 1 {
         return s;
     }
     write!(s, "\n\nCaused by:\n").ok();
-    let mut idx = 0;
-
-    while let Some(source) = err.source() {
-        write!(s, "  {idx}: {source}").ok();
-        err = source;
-        idx += 1;
+    for (idx, cause) in chain[1..].iter().enumerate() {
+        writeln!(s, "  {idx}: {cause}").ok();
     }
     s.pop();
     s
 }
+
+/// The message of `err`, followed by the messages of its sources, outermost first. For showing
+/// each cause separately, [display_error_context] formats them as one string.
+pub fn error_chain(err: &dyn std::error::Error) -> Vec<String> {
+    let mut chain = vec![err.to_string()];
+    let mut source = err.source();
+    while let Some(x) = source {
+        chain.push(x.to_string());
+        source = x.source();
+    }
+    chain
+}
+
+/// Remove ANSI escape sequences, like the SGR sequences which color terminal output, from `text`.
+///
+/// Programs which log pre-colored strings end up with these in their attributes, where they only
+/// make sense to a terminal. Handles CSI (`ESC [ ... final byte`), OSC (`ESC ] ... BEL` or
+/// `ESC ] ... ESC \`) and the other `ESC` sequences, like `ESC ( B`. A sequence cut off by the end
+/// of `text` is removed too.
+pub fn strip_ansi_escapes(text: &str) -> Cow<'_, str> {
+    if !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // parameter and intermediate bytes, ended by a final byte in `@..=~`
+            Some('[') => {
+                chars.by_ref().find(|c| ('@'..='~').contains(c));
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' {
+                        // the `\` of the string terminator
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // intermediate bytes, ended by a final byte, like `ESC ( B`
+            Some(c) if (' '..='/').contains(&c) => {
+                chars.by_ref().find(|c| !(' '..='/').contains(c));
+            }
+            _ => (),
+        }
+    }
+    Cow::Owned(out)
+}
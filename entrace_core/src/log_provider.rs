@@ -1,5 +1,13 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    ops::RangeInclusive,
+    str::FromStr,
+    thread::JoinHandle,
+};
+
 use crate::{
-    Header, MetadataRefContainer,
+    Header, MagicParseError, MetadataRefContainer,
     remote::{
         BaseIETLogProvider, FileIETError, FileIETLogProvider, RemoteLogProvider,
         RemoteLogProviderError,
@@ -14,6 +22,14 @@ pub enum LogProviderError {
     // TODO: investigate if boxing here would result in better or worse performance
     #[error("Failed to decode a binary value")]
     DecodeError(#[from] bincode::error::DecodeError),
+    /// A trace entry which can't be decoded while streaming, `offset` bytes into the stream or
+    /// file.
+    #[error("Failed to decode the entry at byte {offset}")]
+    DecodeAt {
+        offset: u64,
+        #[source]
+        source: bincode::error::DecodeError,
+    },
     #[error(transparent)]
     IO(#[from] std::io::Error),
 
@@ -21,19 +37,141 @@ pub enum LogProviderError {
     FileIETError(#[from] FileIETError),
     #[error(transparent)]
     RemoteLogProviderError(#[from] RemoteLogProviderError),
+    #[error("Failed to parse magic")]
+    BadMagic(#[from] MagicParseError),
+    #[error(transparent)]
+    LengthPrefix(#[from] crate::LengthPrefixError),
     /// This is not actually an error, we just use it to signal that the lua vm should quit
     #[error("This thread was shutdown during a join")]
     JoinShutdown,
 }
 pub type LogProviderResult<T> = Result<T, LogProviderError>;
+
+/// A run of consecutive span ids which are not properly linked into the tree, found by
+/// [LogProvider::find_gaps].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GapReport {
+    pub ids: RangeInclusive<u32>,
+    pub kind: GapKind,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapKind {
+    /// The parent of the span doesn't exist (or is the span itself), for example because it was
+    /// lost in transit.
+    MissingParent,
+    /// The parent exists, but doesn't list the span as its child, so the span is not reachable
+    /// from the root.
+    Unreferenced,
+}
+/// How [LogProvider::aggregate_children] combines the values of an attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateOp {
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Count,
+}
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown aggregate `{0}`, expected one of sum, avg, min, max, count")]
+pub struct UnknownAggregateOp(pub String);
+impl AggregateOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggregateOp::Sum => "sum",
+            AggregateOp::Avg => "avg",
+            AggregateOp::Min => "min",
+            AggregateOp::Max => "max",
+            AggregateOp::Count => "count",
+        }
+    }
+}
+impl FromStr for AggregateOp {
+    type Err = UnknownAggregateOp;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use AggregateOp::*;
+        [Sum, Avg, Min, Max, Count]
+            .into_iter()
+            .find(|x| x.name() == s)
+            .ok_or_else(|| UnknownAggregateOp(s.to_string()))
+    }
+}
+impl Display for AggregateOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An [EnValueRef] which can be hashed, to find distinct values. Floats are compared bitwise.
+#[derive(Hash, PartialEq, Eq)]
+enum ValueKey<'a> {
+    String(&'a str),
+    Bytes(&'a [u8]),
+    Bool(bool),
+    Float(u64),
+    U64(u64),
+    I64(i64),
+    U128(u128),
+    I128(i128),
+    Debug(&'a str),
+}
+impl<'a> From<EnValueRef<'a>> for ValueKey<'a> {
+    fn from(value: EnValueRef<'a>) -> Self {
+        match value {
+            EnValueRef::String(x) => ValueKey::String(x),
+            EnValueRef::Bytes(x) => ValueKey::Bytes(x),
+            EnValueRef::Bool(x) => ValueKey::Bool(x),
+            EnValueRef::Float(x) => ValueKey::Float(x.to_bits()),
+            EnValueRef::U64(x) => ValueKey::U64(x),
+            EnValueRef::I64(x) => ValueKey::I64(x),
+            EnValueRef::U128(x) => ValueKey::U128(x),
+            EnValueRef::I128(x) => ValueKey::I128(x),
+            EnValueRef::Debug(x) => ValueKey::Debug(x),
+        }
+    }
+}
 #[allow(clippy::len_without_is_empty)]
 /// The primary interface to read spans out of entrace traces.
 ///
 /// Get one with [crate::load_trace] or [crate::remote::RemoteLogProvider].
+///
+/// Id 0 is always the root: `parent(0)` is 0, and `children(0)` succeeds, even for an empty trace,
+/// like a remote one before the client sent anything. Until its root arrives, such a trace has a
+/// placeholder root without children, and [LogProvider::len] is 0.
 pub trait LogProvider {
     fn children(&self, idx: u32) -> LogProviderResult<&[u32]>;
+    /// The parent of `idx`. The root is its own parent.
     fn parent(&self, idx: u32) -> LogProviderResult<u32>;
 
+    /// Whether the entry was recorded from a `tracing` event, as opposed to a span.
+    ///
+    /// Files don't record the kind of an entry, so by default this falls back to a heuristic:
+    /// events never have children, so every leaf is considered an event. This means spans that
+    /// were entered without logging anything are reported as events too.
+    fn is_event(&self, idx: u32) -> LogProviderResult<bool> {
+        Ok(idx != 0 && self.children(idx)?.is_empty())
+    }
+    /// The children of `idx` which are events, see [LogProvider::is_event].
+    fn child_events(&self, idx: u32) -> LogProviderResult<Vec<u32>> {
+        let mut events = vec![];
+        for &child in self.children(idx)? {
+            if self.is_event(child)? {
+                events.push(child);
+            }
+        }
+        Ok(events)
+    }
+    /// The children of `idx` which are spans, see [LogProvider::is_event].
+    fn child_spans(&self, idx: u32) -> LogProviderResult<Vec<u32>> {
+        let mut spans = vec![];
+        for &child in self.children(idx)? {
+            if !self.is_event(child)? {
+                spans.push(child);
+            }
+        }
+        Ok(spans)
+    }
+
     fn attr_names(&'_ self, idx: u32) -> LogProviderResult<Vec<&'_ str>>;
     fn attr_values(&'_ self, idx: u32) -> LogProviderResult<Vec<EnValueRef<'_>>>;
     /// Equivalent to a search on attr_names/attr_values, but might be faster depending on the
@@ -44,12 +182,183 @@ pub trait LogProvider {
         Ok(attr_names.iter().position(|&k| k == name).map(|i| attr_values[i].clone()))
     }
 
+    /// The names of all attributes that appear on at least one span, sorted and deduplicated.
+    /// This reads every span in the trace, so callers should cache the result.
+    fn distinct_attr_names(&self) -> LogProviderResult<Vec<String>> {
+        let mut names = BTreeSet::new();
+        for idx in 0..self.len() as u32 {
+            names.extend(self.attr_names(idx)?);
+        }
+        Ok(names.into_iter().map(|x| x.to_string()).collect())
+    }
+
+    /// The number of distinct values of attribute `name`, over every span having it. Values of
+    /// different types are distinct, even if they print the same, like `1` and `"1"`.
+    /// This reads every span in the trace, so callers should cache the result.
+    fn attr_cardinality(&self, name: &str) -> LogProviderResult<usize> {
+        let mut values = HashSet::new();
+        for idx in 0..self.len() as u32 {
+            let names = self.attr_names(idx)?;
+            let attrs = names.into_iter().zip(self.attr_values(idx)?);
+            values.extend(attrs.filter(|(k, _)| *k == name).map(|(_, v)| ValueKey::from(v)));
+        }
+        Ok(values.len())
+    }
+    /// [LogProvider::attr_cardinality] of every attribute, by name, in one pass over the trace.
+    fn attr_cardinalities(&self) -> LogProviderResult<BTreeMap<String, usize>> {
+        let mut values: HashMap<&str, HashSet<ValueKey>> = HashMap::new();
+        for idx in 0..self.len() as u32 {
+            let names = self.attr_names(idx)?;
+            for (name, value) in names.into_iter().zip(self.attr_values(idx)?) {
+                values.entry(name).or_default().insert(ValueKey::from(value));
+            }
+        }
+        Ok(values.into_iter().map(|(name, values)| (name.to_string(), values.len())).collect())
+    }
+
+    /// Combine attribute `attr` of the direct children of `parent` with `op`, like the total
+    /// latency of the requests under a span.
+    ///
+    /// Only numeric values count, with booleans as 0 or 1. Children without `attr`, or with a
+    /// string or bytes value for it, are skipped, and so is every value after the first if a name
+    /// is repeated. `Count` is the number of children with a numeric value. If there are none,
+    /// `Sum` and `Count` are 0, and the others `None`.
+    fn aggregate_children(
+        &self, parent: u32, attr: &str, op: AggregateOp,
+    ) -> LogProviderResult<Option<f64>> {
+        let (mut count, mut sum) = (0usize, 0.0);
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for &child in self.children(parent)? {
+            let Some(x) = self.attr_value(child, attr)?.and_then(|x| x.as_f64()) else {
+                continue;
+            };
+            count += 1;
+            sum += x;
+            min = min.min(x);
+            max = max.max(x);
+        }
+        Ok(match op {
+            AggregateOp::Sum => Some(sum),
+            AggregateOp::Count => Some(count as f64),
+            _ if count == 0 => None,
+            AggregateOp::Avg => Some(sum / count as f64),
+            AggregateOp::Min => Some(min),
+            AggregateOp::Max => Some(max),
+        })
+    }
+
+    /// The smallest and largest span id, or `None` if there are no spans.
+    /// Ids are contiguous, so every id in between is valid too.
+    fn id_bounds(&self) -> Option<(u32, u32)> {
+        (self.len() > 0).then(|| (0, self.len() as u32 - 1))
+    }
+
+    /// Find spans which are not linked into the tree properly, which indicates that the trace is
+    /// corrupt, or that entries were lost while streaming it.
+    /// Consecutive ids with the same problem are reported as one range.
+    ///
+    /// This reads the parent and children of every span, so callers should cache the result.
+    fn find_gaps(&self) -> LogProviderResult<Vec<GapReport>> {
+        let Some((_, max)) = self.id_bounds() else { return Ok(vec![]) };
+        let mut referenced = vec![false; max as usize + 1];
+        for idx in 0..=max {
+            for &child in self.children(idx)? {
+                if child <= max && self.parent(child)? == idx {
+                    referenced[child as usize] = true;
+                }
+            }
+        }
+        let mut reports: Vec<GapReport> = vec![];
+        // the root is its own parent
+        for idx in 1..=max {
+            let parent = self.parent(idx)?;
+            let kind = if parent > max || parent == idx {
+                GapKind::MissingParent
+            } else if !referenced[idx as usize] {
+                GapKind::Unreferenced
+            } else {
+                continue;
+            };
+            match reports.last_mut() {
+                Some(last) if last.kind == kind && *last.ids.end() + 1 == idx => {
+                    last.ids = *last.ids.start()..=idx;
+                }
+                _ => reports.push(GapReport { ids: idx..=idx, kind }),
+            }
+        }
+        Ok(reports)
+    }
+
+    /// The ids on a longest path from the root down to a span, starting with the root. If there
+    /// are several, this is the first one in tree order.
+    ///
+    /// This is one depth-first walk over the tree, without recursion, so it handles arbitrarily
+    /// deep traces. Children with an id not larger than their parent's can only come from a corrupt
+    /// trace, and are skipped, so that a cycle can't make it loop forever.
+    fn max_depth_path(&self) -> LogProviderResult<Vec<u32>> {
+        let len = self.len().max(1);
+        // the parent each span was reached from, to walk back from the deepest one
+        let mut reached_from = vec![0; len];
+        let (mut deepest, mut max_depth) = (0, 0);
+        let mut stack = vec![(0, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            if depth > max_depth {
+                (deepest, max_depth) = (id, depth);
+            }
+            // reversed, so that the first child is visited first
+            for &child in self.children(id)?.iter().rev() {
+                if child > id && (child as usize) < len {
+                    reached_from[child as usize] = id;
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        let mut path = vec![deepest];
+        while deepest != 0 {
+            deepest = reached_from[deepest as usize];
+            path.push(deepest);
+        }
+        path.reverse();
+        Ok(path)
+    }
+    /// The number of spans between the root and the deepest span, which is 0 for a trace without
+    /// spans. See [LogProvider::max_depth_path].
+    fn max_depth(&self) -> LogProviderResult<usize> {
+        Ok(self.max_depth_path()?.len() - 1)
+    }
+
     fn header(&'_ self, idx: u32) -> LogProviderResult<Header<'_>>;
     fn meta(&'_ self, idx: u32) -> LogProviderResult<MetadataRefContainer<'_>>;
+    /// Like [LogProvider::header], but the message is taken from the string attribute
+    /// `message_attr`, for traces which log their text under another name than `message`.
+    /// Spans without such an attribute keep their own message.
+    fn header_with_message_attr(
+        &'_ self, idx: u32, message_attr: &str,
+    ) -> LogProviderResult<Header<'_>> {
+        let mut header = self.header(idx)?;
+        if message_attr == "message" {
+            return Ok(header);
+        }
+        let names = self.attr_names(idx)?;
+        if let Some(pos) = names.iter().position(|x| *x == message_attr)
+            && let Some(EnValueRef::String(message)) = self.attr_values(idx)?.get(pos)
+        {
+            header.message = Some(message);
+        }
+        Ok(header)
+    }
     /// Equivalent to header.message, but some implementations might offer a fast path for this.
     fn message(&'_ self, idx: u32) -> LogProviderResult<Option<&'_ str>> {
         Ok(self.header(idx)?.message)
     }
+    /// The spans `idx` follows from, as recorded with `tracing`'s `Span::follows_from`, in the
+    /// order they were recorded. These are causal links in addition to the parent, for example to
+    /// the span which scheduled an async task.
+    ///
+    /// Implemented by default as an empty list, for providers which don't keep these links.
+    fn follows_from(&self, _idx: u32) -> LogProviderResult<Vec<u32>> {
+        Ok(vec![])
+    }
 
     /// The total amount of messages in this provider.
     /// This MUST be cheap as the frontend might call this every frame.
@@ -61,6 +370,133 @@ pub trait LogProvider {
     /// The [LogProvider] implementation MUST ensure that this terminates quickly,
     /// as it directly affects FPS.
     fn frame_callback(&mut self) {}
+
+    /// A copy of the trace as it is now, which doesn't change as this provider receives new data.
+    ///
+    /// Use this to read a [live](LogProvider::is_live) trace for a long time, like for an export,
+    /// without holding a lock which blocks updates. The snapshot never grows.
+    ///
+    /// For traces held in memory this clones every span, so it takes about as much memory as the
+    /// trace itself, and a trace which barely fits in memory can't be snapshotted. Memory mapped
+    /// traces share the mapping, and only copy the offset table and child lists.
+    fn snapshot(&self) -> Box<dyn LogProvider + Send + Sync>;
+
+    /// Whether the trace may still grow, for example because a file is watched or a server is
+    /// still connected. The frontend can skip polling for new data if this is false.
+    /// Like [LogProvider::len], this MUST be cheap.
+    fn is_live(&self) -> bool {
+        false
+    }
+
+    /// The size of the trace as it was read: the size of the file for traces loaded from one
+    /// (compressed, if it is), or the bytes received so far for streamed ones.
+    ///
+    /// `None` for traces which were not read from anywhere, like ones built in memory. This may
+    /// stat the file, so don't call it every frame.
+    fn source_bytes(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A compact, single-line summary of span `id`: `[I] target name message {k=v, ...}`.
+///
+/// The message is left out if the span has none, and the braces if it has no other attributes.
+/// Bytes are written in hex, and line breaks in strings as `\n`, so the result never spans
+/// more than one line.
+pub fn format_span_oneline(
+    log: &(impl LogProvider + ?Sized), id: u32,
+) -> LogProviderResult<String> {
+    use std::fmt::Write;
+    let meta = log.meta(id)?;
+    let mut out = format!("{} {} {}", meta.level.symbol(), meta.target, meta.name);
+    let message = log.message(id)?;
+    if let Some(message) = message {
+        out.push(' ');
+        push_escaped(&mut out, message);
+    }
+    let names = log.attr_names(id)?;
+    let values = log.attr_values(id)?;
+    // the message is also stored as an attribute, which is already written
+    let mut skip_message = message.is_some();
+    let mut first = true;
+    for (name, value) in names.into_iter().zip(values) {
+        if skip_message && name == "message" {
+            skip_message = false;
+            continue;
+        }
+        out.push_str(if first { " {" } else { ", " });
+        first = false;
+        out.push_str(name);
+        out.push('=');
+        match value {
+            EnValueRef::String(x) | EnValueRef::Debug(x) => push_escaped(&mut out, x),
+            EnValueRef::Bytes(x) => {
+                out.push_str("0x");
+                x.iter().for_each(|b| write!(out, "{b:02x}").unwrap());
+            }
+            other => write!(out, "{other}").unwrap(),
+        }
+    }
+    if !first {
+        out.push('}');
+    }
+    Ok(out)
+}
+/// The position of span `id` in the tree, as the child indices leading to it from the root,
+/// like `0/3/12/1`: the root is `0`, and `0/3` is the fourth child of the root.
+///
+/// Unlike the id, this stays the same in another recording of the same program, as long as the
+/// spans before it in tree order are the same. `None` if the span is not reachable from the root.
+/// See [resolve_span_path] for the way back.
+pub fn span_path(log: &(impl LogProvider + ?Sized), id: u32) -> LogProviderResult<Option<String>> {
+    let mut indices = vec![];
+    let mut current = id;
+    while current != 0 {
+        // a corrupt trace may have a cycle, which never reaches the root
+        if indices.len() > log.len() {
+            return Ok(None);
+        }
+        let parent = log.parent(current)?;
+        let Some(idx) = log.children(parent)?.iter().position(|&x| x == current) else {
+            return Ok(None);
+        };
+        indices.push(idx);
+        current = parent;
+    }
+    let mut path = String::from("0");
+    for idx in indices.iter().rev() {
+        path.push('/');
+        path.push_str(&idx.to_string());
+    }
+    Ok(Some(path))
+}
+/// The span at a path written by [span_path]. `None` if the path is malformed, or there is no
+/// span at it.
+pub fn resolve_span_path(
+    log: &(impl LogProvider + ?Sized), path: &str,
+) -> LogProviderResult<Option<u32>> {
+    let mut parts = path.trim().split('/');
+    if parts.next() != Some("0") {
+        return Ok(None);
+    }
+    let mut current = 0;
+    for part in parts {
+        let Ok(idx) = part.parse::<usize>() else { return Ok(None) };
+        match log.children(current)?.get(idx) {
+            Some(&child) => current = child,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+fn push_escaped(out: &mut String, x: &str) {
+    for c in x.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
 }
 
 pub enum LogProviderImpl {
@@ -71,6 +507,33 @@ pub enum LogProviderImpl {
     Mmap(crate::mmap::MmapLogProvider),
 }
 
+impl LogProviderImpl {
+    /// Stop the worker thread of the provider, if it has one, and free the trace.
+    ///
+    /// Returns the handle of the worker, which exits soon after, for waiting on it. Dropping the
+    /// provider stops the worker too.
+    pub fn close(self) -> Option<JoinHandle<()>> {
+        match self {
+            LogProviderImpl::BaseIET(x) => Some(x.close()),
+            LogProviderImpl::FileIET(x) => Some(x.close()),
+            LogProviderImpl::Remote(x) => Some(x.close()),
+            #[cfg(feature = "mmap")]
+            LogProviderImpl::Mmap(_) => None,
+        }
+    }
+    /// How many messages wait for [LogProvider::frame_callback] to add them to the trace, for
+    /// example while the frontend pauses updates. Always 0 for traces which don't grow.
+    pub fn pending_messages(&self) -> usize {
+        match self {
+            LogProviderImpl::BaseIET(x) => x.pending_messages(),
+            LogProviderImpl::FileIET(x) => x.pending_messages(),
+            LogProviderImpl::Remote(x) => x.pending_messages(),
+            #[cfg(feature = "mmap")]
+            LogProviderImpl::Mmap(_) => 0,
+        }
+    }
+}
+
 macro_rules! dispatch {
     (fn $name:ident ( $($varname:ident : $vart:ty),* ) -> $res: ty) => {
         fn $name(&self, $($varname : $vart),*) -> $res {
@@ -87,13 +550,18 @@ macro_rules! dispatch {
 impl LogProvider for LogProviderImpl {
     dispatch!(fn children(x: u32)-> LogProviderResult<&[u32]>);
     dispatch!(fn parent(x: u32)-> LogProviderResult<u32>);
+    dispatch!(fn is_event(x: u32)-> LogProviderResult<bool>);
     dispatch!(fn attr_names(x: u32)-> LogProviderResult<Vec<&'_ str>>);
     dispatch!(fn attr_values(x: u32)-> LogProviderResult<Vec<EnValueRef<'_>>>);
     dispatch!(fn attr_value(x: u32, name: &str)-> LogProviderResult<Option<EnValueRef<'_>>>);
     dispatch!(fn header(x: u32)-> LogProviderResult<Header<'_>>);
     dispatch!(fn message(x: u32)-> LogProviderResult<Option<&str>>);
     dispatch!(fn meta(x: u32)-> LogProviderResult<MetadataRefContainer<'_>>);
+    dispatch!(fn follows_from(x: u32)-> LogProviderResult<Vec<u32>>);
     dispatch!(fn len()-> usize);
+    dispatch!(fn is_live()-> bool);
+    dispatch!(fn source_bytes()-> Option<u64>);
+    dispatch!(fn snapshot()-> Box<dyn LogProvider + Send + Sync>);
 
     fn frame_callback(&mut self) {
         match self {
@@ -2,21 +2,22 @@ use std::{
     any::Any,
     collections::BTreeMap,
     io::{BufReader, BufWriter, Read, Seek, Write},
-    sync::RwLock,
+    sync::{Arc, OnceLock, RwLock},
     thread::JoinHandle,
 };
 
 use crate::{
-    EN_DISK_VERSION, EnValueRef, MixedTraceEntry, PoolEntry, TraceEntry,
+    AttrEncoding, EN_DISK_VERSION, EnValueRef, MixedTraceEntry, PoolEntry, TraceEntry,
     convert::{self, ConvertError, IETTableDataRef},
-    entrace_magic_for,
+    encode_follows_from_record, entrace_magic_with,
     mmap::ETShutdownValue,
-    storage::Storage,
-    tree_layer::EnValue,
+    storage::{PendingEntries, Storage},
+    tree_layer::{EnValue, SpanLifecycle},
 };
 
 pub enum Message<Q: FileLike + Send> {
     Entry { id: u32, entry: MixedTraceEntry },
+    FollowsFrom { id: u32, follows: u32 },
     Shutdown(Q),
 }
 #[derive(thiserror::Error, Debug)]
@@ -43,59 +44,71 @@ pub enum ETStorageError<T: FileLike> {
 pub trait FileLike: Read + Write + Seek {}
 impl<T: Read + Write + Seek> FileLike for T {}
 pub type ETResult<A, T> = Result<A, ETStorageError<T>>;
+pub type ETWorkerHandle<T, Q> = JoinHandle<ETResult<ETShutdownValue<T, Q>, T>>;
 pub struct ETStorage<T: FileLike, Q: FileLike + Send> {
     pub sender: crossbeam_channel::Sender<Message<Q>>,
-    pub thread_handle: RwLock<Option<JoinHandle<ETResult<ETShutdownValue<T, Q>, T>>>>,
+    pub thread_handle: RwLock<Option<ETWorkerHandle<T, Q>>>,
+    /// Summarized when the storage is finished, see [Storage::set_lifecycle].
+    pub lifecycle: OnceLock<Arc<SpanLifecycle>>,
 }
 impl<T: FileLike + Send + 'static, Q: FileLike + Send + 'static> ETStorage<T, Q> {
-    pub fn init(mut file: T) -> Self
+    pub fn init(file: T) -> Self
+    where
+        Self: std::marker::Sized,
+    {
+        Self::init_with_attr_encoding(file, AttrEncoding::Standard)
+    }
+
+    /// Like [ETStorage::init], but writes attribute values in `attr_encoding`.
+    pub fn init_with_attr_encoding(mut file: T, attr_encoding: AttrEncoding) -> Self
     where
         Self: std::marker::Sized,
     {
         let (tx, rx) = crossbeam_channel::unbounded::<Message<Q>>();
         let thread_handle = std::thread::spawn(move || {
-            let magic = entrace_magic_for(EN_DISK_VERSION, crate::StorageFormat::IET);
+            let magic =
+                entrace_magic_with(EN_DISK_VERSION, crate::StorageFormat::IET, attr_encoding);
             file.write_all(&magic).unwrap();
             let mut writer = BufWriter::new(&mut file);
             // Offsets relative to the start of the data section
             let mut offsets = vec![0u64];
+            let mut lengths = vec![];
             let mut child_lists = vec![PoolEntry::new()];
+            // links made after their entry was written, which the conversion adds to it
+            let mut follows_from: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
             let mut cur_offset = 0u64;
-            let config = bincode::config::standard();
-            let len =
-                bincode::serde::encode_into_std_write(TraceEntry::root(), &mut writer, config)
-                    .unwrap();
+            let len = attr_encoding.encode_entry(&TraceEntry::root(), &mut writer).unwrap();
+            lengths.push(len as u64);
             cur_offset += len as u64;
 
-            // If multiple threads are writing subspans to the same parent, there can be a race
-            // condition where a child arrives before the parent. This is reproducable on Windows
-            // only, see issue #4.
-            // To prevent this, we store disjoint leaves in a buffer until their parent arrives.
-            // next_id keeps track of what id comes next in sequential order. if the buffer has next_id, we are good and the entry can be written.
-            // Else we wait for it to appear.
-            let mut buffer = BTreeMap::new();
-            let mut next_id = 1;
-
+            let mut pending = PendingEntries::new();
             while let Ok(msg) = rx.recv() {
                 match msg {
                     Message::Entry { id, entry } => {
-                        buffer.insert(id, entry);
-                        while let Some(entry) = buffer.remove(&next_id) {
+                        pending.insert(id, entry);
+                        while let Some(entry) = pending.pop_ready() {
                             offsets.push(cur_offset);
                             let len = child_lists.len() as u32;
                             child_lists[entry.parent as usize].children.push(len);
                             child_lists.push(PoolEntry::new());
-                            let cfg = config;
-                            let written =
-                                bincode::serde::encode_into_std_write(&entry, &mut writer, cfg)
-                                    .unwrap();
-                            cur_offset += written as u64;
-                            next_id += 1;
+                            let written = attr_encoding.encode_mixed_entry(&entry, &mut writer);
+                            let written = written.unwrap() as u64;
+                            lengths.push(written);
+                            cur_offset += written;
+                        }
+                    }
+                    Message::FollowsFrom { id, follows } => {
+                        if !pending.add_follows_from(id, follows) {
+                            // also written to the IET file, which is kept if the conversion fails
+                            let written = encode_follows_from_record(id, follows, &mut writer);
+                            cur_offset += written.unwrap() as u64;
+                            follows_from.entry(id).or_default().push(follows);
                         }
                     }
                     Message::Shutdown(mut tmp_buf) => {
                         let mut tmp_buf_writer = BufWriter::new(&mut tmp_buf);
-                        let table_data = IETTableDataRef::new(&offsets, &child_lists);
+                        let table_data =
+                            IETTableDataRef::new(&offsets, &lengths, &child_lists, &follows_from);
                         writer.flush().ok();
                         drop(writer);
                         let mut old_reader = BufReader::new(&mut file);
@@ -121,7 +134,11 @@ impl<T: FileLike + Send + 'static, Q: FileLike + Send + 'static> ETStorage<T, Q>
             Ok(ETShutdownValue { temp_buf: None, iet_buf: None })
         });
 
-        Self { sender: tx, thread_handle: RwLock::new(Some(thread_handle)) }
+        Self {
+            sender: tx,
+            thread_handle: RwLock::new(Some(thread_handle)),
+            lifecycle: OnceLock::new(),
+        }
     }
 
     pub fn finish(&self, param: Q) -> Result<ETShutdownValue<T, Q>, ETStorageError<T>> {
@@ -129,7 +146,11 @@ impl<T: FileLike + Send + 'static, Q: FileLike + Send + 'static> ETStorage<T, Q>
         self.sender.send(Message::Shutdown(param)).map_err(|_| ShutdownSend)?;
         let mut thread_handle = self.thread_handle.write().map_err(|_| Poisoned)?;
         let thread_handle = std::mem::take(&mut *thread_handle).ok_or(NoHandle)?;
-        thread_handle.join().map_err(ThreadJoin)?
+        let result = thread_handle.join().map_err(ThreadJoin)?;
+        if let Some(lifecycle) = self.lifecycle.get() {
+            lifecycle.log_summary();
+        }
+        result
     }
 }
 impl<T: FileLike + Send + 'static, Q: FileLike + Send + 'static> Storage for ETStorage<T, Q> {
@@ -152,4 +173,10 @@ impl<T: FileLike + Send + 'static, Q: FileLike + Send + 'static> Storage for ETS
 
         self.sender.send(Message::Entry { id, entry }).ok();
     }
+    fn follows_from(&self, id: u32, follows: u32) {
+        self.sender.send(Message::FollowsFrom { id, follows }).ok();
+    }
+    fn set_lifecycle(&self, lifecycle: Arc<SpanLifecycle>) {
+        self.lifecycle.set(lifecycle).ok();
+    }
 }
@@ -1,19 +1,23 @@
-use std::fs::File;
+use std::{fs::File, sync::Arc};
 
 use memmap2::{Mmap, MmapOptions};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    Header, LevelContainer, MetadataRefContainer, PoolEntry, TraceEntryRef,
+    AttrEncoding, Header, LevelContainer, MagicParseError, MetadataRefContainer, PoolEntry,
+    entry::find_attr,
     log_provider::{LogProvider, LogProviderError, LogProviderResult},
+    parse_entrace_magic,
     tree_layer::EnValueRef,
 };
 
 pub struct MmapLogProvider {
-    map: Mmap,
+    /// Shared with snapshots, see [LogProvider::snapshot].
+    map: Arc<Mmap>,
     pub offset_table: Vec<u64>,
     pub child_lists: Vec<PoolEntry>,
     pub entries_start_offset: usize,
+    pub attr_encoding: AttrEncoding,
 }
 #[derive(Debug, thiserror::Error)]
 pub enum MmapError {
@@ -23,6 +27,8 @@ pub enum MmapError {
     DecodeOffsetTable(#[source] bincode::error::DecodeError),
     #[error("Failed to decode the child-list pool")]
     DecodePool(#[source] bincode::error::DecodeError),
+    #[error("Failed to parse magic")]
+    BadMagic(#[from] MagicParseError),
 }
 impl MmapLogProvider {
     /// # Safety
@@ -31,6 +37,10 @@ impl MmapLogProvider {
     pub unsafe fn from_file(file: &File) -> Result<Self, MmapError> {
         use MmapError::*;
         let map = unsafe { MmapOptions::new().map(file) }.map_err(MapFileError)?;
+        let magic = map
+            .first_chunk::<10>()
+            .ok_or_else(|| MagicParseError::IoError(std::io::ErrorKind::UnexpectedEof.into()))?;
+        let (_version, _format, attr_encoding) = parse_entrace_magic(magic)?;
         let mut offset = 10;
         let (offset_table, offset_table_len): (Vec<u64>, usize) =
             bincode::serde::borrow_decode_from_slice(&map[offset..], CFG)
@@ -39,7 +49,13 @@ impl MmapLogProvider {
         let (child_lists, pool_len): (Vec<PoolEntry>, usize) =
             bincode::serde::decode_from_slice(&map[offset..], CFG).map_err(DecodePool)?;
         offset += pool_len;
-        Ok(Self { map, offset_table, child_lists, entries_start_offset: offset })
+        Ok(Self {
+            map: Arc::new(map),
+            offset_table,
+            child_lists,
+            entries_start_offset: offset,
+            attr_encoding,
+        })
     }
     pub fn offset_of(&self, id: u32) -> Result<usize, LogProviderError> {
         self.offset_table
@@ -48,14 +64,16 @@ impl MmapLogProvider {
             .ok_or_else(|| LogProviderError::OutOfBounds { idx: id as usize, len: self.len() })
     }
 }
-const CFG: bincode::config::Configuration = bincode::config::standard();
+const CFG: bincode::config::Configuration = crate::BINCODE_CONFIG;
 impl LogProvider for MmapLogProvider {
     fn children(&self, x: u32) -> LogProviderResult<&[u32]> {
         let idx = x as usize;
-        self.child_lists
-            .get(idx)
-            .map(|x| x.children.as_slice())
-            .ok_or_else(|| LogProviderError::OutOfBounds { idx, len: self.len() })
+        match self.child_lists.get(idx) {
+            Some(entry) => Ok(entry.children.as_slice()),
+            // an empty trace, converted from an IET file without a root
+            None if idx == 0 => Ok(&[]),
+            None => Err(LogProviderError::OutOfBounds { idx, len: self.len() }),
+        }
     }
 
     fn attr_names(&'_ self, idx: u32) -> LogProviderResult<Vec<&'_ str>> {
@@ -75,12 +93,15 @@ impl LogProvider for MmapLogProvider {
     }
     fn attr_values(&'_ self, idx: u32) -> LogProviderResult<Vec<EnValueRef<'_>>> {
         let offset = self.offset_of(idx)?;
-        let decoded: (TraceEntryRef, usize) =
-            bincode::serde::borrow_decode_from_slice(&self.map[offset..], CFG)?;
-        Ok(decoded.0.attr_values)
+        Ok(self.attr_encoding.borrow_decode_attr_values(&self.map[offset..])?)
     }
     fn attr_value(&self, x: u32, name: &str) -> LogProviderResult<Option<EnValueRef<'_>>> {
         let offset = self.offset_of(x)?;
+        if self.attr_encoding == AttrEncoding::Compact {
+            // compact values can't be skipped to individually, decode them all
+            let Some(idx) = find_attr(&self.attr_names(x)?, name) else { return Ok(None) };
+            return Ok(self.attr_values(x)?.get(idx).cloned());
+        }
         #[derive(Serialize, Deserialize, Clone, Debug)]
         pub struct EntryHead<'a> {
             pub parent: u32,
@@ -95,13 +116,13 @@ impl LogProvider for MmapLogProvider {
         // TODO:: could optimize further by lazily decoding values
         let (decoded, len): (EntryHead, _) =
             bincode::serde::borrow_decode_from_slice(&self.map[offset..], CFG)?;
-        match decoded.attr_names.binary_search(&name) {
-            Ok(idx) => {
+        match find_attr(&decoded.attr_names, name) {
+            Some(idx) => {
                 let (values, _): (Vec<EnValueRef<'_>>, _) =
                     bincode::serde::borrow_decode_from_slice(&self.map[offset + len..], CFG)?;
                 Ok(Some(values[idx].clone()))
             }
-            Err(_) => Ok(None),
+            None => Ok(None),
         }
     }
 
@@ -158,11 +179,33 @@ impl LogProvider for MmapLogProvider {
 
         Ok(decoded.0.metadata)
     }
+    fn follows_from(&self, x: u32) -> LogProviderResult<Vec<u32>> {
+        let offset = self.offset_of(x)?;
+        Ok(self.attr_encoding.decode_follows_from(&self.map[offset..])?)
+    }
+    fn source_bytes(&self) -> Option<u64> {
+        Some(self.map.len() as u64)
+    }
+
     fn len(&self) -> usize {
         self.child_lists.len()
     }
 
+    /// The file can't change, so this only exists for the trait. It still copies the tables.
+    fn snapshot(&self) -> Box<dyn LogProvider + Send + Sync> {
+        Box::new(MmapLogProvider {
+            map: self.map.clone(),
+            offset_table: self.offset_table.clone(),
+            child_lists: self.child_lists.clone(),
+            entries_start_offset: self.entries_start_offset,
+            attr_encoding: self.attr_encoding,
+        })
+    }
+
     fn parent(&self, x: u32) -> LogProviderResult<u32> {
+        if x == 0 {
+            return Ok(0);
+        }
         let offset = self.offset_of(x)?;
         // there is a MemmapEntryRef at this offset. but since its first field is the parent,
         // decode just that.
@@ -1,12 +1,16 @@
+use crate::AttrEncoding;
+use crate::AttrTransform;
 use crate::IETLoadConfig;
 use crate::LogProviderError;
 #[cfg(feature = "notify-watch")]
 use crate::remote::IETInfo;
 use crate::tree_layer::EnValueRef;
 use std::{
+    collections::HashMap,
     fs::File,
     io::{self, BufReader, Read, Seek, SeekFrom},
     path::PathBuf,
+    thread::JoinHandle,
     time::Instant,
 };
 
@@ -16,9 +20,11 @@ use tracing::trace;
 use tracing::{error, info};
 
 use crate::{
-    Header, IETPresentationConfig, MetadataRefContainer, PoolEntry, TraceEntry,
+    FrameError, Header, IETPresentationConfig, IETRecord, LengthPrefixError, MetadataRefContainer,
+    PoolEntry, TraceEntry, check_parent_order, decode_length_prefix,
     log_provider::{LogProvider, LogProviderResult},
-    remote::{BaseIETLogProvider, MainThreadMessage, Refresh},
+    read_frame_into,
+    remote::{AdaptiveBatchSize, BaseIETLogProvider, MainThreadMessage, Refresh},
 };
 #[derive(Debug, thiserror::Error)]
 pub enum LoadIETError {
@@ -33,6 +39,10 @@ pub enum LoadIETError {
     IO(#[from] std::io::Error),
     #[error("Want to watch a file, but you didn't enable the notify-watch feature in entrace_core")]
     NotifyNeeded,
+    #[error("There is no span with id {0} in the trace")]
+    NoSuchSpan(u32),
+    #[error(transparent)]
+    LengthPrefix(#[from] LengthPrefixError),
 }
 
 pub struct InitialIETData {
@@ -44,75 +54,217 @@ pub struct InitialIETData {
 ///
 /// `length_prefixed` is usually `false` for files and `true` for streams.
 ///
+/// The IET loaders read uncompressed entries, starting after the magic. Only [crate::load_trace]
+/// decompresses gzip files (like `.iet.gz`), so decompress them first to use these directly.
+///
 /// To get something that implements [LogProvider], see [FileIETLogProvider::new]
 pub fn load_iet_trace(
-    mut reader: impl std::io::Read, length_prefixed: bool,
+    reader: impl std::io::Read, length_prefixed: bool,
 ) -> Result<InitialIETData, LoadIETError> {
-    let cfg = bincode::config::standard();
-    let mut pool: Vec<PoolEntry> = vec![PoolEntry::new()];
-    // no root entry here, the client has to send it.
-    let mut data = vec![];
-    let mut had_root = false;
-    loop {
-        if length_prefixed {
-            let mut cl_buf = [0; 8];
-            if let Err(y) = reader.read_exact(&mut cl_buf) {
-                if y.kind() == std::io::ErrorKind::UnexpectedEof {
-                    break;
-                } else {
-                    return Err(LoadIETError::IO(y));
-                }
+    load_iet_trace_with_encoding(reader, length_prefixed, AttrEncoding::Standard)
+}
+
+/// Like [load_iet_trace], but for a trace whose entries use `attr_encoding`.
+pub fn load_iet_trace_with_encoding(
+    reader: impl std::io::Read, length_prefixed: bool, attr_encoding: AttrEncoding,
+) -> Result<InitialIETData, LoadIETError> {
+    load_iet_trace_transformed(reader, length_prefixed, attr_encoding, None)
+}
+
+/// Like [load_iet_trace_with_encoding], but run `attr_transform` on the attributes of every
+/// entry as it is read.
+pub fn load_iet_trace_transformed(
+    mut reader: impl std::io::Read, length_prefixed: bool, attr_encoding: AttrEncoding,
+    attr_transform: Option<&dyn AttrTransform>,
+) -> Result<InitialIETData, LoadIETError> {
+    // no root entry here, the first entry of the trace is the root.
+    let mut pool: Vec<PoolEntry> = vec![];
+    let mut data: Vec<TraceEntry> = vec![];
+    let mut frame = vec![];
+    while let Some(record) = read_record(&mut reader, length_prefixed, attr_encoding, &mut frame)? {
+        let mut x = match record {
+            IETRecord::Entry(x) => x,
+            IETRecord::FollowsFrom { id, follows } => {
+                let entry = data.get_mut(id as usize).ok_or(LoadIETError::NoSuchSpan(id))?;
+                entry.follows_from.push(follows);
+                continue;
             }
+        };
+        if !length_prefixed {
+            check_parent_order(data.len() as u64, x.parent)?;
+        }
+        if let Some(transform) = attr_transform {
+            x.transform_attrs(transform);
         }
-        // TODO: mabye be paranoid here, and only read up to content-len.
-        let decoded: Result<TraceEntry, _> = bincode::serde::decode_from_std_read(&mut reader, cfg);
-        match decoded {
-            Ok(x) => {
-                let pl = pool.len() as u32;
-                // we are pushing a pool entry with "delayed" data for the root, account
-                // for this.
-                if had_root {
-                    pool[x.parent as usize].children.push(pl);
-                    pool.push(PoolEntry::new())
+        PoolEntry::push_linked(&mut pool, x.parent);
+        data.push(x);
+    }
+    Ok(InitialIETData { pool, data })
+}
+
+/// Read the next record of an IET trace, or `None` at its end. A record cut off by the end of the
+/// data counts as the end, since it may still be being written.
+///
+/// With `length_prefixed`, the record has to fill its frame, see
+/// [AttrEncoding::decode_framed_record]. `frame` is a buffer for it, reused between calls.
+fn read_record(
+    reader: &mut impl Read, length_prefixed: bool, attr_encoding: AttrEncoding, frame: &mut Vec<u8>,
+) -> Result<Option<IETRecord>, LoadIETError> {
+    fn at_end(y: &io::Error) -> bool {
+        y.kind() == io::ErrorKind::UnexpectedEof
+    }
+    if !length_prefixed {
+        return match attr_encoding.decode_record(reader) {
+            Ok(x) => Ok(Some(x)),
+            Err(DecodeError::Io { inner, .. }) if at_end(&inner) => Ok(None),
+            Err(y) => Err(LoadIETError::DecodeError(y)),
+        };
+    }
+    let mut cl_buf = [0; 8];
+    match reader.read_exact(&mut cl_buf) {
+        Ok(()) => (),
+        Err(y) if at_end(&y) => return Ok(None),
+        Err(y) => return Err(LoadIETError::IO(y)),
+    }
+    let len = decode_length_prefix(cl_buf)?;
+    match read_frame_into(reader, len, frame) {
+        Ok(()) => (),
+        Err(y) if at_end(&y) => return Ok(None),
+        Err(y) => return Err(LoadIETError::IO(y)),
+    }
+    match attr_encoding.decode_framed_record(frame) {
+        Ok(x) => Ok(Some(x)),
+        Err(FrameError::Decode(y)) => Err(LoadIETError::DecodeError(y)),
+        Err(FrameError::Prefix(y)) => Err(LoadIETError::LengthPrefix(y)),
+    }
+}
+
+/// One subtree of an IET trace, loaded by [load_iet_subtree].
+pub struct IETSubtree {
+    /// The entries of the subtree, renumbered so they can be used like a full trace: 0 is the
+    /// root of the trace, and 1 is the requested span.
+    pub initial: InitialIETData,
+    /// For each entry of [IETSubtree::initial], its id in the full trace.
+    pub original_ids: Vec<u32>,
+}
+
+/// Like [load_iet_trace_with_encoding], but only keep `root`, its descendants, and the root of
+/// the trace.
+///
+/// The whole trace is still read and decoded, since entries have no fixed size, but the entries
+/// outside the subtree are dropped right away. Memory use is proportional to the size of the
+/// subtree instead of the trace, so a small subtree of a trace too large to fit in memory can be
+/// loaded. Passing 0 as `root` loads the whole trace.
+///
+/// This relies on parents being written before their children, which [crate::TreeLayer] ensures.
+/// Follows-from links to spans outside the subtree are dropped. Like [load_iet_trace], it doesn't
+/// decompress gzip.
+pub fn load_iet_subtree(
+    mut reader: impl std::io::Read, root: u32, length_prefixed: bool, attr_encoding: AttrEncoding,
+) -> Result<IETSubtree, LoadIETError> {
+    let mut pool: Vec<PoolEntry> = vec![PoolEntry::new()];
+    let mut data: Vec<TraceEntry> = vec![];
+    let mut original_ids = vec![];
+    // original id -> new id, for entries in the subtree
+    let mut new_ids: HashMap<u32, u32> = HashMap::new();
+    let mut next_id = 0u32;
+    let mut frame = vec![];
+    while let Some(record) = read_record(&mut reader, length_prefixed, attr_encoding, &mut frame)? {
+        let mut entry = match record {
+            IETRecord::Entry(entry) => entry,
+            IETRecord::FollowsFrom { id, follows } if id < next_id => {
+                if let Some(&new_id) = new_ids.get(&id) {
+                    data[new_id as usize].follows_from.push(follows);
                 }
-                data.push(x);
-                had_root = true;
+                continue;
             }
-            Err(y) => match y {
-                DecodeError::Io { inner, .. } if inner.kind() == io::ErrorKind::UnexpectedEof => {
-                    break;
-                }
-                _ => return Err(LoadIETError::DecodeError(y)),
-            },
+            IETRecord::FollowsFrom { id, .. } => return Err(LoadIETError::NoSuchSpan(id)),
+        };
+        if !length_prefixed {
+            check_parent_order(next_id as u64, entry.parent)?;
         }
+        let id = next_id;
+        next_id += 1;
+        let new_parent = if id == 0 {
+            // the root of the trace is always kept, but its other children are not
+            if root == 0 {
+                new_ids.insert(0, 0);
+            }
+            data.push(entry);
+            original_ids.push(0);
+            continue;
+        } else if id == root {
+            0
+        } else if let Some(&parent) = new_ids.get(&entry.parent) {
+            parent
+        } else {
+            continue;
+        };
+        let new_id = pool.len() as u32;
+        new_ids.insert(id, new_id);
+        pool[new_parent as usize].children.push(new_id);
+        pool.push(PoolEntry::new());
+        entry.parent = new_parent;
+        data.push(entry);
+        original_ids.push(id);
     }
 
+    if !new_ids.contains_key(&root) {
+        return Err(LoadIETError::NoSuchSpan(root));
+    }
+    // a span can follow from one created after it, so this is only possible once all ids are known
+    for entry in &mut data {
+        entry.follows_from.retain_mut(|id| match new_ids.get(id) {
+            Some(new_id) => {
+                *id = *new_id;
+                true
+            }
+            None => false,
+        });
+    }
     let data_len = data.len();
     let pool_len = pool.len();
     if data.len() != pool.len() {
         return Err(LoadIETError::LengthMismatch { data_len, pool_len });
     }
-    Ok(InitialIETData { pool, data })
+    Ok(IETSubtree { initial: InitialIETData { pool, data }, original_ids })
 }
 
 pub enum FileWatchConfig {
     DontWatch,
     Watch(PathBuf),
 }
-pub struct FileIETLogProvider(BaseIETLogProvider);
+/// A trace loaded from an IET file, which is watched for new entries if configured so.
+///
+/// The watcher stops when the provider is dropped or [closed](FileIETLogProvider::close).
+///
+/// Keeps a handle to the file, to report its size as it grows, see [LogProvider::source_bytes].
+pub struct FileIETLogProvider(BaseIETLogProvider, Sender<()>, File);
 impl FileIETLogProvider {
+    /// Load `file`, positioned after its magic. The file has to be uncompressed, as it is read and
+    /// watched in place. [crate::load_trace] loads a gzip file into a [BaseIETLogProvider] instead.
     pub fn new<R>(
         mut file: File, load_config: IETLoadConfig<R>, length_prefixed: bool,
+        attr_encoding: AttrEncoding,
     ) -> Result<Self, LoadIETError>
     where
         R: Refresh + Send + 'static,
     {
+        let stat_handle = file.try_clone()?;
         let mut reader = BufReader::new(&mut file);
 
         let start = Instant::now();
-        let initial = load_iet_trace(&mut reader, length_prefixed)?;
+        let attr_transform = load_config.attr_transform;
+        let initial = load_iet_trace_transformed(
+            &mut reader,
+            length_prefixed,
+            attr_encoding,
+            attr_transform.as_deref(),
+        )?;
         info!(duration = ?start.elapsed(), "RemoteLogProvider: loaded initial iet file");
 
+        // never sent to, the worker stops when it is disconnected
+        let (stop_tx, stop_rx) = crossbeam_channel::bounded::<()>(0);
         let worker_thread =
             move |mut file2, tx: Sender<_>, config2: IETPresentationConfig<R>| match load_config
                 .watch
@@ -122,9 +274,16 @@ impl FileIETLogProvider {
                     #[cfg(feature = "notify-watch")]
                     {
                         let mut reader = BufReader::new(&mut file2);
-                        let mut worker =
-                            IETNotifyWorker::new(tx, &mut reader, file_path, config2, false);
-                        if let Err(y) = worker.work() {
+                        let mut worker = IETNotifyWorker::new(
+                            tx,
+                            &mut reader,
+                            file_path,
+                            config2,
+                            false,
+                            attr_encoding,
+                            attr_transform,
+                        );
+                        if let Err(y) = worker.work(&stop_rx) {
                             if let LogProviderError::FileIETError(ref yy) = y
                                 && yy.is_fatal()
                             {
@@ -140,6 +299,7 @@ impl FileIETLogProvider {
                             use crate::remote::IETEvent;
                             etx.send(IETEvent::Error(FileIETError::NeedNotify.into())).ok();
                         }
+                        drop(stop_rx);
                         return;
                     }
                 }
@@ -147,7 +307,20 @@ impl FileIETLogProvider {
         let mut base = BaseIETLogProvider::new(file, load_config.presentation, worker_thread);
         base.pool = initial.pool;
         base.data = initial.data;
-        Ok(Self(base))
+        Ok(Self(base, stop_tx, stat_handle))
+    }
+    /// Stop watching the file, and free the trace.
+    ///
+    /// Returns the handle of the worker, which exits soon after, for waiting on it. Dropping the
+    /// provider stops the worker too.
+    pub fn close(self) -> JoinHandle<()> {
+        let Self(base, stop, _) = self;
+        drop(stop);
+        base.close()
+    }
+    /// See [BaseIETLogProvider::pending_messages].
+    pub fn pending_messages(&self) -> usize {
+        self.0.pending_messages()
     }
 }
 #[derive(thiserror::Error, Debug)]
@@ -157,8 +330,13 @@ pub enum FileIETError {
     #[cfg(feature = "notify-watch")]
     #[error(transparent)]
     NotifyError(#[from] notify::Error),
-    #[error("Failed to read incoming data after 8 retries")]
-    NoMoreRetries(#[from] bincode::error::DecodeError),
+    /// The entry at byte `offset` of the file still can't be decoded after 8 modifications.
+    #[error("Failed to read incoming data at byte {offset} after 8 retries")]
+    NoMoreRetries {
+        offset: u64,
+        #[source]
+        source: bincode::error::DecodeError,
+    },
 }
 impl FileIETError {
     pub fn is_fatal(&self) -> bool {
@@ -166,7 +344,7 @@ impl FileIETError {
             FileIETError::NeedNotify => true,
             #[cfg(feature = "notify-watch")]
             FileIETError::NotifyError(_) => false,
-            FileIETError::NoMoreRetries(_) => true,
+            FileIETError::NoMoreRetries { .. } => true,
         }
     }
 }
@@ -181,17 +359,23 @@ pub struct IETNotifyWorker<'a, F: Read + Seek, R: Refresh> {
     file_path: PathBuf,
     cfg: IETPresentationConfig<R>,
     length_prefixed: bool,
+    attr_encoding: AttrEncoding,
+    attr_transform: Option<std::sync::Arc<dyn AttrTransform>>,
 
     reader: &'a mut F,
     last_good_position: u64,
     read_state: ReadState,
     entries: Vec<TraceEntry>,
+    batch_size: AdaptiveBatchSize,
+    /// The frame being read, for length-prefixed files.
+    frame: Vec<u8>,
 }
 #[cfg(feature = "notify-watch")]
 impl<'a, R: Refresh, F: Read + Seek> IETNotifyWorker<'a, F, R> {
     pub fn new(
         tx: Sender<MainThreadMessage>, reader: &'a mut F, file_path: PathBuf,
-        config: IETPresentationConfig<R>, length_prefixed: bool,
+        config: IETPresentationConfig<R>, length_prefixed: bool, attr_encoding: AttrEncoding,
+        attr_transform: Option<std::sync::Arc<dyn AttrTransform>>,
     ) -> Self {
         let last_good_position = reader.stream_position().unwrap();
         Self {
@@ -199,10 +383,14 @@ impl<'a, R: Refresh, F: Read + Seek> IETNotifyWorker<'a, F, R> {
             cfg: config,
             file_path,
             length_prefixed,
+            attr_encoding,
+            attr_transform,
             last_good_position,
             reader,
             read_state: ReadState::Standby,
             entries: vec![],
+            batch_size: AdaptiveBatchSize::new(),
+            frame: vec![],
         }
     }
     pub fn send_err(&self, err: LogProviderError) {
@@ -220,50 +408,80 @@ impl<'a, R: Refresh, F: Read + Seek> IETNotifyWorker<'a, F, R> {
 
     pub fn send_entries(&mut self) {
         match self.entries.len() {
-            0 => (),
+            // nothing was sent, so the backlog says nothing about the batch size
+            0 => return,
             1 => {
                 let pop = self.entries.pop().unwrap();
-                self.tx.send(MainThreadMessage::Insert(pop)).unwrap();
+                // fails if the provider is closed, and then the entries are not needed anymore
+                self.tx.send(MainThreadMessage::Insert(pop)).ok();
                 self.cfg.refresher.refresh();
             }
             x => {
-                self.tx
-                    .send(MainThreadMessage::InsertMany(std::mem::take(&mut self.entries)))
-                    .unwrap();
+                self.tx.send(MainThreadMessage::InsertMany(std::mem::take(&mut self.entries))).ok();
                 self.cfg.refresher.refresh();
-                println!("Sent batch of {x}");
+                trace!(len = x, next_batch_size = self.batch_size.get(), "Sent batch");
             }
         }
+        self.batch_size.update(self.tx.len());
+    }
+    /// Read the next length-prefixed frame into `self.frame`. Returns false if it is not
+    /// completely written yet.
+    fn read_frame(&mut self) -> Result<bool, LogProviderError> {
+        let mut cl_buf = [0; 8];
+        match self.reader.read_exact(&mut cl_buf) {
+            Ok(()) => (),
+            Err(y) if y.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(y) => return Err(y.into()),
+        }
+        let len = decode_length_prefix(cl_buf)?;
+        match read_frame_into(&mut self.reader, len, &mut self.frame) {
+            Ok(()) => Ok(true),
+            Err(y) if y.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(y) => Err(y.into()),
+        }
     }
     pub fn on_modify(&mut self) -> Result<(), LogProviderError> {
-        let cfg = bincode::config::standard();
-
         loop {
-            if self.length_prefixed {
-                let mut cl_buf = [0; 8];
-                if let Err(y) = self.reader.read_exact(&mut cl_buf) {
-                    if y.kind() == std::io::ErrorKind::UnexpectedEof {
-                        // wait for next wake up on new data
-                        break;
-                    } else {
-                        return Err(LogProviderError::IO(y));
+            let decoded = if self.length_prefixed {
+                if !self.read_frame()? {
+                    // wait for next wake up on new data
+                    self.reader.seek(SeekFrom::Start(self.last_good_position))?;
+                    break;
+                }
+                match self.attr_encoding.decode_framed_record(&self.frame) {
+                    Ok(x) => Ok(x),
+                    Err(FrameError::Decode(y)) => Err(y),
+                    // the frame is complete, so this is not a write in progress
+                    Err(FrameError::Prefix(y)) => {
+                        self.send_entries();
+                        return Err(y.into());
                     }
                 }
-                //let content_len = u64::from_le_bytes(cl_buf);
-            }
-
-            let decoded: Result<TraceEntry, _> =
-                bincode::serde::decode_from_std_read(&mut self.reader, cfg);
+            } else {
+                self.attr_encoding.decode_record(&mut self.reader)
+            };
             match decoded {
-                Ok(x) => {
+                Ok(IETRecord::Entry(mut x)) => {
+                    if let Some(transform) = &self.attr_transform {
+                        x.transform_attrs(&**transform);
+                    }
                     self.entries.push(x);
-                    if self.entries.len() > 32 {
+                    if self.entries.len() >= self.batch_size.get() {
                         self.send_entries();
                     }
 
                     self.last_good_position = self.reader.stream_position()?;
                     self.read_state = ReadState::Standby;
                 }
+                Ok(IETRecord::FollowsFrom { id, follows }) => {
+                    // the entry may still be in the batch, which has to arrive first
+                    self.send_entries();
+                    self.tx.send(MainThreadMessage::FollowsFrom { id, follows }).ok();
+                    self.cfg.refresher.refresh();
+
+                    self.last_good_position = self.reader.stream_position()?;
+                    self.read_state = ReadState::Standby;
+                }
                 Err(y) => {
                     if matches!(y, bincode::error::DecodeError::UnexpectedEnd { .. })
                         || matches!(y,  bincode::error::DecodeError::Io { ref inner, .. } if inner.kind() == std::io::ErrorKind::UnexpectedEof)
@@ -273,15 +491,20 @@ impl<'a, R: Refresh, F: Read + Seek> IETNotifyWorker<'a, F, R> {
                         self.cfg.refresher.refresh();
                         break; // wait for the next wake up
                     } else {
-                        // this could still be an incomplete write
-                        if let ReadState::Retrying { ref mut retries } = self.read_state {
-                            if *retries > 8 {
-                                self.send_entries();
-                                return Err(FileIETError::NoMoreRetries(y).into());
-                            } else {
-                                *retries += 1;
-                            };
+                        // this could still be an incomplete write, so try again from the last
+                        // good entry on the next wake up
+                        let retries = match self.read_state {
+                            ReadState::Standby => 0,
+                            ReadState::Retrying { retries } => retries + 1,
+                        };
+                        if retries >= 8 {
+                            self.send_entries();
+                            let offset = self.last_good_position;
+                            return Err(FileIETError::NoMoreRetries { offset, source: y }.into());
                         }
+                        self.read_state = ReadState::Retrying { retries };
+                        self.reader.seek(SeekFrom::Start(self.last_good_position))?;
+                        break;
                     }
                 }
             }
@@ -290,25 +513,36 @@ impl<'a, R: Refresh, F: Read + Seek> IETNotifyWorker<'a, F, R> {
         Ok(())
     }
 
-    pub fn work(&mut self) -> Result<(), LogProviderError> {
+    /// Read new entries whenever the file is modified, until `stop` is disconnected.
+    pub fn work(&mut self, stop: &crossbeam_channel::Receiver<()>) -> Result<(), LogProviderError> {
         use notify::{EventKind, Watcher, event::ModifyKind};
         info!("FileIETLogProvider worker start");
-        let (atx, arx) = std::sync::mpsc::channel::<notify::Result<notify::Event>>();
-        let mut watcher = notify::recommended_watcher(atx).map_err(FileIETError::NotifyError)?;
+        let (atx, arx) = crossbeam_channel::unbounded::<notify::Result<notify::Event>>();
+        let handler = move |event| {
+            atx.send(event).ok();
+        };
+        let mut watcher =
+            notify::recommended_watcher(handler).map_err(FileIETError::NotifyError)?;
         watcher.watch(self.file_path.as_path(), notify::RecursiveMode::NonRecursive).ok();
         info!("Setting up file watcher for IET file");
 
         loop {
-            match arx.recv() {
-                Ok(Ok(x)) => {
-                    if let EventKind::Modify(ModifyKind::Data(_)) = x.kind {
-                        trace!("IET file watcher fired");
-                        if let Err(y) = self.on_modify() {
-                            self.send_err(y);
+            crossbeam_channel::select! {
+                recv(arx) -> event => match event {
+                    Ok(Ok(x)) => {
+                        if let EventKind::Modify(ModifyKind::Data(_)) = x.kind {
+                            trace!("IET file watcher fired");
+                            if let Err(y) = self.on_modify() {
+                                self.send_err(y);
+                            }
                         }
                     }
+                    x => error!(error=?x,"File watcher error"),
+                },
+                recv(stop) -> _ => {
+                    info!("FileIETLogProvider worker stop");
+                    return Ok(());
                 }
-                x => error!(error=?x,"File watcher error"),
             }
         }
     }
@@ -330,7 +564,14 @@ impl LogProvider for FileIETLogProvider {
     dispatch_to_parent!(fn header(x: u32) -> LogProviderResult<Header<'_>>);
     dispatch_to_parent!(fn message(x: u32) -> LogProviderResult<Option<&str>>);
     dispatch_to_parent!(fn meta(x: u32) -> LogProviderResult<MetadataRefContainer<'_>>);
+    dispatch_to_parent!(fn follows_from(x: u32) -> LogProviderResult<Vec<u32>>);
     dispatch_to_parent!(fn len()-> usize);
+    dispatch_to_parent!(fn is_live()-> bool);
+
+    fn source_bytes(&self) -> Option<u64> {
+        self.2.metadata().ok().map(|x| x.len())
+    }
+    dispatch_to_parent!(fn snapshot()-> Box<dyn LogProvider + Send + Sync>);
 
     fn frame_callback(&mut self) {
         self.0.frame_callback();
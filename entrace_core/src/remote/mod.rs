@@ -1,4 +1,13 @@
-use std::{fmt::Display, thread::JoinHandle, time::Duration};
+use std::{
+    cell::Cell,
+    fmt::Display,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
 use crate::{
     Header, IETPresentationConfig, LevelContainer, MetadataRefContainer, PoolEntry, TraceEntry,
@@ -13,10 +22,14 @@ mod remote_storage;
 pub use remote_storage::*;
 mod remote_log_provider;
 pub use remote_log_provider::*;
+mod preview_storage;
+pub use preview_storage::*;
 
 pub enum IETInfo {
     ServerStarted,
     ReceivedConnection,
+    /// Connected to a traced process, see [RemoteLogProvider::connect].
+    Connected,
     RemoteClosedConnection,
 }
 impl Display for IETInfo {
@@ -24,6 +37,7 @@ impl Display for IETInfo {
         match self {
             IETInfo::ServerStarted => write!(f, "Server started, waiting for connections"),
             IETInfo::ReceivedConnection => write!(f, "Received connection"),
+            IETInfo::Connected => write!(f, "Connected"),
             IETInfo::RemoteClosedConnection => write!(f, "Remote client closed connection"),
         }
     }
@@ -39,6 +53,44 @@ pub enum MainThreadMessage {
     InsertMany(Vec<TraceEntry>),
     ReplacePool(Vec<PoolEntry>),
     ReplaceData(Vec<TraceEntry>),
+    /// A follows-from link of an entry which was sent already, see [crate::IETRecord].
+    FollowsFrom {
+        id: u32,
+        follows: u32,
+    },
+}
+
+/// Batch size for sending entries from a worker thread to the main thread, adapting to load.
+///
+/// When the main thread falls behind (the channel has a backlog), batches grow, so fewer and
+/// larger [MainThreadMessage::InsertMany]s are sent. When the channel is drained, batches shrink
+/// again, so new entries show up with low latency.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveBatchSize {
+    current: usize,
+}
+impl AdaptiveBatchSize {
+    pub const MIN: usize = 16;
+    pub const MAX: usize = 16 * 1024;
+    pub fn new() -> Self {
+        Self { current: Self::MIN }
+    }
+    pub fn get(&self) -> usize {
+        self.current
+    }
+    /// Call after sending a batch, with the number of messages still waiting in the channel.
+    pub fn update(&mut self, backlog: usize) {
+        self.current = if backlog > 1 {
+            (self.current * 2).min(Self::MAX)
+        } else {
+            (self.current / 2).max(Self::MIN)
+        };
+    }
+}
+impl Default for AdaptiveBatchSize {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct BaseIETLogProvider {
@@ -47,6 +99,8 @@ pub struct BaseIETLogProvider {
     // TODO: memory representation could likely be more concise
     pub pool: Vec<PoolEntry>,
     pub data: Vec<TraceEntry>,
+    /// See [LogProvider::source_bytes]. Shared with the worker thread if it reads a stream.
+    pub source_bytes: Option<Arc<AtomicU64>>,
 }
 
 impl BaseIETLogProvider {
@@ -63,22 +117,40 @@ impl BaseIETLogProvider {
     {
         let (tx, rx) = crossbeam_channel::unbounded();
         let handle = std::thread::spawn(move || worker_thread(buf, tx, config));
-        // no root data entry here, the client has to send it.
-        Self { handle, receiver: rx, pool: vec![], data: vec![] }
+        // no root data entry here, the client has to send it. Until then, 0 is a placeholder.
+        Self { handle, receiver: rx, pool: vec![], data: vec![], source_bytes: None }
+    }
+    /// Free the trace, and return the handle of the worker thread.
+    ///
+    /// This doesn't stop the worker, but its messages aren't received anymore. Workers which may
+    /// block forever are stopped by their provider, like [RemoteLogProvider::close].
+    pub fn close(self) -> JoinHandle<()> {
+        self.handle
+    }
+    /// How many messages of the worker wait for [LogProvider::frame_callback]. A message may hold
+    /// many entries.
+    pub fn pending_messages(&self) -> usize {
+        self.receiver.len()
     }
     /// Helper method for wrapping self.data.get() with an OutOfBounds error
+    ///
+    /// Until the root arrives, id 0 is a placeholder root, see [LogProvider].
     fn data_get(&self, x: u32) -> LogProviderResult<&TraceEntry> {
-        self.data
-            .get(x as usize)
-            .ok_or_else(|| LogProviderError::OutOfBounds { idx: x as usize, len: self.len() })
+        static PLACEHOLDER_ROOT: LazyLock<TraceEntry> = LazyLock::new(TraceEntry::root);
+        match self.data.get(x as usize) {
+            Some(entry) => Ok(entry),
+            None if x == 0 => Ok(&PLACEHOLDER_ROOT),
+            None => Err(LogProviderError::OutOfBounds { idx: x as usize, len: self.len() }),
+        }
     }
 }
 impl LogProvider for BaseIETLogProvider {
     fn children(&self, x: u32) -> LogProviderResult<&[u32]> {
-        self.pool
-            .get(x as usize)
-            .map(|x| x.children.as_slice())
-            .ok_or_else(|| LogProviderError::OutOfBounds { idx: x as usize, len: self.len() })
+        match self.pool.get(x as usize) {
+            Some(entry) => Ok(entry.children.as_slice()),
+            None if x == 0 => Ok(&[]),
+            None => Err(LogProviderError::OutOfBounds { idx: x as usize, len: self.len() }),
+        }
     }
 
     fn parent(&self, idx: u32) -> LogProviderResult<u32> {
@@ -116,43 +188,62 @@ impl LogProvider for BaseIETLogProvider {
         Ok(self.data_get(idx)?.metadata.as_ref())
     }
 
+    fn follows_from(&self, idx: u32) -> LogProviderResult<Vec<u32>> {
+        Ok(self.data_get(idx)?.follows_from.clone())
+    }
+
+    /// Live while the worker thread runs, or it has sent data that [LogProvider::frame_callback]
+    /// didn't process yet.
+    fn is_live(&self) -> bool {
+        !self.handle.is_finished() || !self.receiver.is_empty()
+    }
+
+    /// Clones the spans processed by [LogProvider::frame_callback] so far.
+    fn snapshot(&self) -> Box<dyn LogProvider + Send + Sync> {
+        let mut snapshot =
+            BaseIETLogProvider::new((), IETPresentationConfig::default(), |_, _, _| ());
+        snapshot.pool = self.pool.clone();
+        snapshot.data = self.data.clone();
+        snapshot.source_bytes = self.source_bytes().map(|x| Arc::new(AtomicU64::new(x)));
+        Box::new(snapshot)
+    }
+
     fn frame_callback(&mut self) {
+        // Instead of a fixed number of messages, drain the channel until it is empty or we run out
+        // of time for this frame. Workers grow their batches when we fall behind, so a backlog is
+        // worked off in a few large messages.
         // TODO: make configurable ( maybe an interface for Storage to provide extra settings in
         // the dialog ? )
-        #[allow(non_snake_case)]
-        let N = 50;
-        for _ in 0..N {
+        const FRAME_BUDGET: Duration = Duration::from_millis(4);
+        let start = Instant::now();
+        while start.elapsed() < FRAME_BUDGET {
             match self.receiver.try_recv() {
                 Ok(msg) => {
                     use MainThreadMessage::Insert;
                     match msg {
                         Insert(event) => {
-                            let pl = self.pool.len() as u32;
-
-                            self.pool.push(PoolEntry::new());
-                            if pl != 0 {
-                                self.pool[event.parent as usize].children.push(pl);
-                            }
+                            PoolEntry::push_linked(&mut self.pool, event.parent);
                             self.data.push(event);
                         }
                         MainThreadMessage::ReplacePool(pool) => self.pool = pool,
                         MainThreadMessage::ReplaceData(data) => self.data = data,
                         MainThreadMessage::InsertMany(events) => {
-                            let old_pl = self.pool.len();
-                            self.pool.extend(std::iter::repeat_n(PoolEntry::new(), events.len()));
-                            for (idx, event) in events.iter().enumerate() {
-                                let idx = idx + old_pl;
-                                if idx != 0 {
-                                    self.pool[event.parent as usize].children.push(idx as u32);
-                                }
+                            self.pool.reserve(events.len());
+                            for event in &events {
+                                PoolEntry::push_linked(&mut self.pool, event.parent);
                             }
                             self.data.extend(events);
                         }
+                        MainThreadMessage::FollowsFrom { id, follows } => {
+                            if let Some(entry) = self.data.get_mut(id as usize) {
+                                entry.follows_from.push(follows);
+                            }
+                        }
                     }
                 }
                 Err(y) => match y {
-                    crossbeam_channel::TryRecvError::Empty => (),
-                    crossbeam_channel::TryRecvError::Disconnected => (),
+                    crossbeam_channel::TryRecvError::Empty => break,
+                    crossbeam_channel::TryRecvError::Disconnected => break,
                 },
             }
         }
@@ -161,17 +252,63 @@ impl LogProvider for BaseIETLogProvider {
     fn len(&self) -> usize {
         self.data.len()
     }
+
+    fn source_bytes(&self) -> Option<u64> {
+        self.source_bytes.as_ref().map(|x| x.load(Ordering::Relaxed))
+    }
 }
 
 pub trait Refresh {
     /// A way of signaling from entrace to the consuming library that the data has changed
     fn refresh(&self);
+    /// Like [Refresh::refresh], but the change only has to be shown after `delay`.
+    fn refresh_after(&self, _delay: Duration) {
+        self.refresh();
+    }
 }
 pub struct DummyRefresher {}
 impl Refresh for DummyRefresher {
     fn refresh(&self) {}
 }
 
+/// Forwards [Refresh::refresh] at most once per window, for streams which send batches much
+/// faster than they can be shown.
+///
+/// A refresh inside the window is not dropped, but deferred to its end with
+/// [Refresh::refresh_after], once per window. So the last batch of a burst is shown too.
+pub struct ThrottledRefresher<R: Refresh> {
+    inner: R,
+    window: Duration,
+    /// When a refresh was last forwarded.
+    last: Cell<Option<Instant>>,
+    /// Whether a refresh was deferred since `last`.
+    deferred: Cell<bool>,
+}
+/// The default window of [ThrottledRefresher], about one frame at 60 FPS.
+pub const DEFAULT_REFRESH_WINDOW: Duration = Duration::from_millis(16);
+impl<R: Refresh> ThrottledRefresher<R> {
+    pub fn new(inner: R, window: Duration) -> Self {
+        Self { inner, window, last: Cell::new(None), deferred: Cell::new(false) }
+    }
+}
+impl<R: Refresh> Refresh for ThrottledRefresher<R> {
+    fn refresh(&self) {
+        let now = Instant::now();
+        match self.last.get().map(|last| now.duration_since(last)) {
+            Some(elapsed) if elapsed < self.window => {
+                if !self.deferred.replace(true) {
+                    self.inner.refresh_after(self.window - elapsed);
+                }
+            }
+            _ => {
+                self.last.set(Some(now));
+                self.deferred.set(false);
+                self.inner.refresh();
+            }
+        }
+    }
+}
+
 pub trait Notify {
     fn add_notification(&self, severity: LevelContainer, text: String, duration: Duration);
     fn remove_notification(&self, idx: usize);
@@ -194,13 +331,7 @@ impl<T: Notify> NotifyExt for T {
 pub struct StderrNotifier {}
 impl Notify for StderrNotifier {
     fn add_notification(&self, severity: LevelContainer, text: String, _duration: Duration) {
-        let level = match severity {
-            LevelContainer::Trace => "[T]",
-            LevelContainer::Debug => "[D]",
-            LevelContainer::Info => "[I]",
-            LevelContainer::Warn => "[W]",
-            LevelContainer::Error => "[E]",
-        };
+        let level = severity.symbol();
         eprintln!("entrace notification: {level}: {text}",);
     }
 
@@ -0,0 +1,137 @@
+use crate::{
+    EnValueRef, IETPresentationConfig, TraceEntry,
+    remote::{AdaptiveBatchSize, BaseIETLogProvider, MainThreadMessage, Refresh, RemoteMessage},
+    storage::{PendingEntries, Storage},
+    tree_layer::{EnValue, SpanLifecycle},
+};
+use crossbeam_channel::{Receiver, Sender};
+use std::sync::Arc;
+
+/// A [Storage] which passes everything on to another storage, and also shows the trace in a
+/// [BaseIETLogProvider] of the same process, without reading it back from a file or socket.
+///
+/// This lets a program embed a live view of its own trace. The provider gets new spans in its
+/// [crate::LogProvider::frame_callback], like one reading a watched file.
+/// ```rust,ignore
+/// let file_storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(file)));
+/// let (storage, mut preview) = PreviewStorage::new(file_storage.clone(), Default::default());
+/// let storage = Arc::new(storage);
+/// Registry::default().with(TreeLayer::from_storage(storage.clone())).init();
+/// // ... in the UI loop
+/// preview.frame_callback();
+/// // on shutdown
+/// storage.finish();
+/// file_storage.finish().unwrap();
+/// ```
+pub struct PreviewStorage<S: Storage> {
+    pub inner: Arc<S>,
+    pub sender: Sender<RemoteMessage>,
+}
+impl<S: Storage> PreviewStorage<S> {
+    /// Wrap `inner`, and return the provider which shows what is recorded from now on.
+    pub fn new<R: Refresh + Send + 'static>(
+        inner: Arc<S>, config: IETPresentationConfig<R>,
+    ) -> (Self, BaseIETLogProvider) {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let provider = BaseIETLogProvider::new(rx, config, preview_worker);
+        (Self { inner, sender: tx }, provider)
+    }
+    /// Stop updating the provider, after it got the entries recorded so far. The provider stays
+    /// live until it has processed them. Doesn't finish the inner storage.
+    pub fn finish(&self) {
+        self.sender.send(RemoteMessage::Shutdown).ok();
+    }
+    fn preview(
+        &self, id: u32, parent: u32, attr_names: &[String], attr_values: &[EnValue],
+        meta: &'static tracing::Metadata<'_>,
+    ) {
+        let mut entry = TraceEntry::from_unsorted_attrs(
+            parent,
+            None,
+            meta.into(),
+            attr_names.to_vec(),
+            attr_values.to_vec(),
+        );
+        if let Some(val) = entry.as_ref().get_attr("message")
+            && let EnValueRef::String(s) = val
+        {
+            entry.message = Some(s.to_string())
+        }
+        self.sender.send(RemoteMessage::NewSpan { id, entry }).ok();
+    }
+}
+impl<S: Storage> Storage for PreviewStorage<S> {
+    fn new_span(
+        &self, id: u32, parent: u32, attr_names: Vec<String>, attr_values: Vec<EnValue>,
+        meta: &'static tracing::Metadata<'_>,
+    ) {
+        self.preview(id, parent, &attr_names, &attr_values, meta);
+        self.inner.new_span(id, parent, attr_names, attr_values, meta);
+    }
+    fn new_event(
+        &self, id: u32, parent: u32, attr_names: Vec<String>, attr_values: Vec<EnValue>,
+        meta: &'static tracing::Metadata<'_>,
+    ) {
+        self.preview(id, parent, &attr_names, &attr_values, meta);
+        self.inner.new_event(id, parent, attr_names, attr_values, meta);
+    }
+    fn follows_from(&self, id: u32, follows: u32) {
+        self.sender.send(RemoteMessage::FollowsFrom { id, follows }).ok();
+        self.inner.follows_from(id, follows);
+    }
+    /// The summary is printed when the inner storage is finished.
+    fn set_lifecycle(&self, lifecycle: Arc<SpanLifecycle>) {
+        self.inner.set_lifecycle(lifecycle);
+    }
+}
+
+/// Orders the entries like [crate::remote::IETStorage] does before writing them, and sends them
+/// to the provider in batches.
+fn preview_worker<R: Refresh>(
+    rx: Receiver<RemoteMessage>, tx: Sender<MainThreadMessage>, config: IETPresentationConfig<R>,
+) {
+    let mut batch_size = AdaptiveBatchSize::new();
+    let mut batch = vec![TraceEntry::root()];
+    let mut pending = PendingEntries::new();
+    let flush = |batch: &mut Vec<TraceEntry>, batch_size: &mut AdaptiveBatchSize| {
+        if batch.is_empty() {
+            return true;
+        }
+        let entries = std::mem::replace(batch, Vec::with_capacity(batch_size.get()));
+        if tx.send(MainThreadMessage::InsertMany(entries)).is_err() {
+            // the provider was dropped
+            return false;
+        }
+        batch_size.update(tx.len());
+        config.refresher.refresh();
+        true
+    };
+    loop {
+        // don't sit on a partial batch while nothing else arrives
+        if rx.is_empty() && !flush(&mut batch, &mut batch_size) {
+            return;
+        }
+        match rx.recv() {
+            Ok(RemoteMessage::NewSpan { id, entry }) => pending.insert(id, entry),
+            Ok(RemoteMessage::FollowsFrom { id, follows }) => {
+                if !pending.add_follows_from(id, follows) {
+                    // the entry may still be in the batch, which has to arrive first
+                    if !flush(&mut batch, &mut batch_size)
+                        || tx.send(MainThreadMessage::FollowsFrom { id, follows }).is_err()
+                    {
+                        return;
+                    }
+                    config.refresher.refresh();
+                }
+            }
+            Ok(RemoteMessage::Shutdown) | Err(_) => break,
+        }
+        while let Some(entry) = pending.pop_ready() {
+            batch.push(entry);
+            if batch.len() >= batch_size.get() && !flush(&mut batch, &mut batch_size) {
+                return;
+            }
+        }
+    }
+    flush(&mut batch, &mut batch_size);
+}
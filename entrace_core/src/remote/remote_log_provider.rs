@@ -1,18 +1,26 @@
-use crate::TraceEntry;
 use crate::remote::IETInfo;
 use crate::tree_layer::EnValueRef;
+use crate::{
+    AttrEncoding, FrameError, IETRecord, TraceEntry, decode_length_prefix, parse_entrace_magic,
+    read_frame_into,
+};
 use crate::{LogProviderError, remote::IETEvent};
 use std::{
     io::{BufRead, BufReader, Read},
-    net::{TcpListener, TcpStream},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
     ops::ControlFlow,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::JoinHandle,
     time::Duration,
 };
 
 use crate::{
     Header, IETPresentationConfig, MetadataRefContainer,
     log_provider::{LogProvider, LogProviderResult},
-    remote::{BaseIETLogProvider, MainThreadMessage, Refresh},
+    remote::{AdaptiveBatchSize, BaseIETLogProvider, MainThreadMessage, Refresh},
 };
 use crossbeam_channel::Sender;
 
@@ -29,38 +37,53 @@ struct RemoteWorkerState<'a, R: Refresh> {
     read_state: ReadState,
     event_buf: Vec<TraceEntry>,
     byte_buf: Vec<u8>,
+    batch_size: AdaptiveBatchSize,
+    attr_encoding: AttrEncoding,
+    /// The bytes read so far, see [LogProvider::source_bytes].
+    received: Arc<AtomicU64>,
+    /// The bytes read from this stream so far, for locating decode errors.
+    offset: u64,
 }
 impl<'a, R: Refresh> RemoteWorkerState<'a, R> {
     pub fn new(
         event_tx: Option<crossbeam_channel::Sender<IETEvent>>, refresher: R,
         reader: BufReader<&'a mut TcpStream>, tx: Sender<MainThreadMessage>, read_state: ReadState,
+        received: Arc<AtomicU64>,
     ) -> RemoteWorkerState<'a, R> {
         Self {
+            received,
+            offset: 0,
             refresher,
             reader,
             tx,
             read_state,
-            event_buf: Vec::with_capacity(512),
+            event_buf: Vec::with_capacity(AdaptiveBatchSize::MIN),
             byte_buf: Vec::with_capacity(1024),
             event_tx,
+            batch_size: AdaptiveBatchSize::new(),
+            attr_encoding: AttrEncoding::Standard,
         }
     }
     pub fn send_event_buf(&mut self) {
         use MainThreadMessage::*;
         match self.event_buf.len() {
-            0 => (),
+            // nothing was sent, so the backlog says nothing about the batch size
+            0 => return,
             1 => {
                 let msg = Insert(self.event_buf.pop().unwrap());
-                self.tx.send(msg).unwrap();
+                // fails if the provider is closed, and then the entries are not needed anymore
+                self.tx.send(msg).ok();
                 self.refresher.refresh();
             }
             _x => {
-                let old_event_buf = std::mem::replace(&mut self.event_buf, Vec::with_capacity(512));
+                let new_event_buf = Vec::with_capacity(self.batch_size.get());
+                let old_event_buf = std::mem::replace(&mut self.event_buf, new_event_buf);
                 let msg = InsertMany(old_event_buf);
-                self.tx.send(msg).unwrap();
+                self.tx.send(msg).ok();
                 self.refresher.refresh();
             }
         }
+        self.batch_size.update(self.tx.len());
     }
     const SHORT_TIMEOUT: Option<Duration> = Some(Duration::from_millis(50));
     pub fn set_short_timeout(&mut self) -> Result<(), LogProviderError> {
@@ -85,18 +108,43 @@ impl<'a, R: Refresh> RemoteWorkerState<'a, R> {
             x.send(IETEvent::Error(e)).ok();
         }
     }
+    fn count_read(&mut self, bytes: u64) {
+        self.offset += bytes;
+        self.received.fetch_add(bytes, Ordering::Relaxed);
+    }
     pub fn read_loop_body(&mut self) -> ControlFlow<Option<LogProviderError>> {
-        let cfg = bincode::config::standard();
         match self.read_state {
             ReadState::WantMagic => {
                 let mut header_buf = [0; 10];
                 if let Err(y) = self.reader.read_exact(&mut header_buf) {
-                    self.err(y.into());
+                    use std::io::ErrorKind::*;
+                    match y.kind() {
+                        WouldBlock | TimedOut => {
+                            if let Err(y) = self.block_on_data() {
+                                self.err(y);
+                            }
+                        }
+                        UnexpectedEof => {
+                            self.info(IETInfo::RemoteClosedConnection);
+                            return ControlFlow::Break(None);
+                        }
+                        _ => return ControlFlow::Break(Some(y.into())),
+                    }
                 } else {
+                    self.count_read(header_buf.len() as u64);
+                    match parse_entrace_magic(&header_buf) {
+                        Ok((_, _, attr_encoding)) => self.attr_encoding = attr_encoding,
+                        Err(y) => self.err(y.into()),
+                    }
                     self.read_state = ReadState::WantMessage;
                 }
             }
             ReadState::WantMessage => {
+                // everything received so far is decoded, so don't hold it back until the read
+                // times out
+                if self.reader.buffer().is_empty() {
+                    self.send_event_buf();
+                }
                 // buffer for the content-length
                 let mut cl_buf = [0; 8];
                 if let Err(y) = self.reader.read_exact(&mut cl_buf) {
@@ -113,13 +161,18 @@ impl<'a, R: Refresh> RemoteWorkerState<'a, R> {
                         self.refresher.refresh();
                         return ControlFlow::Break(None);
                     } else {
-                        self.err(y.into());
+                        self.send_event_buf();
+                        return ControlFlow::Break(Some(y.into()));
                     }
                 }
 
-                let content_len = u64::from_le_bytes(cl_buf);
+                let content_len = match decode_length_prefix(cl_buf) {
+                    Ok(x) => x,
+                    // the stream can't be framed anymore
+                    Err(y) => return ControlFlow::Break(Some(y.into())),
+                };
                 // Today's BufReader api doesn't allow to block until we have a specific number of
-                // bytes is available, except for `read_exact`.
+                // bytes is available, except for reading them out, like `read_frame_into` does.
                 // (Because `fill_buf` won't fill anything if there is data left in the buffer).
                 // Therefore we are technically copying things twice here, once to BufReader's
                 // internal buffer, then to ours.
@@ -129,16 +182,37 @@ impl<'a, R: Refresh> RemoteWorkerState<'a, R> {
                 // An alternative could be to use bincode::serde::decode_from_std_read, but we
                 // choose to be paranoid about the content-len here.
                 // See also: https://graphallthethings.com/posts/better-buf-read
-                self.byte_buf.clear();
-                self.byte_buf.resize(content_len as usize, 0);
-                if let Err(y) = self.reader.read_exact(&mut self.byte_buf) {
+                if let Err(y) = read_frame_into(&mut self.reader, content_len, &mut self.byte_buf) {
                     return ControlFlow::Break(Some(y.into()));
                 };
-                let decoded: Result<(TraceEntry, usize), _> =
-                    bincode::serde::decode_from_slice(&self.byte_buf, cfg);
+                let frame_start = self.offset;
+                self.count_read(cl_buf.len() as u64 + content_len);
+                let decoded = match self.attr_encoding.decode_framed_record(&self.byte_buf) {
+                    Ok(x) => Ok(x),
+                    Err(FrameError::Decode(y)) => Err(y),
+                    // the frames can't be trusted anymore
+                    Err(FrameError::Prefix(y)) => {
+                        self.send_event_buf();
+                        return ControlFlow::Break(Some(y.into()));
+                    }
+                };
                 match decoded {
-                    Ok(x) => self.event_buf.push(x.0),
-                    Err(y) => self.err(y.into()),
+                    Ok(IETRecord::FollowsFrom { id, follows }) => {
+                        // the entry may still be in the buffer, which has to arrive first
+                        self.send_event_buf();
+                        self.tx.send(MainThreadMessage::FollowsFrom { id, follows }).ok();
+                        self.refresher.refresh();
+                    }
+                    Ok(IETRecord::Entry(x)) => {
+                        self.event_buf.push(x);
+                        // under sustained load, reads never time out, so also flush full batches
+                        if self.event_buf.len() >= self.batch_size.get() {
+                            self.send_event_buf();
+                        }
+                    }
+                    Err(source) => {
+                        self.err(LogProviderError::DecodeAt { offset: frame_start, source })
+                    }
                 }
             }
         }
@@ -149,15 +223,111 @@ impl<'a, R: Refresh> RemoteWorkerState<'a, R> {
 pub enum RemoteLogProviderError {
     #[error("Server sees a connection, but cannot establish a TCPStream. Quitting.")]
     CannotAccept(#[source] std::io::Error),
+    #[error("Cannot connect to {0}")]
+    CannotConnect(SocketAddr, #[source] std::io::Error),
+}
+
+/// Read the trace sent over `stream` until it is closed, and send its entries to `tx`. Counts the
+/// bytes read in `received`.
+fn read_stream<R: Refresh>(
+    stream: &mut TcpStream, event_tx: Option<Sender<IETEvent>>, refresher: R,
+    tx: Sender<MainThreadMessage>, received: Arc<AtomicU64>,
+) {
+    let reader = BufReader::new(stream);
+    let read_state = ReadState::WantMagic;
+    let mut state = RemoteWorkerState::new(event_tx, refresher, reader, tx, read_state, received);
+    if let Err(y) = state.set_short_timeout() {
+        state.err(y);
+    }
+    loop {
+        match state.read_loop_body() {
+            ControlFlow::Continue(_) => (),
+            ControlFlow::Break(Some(y)) => {
+                state.err(y);
+                break;
+            }
+            ControlFlow::Break(None) => break,
+        }
+    }
+}
+
+/// Stops the worker of a [RemoteLogProvider], which otherwise blocks in `accept` or `read`.
+struct RemoteShutdown {
+    /// Where the server listens, `None` when connecting out, see [RemoteLogProvider::connect].
+    listen_addr: Option<SocketAddr>,
+    state: Mutex<RemoteShutdownState>,
+}
+#[derive(Default)]
+struct RemoteShutdownState {
+    requested: bool,
+    /// A clone of the accepted connection.
+    stream: Option<TcpStream>,
+}
+impl RemoteShutdown {
+    fn connecting() -> Self {
+        Self { listen_addr: None, state: Mutex::default() }
+    }
+    fn new(listener: &TcpListener) -> Self {
+        // connecting to an unspecified address is not portable, use the loopback one instead
+        let listen_addr = listener.local_addr().ok().map(|mut addr| {
+            match addr.ip() {
+                IpAddr::V4(x) if x.is_unspecified() => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+                IpAddr::V6(x) if x.is_unspecified() => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+                _ => (),
+            }
+            addr
+        });
+        Self { listen_addr, state: Mutex::default() }
+    }
+    /// Called by the worker after accepting or connecting `stream`. Returns false if it should
+    /// stop instead.
+    fn register(&self, stream: &TcpStream) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.requested {
+            return false;
+        }
+        state.stream = stream.try_clone().ok();
+        true
+    }
+    fn request(&self) {
+        let mut state = self.state.lock().unwrap();
+        if std::mem::replace(&mut state.requested, true) {
+            return;
+        }
+        match state.stream.take() {
+            // makes blocked reads return EOF
+            Some(stream) => {
+                stream.shutdown(Shutdown::Both).ok();
+            }
+            // wake up `accept`, the worker then sees the request in `register`
+            None => {
+                if let Some(addr) = self.listen_addr {
+                    TcpStream::connect_timeout(&addr, Duration::from_secs(1)).ok();
+                }
+            }
+        }
+    }
+}
+/// Requests a [RemoteShutdown] when dropped.
+struct RemoteShutdownGuard(Arc<RemoteShutdown>);
+impl Drop for RemoteShutdownGuard {
+    fn drop(&mut self) {
+        self.0.request();
+    }
 }
+
 /// Provides a [crate::log_provider::LogProvider] based on incoming data from a TCP stream.
-pub struct RemoteLogProvider(BaseIETLogProvider);
+///
+/// The worker thread stops when the provider is dropped or [closed](RemoteLogProvider::close),
+/// closing the connection.
+pub struct RemoteLogProvider(BaseIETLogProvider, RemoteShutdownGuard);
 impl RemoteLogProvider {
     pub fn new<R: Refresh + Send + 'static>(
         listener: TcpListener, config: IETPresentationConfig<R>,
     ) -> Self {
         fn worker<R: Refresh + Send>(
-            listener: TcpListener, tx: Sender<MainThreadMessage>, config: IETPresentationConfig<R>,
+            listener: TcpListener, shutdown: &RemoteShutdown, tx: Sender<MainThreadMessage>,
+            config: IETPresentationConfig<R>, received: Arc<AtomicU64>,
         ) {
             let IETPresentationConfig { refresher, event_tx } = config;
             let info = |i| {
@@ -181,27 +351,80 @@ impl RemoteLogProvider {
                     return;
                 }
             };
+            drop(listener);
+            if !shutdown.register(&stream) {
+                return;
+            }
             info(IETInfo::ReceivedConnection);
             refresher.refresh();
-            let reader = BufReader::new(&mut stream);
-            let mut state =
-                RemoteWorkerState::new(event_tx, refresher, reader, tx, ReadState::WantMagic);
-            if let Err(y) = state.set_short_timeout() {
-                state.err(y);
-            }
-            loop {
-                match state.read_loop_body() {
-                    ControlFlow::Continue(_) => (),
-                    ControlFlow::Break(Some(y)) => {
-                        state.err(y);
-                        break;
+            read_stream(&mut stream, event_tx, refresher, tx, received);
+        }
+        let shutdown = Arc::new(RemoteShutdown::new(&listener));
+        let worker_shutdown = shutdown.clone();
+        let received = Arc::new(AtomicU64::new(0));
+        let worker_received = received.clone();
+        let mut base = BaseIETLogProvider::new(listener, config, move |listener, tx, config| {
+            worker(listener, &worker_shutdown, tx, config, worker_received)
+        });
+        base.source_bytes = Some(received);
+        Self(base, RemoteShutdownGuard(shutdown))
+    }
+    /// Connect to a traced process listening on `addr`, and read the trace it sends, instead of
+    /// waiting for it to connect like [RemoteLogProvider::new].
+    ///
+    /// The connection is made on the worker thread, so this doesn't block. If it fails, or takes
+    /// more than 10 seconds, the error is sent to [IETPresentationConfig::event_tx].
+    pub fn connect<R: Refresh + Send + 'static>(
+        addr: SocketAddr, config: IETPresentationConfig<R>,
+    ) -> Self {
+        fn worker<R: Refresh + Send>(
+            addr: SocketAddr, shutdown: &RemoteShutdown, tx: Sender<MainThreadMessage>,
+            config: IETPresentationConfig<R>, received: Arc<AtomicU64>,
+        ) {
+            let IETPresentationConfig { refresher, event_tx } = config;
+            // bounded, as the worker can't be stopped while connecting
+            let mut stream = match TcpStream::connect_timeout(&addr, Duration::from_secs(10)) {
+                Ok(stream) => stream,
+                Err(y) => {
+                    if let Some(q) = &event_tx {
+                        let error = RemoteLogProviderError::CannotConnect(addr, y);
+                        q.send(IETEvent::Error(error.into())).ok();
                     }
-                    ControlFlow::Break(None) => break,
+                    refresher.refresh();
+                    return;
                 }
+            };
+            if !shutdown.register(&stream) {
+                return;
+            }
+            if let Some(q) = &event_tx {
+                q.send(IETEvent::Info(IETInfo::Connected)).ok();
             }
+            refresher.refresh();
+            read_stream(&mut stream, event_tx, refresher, tx, received);
         }
-        let base = BaseIETLogProvider::new(listener, config, worker);
-        Self(base)
+        let shutdown = Arc::new(RemoteShutdown::connecting());
+        let worker_shutdown = shutdown.clone();
+        let received = Arc::new(AtomicU64::new(0));
+        let worker_received = received.clone();
+        let mut base = BaseIETLogProvider::new(addr, config, move |addr, tx, config| {
+            worker(addr, &worker_shutdown, tx, config, worker_received)
+        });
+        base.source_bytes = Some(received);
+        Self(base, RemoteShutdownGuard(shutdown))
+    }
+    /// Stop the worker thread, closing the connection, and free the trace.
+    ///
+    /// Returns the handle of the worker, which exits soon after, for waiting on it. Dropping the
+    /// provider stops the worker too.
+    pub fn close(self) -> JoinHandle<()> {
+        let Self(base, shutdown) = self;
+        drop(shutdown);
+        base.close()
+    }
+    /// See [BaseIETLogProvider::pending_messages].
+    pub fn pending_messages(&self) -> usize {
+        self.0.pending_messages()
     }
 }
 
@@ -221,7 +444,11 @@ impl LogProvider for RemoteLogProvider {
     dispatch_to_parent!(fn header(x: u32) -> LogProviderResult<Header<'_>>);
     dispatch_to_parent!(fn message(x: u32) -> LogProviderResult<Option<&str>>);
     dispatch_to_parent!(fn meta(x: u32) -> LogProviderResult<MetadataRefContainer<'_>>);
+    dispatch_to_parent!(fn follows_from(x: u32) -> LogProviderResult<Vec<u32>>);
     dispatch_to_parent!(fn len()-> usize);
+    dispatch_to_parent!(fn is_live()-> bool);
+    dispatch_to_parent!(fn source_bytes()-> Option<u64>);
+    dispatch_to_parent!(fn snapshot()-> Box<dyn LogProvider + Send + Sync>);
 
     fn frame_callback(&mut self) {
         self.0.frame_callback();
@@ -1,27 +1,45 @@
+use crate::tree_layer::SpanLifecycle;
 use crate::{
-    EN_DISK_VERSION, EnValueRef, StorageFormat, TraceEntry, entrace_magic_for, storage::Storage,
+    AttrEncoding, EN_DISK_VERSION, EnValueRef, IETRecord, StorageFormat, TraceEntry,
+    encode_length_prefix, entrace_magic_with,
+    storage::{PendingEntries, Storage},
     tree_layer::EnValue,
 };
 use crossbeam_channel::{SendError, Sender};
-use std::{any::Any, collections::BTreeMap, io::Write, sync::RwLock, thread::JoinHandle};
+use std::{
+    any::Any,
+    io::Write,
+    sync::{Arc, OnceLock, RwLock},
+    thread::JoinHandle,
+};
 
+// entries are the common case, boxing them would cost an allocation each
+#[allow(clippy::large_enum_variant)]
 pub enum RemoteMessage {
     NewSpan { id: u32, entry: TraceEntry },
+    FollowsFrom { id: u32, follows: u32 },
     Shutdown,
 }
 pub struct IETStorageConfig<T: Write + Send> {
     writable: T,
     length_prefixed: bool,
+    attr_encoding: AttrEncoding,
 }
 impl<T: Write + Send> IETStorageConfig<T> {
     /// Recommended for [std::net::TcpStream] or [`std::io::BufWriter<std::net::TcpStream>`]
     pub fn length_prefixed(writable: T) -> Self {
-        Self { writable, length_prefixed: true }
+        Self { writable, length_prefixed: true, attr_encoding: AttrEncoding::Standard }
     }
 
     /// Recommended for [std::fs::File] or [std::io::BufWriter<File>]
     pub fn non_length_prefixed(writable: T) -> Self {
-        Self { writable, length_prefixed: false }
+        Self { writable, length_prefixed: false, attr_encoding: AttrEncoding::Standard }
+    }
+
+    /// Write attribute values in `attr_encoding` instead of [AttrEncoding::Standard].
+    pub fn with_attr_encoding(mut self, attr_encoding: AttrEncoding) -> Self {
+        self.attr_encoding = attr_encoding;
+        self
     }
 }
 #[derive(thiserror::Error, Debug)]
@@ -40,6 +58,8 @@ pub enum IETStorageError {
 pub struct IETStorage<T: Write + Send + 'static> {
     pub sender: Sender<RemoteMessage>,
     pub thread_handle: RwLock<Option<JoinHandle<T>>>,
+    /// Summarized when the storage is finished, see [Storage::set_lifecycle].
+    pub lifecycle: OnceLock<Arc<SpanLifecycle>>,
 }
 impl<T: Write + Send + 'static> IETStorage<T> {
     pub fn init(mut config: IETStorageConfig<T>) -> Self {
@@ -47,42 +67,39 @@ impl<T: Write + Send + 'static> IETStorage<T> {
         let format =
             if config.length_prefixed { StorageFormat::IETPrefix } else { StorageFormat::IET };
         let thread_handle = std::thread::spawn(move || {
-            let magic = entrace_magic_for(EN_DISK_VERSION, format);
+            let magic = entrace_magic_with(EN_DISK_VERSION, format, config.attr_encoding);
             config.writable.write_all(&magic).unwrap();
             let mut buffer: Vec<u8> = Vec::with_capacity(1024);
-            /// Write a length-prefixed message.
+            /// Write a record, with a length prefix if configured so.
             fn write_message<T: Write + Send>(
-                buffer: &mut Vec<u8>, message: TraceEntry, config: &mut IETStorageConfig<T>,
+                buffer: &mut Vec<u8>, message: IETRecord, config: &mut IETStorageConfig<T>,
             ) {
-                let bcfg = bincode::config::standard();
+                let encoding = config.attr_encoding;
                 if config.length_prefixed {
                     buffer.clear();
-                    bincode::serde::encode_into_std_write(message, buffer, bcfg).unwrap();
+                    encoding.encode_record(&message, buffer).unwrap();
 
-                    config.writable.write_all(&(buffer.len() as u64).to_le_bytes()).unwrap();
+                    config.writable.write_all(&encode_length_prefix(buffer.len() as u64)).unwrap();
                     std::io::copy(&mut buffer.as_slice(), &mut config.writable).unwrap();
                 } else {
-                    bincode::serde::encode_into_std_write(message, &mut config.writable, bcfg)
-                        .unwrap();
+                    encoding.encode_record(&message, &mut config.writable).unwrap();
                 }
             }
 
-            write_message(&mut buffer, TraceEntry::root(), &mut config);
-            // If multiple threads are writing subspans to the same parent, there can be a race
-            // condition where a child arrives before the parent. This is reproducable on Windows
-            // only, see issue #4.
-            // To prevent this, we store disjoint leaves in a buffer until their parent arrives.
-            // next_id keeps track of what id comes next in sequential order. if the buffer has next_id, we are good and the entry can be written.
-            // Else we wait for it to appear.
-            let mut reorder_buffer = BTreeMap::new();
-            let mut next_id = 1u32;
+            write_message(&mut buffer, IETRecord::Entry(TraceEntry::root()), &mut config);
+            let mut pending = PendingEntries::new();
             while let Ok(msg) = rx.recv() {
                 match msg {
                     RemoteMessage::NewSpan { id, entry } => {
-                        reorder_buffer.insert(id, entry);
-                        while let Some(entry) = reorder_buffer.remove(&next_id) {
-                            write_message(&mut buffer, entry, &mut config);
-                            next_id += 1;
+                        pending.insert(id, entry);
+                        while let Some(entry) = pending.pop_ready() {
+                            write_message(&mut buffer, IETRecord::Entry(entry), &mut config);
+                        }
+                    }
+                    RemoteMessage::FollowsFrom { id, follows } => {
+                        if !pending.add_follows_from(id, follows) {
+                            let link = IETRecord::FollowsFrom { id, follows };
+                            write_message(&mut buffer, link, &mut config);
                         }
                     }
                     RemoteMessage::Shutdown => break,
@@ -91,7 +108,11 @@ impl<T: Write + Send + 'static> IETStorage<T> {
             config.writable.flush().ok();
             config.writable
         });
-        IETStorage { sender: tx, thread_handle: RwLock::new(Some(thread_handle)) }
+        IETStorage {
+            sender: tx,
+            thread_handle: RwLock::new(Some(thread_handle)),
+            lifecycle: OnceLock::new(),
+        }
     }
 
     pub fn finish(&self) -> Result<T, IETStorageError> {
@@ -99,7 +120,11 @@ impl<T: Write + Send + 'static> IETStorage<T> {
         let mut thread_handle =
             self.thread_handle.write().map_err(|_| IETStorageError::Poisoned)?;
         let thread_handle = std::mem::take(&mut *thread_handle).ok_or(IETStorageError::NoHandle)?;
-        thread_handle.join().map_err(IETStorageError::ThreadJoin)
+        let writable = thread_handle.join().map_err(IETStorageError::ThreadJoin)?;
+        if let Some(lifecycle) = self.lifecycle.get() {
+            lifecycle.log_summary();
+        }
+        Ok(writable)
     }
 }
 impl<T: Write + Send + 'static> Storage for IETStorage<T> {
@@ -116,6 +141,12 @@ impl<T: Write + Send + 'static> Storage for IETStorage<T> {
         }
         self.sender.send(RemoteMessage::NewSpan { id, entry }).ok();
     }
+    fn follows_from(&self, id: u32, follows: u32) {
+        self.sender.send(RemoteMessage::FollowsFrom { id, follows }).ok();
+    }
+    fn set_lifecycle(&self, lifecycle: Arc<SpanLifecycle>) {
+        self.lifecycle.set(lifecycle).ok();
+    }
 }
 
 impl<T: Write + Send + 'static> Drop for IETStorage<T> {
@@ -1,5 +1,5 @@
-use crate::EnValue;
-use std::thread::JoinHandle;
+use crate::{EnValue, MixedTraceEntry, TraceEntry, tree_layer::SpanLifecycle};
+use std::{collections::BTreeMap, sync::Arc, thread::JoinHandle};
 use tracing::Metadata;
 
 pub trait Close {
@@ -34,4 +34,65 @@ pub trait Storage {
     ) {
         self.new_span(id, parent, attr_names, attr_values, meta);
     }
+
+    /// Record that span `id` follows from span `follows`, which both already exist.
+    /// Implemented by default as a no-op, for storages which don't keep these links.
+    fn follows_from(&self, _id: u32, _follows: u32) {}
+
+    /// Called by [crate::tree_layer::TreeLayer::from_storage] with the span counters of the layer,
+    /// so that finishing the storage can print [SpanLifecycle::log_summary].
+    /// Implemented by default as a no-op.
+    fn set_lifecycle(&self, _lifecycle: Arc<SpanLifecycle>) {}
+}
+
+pub(crate) trait FollowsFromMut {
+    fn follows_from_mut(&mut self) -> &mut Vec<u32>;
+}
+impl FollowsFromMut for TraceEntry {
+    fn follows_from_mut(&mut self) -> &mut Vec<u32> {
+        &mut self.follows_from
+    }
+}
+impl FollowsFromMut for MixedTraceEntry {
+    fn follows_from_mut(&mut self) -> &mut Vec<u32> {
+        &mut self.follows_from
+    }
+}
+
+/// Entries received by a storage worker which can't be written yet.
+///
+/// If multiple threads are writing subspans to the same parent, there can be a race condition
+/// where a child arrives before the parent. This is reproducable on Windows only, see issue #4.
+/// To prevent this, entries are only released in id order, so disjoint leaves wait here until
+/// their parent arrives. Every other entry is released as soon as it arrives.
+pub(crate) struct PendingEntries<E> {
+    /// Entries which are waiting for an entry with a lower id.
+    out_of_order: BTreeMap<u32, E>,
+    next_id: u32,
+}
+impl<E: FollowsFromMut> PendingEntries<E> {
+    /// The root is written by the worker itself, so this starts at id 1.
+    pub fn new() -> Self {
+        Self { out_of_order: BTreeMap::new(), next_id: 1 }
+    }
+    pub fn insert(&mut self, id: u32, entry: E) {
+        self.out_of_order.insert(id, entry);
+    }
+    /// Add a follows-from link to `id` if it is still waiting. Returns `false` if it was released
+    /// already, then the link has to be written as its own record, see [crate::IETRecord].
+    pub fn add_follows_from(&mut self, id: u32, follows: u32) -> bool {
+        match self.out_of_order.get_mut(&id) {
+            Some(entry) => {
+                entry.follows_from_mut().push(follows);
+                true
+            }
+            None => false,
+        }
+    }
+    /// The next entry which can be written.
+    pub fn pop_ready(&mut self) -> Option<E> {
+        let entry = self.out_of_order.remove(&self.next_id)?;
+        self.next_id += 1;
+        Some(entry)
+    }
 }
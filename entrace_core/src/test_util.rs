@@ -0,0 +1,131 @@
+//! Helpers for building traces in memory, without a file or a tracing subscriber.
+//!
+//! Enabled by the `test-util` feature.
+//!
+//! ```
+//! use entrace_core::{EnValue, LevelContainer, test_util::LogProviderBuilder};
+//!
+//! let mut builder = LogProviderBuilder::new();
+//! let request = builder.span(0, "request").attr("user", EnValue::String("bob".into())).add();
+//! let event = builder.span(request, "event").message("done").level(LevelContainer::Warn).add();
+//! let log = builder.build();
+//!
+//! assert_eq!(log.children(0).unwrap(), &[request]);
+//! assert_eq!(log.children(request).unwrap(), &[event]);
+//! assert_eq!(log.message(event).unwrap(), Some("done"));
+//! assert_eq!(log.header(event).unwrap().display_text(event), "[W]: done");
+//! ```
+use crate::{
+    EnValue, IETPresentationConfig, LevelContainer, LogProvider, LogProviderImpl,
+    MetadataContainer, PoolEntry, TraceEntry, remote::BaseIETLogProvider,
+};
+
+/// Builds a [LogProvider] from spans added one by one.
+///
+/// Span ids are handed out in insertion order, starting with 1, as 0 is the root.
+pub struct LogProviderBuilder {
+    pool: Vec<PoolEntry>,
+    data: Vec<TraceEntry>,
+}
+impl LogProviderBuilder {
+    /// A builder holding only the root span.
+    pub fn new() -> Self {
+        Self { pool: vec![PoolEntry::new()], data: vec![TraceEntry::root()] }
+    }
+    /// Start a span under `parent`. Call [SpanBuilder::add] to insert it.
+    ///
+    /// # Panics
+    /// When it is added, if `parent` is not a span added earlier.
+    pub fn span(&mut self, parent: u32, name: &str) -> SpanBuilder<'_> {
+        let metadata = MetadataContainer { name: name.into(), ..Default::default() };
+        SpanBuilder {
+            builder: self,
+            parent,
+            metadata,
+            message: None,
+            attrs: vec![],
+            follows_from: vec![],
+        }
+    }
+    /// Like [LogProviderBuilder::build], but without type erasure.
+    pub fn build_base(self) -> BaseIETLogProvider {
+        let mut provider =
+            BaseIETLogProvider::new((), IETPresentationConfig::default(), |_, _, _| ());
+        provider.pool = self.pool;
+        provider.data = self.data;
+        provider
+    }
+    /// Like [LogProviderBuilder::build], for APIs which take a [LogProviderImpl].
+    pub fn build_impl(self) -> LogProviderImpl {
+        LogProviderImpl::BaseIET(self.build_base())
+    }
+    pub fn build(self) -> Box<dyn LogProvider> {
+        Box::new(self.build_base())
+    }
+}
+impl Default for LogProviderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A span being built by [LogProviderBuilder::span].
+pub struct SpanBuilder<'a> {
+    builder: &'a mut LogProviderBuilder,
+    parent: u32,
+    metadata: MetadataContainer,
+    message: Option<String>,
+    attrs: Vec<(String, EnValue)>,
+    follows_from: Vec<u32>,
+}
+impl SpanBuilder<'_> {
+    pub fn attr(mut self, name: &str, value: EnValue) -> Self {
+        self.attrs.push((name.into(), value));
+        self
+    }
+    /// Set the message, which is also stored in the `message` attribute, like `tracing` does.
+    pub fn message(mut self, message: &str) -> Self {
+        self.message = Some(message.into());
+        self.attr("message", EnValue::String(message.into()))
+    }
+    pub fn level(mut self, level: LevelContainer) -> Self {
+        self.metadata.level = level;
+        self
+    }
+    pub fn target(mut self, target: &str) -> Self {
+        self.metadata.target = target.into();
+        self
+    }
+    pub fn module_path(mut self, module_path: &str) -> Self {
+        self.metadata.module_path = Some(module_path.into());
+        self
+    }
+    pub fn file(mut self, file: &str) -> Self {
+        self.metadata.file = Some(file.into());
+        self
+    }
+    pub fn line(mut self, line: u32) -> Self {
+        self.metadata.line = Some(line);
+        self
+    }
+    /// Add a follows-from link to the span `id`, which doesn't have to exist yet.
+    pub fn follows_from(mut self, id: u32) -> Self {
+        self.follows_from.push(id);
+        self
+    }
+    /// Insert the span, returning its id.
+    pub fn add(self) -> u32 {
+        let SpanBuilder { builder, parent, metadata, message, attrs, follows_from } = self;
+        let id = builder.data.len() as u32;
+        let parent_entry = builder.pool.get_mut(parent as usize);
+        let parent_entry = parent_entry.unwrap_or_else(|| panic!("No span with id {parent}"));
+        parent_entry.children.push(id);
+        builder.pool.push(PoolEntry::new());
+
+        let (names, values) = attrs.into_iter().unzip();
+        let entry = TraceEntry::from_unsorted_attrs(parent, message, metadata, names, values)
+            .with_follows_from(follows_from);
+        builder.data.push(entry);
+        id
+    }
+}
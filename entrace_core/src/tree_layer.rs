@@ -1,28 +1,115 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock, atomic::AtomicU32},
+    fmt::Display,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
 };
 
 use tracing::{Subscriber, error};
 use tracing_subscriber::Layer;
 
-use crate::Storage;
+use crate::{PoolRef, Storage};
 
+/// A [Layer] which saves spans and events into a [Storage].
+///
+/// A trace can hold at most [TreeLayer::max_id] spans and events, later ones are dropped, see
+/// [PoolRef].
 pub struct TreeLayer<S: Storage> {
-    pub id_to_pool: RwLock<HashMap<tracing::span::Id, u32>>,
+    pub id_to_pool: RwLock<HashMap<tracing::span::Id, PoolRef>>,
+    /// Spans dropped by [TreeLayer::target_filter], with the id of the parent their children are
+    /// attached to instead.
+    pub filtered: RwLock<HashMap<tracing::span::Id, PoolRef>>,
+    /// The last id given out.
     pub counter: AtomicU32,
+    /// No ids larger than this are given out, see [PoolRef].
+    pub max_id: PoolRef,
+    /// String and bytes attributes longer than this many bytes are cut, see
+    /// [TreeLayer::with_max_attr_len].
+    pub max_attr_len: usize,
+    /// Spans and events from the targets this rejects are dropped, see
+    /// [TreeLayer::with_target_filter].
+    pub target_filter: TargetFilter,
     pub storage: Arc<S>,
+    pub lifecycle: Arc<SpanLifecycle>,
 }
 impl<S: Storage> TreeLayer<S> {
     pub fn from_storage(storage: Arc<S>) -> Self {
-        Self { id_to_pool: RwLock::new(HashMap::new()), counter: AtomicU32::new(0), storage }
+        let lifecycle = Arc::<SpanLifecycle>::default();
+        storage.set_lifecycle(lifecycle.clone());
+        Self {
+            id_to_pool: RwLock::new(HashMap::new()),
+            filtered: RwLock::new(HashMap::new()),
+            counter: AtomicU32::new(0),
+            max_id: PoolRef::MAX,
+            max_attr_len: usize::MAX,
+            target_filter: TargetFilter::default(),
+            storage,
+            lifecycle,
+        }
+    }
+    /// Lower the id limit, to test how running out of ids is handled.
+    pub fn with_max_id(mut self, max_id: PoolRef) -> Self {
+        self.max_id = max_id;
+        self
+    }
+    /// Cut string and bytes attributes (including messages and `Debug`-formatted values) to
+    /// `max_attr_len` bytes, and mark them with [TRUNCATED_MARKER]. Unlimited by default.
+    ///
+    /// This happens before the values are stored, so it is lossy: the cut off part can't be
+    /// recovered from the trace.
+    pub fn with_max_attr_len(mut self, max_attr_len: usize) -> Self {
+        self.max_attr_len = max_attr_len;
+        self
+    }
+    /// Drop the spans and events whose target `target_filter` rejects, like those of noisy
+    /// dependencies, before they reach the storage. Everything is kept by default.
+    ///
+    /// Dropped spans don't use up ids. The children of a dropped span, if they are kept, are
+    /// attached to its closest kept ancestor instead, so they don't disappear with it.
+    pub fn with_target_filter(mut self, target_filter: TargetFilter) -> Self {
+        self.target_filter = target_filter;
+        self
+    }
+    /// A handle to the span lifecycle counters of this layer.
+    /// Get it before installing the layer, as the subscriber takes ownership of it.
+    pub fn lifecycle(&self) -> Arc<SpanLifecycle> {
+        self.lifecycle.clone()
+    }
+
+    /// The id of a new span or event, or `None` if there are no ids left. The first time that
+    /// happens, an error is printed to stderr: `tracing` can't be used here, as its events would
+    /// come back to this layer.
+    fn next_id(&self) -> Option<PoolRef> {
+        let prev = self.counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| {
+            (x < self.max_id).then_some(x + 1)
+        });
+        match prev {
+            Ok(x) => Some(x + 1),
+            Err(_) => {
+                if self.lifecycle.over_id_limit.fetch_add(1, Ordering::Relaxed) == 0 {
+                    eprintln!(
+                        "entrace: FATAL: the trace reached the limit of {} spans and events. New \
+                         ones are dropped from now on.",
+                        self.max_id
+                    );
+                }
+                None
+            }
+        }
     }
 
-    fn id_to_pool_index(&self, x: &tracing::Id) -> u32 {
+    fn id_to_pool_index(&self, x: &tracing::Id) -> PoolRef {
         let id_to_pool_r = self.id_to_pool.read().unwrap();
-        match id_to_pool_r.get(x) {
+        if let Some(x) = id_to_pool_r.get(x) {
+            return *x;
+        }
+        drop(id_to_pool_r);
+        match self.filtered.read().unwrap().get(x) {
             Some(x) => *x,
             None => {
+                self.lifecycle.unknown_parents.fetch_add(1, Ordering::Relaxed);
                 error!(
                     "CRITICAL \nno known map of {x:?} to pool id, this shouldn't happen. Will \
                      become child of root instead. "
@@ -33,12 +120,101 @@ impl<S: Storage> TreeLayer<S> {
     }
 }
 
+/// Counts how the spans seen by a [TreeLayer] were opened and closed, to help debugging
+/// instrumentation.
+///
+/// `tracing` closes a span once every handle to it is dropped, so a span which is still open when
+/// the trace is finished was leaked (or is still held, like a span around `main`).
+#[derive(Default, Debug)]
+pub struct SpanLifecycle {
+    created: AtomicU64,
+    closed: AtomicU64,
+    closed_without_creation: AtomicU64,
+    unknown_parents: AtomicU64,
+    over_id_limit: AtomicU64,
+}
+impl SpanLifecycle {
+    pub fn report(&self) -> SpanLifecycleReport {
+        let created = self.created.load(Ordering::Relaxed);
+        let closed = self.closed.load(Ordering::Relaxed);
+        SpanLifecycleReport {
+            created,
+            never_closed: created.saturating_sub(closed),
+            closed_without_creation: self.closed_without_creation.load(Ordering::Relaxed),
+            unknown_parents: self.unknown_parents.load(Ordering::Relaxed),
+            over_id_limit: self.over_id_limit.load(Ordering::Relaxed),
+        }
+    }
+    /// Print the [SpanLifecycleReport] to stderr if it found anything suspicious.
+    /// The storages of this crate call this when they are finished, when all spans should be
+    /// closed, see [Storage::set_lifecycle].
+    pub fn log_summary(&self) -> SpanLifecycleReport {
+        let report = self.report();
+        if !report.is_balanced() {
+            eprintln!("entrace: {report}");
+        }
+        report
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpanLifecycleReport {
+    pub created: u64,
+    /// Spans which are still open.
+    pub never_closed: u64,
+    /// Close notifications for spans the layer has never seen created.
+    pub closed_without_creation: u64,
+    /// Spans and events whose parent span was not known to the layer, so they were attached to
+    /// the root instead.
+    pub unknown_parents: u64,
+    /// Spans and events which were dropped, as the trace ran out of ids, see [crate::PoolRef].
+    pub over_id_limit: u64,
+}
+impl SpanLifecycleReport {
+    pub fn is_balanced(&self) -> bool {
+        self.never_closed == 0
+            && self.closed_without_creation == 0
+            && self.unknown_parents == 0
+            && self.over_id_limit == 0
+    }
+}
+impl Display for SpanLifecycleReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let SpanLifecycleReport {
+            created,
+            never_closed,
+            closed_without_creation,
+            unknown_parents,
+            over_id_limit,
+        } = self;
+        write!(
+            f,
+            "{created} spans created, {never_closed} never closed, {closed_without_creation} \
+             closed without being created, {unknown_parents} with an unknown parent"
+        )?;
+        if *over_id_limit > 0 {
+            write!(f, ", {over_id_limit} spans and events dropped over the id limit")?;
+        }
+        Ok(())
+    }
+}
+
 impl<S: Subscriber, S2: Storage + 'static> Layer<S> for TreeLayer<S2> {
     fn on_new_span(
         &self, attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id,
         ctx: tracing_subscriber::layer::Context<'_, S>,
     ) {
-        let parent: u32;
+        let filtered = !self.target_filter.is_enabled(attrs.metadata().target());
+        // before the parent lookup, so that nothing is looked up or counted for spans over the id
+        // limit
+        let pool_id = match filtered {
+            true => None,
+            false => {
+                let Some(pool_id) = self.next_id() else { return };
+                Some(pool_id)
+            }
+        };
+        let parent: PoolRef;
         if let Some(x) = attrs.parent() {
             parent = self.id_to_pool_index(x);
         } else if attrs.is_root() {
@@ -51,16 +227,24 @@ impl<S: Subscriber, S2: Storage + 'static> Layer<S> for TreeLayer<S2> {
         } else {
             unreachable!()
         }
-        let mut visitor = EventVisitor::new();
+        let Some(pool_id) = pool_id else {
+            self.filtered.write().unwrap().insert(id.clone(), parent);
+            return;
+        };
+        let mut visitor = EventVisitor::new(self.max_attr_len);
         attrs.values().record(&mut visitor);
-        let pool_id: u32 = self.counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1; // the atomic returns the previous value, so add one here too
         self.id_to_pool.write().unwrap().insert(id.clone(), pool_id);
+        self.lifecycle.created.fetch_add(1, Ordering::Relaxed);
         let (attr_names, attr_values) =
             visitor.attrs.into_iter().map(|x| (x.0.to_string(), x.1)).unzip();
         self.storage.new_span(pool_id, parent, attr_names, attr_values, attrs.metadata());
     }
     fn on_event(&self, event: &tracing::Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
-        let parent: u32;
+        if !self.target_filter.is_enabled(event.metadata().target()) {
+            return;
+        }
+        let Some(pool_id) = self.next_id() else { return };
+        let parent: PoolRef;
         if let Some(x) = event.parent() {
             parent = self.id_to_pool_index(x);
         } else if event.is_root() {
@@ -74,22 +258,92 @@ impl<S: Subscriber, S2: Storage + 'static> Layer<S> for TreeLayer<S2> {
             unreachable!()
         }
 
-        let mut visitor = EventVisitor::new();
+        let mut visitor = EventVisitor::new(self.max_attr_len);
         event.record(&mut visitor);
-        let pool_id = self.counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
 
         let (attr_names, attr_values) =
             visitor.attrs.into_iter().map(|x| (x.0.to_string(), x.1)).unzip();
         self.storage.new_event(pool_id, parent, attr_names, attr_values, event.metadata());
     }
+    /// `tracing` only reports links after both spans are created, so the storage has to attach
+    /// them to an entry which may already be written, see [crate::IETRecord].
+    /// Links to spans which are already closed, or were never seen by this layer, are dropped.
+    fn on_follows_from(
+        &self, span: &tracing::span::Id, follows: &tracing::span::Id,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let id_to_pool = self.id_to_pool.read().unwrap();
+        if let (Some(&id), Some(&follows)) = (id_to_pool.get(span), id_to_pool.get(follows)) {
+            drop(id_to_pool);
+            self.storage.follows_from(id, follows);
+        }
+    }
     fn on_close(&self, id: tracing::span::Id, _ctx: tracing_subscriber::layer::Context<'_, S>) {
-        self.id_to_pool.write().unwrap().remove(&id);
+        let counter = match self.id_to_pool.write().unwrap().remove(&id) {
+            Some(_) => &self.lifecycle.closed,
+            None if self.filtered.write().unwrap().remove(&id).is_some() => return,
+            // spans over the id limit were never added
+            None if self.lifecycle.over_id_limit.load(Ordering::Relaxed) > 0 => return,
+            None => &self.lifecycle.closed_without_creation,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+/// Which targets a [TreeLayer] keeps, see [TreeLayer::with_target_filter].
+///
+/// A target is kept if it matches no deny pattern, and it matches an allow pattern, or there are
+/// none. Patterns are matched against the whole target:
+/// - `h2` matches the target `h2` and its submodules, like `h2::proto`, but not `h2c`.
+/// - `*` matches any text, so `hyper*` also matches `hyper_util`, and `*::pool` matches every
+///   module named `pool`.
+#[derive(Clone, Debug, Default)]
+pub struct TargetFilter {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+impl TargetFilter {
+    /// Only keep the targets matching `pattern`, or another allowed one.
+    pub fn allow(mut self, pattern: impl Into<String>) -> Self {
+        self.allow.push(pattern.into());
+        self
+    }
+    /// Drop the targets matching `pattern`.
+    pub fn deny(mut self, pattern: impl Into<String>) -> Self {
+        self.deny.push(pattern.into());
+        self
+    }
+    pub fn is_enabled(&self, target: &str) -> bool {
+        let matches = |pattern: &String| target_matches(pattern, target);
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
     }
 }
+/// Whether `target` matches `pattern`, see [TargetFilter].
+pub fn target_matches(pattern: &str, target: &str) -> bool {
+    if !pattern.contains('*') {
+        return match target.strip_prefix(pattern) {
+            Some(rest) => rest.is_empty() || rest.starts_with("::"),
+            None => false,
+        };
+    }
+    let mut parts = pattern.split('*');
+    // there is at least one `*`, so at least two parts
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = target.strip_prefix(first) else { return false };
+    let mut parts = parts.collect::<Vec<_>>();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
 /// A value which can be saved into an entrace file.
 ///
 /// The canonical field order is:
-/// `String`, `Bytes`, `Bool`, `Float`, `U64`, `I64`, `U128`, `I128`
+/// `String`, `Bytes`, `Bool`, `Float`, `U64`, `I64`, `U128`, `I128`, `Debug`
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
 pub enum EnValue {
     String(String),
@@ -100,6 +354,11 @@ pub enum EnValue {
     I64(i64),
     U128(u128),
     I128(i128),
+    /// A value which `tracing` only gave as text, formatted with `Debug` (or `Display`), like
+    /// `?request` or `%error`. Messages are kept as [EnValue::String].
+    ///
+    /// This comes last, so files written before it existed decode the same.
+    Debug(String),
 }
 impl EnValue {
     pub fn as_ref(&'_ self) -> EnValueRef<'_> {
@@ -112,6 +371,7 @@ impl EnValue {
             EnValue::I64(q) => EnValueRef::I64(*q),
             EnValue::U128(q) => EnValueRef::U128(*q),
             EnValue::I128(q) => EnValueRef::I128(*q),
+            EnValue::Debug(q) => EnValueRef::Debug(q.as_str()),
         }
     }
 }
@@ -119,7 +379,7 @@ impl EnValue {
 /// Container for borrowed versions of [EnValue]'s data, where it makes sense.
 ///
 /// The canonical field order is:
-/// `String`, `Bytes`, `Bool`, `Float`, `U64`, `I64`, `U128`, `I128`
+/// `String`, `Bytes`, `Bool`, `Float`, `U64`, `I64`, `U128`, `I128`, `Debug`
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
 pub enum EnValueRef<'a> {
     String(&'a str),
@@ -130,6 +390,8 @@ pub enum EnValueRef<'a> {
     I64(i64),
     U128(u128),
     I128(i128),
+    /// See [EnValue::Debug].
+    Debug(&'a str),
 }
 impl<'a> EnValueRef<'a> {
     pub fn to_owned(&self) -> EnValue {
@@ -142,6 +404,7 @@ impl<'a> EnValueRef<'a> {
             EnValueRef::I64(q) => EnValue::I64(*q),
             EnValueRef::U128(q) => EnValue::U128(*q),
             EnValueRef::I128(q) => EnValue::I128(*q),
+            EnValueRef::Debug(q) => EnValue::Debug(q.to_string()),
         }
     }
     pub fn into_owned(self) -> EnValue {
@@ -154,6 +417,19 @@ impl<'a> EnValueRef<'a> {
             EnValueRef::I64(q) => EnValue::I64(q),
             EnValueRef::U128(q) => EnValue::U128(q),
             EnValueRef::I128(q) => EnValue::I128(q),
+            EnValueRef::Debug(q) => EnValue::Debug(q.to_string()),
+        }
+    }
+    /// The value as a number, with booleans as 0 or 1. `None` for strings and bytes.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            EnValueRef::Float(x) => Some(*x),
+            EnValueRef::U64(x) => Some(*x as f64),
+            EnValueRef::I64(x) => Some(*x as f64),
+            EnValueRef::U128(x) => Some(*x as f64),
+            EnValueRef::I128(x) => Some(*x as f64),
+            EnValueRef::Bool(x) => Some(*x as u8 as f64),
+            EnValueRef::String(_) | EnValueRef::Bytes(_) | EnValueRef::Debug(_) => None,
         }
     }
 }
@@ -169,6 +445,7 @@ impl std::fmt::Display for EnValue {
             EnValue::I64(q) => q.fmt(f),
             EnValue::U128(q) => q.fmt(f),
             EnValue::I128(q) => q.fmt(f),
+            EnValue::Debug(q) => q.fmt(f),
         }
     }
 }
@@ -184,33 +461,51 @@ impl<'a> std::fmt::Display for EnValueRef<'a> {
             EnValueRef::I64(q) => q.fmt(f),
             EnValueRef::U128(q) => q.fmt(f),
             EnValueRef::I128(q) => q.fmt(f),
+            EnValueRef::Debug(q) => q.fmt(f),
         }
     }
 }
 
+/// Appended to attributes cut by [TreeLayer::with_max_attr_len].
+pub const TRUNCATED_MARKER: &str = "…(truncated)";
+
 struct EventVisitor {
     pub attrs: Vec<(&'static str, EnValue)>,
+    pub max_attr_len: usize,
 }
 impl tracing::field::Visit for EventVisitor {
     fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
-        self.new_attr(field, EnValue::String(format!("{value:?}")))
+        // the message is given as `fmt::Arguments`, but it is text nonetheless
+        match field.name() {
+            "message" => self.new_string(field, format!("{value:?}")),
+            _ => self.new_debug(field, format!("{value:?}")),
+        }
     }
     fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
         self.new_attr(field, EnValue::Bool(value))
     }
     fn record_bytes(&mut self, field: &tracing::field::Field, value: &[u8]) {
-        self.new_attr(field, EnValue::Bytes(value.into()))
+        if value.len() <= self.max_attr_len {
+            return self.new_attr(field, EnValue::Bytes(value.into()));
+        }
+        let mut cut = value[..self.max_attr_len].to_vec();
+        cut.extend_from_slice(TRUNCATED_MARKER.as_bytes());
+        self.new_attr(field, EnValue::Bytes(cut))
     }
     fn record_error(
         &mut self, field: &tracing::field::Field, value: &(dyn std::error::Error + 'static),
     ) {
-        self.new_attr(field, EnValue::String(format!("{value:?}")))
+        self.new_debug(field, format!("{value:?}"))
     }
     fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
         self.new_attr(field, EnValue::Float(value))
     }
     fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
-        self.new_attr(field, EnValue::String(value.into()))
+        if value.len() <= self.max_attr_len {
+            return self.new_attr(field, EnValue::String(value.into()));
+        }
+        let cut = &value[..value.floor_char_boundary(self.max_attr_len)];
+        self.new_attr(field, EnValue::String(format!("{cut}{TRUNCATED_MARKER}")))
     }
     fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
         self.new_attr(field, EnValue::U64(value))
@@ -226,8 +521,25 @@ impl tracing::field::Visit for EventVisitor {
     }
 }
 impl EventVisitor {
-    pub fn new() -> Self {
-        Self { attrs: vec![] }
+    pub fn new(max_attr_len: usize) -> Self {
+        Self { attrs: vec![], max_attr_len }
+    }
+    /// Add a string attribute, cut to [EventVisitor::max_attr_len].
+    pub fn new_string(&mut self, field: &tracing::field::Field, value: String) {
+        let value = self.truncate(value);
+        self.new_attr(field, EnValue::String(value))
+    }
+    /// Like [EventVisitor::new_string], but for text formatted with `Debug`, see [EnValue::Debug].
+    pub fn new_debug(&mut self, field: &tracing::field::Field, value: String) {
+        let value = self.truncate(value);
+        self.new_attr(field, EnValue::Debug(value))
+    }
+    fn truncate(&self, mut value: String) -> String {
+        if value.len() > self.max_attr_len {
+            value.truncate(value.floor_char_boundary(self.max_attr_len));
+            value.push_str(TRUNCATED_MARKER);
+        }
+        value
     }
     pub fn new_attr(&mut self, field: &tracing::field::Field, value: EnValue) {
         self.attrs.push((field.name(), value));
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    AggregateOp, IETPresentationConfig, LogProvider, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info, info_span};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// A `server` span (id 1) with requests taking 10, 20, 30 and 40 ms, one request without a
+/// latency, one with a string latency and one with a boolean, and an empty `idle` span.
+fn requests() -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    {
+        let subscriber = Registry::default().with(TreeLayer::from_storage(storage.clone()));
+        let _guard = subscriber.set_default();
+        let span = info_span!("server").entered();
+        for latency in [10u64, 20, 30] {
+            info!(latency, "request");
+        }
+        info!(latency = 40.0, "request");
+        info!("request");
+        info!(latency = "slow", "request");
+        info!(failed = true, "request");
+        drop(span);
+        info_span!("idle").in_scope(|| ());
+    }
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), IETPresentationConfig::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+#[test]
+fn test_aggregate_children() {
+    let trace = requests();
+    let agg = |parent, attr, op| trace.aggregate_children(parent, attr, op).unwrap();
+    assert_eq!(agg(1, "latency", AggregateOp::Sum), Some(100.0));
+    assert_eq!(agg(1, "latency", AggregateOp::Avg), Some(25.0));
+    assert_eq!(agg(1, "latency", AggregateOp::Min), Some(10.0));
+    assert_eq!(agg(1, "latency", AggregateOp::Max), Some(40.0));
+    // the missing and the string value are skipped
+    assert_eq!(agg(1, "latency", AggregateOp::Count), Some(4.0));
+    // booleans count as 0 or 1
+    assert_eq!(agg(1, "failed", AggregateOp::Sum), Some(1.0));
+}
+
+#[test]
+fn test_aggregate_without_values() {
+    let trace = requests();
+    let idle = 9;
+    assert_eq!(trace.meta(idle).unwrap().name, "idle");
+    for parent in [idle, 1] {
+        let agg = |op| trace.aggregate_children(parent, "missing", op).unwrap();
+        assert_eq!(agg(AggregateOp::Sum), Some(0.0));
+        assert_eq!(agg(AggregateOp::Count), Some(0.0));
+        assert_eq!(agg(AggregateOp::Avg), None);
+        assert_eq!(agg(AggregateOp::Min), None);
+        assert_eq!(agg(AggregateOp::Max), None);
+    }
+}
+
+#[test]
+fn test_parse_op() {
+    for op in ["sum", "avg", "min", "max", "count"] {
+        assert_eq!(op.parse::<AggregateOp>().unwrap().to_string(), op);
+    }
+    assert!("median".parse::<AggregateOp>().is_err());
+}
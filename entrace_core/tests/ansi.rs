@@ -0,0 +1,27 @@
+use std::borrow::Cow;
+
+use entrace_core::strip_ansi_escapes;
+
+#[test]
+fn test_strip_sgr() {
+    let colored = "\x1b[1m\x1b[31mERROR\x1b[0m: disk \x1b[38;5;208mfull\x1b[39m";
+    assert_eq!(strip_ansi_escapes(colored), "ERROR: disk full");
+    // the output of entrace's own formatter
+    assert_eq!(strip_ansi_escapes("\x1b[1m\x1b[34m[I]\x1b[22m app:\x1b[0m hi"), "[I] app: hi");
+}
+
+#[test]
+fn test_strip_other_sequences() {
+    // cursor movement, a hyperlink (OSC 8) with both terminators, and a two-character sequence
+    let text = "a\x1b[2Kb\x1b]8;;https://x.org\x07link\x1b]8;;\x1b\\c\x1b(Bd";
+    assert_eq!(strip_ansi_escapes(text), "ablinkcd");
+    // cut off sequences, and text after a bare escape
+    assert_eq!(strip_ansi_escapes("ok\x1b[38;5"), "ok");
+    assert_eq!(strip_ansi_escapes("ok\x1b"), "ok");
+    assert_eq!(strip_ansi_escapes("ünï\x1b[0mcødé"), "ünïcødé");
+}
+
+#[test]
+fn test_plain_text_is_borrowed() {
+    assert!(matches!(strip_ansi_escapes("no [escapes] here"), Cow::Borrowed("no [escapes] here")));
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    IETPresentationConfig, LogProvider, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info, info_span};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// 100 requests, with a unique `id` and one of 3 `status`es.
+fn requests() -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    {
+        let subscriber = Registry::default().with(TreeLayer::from_storage(storage.clone()));
+        let _guard = subscriber.set_default();
+        let _span = info_span!("server", status = "starting").entered();
+        for id in 0..100u64 {
+            let status = [200, 404, 500][id as usize % 3];
+            info!(id, status, "request");
+        }
+    }
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), IETPresentationConfig::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+#[test]
+fn test_attr_cardinality() {
+    let trace = requests();
+    assert_eq!(trace.attr_cardinality("id").unwrap(), 100);
+    // the string on the span is distinct from the numbers on the events
+    assert_eq!(trace.attr_cardinality("status").unwrap(), 4);
+    assert_eq!(trace.attr_cardinality("message").unwrap(), 1);
+    assert_eq!(trace.attr_cardinality("missing").unwrap(), 0);
+}
+
+#[test]
+fn test_attr_cardinalities() {
+    let trace = requests();
+    let all = trace.attr_cardinalities().unwrap();
+    let expected = [("id", 100), ("message", 1), ("status", 4)];
+    assert_eq!(all.iter().map(|(k, v)| (k.as_str(), *v)).collect::<Vec<_>>(), expected);
+    for (name, count) in all {
+        assert_eq!(trace.attr_cardinality(&name).unwrap(), count);
+    }
+}
@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    IETPresentationConfig, LogProvider, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info, info_span, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+#[test]
+fn test_distinct_attr_names() {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let tree_layer = TreeLayer::from_storage(storage.clone());
+    Registry::default().with(LevelFilter::TRACE).with(tree_layer).init();
+    let span = info_span!("request", user = "alice", id = 1).entered();
+    info!(id = 2, status = 200, "done");
+    info!(status = 404, "not found");
+    drop(span);
+    let iet = storage.finish().unwrap();
+
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), IETPresentationConfig::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+
+    let names = provider.distinct_attr_names().unwrap();
+    assert_eq!(names, ["id", "message", "status", "user"]);
+}
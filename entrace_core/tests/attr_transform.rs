@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    AttrEncoding, EnValue, EnValueRef, IETLoadConfig, LoadConfig, LogProvider, TreeLayer,
+    remote::{IETStorage, IETStorageConfig, load_iet_trace_transformed},
+};
+use tracing::{info, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn get_iet() -> Vec<u8> {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    info!(latency = "12", route = "/", "request");
+    info!(latency = "slow", "request");
+    drop(guard);
+    storage.finish().unwrap()
+}
+
+/// Parse `latency` into a number, if it is one.
+fn parse_latency(name: &str, value: &mut EnValue) {
+    if name == "latency"
+        && let EnValue::String(x) = value
+        && let Ok(parsed) = x.parse()
+    {
+        *value = EnValue::U64(parsed);
+    }
+}
+
+#[test]
+fn test_transform_string_to_number() {
+    let iet = get_iet();
+    let initial =
+        load_iet_trace_transformed(&iet[10..], false, AttrEncoding::Standard, Some(&parse_latency))
+            .unwrap();
+    let entry = initial.data[1].as_ref();
+    assert!(matches!(entry.get_attr("latency"), Some(EnValueRef::U64(12))));
+    assert!(matches!(entry.get_attr("route"), Some(EnValueRef::String("/"))));
+    let entry = initial.data[2].as_ref();
+    assert!(matches!(entry.get_attr("latency"), Some(EnValueRef::String("slow"))));
+}
+
+#[test]
+fn test_transform_in_load_config() {
+    let path = std::env::temp_dir().join(format!("entrace-transform-{}.iet", std::process::id()));
+    std::fs::write(&path, get_iet()).unwrap();
+    let config = LoadConfig {
+        iht: IETLoadConfig { attr_transform: Some(Arc::new(parse_latency)), ..Default::default() },
+    };
+    // SAFETY: this is an IET file, nothing is memory mapped.
+    let provider = unsafe { entrace_core::load_trace(&path, config) };
+    std::fs::remove_file(&path).ok();
+    let provider = provider.unwrap();
+    assert!(matches!(provider.attr_value(1, "latency").unwrap(), Some(EnValueRef::U64(12))));
+}
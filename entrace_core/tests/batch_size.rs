@@ -0,0 +1,23 @@
+use entrace_core::remote::AdaptiveBatchSize;
+
+#[test]
+fn test_batch_size_adapts_to_backlog() {
+    let mut batch = AdaptiveBatchSize::new();
+    assert_eq!(batch.get(), AdaptiveBatchSize::MIN);
+
+    // main thread is behind: batches grow, up to the maximum
+    batch.update(10);
+    assert_eq!(batch.get(), AdaptiveBatchSize::MIN * 2);
+    for _ in 0..64 {
+        batch.update(10);
+    }
+    assert_eq!(batch.get(), AdaptiveBatchSize::MAX);
+
+    // main thread caught up: batches shrink, down to the minimum
+    batch.update(0);
+    assert_eq!(batch.get(), AdaptiveBatchSize::MAX / 2);
+    for _ in 0..64 {
+        batch.update(1);
+    }
+    assert_eq!(batch.get(), AdaptiveBatchSize::MIN);
+}
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    LogProvider, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info, info_span, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+const OUTER: u32 = 1;
+const FIRST_EVENT: u32 = 2;
+const INNER: u32 = 3;
+const INNER_EVENT: u32 = 4;
+const EMPTY: u32 = 5;
+const LAST_EVENT: u32 = 6;
+
+fn get_trace() -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    {
+        let _outer = info_span!("outer").entered();
+        info!("first");
+        info_span!("inner").in_scope(|| info!("inner event"));
+        drop(info_span!("empty").entered());
+        info!("last");
+    }
+    drop(guard);
+    let iet = storage.finish().unwrap();
+
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+#[test]
+fn test_child_events_and_spans() {
+    let trace = get_trace();
+    assert_eq!(trace.children(OUTER).unwrap(), [FIRST_EVENT, INNER, EMPTY, LAST_EVENT]);
+    assert_eq!(trace.child_spans(0).unwrap(), [OUTER]);
+    assert_eq!(trace.child_events(0).unwrap(), Vec::<u32>::new());
+    assert_eq!(trace.child_events(INNER).unwrap(), [INNER_EVENT]);
+    // without a kind flag in the file, an empty span is indistinguishable from an event
+    assert_eq!(trace.child_spans(OUTER).unwrap(), [INNER]);
+    assert_eq!(trace.child_events(OUTER).unwrap(), [FIRST_EVENT, EMPTY, LAST_EVENT]);
+    assert!(!trace.is_event(0).unwrap());
+}
@@ -0,0 +1,72 @@
+use std::{
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use entrace_core::{
+    IETLoadConfig, IETPresentationConfig, LoadConfig, LogProvider, LogProviderImpl, TreeLayer,
+    remote::{FileWatchConfig, IETEvent, IETInfo, IETStorage, IETStorageConfig, RemoteLogProvider},
+};
+use tracing::info;
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Whether the worker of a closed provider exits in time.
+fn stops(handle: JoinHandle<()>) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if handle.is_finished() {
+            return handle.join().is_ok();
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    false
+}
+
+fn remote() -> (RemoteLogProvider, std::net::SocketAddr, crossbeam_channel::Receiver<IETEvent>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (event_tx, event_rx) = crossbeam_channel::unbounded();
+    let config = IETPresentationConfig { event_tx: Some(event_tx), ..Default::default() };
+    (RemoteLogProvider::new(listener, config), addr, event_rx)
+}
+
+#[test]
+fn test_close_remote_before_connection() {
+    let (provider, _, _) = remote();
+    assert!(stops(provider.close()));
+}
+
+#[test]
+fn test_close_remote_while_connected() {
+    let (provider, addr, events) = remote();
+    let _client = TcpStream::connect(addr).unwrap();
+    let connected = events.iter().any(|x| matches!(x, IETEvent::Info(IETInfo::ReceivedConnection)));
+    assert!(connected);
+    // the client is still connected, but doesn't send anything
+    assert!(stops(LogProviderImpl::Remote(provider).close().unwrap()));
+}
+
+#[test]
+fn test_close_watched_file() {
+    let config = IETStorageConfig::non_length_prefixed(vec![]);
+    let storage = Arc::new(IETStorage::init(config));
+    {
+        let subscriber = Registry::default().with(TreeLayer::from_storage(storage.clone()));
+        let _guard = subscriber.set_default();
+        info!("before close");
+    }
+    let iet = storage.finish().unwrap();
+    let path = std::env::temp_dir().join(format!("entrace-close-{}.iet", std::process::id()));
+    std::fs::write(&path, iet).unwrap();
+
+    let iht = IETLoadConfig { watch: FileWatchConfig::Watch(path.clone()), ..Default::default() };
+    // SAFETY: nothing else touches this file while it is loaded.
+    let provider = unsafe { entrace_core::load_trace(&path, LoadConfig { iht }) }.unwrap();
+    assert!(matches!(provider, LogProviderImpl::FileIET(_)));
+    assert_eq!(provider.len(), 2);
+    assert!(provider.is_live());
+    assert!(stops(provider.close().unwrap()));
+    std::fs::remove_file(&path).ok();
+}
@@ -0,0 +1,18 @@
+//! Setup shared by the core tests.
+
+use std::sync::Arc;
+
+use entrace_core::{
+    TreeLayer,
+    remote::{IETStorage, IETStorageConfig},
+};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Traces what `record` emits with a [TreeLayer] into an [IETStorage], and returns the bytes it wrote.
+pub fn recorded_iet(config: IETStorageConfig<Vec<u8>>, record: impl FnOnce()) -> Vec<u8> {
+    let storage = Arc::new(IETStorage::init(config));
+    let guard = Registry::default().with(TreeLayer::from_storage(storage.clone())).set_default();
+    record();
+    drop(guard);
+    storage.finish().unwrap()
+}
@@ -0,0 +1,132 @@
+use std::{io::Cursor, sync::Arc};
+
+use entrace_core::{
+    AttrEncoding, LoadConfig, LogProvider, MetadataContainer, StorageFormat, TraceEntry, TreeLayer,
+    convert, entrace_magic_with,
+    mmap::ETStorage,
+    parse_entrace_magic,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace_with_encoding},
+    storage::Storage,
+};
+use tracing::{info, info_span, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn emit<S: Storage + Send + Sync + 'static>(storage: Arc<S>) {
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage));
+    let _guard = subscriber.set_default();
+    let _span = info_span!("outer", small = 3u64, big = u64::MAX, neg = -5i64).entered();
+    for i in 0..300u64 {
+        info!(msg_idx = i, "Message {i}");
+    }
+    info!(ratio = 0.5, ok = true, bad = false, name = "not the message", "mixed");
+    info!(wide = u128::MAX, "wide");
+}
+
+fn iet_with(attr_encoding: AttrEncoding) -> Vec<u8> {
+    let config = IETStorageConfig::non_length_prefixed(vec![]).with_attr_encoding(attr_encoding);
+    let storage = Arc::new(IETStorage::init(config));
+    emit(storage.clone());
+    storage.finish().unwrap()
+}
+
+fn provider_of(iet: &[u8], attr_encoding: AttrEncoding) -> BaseIETLogProvider {
+    let initial = load_iet_trace_with_encoding(&iet[10..], false, attr_encoding).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+fn encoding_of(file: &[u8]) -> AttrEncoding {
+    parse_entrace_magic(file.first_chunk().unwrap()).unwrap().2
+}
+
+fn assert_same_spans(a: &dyn LogProvider, b: &dyn LogProvider) {
+    assert_eq!(a.len(), b.len());
+    for id in 0..a.len() as u32 {
+        assert_eq!(a.message(id).unwrap(), b.message(id).unwrap());
+        assert_eq!(a.attr_names(id).unwrap(), b.attr_names(id).unwrap());
+        let values_a = format!("{:?}", a.attr_values(id).unwrap());
+        let values_b = format!("{:?}", b.attr_values(id).unwrap());
+        assert_eq!(values_a, values_b, "attribute values of span {id} differ");
+        for name in a.attr_names(id).unwrap() {
+            let value_a = format!("{:?}", a.attr_value(id, name).unwrap());
+            let value_b = format!("{:?}", b.attr_value(id, name).unwrap());
+            assert_eq!(value_a, value_b);
+        }
+    }
+}
+
+#[test]
+fn magic_round_trip() {
+    for encoding in [AttrEncoding::Standard, AttrEncoding::Compact] {
+        for format in [StorageFormat::ET, StorageFormat::IET, StorageFormat::IETPrefix] {
+            let magic = entrace_magic_with(2, format, encoding);
+            let (version, parsed_format, parsed_encoding) = parse_entrace_magic(&magic).unwrap();
+            assert_eq!(version, 2);
+            assert_eq!(parsed_format, format);
+            assert_eq!(parsed_encoding, encoding);
+        }
+    }
+}
+
+#[test]
+fn compact_iet_round_trip() {
+    let standard = iet_with(AttrEncoding::Standard);
+    let compact = iet_with(AttrEncoding::Compact);
+    assert!(
+        compact.len() < standard.len(),
+        "compact ({}) should be smaller than standard ({})",
+        compact.len(),
+        standard.len()
+    );
+    assert_eq!(encoding_of(&compact), AttrEncoding::Compact);
+
+    let standard = provider_of(&standard, AttrEncoding::Standard);
+    let compact = provider_of(&compact, AttrEncoding::Compact);
+    assert_eq!(standard.len(), 304);
+    assert_same_spans(&standard, &compact);
+}
+
+#[test]
+fn compact_et_round_trip() {
+    let storage =
+        Arc::new(ETStorage::init_with_attr_encoding(Cursor::new(vec![]), AttrEncoding::Compact));
+    emit(storage.clone());
+    let et = storage.finish(Cursor::new(vec![])).unwrap().temp_buf.unwrap().into_inner();
+
+    // converting to IET keeps the encoding
+    let mut iet = vec![];
+    entrace_core::convert::et_to_iet(&mut Cursor::new(&et), &mut iet, true).unwrap();
+    assert_eq!(encoding_of(&iet), AttrEncoding::Compact);
+    let expected = provider_of(&iet_with(AttrEncoding::Standard), AttrEncoding::Standard);
+    assert_same_spans(&expected, &provider_of(&iet, AttrEncoding::Compact));
+
+    let path = std::env::temp_dir().join(format!("entrace-compact-{}.et", std::process::id()));
+    std::fs::write(&path, &et).unwrap();
+    // SAFETY: nothing else touches this file while it is mapped.
+    let provider = unsafe { entrace_core::load_trace(&path, LoadConfig::default()) };
+    let provider = provider.unwrap();
+    assert_same_spans(&expected, &provider);
+    drop(provider);
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn compact_v3_to_v4_keeps_integers() {
+    let cfg = entrace_core::BINCODE_CONFIG;
+    let mut v3 = entrace_magic_with(3, StorageFormat::IET, AttrEncoding::Compact).to_vec();
+    AttrEncoding::Compact.encode_entry(&TraceEntry::root(), &mut v3).unwrap();
+    // before version 4, tag 255 was the integer 245, it is a `Debug` value now
+    let names = vec!["n".to_string()];
+    let entry =
+        (0u32, None::<String>, MetadataContainer::root(), names, vec![255u8], Vec::<u32>::new());
+    bincode::serde::encode_into_std_write(entry, &mut v3, cfg).unwrap();
+
+    let mut v4 = vec![];
+    convert::v3_to_v4(&mut Cursor::new(&v3), &mut v4, false).unwrap();
+    assert_eq!(parse_entrace_magic(v4.first_chunk().unwrap()).unwrap().0, 4);
+    let provider = provider_of(&v4, AttrEncoding::Compact);
+    assert_eq!(format!("{:?}", provider.attr_value(1, "n").unwrap()), "Some(U64(245))");
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    AttrEncoding, EnValue, EnValueRef, LogProvider, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace_with_encoding},
+};
+use tracing::{info, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+#[allow(dead_code)]
+#[derive(Debug)]
+struct Foo {
+    x: u32,
+}
+
+fn record(attr_encoding: AttrEncoding) -> BaseIETLogProvider {
+    let config = IETStorageConfig::non_length_prefixed(vec![]).with_attr_encoding(attr_encoding);
+    let storage = Arc::new(IETStorage::init(config));
+    {
+        let layer = TreeLayer::from_storage(storage.clone());
+        let subscriber = Registry::default().with(LevelFilter::TRACE).with(layer);
+        let _guard = subscriber.set_default();
+        let err = std::fmt::Error;
+        info!(name = "Foo { x: 1 }", value = ?Foo { x: 1 }, shown = %Foo { x: 1 }.x, err = %err, "msg");
+    }
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace_with_encoding(&iet[10..], false, attr_encoding).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+fn attr(provider: &dyn LogProvider, name: &str) -> EnValue {
+    provider.attr_value(1, name).unwrap().unwrap().to_owned()
+}
+
+fn assert_attr(provider: &dyn LogProvider, name: &str, expected: EnValue) {
+    assert_eq!(format!("{:?}", attr(provider, name)), format!("{expected:?}"), "{name}");
+}
+
+#[test]
+fn test_debug_values_are_kept_apart() {
+    let provider = record(AttrEncoding::Standard);
+    assert_attr(&provider, "name", EnValue::String("Foo { x: 1 }".into()));
+    assert_attr(&provider, "value", EnValue::Debug("Foo { x: 1 }".into()));
+    assert_attr(&provider, "shown", EnValue::Debug("1".into()));
+    assert!(matches!(attr(&provider, "err"), EnValue::Debug(_)));
+    // the message is text, not a formatted value
+    assert_attr(&provider, "message", EnValue::String("msg".into()));
+    assert_eq!(provider.message(1).unwrap(), Some("msg"));
+    // both look the same when shown
+    let value = provider.attr_value(1, "value").unwrap().unwrap();
+    assert!(matches!(value, EnValueRef::Debug(_)));
+    assert_eq!(value.to_string(), "Foo { x: 1 }");
+}
+
+#[test]
+fn test_compact_keeps_debug_values() {
+    let standard = record(AttrEncoding::Standard);
+    let compact = record(AttrEncoding::Compact);
+    for name in ["name", "value", "shown", "err", "message"] {
+        assert_attr(&compact, name, attr(&standard, name));
+    }
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::{
+    io::{Cursor, Seek, SeekFrom, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use entrace_core::{
+    IETPresentationConfig, LogProviderError, encode_length_prefix,
+    remote::{DummyRefresher, IETEvent, IETStorageConfig, RemoteLogProvider},
+};
+use tracing::info;
+
+/// Not a valid entry, and not the start of a longer one either.
+const CORRUPT: [u8; 16] = [0xff; 16];
+
+fn recorded_iet(config: IETStorageConfig<Vec<u8>>) -> Vec<u8> {
+    common::recorded_iet(config, || {
+        for i in 0..10 {
+            info!(i, "event");
+        }
+    })
+}
+
+#[test]
+fn test_stream_decode_offset() {
+    let mut sent = recorded_iet(IETStorageConfig::length_prefixed(vec![]));
+    let corrupt_at = sent.len() as u64;
+    sent.extend_from_slice(&encode_length_prefix(CORRUPT.len() as u64));
+    sent.extend_from_slice(&CORRUPT);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (event_tx, event_rx) = crossbeam_channel::unbounded();
+    let config = IETPresentationConfig::new(Some(event_tx), DummyRefresher {});
+    let _provider = RemoteLogProvider::new(listener, config);
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&sent).unwrap();
+    drop(stream);
+
+    loop {
+        match event_rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            IETEvent::Error(LogProviderError::DecodeAt { offset, .. }) => {
+                assert_eq!(offset, corrupt_at);
+                break;
+            }
+            IETEvent::Error(e) => panic!("unexpected error {e}"),
+            IETEvent::Info(_) => (),
+        }
+    }
+}
+
+#[cfg(feature = "notify-watch")]
+#[test]
+fn test_file_decode_offset() {
+    use entrace_core::{
+        AttrEncoding,
+        remote::{FileIETError, IETNotifyWorker, MainThreadMessage},
+    };
+
+    let mut bytes = recorded_iet(IETStorageConfig::non_length_prefixed(vec![]));
+    let corrupt_at = bytes.len() as u64;
+    bytes.extend_from_slice(&CORRUPT);
+    let mut reader = Cursor::new(bytes);
+    // past the magic, like after the initial load
+    reader.seek(SeekFrom::Start(10)).unwrap();
+
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let mut worker = IETNotifyWorker::new(
+        tx,
+        &mut reader,
+        "unused.iet".into(),
+        IETPresentationConfig::default(),
+        false,
+        AttrEncoding::Standard,
+        None,
+    );
+    // a corrupt entry may be a write in progress, so it is retried on the next modifications
+    let mut modifications = 1;
+    let err = loop {
+        match worker.on_modify() {
+            Ok(()) => modifications += 1,
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(modifications, 9);
+    let LogProviderError::FileIETError(FileIETError::NoMoreRetries { offset, .. }) = err else {
+        panic!("unexpected error {err}");
+    };
+    assert_eq!(offset, corrupt_at);
+    // the good entries before it, and the root, are still read once
+    drop(worker);
+    let entries: usize = rx
+        .try_iter()
+        .map(|msg| match msg {
+            MainThreadMessage::Insert(_) => 1,
+            MainThreadMessage::InsertMany(entries) => entries.len(),
+            _ => 0,
+        })
+        .sum();
+    assert_eq!(entries, 11);
+}
@@ -0,0 +1,60 @@
+use std::{io::Cursor, sync::Arc};
+
+use entrace_core::{
+    AttrEncoding, EnValueRef, LoadConfig, LogProvider, TreeLayer,
+    mmap::ETStorage,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+    storage::Storage,
+};
+use tracing::{info, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn emit<S: Storage + Send + Sync + 'static>(storage: Arc<S>) {
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage));
+    let _guard = subscriber.set_default();
+    info!(x = 3u64, a = 0u64, x = 1u64, z = 0u64, x = 2u64, "dup");
+}
+
+fn assert_first_wins(provider: &dyn LogProvider) {
+    let names = provider.attr_names(1).unwrap();
+    assert_eq!(names, ["a", "message", "x", "x", "x", "z"]);
+    let xs: Vec<_> = names
+        .iter()
+        .zip(provider.attr_values(1).unwrap())
+        .filter(|(k, _)| **k == "x")
+        .map(|(_, v)| v)
+        .collect();
+    assert!(matches!(xs[..], [EnValueRef::U64(3), EnValueRef::U64(1), EnValueRef::U64(2)]));
+    assert!(matches!(provider.attr_value(1, "x").unwrap(), Some(EnValueRef::U64(3))));
+}
+
+#[test]
+fn test_duplicate_attrs_iet() {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    emit(storage.clone());
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    assert_first_wins(&provider);
+}
+
+#[test]
+fn test_duplicate_attrs_et() {
+    for encoding in [AttrEncoding::Standard, AttrEncoding::Compact] {
+        let storage = Arc::new(ETStorage::init_with_attr_encoding(Cursor::new(vec![]), encoding));
+        emit(storage.clone());
+        let et = storage.finish(Cursor::new(vec![])).unwrap().temp_buf.unwrap().into_inner();
+
+        let path = std::env::temp_dir()
+            .join(format!("entrace-duplicate-{encoding:?}-{}.et", std::process::id()));
+        std::fs::write(&path, &et).unwrap();
+        // SAFETY: nothing else touches this file while it is mapped.
+        let provider = unsafe { entrace_core::load_trace(&path, LoadConfig::default()) }.unwrap();
+        assert_first_wins(&provider);
+        drop(provider);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    io::{Cursor, ErrorKind},
+    sync::Arc,
+};
+
+use entrace_core::{
+    LengthPrefixError, MAX_PREFIXED_LEN, TreeLayer,
+    convert::{ConvertError, gather_iet_table_data},
+    decode_length_prefix, encode_length_prefix, read_frame_into,
+    remote::{IETStorage, IETStorageConfig, LoadIETError, load_iet_trace},
+};
+use tracing::{info, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn prefixed_iet() -> Vec<u8> {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::length_prefixed(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    info!(x = 1, "first");
+    info!(x = 2, "second");
+    drop(guard);
+    storage.finish().unwrap()
+}
+
+#[test]
+fn test_length_prefix_is_little_endian() {
+    assert_eq!(encode_length_prefix(0x0102), [2, 1, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(decode_length_prefix([2, 1, 0, 0, 0, 0, 0, 0]), Ok(0x0102));
+    assert_eq!(decode_length_prefix(encode_length_prefix(MAX_PREFIXED_LEN)), Ok(MAX_PREFIXED_LEN));
+    let swapped = 0x0102u64.to_be_bytes();
+    assert_eq!(decode_length_prefix(swapped), Err(LengthPrefixError::ByteSwapped(0x0201 << 48)));
+    // neither way around is plausible
+    let garbage = u64::MAX.to_le_bytes();
+    assert_eq!(decode_length_prefix(garbage), Err(LengthPrefixError::TooLarge(u64::MAX)));
+}
+
+#[test]
+fn test_byte_swapped_prefix_rejected() {
+    let mut iet = prefixed_iet();
+    let initial = load_iet_trace(&iet[10..], true).unwrap();
+    assert_eq!(initial.data.len(), 3);
+
+    // swap the prefix of the first message (the root), as a big-endian writer would write it
+    let prefix: [u8; 8] = iet[10..18].try_into().unwrap();
+    let len = u64::from_le_bytes(prefix);
+    iet[10..18].copy_from_slice(&len.to_be_bytes());
+
+    let loaded = load_iet_trace(&iet[10..], true);
+    assert!(
+        matches!(loaded, Err(LoadIETError::LengthPrefix(LengthPrefixError::ByteSwapped(_)))),
+        "{:?}",
+        loaded.err()
+    );
+    let gathered = gather_iet_table_data(&mut Cursor::new(&iet), true, true);
+    assert!(matches!(gathered, Err(ConvertError::LengthPrefix(LengthPrefixError::ByteSwapped(_)))));
+}
+
+#[test]
+fn test_truncated_large_frame() {
+    // a prefix of the largest allowed length, cut off after a few bytes of the frame
+    let mut iet = prefixed_iet();
+    iet.extend_from_slice(&encode_length_prefix(MAX_PREFIXED_LEN));
+    iet.extend_from_slice(&[1, 2, 3]);
+
+    let mut frame = vec![];
+    let err = read_frame_into(&mut &iet[iet.len() - 3..], MAX_PREFIXED_LEN, &mut frame);
+    assert_eq!(err.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(frame, [1, 2, 3]);
+    assert!(frame.capacity() < 1 << 20, "{}", frame.capacity());
+
+    // the loaders stop at the cut-off frame, like at the end of the trace
+    assert_eq!(load_iet_trace(&iet[10..], true).unwrap().data.len(), 3);
+    assert!(gather_iet_table_data(&mut Cursor::new(&iet), true, true).is_ok());
+}
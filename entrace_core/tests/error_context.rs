@@ -0,0 +1,25 @@
+use entrace_core::{display_error_context, error_chain};
+
+#[derive(thiserror::Error, Debug)]
+enum TestError {
+    #[error("outer")]
+    Outer(#[source] Box<TestError>),
+    #[error("middle")]
+    Middle(#[source] std::io::Error),
+}
+
+#[test]
+fn test_one_cause_per_line() {
+    let inner = std::io::Error::other("inner");
+    let err = TestError::Outer(Box::new(TestError::Middle(inner)));
+    assert_eq!(display_error_context(&err), "outer\n\nCaused by:\n  0: middle\n  1: inner");
+    assert_eq!(display_error_context(&std::io::Error::other("alone")), "alone");
+}
+
+#[test]
+fn test_error_chain() {
+    let inner = std::io::Error::other("inner");
+    let err = TestError::Outer(Box::new(TestError::Middle(inner)));
+    assert_eq!(error_chain(&err), ["outer", "middle", "inner"]);
+    assert_eq!(error_chain(&std::io::Error::other("alone")), ["alone"]);
+}
@@ -0,0 +1,286 @@
+use std::{
+    io::Cursor,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use entrace_core::{
+    AttrEncoding, FOLLOWS_FROM_MARKER, LoadConfig, LogProvider, LogProviderImpl, StorageFormat,
+    TreeLayer,
+    convert::{self, gather_iet_table_data},
+    entrace_magic_with,
+    mmap::ETStorage,
+    parse_entrace_magic,
+    remote::{
+        BaseIETLogProvider, IETStorage, IETStorageConfig, PreviewStorage, load_iet_subtree,
+        load_iet_trace_with_encoding,
+    },
+    storage::Storage,
+};
+use tracing::{info, info_span, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Spans 1-3 are `producer`, `consumer` and `closed`, and the consumer follows from the producer.
+/// Its link to `closed` is dropped, as that span is already closed when the link is made.
+fn emit<S: Storage + Send + Sync + 'static>(storage: Arc<S>) {
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage));
+    let _guard = subscriber.set_default();
+    let producer = info_span!("producer");
+    let consumer = info_span!("consumer");
+    let closed = info_span!("closed");
+    let closed_id = closed.id();
+    drop(closed);
+    consumer.follows_from(&producer);
+    consumer.follows_from(closed_id);
+    consumer.in_scope(|| info!("consumed"));
+}
+
+/// Like [emit], but the consumer is linked long after its entry was written, so the link is
+/// written as its own record.
+fn emit_late<S: Storage + Send + Sync + 'static>(storage: Arc<S>) {
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage));
+    let _guard = subscriber.set_default();
+    let producer = info_span!("producer");
+    let consumer = info_span!("consumer");
+    let closed = info_span!("closed");
+    let closed_id = closed.id();
+    drop(closed);
+    std::thread::sleep(Duration::from_millis(50));
+    consumer.follows_from(&producer);
+    consumer.follows_from(closed_id);
+    consumer.in_scope(|| info!("consumed"));
+}
+
+fn iet_with(attr_encoding: AttrEncoding) -> Vec<u8> {
+    let config = IETStorageConfig::non_length_prefixed(vec![]).with_attr_encoding(attr_encoding);
+    let storage = Arc::new(IETStorage::init(config));
+    emit(storage.clone());
+    storage.finish().unwrap()
+}
+
+fn provider_of(iet: &[u8]) -> BaseIETLogProvider {
+    let (_, format, encoding) = parse_entrace_magic(iet.first_chunk().unwrap()).unwrap();
+    let length_prefixed = format == StorageFormat::IETPrefix;
+    let initial = load_iet_trace_with_encoding(&iet[10..], length_prefixed, encoding).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+fn assert_links(log: &dyn LogProvider) {
+    assert_eq!(log.len(), 5);
+    assert_eq!(log.meta(2).unwrap().name, "consumer");
+    assert_eq!(log.follows_from(2).unwrap(), [1]);
+    for id in [0, 1, 3, 4] {
+        assert!(log.follows_from(id).unwrap().is_empty(), "span {id} should have no links");
+    }
+}
+
+#[test]
+fn test_iet_links() {
+    for encoding in [AttrEncoding::Standard, AttrEncoding::Compact] {
+        assert_links(&provider_of(&iet_with(encoding)));
+    }
+}
+
+#[test]
+fn test_late_iet_links() {
+    for encoding in [AttrEncoding::Standard, AttrEncoding::Compact] {
+        for length_prefixed in [false, true] {
+            let config = match length_prefixed {
+                false => IETStorageConfig::non_length_prefixed(vec![]),
+                true => IETStorageConfig::length_prefixed(vec![]),
+            };
+            let storage = Arc::new(IETStorage::init(config.with_attr_encoding(encoding)));
+            emit_late(storage.clone());
+            let iet = storage.finish().unwrap();
+            // the link comes after the entry of `consumer`, and before the event in it
+            let record = [FOLLOWS_FROM_MARKER, 2, 1];
+            assert!(iet.windows(3).any(|x| x == record), "{encoding:?} {length_prefixed}");
+            assert_links(&provider_of(&iet));
+
+            let table = gather_iet_table_data(&mut Cursor::new(&iet), true, length_prefixed);
+            let mut et = vec![];
+            convert::iet_to_et_with_table(
+                &table.unwrap().to_ref(),
+                &mut Cursor::new(&iet),
+                &mut et,
+                true,
+            )
+            .unwrap();
+            assert_links(&et_provider(&et));
+        }
+    }
+}
+
+#[test]
+fn test_late_subtree_links() {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    emit_late(storage.clone());
+    let iet = storage.finish().unwrap();
+    let subtree = load_iet_subtree(&iet[10..], 0, false, AttrEncoding::Standard).unwrap();
+    assert_eq!(subtree.initial.data[2].follows_from, [1]);
+    let subtree = load_iet_subtree(&iet[10..], 2, false, AttrEncoding::Standard).unwrap();
+    assert!(subtree.initial.data[1].follows_from.is_empty());
+}
+
+#[test]
+fn test_late_preview_links() {
+    let file_storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let (storage, mut preview) = PreviewStorage::new(file_storage.clone(), Default::default());
+    let storage = Arc::new(storage);
+    emit_late(storage.clone());
+    storage.finish();
+    while preview.is_live() {
+        preview.frame_callback();
+    }
+    assert_links(&preview);
+    assert_links(&provider_of(&file_storage.finish().unwrap()));
+}
+
+/// Load an ET file from `et`, through a temporary file, as ET files are memory mapped.
+fn et_provider(et: &[u8]) -> LogProviderImpl {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNT.fetch_add(1, Ordering::Relaxed);
+    let name = format!("entrace-follows-{}-{n}.et", std::process::id());
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, et).unwrap();
+    // SAFETY: nothing else touches this file while it is mapped.
+    let provider = unsafe { entrace_core::load_trace(&path, LoadConfig::default()) };
+    std::fs::remove_file(&path).ok();
+    provider.unwrap()
+}
+
+#[test]
+fn test_late_et_links() {
+    let storage =
+        Arc::new(ETStorage::init_with_attr_encoding(Cursor::new(vec![]), AttrEncoding::Compact));
+    emit_late(storage.clone());
+    let et = storage.finish(Cursor::new(vec![])).unwrap().temp_buf.unwrap().into_inner();
+    assert_links(&et_provider(&et));
+}
+
+#[test]
+fn test_et_links() {
+    let storage =
+        Arc::new(ETStorage::init_with_attr_encoding(Cursor::new(vec![]), AttrEncoding::Compact));
+    emit(storage.clone());
+    let et = storage.finish(Cursor::new(vec![])).unwrap().temp_buf.unwrap().into_inner();
+    assert_links(&et_provider(&et));
+}
+
+#[test]
+fn test_subtree_drops_outside_links() {
+    let iet = iet_with(AttrEncoding::Standard);
+    let subtree = load_iet_subtree(&iet[10..], 2, false, AttrEncoding::Standard).unwrap();
+    assert_eq!(subtree.original_ids, [0, 2, 4]);
+    assert!(subtree.initial.data[1].follows_from.is_empty());
+}
+
+#[test]
+fn test_v3_to_v4_keeps_entries() {
+    for encoding in [AttrEncoding::Standard, AttrEncoding::Compact] {
+        let config = IETStorageConfig::non_length_prefixed(vec![]).with_attr_encoding(encoding);
+        let storage = Arc::new(IETStorage::init(config));
+        emit_plain(storage.clone());
+        let iet = storage.finish().unwrap();
+        let mut v3 = iet.clone();
+        v3[8] = 3;
+        let mut converted = vec![];
+        convert::v3_to_v4(&mut Cursor::new(&v3), &mut converted, false).unwrap();
+        assert_eq!(converted, iet);
+    }
+    let iet = iet_with(AttrEncoding::Standard);
+    let wrong = convert::v3_to_v4(&mut Cursor::new(&iet), &mut vec![], false);
+    assert!(matches!(wrong, Err(convert::ConvertError::InputVersionMismatch(4, 3))));
+}
+
+/// Turn a version 4 file without links into the version 2 file it was converted from, by removing
+/// the empty link list (a single 0 byte) from the end of each entry.
+fn downgrade(entries: &[u8], offsets: &[u64]) -> (Vec<u8>, Vec<u64>) {
+    let mut out = vec![];
+    let mut new_offsets = vec![];
+    for (i, &start) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).map_or(entries.len(), |x| *x as usize);
+        let entry = &entries[start as usize..end];
+        assert_eq!(entry.last(), Some(&0));
+        new_offsets.push(out.len() as u64);
+        out.extend_from_slice(&entry[..entry.len() - 1]);
+    }
+    (out, new_offsets)
+}
+
+/// Like [emit], but without links.
+fn emit_plain(storage: Arc<impl Storage + Send + Sync + 'static>) {
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage));
+    let _guard = subscriber.set_default();
+    let _span = info_span!("outer", n = 3).entered();
+    for i in 0..20u64 {
+        info!(msg_idx = i, "Message {i}");
+    }
+}
+
+#[test]
+fn test_iet_v2_to_v3() {
+    for encoding in [AttrEncoding::Standard, AttrEncoding::Compact] {
+        let config = IETStorageConfig::non_length_prefixed(vec![]).with_attr_encoding(encoding);
+        let storage = Arc::new(IETStorage::init(config));
+        emit_plain(storage.clone());
+        let v4 = storage.finish().unwrap();
+
+        let table = gather_iet_table_data(&mut Cursor::new(&v4), true, false).unwrap();
+        let et = {
+            let mut et = vec![];
+            convert::iet_to_et_with_table(&table.to_ref(), &mut Cursor::new(&v4), &mut et, true)
+                .unwrap();
+            et
+        };
+        // the offset table is the first thing after the magic of an ET file
+        let (offsets, _): (Vec<u64>, _) =
+            bincode::serde::decode_from_slice(&et[10..], entrace_core::BINCODE_CONFIG).unwrap();
+        let (entries, _) = downgrade(&v4[10..], &offsets);
+        let mut v2 = entrace_magic_with(2, StorageFormat::IET, encoding).to_vec();
+        v2.extend(entries);
+
+        let mut v3 = vec![];
+        convert::iet_v2_to_v3(&mut Cursor::new(&v2), &mut v3, false).unwrap();
+        let mut converted = vec![];
+        convert::v3_to_v4(&mut Cursor::new(&v3), &mut converted, false).unwrap();
+        pretty_assertions::assert_eq!(converted, v4);
+    }
+}
+
+#[test]
+fn test_et_v2_to_v3() {
+    use bincode::serde::{decode_from_slice, encode_to_vec};
+    let cfg = entrace_core::BINCODE_CONFIG;
+    let storage = Arc::new(ETStorage::init(Cursor::new(vec![])));
+    emit_plain(storage.clone());
+    let v4 = storage.finish(Cursor::new(vec![])).unwrap().temp_buf.unwrap().into_inner();
+
+    let (offsets, offsets_len): (Vec<u64>, _) = decode_from_slice(&v4[10..], cfg).unwrap();
+    let pool_start = 10 + offsets_len;
+    let (pool, pool_len): (Vec<entrace_core::PoolEntry>, _) =
+        decode_from_slice(&v4[pool_start..], cfg).unwrap();
+    let (entries, v2_offsets) = downgrade(&v4[pool_start + pool_len..], &offsets);
+    let mut v2 = entrace_magic_with(2, StorageFormat::ET, AttrEncoding::Standard).to_vec();
+    v2.extend(encode_to_vec(v2_offsets, cfg).unwrap());
+    v2.extend(encode_to_vec(pool, cfg).unwrap());
+    v2.extend(entries);
+
+    let mut v3 = vec![];
+    convert::et_v2_to_v3(&mut Cursor::new(&v2), &mut v3, false).unwrap();
+    let mut converted = vec![];
+    convert::v3_to_v4(&mut Cursor::new(&v3), &mut converted, false).unwrap();
+    pretty_assertions::assert_eq!(converted, v4);
+
+    let wrong = convert::iet_v2_to_v3(&mut Cursor::new(&v2), &mut vec![], false);
+    assert!(matches!(wrong, Err(convert::ConvertError::InputFormatMismatch(..))));
+}
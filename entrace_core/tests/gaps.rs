@@ -0,0 +1,66 @@
+use entrace_core::{
+    GapKind, GapReport, LogProvider, MetadataContainer, PoolEntry, TraceEntry,
+    remote::BaseIETLogProvider,
+};
+
+/// Build a provider from `(parent, listed by the parent)` pairs, one for each span after the root.
+fn provider(spans: &[(u32, bool)]) -> BaseIETLogProvider {
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.data.push(TraceEntry::root());
+    provider.pool.push(PoolEntry::new());
+    for (i, &(parent, listed)) in spans.iter().enumerate() {
+        let id = i as u32 + 1;
+        let entry = TraceEntry::from_unsorted_attrs(
+            parent,
+            None,
+            MetadataContainer::default(),
+            vec![],
+            vec![],
+        );
+        provider.data.push(entry);
+        provider.pool.push(PoolEntry::new());
+        if listed {
+            provider.pool[parent as usize].children.push(id);
+        }
+    }
+    provider
+}
+
+#[test]
+fn test_healthy_trace() {
+    let provider = provider(&[(0, true), (1, true), (1, true), (0, true)]);
+    assert_eq!(provider.id_bounds(), Some((0, 4)));
+    assert!(provider.find_gaps().unwrap().is_empty());
+}
+
+#[test]
+fn test_orphaned_spans() {
+    let provider = provider(&[
+        (0, true),
+        // parents that were never received
+        (100, false),
+        (101, false),
+        (1, true),
+        // its parent doesn't know about it
+        (1, false),
+        // a span that is its own parent
+        (6, false),
+        (0, true),
+    ]);
+    let gaps = provider.find_gaps().unwrap();
+    assert_eq!(
+        gaps,
+        [
+            GapReport { ids: 2..=3, kind: GapKind::MissingParent },
+            GapReport { ids: 5..=5, kind: GapKind::Unreferenced },
+            GapReport { ids: 6..=6, kind: GapKind::MissingParent },
+        ]
+    );
+}
+
+#[test]
+fn test_empty_bounds() {
+    let provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    assert_eq!(provider.id_bounds(), None);
+    assert!(provider.find_gaps().unwrap().is_empty());
+}
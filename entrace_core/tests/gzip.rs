@@ -0,0 +1,50 @@
+#![cfg(feature = "gzip")]
+use std::{io::Write, sync::Arc};
+
+use entrace_core::{
+    LoadConfig, LogProvider, MagicParseError, StorageFormat, TreeLayer, entrace_magic_for,
+    parse_entrace_magic,
+    remote::{IETStorage, IETStorageConfig},
+};
+use flate2::{Compression, write::GzEncoder};
+use tracing::{info, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn get_hello_iet() -> Vec<u8> {
+    let buf = vec![];
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(buf)));
+    let tree_layer = TreeLayer::from_storage(storage.clone());
+    Registry::default().with(LevelFilter::TRACE).with(tree_layer).init();
+    info!("h");
+
+    storage.finish().unwrap()
+}
+
+#[test]
+fn test_load_gzipped_iet() {
+    let hello_iet = get_hello_iet();
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&hello_iet).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let path = std::env::temp_dir().join(format!("entrace-gzip-{}.iet.gz", std::process::id()));
+    std::fs::write(&path, compressed).unwrap();
+    // SAFETY: this is an IET file, nothing is memory mapped.
+    let provider = unsafe { entrace_core::load_trace(&path, LoadConfig::default()) };
+    std::fs::remove_file(&path).ok();
+    let provider = provider.unwrap();
+
+    assert_eq!(provider.len(), 2);
+    assert_eq!(provider.children(0).unwrap(), &[1]);
+    assert_eq!(provider.message(1).unwrap(), Some("h"));
+}
+
+#[test]
+fn test_gzipped_magic_is_reported() {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&entrace_magic_for(0, StorageFormat::IET)).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let magic = compressed.first_chunk::<10>().unwrap();
+    assert!(matches!(parse_entrace_magic(magic), Err(MagicParseError::Gzipped)));
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use entrace_core::human::{bytes_to_human, duration_to_human, format_si, us_to_human};
+
+#[test]
+fn test_us_to_human() {
+    let cases = [
+        (0, "0us"),
+        (1, "1us"),
+        (999, "999us"),
+        (1000, "1ms"),
+        (1001, "1ms 1us"),
+        (999_999, "999ms 999us"),
+        (1_000_000, "1s"),
+        (1_020_005, "1s 20ms 5us"),
+        (60_000_000, "60s"),
+        (3_600_000_000, "3600s"),
+        (u64::MAX, "18446744073709s 551ms 615us"),
+    ];
+    for (us, expected) in cases {
+        assert_eq!(us_to_human(us), expected, "{us}");
+    }
+}
+
+#[test]
+fn test_duration_to_human() {
+    assert_eq!(duration_to_human(Duration::from_nanos(999)), "0us");
+    assert_eq!(duration_to_human(Duration::from_millis(1500)), "1s 500ms");
+    assert_eq!(duration_to_human(Duration::MAX), "18446744073709s 551ms 615us");
+}
+
+#[test]
+fn test_bytes_to_human() {
+    let cases = [
+        (0, "0 B"),
+        (1, "1 B"),
+        (999, "999 B"),
+        (1000, "1.0 kB"),
+        (1024, "1.0 kB"),
+        (1050, "1.1 kB"),
+        (999_999, "1.0 MB"),
+        (950_000, "950.0 kB"),
+        (1_200_000, "1.2 MB"),
+        (57_000_000, "57.0 MB"),
+        (1_200_000_000, "1.2 GB"),
+        (4_300_000_000_000, "4.3 TB"),
+        (5_000_000_000_000_000, "5000.0 TB"),
+    ];
+    for (bytes, expected) in cases {
+        assert_eq!(bytes_to_human(bytes), expected, "{bytes}");
+    }
+}
+
+#[test]
+fn test_format_si_without_unit() {
+    assert_eq!(format_si(0.0, ""), "0");
+    assert_eq!(format_si(42.0, ""), "42");
+    assert_eq!(format_si(3_400_000.0, ""), "3.4M");
+    assert_eq!(format_si(12_500.0, ""), "12.5k");
+}
@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+
+use entrace_core::{
+    LogProvider, MetadataContainer, TraceEntry,
+    remote::{BaseIETLogProvider, MainThreadMessage},
+};
+
+fn wait_until(mut f: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if f() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    false
+}
+
+#[test]
+fn test_static_provider_is_not_live() {
+    let provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    assert!(wait_until(|| !provider.is_live()));
+}
+
+#[test]
+fn test_live_until_worker_done_and_drained() {
+    let (stop_tx, stop_rx) = crossbeam_channel::bounded::<()>(0);
+    let mut provider = BaseIETLogProvider::new(stop_rx, Default::default(), |stop_rx, tx, _| {
+        let root = TraceEntry::root();
+        let entry =
+            TraceEntry::from_unsorted_attrs(0, None, MetadataContainer::default(), vec![], vec![]);
+        tx.send(MainThreadMessage::InsertMany(vec![root, entry])).unwrap();
+        stop_rx.recv().ok();
+    });
+    assert!(provider.is_live());
+    stop_tx.send(()).unwrap();
+    // the worker is done, but its data is not processed yet
+    assert!(wait_until(|| provider.handle.is_finished()));
+    assert!(provider.is_live());
+    assert_eq!(provider.pending_messages(), 1);
+    provider.frame_callback();
+    assert_eq!(provider.len(), 2);
+    assert_eq!(provider.pending_messages(), 0);
+    assert!(!provider.is_live());
+}
+
+#[test]
+fn test_snapshot_does_not_follow_updates() {
+    let (more_tx, more_rx) = crossbeam_channel::bounded::<()>(0);
+    let mut provider = BaseIETLogProvider::new(more_rx, Default::default(), |more_rx, tx, _| {
+        let entry = || {
+            TraceEntry::from_unsorted_attrs(0, None, MetadataContainer::default(), vec![], vec![])
+        };
+        tx.send(MainThreadMessage::InsertMany(vec![TraceEntry::root(), entry()])).unwrap();
+        more_rx.recv().ok();
+        tx.send(MainThreadMessage::Insert(entry())).unwrap();
+    });
+    assert!(wait_until(|| {
+        provider.frame_callback();
+        provider.len() == 2
+    }));
+    let snapshot = provider.snapshot();
+    assert!(wait_until(|| !snapshot.is_live()));
+
+    more_tx.send(()).unwrap();
+    assert!(wait_until(|| provider.handle.is_finished()));
+    provider.frame_callback();
+    assert_eq!(provider.len(), 3);
+    assert_eq!(provider.children(0).unwrap(), [1, 2]);
+    // snapshots are meant to be read on another thread
+    let (len, children) =
+        std::thread::spawn(move || (snapshot.len(), snapshot.children(0).unwrap().to_vec()))
+            .join()
+            .unwrap();
+    assert_eq!(len, 2);
+    assert_eq!(children, [1]);
+}
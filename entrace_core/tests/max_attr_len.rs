@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    EnValue, LogProvider, TRUNCATED_MARKER, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Record the events of `emit` through a layer with `max_attr_len`, and load them back.
+fn record(max_attr_len: usize, emit: impl FnOnce()) -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    {
+        let layer = TreeLayer::from_storage(storage.clone()).with_max_attr_len(max_attr_len);
+        let subscriber = Registry::default().with(LevelFilter::TRACE).with(layer);
+        let _guard = subscriber.set_default();
+        emit();
+    }
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+fn attr(provider: &dyn LogProvider, name: &str) -> EnValue {
+    provider.attr_value(1, name).unwrap().unwrap().to_owned()
+}
+
+#[test]
+fn test_long_attrs_are_cut() {
+    let body = "x".repeat(10 * 1024);
+    let provider = record(100, || info!(body, debug = ?body, short = "ok", "{body}"));
+    for name in ["body", "message"] {
+        let EnValue::String(value) = attr(&provider, name) else { panic!() };
+        assert_eq!(value, format!("{}{TRUNCATED_MARKER}", "x".repeat(100)));
+    }
+    // the quotes added by `Debug` count towards the limit
+    let EnValue::Debug(debug) = attr(&provider, "debug") else { panic!() };
+    assert_eq!(debug, format!("\"{}{TRUNCATED_MARKER}", "x".repeat(99)));
+    assert!(matches!(attr(&provider, "short"), EnValue::String(x) if x == "ok"));
+
+    let bytes = vec![7u8; 10 * 1024];
+    let provider = record(100, || info!(bytes = bytes.as_slice(), "m"));
+    let EnValue::Bytes(cut) = attr(&provider, "bytes") else { panic!() };
+    assert_eq!(cut.len(), 100 + TRUNCATED_MARKER.len());
+    assert!(cut.ends_with(TRUNCATED_MARKER.as_bytes()));
+}
+
+#[test]
+fn test_cut_at_char_boundary() {
+    // 'é' is 2 bytes, so a limit of 5 bytes falls inside the third one
+    let provider = record(5, || info!(text = "ééé", "m"));
+    let EnValue::String(text) = attr(&provider, "text") else { panic!() };
+    assert_eq!(text, format!("éé{TRUNCATED_MARKER}"));
+}
+
+#[test]
+fn test_unlimited_by_default() {
+    let body = "x".repeat(10 * 1024);
+    let provider = record(usize::MAX, || info!(body = body.as_str(), "m"));
+    assert!(matches!(attr(&provider, "body"), EnValue::String(x) if x == body));
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    LogProvider, PoolEntry, TraceEntry, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::info_span;
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+const DEPTH: usize = 6;
+const BREADTH: usize = 3;
+
+fn provider(pool: Vec<PoolEntry>, data: Vec<TraceEntry>) -> BaseIETLogProvider {
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = pool;
+    provider.data = data;
+    provider
+}
+
+/// `BREADTH` chains of `DEPTH` nested spans under the root, like `paramtree` in the example.
+/// Chain `b` holds the ids `1 + b * DEPTH ..= (b + 1) * DEPTH`, from the outermost span.
+fn paramtree() -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    {
+        let subscriber = Registry::default().with(TreeLayer::from_storage(storage.clone()));
+        let _guard = subscriber.set_default();
+        for b in 0..BREADTH {
+            let spans: Vec<_> = (0..DEPTH).map(|d| info_span!("node", d, b).entered()).collect();
+            drop(spans);
+        }
+    }
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    provider(initial.pool, initial.data)
+}
+
+#[test]
+fn test_paramtree_depth() {
+    let trace = paramtree();
+    assert_eq!(trace.len(), 1 + DEPTH * BREADTH);
+    assert_eq!(trace.max_depth().unwrap(), DEPTH);
+    // every chain is as deep, the first one wins
+    let first_chain: Vec<u32> = (0..=DEPTH as u32).collect();
+    assert_eq!(trace.max_depth_path().unwrap(), first_chain);
+}
+
+#[test]
+fn test_empty_and_deep_traces() {
+    let empty = provider(vec![], vec![]);
+    assert_eq!(empty.max_depth_path().unwrap(), [0]);
+    assert_eq!(empty.max_depth().unwrap(), 0);
+
+    // far deeper than a recursive walk could go
+    const LEN: u32 = 1_000_000;
+    let mut pool = vec![];
+    let mut data = vec![];
+    for id in 0..LEN {
+        let parent = id.saturating_sub(1);
+        PoolEntry::push_linked(&mut pool, parent);
+        let mut entry = TraceEntry::root();
+        entry.parent = parent;
+        data.push(entry);
+    }
+    let chain = provider(pool, data);
+    assert_eq!(chain.max_depth().unwrap(), LEN as usize - 1);
+}
+
+#[test]
+fn test_cycles_are_skipped() {
+    let mut pool = vec![PoolEntry::new(), PoolEntry::new(), PoolEntry::new()];
+    pool[0].children = vec![1];
+    pool[1].children = vec![2];
+    // a corrupt link back to the root
+    pool[2].children = vec![0, 1];
+    let data = vec![TraceEntry::root(); 3];
+    assert_eq!(provider(pool, data).max_depth_path().unwrap(), [0, 1, 2]);
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    LogProvider, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info, info_span};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Spans 1-4: an event with a `msg`, one with a numeric `msg`, one with a regular message, and a
+/// span with a `msg`.
+fn provider() -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    {
+        let _guard =
+            Registry::default().with(TreeLayer::from_storage(storage.clone())).set_default();
+        info!(msg = "disk full", code = 28);
+        info!(msg = 3);
+        info!("plain");
+        let _span = info_span!("request", msg = "GET /").entered();
+    }
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+#[test]
+fn test_custom_message_attr() {
+    let log = provider();
+    let header = |id| log.header_with_message_attr(id, "msg").unwrap();
+    assert_eq!(header(1).message, Some("disk full"));
+    assert_eq!(header(1).display_text(1), "[I]: disk full");
+    // only strings are used as messages
+    assert_eq!(header(2).message, None);
+    assert_eq!(header(3).message, Some("plain"));
+    assert_eq!(header(4).display_text(4), "[I]: GET /");
+}
+
+#[test]
+fn test_default_message_attr() {
+    let log = provider();
+    for id in 0..log.len() as u32 {
+        let header = log.header_with_message_attr(id, "message").unwrap();
+        assert_eq!(header.message, log.header(id).unwrap().message);
+    }
+    assert_eq!(log.header(1).unwrap().message, None);
+    assert_eq!(log.header(3).unwrap().message, Some("plain"));
+}
@@ -0,0 +1,114 @@
+#![cfg(feature = "json")]
+use std::sync::Arc;
+
+use entrace_core::{
+    TreeLayer,
+    export::{SPAN_JSON_MAX_SPANS, export_ndjson, format_span_json},
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use serde_json::{Value, json};
+use tracing::{info, info_span, level_filters::LevelFilter, warn};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn provider() -> BaseIETLogProvider {
+    record(|| {
+        info_span!(target: "app", "request", id = "abc", bytes = &[1u8, 2][..]).in_scope(|| {
+            warn!(target: "app::db", rows = 3, big = u128::MAX, "slow\n\"query\"");
+            info!(target: "app", ok = true, ratio = f64::NAN, "done");
+        });
+    })
+}
+
+fn record(emit: impl FnOnce()) -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    emit();
+    drop(guard);
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+#[test]
+fn test_each_line_is_json() {
+    let mut out = vec![];
+    export_ndjson(&provider(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.ends_with('\n'));
+    let lines: Vec<Value> = out.lines().map(|x| serde_json::from_str(x).unwrap()).collect();
+    assert_eq!(lines.len(), 3);
+
+    assert_eq!(lines[0]["id"], 1);
+    assert_eq!(lines[0]["parent"], 0);
+    assert_eq!(lines[0]["name"], "request");
+    assert_eq!(lines[0]["message"], Value::Null);
+    // the id attribute would shadow the span id
+    assert_eq!(lines[0]["attr.id"], "abc");
+    assert_eq!(lines[0]["bytes"], json!([1, 2]));
+
+    assert_eq!(lines[1]["parent"], 1);
+    assert_eq!(lines[1]["level"], "WARN");
+    assert_eq!(lines[1]["target"], "app::db");
+    assert_eq!(lines[1]["message"], "slow\n\"query\"");
+    assert_eq!(lines[1]["rows"], 3);
+    // exact, even if serde_json::Value can only parse it as a float
+    assert!(out.contains(&format!("\"big\":{}", u128::MAX)));
+
+    assert_eq!(lines[2]["ok"], true);
+    assert_eq!(lines[2]["ratio"], Value::Null);
+}
+
+#[test]
+fn test_span_json() {
+    let provider = provider();
+    let json: Value =
+        serde_json::from_str(&format_span_json(&provider, 2, false).unwrap().json).unwrap();
+    assert_eq!(
+        json,
+        json!({
+            "spans": [{
+                "id": 2,
+                "parent": 1,
+                "level": "WARN",
+                "target": "app::db",
+                "name": json["spans"][0]["name"],
+                "message": "slow\n\"query\"",
+                "rows": 3,
+                "big": u128::MAX as f64,
+            }],
+            "omitted_spans": 0,
+        })
+    );
+
+    let json: Value =
+        serde_json::from_str(&format_span_json(&provider, 1, true).unwrap().json).unwrap();
+    let spans = json["spans"].as_array().unwrap();
+    let ids: Vec<_> = spans.iter().map(|x| (x["id"].clone(), x["parent"].clone())).collect();
+    assert_eq!(ids, [(json!(1), json!(0)), (json!(2), json!(1)), (json!(3), json!(1))]);
+    assert_eq!(spans[0]["attr.id"], "abc");
+    assert_eq!(spans[2]["message"], "done");
+    assert_eq!(json["omitted_spans"], 0);
+}
+
+#[test]
+fn test_span_json_is_cut() {
+    let provider = record(|| {
+        info_span!("outer").in_scope(|| {
+            for i in 0..SPAN_JSON_MAX_SPANS + 10 {
+                info!(i, "event");
+            }
+        })
+    });
+    let output = format_span_json(&provider, 1, true).unwrap();
+    assert_eq!(output.omitted_spans, 11);
+    let json: Value = serde_json::from_str(&output.json).unwrap();
+    let spans = json["spans"].as_array().unwrap();
+    assert_eq!(spans.len(), SPAN_JSON_MAX_SPANS);
+    assert_eq!(spans.last().unwrap()["i"], SPAN_JSON_MAX_SPANS - 2);
+    assert_eq!(json["omitted_spans"], 11);
+}
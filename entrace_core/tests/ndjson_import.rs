@@ -0,0 +1,89 @@
+#![cfg(feature = "json")]
+use std::sync::Arc;
+
+use entrace_core::{
+    TreeLayer,
+    export::export_ndjson,
+    import::{ImportError, from_ndjson},
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info, info_span, level_filters::LevelFilter, warn};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn load(iet: &[u8]) -> BaseIETLogProvider {
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+fn record() -> Vec<u8> {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    info_span!(target: "app", "request", id = "abc", bytes = &[1u8, 2][..]).in_scope(|| {
+        warn!(target: "app::db", rows = 3, delta = -4, "slow\n\"query\"");
+        info_span!("inner", ratio = 0.5).in_scope(|| info!(ok = true, "done"));
+    });
+    info!(target: "other", "after");
+    drop(guard);
+    storage.finish().unwrap()
+}
+
+fn export(provider: &BaseIETLogProvider) -> String {
+    let mut out = vec![];
+    export_ndjson(provider, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_round_trip() {
+    let original = load(&record());
+    let exported = export(&original);
+
+    let mut iet = vec![];
+    let count = from_ndjson(exported.as_bytes(), &mut iet).unwrap();
+    assert_eq!(count as usize, original.pool.len() - 1);
+    let imported = load(&iet);
+    assert_eq!(imported.pool.len(), original.pool.len());
+    assert_eq!(export(&imported), exported);
+}
+
+#[test]
+fn test_ids_are_remapped() {
+    let input = "{\"id\":10,\"parent\":0,\"name\":\"a\"}\n\n{\"id\":7,\"parent\":10,\"level\":\"debug\",\"message\":\"hi\",\"n\":1}\n";
+    let mut iet = vec![];
+    assert_eq!(from_ndjson(input.as_bytes(), &mut iet).unwrap(), 2);
+    let out = export(&load(&iet));
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(
+        lines[0],
+        r#"{"id":1,"parent":0,"level":"INFO","target":"","name":"a","message":null}"#
+    );
+    assert_eq!(
+        lines[1],
+        r#"{"id":2,"parent":1,"level":"DEBUG","target":"","name":"","message":"hi","n":1}"#
+    );
+}
+
+#[test]
+fn test_invalid_input() {
+    let import = |input: &str| from_ndjson(input.as_bytes(), &mut vec![]).unwrap_err();
+    assert!(matches!(
+        import("{\"id\":1,\"parent\":2}\n{\"id\":2,\"parent\":0}"),
+        ImportError::UnknownParent { line: 1, parent: 2 }
+    ));
+    assert!(matches!(
+        import("{\"id\":1,\"parent\":0}\n{\"id\":1,\"parent\":0}"),
+        ImportError::DuplicateId { line: 2, id: 1 }
+    ));
+    assert!(matches!(
+        import("{\"id\":1,\"parent\":0,\"level\":\"LOUD\"}"),
+        ImportError::BadValue { line: 1, key: "level", .. }
+    ));
+    assert!(matches!(import("{\"parent\":0}"), ImportError::MissingKey { line: 1, key: "id" }));
+    assert!(matches!(import("[1]"), ImportError::NotAnObject { line: 1 }));
+    assert!(matches!(import("{"), ImportError::Json { line: 1, .. }));
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    LogProvider, TreeLayer, format_span_oneline,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info_span, level_filters::LevelFilter, warn};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn provider(emit: impl FnOnce()) -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    emit();
+    drop(guard);
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+#[test]
+fn test_oneline_with_message() {
+    let log = provider(|| warn!(target: "app::db", rows = 3, table = "users", "slow\nquery"));
+    let line = format_span_oneline(&log, 1).unwrap();
+    let name = log.meta(1).unwrap().name;
+    assert_eq!(line, format!("[W] app::db {name} slow\\nquery {{rows=3, table=users}}"));
+}
+
+#[test]
+fn test_oneline_without_message() {
+    let log = provider(|| {
+        info_span!(target: "app", "request").in_scope(|| ());
+        info_span!(target: "app", "request", ok = true).in_scope(|| ());
+    });
+    assert_eq!(format_span_oneline(&log, 1).unwrap(), "[I] app request");
+    assert_eq!(format_span_oneline(&log, 2).unwrap(), "[I] app request {ok=true}");
+}
+
+#[test]
+fn test_oneline_bytes() {
+    let log = provider(|| {
+        info_span!(target: "net", "packet", payload = &[0x00u8, 0x1f, 0xff][..]).in_scope(|| ())
+    });
+    let line = format_span_oneline(&log, 1).unwrap();
+    assert_eq!(line, "[I] net packet {payload=0x001fff}");
+    assert!(!line.contains('\n'));
+}
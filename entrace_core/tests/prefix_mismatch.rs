@@ -0,0 +1,94 @@
+mod common;
+
+use std::{
+    io::{Cursor, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use entrace_core::{
+    IETPresentationConfig, LengthPrefixError, LogProviderError,
+    convert::{ConvertError, gather_iet_table_data},
+    remote::{
+        DummyRefresher, IETEvent, IETStorageConfig, LoadIETError, RemoteLogProvider, load_iet_trace,
+    },
+};
+use tracing::{info, info_span};
+
+fn recorded_iet(config: IETStorageConfig<Vec<u8>>) -> Vec<u8> {
+    common::recorded_iet(config, || {
+        let _span = info_span!("outer").entered();
+        for i in 0..4 {
+            info!(i, "event");
+        }
+    })
+}
+
+fn is_mismatch(e: &LengthPrefixError) -> bool {
+    matches!(
+        e,
+        LengthPrefixError::FrameMismatch { .. }
+            | LengthPrefixError::TooLarge(_)
+            | LengthPrefixError::ParentAfterChild { .. }
+    )
+}
+
+#[test]
+fn test_matching_modes_load() {
+    let plain = recorded_iet(IETStorageConfig::non_length_prefixed(vec![]));
+    assert_eq!(load_iet_trace(&plain[10..], false).unwrap().data.len(), 6);
+    let prefixed = recorded_iet(IETStorageConfig::length_prefixed(vec![]));
+    assert_eq!(load_iet_trace(&prefixed[10..], true).unwrap().data.len(), 6);
+}
+
+#[test]
+fn test_prefixed_read_as_plain() {
+    let prefixed = recorded_iet(IETStorageConfig::length_prefixed(vec![]));
+    let err = load_iet_trace(&prefixed[10..], false).err().expect("mismatch not detected");
+    let LoadIETError::LengthPrefix(e) = &err else { panic!("unexpected error {err}") };
+    assert!(is_mismatch(e), "{e}");
+    assert!(err.to_string().contains("writes length prefixes"), "{err}");
+
+    let err = gather_iet_table_data(&mut Cursor::new(&prefixed[10..]), false, false).unwrap_err();
+    assert!(matches!(err, ConvertError::LengthPrefix(ref e) if is_mismatch(e)), "{err}");
+}
+
+#[test]
+fn test_plain_read_as_prefixed() {
+    let plain = recorded_iet(IETStorageConfig::non_length_prefixed(vec![]));
+    let err = load_iet_trace(&plain[10..], true).err().expect("mismatch not detected");
+    let LoadIETError::LengthPrefix(e) = &err else { panic!("unexpected error {err}") };
+    assert!(is_mismatch(e), "{e}");
+    assert!(err.to_string().contains("without length prefixes"), "{err}");
+
+    let err = gather_iet_table_data(&mut Cursor::new(&plain[10..]), false, true).unwrap_err();
+    assert!(matches!(err, ConvertError::LengthPrefix(ref e) if is_mismatch(e)), "{err}");
+}
+
+#[test]
+fn test_stream_switching_to_plain() {
+    let prefixed = recorded_iet(IETStorageConfig::length_prefixed(vec![]));
+    let plain = recorded_iet(IETStorageConfig::non_length_prefixed(vec![]));
+    let mut sent = prefixed.clone();
+    sent.extend_from_slice(&plain[10..]);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (event_tx, event_rx) = crossbeam_channel::unbounded();
+    let config = IETPresentationConfig::new(Some(event_tx), DummyRefresher {});
+    let _provider = RemoteLogProvider::new(listener, config);
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&sent).unwrap();
+    drop(stream);
+
+    loop {
+        match event_rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            IETEvent::Error(LogProviderError::LengthPrefix(e)) => {
+                assert!(is_mismatch(&e), "{e}");
+                break;
+            }
+            IETEvent::Error(e) => panic!("unexpected error {e}"),
+            IETEvent::Info(_) => (),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use entrace_core::{
+    LogProvider, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, PreviewStorage, load_iet_trace},
+};
+use tracing::{info, info_span, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn wait_until(mut f: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if f() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    false
+}
+
+fn names(log: &dyn LogProvider) -> Vec<(u32, String, Option<String>)> {
+    (0..log.len() as u32)
+        .map(|id| {
+            let header = log.header(id).unwrap();
+            (log.parent(id).unwrap(), header.name.to_string(), header.message.map(String::from))
+        })
+        .collect()
+}
+
+#[test]
+fn test_preview_while_recording() {
+    let file_storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let (storage, mut preview) = PreviewStorage::new(file_storage.clone(), Default::default());
+    let storage = Arc::new(storage);
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    let outer = info_span!("outer", n = 1);
+    outer.in_scope(|| info!("first"));
+    // the spans show up while the program still runs
+    assert!(wait_until(|| {
+        preview.frame_callback();
+        preview.len() == 3
+    }));
+    assert!(preview.is_live());
+    assert_eq!(preview.attr_value(1, "n").unwrap().unwrap().to_string(), "1");
+
+    outer.in_scope(|| info!("second"));
+    drop(outer);
+    drop(guard);
+    storage.finish();
+    assert!(wait_until(|| {
+        preview.frame_callback();
+        !preview.is_live()
+    }));
+
+    // and match what the inner storage wrote
+    let iet = file_storage.finish().unwrap();
+    let written = load_iet_trace(&iet[10..], false).unwrap();
+    let mut written_provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    written_provider.pool = written.pool;
+    written_provider.data = written.data;
+    assert_eq!(preview.len(), 4);
+    assert_eq!(names(&preview), names(&written_provider));
+    assert_eq!(preview.children(1).unwrap(), [2, 3]);
+    assert_eq!(preview.message(3).unwrap(), Some("second"));
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    LoadConfig, LogProvider, LogProviderImpl, StorageFormat, TreeLayer,
+    convert::{provider_to_et, provider_to_iet},
+    parse_entrace_magic,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use tracing::{info, info_span, level_filters::LevelFilter, warn};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn loaded() -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    let producer = info_span!("producer", queue = "jobs");
+    producer.in_scope(|| info!(n = 2, "queued"));
+    let consumer = info_span!("consumer", bytes = &[1u8, 2][..]);
+    consumer.follows_from(&producer);
+    consumer.in_scope(|| warn!(ratio = 0.5, big = u128::MAX, "slow"));
+    drop(guard);
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+/// Write `bytes` to a temporary file and load it like the GUI would.
+fn load_file(bytes: &[u8], extension: &str) -> LogProviderImpl {
+    let path = std::env::temp_dir()
+        .join(format!("entrace-provider-to-file-{}.{extension}", std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    // SAFETY: nothing else touches this file while it is mapped.
+    let provider = unsafe { entrace_core::load_trace(&path, LoadConfig::default()) }.unwrap();
+    std::fs::remove_file(&path).ok();
+    provider
+}
+
+fn assert_same(a: &dyn LogProvider, b: &dyn LogProvider) {
+    assert_eq!(a.len(), b.len());
+    for id in 0..a.len() as u32 {
+        assert_eq!(a.parent(id).unwrap(), b.parent(id).unwrap(), "{id}");
+        assert_eq!(a.children(id).unwrap(), b.children(id).unwrap(), "{id}");
+        // neither implements PartialEq
+        let debug = |x: &dyn LogProvider| {
+            format!("{:?} {:?}", x.meta(id).unwrap(), x.attr_values(id).unwrap())
+        };
+        assert_eq!(debug(a), debug(b), "{id}");
+        assert_eq!(a.message(id).unwrap(), b.message(id).unwrap(), "{id}");
+        assert_eq!(a.attr_names(id).unwrap(), b.attr_names(id).unwrap(), "{id}");
+        assert_eq!(a.follows_from(id).unwrap(), b.follows_from(id).unwrap(), "{id}");
+    }
+}
+
+#[test]
+fn test_provider_to_iet() {
+    let original = loaded();
+    let mut iet = vec![];
+    provider_to_iet(&original, &mut iet).unwrap();
+    assert_eq!(parse_entrace_magic(iet[..10].try_into().unwrap()).unwrap().1, StorageFormat::IET);
+    let reloaded = load_file(&iet, "iet");
+    assert_same(&original, &reloaded);
+    // writing it again gives the same file
+    let mut again = vec![];
+    provider_to_iet(&reloaded, &mut again).unwrap();
+    assert_eq!(iet, again);
+}
+
+#[test]
+fn test_provider_to_et() {
+    let original = loaded();
+    let mut et = vec![];
+    provider_to_et(&original, &mut et).unwrap();
+    let reloaded = load_file(&et, "et");
+    assert!(matches!(reloaded, LogProviderImpl::Mmap(_)));
+    assert_same(&original, &reloaded);
+}
+
+#[test]
+fn test_snapshot_of_live_trace() {
+    let original = loaded();
+    let snapshot = original.snapshot();
+    let mut iet = vec![];
+    provider_to_iet(&*snapshot, &mut iet).unwrap();
+    assert_same(&original, &load_file(&iet, "iet"));
+}
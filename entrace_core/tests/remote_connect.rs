@@ -0,0 +1,61 @@
+use std::{
+    net::TcpListener,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use entrace_core::{
+    IETPresentationConfig, LogProvider, LogProviderError, TreeLayer,
+    remote::{
+        IETEvent, IETInfo, IETStorage, IETStorageConfig, RemoteLogProvider, RemoteLogProviderError,
+    },
+};
+use tracing::info;
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+#[test]
+fn test_connect_to_listening_process() {
+    // the traced process listens, and sends its trace to the viewer which connects
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let traced = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let storage = Arc::new(IETStorage::init(IETStorageConfig::length_prefixed(stream)));
+        let subscriber = Registry::default().with(TreeLayer::from_storage(storage.clone()));
+        let _guard = subscriber.set_default();
+        info!("first");
+        info!("second");
+        storage.finish().unwrap();
+    });
+
+    let (event_tx, events) = crossbeam_channel::unbounded();
+    let config = IETPresentationConfig { event_tx: Some(event_tx), ..Default::default() };
+    let mut provider = RemoteLogProvider::connect(addr, config);
+    traced.join().unwrap();
+    let start = Instant::now();
+    while provider.len() < 3 && start.elapsed() < Duration::from_secs(5) {
+        provider.frame_callback();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(provider.children(0).unwrap(), [1, 2]);
+    assert_eq!(provider.message(2).unwrap(), Some("second"));
+
+    let infos: Vec<_> = events.iter().take(2).collect();
+    assert!(matches!(infos[0], IETEvent::Info(IETInfo::Connected)));
+    assert!(matches!(infos[1], IETEvent::Info(IETInfo::RemoteClosedConnection)));
+}
+
+#[test]
+fn test_connect_error() {
+    // nothing listens here anymore
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let (event_tx, events) = crossbeam_channel::unbounded();
+    let config = IETPresentationConfig { event_tx: Some(event_tx), ..Default::default() };
+    let provider = RemoteLogProvider::connect(addr, config);
+    let event = events.recv_timeout(Duration::from_secs(15)).unwrap();
+    let IETEvent::Error(LogProviderError::RemoteLogProviderError(error)) = event else {
+        panic!("expected a connection error");
+    };
+    assert!(matches!(error, RemoteLogProviderError::CannotConnect(x, _) if x == addr));
+    assert_eq!(provider.len(), 0);
+}
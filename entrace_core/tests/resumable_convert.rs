@@ -0,0 +1,128 @@
+use std::{
+    io::{Cursor, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use entrace_core::{
+    TreeLayer,
+    convert::{ConvertCheckpoint, ConvertError, et_to_iet, et_to_iet_resumable},
+    mmap::ETStorage,
+};
+use tracing::{info, info_span, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn get_et() -> Vec<u8> {
+    let storage = Arc::new(ETStorage::init(Cursor::new(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    let span = info_span!("outer").entered();
+    for i in 0..200u64 {
+        info!(i, "message {i}");
+    }
+    drop(span);
+    drop(guard);
+    storage.finish(Cursor::new(vec![])).unwrap().temp_buf.unwrap().into_inner()
+}
+
+fn checkpoint_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("entrace-{name}-{}.progress", std::process::id()))
+}
+
+/// Stands in for a process that is killed after writing `budget` bytes.
+struct FailAfter {
+    inner: Cursor<Vec<u8>>,
+    budget: usize,
+}
+impl Write for FailAfter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.budget == 0 {
+            return Err(std::io::Error::other("interrupted"));
+        }
+        let n = buf.len().min(self.budget);
+        self.budget -= n;
+        self.inner.write(&buf[..n])
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+impl Seek for FailAfter {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn test_interrupt_and_resume() {
+    let et = get_et();
+    let mut expected = vec![];
+    et_to_iet(&mut Cursor::new(&et), &mut expected, true).unwrap();
+    let checkpoint = checkpoint_path("interrupt");
+    std::fs::remove_file(&checkpoint).ok();
+
+    let mut out = FailAfter { inner: Cursor::new(vec![]), budget: expected.len() / 2 };
+    let mut first_run = vec![];
+    let res = et_to_iet_resumable(&mut Cursor::new(&et), &mut out, &checkpoint, 16, |x| {
+        first_run.push(x.entries_written)
+    });
+    assert!(res.is_err());
+    let saved = ConvertCheckpoint::load(&checkpoint).unwrap().unwrap();
+    assert_eq!(saved.entries_written, *first_run.last().unwrap());
+    assert!(0 < saved.entries_written && saved.entries_written < saved.entries_total);
+
+    // the output has more bytes than the checkpoint covers, those are overwritten
+    let mut out = Cursor::new(out.inner.into_inner());
+    assert!(out.get_ref().len() as u64 > saved.output_len);
+    let mut second_run = vec![];
+    et_to_iet_resumable(&mut Cursor::new(&et), &mut out, &checkpoint, 16, |x| {
+        second_run.push(x.entries_written)
+    })
+    .unwrap();
+    assert_eq!(second_run[0], saved.entries_written + 16);
+    assert_eq!(*second_run.last().unwrap(), saved.entries_total);
+    assert_eq!(out.into_inner(), expected);
+    assert!(!checkpoint.exists());
+}
+
+#[test]
+fn test_uninterrupted_matches_et_to_iet() {
+    let et = get_et();
+    let mut expected = vec![];
+    et_to_iet(&mut Cursor::new(&et), &mut expected, true).unwrap();
+    let checkpoint = checkpoint_path("uninterrupted");
+    let mut out = Cursor::new(vec![]);
+    et_to_iet_resumable(&mut Cursor::new(&et), &mut out, &checkpoint, 1000, |_| ()).unwrap();
+    assert_eq!(out.into_inner(), expected);
+}
+
+#[test]
+fn test_checkpoint_for_other_input() {
+    let et = get_et();
+    let checkpoint = checkpoint_path("other");
+    let other = ConvertCheckpoint {
+        input_len: et.len() as u64 + 1,
+        entries_total: 202,
+        entries_written: 10,
+        output_len: 100,
+    };
+    other.store(&checkpoint).unwrap();
+    let res = et_to_iet_resumable(
+        &mut Cursor::new(&et),
+        &mut Cursor::new(vec![]),
+        &checkpoint,
+        16,
+        |_| (),
+    );
+    assert!(matches!(res, Err(ConvertError::CheckpointMismatch(_))));
+    std::fs::remove_file(&checkpoint).ok();
+}
+
+#[test]
+fn test_checkpoint_text_round_trip() {
+    let checkpoint =
+        ConvertCheckpoint { input_len: 1, entries_total: 2, entries_written: 3, output_len: 4 };
+    assert_eq!(ConvertCheckpoint::parse(&checkpoint.to_text()).unwrap(), checkpoint);
+    assert!(ConvertCheckpoint::parse("entrace-convert-checkpoint 1\ninput_len x\n").is_err());
+}
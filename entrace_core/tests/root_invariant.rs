@@ -0,0 +1,93 @@
+mod common;
+
+use std::{
+    io::Cursor,
+    net::TcpListener,
+    time::{Duration, Instant},
+};
+
+use entrace_core::{
+    EN_DISK_VERSION, IETPresentationConfig, LoadConfig, LogProvider, LogProviderImpl,
+    StorageFormat, TraceEntry, entrace_magic_for,
+    remote::{BaseIETLogProvider, IETStorageConfig, MainThreadMessage, RemoteLogProvider},
+};
+use tracing::info;
+
+fn assert_root(log: &dyn LogProvider, children: &[u32]) {
+    assert_eq!(log.parent(0).unwrap(), 0);
+    assert_eq!(log.children(0).unwrap(), children);
+}
+
+fn recorded_iet() -> Vec<u8> {
+    common::recorded_iet(IETStorageConfig::non_length_prefixed(vec![]), || {
+        info!("first");
+        info!("second");
+    })
+}
+
+fn iet_to_et(iet: &[u8]) -> Vec<u8> {
+    let mut et = vec![];
+    entrace_core::convert::iet_to_et(&mut Cursor::new(iet), &mut et, true, false).unwrap();
+    et
+}
+
+/// Load `bytes` from a file, like a trace opened in the GUI.
+fn load(bytes: &[u8], name: &str) -> LogProviderImpl {
+    let path = std::env::temp_dir().join(format!("entrace-root-{}-{name}", std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    // SAFETY: nothing else touches this file while it is mapped.
+    let provider = unsafe { entrace_core::load_trace(&path, LoadConfig::default()) }.unwrap();
+    std::fs::remove_file(&path).ok();
+    provider
+}
+
+#[test]
+fn test_empty_traces() {
+    let empty = BaseIETLogProvider::new((), IETPresentationConfig::default(), |_, _, _| ());
+    assert_root(&empty, &[]);
+    assert_eq!(empty.len(), 0);
+    assert_eq!(empty.header(0).unwrap().name, "root");
+
+    let magic_only = entrace_magic_for(EN_DISK_VERSION, StorageFormat::IET);
+    let iet = load(&magic_only, "empty.iet");
+    assert!(matches!(iet, LogProviderImpl::FileIET(_)));
+    assert_root(&iet, &[]);
+
+    let et = load(&iet_to_et(&magic_only), "empty.et");
+    assert!(matches!(et, LogProviderImpl::Mmap(_)));
+    assert_root(&et, &[]);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let remote = RemoteLogProvider::new(listener, IETPresentationConfig::default());
+    assert_root(&remote, &[]);
+}
+
+#[test]
+fn test_recorded_traces() {
+    let iet = recorded_iet();
+    let loaded = load(&iet, "recorded.iet");
+    assert_root(&loaded, &[1, 2]);
+    assert_eq!(loaded.len(), 3);
+    let loaded = load(&iet_to_et(&iet), "recorded.et");
+    assert_root(&loaded, &[1, 2]);
+    assert_eq!(loaded.len(), 3);
+}
+
+#[test]
+fn test_root_sent_by_worker() {
+    let mut child = TraceEntry::root();
+    child.parent = 1;
+    let (root, first, second) = (TraceEntry::root(), TraceEntry::root(), child);
+    let worker = move |_, tx: crossbeam_channel::Sender<_>, _| {
+        tx.send(MainThreadMessage::InsertMany(vec![root, first])).unwrap();
+        tx.send(MainThreadMessage::Insert(second)).unwrap();
+    };
+    let mut provider = BaseIETLogProvider::new((), IETPresentationConfig::default(), worker);
+    let start = Instant::now();
+    while provider.len() < 3 && start.elapsed() < Duration::from_secs(5) {
+        provider.frame_callback();
+    }
+    assert_root(&provider, &[1]);
+    assert_eq!(provider.children(1).unwrap(), [2]);
+    assert_eq!(provider.parent(2).unwrap(), 1);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::{
+    io::{Cursor, Write},
+    net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+use entrace_core::{
+    IETPresentationConfig, LoadConfig, LogProvider, LogProviderImpl,
+    remote::{BaseIETLogProvider, IETStorageConfig, RemoteLogProvider},
+};
+use tracing::info;
+
+fn recorded_iet(config: IETStorageConfig<Vec<u8>>) -> Vec<u8> {
+    common::recorded_iet(config, || {
+        for i in 0..100 {
+            info!(i, "event");
+        }
+    })
+}
+
+/// Load `bytes` from a file, and check that [LogProvider::source_bytes] is its size.
+fn assert_file_size(bytes: &[u8], name: &str) -> LogProviderImpl {
+    let path = std::env::temp_dir().join(format!("entrace-size-{}-{name}", std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    // SAFETY: nothing else touches this file while it is mapped.
+    let provider = unsafe { entrace_core::load_trace(&path, LoadConfig::default()) }.unwrap();
+    assert_eq!(provider.source_bytes(), Some(bytes.len() as u64), "{name}");
+    std::fs::remove_file(&path).ok();
+    provider
+}
+
+#[test]
+fn test_file_size() {
+    let iet = recorded_iet(IETStorageConfig::non_length_prefixed(vec![]));
+    let provider = assert_file_size(&iet, "trace.iet");
+    assert!(matches!(provider, LogProviderImpl::FileIET(_)));
+
+    let mut et = vec![];
+    entrace_core::convert::iet_to_et(&mut Cursor::new(&iet), &mut et, true, false).unwrap();
+    let provider = assert_file_size(&et, "trace.et");
+    assert_eq!(provider.snapshot().source_bytes(), Some(et.len() as u64));
+
+    #[cfg(feature = "gzip")]
+    {
+        use flate2::{Compression, write::GzEncoder};
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&iet).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < iet.len());
+        assert_file_size(&compressed, "trace.iet.gz");
+    }
+
+    let in_memory = BaseIETLogProvider::new((), IETPresentationConfig::default(), |_, _, _| ());
+    assert_eq!(in_memory.source_bytes(), None);
+}
+
+#[test]
+fn test_bytes_received() {
+    let sent = recorded_iet(IETStorageConfig::length_prefixed(vec![]));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut provider = RemoteLogProvider::new(listener, IETPresentationConfig::default());
+    assert_eq!(provider.source_bytes(), Some(0));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&sent).unwrap();
+    drop(stream);
+    let start = Instant::now();
+    while provider.len() < 101 && start.elapsed() < Duration::from_secs(5) {
+        provider.frame_callback();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(provider.len(), 101);
+    assert_eq!(provider.source_bytes(), Some(sent.len() as u64));
+}
@@ -0,0 +1,143 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use entrace_core::{
+    IETPresentationConfig, SpanLifecycleReport, TreeLayer,
+    remote::{IETStorage, IETStorageConfig, PreviewStorage},
+};
+use tracing::{
+    Event, Id, Metadata, Subscriber, dispatcher, info, info_span,
+    span::{Attributes, Record},
+};
+use tracing_subscriber::{Layer, Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn storage() -> Arc<IETStorage<Vec<u8>>> {
+    Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])))
+}
+
+#[test]
+fn balanced_spans() {
+    let layer = TreeLayer::from_storage(storage());
+    let lifecycle = layer.lifecycle();
+    let guard = Registry::default().with(layer).set_default();
+    {
+        let _outer = info_span!("outer").entered();
+        for _ in 0..3 {
+            let inner = info_span!("inner");
+            // entering the same span repeatedly doesn't close it
+            inner.in_scope(|| info!("first"));
+            inner.in_scope(|| info!("second"));
+        }
+    }
+    drop(guard);
+    let report = lifecycle.log_summary();
+    assert!(report.is_balanced(), "{report}");
+    assert_eq!(report.created, 4);
+}
+
+#[test]
+fn leaked_spans() {
+    let layer = TreeLayer::from_storage(storage());
+    let lifecycle = layer.lifecycle();
+    let guard = Registry::default().with(layer).set_default();
+    std::mem::forget(info_span!("leaked"));
+    let held = info_span!("held");
+    drop(info_span!("closed"));
+    let report = lifecycle.log_summary();
+    assert_eq!(report.created, 3);
+    assert_eq!(report.never_closed, 2);
+
+    drop(held);
+    assert_eq!(lifecycle.report().never_closed, 1);
+    drop(guard);
+}
+
+/// A subscriber that accepts closing any span, as if the layer had missed its creation.
+struct Forgetful(AtomicU64);
+impl Subscriber for Forgetful {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(self.0.fetch_add(1, Ordering::Relaxed))
+    }
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, _: &Event<'_>) {}
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+    fn try_close(&self, _: Id) -> bool {
+        true
+    }
+}
+
+#[test]
+fn closed_without_creation() {
+    let layer = TreeLayer::from_storage(storage());
+    let lifecycle = layer.lifecycle();
+    let subscriber = layer.with_subscriber(Forgetful(AtomicU64::new(1)));
+    let guard = subscriber.set_default();
+    let span = info_span!("real");
+    let id = span.id().unwrap();
+    dispatcher::get_default(|d| {
+        d.try_close(Id::from_u64(1000));
+        // closing the real span twice: the second close is for a span the layer already forgot
+        d.try_close(id.clone());
+        d.try_close(id.clone());
+    });
+    std::mem::forget(span);
+    drop(guard);
+    let report = lifecycle.log_summary();
+    let expected = SpanLifecycleReport {
+        created: 1,
+        never_closed: 0,
+        closed_without_creation: 2,
+        unknown_parents: 0,
+        over_id_limit: 0,
+    };
+    assert_eq!(report, expected);
+}
+
+#[test]
+fn over_id_limit() {
+    let layer = TreeLayer::from_storage(storage()).with_max_id(3);
+    let lifecycle = layer.lifecycle();
+    let guard = Registry::default().with(layer).set_default();
+    {
+        let _outer = info_span!("outer").entered();
+        info!("kept");
+        let _inner = info_span!("inner").entered();
+        // over the limit from here
+        info!("dropped");
+        let _dropped = info_span!("dropped").entered();
+        info!("dropped inside a dropped span");
+    }
+    drop(guard);
+    let report = lifecycle.log_summary();
+    let expected = SpanLifecycleReport {
+        created: 2,
+        never_closed: 0,
+        closed_without_creation: 0,
+        unknown_parents: 0,
+        over_id_limit: 3,
+    };
+    assert_eq!(report, expected);
+    assert!(!report.is_balanced());
+    assert!(report.to_string().contains("3 spans and events dropped"), "{report}");
+}
+
+#[test]
+fn storages_summarize_on_finish() {
+    // the layer hands its counters to the storage, which prints them when it is finished
+    let storage = storage();
+    let layer = TreeLayer::from_storage(storage.clone());
+    assert!(Arc::ptr_eq(storage.lifecycle.get().unwrap(), &layer.lifecycle()));
+
+    // a preview passes them on to the storage which writes the trace
+    let inner = self::storage();
+    let (preview, _provider) = PreviewStorage::new(inner.clone(), IETPresentationConfig::default());
+    let layer = TreeLayer::from_storage(Arc::new(preview));
+    assert!(Arc::ptr_eq(inner.lifecycle.get().unwrap(), &layer.lifecycle()));
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    LogProvider, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+    resolve_span_path, span_path,
+};
+use tracing::{info, info_span};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// root
+/// |- 1 outer
+/// |  |- 2 first
+/// |  |- 3 inner
+/// |     |- 4 deep
+/// |- 5 second
+fn recorded() -> BaseIETLogProvider {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    {
+        let subscriber = Registry::default().with(TreeLayer::from_storage(storage.clone()));
+        let _guard = subscriber.set_default();
+        info_span!("outer").in_scope(|| {
+            info!("first");
+            info_span!("inner").in_scope(|| info!("deep"));
+        });
+        info!("second");
+    }
+    let iet = storage.finish().unwrap();
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+#[test]
+fn test_round_trip() {
+    let trace = recorded();
+    let expected = ["0", "0/0", "0/0/0", "0/0/1", "0/0/1/0", "0/1"];
+    assert_eq!(trace.len(), expected.len());
+    for (id, path) in expected.iter().enumerate() {
+        assert_eq!(span_path(&trace, id as u32).unwrap().as_deref(), Some(*path));
+        assert_eq!(resolve_span_path(&trace, path).unwrap(), Some(id as u32), "{path}");
+    }
+}
+
+#[test]
+fn test_bad_paths() {
+    let trace = recorded();
+    for path in ["", "1", "0/2", "0/0/1/0/0", "0/x", "0//1", "/0/1", "0/-1"] {
+        assert_eq!(resolve_span_path(&trace, path).unwrap(), None, "{path}");
+    }
+    assert_eq!(resolve_span_path(&trace, " 0/1\n").unwrap(), Some(5));
+}
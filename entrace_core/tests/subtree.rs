@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use entrace_core::{
+    AttrEncoding, LogProvider, TreeLayer,
+    remote::{
+        BaseIETLogProvider, IETStorage, IETStorageConfig, InitialIETData, LoadIETError,
+        load_iet_subtree, load_iet_trace,
+    },
+};
+use tracing::{info, info_span, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+fn get_iet() -> Vec<u8> {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let subscriber =
+        Registry::default().with(LevelFilter::TRACE).with(TreeLayer::from_storage(storage.clone()));
+    let guard = subscriber.set_default();
+    for request in 0..3 {
+        let _request = info_span!("request", request).entered();
+        info!(request, "start");
+        info_span!("db", table = "users").in_scope(|| info!(rows = 3, "query"));
+        info!(ok = true, "end");
+    }
+    drop(guard);
+    storage.finish().unwrap()
+}
+
+fn provider(initial: InitialIETData) -> BaseIETLogProvider {
+    let mut provider = BaseIETLogProvider::new((), Default::default(), |_, _, _| ());
+    provider.pool = initial.pool;
+    provider.data = initial.data;
+    provider
+}
+
+/// Check that `id` in `subtree` has the same attrs, metadata and children as `original_id`
+/// in `full`.
+fn assert_same_subtree(
+    full: &BaseIETLogProvider, subtree: &BaseIETLogProvider, original_ids: &[u32], id: u32,
+) {
+    let original_id = original_ids[id as usize];
+    assert_eq!(subtree.attr_names(id).unwrap(), full.attr_names(original_id).unwrap());
+    // these have no PartialEq, but their Debug output covers every field
+    let debug = |x: &dyn std::fmt::Debug| format!("{x:?}");
+    let values = |x: &BaseIETLogProvider, id| debug(&x.attr_values(id).unwrap());
+    assert_eq!(values(subtree, id), values(full, original_id));
+    assert_eq!(debug(&subtree.meta(id).unwrap()), debug(&full.meta(original_id).unwrap()));
+    let children = subtree.children(id).unwrap();
+    let original_children: Vec<u32> = children.iter().map(|x| original_ids[*x as usize]).collect();
+    assert_eq!(original_children, full.children(original_id).unwrap());
+    for child in children {
+        assert_eq!(subtree.parent(*child).unwrap(), id);
+        assert_same_subtree(full, subtree, original_ids, *child);
+    }
+}
+
+#[test]
+fn test_subtree_matches_full_load() {
+    let iet = get_iet();
+    let full = provider(load_iet_trace(&iet[10..], false).unwrap());
+    let requests = full.children(0).unwrap();
+    assert_eq!(requests.len(), 3);
+    let second = requests[1];
+
+    let loaded = load_iet_subtree(&iet[10..], second, false, AttrEncoding::Standard).unwrap();
+    let original_ids = loaded.original_ids;
+    let subtree = provider(loaded.initial);
+    // root, the request, its two events, the db span and its event
+    assert_eq!(subtree.len(), 6);
+    assert_eq!(subtree.children(0).unwrap(), [1]);
+    assert_eq!(original_ids[1], second);
+    assert_same_subtree(&full, &subtree, &original_ids, 1);
+}
+
+#[test]
+fn test_subtree_of_root_is_full_trace() {
+    let iet = get_iet();
+    let full = provider(load_iet_trace(&iet[10..], false).unwrap());
+    let loaded = load_iet_subtree(&iet[10..], 0, false, AttrEncoding::Standard).unwrap();
+    assert_eq!(loaded.original_ids, (0..full.len() as u32).collect::<Vec<_>>());
+    let subtree = provider(loaded.initial);
+    assert_same_subtree(&full, &subtree, &loaded.original_ids, 0);
+}
+
+#[test]
+fn test_missing_subtree_root() {
+    let iet = get_iet();
+    let loaded = load_iet_subtree(&iet[10..], 1000, false, AttrEncoding::Standard);
+    assert!(matches!(loaded, Err(LoadIETError::NoSuchSpan(1000))));
+}
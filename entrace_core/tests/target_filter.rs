@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex};
+
+use entrace_core::{EnValue, TargetFilter, TreeLayer, storage::Storage, target_matches};
+use tracing::{info, info_span, level_filters::LevelFilter};
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Keeps `(id, parent, name)` of every span and event.
+#[derive(Default)]
+struct Recorder(Mutex<Vec<(u32, u32, &'static str)>>);
+impl Storage for Recorder {
+    fn new_span(
+        &self, id: u32, parent: u32, _attr_names: Vec<String>, _attr_values: Vec<EnValue>,
+        meta: &'static tracing::Metadata<'_>,
+    ) {
+        self.0.lock().unwrap().push((id, parent, meta.name()));
+    }
+}
+
+#[test]
+fn test_exact_and_prefix_patterns() {
+    // a pattern without `*` is a module and its submodules
+    assert!(target_matches("h2", "h2"));
+    assert!(target_matches("h2", "h2::proto::streams"));
+    assert!(!target_matches("h2", "h2c"));
+    assert!(!target_matches("h2::proto", "h2"));
+    assert!(!target_matches("hyper", "my_app::hyper"));
+    // `*` matches any text
+    assert!(target_matches("hyper*", "hyper_util::client"));
+    assert!(target_matches("hyper*", "hyper"));
+    assert!(!target_matches("hyper*", "my_hyper"));
+    assert!(target_matches("*::pool", "hyper::client::pool"));
+    assert!(!target_matches("*::pool", "hyper::pool::conn"));
+    assert!(target_matches("a*b*c", "a_b_c"));
+    assert!(!target_matches("a*b*c", "a_c"));
+    assert!(target_matches("*", "anything"));
+}
+
+#[test]
+fn test_allow_and_deny() {
+    let everything = TargetFilter::default();
+    assert!(everything.is_enabled("h2"));
+    let deny = TargetFilter::default().deny("h2").deny("hyper*");
+    assert!(!deny.is_enabled("h2::codec"));
+    assert!(!deny.is_enabled("hyper_util"));
+    assert!(deny.is_enabled("my_app"));
+    let allow = TargetFilter::default().allow("my_app").deny("my_app::noisy");
+    assert!(allow.is_enabled("my_app::db"));
+    assert!(!allow.is_enabled("my_app::noisy::loop"));
+    assert!(!allow.is_enabled("tokio"));
+}
+
+#[test]
+fn test_filtered_spans_are_dropped() {
+    let storage = Arc::new(Recorder::default());
+    let filter = TargetFilter::default().deny("h2");
+    let layer = TreeLayer::from_storage(storage.clone()).with_target_filter(filter);
+    let lifecycle = layer.lifecycle();
+    let subscriber = Registry::default().with(LevelFilter::TRACE).with(layer);
+    {
+        let _guard = subscriber.set_default();
+        let request = info_span!("request");
+        request.in_scope(|| {
+            let conn = info_span!(target: "h2::proto", "connection");
+            conn.in_scope(|| {
+                info!(target: "h2::proto", "frame");
+                // kept, and attached to `request`, the closest kept ancestor
+                let handler = info_span!("handler");
+                handler.in_scope(|| info!("handled"));
+            });
+        });
+        info!(target: "h2c", "not h2");
+    }
+    let recorded = storage.0.lock().unwrap().clone();
+    let ids: Vec<_> = recorded.iter().map(|(id, parent, _)| (*id, *parent)).collect();
+    // dropped spans and events don't use up ids
+    assert_eq!(ids, [(1, 0), (2, 1), (3, 2), (4, 0)]);
+    assert_eq!((recorded[0].2, recorded[1].2), ("request", "handler"));
+    let report = lifecycle.report();
+    assert_eq!((report.created, report.never_closed, report.closed_without_creation), (2, 0, 0));
+}
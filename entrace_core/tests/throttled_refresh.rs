@@ -0,0 +1,54 @@
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use entrace_core::remote::{Refresh, ThrottledRefresher};
+
+#[derive(Debug, PartialEq)]
+enum Call {
+    Now,
+    After,
+}
+
+#[derive(Clone, Default)]
+struct Recorder(Rc<RefCell<Vec<Call>>>);
+impl Refresh for Recorder {
+    fn refresh(&self) {
+        self.0.borrow_mut().push(Call::Now);
+    }
+    fn refresh_after(&self, _delay: Duration) {
+        self.0.borrow_mut().push(Call::After);
+    }
+}
+
+#[test]
+fn test_refreshes_are_coalesced() {
+    let recorder = Recorder::default();
+    let throttled = ThrottledRefresher::new(recorder.clone(), Duration::from_secs(3600));
+    for _ in 0..1000 {
+        throttled.refresh();
+    }
+    // the first one goes through, the rest is deferred once to the end of the window
+    assert_eq!(*recorder.0.borrow(), [Call::Now, Call::After]);
+}
+
+#[test]
+fn test_refresh_after_window() {
+    let recorder = Recorder::default();
+    let window = Duration::from_millis(20);
+    let throttled = ThrottledRefresher::new(recorder.clone(), window);
+    throttled.refresh();
+    throttled.refresh();
+    std::thread::sleep(window * 2);
+    throttled.refresh();
+    throttled.refresh();
+    assert_eq!(*recorder.0.borrow(), [Call::Now, Call::After, Call::Now, Call::After]);
+}
+
+#[test]
+fn test_zero_window_forwards_everything() {
+    let recorder = Recorder::default();
+    let throttled = ThrottledRefresher::new(recorder.clone(), Duration::ZERO);
+    for _ in 0..3 {
+        throttled.refresh();
+    }
+    assert_eq!(*recorder.0.borrow(), [Call::Now, Call::Now, Call::Now]);
+}
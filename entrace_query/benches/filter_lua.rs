@@ -0,0 +1,48 @@
+//! Compare filtering with a Lua predicate against the same native filter.
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+
+use divan::{Bencher, black_box};
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+use entrace_query::lua_api::{JoinCtx, LuaEvalState, setup_lua_no_lock};
+
+fn main() {
+    divan::main();
+}
+
+const SPANS: u32 = 100_000;
+
+fn setup() -> mlua::Lua {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..SPANS as u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i % 10)).add();
+    }
+    let mut lua = mlua::Lua::new();
+    let join_ctx = Arc::new(JoinCtx::from_thread_count(1));
+    let state = LuaEvalState::new(join_ctx, 0..=SPANS, Rc::new(RefCell::new(HashMap::new())));
+    setup_lua_no_lock(&mut lua, Arc::new(builder.build_impl()), state).unwrap();
+    lua
+}
+
+fn bench_query(bencher: Bencher, query: &str) {
+    let lua = setup();
+    let query = query.replace("SPANS", &SPANS.to_string());
+    bencher.bench_local(|| black_box(lua.load(&query).eval::<Vec<u32>>().unwrap()));
+}
+
+#[divan::bench]
+fn native(bencher: Bencher) {
+    bench_query(
+        bencher,
+        r#"return en_filterset_materialize(en_filter("i", "EQ", 0, en_filterset_from_range(1, SPANS)))"#,
+    );
+}
+
+#[divan::bench]
+fn lua_predicate(bencher: Bencher) {
+    bench_query(
+        bencher,
+        r#"return en_filter_lua(en_filterset_from_range(1, SPANS), function(id)
+            return en_attr_by_name(id, "i") == 0
+        end)"#,
+    );
+}
@@ -0,0 +1,130 @@
+//! The filterset engine without the GUI: normalizing, materializing and the scans of [EnMatcher]
+//! over synthetic traces of a few sizes, and the single-predicate `en_where_limit` against the
+//! full filterset path.
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, rc::Rc, sync::Arc};
+
+use croaring::Bitmap as Roaring;
+use divan::{Bencher, black_box};
+use entrace_core::{EnValue, LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::{Evaluator, Filterset, FiltersetId, Matcher, Predicate},
+    lua_api::{EnMatcher, JoinCtx, LuaEvalState, setup_lua_no_lock},
+};
+
+fn main() {
+    divan::main();
+}
+
+const SIZES: [u32; 3] = [1_000, 10_000, 100_000];
+
+/// `spans` spans with the attributes `i = n % 10` and `j = n % 7`.
+fn trace(spans: u32) -> LogProviderImpl {
+    let mut builder = LogProviderBuilder::new();
+    for n in 0..spans as u64 {
+        builder
+            .span(0, "span")
+            .attr("i", EnValue::U64(n % 10))
+            .attr("j", EnValue::U64(n % 7))
+            .add();
+    }
+    builder.build_impl()
+}
+fn eq(attr: &str, value: u64) -> Predicate<EnValue> {
+    Predicate::new(attr, Ordering::Equal, EnValue::U64(value))
+}
+/// `or(and(filter(i == k, src), filter(j == k % 7, src)) for k in 0..width)`, which normalizing
+/// merges into a single filter with `width` clauses.
+fn query(spans: u32, width: u64) -> (Evaluator<EnValue>, FiltersetId) {
+    let mut evaluator = Evaluator::new(spans + 1);
+    let src = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(1..spans + 1)));
+    let ands = (0..width).map(|k| {
+        let i = evaluator.new_dnf(vec![vec![eq("i", k % 10)]], src);
+        let j = evaluator.new_dnf(vec![vec![eq("j", k % 7)]], src);
+        evaluator.new_filterset(Filterset::And([i, j].into_iter().collect()))
+    });
+    let ands = ands.collect();
+    let root = evaluator.new_filterset(Filterset::Or(ands));
+    (evaluator, root)
+}
+
+#[divan::bench(args = [4, 16, 64])]
+fn normalize(bencher: Bencher, width: u64) {
+    bencher
+        .with_inputs(|| query(1000, width))
+        .bench_local_values(|(mut evaluator, root)| evaluator.normalize(root));
+}
+
+#[divan::bench(args = SIZES)]
+fn materialize(bencher: Bencher, spans: u32) {
+    let log = trace(spans);
+    bencher
+        .with_inputs(|| {
+            let (mut evaluator, root) = query(spans, 8);
+            evaluator.normalize(root);
+            (evaluator, root)
+        })
+        .bench_local_values(|(mut evaluator, root)| {
+            evaluator.materialize(&EnMatcher::new(&log), root).unwrap();
+            evaluator
+        });
+}
+
+#[divan::bench(args = SIZES)]
+fn materialize_unnormalized(bencher: Bencher, spans: u32) {
+    let log = trace(spans);
+    bencher.with_inputs(|| query(spans, 8)).bench_local_values(|(mut evaluator, root)| {
+        evaluator.materialize(&EnMatcher::new(&log), root).unwrap();
+        evaluator
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn scan_predicate(bencher: Bencher, spans: u32) {
+    let (log, predicate) = (trace(spans), eq("i", 0));
+    let input = Roaring::from_range(1..spans + 1);
+    bencher.bench_local(|| black_box(EnMatcher::new(&log).subset_matching(&predicate, &input)));
+}
+
+#[divan::bench(args = SIZES)]
+fn scan_dnf(bencher: Bencher, spans: u32) {
+    let log = trace(spans);
+    let clauses: Vec<Vec<_>> = (0..4).map(|k| vec![eq("i", k), eq("j", k)]).collect();
+    let input = Roaring::from_range(1..spans + 1);
+    bencher.bench_local(|| {
+        let clauses = clauses.iter().map(|x| x.iter());
+        black_box(EnMatcher::new(&log).subset_matching_dnf(clauses, &input))
+    });
+}
+
+fn lua(spans: u32) -> mlua::Lua {
+    let mut lua = mlua::Lua::new();
+    let join_ctx = Arc::new(JoinCtx::from_thread_count(1));
+    let state = LuaEvalState::new(join_ctx, 0..=spans, Rc::new(RefCell::new(HashMap::new())));
+    setup_lua_no_lock(&mut lua, Arc::new(trace(spans)), state).unwrap();
+    lua
+}
+fn bench_query(bencher: Bencher, spans: u32, query: &str) {
+    let lua = lua(spans);
+    let query = query.replace("SPANS", &spans.to_string());
+    bencher.bench_local(|| black_box(lua.load(&query).eval::<Vec<u32>>().unwrap()));
+}
+
+/// Every match, so this compares the scans rather than stopping early.
+#[divan::bench(args = SIZES)]
+fn where_single_predicate(bencher: Bencher, spans: u32) {
+    bench_query(
+        bencher,
+        spans,
+        r#"return en_where_limit(en_filterset_from_range(1, SPANS),
+            { target = "i", relation = "EQ", value = 0 }, SPANS)"#,
+    );
+}
+
+#[divan::bench(args = SIZES)]
+fn filterset_single_predicate(bencher: Bencher, spans: u32) {
+    bench_query(
+        bencher,
+        spans,
+        r#"return en_filterset_materialize(en_filter("i", "EQ", 0, en_filterset_from_range(1, SPANS)))"#,
+    );
+}
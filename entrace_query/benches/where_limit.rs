@@ -0,0 +1,50 @@
+//! Compare stopping at the first `n` matches with materializing every match.
+use std::cmp::Ordering;
+
+use croaring::Bitmap as Roaring;
+use divan::{Bencher, black_box};
+use entrace_core::{EnValue, LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::{Evaluator, Filterset, Predicate},
+    lua_api::EnMatcher,
+};
+
+fn main() {
+    divan::main();
+}
+
+const SPANS: u32 = 1_000_000;
+
+fn setup() -> LogProviderImpl {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..SPANS as u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i % 10)).add();
+    }
+    builder.build_impl()
+}
+/// Matches every tenth span.
+fn predicate() -> Predicate<EnValue> {
+    Predicate::new("i", Ordering::Equal, EnValue::U64(0))
+}
+
+#[divan::bench(args = [10, 100, 1000])]
+fn where_limit(bencher: Bencher, n: usize) {
+    let (log, predicate) = (setup(), predicate());
+    let source = Roaring::from_range(1..SPANS + 1);
+    bencher.bench_local(|| {
+        let matcher = EnMatcher::new(&log);
+        black_box(matcher.first_matching(&predicate, &source, n).unwrap())
+    });
+}
+
+#[divan::bench(args = [10, 100, 1000])]
+fn materialize_then_take(bencher: Bencher, n: usize) {
+    let log = setup();
+    bencher.bench_local(|| {
+        let mut evaluator = Evaluator::new(SPANS + 1);
+        let src = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(1..SPANS + 1)));
+        let root = evaluator.new_dnf(vec![vec![predicate()]], src);
+        evaluator.materialize(&EnMatcher::new(&log), root).unwrap();
+        black_box(evaluator.results[&root].iter().take(n).collect::<Vec<_>>())
+    });
+}
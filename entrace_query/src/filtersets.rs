@@ -1,23 +1,62 @@
-use croaring::Bitmap as Roaring;
+use croaring::{Bitmap as Roaring, Portable};
 use itertools::Itertools;
 use std::collections::HashMap;
-use std::fmt::{Debug, Write};
+use std::fmt::{Debug, Display, Write};
 use std::{
     cmp::Ordering,
     collections::{HashSet, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering as AtomicOrdering},
 };
+use tracing::warn;
 
 pub type FiltersetId = usize;
 pub type PredicateId = usize;
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum EvaluatorError {
+    /// A rewrite left a reference to a filterset it removed. This is always a bug in the
+    /// evaluator, [Evaluator::dot] shows the filtersets to find it.
+    #[error(
+        "Internal filterset bug: tried to materialize #{0}, which was removed while simplifying the \
+         filterset. This is an error in entrace, and not in your query."
+    )]
+    MaterializedDead(FiltersetId),
+    /// A result written by [Evaluator::with_spill] could not be read back, like when the spill
+    /// directory was cleaned up during the query.
+    #[error("Failed to read the spilled result of filterset #{0} from {1}: {2}")]
+    SpillRead(FiltersetId, PathBuf, String),
+}
+/// How a [Predicate] relates an attribute to its constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Relation {
+    /// The attribute is present, and compares to the constant like this.
+    Cmp(Ordering),
+    /// The attribute is present, whatever its value. The constant is ignored.
+    Exists,
+    /// The attribute is a string matching the constant as a regex. Only supported for the string
+    /// fields of the metadata, see [crate::lua_api::REGEX_META_FIELDS].
+    Regex,
+    /// The attribute is present, and equal to one of [Predicate::members]. The constant is
+    /// ignored.
+    In,
+}
+impl From<Ordering> for Relation {
+    fn from(value: Ordering) -> Self {
+        Relation::Cmp(value)
+    }
+}
 #[derive(Debug)]
 pub struct Predicate<T> {
     pub attr: String,
-    pub rel: Ordering,
+    pub rel: Relation,
     pub constant: T,
+    /// The constants of a [Relation::In] predicate, empty for the other relations.
+    pub members: Vec<T>,
 }
 impl<T> Predicate<T> {
-    pub fn new(attrname: impl ToString, rel: Ordering, constant: T) -> Self {
-        Self { attr: attrname.to_string(), rel, constant }
+    pub fn new(attrname: impl ToString, rel: impl Into<Relation>, constant: T) -> Self {
+        Self { attr: attrname.to_string(), rel: rel.into(), constant, members: vec![] }
     }
 }
 #[derive(Debug)]
@@ -29,6 +68,8 @@ pub enum Filterset {
     And(HashSet<FiltersetId>),
     Or(HashSet<FiltersetId>),
     Invert(FiltersetId, FiltersetId),
+    /// The ids in an odd number of the items. A list, as an item twice cancels out.
+    Xor(Vec<FiltersetId>),
 }
 impl Filterset {
     pub fn children(&self) -> ChildrenIter<'_> {
@@ -37,15 +78,62 @@ impl Filterset {
             Filterset::BlackBox(a) | Filterset::RelDnf(_, a) => ChildrenIter::One(*a),
             Filterset::Invert(a, u) => ChildrenIter::Two(*a, *u),
             Filterset::And(i) | Filterset::Or(i) => ChildrenIter::Many(i.iter()),
+            Filterset::Xor(i) => ChildrenIter::List(i.iter()),
         }
     }
 }
+/// A filterset without its ids in an [Evaluator], see [Evaluator::canonical]. Equal canonical
+/// filtersets have equal results on the same trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CanonicalFilterset {
+    Dead,
+    Primitive(Roaring),
+    BlackBox(Box<CanonicalFilterset>),
+    RelDnf(Vec<Vec<CanonicalPredicate>>, Box<CanonicalFilterset>),
+    And(Vec<CanonicalFilterset>),
+    Or(Vec<CanonicalFilterset>),
+    Invert(Box<CanonicalFilterset>, Box<CanonicalFilterset>),
+    Xor(Vec<CanonicalFilterset>),
+}
+/// Primitives only hash their size and bounds, as hashing every id of a huge range is slow.
+/// Equality compares them fully.
+impl Hash for CanonicalFilterset {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            CanonicalFilterset::Dead => (),
+            CanonicalFilterset::Primitive(r) => {
+                (r.cardinality(), r.minimum(), r.maximum()).hash(state)
+            }
+            CanonicalFilterset::BlackBox(src) => src.hash(state),
+            CanonicalFilterset::RelDnf(clauses, src) => (clauses, src).hash(state),
+            CanonicalFilterset::And(items)
+            | CanonicalFilterset::Or(items)
+            | CanonicalFilterset::Xor(items) => items.hash(state),
+            CanonicalFilterset::Invert(src, universe) => (src, universe).hash(state),
+        }
+    }
+}
+/// A [Predicate] with its constants as their [Debug] output, see [CanonicalFilterset].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CanonicalPredicate {
+    pub attr: String,
+    pub rel: Relation,
+    pub constant: String,
+    pub members: String,
+}
+fn hash_of(x: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    x.hash(&mut hasher);
+    hasher.finish()
+}
 #[derive(Debug)]
 pub enum RewriteAction {
     None,
     // Pointer of outer and, and the "and" items in its list
     CompressAnd(FiltersetId, Vec<FiltersetId>),
     CompressOr(FiltersetId, Vec<FiltersetId>),
+    CompressXor(FiltersetId, Vec<FiltersetId>),
     EliminateNotNot(FiltersetId, FiltersetId, FiltersetId),
     /// Outer DNF, inner DNF, inner DNF source
     DnfDnf(FiltersetId, FiltersetId, FiltersetId),
@@ -54,6 +142,65 @@ pub enum RewriteAction {
     /// Or([A]) -> A
     EliminateSingleOr(FiltersetId),
     EliminateSingleAnd(FiltersetId),
+    EliminateSingleXor(FiltersetId),
+}
+impl Display for RewriteAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn merges(
+            f: &mut std::fmt::Formatter<'_>, by_source: &HashMap<usize, Vec<usize>>,
+        ) -> std::fmt::Result {
+            let mut groups: Vec<(usize, Vec<usize>)> = by_source
+                .iter()
+                .filter(|(_, dnfs)| dnfs.len() > 1)
+                .map(|(src, dnfs)| (*src, dnfs.iter().copied().sorted().collect()))
+                .collect();
+            groups.sort();
+            for (idx, (src, dnfs)) in groups.iter().enumerate() {
+                let sep = if idx == 0 { "" } else { "," };
+                write!(f, "{sep} {} on #{src}", dnfs.iter().map(|x| format!("#{x}")).join(" + "))?;
+            }
+            Ok(())
+        }
+        let ids = |x: &[usize]| x.iter().map(|x| format!("#{x}")).join(", ");
+        match self {
+            RewriteAction::None => write!(f, "nothing to rewrite"),
+            RewriteAction::CompressAnd(id, ands) => {
+                write!(f, "flatten the nested intersections {} into #{id}", ids(ands))
+            }
+            RewriteAction::CompressOr(id, ors) => {
+                write!(f, "flatten the nested unions {} into #{id}", ids(ors))
+            }
+            RewriteAction::CompressXor(id, xors) => {
+                write!(f, "flatten the nested symmetric differences {} into #{id}", ids(xors))
+            }
+            RewriteAction::EliminateNotNot(outer, inner, src) => {
+                write!(f, "remove the double inversion #{outer} of #{inner}, leaving #{src}")
+            }
+            RewriteAction::DnfDnf(outer, inner, src) => {
+                write!(
+                    f,
+                    "combine the filter #{outer} with the filter #{inner} it refines, on #{src}"
+                )
+            }
+            RewriteAction::MergeDnfsInOr(id, by_source) => {
+                write!(f, "in the union #{id}, merge the filters")?;
+                merges(f, by_source)
+            }
+            RewriteAction::MergeDnfsInAnd(id, by_source) => {
+                write!(f, "in the intersection #{id}, merge the filters")?;
+                merges(f, by_source)
+            }
+            RewriteAction::EliminateSingleOr(id) => {
+                write!(f, "replace the union #{id} with its only item")
+            }
+            RewriteAction::EliminateSingleAnd(id) => {
+                write!(f, "replace the intersection #{id} with its only item")
+            }
+            RewriteAction::EliminateSingleXor(id) => {
+                write!(f, "replace the symmetric difference #{id} with its only item")
+            }
+        }
+    }
 }
 
 pub enum ChildrenIter<'a> {
@@ -61,6 +208,7 @@ pub enum ChildrenIter<'a> {
     One(FiltersetId),
     Two(FiltersetId, FiltersetId),
     Many(std::collections::hash_set::Iter<'a, FiltersetId>),
+    List(std::slice::Iter<'a, FiltersetId>),
 }
 impl<'a> Iterator for ChildrenIter<'a> {
     type Item = FiltersetId;
@@ -81,6 +229,13 @@ impl<'a> Iterator for ChildrenIter<'a> {
                 }
                 next_item.copied()
             }
+            ChildrenIter::List(mut iter) => {
+                let next_item = iter.next();
+                if next_item.is_some() {
+                    *self = ChildrenIter::List(iter);
+                }
+                next_item.copied()
+            }
         }
     }
 }
@@ -90,13 +245,102 @@ const DNFS_IN_AND_MERGE_MAX_CLAUSES: usize = MAX_DNF_CLAUSES / 2;
 pub struct Evaluator<T> {
     pool: Vec<Filterset>,
     pub predicates: Vec<Predicate<T>>,
+    /// The results in memory. With [Evaluator::with_spill], some are on disk instead, see
+    /// [Evaluator::result].
     pub results: HashMap<FiltersetId, Roaring>,
-    /// needed for not to function correctly
+    /// The number of spans in the trace. Inversions are clamped to ids below this.
     pub nitems: u32,
+    spill: Option<Spill>,
 }
+/// Where results are spilled to, and what is there. See [Evaluator::with_spill].
+struct Spill {
+    max_bytes: usize,
+    dir: PathBuf,
+    /// Unique per evaluator, so the files of concurrent queries don't clash.
+    prefix: String,
+    files: HashMap<FiltersetId, PathBuf>,
+    /// [Evaluator::result_sizes] of the spilled results.
+    sizes: HashMap<FiltersetId, u64>,
+}
+impl Spill {
+    fn path(&self, id: FiltersetId) -> PathBuf {
+        self.dir.join(format!("{}-{id}.roaring", self.prefix))
+    }
+}
+static SPILL_COUNTER: AtomicU64 = AtomicU64::new(0);
 impl<T> Evaluator<T> {
     pub fn new(nitems: u32) -> Self {
-        Self { pool: vec![], predicates: vec![], results: HashMap::new(), nitems }
+        Self { pool: vec![], predicates: vec![], results: HashMap::new(), nitems, spill: None }
+    }
+    /// Keep the results in memory under about `max_bytes` while materializing, by writing the
+    /// largest ones to files in `dir` (like [std::env::temp_dir]), and reading them back when they
+    /// are needed again. The size of a result is its serialized size, which is close to the memory
+    /// it takes.
+    ///
+    /// This trades latency for memory: every spilled result is written and read back once more,
+    /// which is slow compared to the bitmap operations themselves. Only use it for huge traces
+    /// with many intermediate filtersets, where the results wouldn't fit in memory otherwise.
+    ///
+    /// The result of the materialized filterset is always kept in memory, the spilled ones can be
+    /// read with [Evaluator::result]. Spill files are removed when the evaluator is dropped.
+    pub fn with_spill(mut self, max_bytes: usize, dir: PathBuf) -> Self {
+        let n = SPILL_COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+        let prefix = format!("entrace-filterset-{}-{n}", std::process::id());
+        let (files, sizes) = (HashMap::new(), HashMap::new());
+        self.spill = Some(Spill { max_bytes, dir, prefix, files, sizes });
+        self
+    }
+    /// The ids of the results which are spilled to disk right now.
+    pub fn spilled(&self) -> Vec<FiltersetId> {
+        self.spill.as_ref().map(|x| x.files.keys().copied().sorted().collect()).unwrap_or_default()
+    }
+    /// The result of `id`, read back from disk if it was spilled. `None` if it wasn't
+    /// materialized.
+    pub fn result(&mut self, id: FiltersetId) -> Result<Option<&Roaring>, EvaluatorError> {
+        self.load(id)?;
+        Ok(self.results.get(&id))
+    }
+    /// Read the result of `id` back into [Evaluator::results] if it was spilled.
+    fn load(&mut self, id: FiltersetId) -> Result<(), EvaluatorError> {
+        let Some(spill) = &mut self.spill else { return Ok(()) };
+        let Some(path) = spill.files.remove(&id) else { return Ok(()) };
+        spill.sizes.remove(&id);
+        let read_error = |e: String| EvaluatorError::SpillRead(id, path.clone(), e);
+        let bytes = std::fs::read(&path).map_err(|e| read_error(e.to_string()))?;
+        let result = Roaring::try_deserialize::<Portable>(&bytes)
+            .ok_or_else(|| read_error("not a serialized bitmap".into()))?;
+        std::fs::remove_file(&path).ok();
+        self.results.insert(id, result);
+        Ok(())
+    }
+    /// Write the largest results other than `keep` to disk, until the rest fit in the limit.
+    fn spill_over_limit(&mut self, keep: FiltersetId) {
+        let Some(spill) = &mut self.spill else { return };
+        let mut sizes: Vec<(usize, FiltersetId)> = self
+            .results
+            .iter()
+            .map(|(id, r)| (r.get_serialized_size_in_bytes::<Portable>(), *id))
+            .collect();
+        let mut total: usize = sizes.iter().map(|x| x.0).sum();
+        sizes.sort_unstable();
+        while total > spill.max_bytes {
+            let Some((size, id)) = sizes.pop() else { break };
+            if id == keep {
+                continue;
+            }
+            let path = spill.path(id);
+            let result = &self.results[&id];
+            let bytes = result.serialize::<Portable>();
+            if let Err(e) = std::fs::write(&path, bytes) {
+                // keep going in memory, that is still correct
+                warn!(path = %path.display(), "Failed to spill filterset result: {e}");
+                return;
+            }
+            spill.sizes.insert(id, result.range_cardinality(..self.nitems));
+            self.results.remove(&id);
+            spill.files.insert(id, path);
+            total -= size;
+        }
     }
     pub fn is_and(&self, id: FiltersetId) -> bool {
         matches!(self.pool[id], Filterset::And(_))
@@ -104,6 +348,9 @@ impl<T> Evaluator<T> {
     pub fn is_or(&self, id: FiltersetId) -> bool {
         matches!(self.pool[id], Filterset::Or(_))
     }
+    pub fn is_xor(&self, id: FiltersetId) -> bool {
+        matches!(self.pool[id], Filterset::Xor(_))
+    }
     pub fn is_dnf(&self, id: FiltersetId) -> bool {
         matches!(self.pool[id], Filterset::RelDnf(..))
     }
@@ -199,6 +446,15 @@ impl<T> Evaluator<T> {
                 }
             }
 
+            Filterset::Xor(items) => {
+                if items.len() == 1 {
+                    return RewriteAction::EliminateSingleXor(id);
+                }
+                let xors: Vec<usize> = items.iter().copied().filter(|x| self.is_xor(*x)).collect();
+                if !xors.is_empty() {
+                    return RewriteAction::CompressXor(id, xors);
+                }
+            }
             Filterset::Invert(y, u) => {
                 if let Filterset::Invert(q, u2) = &self.pool[*y]
                     && u == u2
@@ -264,6 +520,30 @@ impl<T> Evaluator<T> {
                 }
                 self.pool[*id] = Filterset::Or(items);
             }
+            RewriteAction::CompressXor(id, inner_xors) => {
+                let Filterset::Xor(items) = std::mem::replace(&mut self.pool[*id], Filterset::Dead)
+                else {
+                    unreachable!()
+                };
+                let mut inner = HashMap::new();
+                for ptr in inner_xors {
+                    if let Filterset::Xor(others) =
+                        std::mem::replace(&mut self.pool[*ptr], Filterset::Dead)
+                    {
+                        inner.insert(*ptr, others);
+                    }
+                }
+                // splice the inner lists in place. An item has to stay as often as it was, as
+                // pairs cancel out.
+                let mut flat = Vec::with_capacity(items.len());
+                for item in items {
+                    match inner.get(&item) {
+                        Some(others) => flat.extend(others.iter().copied()),
+                        None => flat.push(item),
+                    }
+                }
+                self.pool[*id] = Filterset::Xor(flat);
+            }
             RewriteAction::EliminateSingleOr(id) => {
                 let Filterset::Or(srcs) = std::mem::replace(&mut self.pool[*id], Filterset::Dead)
                 else {
@@ -278,6 +558,13 @@ impl<T> Evaluator<T> {
                 };
                 self.pool.swap(*id, *srcs.iter().next().unwrap());
             }
+            RewriteAction::EliminateSingleXor(id) => {
+                let Filterset::Xor(srcs) = std::mem::replace(&mut self.pool[*id], Filterset::Dead)
+                else {
+                    unreachable!()
+                };
+                self.pool.swap(*id, srcs[0]);
+            }
             RewriteAction::EliminateNotNot(not1p, not2p, innerp) => {
                 self.pool[*not1p] = std::mem::replace(&mut self.pool[*innerp], Filterset::Dead);
                 self.pool[*not2p] = Filterset::Dead;
@@ -384,6 +671,18 @@ impl<T> Evaluator<T> {
     }
 
     pub fn normalize(&mut self, root: FiltersetId) {
+        self.normalize_with(root, |_| ());
+    }
+    /// Like [Evaluator::normalize], but returns the rewrite actions that were applied, in order.
+    ///
+    /// This still rewrites the filterset. To only look at what would happen, call it on a
+    /// separate evaluator.
+    pub fn explain(&mut self, root: FiltersetId) -> Vec<RewriteAction> {
+        let mut actions = vec![];
+        self.normalize_with(root, |x| actions.push(x));
+        actions
+    }
+    fn normalize_with(&mut self, root: FiltersetId, mut on_action: impl FnMut(RewriteAction)) {
         if !self.results.is_empty() {
             panic!("Normalizing after there are results is unsafe");
         }
@@ -393,12 +692,17 @@ impl<T> Evaluator<T> {
 
         pub fn inner<T>(
             this: &mut Evaluator<T>, x: FiltersetId, worklist: &mut VecDeque<FiltersetId>,
-            parent_of: &[usize], root: FiltersetId,
+            parent_of: &[usize], root: FiltersetId, on_action: &mut impl FnMut(RewriteAction),
         ) {
             // reach a local fixpoint before queuing parent
             let mut any_action = false;
-            while !matches!(this.rewrite_one(x), RewriteAction::None) {
+            loop {
+                let action = this.rewrite_one(x);
+                if matches!(action, RewriteAction::None) {
+                    break;
+                }
                 any_action = true;
+                on_action(action);
             }
             if any_action && x != root {
                 let parent = parent_of[x];
@@ -412,15 +716,21 @@ impl<T> Evaluator<T> {
         // While there were children rewritten, rewrite the parents (so rewrite until there are no
         // changes left)
         while let Some(x) = worklist.pop_front() {
-            inner(self, x, &mut worklist, &parent_of, root);
+            inner(self, x, &mut worklist, &parent_of, root, &mut on_action);
         }
     }
 
     /// For good performance, you must normalize() first.
     /// Guarantees that `results[id]` will exist.
-    /// WARNING: because of how Not() is implemented, the Roaring in results[id] might contain ids
-    /// beyond the end of the actual data. Please clamp it to your actual data ID range.
-    pub fn materialize(&mut self, matcher: &impl Matcher<T>, id: FiltersetId) {
+    /// Inversions only keep ids below [Evaluator::nitems], but primitives are taken as they are, so
+    /// the Roaring in results[id] might contain ids beyond the end of the actual data if a
+    /// primitive does. Please clamp it to your actual data ID range.
+    ///
+    /// Fails if a filterset removed while normalizing is still used, in which case the results are
+    /// incomplete.
+    pub fn materialize(
+        &mut self, matcher: &impl Matcher<T>, id: FiltersetId,
+    ) -> Result<(), EvaluatorError> {
         let mut stack = vec![(id, false)];
         // "two-phase scheduling" algorithm. a node can either be "ready", meaning we can materialize it right
         // away, or "unready" which means we need to materialize its children first.
@@ -442,54 +752,176 @@ impl<T> Evaluator<T> {
                 continue;
             }
             // ready to materialize.
-            match &self.pool[node] {
-                Filterset::Dead => {
-                    eprintln!("Tried to materialize Dead. In the future, this may panic.");
-                    self.results.insert(node, Roaring::new());
-                }
-                Filterset::Primitive(bm) => {
-                    self.results.insert(node, bm.clone());
-                }
-                Filterset::BlackBox(src) => {
-                    let source_result = &self.results[src];
-                    self.results.insert(node, source_result.clone());
+            if self.spill.is_some() {
+                let children: Vec<_> = self.pool[node].children().collect();
+                for child in children {
+                    self.load(child)?;
                 }
-                // TODO: maybe we could speed this up?
-                Filterset::And(items) => {
-                    let mut it = items.iter().map(|x| &self.results[x]);
-                    let Some(mut r) = it.next().cloned() else { continue };
-                    for x in it {
-                        r.and_inplace(x)
-                    }
-                    self.results.insert(node, r);
+            }
+            self.materialize_node(matcher, node)?;
+            self.spill_over_limit(node);
+        }
+        Ok(())
+    }
+    /// Materialize `node`, whose children are already materialized and in memory.
+    fn materialize_node(
+        &mut self, matcher: &impl Matcher<T>, node: FiltersetId,
+    ) -> Result<(), EvaluatorError> {
+        match &self.pool[node] {
+            Filterset::Dead => return Err(EvaluatorError::MaterializedDead(node)),
+            Filterset::Primitive(bm) => {
+                self.results.insert(node, bm.clone());
+            }
+            Filterset::BlackBox(src) => {
+                let source_result = &self.results[src];
+                self.results.insert(node, source_result.clone());
+            }
+            // TODO: maybe we could speed this up?
+            Filterset::And(items) => {
+                let mut it = items.iter().map(|x| &self.results[x]);
+                let Some(mut r) = it.next().cloned() else { return Ok(()) };
+                for x in it {
+                    r.and_inplace(x)
                 }
-                Filterset::Or(items) => {
-                    let mut it = items.iter().map(|x| &self.results[x]);
-                    let Some(mut r) = it.next().cloned() else { continue };
-                    for x in it {
-                        r.or_inplace(x)
-                    }
-                    self.results.insert(node, r);
+                self.results.insert(node, r);
+            }
+            Filterset::Or(items) => {
+                let mut it = items.iter().map(|x| &self.results[x]);
+                let Some(mut r) = it.next().cloned() else { return Ok(()) };
+                for x in it {
+                    r.or_inplace(x)
                 }
-                Filterset::Invert(src, universe) => {
-                    let source_result = &self.results[src];
-                    let universe_result = &self.results[universe];
-                    self.results.insert(node, universe_result - source_result);
+                self.results.insert(node, r);
+            }
+            Filterset::Xor(items) => {
+                let mut it = items.iter().map(|x| &self.results[x]);
+                let Some(mut r) = it.next().cloned() else { return Ok(()) };
+                for x in it {
+                    r.xor_inplace(x)
                 }
-                Filterset::RelDnf(items, src) => {
-                    let this_result = matcher.subset_matching_dnf(
-                        items.iter().map(|x| x.iter().map(|y| &self.predicates[*y])),
-                        &self.results[src],
-                    );
+                self.results.insert(node, r);
+            }
+            Filterset::Invert(src, universe) => {
+                let source_result = &self.results[src];
+                let universe_result = &self.results[universe];
+                // the universe can be a range past the end of the trace, don't return ids which
+                // don't exist
+                let mut r = universe_result - source_result;
+                r.remove_range(self.nitems..);
+                self.results.insert(node, r);
+            }
+            Filterset::RelDnf(items, src) => {
+                let this_result = matcher.subset_matching_dnf(
+                    items.iter().map(|x| x.iter().map(|y| &self.predicates[*y])),
+                    &self.results[src],
+                );
 
-                    self.results.insert(node, this_result);
-                }
+                self.results.insert(node, this_result);
             }
         }
+        Ok(())
+    }
+}
+
+impl<T> Evaluator<T> {
+    /// The number of spans in the result of every materialized filterset, counting only ids below
+    /// [Evaluator::nitems].
+    ///
+    /// Comparing the sizes of the inputs and outputs of a filter shows how selective it is.
+    pub fn result_sizes(&self) -> HashMap<FiltersetId, u64> {
+        let spilled = self.spill.iter().flat_map(|x| x.sizes.iter().map(|(id, n)| (*id, *n)));
+        self.results
+            .iter()
+            .map(|(id, r)| (*id, r.range_cardinality(..self.nitems)))
+            .chain(spilled)
+            .collect()
+    }
+}
+impl<T> Drop for Evaluator<T> {
+    fn drop(&mut self) {
+        if let Some(spill) = &self.spill {
+            for path in spill.files.values() {
+                std::fs::remove_file(path).ok();
+            }
+        }
+    }
+}
+impl<T: Display> Evaluator<T> {
+    /// A short, human-readable description of filterset `id`, referring to others like `#3`.
+    pub fn describe(&self, id: FiltersetId) -> String {
+        let ids = |x: &HashSet<FiltersetId>| {
+            x.iter().copied().sorted().map(|x| format!("#{x}")).join(", ")
+        };
+        let list = |x: &[FiltersetId]| x.iter().map(|x| format!("#{x}")).join(", ");
+        match &self.pool[id] {
+            Filterset::Dead => "removed by normalization".into(),
+            Filterset::Primitive(r) => format!("list of {} ids", r.cardinality()),
+            Filterset::BlackBox(src) => format!("black box on #{src}"),
+            Filterset::RelDnf(clauses, src) => {
+                let clauses = clauses
+                    .iter()
+                    .map(|clause| clause.iter().map(|x| self.describe_predicate(*x)).join(" and "))
+                    .join(" or ");
+                format!("filter {clauses} on #{src}")
+            }
+            Filterset::And(items) => format!("intersection of {}", ids(items)),
+            Filterset::Or(items) => format!("union of {}", ids(items)),
+            Filterset::Invert(src, universe) => format!("#{universe} without #{src}"),
+            Filterset::Xor(items) => format!("symmetric difference of {}", list(items)),
+        }
+    }
+    fn describe_predicate(&self, id: PredicateId) -> String {
+        let Predicate { attr, rel, constant, members } = &self.predicates[id];
+        match rel {
+            Relation::Cmp(Ordering::Less) => format!("{attr} LT {constant}"),
+            Relation::Cmp(Ordering::Equal) => format!("{attr} EQ {constant}"),
+            Relation::Cmp(Ordering::Greater) => format!("{attr} GT {constant}"),
+            Relation::Exists => format!("{attr} EXISTS"),
+            Relation::Regex => format!("{attr} REGEX {constant}"),
+            Relation::In => format!("{attr} IN {{{}}}", members.iter().join(", ")),
+        }
     }
 }
 
 impl<T: Debug> Evaluator<T> {
+    /// What filterset `id` computes: its structure, predicates and primitive ids. The ids of
+    /// filtersets in the pool and the order of intersections, unions and clauses don't matter, so
+    /// equal filtersets built by separate evaluators are equal here too. Normalize first, so
+    /// different ways of writing a filterset are equal more often.
+    pub fn canonical(&self, id: FiltersetId) -> CanonicalFilterset {
+        use CanonicalFilterset as C;
+        let boxed = |x| Box::new(self.canonical(x));
+        let sorted = |id: FiltersetId| {
+            let mut items = self.pool[id].children().map(|x| self.canonical(x)).collect_vec();
+            items.sort_by_cached_key(hash_of);
+            items
+        };
+        match &self.pool[id] {
+            Filterset::Dead => C::Dead,
+            Filterset::Primitive(r) => C::Primitive(r.clone()),
+            Filterset::BlackBox(src) => C::BlackBox(boxed(*src)),
+            Filterset::RelDnf(clauses, src) => {
+                let clauses = clauses.iter().map(|clause| {
+                    clause.iter().map(|x| self.canonical_predicate(*x)).sorted().collect_vec()
+                });
+                C::RelDnf(clauses.sorted().collect(), boxed(*src))
+            }
+            Filterset::And(_) => C::And(sorted(id)),
+            Filterset::Or(_) => C::Or(sorted(id)),
+            // a xor has its items in a list, as their count matters, but not their order either
+            Filterset::Xor(_) => C::Xor(sorted(id)),
+            Filterset::Invert(src, universe) => C::Invert(boxed(*src), boxed(*universe)),
+        }
+    }
+    fn canonical_predicate(&self, id: PredicateId) -> CanonicalPredicate {
+        let Predicate { attr, rel, constant, members } = &self.predicates[id];
+        CanonicalPredicate {
+            attr: attr.clone(),
+            rel: *rel,
+            constant: format!("{constant:?}"),
+            members: format!("{members:?}"),
+        }
+    }
     /// Pretty-print the graph in GraphViz .dot
     pub fn dot(&mut self, root: FiltersetId) -> String {
         let mut out = String::from("digraph D {\n");
@@ -2,6 +2,8 @@ pub mod filtersets;
 pub mod lua_api;
 pub mod lua_value;
 
+use std::{fmt::Display, ops::RangeInclusive, time::Duration};
+
 #[derive(thiserror::Error, Debug, Clone)]
 pub enum QueryError {
     #[error("Index out of bounds. Tried to access element {index} of a container of size {actual}")]
@@ -14,12 +16,68 @@ pub enum QueryError {
     #[error("Error while running your query")]
     LuaError(#[source] mlua::Error),
     #[error(
-        "Failed to coerce the result of your query to Vec<u32>. The query should return a list of \
-         integers, or a filterset!"
+        "Failed to coerce the result of your query to Vec<u32>. The query should return a span \
+         id, a list of span ids, or a filterset!"
     )]
     FailedToCoerce,
     #[error("The filterset returned by your query failed to evaluate")]
     FiltersetEvalFail(#[source] mlua::Error),
+    #[error("Error on spans {}..={} (query thread {thread})", range.start(), range.end())]
+    InRange {
+        thread: u32,
+        range: RangeInclusive<u32>,
+        #[source]
+        source: Box<QueryError>,
+    },
+}
+impl QueryError {
+    /// Attach the span range (and the index of the thread evaluating it) to an error,
+    /// so errors of multi-threaded queries can be traced back to the partition that produced them.
+    pub fn in_range(self, thread: u32, range: RangeInclusive<u32>) -> Self {
+        QueryError::InRange { thread, range, source: Box::new(self) }
+    }
+}
+
+/// How much work a query did, to help find out why a query is slow.
+///
+/// Filled in by materializing filtersets, see [lua_api::materialize_filterset]. For multi-threaded
+/// queries, the stats of each thread are [merged](QueryStats::merge), so the phase durations are
+/// the sum of time spent on all threads, not wall time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Spans tested against a filter.
+    pub spans_scanned: u64,
+    /// Predicates evaluated on these spans. At least one per scanned span.
+    pub predicates_evaluated: u64,
+    /// Time spent simplifying filtersets before materializing them.
+    pub normalize: Duration,
+    /// Time spent matching spans against filtersets.
+    pub materialize: Duration,
+    /// Time spent combining the results of query threads.
+    pub reconcile: Duration,
+}
+impl QueryStats {
+    pub fn merge(&mut self, other: &QueryStats) {
+        self.spans_scanned += other.spans_scanned;
+        self.predicates_evaluated += other.predicates_evaluated;
+        self.normalize += other.normalize;
+        self.materialize += other.materialize;
+        self.reconcile += other.reconcile;
+    }
+}
+impl Display for QueryStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} spans scanned, {} predicates evaluated. normalize: {:?}, materialize: {:?}, \
+             reconcile: {:?}",
+            self.spans_scanned,
+            self.predicates_evaluated,
+            self.normalize,
+            self.materialize,
+            self.reconcile
+        )
+    }
 }
 
 pub mod lua_api_docs {
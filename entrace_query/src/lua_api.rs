@@ -1,31 +1,34 @@
 use std::fmt::Write;
 use std::{
-    cell::RefCell,
+    any::Any,
+    cell::{Cell, OnceCell, RefCell},
     cmp::Ordering,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     error::Error,
     ops::RangeInclusive,
+    path::PathBuf,
     rc::Rc,
     sync::{
-        Arc, RwLock,
+        Arc, RwLock, Weak,
         atomic::{self, AtomicBool, AtomicUsize},
     },
     time::Instant,
 };
 
 use crate::{
-    QueryError,
-    filtersets::{Filterset, Matcher, Predicate, PredicateId},
+    QueryError, QueryStats,
+    filtersets::{CanonicalFilterset, Filterset, Matcher, Predicate, PredicateId, Relation},
     lua_value::LuaValueRefRef,
 };
 use anyhow::bail;
 use croaring::Bitmap as Roaring;
 use entrace_core::{
-    EnValue, EnValueRef, LevelContainer, LogProvider, LogProviderError, LogProviderImpl,
-    LogProviderResult, MetadataRefContainer,
+    AggregateOp, EnValue, EnValueRef, LevelContainer, LogProvider, LogProviderError,
+    LogProviderImpl, LogProviderResult, MetadataRefContainer, format_span_oneline,
+    resolve_span_path, span_path,
 };
 use memchr::memmem::Finder;
-use mlua::{ExternalError, IntoLua, Lua, MultiValue, Table, Value};
+use mlua::{ExternalError, FromLua, IntoLua, Lua, MultiValue, Table, Value};
 fn level_to_u8(level: &entrace_core::LevelContainer) -> u8 {
     match level {
         LevelContainer::Trace => 1,
@@ -83,11 +86,108 @@ pub fn en_child_cnt(tcc: &impl LogProvider) -> impl Fn(u32) -> Result<usize, Log
     move |id: u32| Ok(tcc.children(id)?.len())
 }
 
+#[doc = include_str!("../api-docs/en_parent.md")]
+pub fn en_parent(tcc: &impl LogProvider) -> impl Fn(u32) -> mlua::Result<u32> {
+    move |id: u32| {
+        if id as usize >= tcc.len() {
+            return Err(make_oob_error(id, tcc.len()));
+        }
+        tcc.parent(id).map_err(to_lua_err)
+    }
+}
+
+#[doc = include_str!("../api-docs/en_depth.md")]
+pub fn en_depth(tcc: &impl LogProvider) -> impl Fn(u32) -> mlua::Result<u32> {
+    move |id: u32| {
+        if id as usize >= tcc.len() {
+            return Err(make_oob_error(id, tcc.len()));
+        }
+        depth_of(tcc, id)
+    }
+}
+
+#[doc = include_str!("../api-docs/en_subtree.md")]
+pub fn en_subtree(tcc: &impl LogProvider) -> impl Fn(u32) -> mlua::Result<Vec<u32>> {
+    move |id: u32| {
+        if id as usize >= tcc.len() {
+            return Err(make_oob_error(id, tcc.len()));
+        }
+        subtree_of(tcc, id)
+    }
+}
+
+#[doc = include_str!("../api-docs/en_descendant_count.md")]
+pub fn en_descendant_count(tcc: &impl LogProvider) -> impl Fn(u32) -> mlua::Result<usize> {
+    move |id: u32| {
+        if id as usize >= tcc.len() {
+            return Err(make_oob_error(id, tcc.len()));
+        }
+        Ok(subtree_size_of(tcc, id)? as usize - 1)
+    }
+}
+
+#[doc = include_str!("../api-docs/en_ancestors.md")]
+pub fn en_ancestors(tcc: &impl LogProvider) -> impl Fn(u32) -> mlua::Result<Vec<u32>> {
+    move |id: u32| {
+        if id as usize >= tcc.len() {
+            return Err(make_oob_error(id, tcc.len()));
+        }
+        let mut path = vec![id];
+        let mut current = id;
+        while current != 0 {
+            // a well-formed trace reaches the root in fewer steps, this only guards against cycles
+            if path.len() >= tcc.len() {
+                return Err(no_root_error(id));
+            }
+            current = tcc.parent(current).map_err(to_lua_err)?;
+            path.push(current);
+        }
+        path.reverse();
+        Ok(path)
+    }
+}
+
+#[doc = include_str!("../api-docs/en_on_path_to.md")]
+pub fn en_on_path_to(tcc: &impl LogProvider) -> impl Fn(u32) -> mlua::Result<Vec<u32>> {
+    en_ancestors(tcc)
+}
+
+#[doc = include_str!("../api-docs/en_child_events.md")]
+pub fn en_child_events(
+    tcc: &impl LogProvider,
+) -> impl Fn(u32) -> Result<Vec<u32>, LogProviderError> {
+    move |id: u32| tcc.child_events(id)
+}
+
+#[doc = include_str!("../api-docs/en_child_spans.md")]
+pub fn en_child_spans(
+    tcc: &impl LogProvider,
+) -> impl Fn(u32) -> Result<Vec<u32>, LogProviderError> {
+    move |id: u32| tcc.child_spans(id)
+}
+
+#[doc = include_str!("../api-docs/en_follows_from.md")]
+pub fn en_follows_from(
+    tcc: &impl LogProvider,
+) -> impl Fn(u32) -> Result<Vec<u32>, LogProviderError> {
+    move |id: u32| tcc.follows_from(id)
+}
+
 #[doc = include_str!("../api-docs/en_span_cnt.md")]
 pub fn en_span_cnt(tcc: &impl LogProvider) -> impl Fn(()) -> mlua::Result<usize> {
     move |_: ()| Ok(tcc.len())
 }
 
+#[doc = include_str!("../api-docs/en_message.md")]
+pub fn en_message(tcc: &impl LogProvider) -> impl Fn(u32) -> LogProviderResult<Option<String>> {
+    move |id: u32| Ok(tcc.message(id)?.map(|x| x.to_string()))
+}
+
+#[doc = include_str!("../api-docs/en_header_text.md")]
+pub fn en_header_text(tcc: &impl LogProvider) -> impl Fn(u32) -> LogProviderResult<String> {
+    move |id: u32| Ok(tcc.header(id)?.display_text(id))
+}
+
 #[doc = include_str!("../api-docs/en_metadata_table.md")]
 pub fn en_metadata_table(tcc: &impl LogProvider, lua: &Lua) -> impl Fn(u32) -> mlua::Result<Table> {
     move |id: u32| {
@@ -161,7 +261,12 @@ pub fn en_attrs(tcc: &impl LogProvider, lua: &Lua) -> impl Fn(u32) -> mlua::Resu
         let attr_names = tcc.attr_names(id).map_err(to_lua_err)?;
         let attr_values = tcc.attr_values(id).map_err(to_lua_err)?;
         let table = lua.create_table_with_capacity(0, attr_names.len())?;
-        for (&key, value) in attr_names.iter().zip(attr_values.iter()) {
+        // names are sorted, so duplicates are next to each other. keep the first one, like
+        // en_attr_by_name does
+        for (i, (&key, value)) in attr_names.iter().zip(attr_values.iter()).enumerate() {
+            if i > 0 && attr_names[i - 1] == key {
+                continue;
+            }
             table.set(key, LuaValueRefRef(value))?;
         }
         Ok(table)
@@ -223,6 +328,21 @@ pub fn en_attr_by_name(
     }
 }
 
+#[doc = include_str!("../api-docs/en_attr_all.md")]
+pub fn en_attr_all(
+    tcc: &impl LogProvider, lua: &Lua,
+) -> impl Fn((u32, String)) -> mlua::Result<Vec<mlua::Value>> {
+    move |(id, key): (u32, String)| {
+        let attr_names = tcc.attr_names(id).map_err(to_lua_err)?;
+        let attr_values = tcc.attr_values(id).map_err(to_lua_err)?;
+        let mut values = vec![];
+        for (_, value) in attr_names.iter().zip(attr_values.iter()).filter(|(k, _)| **k == key) {
+            values.push(LuaValueRefRef(value).into_lua(lua)?);
+        }
+        Ok(values)
+    }
+}
+
 #[doc = include_str!("../api-docs/en_attr_name.md")]
 pub fn en_attr_name(
     tcc: &impl LogProvider, lua: &Lua,
@@ -245,6 +365,82 @@ pub fn en_attr_value(
     }
 }
 
+#[doc = include_str!("../api-docs/en_attr_cnt.md")]
+pub fn en_attr_cnt(tcc: &impl LogProvider) -> impl Fn(u32) -> LogProviderResult<usize> {
+    move |id: u32| Ok(tcc.attr_names(id)?.len())
+}
+
+#[doc = include_str!("../api-docs/en_attr_pairs_range.md")]
+pub fn en_attr_pairs_range(
+    tcc: &impl LogProvider, lua: &Lua,
+) -> impl Fn((u32, usize, Option<usize>)) -> mlua::Result<Vec<Table>> {
+    move |(id, start, count): (u32, usize, Option<usize>)| {
+        let names = tcc.attr_names(id).map_err(to_lua_err)?;
+        if start > names.len() {
+            return Err(make_oob_error(start as u32, names.len()));
+        }
+        let end = match count {
+            Some(count) => start.saturating_add(count).min(names.len()),
+            None => names.len(),
+        };
+        let values = tcc.attr_values(id).map_err(to_lua_err)?;
+        let mut pairs = Vec::with_capacity(end - start);
+        for (name, value) in names[start..end].iter().zip(&values[start..end]) {
+            let pair = lua.create_table_with_capacity(2, 0)?;
+            pair.push(lua.create_string(name)?)?;
+            pair.push(LuaValueRefRef(value))?;
+            pairs.push(pair);
+        }
+        Ok(pairs)
+    }
+}
+
+#[doc = include_str!("../api-docs/en_attr_universe.md")]
+pub fn en_attr_universe(tcc: &impl LogProvider) -> impl Fn(()) -> LogProviderResult<Vec<String>> {
+    move |_: ()| tcc.distinct_attr_names()
+}
+
+#[doc = include_str!("../api-docs/en_attr_cardinality.md")]
+pub fn en_attr_cardinality(tcc: &impl LogProvider) -> impl Fn(String) -> LogProviderResult<usize> {
+    move |name: String| tcc.attr_cardinality(&name)
+}
+
+#[doc = include_str!("../api-docs/en_attr_is_debug.md")]
+pub fn en_attr_is_debug(
+    tcc: &impl LogProvider,
+) -> impl Fn((u32, String)) -> LogProviderResult<Option<bool>> {
+    move |(id, name): (u32, String)| {
+        Ok(tcc.attr_value(id, &name)?.map(|x| matches!(x, EnValueRef::Debug(_))))
+    }
+}
+
+#[doc = include_str!("../api-docs/en_aggregate_children.md")]
+pub fn en_aggregate_children(
+    tcc: &impl LogProvider,
+) -> impl Fn((u32, String, String)) -> mlua::Result<Option<f64>> {
+    move |(parent, attr, op): (u32, String, String)| {
+        let op: AggregateOp = op.parse().map_err(to_lua_err)?;
+        tcc.aggregate_children(parent, &attr, op).map_err(to_lua_err)
+    }
+}
+
+#[doc = include_str!("../api-docs/en_deepest_path.md")]
+pub fn en_deepest_path(tcc: &impl LogProvider) -> impl Fn(()) -> LogProviderResult<Vec<u32>> {
+    move |_: ()| tcc.max_depth_path()
+}
+
+#[doc = include_str!("../api-docs/en_span_path.md")]
+pub fn en_span_path(tcc: &impl LogProvider) -> impl Fn(u32) -> LogProviderResult<Option<String>> {
+    move |id: u32| span_path(tcc, id)
+}
+
+#[doc = include_str!("../api-docs/en_resolve_path.md")]
+pub fn en_resolve_path(
+    tcc: &impl LogProvider,
+) -> impl Fn(String) -> LogProviderResult<Option<u32>> {
+    move |path: String| resolve_span_path(tcc, &path)
+}
+
 #[doc = include_str!("../api-docs/en_as_string.md")]
 pub fn en_as_string(tcc: &impl LogProvider) -> impl Fn(u32) -> LogProviderResult<String> {
     move |id: u32| {
@@ -266,36 +462,62 @@ pub fn en_as_string(tcc: &impl LogProvider) -> impl Fn(u32) -> LogProviderResult
     }
 }
 
+#[doc = include_str!("../api-docs/en_as_oneline.md")]
+pub fn en_as_oneline(tcc: &impl LogProvider) -> impl Fn(u32) -> LogProviderResult<String> {
+    move |id: u32| format_span_oneline(tcc, id)
+}
+
 #[doc = include_str!("../api-docs/en_contains_anywhere.md")]
 pub fn en_contains_anywhere(
     tcc: &impl LogProvider, finder_cache: Rc<RefCell<HashMap<String, Finder>>>,
     buffer: Rc<RefCell<ReusableString>>,
 ) -> impl FnMut((u32, String)) -> LogProviderResult<bool> {
     move |(id, needle): (u32, String)| {
-        let mut finder_w = finder_cache.borrow_mut();
-        let finder = finder_w
-            .entry(needle.clone())
-            .or_insert_with(|| memchr::memmem::Finder::new(&needle).into_owned());
-        let mut buf = buffer.borrow_mut();
-        buf.clear();
-        let attr_names = tcc.attr_names(id)?;
-        let attr_values = tcc.attr_values(id)?;
-        let attrs: Vec<(&str, EnValueRef)> = attr_names.into_iter().zip(attr_values).collect();
-        let meta = tcc.meta(id)?;
-        let children = tcc.children(id)?;
-        #[derive(Debug)]
-        #[allow(dead_code)]
-        struct Entry<'a> {
-            meta: &'a MetadataRefContainer<'a>,
-            attrs: &'a Vec<(&'a str, EnValueRef<'a>)>,
-            children: &'a [u32],
-        }
-        let entry = Entry { meta: &meta, attrs: &attrs, children };
-        write!(&mut buf.buf, "{entry:?}").unwrap();
-        //let s = format!("{entry:?}");
-        let contains = finder.find(buf.buf.as_bytes());
-        Ok(contains.is_some())
+        contains_anywhere(tcc, &finder_cache, &buffer, id, needle, false)
+    }
+}
+
+#[doc = include_str!("../api-docs/en_contains_anywhere_ci.md")]
+pub fn en_contains_anywhere_ci(
+    tcc: &impl LogProvider, finder_cache: Rc<RefCell<HashMap<String, Finder>>>,
+    buffer: Rc<RefCell<ReusableString>>,
+) -> impl FnMut((u32, String)) -> LogProviderResult<bool> {
+    move |(id, needle): (u32, String)| {
+        contains_anywhere(tcc, &finder_cache, &buffer, id, needle.to_ascii_lowercase(), true)
+    }
+}
+
+/// Search the `Debug` text of entry `id` for `needle`, after lowercasing the ASCII letters of the
+/// text if `fold_case`. The finder of a needle is the same either way, so the cache is shared.
+fn contains_anywhere(
+    tcc: &impl LogProvider, finder_cache: &RefCell<HashMap<String, Finder>>,
+    buffer: &RefCell<ReusableString>, id: u32, needle: String, fold_case: bool,
+) -> LogProviderResult<bool> {
+    let mut finder_w = finder_cache.borrow_mut();
+    let finder = finder_w
+        .entry(needle.clone())
+        .or_insert_with(|| memchr::memmem::Finder::new(&needle).into_owned());
+    let mut buf = buffer.borrow_mut();
+    buf.clear();
+    let attr_names = tcc.attr_names(id)?;
+    let attr_values = tcc.attr_values(id)?;
+    let attrs: Vec<(&str, EnValueRef)> = attr_names.into_iter().zip(attr_values).collect();
+    let meta = tcc.meta(id)?;
+    let children = tcc.children(id)?;
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct Entry<'a> {
+        meta: &'a MetadataRefContainer<'a>,
+        attrs: &'a Vec<(&'a str, EnValueRef<'a>)>,
+        children: &'a [u32],
     }
+    let entry = Entry { meta: &meta, attrs: &attrs, children };
+    write!(&mut buf.buf, "{entry:?}").unwrap();
+    if fold_case {
+        buf.buf.make_ascii_lowercase();
+    }
+    let contains = finder.find(buf.buf.as_bytes());
+    Ok(contains.is_some())
 }
 
 #[doc = include_str!("../api-docs/en_foreach.md")]
@@ -332,6 +554,62 @@ pub fn en_foreach(
 
     Ok(results)
 }
+/// Sort `ids` by descending score. The sort is stable, so ties keep their input order.
+fn sort_by_score_desc(ids: Vec<u32>, scores: Vec<f64>) -> Vec<u32> {
+    let mut scored: Vec<(u32, f64)> = ids.into_iter().zip(scores).collect();
+    scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    scored.into_iter().map(|(id, _)| id).collect()
+}
+#[doc = include_str!("../api-docs/en_rank.md")]
+pub fn en_rank(_lua: &Lua, (ids, scorer): (Vec<u32>, mlua::Function)) -> mlua::Result<Vec<u32>> {
+    let mut scores = Vec::with_capacity(ids.len());
+    for &id in ids.iter() {
+        scores.push(scorer.call::<f64>(id)?);
+    }
+    Ok(sort_by_score_desc(ids, scores))
+}
+#[doc = include_str!("../api-docs/en_rank_by_attr.md")]
+pub fn en_rank_by_attr(
+    tcc: &impl LogProvider,
+) -> impl Fn((Vec<u32>, String)) -> LogProviderResult<Vec<u32>> {
+    move |(ids, attr): (Vec<u32>, String)| {
+        let mut scores = Vec::with_capacity(ids.len());
+        for &id in ids.iter() {
+            let value = tcc.attr_value(id, &attr)?;
+            // spans without a numeric value for the attribute go last
+            scores.push(value.and_then(|x| x.as_f64()).unwrap_or(f64::NEG_INFINITY));
+        }
+        Ok(sort_by_score_desc(ids, scores))
+    }
+}
+/// The error of a walk up from `id` which doesn't reach the root.
+fn no_root_error(id: u32) -> mlua::Error {
+    mlua::Error::runtime(format!("span {id} has no path to the root, the trace has a cycle"))
+}
+/// Number of parent hops from `id` to the root. The root has depth 0.
+fn depth_of(tcc: &impl LogProvider, id: u32) -> mlua::Result<u32> {
+    let mut depth = 0;
+    let mut current = id;
+    // a well-formed trace reaches the root in fewer steps, this only guards against cycles
+    while current != 0 {
+        if depth as usize >= tcc.len() {
+            return Err(no_root_error(id));
+        }
+        current = tcc.parent(current).map_err(to_lua_err)?;
+        depth += 1;
+    }
+    Ok(depth)
+}
+#[doc = include_str!("../api-docs/en_rank_by_depth.md")]
+pub fn en_rank_by_depth(tcc: &impl LogProvider) -> impl Fn(Vec<u32>) -> mlua::Result<Vec<u32>> {
+    move |ids: Vec<u32>| {
+        let mut scores = Vec::with_capacity(ids.len());
+        for &id in ids.iter() {
+            scores.push(depth_of(tcc, id)? as f64);
+        }
+        Ok(sort_by_score_desc(ids, scores))
+    }
+}
 fn meta_matches(
     meta: &MetadataRefContainer, target: &str, comparator: Ordering, value: &EnValue,
 ) -> anyhow::Result<bool> {
@@ -374,11 +652,78 @@ fn meta_matches(
         x => bail!("Bad meta field {x}"),
     }
 }
+/// Compare an integer span value with an integer constant without losing precision.
+/// Returns None if the span value is not an integer.
+fn int_ordering(here: &EnValueRef, expected: i128) -> Option<Ordering> {
+    match here {
+        EnValueRef::U64(x) => Some((*x as i128).cmp(&expected)),
+        EnValueRef::I64(x) => Some((*x as i128).cmp(&expected)),
+        EnValueRef::I128(x) => Some(x.cmp(&expected)),
+        EnValueRef::U128(x) => match i128::try_from(*x) {
+            Ok(x) => Some(x.cmp(&expected)),
+            // larger than any i128, so also larger than any constant
+            Err(_) => Some(Ordering::Greater),
+        },
+        _ => None,
+    }
+}
+/// The members of an `IN` predicate, prepared for testing span values against all of them in one
+/// pass.
+pub struct ValueSet<'a> {
+    members: &'a [EnValue],
+    /// For large sets, the string and integer members are hashed. The others are still compared
+    /// one by one.
+    hashed: Option<HashedMembers<'a>>,
+}
+struct HashedMembers<'a> {
+    strings: HashSet<&'a str>,
+    ints: HashSet<i128>,
+    rest: Vec<&'a EnValue>,
+}
+impl<'a> ValueSet<'a> {
+    /// Sets smaller than this are scanned, which is faster than hashing a few values.
+    const HASH_FROM: usize = 16;
+    pub fn new(members: &'a [EnValue]) -> Self {
+        if members.len() < Self::HASH_FROM {
+            return Self { members, hashed: None };
+        }
+        let mut hashed =
+            HashedMembers { strings: HashSet::new(), ints: HashSet::new(), rest: vec![] };
+        for member in members {
+            match member {
+                EnValue::String(x) => _ = hashed.strings.insert(x.as_str()),
+                EnValue::U64(x) => _ = hashed.ints.insert(*x as i128),
+                EnValue::I64(x) => _ = hashed.ints.insert(*x as i128),
+                other => hashed.rest.push(other),
+            }
+        }
+        Self { members, hashed: Some(hashed) }
+    }
+    /// Returns true if `here` equals one of the members, in the sense of [values_match].
+    pub fn contains(&self, here: &EnValueRef) -> bool {
+        let Some(hashed) = &self.hashed else {
+            return self.members.iter().any(|m| values_match(Ordering::Equal, here, m));
+        };
+        let as_int = match here {
+            EnValueRef::String(x) | EnValueRef::Debug(x) => return hashed.strings.contains(x),
+            EnValueRef::U64(x) => Some(*x as i128),
+            EnValueRef::I64(x) => Some(*x as i128),
+            EnValueRef::I128(x) => Some(*x),
+            EnValueRef::U128(x) => i128::try_from(*x).ok(),
+            _ => None,
+        };
+        match as_int {
+            Some(x) => hashed.ints.contains(&x),
+            None => hashed.rest.iter().any(|m| values_match(Ordering::Equal, here, m)),
+        }
+    }
+}
 /// Returns true if span_value R value
 pub fn values_match(comparator: std::cmp::Ordering, here: &EnValueRef, expected: &EnValue) -> bool {
     match expected {
-        EnValue::String(a) => match here {
-            EnValueRef::String(b) => b.cmp(&a.as_str()) == comparator,
+        // Debug values are matched as the text they were formatted to
+        EnValue::String(a) | EnValue::Debug(a) => match here {
+            EnValueRef::String(b) | EnValueRef::Debug(b) => b.cmp(&a.as_str()) == comparator,
             _ => false,
         },
         EnValue::Bool(a) => match here {
@@ -389,26 +734,8 @@ pub fn values_match(comparator: std::cmp::Ordering, here: &EnValueRef, expected:
             EnValueRef::Float(b) => b.total_cmp(a) == comparator,
             _ => false,
         },
-        EnValue::U64(a) => {
-            let span_value_converted = match here {
-                EnValueRef::U64(x) => *x,
-                EnValueRef::I64(x) => *x as u64,
-                EnValueRef::U128(x) => *x as u64,
-                EnValueRef::I128(x) => *x as u64,
-                _ => return false,
-            };
-            span_value_converted.cmp(a) == comparator
-        }
-        EnValue::I64(a) => {
-            let span_value_converted = match here {
-                EnValueRef::U64(x) => *x as i64,
-                EnValueRef::I64(x) => *x,
-                EnValueRef::U128(x) => *x as i64,
-                EnValueRef::I128(x) => *x as i64,
-                _ => return false,
-            };
-            span_value_converted.cmp(a) == comparator
-        }
+        EnValue::U64(a) => int_ordering(here, *a as i128) == Some(comparator),
+        EnValue::I64(a) => int_ordering(here, *a as i128) == Some(comparator),
         // we explicitly don't construct these from the lua tables
         EnValue::U128(_) => false,
         EnValue::I128(_) => false,
@@ -416,26 +743,256 @@ pub fn values_match(comparator: std::cmp::Ordering, here: &EnValueRef, expected:
         EnValue::Bytes(_) => false,
     }
 }
+/// The fields which can be used as `meta.<field>` filter targets.
+pub const META_FIELDS: &[&str] = &["name", "target", "level", "module_path", "file", "line"];
+/// The `meta.<field>` filter targets which can be matched with the `REGEX` relation.
+pub const REGEX_META_FIELDS: &[&str] = &["name", "target", "module_path", "file"];
+/// Whether `haystack` matches the regex `pattern`. Patterns are compiled once per thread, not for
+/// every span tested.
+#[cfg(feature = "regex")]
+fn regex_is_match(pattern: &str, haystack: &str) -> anyhow::Result<bool> {
+    /// Workers live across queries, so forget old patterns at some point.
+    const MAX_CACHED: usize = 64;
+    thread_local! {
+        static COMPILED: RefCell<HashMap<String, regex_lite::Regex>> = Default::default();
+    }
+    COMPILED.with_borrow_mut(|compiled| {
+        if let Some(regex) = compiled.get(pattern) {
+            return Ok(regex.is_match(haystack));
+        }
+        let regex = regex_lite::Regex::new(pattern)
+            .map_err(|e| anyhow::anyhow!("Bad regex `{pattern}`: {e}"))?;
+        let is_match = regex.is_match(haystack);
+        if compiled.len() >= MAX_CACHED {
+            compiled.clear();
+        }
+        compiled.insert(pattern.into(), regex);
+        Ok(is_match)
+    })
+}
+#[cfg(not(feature = "regex"))]
+fn regex_is_match(_pattern: &str, _haystack: &str) -> anyhow::Result<bool> {
+    bail!("The REGEX relation needs the `regex` feature of entrace_query")
+}
+/// Returns true if the string metadata field `target` is present and matches the regex `pattern`.
+fn meta_regex_matches(
+    meta: &MetadataRefContainer, target: &str, pattern: &EnValue,
+) -> anyhow::Result<bool> {
+    let EnValue::String(pattern) = pattern else { bail!("REGEX needs a string pattern") };
+    let value = match target {
+        "name" => Some(meta.name),
+        "target" => Some(meta.target),
+        "module_path" => meta.module_path,
+        "file" => meta.file,
+        x => bail!(
+            "REGEX is not supported for meta.{x}, only for meta.{}",
+            REGEX_META_FIELDS.join(", meta.")
+        ),
+    };
+    match value {
+        Some(value) => regex_is_match(pattern, value),
+        None => Ok(false),
+    }
+}
+/// The value of the metadata field `target`, if present. The level is given as its number.
+fn meta_value<'a>(
+    meta: &MetadataRefContainer<'a>, target: &str,
+) -> anyhow::Result<Option<EnValueRef<'a>>> {
+    Ok(match target {
+        "name" => Some(EnValueRef::String(meta.name)),
+        "target" => Some(EnValueRef::String(meta.target)),
+        "level" => Some(EnValueRef::U64(meta.level as u8 as u64)),
+        "module_path" => meta.module_path.map(EnValueRef::String),
+        "file" => meta.file.map(EnValueRef::String),
+        "line" => meta.line.map(|x| EnValueRef::U64(x as u64)),
+        x => bail!("Bad meta field {x}"),
+    })
+}
+/// Returns true if the metadata field `target` is present.
+fn meta_exists(meta: &MetadataRefContainer, target: &str) -> anyhow::Result<bool> {
+    match target {
+        "name" | "target" | "level" => Ok(true),
+        "module_path" => Ok(meta.module_path.is_some()),
+        "file" => Ok(meta.file.is_some()),
+        "line" => Ok(meta.line.is_some()),
+        x => bail!("Bad meta field {x}"),
+    }
+}
+/// The fields which can be used as `en.<field>` filter targets.
+pub const COMPUTED_FIELDS: &[&str] = &["depth", "subtree_size", "child_count"];
+/// The error of a subtree walk which found more spans than the trace has.
+fn subtree_cycle_error(id: u32) -> mlua::Error {
+    mlua::Error::runtime(format!("the subtree of span {id} contains itself, the trace has a cycle"))
+}
+/// Number of spans in the subtree of `id`, including `id` itself.
+fn subtree_size_of(tcc: &impl LogProvider, id: u32) -> mlua::Result<u64> {
+    let mut size = 1;
+    // the children lists which are not counted yet, so this grows with the depth, not the width
+    let mut stack = vec![tcc.children(id).map_err(to_lua_err)?];
+    while let Some(children) = stack.pop() {
+        size += children.len() as u64;
+        // a well-formed subtree is no larger than the trace, this only guards against cycles
+        if size > tcc.len() as u64 {
+            return Err(subtree_cycle_error(id));
+        }
+        for &child in children {
+            let grandchildren = tcc.children(child).map_err(to_lua_err)?;
+            if !grandchildren.is_empty() {
+                stack.push(grandchildren);
+            }
+        }
+    }
+    Ok(size)
+}
+/// The spans in the subtree of `id`, including `id` itself, in preorder: every span comes before
+/// its children, and children in the order they were recorded. Iterative, as trees can be deep.
+fn subtree_of(tcc: &impl LogProvider, root: u32) -> mlua::Result<Vec<u32>> {
+    let mut subtree = vec![];
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+        if subtree.len() >= tcc.len() {
+            return Err(subtree_cycle_error(root));
+        }
+        subtree.push(id);
+        stack.extend(tcc.children(id).map_err(to_lua_err)?.iter().rev());
+    }
+    Ok(subtree)
+}
+/// The [COMPUTED_FIELDS] which depend on the whole tree above or below a span, for every span of
+/// a trace. [EnMatcher] computes them once, instead of walking the tree for every span it tests.
+struct TreeFields {
+    depth: Vec<u64>,
+    subtree_size: Vec<u64>,
+}
+impl TreeFields {
+    /// One pass down the tree for the depths, and one up for the subtree sizes. Children come
+    /// after their parent, so a child which doesn't is an error, as it could be part of a cycle.
+    fn of(tcc: &impl LogProvider) -> anyhow::Result<Self> {
+        let len = tcc.len();
+        let mut depth = vec![0; len];
+        for id in 0..len as u32 {
+            for &child in tcc.children(id)? {
+                if child <= id || child as usize >= len {
+                    bail!("span {id} lists span {child} as a child, the trace has a cycle");
+                }
+                depth[child as usize] = depth[id as usize] + 1;
+            }
+        }
+        let mut subtree_size = vec![1; len];
+        for id in (0..len as u32).rev() {
+            let children = tcc.children(id)?;
+            subtree_size[id as usize] +=
+                children.iter().map(|&x| subtree_size[x as usize]).sum::<u64>();
+        }
+        Ok(Self { depth, subtree_size })
+    }
+    /// The values of computed field `target` by span id, if it is one of these.
+    fn field(&self, target: &str) -> Option<&[u64]> {
+        match target {
+            "depth" => Some(&self.depth),
+            "subtree_size" => Some(&self.subtree_size),
+            _ => None,
+        }
+    }
+}
+/// Compute the value of the `en.<target>` field of a span, see [COMPUTED_FIELDS].
+fn computed_value(tcc: &impl LogProvider, id: u32, target: &str) -> anyhow::Result<u64> {
+    match target {
+        "depth" => Ok(depth_of(tcc, id)? as u64),
+        "subtree_size" => Ok(subtree_size_of(tcc, id)?),
+        "child_count" => Ok(tcc.children(id)?.len() as u64),
+        x => bail!("Bad computed field {x}, expected one of {}", COMPUTED_FIELDS.join(", ")),
+    }
+}
+/// What a filter target refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetKind {
+    /// An attribute of the span.
+    Attr,
+    /// A metadata field, written as `meta.<field>`.
+    Meta,
+    /// A value computed from the span tree, written as `en.<field>`, see [COMPUTED_FIELDS].
+    Computed,
+}
+/// Returns true if the span has `target`, and for [Relation::Cmp], its value is in that relation
+/// with `en_value`.
+/// A missing attribute never matches a comparison, so `EQ ""` only matches an empty string.
 pub fn span_matches_filter(
-    tcc: &impl LogProvider, id: u32, target: &str, target_is_meta: bool, relation: Ordering,
+    tcc: &impl LogProvider, id: u32, target: &str, kind: TargetKind, relation: Relation,
     en_value: &EnValue,
-) -> bool {
-    if target_is_meta {
-        let meta = tcc.meta(id).unwrap();
-        meta_matches(&meta, target, relation, en_value).map_err(|x| x.into_lua_err()).unwrap()
+) -> anyhow::Result<bool> {
+    if relation == Relation::In {
+        // a single constant, so a set of one
+        return span_matches_set(
+            tcc,
+            id,
+            target,
+            kind,
+            &ValueSet::new(std::slice::from_ref(en_value)),
+        );
+    }
+    if kind == TargetKind::Meta {
+        let meta = tcc.meta(id)?;
+        match relation {
+            Relation::Exists => meta_exists(&meta, target),
+            Relation::Cmp(ordering) => meta_matches(&meta, target, ordering, en_value),
+            Relation::Regex => meta_regex_matches(&meta, target, en_value),
+            Relation::In => unreachable!(),
+        }
+    } else if kind == TargetKind::Computed {
+        let value = computed_value(tcc, id, target)?;
+        Ok(computed_matches(value, relation, en_value))
     } else {
         if target == "message"
+            && let Relation::Cmp(ordering) = relation
             && let EnValue::String(expected) = en_value
         {
-            return tcc.message(id).unwrap().is_some_and(|v| v.cmp(expected) == relation);
+            return Ok(tcc.message(id)?.is_some_and(|v| v.cmp(expected) == ordering));
         }
-        let Some(value_here) = tcc.attr_value(id, target).unwrap() else {
-            return false;
+        let Some(value_here) = tcc.attr_value(id, target)? else {
+            return Ok(false);
         };
 
-        values_match(relation, &value_here, en_value)
+        Ok(match relation {
+            Relation::Exists => true,
+            Relation::Cmp(ordering) => values_match(ordering, &value_here, en_value),
+            Relation::Regex => false,
+            Relation::In => unreachable!(),
+        })
+    }
+}
+/// Returns true if computed field `value` is in `relation` with `en_value`, for the relations
+/// other than [Relation::In].
+fn computed_matches(value: u64, relation: Relation, en_value: &EnValue) -> bool {
+    match relation {
+        Relation::Exists => true,
+        Relation::Cmp(ordering) => values_match(ordering, &EnValueRef::U64(value), en_value),
+        // rejected by parse_predicate
+        Relation::Regex => false,
+        Relation::In => unreachable!(),
     }
 }
+/// Returns true if the span has `target`, and its value is one of `set`, for [Relation::In].
+pub fn span_matches_set(
+    tcc: &impl LogProvider, id: u32, target: &str, kind: TargetKind, set: &ValueSet,
+) -> anyhow::Result<bool> {
+    let here = match kind {
+        TargetKind::Meta => {
+            let meta = tcc.meta(id)?;
+            let value = meta_value(&meta, target)?;
+            return Ok(value.is_some_and(|value| set.contains(&value)));
+        }
+        TargetKind::Computed => EnValueRef::U64(computed_value(tcc, id, target)?),
+        TargetKind::Attr if target == "message" => {
+            return Ok(tcc.message(id)?.is_some_and(|v| set.contains(&EnValueRef::String(v))));
+        }
+        TargetKind::Attr => match tcc.attr_value(id, target)? {
+            Some(value) => value,
+            None => return Ok(false),
+        },
+    };
+    Ok(set.contains(&here))
+}
 
 // =========================================FILTERSET API FOR LUA=========================================
 // A filterset looks like:
@@ -446,8 +1003,8 @@ pub fn span_matches_filter(
 //     { type = "rel_dnf", clauses = {{ target = "", relation = "", value = ""}}, src = 0},
 //   }
 //
-//   Valid item types are: "prim_list", "prim_range", "rel_dnf",
-//   "intersect", "union", "invert"
+//   Valid item types are: "prim_list", "prim_range", "path_to", "rel_dnf",
+//   "intersect", "union", "invert", "xor"
 
 // en_filterset_from_list()
 //  input: list of ids
@@ -473,6 +1030,40 @@ pub fn en_filterset_from_list(lua: &Lua, t: Table) -> mlua::Result<Table> {
     Ok(fs)
 }
 
+// en_filterset_path_to()
+//  input: target
+//  outputs: a table with
+//    type: "filterset"
+//    root: 0
+//    items: {
+//      { type = "path_to"; target = target}
+//    }
+#[doc = include_str!("../api-docs/en_filterset_path_to.md")]
+pub fn en_filterset_path_to(lua: &Lua, target: u32) -> mlua::Result<Table> {
+    let fs = lua.create_table()?;
+    fs.set("type", "filterset")?;
+    fs.set("root", 0)?;
+
+    let item = lua.create_table()?;
+    item.set("type", "path_to")?;
+    item.set("target", target)?;
+
+    let items = lua.create_table()?;
+    items.push(item)?;
+    fs.set("items", items)?;
+    Ok(fs)
+}
+
+#[doc = include_str!("../api-docs/en_filterset_from_subtree.md")]
+pub fn en_filterset_from_subtree(
+    tcc: &impl LogProvider, lua: &Lua,
+) -> impl Fn(u32) -> mlua::Result<Table> {
+    move |id: u32| {
+        let ids = en_subtree(tcc)(id)?;
+        en_filterset_from_list(lua, lua.create_sequence_from(ids)?)
+    }
+}
+
 // en_filterset_from_range()
 //  input: start, end
 //  outputs: a table with
@@ -503,12 +1094,13 @@ pub fn en_filterset_from_range(lua: &Lua, (start, end): (usize, usize)) -> mlua:
 //   en_filter(filter: Table, src: Table)->Table
 //     filter: table with
 //       target: name of variable eg. "message" or "meta.filename"
-//       relation: a relation, one of "EQ", "LT", "GT"
-//       value: a constant to compare with
+//       relation: a relation, one of "EQ", "LT", "GT", "EXISTS", "REGEX", "IN"
+//       value: a constant to compare with, not needed for "EXISTS", a list for "IN"
 //     src: filterset
 // The second:
 //   en_filter(target: String, relation: String, value: T, src:Table)->Table.
 //   (this is basically the same, just sometimes more convenient)
+//   The value can be left out for "EXISTS": en_filter(target, "EXISTS", src)
 // outputs: { type = "filterset", root = 1, items = { src = 0, {type = "rel_dnf", src = 0, clauses = {{ target, relation, value}} }}},
 #[doc = include_str!("../api-docs/en_filter.md")]
 pub fn en_filter(lua: &Lua, args: MultiValue) -> mlua::Result<Table> {
@@ -531,7 +1123,9 @@ pub fn en_filter(lua: &Lua, args: MultiValue) -> mlua::Result<Table> {
         pred2 = lua.create_table()?;
         pred2.set("target", args[0].clone())?;
         pred2.set("relation", args[1].clone())?;
-        pred2.set("value", args[2].clone())?;
+        if args.len() > 3 {
+            pred2.set("value", args[2].clone())?;
+        }
     }
 
     clauses_inner.push(pred2)?;
@@ -549,13 +1143,17 @@ pub fn en_filter(lua: &Lua, args: MultiValue) -> mlua::Result<Table> {
 /// Helper used by [en_filterset_union] and [en_filterset_intersect] to fix up the source pointers
 /// in item lists when concatenating multiple items lists
 fn increment_item_source(amount: i64, item: &Table) -> mlua::Result<()> {
-    if let Ok(mlua::Value::Integer(q)) = item.get("src") {
+    // LuaJIT has no integer subtype, so the pointers can come back as numbers too
+    if let Some(q) = item.get::<Option<i64>>("src")? {
         item.set("src", q + amount)?;
     }
+    if let Some(universe) = item.get::<Option<i64>>("universe")? {
+        item.set("universe", universe + amount)?;
+    }
     if let Ok(mlua::Value::Table(srcs)) = item.get("srcs") {
         let len = srcs.len()?;
         for x in 1..=len {
-            if let Ok(mlua::Value::Integer(q)) = srcs.get(x) {
+            if let Some(q) = srcs.get::<Option<i64>>(x)? {
                 srcs.set(x, q + amount)?;
             }
         }
@@ -783,32 +1381,180 @@ pub fn en_filterset_invert(
     Ok(new_fs)
 }
 
-/// Creates a Predicate from a Table that has keys "target", "relation", "value"
+// en_filterset_xor()
+// input:
+//   filters: a list of filtersets, like for en_filterset_union()
+// outputs: a filterset that matches an item if it is in an odd number of the input filtersets.
+//   The new item is { type = "xor", srcs = { 1, 3 }}
+#[doc = include_str!("../api-docs/en_filterset_xor.md")]
+pub fn en_filterset_xor(lua: &Lua, filters: Table) -> mlua::Result<Table> {
+    let fs = lua.create_table()?;
+    fs.set("type", "filterset")?;
+    let (all_items, srcs) = concat_items_lists(lua, filters)?;
+    let xor = lua.create_table()?;
+    xor.set("type", "xor")?;
+    xor.set("srcs", srcs)?;
+    all_items.push(xor)?;
+    fs.set("root", all_items.len()? - 1)?;
+    fs.set("items", all_items)?;
+    Ok(fs)
+}
+
+// en_filterset_subtract()
+// input:
+//   a, b: two filtersets
+// outputs: a filterset that matches the items of a that are not in b. This is an inversion with a
+//   as the universe: { type = "invert", src = <root of b>, universe = <root of a> }
+#[doc = include_str!("../api-docs/en_filterset_subtract.md")]
+pub fn en_filterset_subtract(lua: &Lua, (a, b): (Table, Table)) -> mlua::Result<Table> {
+    let fs = lua.create_table()?;
+    fs.set("type", "filterset")?;
+    let (all_items, srcs) = {
+        let tbl = lua.create_table()?;
+        tbl.push(a)?;
+        tbl.push(b)?;
+        concat_items_lists(lua, tbl)?
+    };
+    let minus = lua.create_table()?;
+    minus.set("type", "invert")?;
+    minus.set("src", srcs[1])?;
+    minus.set("universe", srcs[0])?;
+    all_items.push(minus)?;
+    fs.set("root", all_items.len()? - 1)?;
+    fs.set("items", all_items)?;
+    Ok(fs)
+}
+
+/// Creates a Predicate from a Table that has keys "target", "relation", "value",
+/// and optionally "value_type".
+///
+/// Without a "value_type", non-negative integers become U64, negative integers I64, and other
+/// numbers Float.
+///
+/// The "EXISTS" relation doesn't need a value. The value of "IN" is a list, whose elements are
+/// converted like the other values.
 fn parse_predicate(t: &Table) -> mlua::Result<Predicate<EnValue>> {
-    //     { type = "rel", target = "", relation = "", value = "", src = 0 },
+    //     { type = "rel", target = "", relation = "", value = "", value_type = "u64", src = 0 },
     let attr: String = t.get("target")?;
+    if let (TargetKind::Computed, field) = split_target(&attr)
+        && !COMPUTED_FIELDS.contains(&field)
+    {
+        let expected = COMPUTED_FIELDS.join(", ");
+        return Err(anyhow::anyhow!("Bad computed field {field}, expected one of {expected}")
+            .into_lua_err());
+    }
     let relation: String = t.get("relation")?;
     let rel = match relation.as_str() {
-        "GT" => Ordering::Greater,
-        "LT" => Ordering::Less,
-        "EQ" => Ordering::Equal,
+        "GT" => Relation::Cmp(Ordering::Greater),
+        "LT" => Relation::Cmp(Ordering::Less),
+        "EQ" => Relation::Cmp(Ordering::Equal),
+        // the constant is ignored, but we still need one
+        "EXISTS" => {
+            return Ok(Predicate::new(attr, Relation::Exists, EnValue::Bool(true)));
+        }
+        "REGEX" => {
+            let pattern = match split_target(&attr) {
+                (TargetKind::Meta, field) if REGEX_META_FIELDS.contains(&field) => {
+                    t.get::<String>("value")?
+                }
+                _ => {
+                    let fields = REGEX_META_FIELDS.join(", meta.");
+                    return Err(anyhow::anyhow!(
+                        "REGEX is not supported for {attr}, only for meta.{fields}"
+                    )
+                    .into_lua_err());
+                }
+            };
+            // report a bad pattern here, not for every span
+            regex_is_match(&pattern, "").map_err(|x| x.into_lua_err())?;
+            return Ok(Predicate::new(attr, Relation::Regex, EnValue::String(pattern)));
+        }
+        "IN" => Relation::In,
         x => return Err(anyhow::anyhow!("Bad filter relation {x}").into_lua_err()),
     };
 
     let value: mlua::Value = t.get("value")?;
-    let en_value = match value {
-        Value::Boolean(f) => EnValue::Bool(f),
-        Value::Integer(k) => EnValue::I64(k),
-        Value::Number(z) => EnValue::Float(z),
-        Value::String(ref q) => EnValue::String(q.to_string_lossy()),
+    let value_type: Option<String> = t.get("value_type")?;
+    if rel == Relation::In {
+        let Value::Table(list) = value else {
+            return Err(anyhow::anyhow!("IN needs a list of values, got {value:?}").into_lua_err());
+        };
+        let members = list
+            .sequence_values::<Value>()
+            .map(|x| to_en_value(x?, value_type.as_deref()))
+            .collect::<mlua::Result<Vec<_>>>()?;
+        // the constant is ignored, but we still need one
+        let mut predicate = Predicate::new(attr, Relation::In, EnValue::Bool(true));
+        predicate.members = members;
+        return Ok(predicate);
+    }
+    Ok(Predicate::new(attr, rel, to_en_value(value, value_type.as_deref())?))
+}
+/// Convert a filter constant to an EnValue, see [parse_predicate].
+fn to_en_value(value: Value, value_type: Option<&str>) -> mlua::Result<EnValue> {
+    Ok(match value_type {
+        Some(ty) => convert_value_to(&value, ty)?,
+        None => match value {
+            Value::Boolean(f) => EnValue::Bool(f),
+            Value::Integer(k) => match u64::try_from(k) {
+                Ok(k) => EnValue::U64(k),
+                Err(_) => EnValue::I64(k),
+            },
+            Value::Number(z) => EnValue::Float(z),
+            Value::String(ref q) => EnValue::String(q.to_string_lossy()),
+            x => {
+                return Err(anyhow::anyhow!("Cannot convert value {x:?} to EnValue").into_lua_err());
+            }
+        },
+    })
+}
+/// Convert a filter constant to the type requested with "value_type".
+/// Errors instead of silently losing precision. Integers may also be given as strings, since Lua
+/// numbers can't represent all of u64.
+fn convert_value_to(value: &Value, ty: &str) -> mlua::Result<EnValue> {
+    fn int_from_lua<T: TryFrom<i64> + std::str::FromStr>(value: &Value) -> Option<T> {
+        match value {
+            Value::Integer(k) => T::try_from(*k).ok(),
+            Value::Number(z) if z.fract() == 0.0 && z.abs() < 2f64.powi(53) => {
+                T::try_from(*z as i64).ok()
+            }
+            Value::String(q) => q.to_str().ok()?.trim().parse().ok(),
+            _ => None,
+        }
+    }
+    let converted = match ty {
+        "u64" => int_from_lua(value).map(EnValue::U64),
+        "i64" => int_from_lua(value).map(EnValue::I64),
+        "float" => match value {
+            Value::Integer(k) => Some(EnValue::Float(*k as f64)),
+            Value::Number(z) => Some(EnValue::Float(*z)),
+            Value::String(q) => {
+                q.to_str().ok().and_then(|x| x.trim().parse().ok()).map(EnValue::Float)
+            }
+            _ => None,
+        },
+        "string" => match value {
+            Value::String(q) => Some(EnValue::String(q.to_string_lossy())),
+            Value::Integer(k) => Some(EnValue::String(k.to_string())),
+            Value::Number(z) => Some(EnValue::String(z.to_string())),
+            _ => None,
+        },
+        "bool" => value.as_boolean().map(EnValue::Bool),
         x => {
-            return Err(anyhow::anyhow!("Cannot convert value {x:?} to EnValue").into_lua_err());
+            return Err(anyhow::anyhow!(
+                "Bad value_type {x}, expected one of u64, i64, float, string, bool"
+            )
+            .into_lua_err());
         }
     };
-    Ok(Predicate { attr, rel, constant: en_value })
+    converted.ok_or_else(|| {
+        anyhow::anyhow!("Cannot convert value {value:?} to {ty} without losing information")
+            .into_lua_err()
+    })
 }
 fn item_to_filterset(
-    item: &Table, mut add_predicate: impl FnMut(Predicate<EnValue>) -> PredicateId,
+    item: &Table, log: &impl LogProvider,
+    mut add_predicate: impl FnMut(Predicate<EnValue>) -> PredicateId,
 ) -> mlua::Result<Filterset> {
     let ty: String = item.get("type")?;
     match ty.as_str() {
@@ -821,6 +1567,12 @@ fn item_to_filterset(
             let end: u32 = item.get("end")?;
             Ok(Filterset::Primitive(Roaring::from_range(start..=end)))
         }
+        "path_to" => {
+            //     { type = "path_to", target = 5 }
+            // the root-to-target chain is known up front, so it is a primitive too
+            let target: u32 = item.get("target")?;
+            Ok(Filterset::Primitive(Roaring::from_iter(en_ancestors(log)(target)?)))
+        }
         "rel_dnf" => {
             //     { type = "rel_dnf",
             //       clauses = {
@@ -851,41 +1603,141 @@ fn item_to_filterset(
             //     { type: "intersect", srcs = { 1, 3 }}
             Ok(Filterset::And(item.get("srcs")?))
         }
-        "union" => Ok(Filterset::And(item.get("srcs")?)),
+        "union" => Ok(Filterset::Or(item.get("srcs")?)),
         "invert" => Ok(Filterset::Invert(item.get("src")?, item.get("universe")?)),
+        "xor" => Ok(Filterset::Xor(item.get("srcs")?)),
         x => Err(anyhow::anyhow!("Unknown filterset item type {x}").into_lua_err()),
     }
 }
 
 pub struct EnMatcher<'a, L: LogProvider> {
     pub log: &'a L,
+    /// Spans tested so far, see [QueryStats::spans_scanned].
+    pub spans_scanned: Cell<u64>,
+    /// Predicates evaluated so far, see [QueryStats::predicates_evaluated].
+    pub predicates_evaluated: Cell<u64>,
+    /// Computed once, by the first predicate on a field in it.
+    tree_fields: OnceCell<TreeFields>,
+    /// The first error a [Matcher] method ran into, see [EnMatcher::take_error].
+    error: RefCell<Option<anyhow::Error>>,
+}
+impl<'a, L: LogProvider> EnMatcher<'a, L> {
+    pub fn new(log: &'a L) -> Self {
+        Self {
+            log,
+            spans_scanned: Cell::new(0),
+            predicates_evaluated: Cell::new(0),
+            tree_fields: OnceCell::new(),
+            error: RefCell::new(None),
+        }
+    }
+    fn count(&self, spans: u64, predicates: u64) {
+        self.spans_scanned.set(self.spans_scanned.get() + spans);
+        self.predicates_evaluated.set(self.predicates_evaluated.get() + predicates);
+    }
+    /// The [Matcher] methods can't fail, so they keep their first error here, and return an
+    /// empty set. Check this after materializing.
+    pub fn take_error(&self) -> Option<anyhow::Error> {
+        self.error.borrow_mut().take()
+    }
+    fn fail(&self, error: anyhow::Error) -> Roaring {
+        self.error.borrow_mut().get_or_insert(error);
+        Roaring::new()
+    }
+    /// Like [predicate_to_en_predicate], but computed fields of the whole tree are looked up in
+    /// [TreeFields], instead of walking the tree for every span.
+    fn prepare<'p>(&'p self, predicate: &'p Predicate<EnValue>) -> anyhow::Result<EnPredicate<'p>> {
+        let mut prepared = predicate_to_en_predicate(predicate);
+        if prepared.kind == TargetKind::Computed
+            && matches!(prepared.target, "depth" | "subtree_size")
+        {
+            if self.tree_fields.get().is_none() {
+                self.tree_fields.set(TreeFields::of(self.log)?).ok();
+            }
+            prepared.tree_field = self.tree_fields.get().and_then(|x| x.field(prepared.target));
+        }
+        Ok(prepared)
+    }
+    /// The first `limit` ids of `input` matching `predicate`, in id order.
+    ///
+    /// Unlike [Matcher::subset_matching], this stops scanning at the `limit`th match.
+    pub fn first_matching(
+        &self, predicate: &Predicate<EnValue>, input: &Roaring, limit: usize,
+    ) -> anyhow::Result<Vec<u32>> {
+        let predicate = self.prepare(predicate)?;
+        let mut res = vec![];
+        let mut scanned = 0;
+        for id in input.iter() {
+            if res.len() >= limit {
+                break;
+            }
+            scanned += 1;
+            if predicate.matches(self.log, id)? {
+                res.push(id);
+            }
+        }
+        self.count(scanned, scanned);
+        Ok(res)
+    }
 }
 pub struct EnPredicate<'a> {
     target: &'a str,
-    target_is_meta: bool,
-    rel: Ordering,
+    kind: TargetKind,
+    rel: Relation,
     con: &'a EnValue,
+    /// The members of a [Relation::In] predicate.
+    set: ValueSet<'a>,
+    /// The values of a computed `target` by span id, if they were computed up front.
+    tree_field: Option<&'a [u64]>,
 }
-pub fn predicate_to_en_predicate<'a>(p: &'a Predicate<EnValue>) -> EnPredicate<'a> {
-    let Predicate { attr, rel, constant: con } = p;
-    let mut target = attr.as_str();
-    let mut target_is_meta = false;
+impl EnPredicate<'_> {
+    pub fn matches(&self, log: &impl LogProvider, id: u32) -> anyhow::Result<bool> {
+        if let Some(&value) = self.tree_field.and_then(|x| x.get(id as usize)) {
+            return Ok(match self.rel {
+                Relation::In => self.set.contains(&EnValueRef::U64(value)),
+                rel => computed_matches(value, rel, self.con),
+            });
+        }
+        match self.rel {
+            Relation::In => span_matches_set(log, id, self.target, self.kind, &self.set),
+            rel => span_matches_filter(log, id, self.target, self.kind, rel, self.con),
+        }
+    }
+}
+/// Split a filter target into its kind and the name without the `meta.` or `en.` prefix.
+pub fn split_target(target: &str) -> (TargetKind, &str) {
     if let Some(stripped) = target.strip_prefix("meta.") {
-        target = stripped;
-        target_is_meta = true;
+        (TargetKind::Meta, stripped)
+    } else if let Some(stripped) = target.strip_prefix("en.") {
+        (TargetKind::Computed, stripped)
+    } else {
+        (TargetKind::Attr, target)
     }
-    EnPredicate { target, target_is_meta, rel: *rel, con }
 }
-impl<L: LogProvider> Matcher<EnValue> for EnMatcher<'_, L> {
+pub fn predicate_to_en_predicate<'a>(p: &'a Predicate<EnValue>) -> EnPredicate<'a> {
+    let Predicate { attr, rel, constant: con, members } = p;
+    let (kind, target) = split_target(attr);
+    EnPredicate { target, kind, rel: *rel, con, set: ValueSet::new(members), tree_field: None }
+}
+/// With the `parallel` feature, large inputs of [Matcher::subset_matching_dnf] are matched on all
+/// cores, so the log has to be [Sync].
+impl<L: LogProvider + Sync> Matcher<EnValue> for EnMatcher<'_, L> {
     fn subset_matching(&self, predicate: &Predicate<EnValue>, input: &Roaring) -> Roaring {
         let mut res = input.clone();
-        let EnPredicate { target, target_is_meta, rel, con } = predicate_to_en_predicate(predicate);
+        let predicate = match self.prepare(predicate) {
+            Ok(x) => x,
+            Err(y) => return self.fail(y),
+        };
         for id in input.iter() {
-            let matches_here = span_matches_filter(self.log, id, target, target_is_meta, rel, con);
-            if !matches_here {
-                res.remove(id);
+            match predicate.matches(self.log, id) {
+                Ok(true) => (),
+                Ok(false) => {
+                    res.remove(id);
+                }
+                Err(y) => return self.fail(y),
             }
         }
+        self.count(input.cardinality(), input.cardinality());
         res
     }
 
@@ -895,62 +1747,317 @@ impl<L: LogProvider> Matcher<EnValue> for EnMatcher<'_, L> {
         I: Iterator<Item = &'a Predicate<EnValue>>,
         EnValue: 'a,
     {
-        let mut res = Roaring::new();
-        let predicates_prepared: Vec<Vec<EnPredicate>> =
-            clauses.map(|x| x.map(|y| predicate_to_en_predicate(y)).collect()).collect();
-        'outer: for id in input.iter() {
-            for anded_clause in predicates_prepared.iter() {
-                let mut matches_in_and = true;
-                for predicate in anded_clause {
-                    let EnPredicate { target, target_is_meta, rel, con } = predicate;
-                    matches_in_and &=
-                        span_matches_filter(self.log, id, target, *target_is_meta, *rel, con)
-                }
-                // OR join -> matches in one anded clause means the whole thing matches
-                if matches_in_and {
-                    res.add(id);
-                    continue 'outer;
+        let predicates_prepared: anyhow::Result<Vec<Vec<EnPredicate>>> =
+            clauses.map(|x| x.map(|y| self.prepare(y)).collect()).collect();
+        let predicates_prepared = match predicates_prepared {
+            Ok(x) => x,
+            Err(y) => return self.fail(y),
+        };
+        #[cfg(feature = "parallel")]
+        if input.cardinality() >= PARALLEL_MIN_SPANS {
+            return match match_dnf_parallel(self.log, &predicates_prepared, input) {
+                Ok((res, predicates_evaluated)) => {
+                    self.count(input.cardinality(), predicates_evaluated);
+                    res
                 }
-            }
+                Err(y) => self.fail(y),
+            };
+        }
+        let mut res = Roaring::new();
+        match match_dnf(self.log, &predicates_prepared, input.iter(), &mut res) {
+            Ok(predicates_evaluated) => self.count(input.cardinality(), predicates_evaluated),
+            Err(y) => return self.fail(y),
         }
         res
     }
 }
+/// Add the `ids` matching one of the and-joined `clauses` to `res`. Returns the number of
+/// predicates evaluated.
+fn match_dnf(
+    log: &impl LogProvider, clauses: &[Vec<EnPredicate>], ids: impl Iterator<Item = u32>,
+    res: &mut Roaring,
+) -> anyhow::Result<u64> {
+    let mut predicates_evaluated = 0;
+    'outer: for id in ids {
+        for anded_clause in clauses {
+            let mut matches_in_and = true;
+            for predicate in anded_clause {
+                matches_in_and &= predicate.matches(log, id)?
+            }
+            predicates_evaluated += anded_clause.len() as u64;
+            // OR join -> matches in one anded clause means the whole thing matches
+            if matches_in_and {
+                res.add(id);
+                continue 'outer;
+            }
+        }
+    }
+    Ok(predicates_evaluated)
+}
+/// The ids of one roaring container, the unit of work of [match_dnf_parallel].
+#[cfg(feature = "parallel")]
+const PARALLEL_BLOCK: u32 = 1 << 16;
+/// Smaller inputs are matched on the calling thread, as handing the blocks to the pool would
+/// take longer.
+#[cfg(feature = "parallel")]
+pub const PARALLEL_MIN_SPANS: u64 = 4 * PARALLEL_BLOCK as u64;
+/// Like [match_dnf], but split `input` into blocks of [PARALLEL_BLOCK] ids, which are matched on
+/// the global rayon pool, and union their results. Returns the result and the number of
+/// predicates evaluated.
+#[cfg(feature = "parallel")]
+fn match_dnf_parallel(
+    log: &(impl LogProvider + Sync), clauses: &[Vec<EnPredicate>], input: &Roaring,
+) -> anyhow::Result<(Roaring, u64)> {
+    use rayon::prelude::*;
+    let (Some(min), Some(max)) = (input.minimum(), input.maximum()) else {
+        return Ok((Roaring::new(), 0));
+    };
+    let blocks = (min / PARALLEL_BLOCK)..=(max / PARALLEL_BLOCK);
+    blocks
+        .into_par_iter()
+        .map(|block| {
+            let end = (block as u64 + 1) * PARALLEL_BLOCK as u64;
+            let mut ids = input.iter();
+            ids.reset_at_or_after(block * PARALLEL_BLOCK);
+            let ids = ids.take_while(|x| (*x as u64) < end);
+            let mut res = Roaring::new();
+            let predicates_evaluated = match_dnf(log, clauses, ids, &mut res)?;
+            Ok((res, predicates_evaluated))
+        })
+        .try_reduce(
+            || (Roaring::new(), 0),
+            |mut total, (res, predicates_evaluated)| {
+                total.0.or_inplace(&res);
+                total.1 += predicates_evaluated;
+                Ok(total)
+            },
+        )
+}
 /// construct an Evaluator that can evaluate the expression in filterset.
 /// You need to normalize and eval yourself.
 pub fn construct_evaluator(
-    filterset: &Table, nitems: u32,
+    filterset: &Table, log: &impl LogProvider,
 ) -> mlua::Result<crate::filtersets::Evaluator<EnValue>> {
-    let mut evaluator = crate::filtersets::Evaluator::new(nitems);
+    let mut evaluator = crate::filtersets::Evaluator::new(log.len() as u32);
     let items: Table = filterset.get("items")?;
     let item_cnt = items.len()?;
 
     for i in 1..=item_cnt {
         let item: Table = items.get(i)?;
-        let fs = item_to_filterset(&item, |p| evaluator.new_predicate(p))?;
+        let fs = item_to_filterset(&item, log, |p| evaluator.new_predicate(p))?;
         evaluator.new_filterset(fs);
     }
 
     Ok(evaluator)
 }
+/// Normalize and materialize `filterset`, adding the work done to `stats`. Intermediate results
+/// are spilled to disk if `spill` is given.
+///
+/// Ids past the end of `log`, which a primitive list or range can contain, are not removed.
+pub fn materialize_filterset(
+    filterset: &Table, log: &(impl LogProvider + Sync), spill: Option<&SpillConfig>,
+    stats: &mut QueryStats,
+) -> mlua::Result<Roaring> {
+    let (mut evaluator, root) = materialize_evaluator(filterset, log, spill, stats)?;
+    Ok(evaluator.results.remove(&root).unwrap_or_default())
+}
+/// Like [materialize_filterset], but return the evaluator with the results of every filterset,
+/// and the id of the root.
+fn materialize_evaluator(
+    filterset: &Table, log: &(impl LogProvider + Sync), spill: Option<&SpillConfig>,
+    stats: &mut QueryStats,
+) -> mlua::Result<(crate::filtersets::Evaluator<EnValue>, usize)> {
+    let (mut evaluator, root) = normalized_evaluator(filterset, log, spill, stats)?;
+    run_materialize(&mut evaluator, root, log, stats)?;
+    Ok((evaluator, root))
+}
+/// Like [materialize_filterset], but look the result up in `cache` first, and store it there.
+fn materialize_cached(
+    filterset: &Table, log: &(impl LogProvider + Sync), cache: &RefCell<MaterializeCache>,
+    trace: &TraceIdentity, spill: Option<&SpillConfig>, stats: &mut QueryStats,
+) -> mlua::Result<Roaring> {
+    let (mut evaluator, root) = normalized_evaluator(filterset, log, spill, stats)?;
+    let key = evaluator.canonical(root);
+    if let Some(result) = cache.borrow_mut().get(trace, log.len(), &key) {
+        return Ok(result.clone());
+    }
+    run_materialize(&mut evaluator, root, log, stats)?;
+    let result = evaluator.results.remove(&root).unwrap_or_default();
+    cache.borrow_mut().insert(trace, log.len(), key, result.clone());
+    Ok(result)
+}
+fn normalized_evaluator(
+    filterset: &Table, log: &impl LogProvider, spill: Option<&SpillConfig>, stats: &mut QueryStats,
+) -> mlua::Result<(crate::filtersets::Evaluator<EnValue>, usize)> {
+    let mut evaluator = construct_evaluator(filterset, log)?;
+    if let Some(SpillConfig { max_bytes, dir }) = spill {
+        evaluator = evaluator.with_spill(*max_bytes, dir.clone());
+    }
+    let root: usize = filterset.get("root")?;
+
+    let nstart = Instant::now();
+    evaluator.normalize(root);
+    stats.normalize += nstart.elapsed();
+    Ok((evaluator, root))
+}
+fn run_materialize(
+    evaluator: &mut crate::filtersets::Evaluator<EnValue>, root: usize,
+    log: &(impl LogProvider + Sync), stats: &mut QueryStats,
+) -> mlua::Result<()> {
+    let mstart = Instant::now();
+    let matcher = EnMatcher::new(log);
+    evaluator.materialize(&matcher, root).map_err(|x| x.into_lua_err())?;
+    if let Some(error) = matcher.take_error() {
+        return Err(error.into_lua_err());
+    }
+    stats.materialize += mstart.elapsed();
+    stats.spans_scanned += matcher.spans_scanned.get();
+    stats.predicates_evaluated += matcher.predicates_evaluated.get();
+    Ok(())
+}
+/// Add `stats` to the [QueryStats] in the app data of `lua`, if it has one.
+/// See [ReusableLua::take_stats].
+fn record_stats(lua: &Lua, stats: &QueryStats) {
+    if let Some(mut total) = lua.app_data_mut::<QueryStats>() {
+        total.merge(stats);
+    }
+}
+/// The matching ids, and the result sizes if they were requested.
+type MaterializeOutput = (Table, Option<Table>);
 #[doc = include_str!("../api-docs/en_filterset_materialize.md")]
 pub fn en_filterset_materialize(
-    log: &impl LogProvider, lua: &Lua,
-) -> impl Fn(Table) -> mlua::Result<Table> {
+    log: &(impl LogProvider + Sync), lua: &Lua,
+) -> impl Fn((Table, Option<Table>)) -> mlua::Result<MaterializeOutput> {
+    |(filterset, options): (Table, Option<Table>)| {
+        let want_sizes = match options {
+            Some(options) => options.get::<Option<bool>>("sizes")?.unwrap_or(false),
+            None => false,
+        };
+        let mut stats = QueryStats::default();
+        // the sizes are of intermediate results, which are not cached
+        let cache = lua.app_data_ref::<Rc<RefCell<MaterializeCache>>>().map(|x| Rc::clone(&x));
+        let trace = lua.app_data_ref::<TraceIdentity>().map(|x| x.clone());
+        let spill = spill_config(lua);
+        if let (Some(cache), Some(trace), false) = (cache, trace, want_sizes) {
+            let results =
+                materialize_cached(&filterset, log, &cache, &trace, spill.as_ref(), &mut stats)?;
+            record_stats(lua, &stats);
+            return Ok((lua.create_sequence_from(results.iter())?, None));
+        }
+        let (mut evaluator, root) =
+            materialize_evaluator(&filterset, log, spill.as_ref(), &mut stats)?;
+        record_stats(lua, &stats);
+
+        let sizes = if want_sizes {
+            let sizes = lua.create_table()?;
+            let mut result_sizes: Vec<_> = evaluator.result_sizes().into_iter().collect();
+            result_sizes.sort_unstable();
+            for (id, size) in result_sizes {
+                let entry = lua.create_table()?;
+                entry.set("id", id)?;
+                entry.set("size", size)?;
+                entry.set("filterset", evaluator.describe(id))?;
+                sizes.push(entry)?;
+            }
+            Some(sizes)
+        } else {
+            None
+        };
+        let results = evaluator.results.remove(&root).unwrap_or_default();
+        let tstart = Instant::now();
+        let table = lua.create_sequence_from(results.iter())?;
+        eprintln!("allocating results table to lua took {:?}", tstart.elapsed());
+        Ok((table, sizes))
+    }
+}
+#[doc = include_str!("../api-docs/en_where_limit.md")]
+pub fn en_where_limit(
+    log: &(impl LogProvider + Sync), lua: &Lua,
+) -> impl Fn((Table, Table, usize)) -> mlua::Result<Vec<u32>> {
+    |(source, filter, limit): (Table, Table, usize)| {
+        let predicate = parse_predicate(&filter)?;
+        let mut stats = QueryStats::default();
+        let mut source =
+            materialize_filterset(&source, log, spill_config(lua).as_ref(), &mut stats)?;
+        // inverting can produce ids past the end
+        source.remove_range(log.len() as u32..);
+
+        let mstart = Instant::now();
+        let matcher = EnMatcher::new(log);
+        let ids =
+            matcher.first_matching(&predicate, &source, limit).map_err(|x| x.into_lua_err())?;
+        stats.materialize += mstart.elapsed();
+        stats.spans_scanned += matcher.spans_scanned.get();
+        stats.predicates_evaluated += matcher.predicates_evaluated.get();
+        record_stats(lua, &stats);
+        Ok(ids)
+    }
+}
+#[doc = include_str!("../api-docs/en_filter_lua.md")]
+pub fn en_filter_lua(
+    log: &(impl LogProvider + Sync), lua: &Lua,
+) -> impl Fn((Table, mlua::Function)) -> mlua::Result<Vec<u32>> {
+    |(source, predicate): (Table, mlua::Function)| {
+        let mut stats = QueryStats::default();
+        let mut source =
+            materialize_filterset(&source, log, spill_config(lua).as_ref(), &mut stats)?;
+        // inverting can produce ids past the end
+        source.remove_range(log.len() as u32..);
+
+        let mstart = Instant::now();
+        let mut ids = vec![];
+        for id in source.iter() {
+            // an error stops the scan, and is returned as is
+            if predicate.call::<bool>(id)? {
+                ids.push(id);
+            }
+        }
+        stats.materialize += mstart.elapsed();
+        stats.spans_scanned += source.cardinality();
+        stats.predicates_evaluated += source.cardinality();
+        record_stats(lua, &stats);
+        Ok(ids)
+    }
+}
+#[doc = include_str!("../api-docs/en_filterset_explain.md")]
+pub fn en_filterset_explain(
+    log: &impl LogProvider, _lua: &Lua,
+) -> impl Fn(Table) -> mlua::Result<Vec<String>> {
     |filterset: Table| {
-        let mut evaluator = construct_evaluator(&filterset, log.len() as u32)?;
-
-        let nstart = Instant::now();
+        // the evaluator is built from the table, so the filterset itself is left alone
+        let mut evaluator = construct_evaluator(&filterset, log)?;
         let root: usize = filterset.get("root")?;
-        evaluator.normalize(root);
-        eprintln!("normalization took {:?}", nstart.elapsed());
-        let matcher = EnMatcher { log };
-        evaluator.materialize(&matcher, root);
+        Ok(evaluator.explain(root).iter().map(|x| x.to_string()).collect())
+    }
+}
 
-        let tstart = Instant::now();
-        let table = lua.create_sequence_from(evaluator.results[&root].iter())?;
-        eprintln!("allocating results table to lua took {:?}", tstart.elapsed());
-        Ok(table)
+/// Turn the value returned by a query into a list of span ids.
+///
+/// Accepts a single span id, a list of span ids, or a filterset, which is materialized. Materializing
+/// is recorded like in [en_filterset_materialize].
+pub fn lua_result_to_ids(
+    result: Value, lua: &Lua, log: &(impl LogProvider + Sync),
+) -> Result<Vec<u32>, QueryError> {
+    match result {
+        Value::Integer(id) => Ok(vec![u32::try_from(id).map_err(|_| QueryError::FailedToCoerce)?]),
+        // LuaJIT numbers are doubles, so accept an integral one too
+        Value::Number(n) if n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&n) => {
+            Ok(vec![n as u32])
+        }
+        Value::Table(table) => {
+            if let Ok(s) = table.get::<String>("type")
+                && s == "filterset"
+            {
+                let mut stats = QueryStats::default();
+                let results =
+                    materialize_filterset(&table, log, spill_config(lua).as_ref(), &mut stats)
+                        .map_err(QueryError::FiltersetEvalFail)?;
+                record_stats(lua, &stats);
+                return Ok(results.iter().collect());
+            }
+            Vec::from_lua(Value::Table(table), lua).map_err(|_| QueryError::FailedToCoerce)
+        }
+        _ => Err(QueryError::FailedToCoerce),
     }
 }
 
@@ -996,22 +2103,43 @@ pub fn en_join(joinable: Arc<JoinCtx>) -> impl Fn(Table) -> LogProviderResult<Ve
         Ok(old_results)
     }
 }
+/// `$join_ctx_of` and `$range_of` are closures returning the current join context and span range,
+/// so they can be swapped between runs, or an error if there is none, see [ReusableLua].
 macro_rules! lua_setup_with_wrappers {
-    ($lua: expr, $trace: expr, $finder_cache: expr, $join_ctx: expr, $range: expr, $lua_wrap: ident, $lua_wrap2: ident) => {
+    ($lua: expr, $trace: expr, $join_ctx_of: expr, $range_of: expr, $lua_wrap: ident, $lua_wrap2: ident) => {
         let globals = $lua.globals();
-        let (range2, range3) = ($range.clone(), $range.clone());
-        let en_range = $lua.create_function(move |_state, _: ()| en_span_range(&range2));
+        let (range2, range3) = ($range_of.clone(), $range_of);
+        let en_range = $lua.create_function(move |_state, _: ()| en_span_range(&range2()?));
         globals.set("en_span_range", en_range?)?;
         globals.set(
             "en_foreach",
-            $lua.create_function(move |lua: &Lua, f: mlua::Function| en_foreach(lua, &range3, f))?,
+            $lua.create_function(move |lua: &Lua, f: mlua::Function| {
+                en_foreach(lua, &range3()?, f)
+            })?,
         )?;
         globals.set("en_log", $lua.create_function(move |_, x| en_log(x))?)?;
         globals.set("en_pretty_table", $lua.create_function(move |_, t| en_pretty_table(t))?)?;
         let t = $trace.clone();
         globals.set("en_children", $lua.create_function($lua_wrap!(t, u32, en_children))?)?;
         globals.set("en_child_cnt", $lua.create_function($lua_wrap!(t, u32, en_child_cnt))?)?;
+        globals.set("en_parent", $lua.create_function($lua_wrap!(t, u32, en_parent))?)?;
+        globals.set("en_ancestors", $lua.create_function($lua_wrap!(t, u32, en_ancestors))?)?;
+        globals.set("en_on_path_to", $lua.create_function($lua_wrap!(t, u32, en_on_path_to))?)?;
+        globals.set("en_depth", $lua.create_function($lua_wrap!(t, u32, en_depth))?)?;
+        globals.set("en_subtree", $lua.create_function($lua_wrap!(t, u32, en_subtree))?)?;
+        globals.set(
+            "en_descendant_count",
+            $lua.create_function($lua_wrap!(t, u32, en_descendant_count))?,
+        )?;
+        let child_events = $lua.create_function($lua_wrap!(t, u32, en_child_events))?;
+        globals.set("en_child_events", child_events)?;
+        let child_spans = $lua.create_function($lua_wrap!(t, u32, en_child_spans))?;
+        globals.set("en_child_spans", child_spans)?;
+        let follows_from = $lua.create_function($lua_wrap!(t, u32, en_follows_from))?;
+        globals.set("en_follows_from", follows_from)?;
         globals.set("en_span_cnt", $lua.create_function($lua_wrap!(t, (), en_span_cnt))?)?;
+        globals.set("en_message", $lua.create_function($lua_wrap!(t, u32, en_message))?)?;
+        globals.set("en_header_text", $lua.create_function($lua_wrap!(t, u32, en_header_text))?)?;
         globals.set(
             "en_metadata_table",
             $lua.create_function($lua_wrap2!(t, u32, en_metadata_table))?,
@@ -1046,6 +2174,10 @@ macro_rules! lua_setup_with_wrappers {
             "en_attr_by_name",
             $lua.create_function($lua_wrap2!(t, (u32, String), en_attr_by_name))?,
         )?;
+        globals.set(
+            "en_attr_all",
+            $lua.create_function($lua_wrap2!(t, (u32, String), en_attr_all))?,
+        )?;
         globals.set(
             "en_attr_name",
             $lua.create_function($lua_wrap2!(t, (u32, usize), en_attr_name))?,
@@ -1054,25 +2186,80 @@ macro_rules! lua_setup_with_wrappers {
             "en_attr_value",
             $lua.create_function($lua_wrap2!(t, (u32, usize), en_attr_value))?,
         )?;
+        globals.set("en_attr_cnt", $lua.create_function($lua_wrap!(t, u32, en_attr_cnt))?)?;
+        globals.set(
+            "en_attr_pairs_range",
+            $lua.create_function($lua_wrap2!(t, (u32, usize, Option<usize>), en_attr_pairs_range))?,
+        )?;
+        globals
+            .set("en_attr_universe", $lua.create_function($lua_wrap!(t, (), en_attr_universe))?)?;
+        globals.set(
+            "en_attr_cardinality",
+            $lua.create_function($lua_wrap!(t, String, en_attr_cardinality))?,
+        )?;
+        globals.set(
+            "en_attr_is_debug",
+            $lua.create_function($lua_wrap!(t, (u32, String), en_attr_is_debug))?,
+        )?;
+        globals.set(
+            "en_aggregate_children",
+            $lua.create_function($lua_wrap!(t, (u32, String, String), en_aggregate_children))?,
+        )?;
+        let deepest_path = $lua.create_function($lua_wrap!(t, (), en_deepest_path))?;
+        globals.set("en_deepest_path", deepest_path)?;
+        globals.set("en_span_path", $lua.create_function($lua_wrap!(t, u32, en_span_path))?)?;
+        globals.set(
+            "en_resolve_path",
+            $lua.create_function($lua_wrap!(t, String, en_resolve_path))?,
+        )?;
         globals.set("en_as_string", $lua.create_function($lua_wrap!(t, u32, en_as_string))?)?;
+        globals.set("en_as_oneline", $lua.create_function($lua_wrap!(t, u32, en_as_oneline))?)?;
+        globals.set("en_rank", $lua.create_function(en_rank)?)?;
+        globals.set(
+            "en_rank_by_attr",
+            $lua.create_function($lua_wrap!(t, (Vec<u32>, String), en_rank_by_attr))?,
+        )?;
+        globals.set(
+            "en_rank_by_depth",
+            $lua.create_function($lua_wrap!(t, Vec<u32>, en_rank_by_depth))?,
+        )?;
         let t = $trace.clone();
 
         globals.set("en_filterset_from_list", $lua.create_function(en_filterset_from_list)?)?;
+        globals.set("en_filterset_path_to", $lua.create_function(en_filterset_path_to)?)?;
+        globals.set(
+            "en_filterset_from_subtree",
+            $lua.create_function($lua_wrap2!(t, u32, en_filterset_from_subtree))?,
+        )?;
         globals.set("en_filterset_from_range", $lua.create_function(en_filterset_from_range)?)?;
         globals.set("en_filter", $lua.create_function(en_filter)?)?;
         globals.set("en_filterset_union", $lua.create_function(en_filterset_union)?)?;
         globals.set("en_filterset_intersect", $lua.create_function(en_filterset_intersect)?)?;
         globals.set("en_filterset_dnf", $lua.create_function(en_filterset_dnf)?)?;
         globals.set("en_filterset_invert", $lua.create_function(en_filterset_invert)?)?;
+        globals.set("en_filterset_xor", $lua.create_function(en_filterset_xor)?)?;
+        globals.set("en_filterset_subtract", $lua.create_function(en_filterset_subtract)?)?;
         globals.set(
             "en_filterset_materialize",
-            $lua.create_function($lua_wrap2!(t, Table, en_filterset_materialize))?,
+            $lua.create_function($lua_wrap2!(t, (Table, Option<Table>), en_filterset_materialize))?,
         )?;
-        let join_fn = en_join($join_ctx);
+        globals.set(
+            "en_filterset_explain",
+            $lua.create_function($lua_wrap2!(t, Table, en_filterset_explain))?,
+        )?;
+        globals.set(
+            "en_where_limit",
+            $lua.create_function($lua_wrap2!(t, (Table, Table, usize), en_where_limit))?,
+        )?;
+        globals.set(
+            "en_filter_lua",
+            $lua.create_function($lua_wrap2!(t, (Table, mlua::Function), en_filter_lua))?,
+        )?;
+        let join_ctx_of = $join_ctx_of;
         globals.set(
             "en_join",
             $lua.create_function(move |_: &Lua, results: Table| {
-                join_fn(results).map_err(to_lua_err)
+                en_join(join_ctx_of()?)(results).map_err(to_lua_err)
             })?,
         )?;
     };
@@ -1098,13 +2285,101 @@ impl Default for ReusableString {
         Self::new()
     }
 }
+/// Results of [en_filterset_materialize] by the
+/// [canonical](crate::filtersets::Evaluator::canonical) normalized filterset, so materializing the
+/// same filterset again is a lookup instead of a scan. See [LuaEvalState::with_materialize_cache].
+///
+/// A cache holds the results of one trace, and drops them when it is asked about another one, or
+/// when the length of the trace changes, as the new spans of a live trace can match too.
+/// [ReusableLua] keeps one per Lua state, so the query threads of the GUI use it.
+#[derive(Default)]
+pub struct MaterializeCache {
+    /// The trace the results are for, and its [LogProvider::len] then.
+    trace: Option<(TraceIdentity, usize)>,
+    results: HashMap<CanonicalFilterset, Roaring>,
+}
+impl MaterializeCache {
+    /// The cache is cleared when it has this many results, as every query typed while
+    /// live-querying would add one.
+    pub const MAX_RESULTS: usize = 64;
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The result of `filterset`, if it was materialized on `trace` when it had `trace_len`
+    /// spans.
+    pub fn get(
+        &mut self, trace: &TraceIdentity, trace_len: usize, filterset: &CanonicalFilterset,
+    ) -> Option<&Roaring> {
+        self.bust(trace, trace_len);
+        self.results.get(filterset)
+    }
+    pub fn insert(
+        &mut self, trace: &TraceIdentity, trace_len: usize, filterset: CanonicalFilterset,
+        result: Roaring,
+    ) {
+        self.bust(trace, trace_len);
+        if self.results.len() >= Self::MAX_RESULTS {
+            self.results.clear();
+        }
+        self.results.insert(filterset, result);
+    }
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+    fn bust(&mut self, trace: &TraceIdentity, trace_len: usize) {
+        let same = |(t, len): &(TraceIdentity, usize)| t.is(trace) && *len == trace_len;
+        if !self.trace.as_ref().is_some_and(same) {
+            self.results.clear();
+            self.trace = Some((trace.clone(), trace_len));
+        }
+    }
+}
+/// Where and above how many bytes materializing spills intermediate results to disk, see
+/// [Evaluator::with_spill](crate::filtersets::Evaluator::with_spill) and
+/// [LuaEvalState::with_spill].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpillConfig {
+    pub max_bytes: usize,
+    pub dir: PathBuf,
+}
+/// The [SpillConfig] of the queries in `lua`, if they spill.
+fn spill_config(lua: &Lua) -> Option<SpillConfig> {
+    lua.app_data_ref::<SpillConfig>().map(|x| x.clone())
+}
+/// Tells traces apart for a [MaterializeCache]. It doesn't keep the trace alive, but as it is a
+/// [Weak] reference, no other trace can take the address of the trace while it exists.
+#[derive(Clone)]
+pub struct TraceIdentity(Weak<dyn Any + Send + Sync>);
+impl TraceIdentity {
+    pub fn of<T: Any + Send + Sync>(trace: &Arc<T>) -> Self {
+        let trace: Weak<T> = Arc::downgrade(trace);
+        Self(trace)
+    }
+    /// Whether both are of the same trace.
+    pub fn is(&self, other: &TraceIdentity) -> bool {
+        Weak::ptr_eq(&self.0, &other.0)
+    }
+}
 pub struct LuaEvalState {
     pub join_ctx: Arc<JoinCtx>,
     pub range: RangeInclusive<u32>,
     pub finder_cache: Rc<RefCell<HashMap<String, Finder<'static>>>>,
     /// used to make throwaway allocations like en_contains_anywhere faster
     pub reusable_buf: Rc<RefCell<ReusableString>>,
+    /// Registers the functions of the embedder, see [LuaEvalState::with_extension].
+    pub extension: Option<LuaExtension>,
+    /// See [LuaEvalState::with_materialize_cache].
+    pub materialize_cache: Option<Rc<RefCell<MaterializeCache>>>,
+    /// See [LuaEvalState::with_spill].
+    pub spill: Option<SpillConfig>,
 }
+/// A callback which registers custom functions in the query [Lua], like a decoder for a format of
+/// your domain. It is called after the built-in functions are registered, so it can use or replace
+/// them.
+pub type LuaExtension = Arc<dyn Fn(&Lua) -> mlua::Result<()> + Send + Sync>;
 impl LuaEvalState {
     pub fn new(
         join_ctx: Arc<JoinCtx>, range: RangeInclusive<u32>,
@@ -1115,8 +2390,34 @@ impl LuaEvalState {
             range,
             finder_cache,
             reusable_buf: Rc::new(RefCell::new(ReusableString::new())),
+            extension: None,
+            materialize_cache: None,
+            spill: None,
         }
     }
+    /// Call `extension` when setting up the Lua state with [setup_lua_on_arc_rwlock] or
+    /// [setup_lua_no_lock].
+    /// ```rust,ignore
+    /// let state = LuaEvalState::new(join_ctx, range, finder_cache).with_extension(Arc::new(|lua| {
+    ///     lua.globals().set("double", lua.create_function(|_, x: u32| Ok(x * 2))?)
+    /// }));
+    /// ```
+    pub fn with_extension(mut self, extension: LuaExtension) -> Self {
+        self.extension = Some(extension);
+        self
+    }
+    /// Reuse the results of [en_filterset_materialize] from `cache`, see [MaterializeCache]. Share
+    /// the cache between the Lua states of a session to keep the results across queries.
+    pub fn with_materialize_cache(mut self, cache: Rc<RefCell<MaterializeCache>>) -> Self {
+        self.materialize_cache = Some(cache);
+        self
+    }
+    /// Spill the intermediate results of materializing filtersets to disk as configured by
+    /// `spill`, to bound the memory of queries on huge traces.
+    pub fn with_spill(mut self, spill: SpillConfig) -> Self {
+        self.spill = Some(spill);
+        self
+    }
 }
 pub fn setup_lua_on_arc_rwlock(
     lua: &mut Lua, trace: Arc<RwLock<LogProviderImpl>>, state: LuaEvalState,
@@ -1144,19 +2445,49 @@ pub fn setup_lua_on_arc_rwlock(
             }
         }};
     }
-    let LuaEvalState { join_ctx, range, finder_cache, reusable_buf } = state;
+    let LuaEvalState {
+        join_ctx,
+        range,
+        finder_cache,
+        reusable_buf,
+        extension,
+        materialize_cache,
+        spill,
+    } = state;
+    if let Some(cache) = materialize_cache {
+        lua.set_app_data(cache);
+        lua.set_app_data(TraceIdentity::of(&trace));
+    }
+    if let Some(spill) = spill {
+        lua.set_app_data(spill);
+    }
     let t = trace.clone();
+    let (cache, buf) = (finder_cache.clone(), reusable_buf.clone());
     lua.globals().set(
         "en_contains_anywhere",
         lua.create_function(move |_lua: &Lua, (id, needle): (u32, String)| {
             let log = t.read().unwrap();
-            en_contains_anywhere(&*log, finder_cache.clone(), reusable_buf.clone())((id, needle))
+            en_contains_anywhere(&*log, cache.clone(), buf.clone())((id, needle))
+                .map_err(to_lua_err)
+        })?,
+    )?;
+    let t = trace.clone();
+    lua.globals().set(
+        "en_contains_anywhere_ci",
+        lua.create_function(move |_lua: &Lua, (id, needle): (u32, String)| {
+            let log = t.read().unwrap();
+            en_contains_anywhere_ci(&*log, finder_cache.clone(), reusable_buf.clone())((id, needle))
                 .map_err(to_lua_err)
         })?,
     )?;
 
-    lua_setup_with_wrappers!(lua, trace, finder_cache, join_ctx, range, lua_wrap, lua_wrap2);
-    Ok(())
+    let join_ctx_of = move || mlua::Result::Ok(join_ctx.clone());
+    let range_of = move || mlua::Result::Ok(range.clone());
+    lua_setup_with_wrappers!(lua, trace, join_ctx_of, range_of, lua_wrap, lua_wrap2);
+    match extension {
+        Some(extension) => extension(lua),
+        None => Ok(()),
+    }
 }
 
 pub fn setup_lua_no_lock(
@@ -1179,15 +2510,197 @@ pub fn setup_lua_no_lock(
             move |lua: &Lua, a: $arg| $fn(&*tp, lua)(a)
         }};
     }
-    let LuaEvalState { join_ctx, range, finder_cache, reusable_buf } = state;
+    let LuaEvalState {
+        join_ctx,
+        range,
+        finder_cache,
+        reusable_buf,
+        extension,
+        materialize_cache,
+        spill,
+    } = state;
+    if let Some(cache) = materialize_cache {
+        lua.set_app_data(cache);
+        lua.set_app_data(TraceIdentity::of(&trace));
+    }
+    if let Some(spill) = spill {
+        lua.set_app_data(spill);
+    }
     let t = trace.clone();
+    let (cache, buf) = (finder_cache.clone(), reusable_buf.clone());
     lua.globals().set(
         "en_contains_anywhere",
         lua.create_function(move |_lua: &Lua, (id, needle): (u32, String)| {
-            en_contains_anywhere(&*t, finder_cache.clone(), reusable_buf.clone())((id, needle))
+            en_contains_anywhere(&*t, cache.clone(), buf.clone())((id, needle)).map_err(to_lua_err)
+        })?,
+    )?;
+    let t = trace.clone();
+    lua.globals().set(
+        "en_contains_anywhere_ci",
+        lua.create_function(move |_lua: &Lua, (id, needle): (u32, String)| {
+            en_contains_anywhere_ci(&*t, finder_cache.clone(), reusable_buf.clone())((id, needle))
                 .map_err(to_lua_err)
         })?,
     )?;
-    lua_setup_with_wrappers!(lua, trace, finder_cache, join_ctx, range, lua_wrap, lua_wrap2);
-    Ok(())
+    let join_ctx_of = move || mlua::Result::Ok(join_ctx.clone());
+    let range_of = move || mlua::Result::Ok(range.clone());
+    lua_setup_with_wrappers!(lua, trace, join_ctx_of, range_of, lua_wrap, lua_wrap2);
+    match extension {
+        Some(extension) => extension(lua),
+        None => Ok(()),
+    }
+}
+
+/// What a [ReusableLua] evaluates the next query against.
+pub struct LuaBindings {
+    pub trace: Arc<RwLock<LogProviderImpl>>,
+    pub join_ctx: Arc<JoinCtx>,
+    pub range: RangeInclusive<u32>,
+}
+/// The bindings of a [ReusableLua], or an error if it is unbound.
+fn bound(bindings: &Option<LuaBindings>) -> mlua::Result<&LuaBindings> {
+    bindings.as_ref().ok_or_else(|| mlua::Error::runtime("No trace to query, it was unbound"))
+}
+/// Run `code` in the global environment of `lua`, so that the functions it defines can be called
+/// from queries. Call this after setting up the API, so the prelude can use it too.
+pub fn load_prelude(lua: &Lua, code: &str) -> mlua::Result<()> {
+    lua.load(code).set_name("@prelude.lua").exec()
+}
+/// A Lua state whose API functions are registered once, and whose [LuaBindings] are swapped
+/// between queries with [ReusableLua::rebind].
+///
+/// Registering the API takes longer than evaluating a small query, so long-lived query threads
+/// should keep one of these around instead of calling [setup_lua_on_arc_rwlock] per query.
+///
+/// Each one has its own [MaterializeCache], so materializing the same filterset as an earlier
+/// query on the same trace is a lookup.
+pub struct ReusableLua {
+    lua: Lua,
+    /// `None` between queries, see [ReusableLua::unbind].
+    bindings: Rc<RefCell<Option<LuaBindings>>>,
+    /// The trace of the last bindings, to tell whether the next query is on the same one.
+    last_trace: RefCell<Weak<RwLock<LogProviderImpl>>>,
+    finder_cache: Rc<RefCell<HashMap<String, Finder<'static>>>>,
+    /// The prelude last passed to [ReusableLua::set_prelude].
+    prelude: Option<Arc<str>>,
+}
+impl ReusableLua {
+    pub fn new(bindings: LuaBindings) -> mlua::Result<Self> {
+        macro_rules! lua_wrap {
+            ($bindings: expr, $arg: ty, $fn: expr) => {{
+                let b = $bindings.clone();
+                move |_lua: &Lua, a: $arg| {
+                    let b = b.borrow();
+                    let log = bound(&b)?.trace.read().unwrap();
+                    $fn(&*log)(a).map_err(|x| x.into_lua_err())
+                }
+            }};
+        }
+        macro_rules! lua_wrap2 {
+            ($bindings: expr, $arg: ty, $fn: expr) => {{
+                let b = $bindings.clone();
+                move |lua: &Lua, a: $arg| {
+                    let b = b.borrow();
+                    let log = bound(&b)?.trace.read().unwrap();
+                    $fn(&*log, lua)(a)
+                }
+            }};
+        }
+        let lua = Lua::new();
+        lua.set_app_data(Rc::new(RefCell::new(MaterializeCache::new())));
+        lua.set_app_data(TraceIdentity::of(&bindings.trace));
+        let last_trace = RefCell::new(Arc::downgrade(&bindings.trace));
+        let bindings = Rc::new(RefCell::new(Some(bindings)));
+        let finder_cache = Rc::new(RefCell::new(HashMap::new()));
+        let reusable_buf = Rc::new(RefCell::new(ReusableString::new()));
+        let (b, cache, buf) = (bindings.clone(), finder_cache.clone(), reusable_buf.clone());
+        lua.globals().set(
+            "en_contains_anywhere",
+            lua.create_function(move |_lua: &Lua, (id, needle): (u32, String)| {
+                let b = b.borrow();
+                let log = bound(&b)?.trace.read().unwrap();
+                en_contains_anywhere(&*log, cache.clone(), buf.clone())((id, needle))
+                    .map_err(to_lua_err)
+            })?,
+        )?;
+        let (b, cache) = (bindings.clone(), finder_cache.clone());
+        lua.globals().set(
+            "en_contains_anywhere_ci",
+            lua.create_function(move |_lua: &Lua, (id, needle): (u32, String)| {
+                let b = b.borrow();
+                let log = bound(&b)?.trace.read().unwrap();
+                en_contains_anywhere_ci(&*log, cache.clone(), reusable_buf.clone())((id, needle))
+                    .map_err(to_lua_err)
+            })?,
+        )?;
+        let b = bindings.clone();
+        let join_ctx_of = move || bound(&b.borrow()).map(|x| x.join_ctx.clone());
+        let b = bindings.clone();
+        let range_of = move || bound(&b.borrow()).map(|x| x.range.clone());
+        lua_setup_with_wrappers!(lua, bindings, join_ctx_of, range_of, lua_wrap, lua_wrap2);
+        lua.set_app_data(QueryStats::default());
+        Ok(Self { lua, bindings, last_trace, finder_cache, prelude: None })
+    }
+    /// Evaluate the next query against `bindings`.
+    ///
+    /// The finder cache is cleared if the trace changed, and trimmed when it grows large, as
+    /// needles typed while live-querying would otherwise accumulate. The [MaterializeCache] drops
+    /// its results by itself when the trace changes or grows.
+    pub fn rebind(&self, bindings: LuaBindings) {
+        let mut last_trace = self.last_trace.borrow_mut();
+        let mut finder_cache = self.finder_cache.borrow_mut();
+        let same_trace = std::ptr::eq(last_trace.as_ptr(), Arc::as_ptr(&bindings.trace));
+        if !same_trace || finder_cache.len() > 64 {
+            finder_cache.clear();
+        }
+        *last_trace = Arc::downgrade(&bindings.trace);
+        self.lua.set_app_data(TraceIdentity::of(&bindings.trace));
+        *self.bindings.borrow_mut() = Some(bindings);
+    }
+    /// Drop the bindings until the next [ReusableLua::rebind], so an idle Lua state doesn't keep
+    /// the trace alive. Queries fail until then.
+    pub fn unbind(&self) {
+        self.bindings.borrow_mut().take();
+    }
+    /// Run `prelude` with [load_prelude], unless it is the same as the last one. Functions the
+    /// previous prelude defined are not removed, but the new prelude can redefine them.
+    ///
+    /// If the prelude fails, it is not retried until it changes.
+    pub fn set_prelude(&mut self, prelude: Option<Arc<str>>) -> mlua::Result<()> {
+        if self.prelude.as_deref() == prelude.as_deref() {
+            return Ok(());
+        }
+        self.prelude = prelude.clone();
+        match prelude {
+            Some(code) => load_prelude(&self.lua, &code),
+            None => Ok(()),
+        }
+    }
+    /// Spill the intermediate results of the next queries as configured by `spill`, or keep them
+    /// in memory if it is `None`. See [LuaEvalState::with_spill].
+    pub fn set_spill(&self, spill: Option<SpillConfig>) {
+        match spill {
+            Some(spill) => drop(self.lua.set_app_data(spill)),
+            None => drop(self.lua.remove_app_data::<SpillConfig>()),
+        }
+    }
+    /// Evaluate `code` in a fresh global environment, so globals assigned by one query don't leak
+    /// into the next. The API functions are still visible through it.
+    pub fn eval(&self, code: &str, name: &str) -> mlua::Result<Value> {
+        let env = self.lua.create_table()?;
+        let meta = self.lua.create_table()?;
+        meta.set("__index", self.lua.globals())?;
+        env.set_metatable(Some(meta))?;
+        self.lua.load(code).set_name(name).set_environment(env).eval()
+    }
+    pub fn lua(&self) -> &Lua {
+        &self.lua
+    }
+    /// Take the [QueryStats] recorded since the last call, including by [lua_result_to_ids] when
+    /// called with [ReusableLua::lua].
+    pub fn take_stats(&self) -> QueryStats {
+        let stats = self.lua.remove_app_data::<QueryStats>().unwrap_or_default();
+        self.lua.set_app_data(QueryStats::default());
+        stats
+    }
 }
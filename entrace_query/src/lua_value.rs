@@ -6,7 +6,7 @@ pub struct LuaValueRefRef<'a>(pub &'a EnValueRef<'a>);
 impl mlua::IntoLua for LuaValue {
     fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
         match self.0 {
-            EnValue::String(q) => q.into_lua(lua),
+            EnValue::String(q) | EnValue::Debug(q) => q.into_lua(lua),
             EnValue::Bool(q) => q.into_lua(lua),
             EnValue::Bytes(q) => q.into_lua(lua),
             EnValue::Float(q) => q.into_lua(lua),
@@ -20,7 +20,7 @@ impl mlua::IntoLua for LuaValue {
 impl<'a> mlua::IntoLua for LuaValueRef<'a> {
     fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
         match self.0 {
-            EnValueRef::String(q) => q.into_lua(lua),
+            EnValueRef::String(q) | EnValueRef::Debug(q) => q.into_lua(lua),
             EnValueRef::Bool(q) => q.into_lua(lua),
             EnValueRef::Bytes(q) => q.into_lua(lua),
             EnValueRef::Float(q) => q.into_lua(lua),
@@ -34,7 +34,7 @@ impl<'a> mlua::IntoLua for LuaValueRef<'a> {
 impl<'a> mlua::IntoLua for LuaValueRefRef<'a> {
     fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
         match self.0 {
-            EnValueRef::String(q) => q.into_lua(lua),
+            EnValueRef::String(q) | EnValueRef::Debug(q) => q.into_lua(lua),
             EnValueRef::Bool(q) => q.into_lua(lua),
             EnValueRef::Bytes(q) => q.into_lua(lua),
             EnValueRef::Float(q) => q.into_lua(lua),
@@ -0,0 +1,34 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+
+#[test]
+fn test_aggregate_children() {
+    let mut builder = LogProviderBuilder::new();
+    let server = builder.span(0, "server").add();
+    for latency in [10, 20, 30] {
+        builder.span(server, "request").attr("latency", EnValue::U64(latency)).add();
+    }
+    builder.span(server, "request").attr("latency", EnValue::String("slow".into())).add();
+    builder.span(server, "request").add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let agg = |parent: u32, op: &str| -> mlua::Result<Option<f64>> {
+        lua.load(format!("return en_aggregate_children({parent}, 'latency', '{op}')")).eval()
+    };
+    assert_eq!(agg(server, "sum").unwrap(), Some(60.0));
+    assert_eq!(agg(server, "avg").unwrap(), Some(20.0));
+    assert_eq!(agg(server, "min").unwrap(), Some(10.0));
+    assert_eq!(agg(server, "max").unwrap(), Some(30.0));
+    assert_eq!(agg(server, "count").unwrap(), Some(3.0));
+    // a leaf has no values
+    assert_eq!(agg(server + 1, "avg").unwrap(), None);
+    assert_eq!(agg(server + 1, "count").unwrap(), Some(0.0));
+    let err = agg(server, "median").unwrap_err().to_string();
+    assert!(err.contains("Unknown aggregate"), "{err}");
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::lua_api::lua_result_to_ids;
+
+#[test]
+fn test_parent_and_ancestors() {
+    let mut builder = LogProviderBuilder::new();
+    let outer = builder.span(0, "outer").add();
+    let inner = builder.span(outer, "inner").add();
+    let leaf = builder.span(inner, "leaf").add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let parent: u32 = lua.load(format!("return en_parent({leaf})")).eval().unwrap();
+    assert_eq!(parent, inner);
+    let ancestors: Vec<u32> = lua.load(format!("return en_ancestors({leaf})")).eval().unwrap();
+    assert_eq!(ancestors, vec![0, outer, inner, leaf]);
+    let root: Vec<u32> = lua.load("return en_ancestors(0)").eval().unwrap();
+    assert_eq!(root, vec![0]);
+
+    for query in ["return en_parent(10)", "return en_ancestors(10)"] {
+        let err = lua.load(query).eval::<mlua::Value>().unwrap_err().to_string();
+        assert!(err.contains("Index out of bounds"), "{err}");
+    }
+}
+
+#[test]
+fn test_depth() {
+    let mut builder = LogProviderBuilder::new();
+    let outer = builder.span(0, "outer").add();
+    let inner = builder.span(outer, "inner").add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let depth =
+        |id: u32| -> mlua::Result<u32> { lua.load(format!("return en_depth({id})")).eval() };
+    assert_eq!(depth(0).unwrap(), 0);
+    assert_eq!(depth(outer).unwrap(), 1);
+    assert_eq!(depth(inner).unwrap(), 2);
+    let err = depth(3).unwrap_err().to_string();
+    assert!(err.contains("Index out of bounds"), "{err}");
+}
+
+#[test]
+fn test_subtree() {
+    let mut builder = LogProviderBuilder::new();
+    let a = builder.span(0, "a").add();
+    let b = builder.span(a, "b").add();
+    let c = builder.span(0, "c").add();
+    let d = builder.span(b, "d").add();
+    let e = builder.span(a, "e").add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace.clone());
+
+    let subtree: Vec<u32> = lua.load(format!("return en_subtree({a})")).eval().unwrap();
+    assert_eq!(subtree, vec![a, b, d, e]);
+    let all: Vec<u32> = lua.load("return en_subtree(0)").eval().unwrap();
+    assert_eq!(all, vec![0, a, b, d, e, c]);
+    let leaf: Vec<u32> = lua.load(format!("return en_subtree({c})")).eval().unwrap();
+    assert_eq!(leaf, vec![c]);
+    let err = lua.load("return en_subtree(6)").eval::<mlua::Value>().unwrap_err().to_string();
+    assert!(err.contains("Index out of bounds"), "{err}");
+
+    let fs: mlua::Table =
+        lua.load(format!("return en_filterset_from_subtree({b})")).eval().unwrap();
+    let ids = lua_result_to_ids(mlua::Value::Table(fs), &lua, &*trace).unwrap();
+    assert_eq!(ids, vec![b, d]);
+}
+
+#[test]
+fn test_path_to() {
+    // two chains of 50 nested spans, the second after the first
+    let mut builder = LogProviderBuilder::new();
+    let mut chains = vec![];
+    for _ in 0..2 {
+        let mut chain = vec![0];
+        for d in 0..50 {
+            let parent = *chain.last().unwrap();
+            chain.push(builder.span(parent, &format!("d{d}")).add());
+        }
+        chains.push(chain);
+    }
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace.clone());
+
+    let target = chains[1][30];
+    let path: Vec<u32> = lua.load(format!("return en_on_path_to({target})")).eval().unwrap();
+    assert_eq!(path, chains[1][..=30]);
+    let fs: mlua::Value =
+        lua.load(format!("return en_filterset_path_to({target})")).eval().unwrap();
+    let ids = lua_result_to_ids(fs, &lua, &*trace).unwrap();
+    assert_eq!(ids, chains[1][..=30]);
+    // it composes with other filtersets
+    let fs: mlua::Value = lua
+        .load(format!(
+            "return en_filterset_intersect({{ en_filterset_path_to({target}), \
+             en_filterset_from_range(0, {}) }})",
+            chains[1][10]
+        ))
+        .eval()
+        .unwrap();
+    let ids = lua_result_to_ids(fs, &lua, &*trace).unwrap();
+    assert_eq!(ids, chains[1][..=10]);
+    let root: Vec<u32> = lua.load("return en_on_path_to(0)").eval().unwrap();
+    assert_eq!(root, [0]);
+}
+
+#[test]
+fn test_descendant_count() {
+    let mut builder = LogProviderBuilder::new();
+    let a = builder.span(0, "a").add();
+    let b = builder.span(a, "b").add();
+    builder.span(0, "c").add();
+    builder.span(b, "d").add();
+    builder.span(a, "e").add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let count = |id: u32| -> mlua::Result<usize> {
+        lua.load(format!("return en_descendant_count({id})")).eval()
+    };
+    assert_eq!(count(0).unwrap(), 5);
+    assert_eq!(count(a).unwrap(), 3);
+    assert_eq!(count(b).unwrap(), 1);
+    assert_eq!(count(5).unwrap(), 0);
+    let err = count(6).unwrap_err().to_string();
+    assert!(err.contains("Index out of bounds"), "{err}");
+}
+
+#[test]
+fn test_ancestors_cycle_errors() {
+    let mut builder = LogProviderBuilder::new();
+    let first = builder.span(0, "first").add();
+    let second = builder.span(first, "second").add();
+    let mut trace = builder.build_base();
+    // first -> second -> first, neither reaches the root
+    trace.data[first as usize].parent = second;
+    let lua = lua_for(Arc::new(LogProviderImpl::BaseIET(trace)));
+
+    for query in [
+        format!("return en_ancestors({second})"),
+        format!("return en_on_path_to({first})"),
+        format!("return en_filterset_materialize(en_filterset_path_to({second}))"),
+    ] {
+        let err = lua.load(query).eval::<mlua::Value>().unwrap_err().to_string();
+        assert!(err.contains("has a cycle"), "{err}");
+    }
+}
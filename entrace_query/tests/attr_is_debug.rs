@@ -0,0 +1,32 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+
+#[test]
+fn test_attr_is_debug() {
+    let mut builder = LogProviderBuilder::new();
+    let span = builder
+        .span(0, "request")
+        .attr("name", EnValue::String("Foo { x: 1 }".into()))
+        .attr("value", EnValue::Debug("Foo { x: 1 }".into()))
+        .add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let is_debug = |name: &str| -> Option<bool> {
+        lua.load(format!("return en_attr_is_debug({span}, '{name}')")).eval().unwrap()
+    };
+    assert_eq!(is_debug("name"), Some(false));
+    assert_eq!(is_debug("value"), Some(true));
+    assert_eq!(is_debug("missing"), None);
+    // both read as the same string
+    let same: bool = lua
+        .load(format!("return en_attr_by_name({span}, 'name') == en_attr_by_name({span}, 'value')"))
+        .eval()
+        .unwrap();
+    assert!(same);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+
+#[test]
+fn test_attr_pairs_range() {
+    let mut builder = LogProviderBuilder::new();
+    let mut span = builder.span(0, "span");
+    for i in 0..5 {
+        span = span.attr(&format!("a{i}"), EnValue::U64(i));
+    }
+    span.add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let count: usize = lua.load("return en_attr_cnt(1)").eval().unwrap();
+    assert_eq!(count, 5);
+    let page = |start: usize, count: &str| -> mlua::Result<Vec<String>> {
+        let query = format!(
+            "local res = {{}}
+             for _, pair in ipairs(en_attr_pairs_range(1, {start}, {count})) do
+               res[#res + 1] = pair[1] .. '=' .. pair[2]
+             end
+             return res"
+        );
+        lua.load(query).eval()
+    };
+    assert_eq!(page(0, "2").unwrap(), ["a0=0", "a1=1"]);
+    assert_eq!(page(2, "2").unwrap(), ["a2=2", "a3=3"]);
+    // the last page is short
+    assert_eq!(page(4, "2").unwrap(), ["a4=4"]);
+    assert!(page(0, "0").unwrap().is_empty());
+    // no count is the rest of the attributes
+    assert_eq!(page(1, "nil").unwrap(), ["a1=1", "a2=2", "a3=3", "a4=4"]);
+    assert_eq!(page(5, "nil").unwrap(), Vec::<String>::new());
+    assert_eq!(lua.load("return #en_attr_pairs_range(1, 3)").eval::<usize>().unwrap(), 2);
+    // the largest integer a Lua number holds exactly
+    assert_eq!(page(1, "2^53").unwrap().len(), 4);
+    // starting at the end is an empty page, after it an error
+    assert!(page(5, "2").unwrap().is_empty());
+    let oob = page(6, "1").unwrap_err().to_string();
+    assert!(oob.contains("out of bounds"), "{oob}");
+    assert!(
+        lua.load("return en_attr_pairs_range(0, 0, 1)")
+            .eval::<Vec<mlua::Table>>()
+            .unwrap()
+            .is_empty()
+    );
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+
+/// Spans 1-10 have `i` = 0..10.
+fn lua() -> mlua::Lua {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..10u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i)).add();
+    }
+    lua_for(Arc::new(builder.build_impl()))
+}
+
+fn materialize(lua: &mlua::Lua, filterset: &str) -> Vec<u32> {
+    lua.load(format!("return en_filterset_materialize({filterset})")).eval().unwrap()
+}
+
+// filters built on their own sources, so the items of the second one are moved when the lists
+// are concatenated
+const LOW: &str = r#"en_filter("i", "LT", 3, en_filterset_from_range(1, 10))"#;
+const HIGH: &str = r#"en_filter("i", "GT", 6, en_filterset_from_range(1, 10))"#;
+const MIDDLE: &str =
+    r#"en_filter("i", "LT", 8, en_filter("i", "GT", 1, en_filterset_from_range(1, 10)))"#;
+
+#[test]
+fn test_union_of_separate_filters() {
+    let lua = lua();
+    assert_eq!(
+        materialize(&lua, &format!("en_filterset_union({{{LOW}, {HIGH}}})")),
+        [1, 2, 3, 8, 9, 10]
+    );
+    let ranges =
+        "en_filterset_union({en_filterset_from_range(1, 10), en_filterset_from_range(1, 2)})";
+    assert_eq!(materialize(&lua, ranges), (1..=10).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_intersect_of_separate_filters() {
+    let lua = lua();
+    let query = format!("en_filterset_intersect({{{LOW}, {MIDDLE}}})");
+    assert_eq!(materialize(&lua, &query), [3]);
+    let query = format!("en_filterset_intersect({{{MIDDLE}, {HIGH}}})");
+    assert_eq!(materialize(&lua, &query), [8]);
+}
+
+#[test]
+fn test_invert_inside_union() {
+    let lua = lua();
+    // the universe of the inversion is moved along with its source
+    let not_middle = format!("en_filterset_invert({MIDDLE}, en_filterset_from_range(1, 10))");
+    let query = format!("en_filterset_union({{{LOW}, {not_middle}}})");
+    assert_eq!(materialize(&lua, &query), [1, 2, 3, 9, 10]);
+}
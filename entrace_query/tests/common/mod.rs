@@ -0,0 +1,59 @@
+//! Setup shared by the query tests. Every test file is its own crate and uses only some of it.
+#![allow(dead_code)]
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+
+use croaring::Bitmap as Roaring;
+use entrace_core::{
+    EnValue, IETPresentationConfig, LogProvider, LogProviderImpl, TreeLayer,
+    remote::{BaseIETLogProvider, IETStorage, IETStorageConfig, load_iet_trace},
+};
+use entrace_query::{
+    filtersets::{Matcher, Predicate},
+    lua_api::{JoinCtx, LuaEvalState, setup_lua_no_lock},
+};
+use mlua::Lua;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Traces what `record` emits with a [TreeLayer], and loads it back the way the GUI does.
+pub fn record_trace(record: impl FnOnce()) -> LogProviderImpl {
+    let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+    let tree_layer = TreeLayer::from_storage(storage.clone());
+    let guard = Registry::default().with(LevelFilter::TRACE).with(tree_layer).set_default();
+    record();
+    drop(guard);
+    let iet = storage.finish().unwrap();
+
+    let initial = load_iet_trace(&iet[10..], false).unwrap();
+    let mut base = BaseIETLogProvider::new((), IETPresentationConfig::default(), |_, _, _| ());
+    base.pool = initial.pool;
+    base.data = initial.data;
+    LogProviderImpl::BaseIET(base)
+}
+
+/// A Lua state with the query API over every span of `trace`, as a single query thread.
+pub fn lua_for(trace: Arc<LogProviderImpl>) -> Lua {
+    lua_with(trace, |state| state)
+}
+
+/// Like [lua_for], but `configure` can add a cache, extension or spill config to the state.
+pub fn lua_with(
+    trace: Arc<LogProviderImpl>, configure: impl FnOnce(LuaEvalState) -> LuaEvalState,
+) -> Lua {
+    let last = (trace.len() as u32).saturating_sub(1);
+    let mut lua = Lua::new();
+    let join_ctx = Arc::new(JoinCtx::from_thread_count(1));
+    let finder_cache = Rc::new(RefCell::new(HashMap::new()));
+    let state = configure(LuaEvalState::new(join_ctx, 0..=last, finder_cache));
+    setup_lua_no_lock(&mut lua, trace, state).unwrap();
+    lua
+}
+
+/// For evaluators without filters, where matching is never called.
+pub struct NoFilters;
+impl Matcher<EnValue> for NoFilters {
+    fn subset_matching(&self, _: &Predicate<EnValue>, _: &Roaring) -> Roaring {
+        unreachable!()
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::{cmp::Ordering, sync::Arc};
+
+use common::lua_for;
+use entrace_core::{EnValue, LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::Relation,
+    lua_api::{TargetKind, span_matches_filter},
+};
+
+const DEPTH: u32 = 3;
+const BREADTH: u32 = 2;
+
+/// The shape `paramtree` from the example produces: `BREADTH` chains of `DEPTH` nested spans
+/// under the root.
+/// Chain `b` holds the ids `1 + b * DEPTH ..= (b + 1) * DEPTH`, from the outermost span.
+fn paramtree() -> LogProviderImpl {
+    let mut builder = LogProviderBuilder::new();
+    for b in 0..BREADTH {
+        let mut parent = 0;
+        for d in 0..DEPTH {
+            parent = builder.span(parent, &format!("d{d}b{b}")).add();
+        }
+    }
+    builder.build_impl()
+}
+
+fn matching(trace: &LogProviderImpl, target: &str, ordering: Ordering, value: u64) -> Vec<u32> {
+    let rel = Relation::Cmp(ordering);
+    let value = EnValue::U64(value);
+    (0..=DEPTH * BREADTH)
+        .filter(|&id| {
+            span_matches_filter(trace, id, target, TargetKind::Computed, rel, &value).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn test_computed_fields() {
+    let trace = paramtree();
+    assert_eq!(matching(&trace, "depth", Ordering::Equal, 0), [0]);
+    assert_eq!(matching(&trace, "depth", Ordering::Equal, 1), [1, 4]);
+    assert_eq!(matching(&trace, "depth", Ordering::Greater, 2), [3, 6]);
+
+    assert_eq!(matching(&trace, "subtree_size", Ordering::Equal, 7), [0]);
+    assert_eq!(matching(&trace, "subtree_size", Ordering::Equal, 3), [1, 4]);
+    assert_eq!(matching(&trace, "subtree_size", Ordering::Equal, 1), [3, 6]);
+
+    assert_eq!(matching(&trace, "child_count", Ordering::Equal, 2), [0]);
+    assert_eq!(matching(&trace, "child_count", Ordering::Equal, 0), [3, 6]);
+    assert_eq!(matching(&trace, "child_count", Ordering::Equal, 1), [1, 2, 4, 5]);
+}
+
+#[test]
+fn test_computed_fields_in_lua() {
+    let trace = Arc::new(paramtree());
+    let lua = lua_for(trace);
+    let ids = |target: &str, relation: &str, value: u32| -> mlua::Result<Vec<u32>> {
+        let query = format!(
+            "return en_filterset_materialize(en_filter('{target}', '{relation}', {value}, \
+             en_filterset_from_range(0, {})))",
+            DEPTH * BREADTH
+        );
+        lua.load(query).eval()
+    };
+    assert_eq!(ids("en.depth", "GT", 2).unwrap(), [3, 6]);
+    assert_eq!(ids("en.subtree_size", "GT", 2).unwrap(), [0, 1, 4]);
+    assert_eq!(ids("en.child_count", "EQ", 0).unwrap(), [3, 6]);
+    assert!(ids("en.height", "EQ", 0).is_err());
+
+    let deepest: Vec<u32> = lua.load("return en_deepest_path()").eval().unwrap();
+    assert_eq!(deepest, (0..=DEPTH).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_computed_fields_with_cycle() {
+    let mut builder = LogProviderBuilder::new();
+    let outer = builder.span(0, "outer").add();
+    builder.span(outer, "inner").add();
+    let mut trace = builder.build_base();
+    // a corrupt trace, where a span is listed as its own child
+    trace.pool[outer as usize].children.push(outer);
+    let lua = lua_for(Arc::new(LogProviderImpl::BaseIET(trace)));
+
+    for query in [
+        "return en_filterset_materialize(en_filter('en.subtree_size', 'GT', 1, \
+         en_filterset_from_range(0, 2)))",
+        "return en_filterset_materialize(en_filter('en.depth', 'EQ', 1, \
+         en_filterset_from_range(0, 2)))",
+        &format!("return en_descendant_count({outer})"),
+        &format!("return en_subtree({outer})"),
+    ] {
+        let err = lua.load(query).eval::<mlua::Value>().unwrap_err().to_string();
+        assert!(err.contains("has a cycle"), "{err}");
+    }
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+
+#[test]
+fn test_contains_anywhere_ci() {
+    let mut builder = LogProviderBuilder::new();
+    let upper = builder.span(0, "request").attr("status", EnValue::String("ERROR".into())).add();
+    let accent = builder.span(0, "request").attr("city", EnValue::String("ÉCOLE".into())).add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let contains = |f: &str, id: u32, needle: &str| -> bool {
+        lua.load(format!("return {f}({id}, '{needle}')")).eval().unwrap()
+    };
+    assert!(!contains("en_contains_anywhere", upper, "error"));
+    assert!(contains("en_contains_anywhere_ci", upper, "error"));
+    assert!(contains("en_contains_anywhere_ci", upper, "ErRoR"));
+    // the cache is shared, and keeps working for the case-sensitive search
+    assert!(contains("en_contains_anywhere", upper, "ERROR"));
+    assert!(!contains("en_contains_anywhere", upper, "error"));
+    // only ASCII is folded
+    assert!(contains("en_contains_anywhere_ci", accent, "École"));
+    assert!(!contains("en_contains_anywhere_ci", accent, "école"));
+}
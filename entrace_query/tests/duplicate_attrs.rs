@@ -0,0 +1,31 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+
+#[test]
+fn test_duplicate_attr_names() {
+    let mut builder = LogProviderBuilder::new();
+    builder
+        .span(0, "span")
+        .attr("x", EnValue::U64(3))
+        .attr("a", EnValue::U64(0))
+        .attr("x", EnValue::U64(1))
+        .attr("x", EnValue::U64(2))
+        .add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let all: Vec<u64> = lua.load("return en_attr_all(1, 'x')").eval().unwrap();
+    assert_eq!(all, [3, 1, 2]);
+    let missing: Vec<u64> = lua.load("return en_attr_all(1, 'y')").eval().unwrap();
+    assert!(missing.is_empty());
+    // en_attr_by_name and en_attrs agree on keeping the first one
+    let first: u64 = lua.load("return en_attr_by_name(1, 'x')").eval().unwrap();
+    assert_eq!(first, 3);
+    let from_table: u64 = lua.load("return en_attrs(1).x").eval().unwrap();
+    assert_eq!(from_table, 3);
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::{cmp::Ordering, sync::Arc};
+
+use common::{lua_for, record_trace};
+use entrace_core::{EnValue, LogProviderImpl};
+use entrace_query::{
+    filtersets::Relation,
+    lua_api::{TargetKind, span_matches_filter},
+};
+use tracing::info;
+
+const EMPTY: u32 = 1;
+const MISSING: u32 = 2;
+const FULL: u32 = 3;
+
+fn get_trace() -> LogProviderImpl {
+    record_trace(|| {
+        info!(x = "", "empty");
+        info!("missing");
+        info!(x = "value", "full");
+    })
+}
+
+#[test]
+fn test_exists_vs_empty() {
+    let trace = Arc::new(get_trace());
+    let matches = |id, rel, value: &EnValue| {
+        span_matches_filter(&*trace, id, "x", TargetKind::Attr, rel, value).unwrap()
+    };
+    let empty = EnValue::String(String::new());
+    let eq = Relation::Cmp(Ordering::Equal);
+
+    assert!(matches(EMPTY, eq, &empty));
+    assert!(!matches(MISSING, eq, &empty));
+    assert!(!matches(FULL, eq, &empty));
+
+    assert!(matches(EMPTY, Relation::Exists, &empty));
+    assert!(!matches(MISSING, Relation::Exists, &empty));
+    assert!(matches(FULL, Relation::Exists, &empty));
+
+    // a missing attribute doesn't compare less than anything either
+    assert!(!matches(MISSING, Relation::Cmp(Ordering::Less), &EnValue::String("a".into())));
+    assert!(
+        span_matches_filter(&*trace, MISSING, "file", TargetKind::Meta, Relation::Exists, &empty)
+            .unwrap()
+    );
+    assert!(
+        !span_matches_filter(&*trace, 0, "line", TargetKind::Meta, Relation::Exists, &empty)
+            .unwrap()
+    );
+
+    let lua = lua_for(trace);
+    let ids = |filter: &str| -> Vec<u32> {
+        let query = format!(
+            "return en_filterset_materialize(en_filter({filter}, en_filterset_from_range(1, 3)))"
+        );
+        lua.load(query).eval().unwrap()
+    };
+    assert_eq!(ids(r#"{ target = "x", relation = "EXISTS" }"#), [EMPTY, FULL]);
+    assert_eq!(ids(r#"{ target = "x", relation = "EQ", value = "" }"#), [EMPTY]);
+    assert_eq!(ids(r#""x", "EXISTS""#), [EMPTY, FULL]);
+    assert_eq!(ids(r#""x", "EQ", """#), [EMPTY]);
+}
@@ -0,0 +1,46 @@
+use std::cmp::Ordering;
+
+use croaring::Bitmap as Roaring;
+use entrace_core::EnValue;
+use entrace_query::filtersets::{Evaluator, Filterset, Predicate, RewriteAction};
+
+fn eq(attr: &str, value: u64) -> Vec<Vec<Predicate<EnValue>>> {
+    vec![vec![Predicate::new(attr, Ordering::Equal, EnValue::U64(value))]]
+}
+
+#[test]
+fn test_explain_redundant_filterset() {
+    // or(filter(b, filter(a, src)), filter(c, src)):
+    // the nested filter can be combined, then both filters on src merged into one,
+    // which leaves a union of a single item.
+    let mut evaluator = Evaluator::new(10);
+    let src = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(0..10)));
+    let a = evaluator.new_dnf(eq("a", 1), src);
+    let b = evaluator.new_dnf(eq("b", 2), a);
+    let c = evaluator.new_dnf(eq("c", 3), src);
+    let root = evaluator.new_filterset(Filterset::Or([b, c].into_iter().collect()));
+
+    let actions = evaluator.explain(root);
+    assert_eq!(actions.len(), 3, "{actions:?}");
+    assert!(matches!(actions[0], RewriteAction::DnfDnf(x, y, z) if (x, y, z) == (b, a, src)));
+    assert!(matches!(actions[1], RewriteAction::MergeDnfsInOr(x, _) if x == root));
+    assert!(matches!(actions[2], RewriteAction::EliminateSingleOr(x) if x == root));
+
+    let explained: Vec<String> = actions.iter().map(|x| x.to_string()).collect();
+    assert_eq!(
+        explained,
+        [
+            "combine the filter #2 with the filter #1 it refines, on #0",
+            "in the union #4, merge the filters #2 + #3 on #0",
+            "replace the union #4 with its only item",
+        ]
+    );
+}
+
+#[test]
+fn test_explain_normal_filterset() {
+    let mut evaluator = Evaluator::new(10);
+    let src = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(0..10)));
+    let root = evaluator.new_dnf(eq("a", 1), src);
+    assert!(evaluator.explain(root).is_empty());
+}
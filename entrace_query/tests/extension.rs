@@ -0,0 +1,37 @@
+mod common;
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+
+use common::lua_with;
+use entrace_core::test_util::LogProviderBuilder;
+use entrace_query::lua_api::{JoinCtx, LuaEvalState, setup_lua_no_lock};
+use mlua::Lua;
+
+#[test]
+fn test_extension_functions() {
+    let mut builder = LogProviderBuilder::new();
+    let span = builder.span(0, "request").add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_with(trace, |state| {
+        state.with_extension(Arc::new(|lua| {
+            lua.globals().set("double", lua.create_function(|_, x: u32| Ok(x * 2))?)
+        }))
+    });
+
+    // custom functions can be mixed with the built-in ones
+    let doubled: u32 = lua.load(format!("return double(en_parent({span}) + 1)")).eval().unwrap();
+    assert_eq!(doubled, 2);
+}
+
+#[test]
+fn test_extension_error() {
+    let trace = Arc::new(LogProviderBuilder::new().build_impl());
+    let mut lua = Lua::new();
+    let join_ctx = Arc::new(JoinCtx::from_thread_count(1));
+    let finder_cache = Rc::new(RefCell::new(HashMap::new()));
+    let state = LuaEvalState::new(join_ctx, 0..=0, finder_cache)
+        .with_extension(Arc::new(|_| Err(mlua::Error::runtime("no decoder"))));
+    let err = setup_lua_no_lock(&mut lua, trace, state).unwrap_err().to_string();
+    assert!(err.contains("no decoder"), "{err}");
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+
+/// Spans 1-10 have `i` = 0..10, and the even ones the message "even".
+fn lua() -> mlua::Lua {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..10u64 {
+        let span = builder.span(0, "span").attr("i", EnValue::U64(i));
+        if i % 2 == 0 { span.message("even") } else { span }.add();
+    }
+    lua_for(Arc::new(builder.build_impl()))
+}
+
+#[test]
+fn test_filter_lua_after_native_filter() {
+    let lua = lua();
+    let query = r#"
+        local big = en_filter("i", "GT", 3, en_filterset_from_range(1, 10))
+        return en_filter_lua(big, function(id) return en_message(id) == "even" end)
+    "#;
+    let ids: Vec<u32> = lua.load(query).eval().unwrap();
+    // i = 4, 6, 8
+    assert_eq!(ids, [5, 7, 9]);
+}
+
+#[test]
+fn test_filter_lua_result_as_source() {
+    let lua = lua();
+    let query = r#"
+        local odd = en_filter_lua(en_filterset_from_range(1, 10), function(id)
+            return en_message(id) == nil
+        end)
+        return en_filterset_materialize(en_filter("i", "LT", 5, en_filterset_from_list(odd)))
+    "#;
+    let ids: Vec<u32> = lua.load(query).eval().unwrap();
+    // i = 1, 3
+    assert_eq!(ids, [2, 4]);
+}
+
+#[test]
+fn test_filter_lua_stops_at_error() {
+    let lua = lua();
+    lua.load("calls = 0").exec().unwrap();
+    let query = r#"
+        return en_filter_lua(en_filterset_from_range(1, 10), function(id)
+            calls = calls + 1
+            if id == 3 then error("bad span") end
+            return true
+        end)
+    "#;
+    let err = lua.load(query).eval::<Vec<u32>>().unwrap_err();
+    assert!(err.to_string().contains("bad span"), "{err}");
+    assert_eq!(lua.globals().get::<u32>("calls").unwrap(), 3);
+}
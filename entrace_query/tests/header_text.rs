@@ -0,0 +1,43 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{lua_for, record_trace};
+use entrace_core::{LogProvider, LogProviderImpl};
+use tracing::{info, info_span, warn};
+
+fn get_trace() -> LogProviderImpl {
+    record_trace(|| {
+        let span = info_span!("outer").entered();
+        info!("hello");
+        warn!(code = 3, "careful");
+        drop(span);
+    })
+}
+
+#[test]
+fn test_message_and_header_text() {
+    let trace = Arc::new(get_trace());
+    let lua = lua_for(trace.clone());
+
+    let message = |id: u32| -> Option<String> {
+        lua.load(format!("return en_message({id})")).eval().unwrap()
+    };
+    assert_eq!(message(0), None);
+    assert_eq!(message(1), None);
+    assert_eq!(message(2).as_deref(), Some("hello"));
+    assert_eq!(message(3).as_deref(), Some("careful"));
+
+    let header_text =
+        |id: u32| -> String { lua.load(format!("return en_header_text({id})")).eval().unwrap() };
+    let expected = ["root", "outer", "[I]: hello", "[W]: careful"];
+    for (id, expected) in expected.into_iter().enumerate() {
+        let id = id as u32;
+        assert_eq!(header_text(id), expected);
+        // the tree view uses the same function to display headers
+        assert_eq!(header_text(id), trace.header(id).unwrap().display_text(id));
+    }
+
+    assert!(lua.load("return en_message(4)").eval::<mlua::Value>().is_err());
+    assert!(lua.load("return en_header_text(4)").eval::<mlua::Value>().is_err());
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::Relation,
+    lua_api::{TargetKind, ValueSet, span_matches_filter, span_matches_set},
+};
+
+/// Spans with targets "a" to "e", ids 1 to 5, each with an attribute `i` equal to its id.
+fn lettered_trace() -> LogProviderImpl {
+    let mut builder = LogProviderBuilder::new();
+    for (i, target) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+        builder.span(0, "event").target(target).attr("i", EnValue::U64(i as u64 + 1)).add();
+    }
+    builder.build_impl()
+}
+
+fn matching(trace: &LogProviderImpl, target: &str, kind: TargetKind, set: &[EnValue]) -> Vec<u32> {
+    let set = ValueSet::new(set);
+    (1..=5).filter(|&id| span_matches_set(trace, id, target, kind, &set).unwrap()).collect()
+}
+
+fn strings(values: &[&str]) -> Vec<EnValue> {
+    values.iter().map(|x| EnValue::String(x.to_string())).collect()
+}
+
+#[test]
+fn test_in() {
+    let trace = lettered_trace();
+    let abc = strings(&["a", "b", "c"]);
+    assert_eq!(matching(&trace, "target", TargetKind::Meta, &abc), [1, 2, 3]);
+    assert_eq!(matching(&trace, "target", TargetKind::Meta, &[]), [] as [u32; 0]);
+    let ints = [EnValue::U64(2), EnValue::I64(4), EnValue::U64(9)];
+    assert_eq!(matching(&trace, "i", TargetKind::Attr, &ints), [2, 4]);
+    // types have to match, like for EQ
+    assert_eq!(matching(&trace, "i", TargetKind::Attr, &strings(&["1", "2"])), [] as [u32; 0]);
+    assert_eq!(matching(&trace, "missing", TargetKind::Attr, &abc), [] as [u32; 0]);
+
+    // a single constant is a set of one
+    let b = EnValue::String("b".into());
+    let single: Vec<u32> = (1..=5)
+        .filter(|&id| {
+            span_matches_filter(&trace, id, "target", TargetKind::Meta, Relation::In, &b).unwrap()
+        })
+        .collect();
+    assert_eq!(single, [2]);
+}
+
+#[test]
+fn test_in_large_set() {
+    let trace = lettered_trace();
+    // large enough to be hashed
+    let mut members: Vec<EnValue> = (100..200).map(EnValue::U64).collect();
+    members.extend(strings(&["c", "e"]));
+    members.push(EnValue::I64(3));
+    members.push(EnValue::Float(1.5));
+    assert_eq!(matching(&trace, "target", TargetKind::Meta, &members), [3, 5]);
+    assert_eq!(matching(&trace, "i", TargetKind::Attr, &members), [3]);
+}
+
+#[test]
+fn test_in_lua() {
+    let trace = Arc::new(lettered_trace());
+    let lua = lua_for(trace);
+    let ids = |filter: &str| -> mlua::Result<Vec<u32>> {
+        let query = format!(
+            "return en_filterset_materialize(en_filter({filter}, en_filterset_from_range(0, 5)))"
+        );
+        lua.load(query).eval()
+    };
+    assert_eq!(ids(r#"'meta.target', 'IN', {"a","b","c"}"#).unwrap(), [1, 2, 3]);
+    let table_form = r#"{target = "i", relation = "IN", value = {"2", "5"}, value_type = "u64"}"#;
+    assert_eq!(ids(table_form).unwrap(), [2, 5]);
+    assert_eq!(ids("'meta.target', 'IN', {}").unwrap(), [] as [u32; 0]);
+
+    let not_a_list = ids("'meta.target', 'IN', 'a'").unwrap_err().to_string();
+    assert!(not_a_list.contains("IN needs a list of values"), "{not_a_list}");
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::NoFilters;
+use croaring::Bitmap as Roaring;
+use entrace_core::EnValue;
+use entrace_query::filtersets::{Evaluator, Filterset};
+
+#[test]
+fn test_invert_empty_is_every_span() {
+    // a universe far past the end of the 10 spans, like en_filterset_from_range(0, 1000)
+    let mut evaluator = Evaluator::<EnValue>::new(10);
+    let empty = evaluator.new_filterset(Filterset::Primitive(Roaring::new()));
+    let universe = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(0..=1000)));
+    let root = evaluator.new_filterset(Filterset::Invert(empty, universe));
+    evaluator.materialize(&NoFilters, root).unwrap();
+    assert_eq!(evaluator.results[&root], Roaring::from_range(0..10));
+}
+
+#[test]
+fn test_invert_is_clamped() {
+    let mut evaluator = Evaluator::<EnValue>::new(10);
+    let src = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(2..5)));
+    let universe = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(0..u32::MAX)));
+    let root = evaluator.new_filterset(Filterset::Invert(src, universe));
+    evaluator.materialize(&NoFilters, root).unwrap();
+    let expected: Roaring = (0..2).chain(5..10).collect();
+    assert_eq!(evaluator.results[&root], expected);
+}
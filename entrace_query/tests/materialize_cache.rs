@@ -0,0 +1,109 @@
+mod common;
+
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+use common::lua_with;
+use croaring::Bitmap as Roaring;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+use entrace_query::{
+    QueryStats,
+    filtersets::CanonicalFilterset,
+    lua_api::{MaterializeCache, TraceIdentity},
+};
+
+/// Spans 1-10 have `i` = 0..10.
+fn lua(cache: Rc<RefCell<MaterializeCache>>) -> mlua::Lua {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..10u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i)).add();
+    }
+    let lua = lua_with(Arc::new(builder.build_impl()), |state| state.with_materialize_cache(cache));
+    lua.set_app_data(QueryStats::default());
+    lua
+}
+
+fn spans_scanned(lua: &mlua::Lua) -> u64 {
+    lua.app_data_ref::<QueryStats>().unwrap().spans_scanned
+}
+
+#[test]
+fn test_second_materialize_is_a_lookup() {
+    let cache = Rc::new(RefCell::new(MaterializeCache::new()));
+    let lua = lua(cache.clone());
+    let low = r#"en_filter("i", "LT", 3, en_filterset_from_range(1, 10))"#;
+    let high = r#"en_filter("i", "GT", 6, en_filterset_from_range(1, 10))"#;
+    let query = format!("return en_filterset_materialize(en_filterset_union({{{low}, {high}}}))");
+
+    let ids: Vec<u32> = lua.load(&query).eval().unwrap();
+    assert_eq!(ids, [1, 2, 3, 8, 9, 10]);
+    let scanned = spans_scanned(&lua);
+    assert!(scanned > 0);
+    assert_eq!(cache.borrow().len(), 1);
+
+    // built again from scratch, and in another order
+    let query = format!("return en_filterset_materialize(en_filterset_union({{{high}, {low}}}))");
+    let ids: Vec<u32> = lua.load(&query).eval().unwrap();
+    assert_eq!(ids, [1, 2, 3, 8, 9, 10]);
+    assert_eq!(spans_scanned(&lua), scanned);
+    assert_eq!(cache.borrow().len(), 1);
+
+    // another constant is another filterset
+    let other = r#"en_filter("i", "LT", 4, en_filterset_from_range(1, 10))"#;
+    let ids: Vec<u32> =
+        lua.load(format!("return en_filterset_materialize({other})")).eval().unwrap();
+    assert_eq!(ids, [1, 2, 3, 4]);
+    assert!(spans_scanned(&lua) > scanned);
+    assert_eq!(cache.borrow().len(), 2);
+}
+
+#[test]
+fn test_sizes_skip_the_cache() {
+    let cache = Rc::new(RefCell::new(MaterializeCache::new()));
+    let lua = lua(cache.clone());
+    let query = r#"
+        local fs = en_filter("i", "LT", 3, en_filterset_from_range(1, 10))
+        local ids, sizes = en_filterset_materialize(fs, { sizes = true })
+        return #sizes
+    "#;
+    assert!(lua.load(query).eval::<usize>().unwrap() > 0);
+    assert!(cache.borrow().is_empty());
+}
+
+fn primitive(ids: &[u32]) -> CanonicalFilterset {
+    CanonicalFilterset::Primitive(Roaring::of(ids))
+}
+
+#[test]
+fn test_cache_is_busted_when_the_trace_grows() {
+    let trace = TraceIdentity::of(&Arc::new(0u8));
+    let mut cache = MaterializeCache::new();
+    cache.insert(&trace, 10, primitive(&[1, 2, 3]), Roaring::from_range(1..4));
+    assert_eq!(cache.get(&trace, 10, &primitive(&[1, 2, 3])), Some(&Roaring::from_range(1..4)));
+    assert_eq!(cache.get(&trace, 10, &primitive(&[1, 2])), None);
+    // new spans may match the filterset too
+    assert_eq!(cache.get(&trace, 11, &primitive(&[1, 2, 3])), None);
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_cache_is_busted_by_another_trace() {
+    let (a, b) = (Arc::new(0u8), Arc::new(0u8));
+    let mut cache = MaterializeCache::new();
+    cache.insert(&TraceIdentity::of(&a), 10, primitive(&[1]), Roaring::of(&[1]));
+    // as long as the trace is
+    assert!(cache.get(&TraceIdentity::of(&a), 10, &primitive(&[1])).is_some());
+    assert_eq!(cache.get(&TraceIdentity::of(&b), 10, &primitive(&[1])), None);
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_equal_hashes_are_told_apart() {
+    // primitives only hash their size and bounds, so these two hash the same
+    let trace = TraceIdentity::of(&Arc::new(0u8));
+    let mut cache = MaterializeCache::new();
+    cache.insert(&trace, 10, primitive(&[1, 3, 5]), Roaring::of(&[1]));
+    assert_eq!(cache.get(&trace, 10, &primitive(&[1, 4, 5])), None);
+    cache.insert(&trace, 10, primitive(&[1, 4, 5]), Roaring::of(&[2]));
+    assert_eq!(cache.get(&trace, 10, &primitive(&[1, 3, 5])), Some(&Roaring::of(&[1])));
+    assert_eq!(cache.get(&trace, 10, &primitive(&[1, 4, 5])), Some(&Roaring::of(&[2])));
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use common::NoFilters;
+use croaring::Bitmap as Roaring;
+use entrace_core::EnValue;
+use entrace_query::filtersets::{Evaluator, EvaluatorError, Filterset};
+
+#[test]
+fn test_materialize_dead_fails() {
+    let mut evaluator = Evaluator::<EnValue>::new(10);
+    let all = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(0..10)));
+    let dead = evaluator.new_filterset(Filterset::Dead);
+    let root = evaluator.new_filterset(Filterset::Or([all, dead].into_iter().collect()));
+    let result = evaluator.materialize(&NoFilters, root);
+    assert_eq!(result, Err(EvaluatorError::MaterializedDead(dead)));
+    assert!(!evaluator.results.contains_key(&root));
+    assert!(result.unwrap_err().to_string().starts_with("Internal filterset bug"));
+    // still printable to find the culprit
+    assert!(evaluator.dot(root).contains("Dead"));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::Relation,
+    lua_api::{TargetKind, span_matches_filter},
+};
+
+/// Spans from a few modules, ids 1 to 4. The last one has no module path.
+fn multi_module_trace() -> LogProviderImpl {
+    let mut builder = LogProviderBuilder::new();
+    for target in ["foo::bar", "foo::bar::baz", "foo::qux"] {
+        builder.span(0, "event").target(target).module_path(target).add();
+    }
+    builder.span(0, "event").target("foobar").add();
+    builder.build_impl()
+}
+
+fn matching(trace: &LogProviderImpl, target: &str, pattern: &str) -> Vec<u32> {
+    let pattern = EnValue::String(pattern.into());
+    (1..=4)
+        .filter(|&id| {
+            span_matches_filter(trace, id, target, TargetKind::Meta, Relation::Regex, &pattern)
+                .unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn test_meta_regex() {
+    let trace = multi_module_trace();
+    assert_eq!(matching(&trace, "target", "^foo::bar(::.*)?$"), [1, 2]);
+    assert_eq!(matching(&trace, "target", "^foo::"), [1, 2, 3]);
+    assert_eq!(matching(&trace, "target", "qux|baz"), [2, 3]);
+    assert_eq!(matching(&trace, "target", "foo"), [1, 2, 3, 4]);
+    // a missing module path doesn't match, even a pattern which matches anything
+    assert_eq!(matching(&trace, "module_path", ".*"), [1, 2, 3]);
+    assert_eq!(matching(&trace, "name", "^ev.nt$"), [1, 2, 3, 4]);
+}
+
+#[test]
+fn test_meta_regex_in_lua() {
+    let trace = Arc::new(multi_module_trace());
+    let lua = lua_for(trace);
+    let ids = |target: &str, pattern: &str| -> mlua::Result<Vec<u32>> {
+        let query = format!(
+            "return en_filterset_materialize(en_filter('{target}', 'REGEX', '{pattern}', \
+             en_filterset_from_range(0, 4)))"
+        );
+        lua.load(query).eval()
+    };
+    assert_eq!(ids("meta.target", "^foo::bar").unwrap(), [1, 2]);
+    assert_eq!(ids("meta.module_path", "::q").unwrap(), [3]);
+
+    let bad_pattern = ids("meta.target", "foo(").unwrap_err().to_string();
+    assert!(bad_pattern.contains("Bad regex `foo(`"), "{bad_pattern}");
+    let not_a_string_field = ids("meta.line", "1").unwrap_err().to_string();
+    assert!(not_a_string_field.contains("REGEX is not supported for meta.line"));
+    assert!(ids("message", "foo").is_err());
+}
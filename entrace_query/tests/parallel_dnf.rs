@@ -0,0 +1,37 @@
+#![cfg(feature = "parallel")]
+use std::cmp::Ordering;
+
+use croaring::Bitmap as Roaring;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::{Matcher, Predicate},
+    lua_api::{EnMatcher, PARALLEL_MIN_SPANS},
+};
+
+#[test]
+fn test_parallel_matches_like_serial() {
+    let spans = PARALLEL_MIN_SPANS as u32 * 2;
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..spans as u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i % 1000)).add();
+    }
+    let log = builder.build_impl();
+    // i < 10 or i == 500, on every other span, so some blocks are sparse
+    let clauses = [
+        vec![Predicate::new("i", Ordering::Less, EnValue::U64(10))],
+        vec![Predicate::new("i", Ordering::Equal, EnValue::U64(500))],
+    ];
+    let input: Roaring = (1..=spans).step_by(2).collect();
+    assert!(input.cardinality() >= PARALLEL_MIN_SPANS);
+
+    let matcher = EnMatcher::new(&log);
+    let result = matcher.subset_matching_dnf(clauses.iter().map(|x| x.iter()), &input);
+    // span `id` has i = (id - 1) % 1000
+    let expected: Roaring =
+        input.iter().filter(|id| matches!((id - 1) % 1000, 0..10 | 500)).collect();
+    assert_eq!(result, expected);
+    assert_eq!(matcher.spans_scanned.get(), input.cardinality());
+    // spans matching the first clause skip the second one
+    let first_clause = input.iter().filter(|id| (id - 1) % 1000 < 10).count() as u64;
+    assert_eq!(matcher.predicates_evaluated.get(), 2 * input.cardinality() - first_clause);
+}
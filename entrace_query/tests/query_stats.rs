@@ -0,0 +1,33 @@
+use std::cmp::Ordering;
+
+use croaring::Bitmap as Roaring;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::{Evaluator, Filterset, Predicate},
+    lua_api::EnMatcher,
+};
+
+#[test]
+fn test_scan_counts() {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..4u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i)).add();
+    }
+    let log = builder.build_impl();
+
+    // i > 1 or i == 0, on spans 1..=4
+    let mut evaluator = Evaluator::new(5);
+    let src = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(1..5)));
+    let clauses = vec![
+        vec![Predicate::new("i", Ordering::Greater, EnValue::U64(1))],
+        vec![Predicate::new("i", Ordering::Equal, EnValue::U64(0))],
+    ];
+    let root = evaluator.new_dnf(clauses, src);
+    let matcher = EnMatcher::new(&log);
+    evaluator.materialize(&matcher, root).unwrap();
+
+    assert_eq!(evaluator.results[&root].to_vec(), [1, 3, 4]);
+    assert_eq!(matcher.spans_scanned.get(), 4);
+    // span 1 matches the second clause, 2 matches neither, 3 and 4 match the first one
+    assert_eq!(matcher.predicates_evaluated.get(), 2 + 2 + 1 + 1);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, LogProviderImpl, test_util::LogProviderBuilder};
+
+#[test]
+fn test_rank_with_scorer() {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..4u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i)).add();
+    }
+    let lua = lua_for(Arc::new(builder.build_impl()));
+
+    let ranked: Vec<u32> =
+        lua.load("return en_rank({1, 2, 3, 4}, function(id) return id % 2 end)").eval().unwrap();
+    // equal scores keep their order
+    assert_eq!(ranked, [1, 3, 2, 4]);
+    // the scorer must return a number
+    let bad_scorer = "return en_rank({1, 2}, function(id) return {} end)";
+    assert!(lua.load(bad_scorer).eval::<mlua::Value>().is_err());
+}
+
+#[test]
+fn test_rank_by_attr() {
+    let mut builder = LogProviderBuilder::new();
+    let small = builder.span(0, "span").attr("elapsed_ms", EnValue::U64(5)).add();
+    let missing = builder.span(0, "span").add();
+    let large = builder.span(0, "span").attr("elapsed_ms", EnValue::Float(12.5)).add();
+    let text = builder.span(0, "span").attr("elapsed_ms", EnValue::String("slow".into())).add();
+    let negative = builder.span(0, "span").attr("elapsed_ms", EnValue::I64(-3)).add();
+    let lua = lua_for(Arc::new(builder.build_impl()));
+
+    let query = format!(
+        "return en_rank_by_attr({{{small}, {missing}, {large}, {text}, {negative}}}, \"elapsed_ms\")"
+    );
+    let ranked: Vec<u32> = lua.load(query).eval().unwrap();
+    // spans without a numeric value go last, in their original order
+    assert_eq!(ranked, [large, small, negative, missing, text]);
+}
+
+#[test]
+fn test_rank_by_depth() {
+    let mut builder = LogProviderBuilder::new();
+    let outer = builder.span(0, "outer").add();
+    let inner = builder.span(outer, "inner").add();
+    let leaf = builder.span(inner, "leaf").add();
+    let sibling = builder.span(0, "sibling").add();
+    let lua = lua_for(Arc::new(builder.build_impl()));
+
+    let query = format!("return en_rank_by_depth({{0, {sibling}, {outer}, {leaf}, {inner}}})");
+    let ranked: Vec<u32> = lua.load(query).eval().unwrap();
+    assert_eq!(ranked, [leaf, inner, sibling, outer, 0]);
+    let empty: Vec<u32> = lua.load("return en_rank_by_depth({})").eval().unwrap();
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn test_depth_of_cycle_errors() {
+    let mut builder = LogProviderBuilder::new();
+    let first = builder.span(0, "first").add();
+    let second = builder.span(first, "second").add();
+    let mut trace = builder.build_base();
+    // first -> second -> first, neither reaches the root
+    trace.data[first as usize].parent = second;
+    let lua = lua_for(Arc::new(LogProviderImpl::BaseIET(trace)));
+
+    for query in
+        [format!("return en_rank_by_depth({{{second}}})"), format!("return en_depth({first})")]
+    {
+        let err = lua.load(query).eval::<mlua::Value>().unwrap_err().to_string();
+        assert!(err.contains("has a cycle"), "{err}");
+    }
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::{EnValue, LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::{QueryError, lua_api::lua_result_to_ids};
+
+fn trace() -> LogProviderImpl {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..4u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i)).add();
+    }
+    builder.build_impl()
+}
+
+fn run(query: &str) -> Result<Vec<u32>, QueryError> {
+    let trace = Arc::new(trace());
+    let lua = lua_for(trace.clone());
+    let result = lua.load(query).eval().unwrap();
+    lua_result_to_ids(result, &lua, &*trace)
+}
+
+#[test]
+fn test_single_id() {
+    assert_eq!(run("return 3").unwrap(), [3]);
+    assert!(matches!(run("return -1"), Err(QueryError::FailedToCoerce)));
+    assert!(matches!(run("return 1.5"), Err(QueryError::FailedToCoerce)));
+}
+
+#[test]
+fn test_list() {
+    assert_eq!(run("return {1, 2}").unwrap(), [1, 2]);
+    assert_eq!(run("return {}").unwrap(), Vec::<u32>::new());
+}
+
+#[test]
+fn test_filterset() {
+    let query = "return en_filter('i', 'GT', 1, en_filterset_from_range(0, 4))";
+    assert_eq!(run(query).unwrap(), [3, 4]);
+    let query = "return en_filterset_materialize(en_filterset_from_range(1, 2))";
+    assert_eq!(run(query).unwrap(), [1, 2]);
+}
+
+#[test]
+fn test_uncoercible() {
+    assert!(matches!(run("return 'a'"), Err(QueryError::FailedToCoerce)));
+    assert!(matches!(run("return { 'a' }"), Err(QueryError::FailedToCoerce)));
+    assert!(matches!(run("return nil"), Err(QueryError::FailedToCoerce)));
+}
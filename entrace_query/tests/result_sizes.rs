@@ -0,0 +1,32 @@
+use std::cmp::Ordering;
+
+use croaring::Bitmap as Roaring;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::{Evaluator, Filterset, Predicate},
+    lua_api::EnMatcher,
+};
+
+#[test]
+fn test_result_sizes() {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..6u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i)).add();
+    }
+    let log = builder.build_impl();
+
+    // spans 1..=6 without the ones where i > 3
+    let mut evaluator = Evaluator::new(7);
+    let all = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(1..7)));
+    let big =
+        evaluator.new_dnf(vec![vec![Predicate::new("i", Ordering::Greater, EnValue::U64(3))]], all);
+    let root = evaluator.new_filterset(Filterset::Invert(big, all));
+    evaluator.materialize(&EnMatcher::new(&log), root).unwrap();
+
+    let sizes = evaluator.result_sizes();
+    assert_eq!(sizes[&all], 6);
+    assert_eq!(sizes[&big], 2);
+    assert_eq!(sizes[&root], 4);
+    assert_eq!(evaluator.describe(big), format!("filter i GT 3 on #{all}"));
+    assert_eq!(evaluator.describe(root), format!("#{all} without #{big}"));
+}
@@ -0,0 +1,107 @@
+use std::sync::{Arc, RwLock};
+
+use entrace_core::{LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::lua_api::{JoinCtx, LuaBindings, ReusableLua};
+
+fn trace(spans: u32) -> Arc<RwLock<LogProviderImpl>> {
+    let mut builder = LogProviderBuilder::new();
+    for _ in 0..spans {
+        builder.span(0, "span").add();
+    }
+    Arc::new(RwLock::new(builder.build_impl()))
+}
+fn bindings(
+    trace: &Arc<RwLock<LogProviderImpl>>, range: std::ops::RangeInclusive<u32>,
+) -> LuaBindings {
+    LuaBindings { trace: trace.clone(), join_ctx: Arc::new(JoinCtx::from_thread_count(1)), range }
+}
+
+#[test]
+fn test_rebind() {
+    let (small, large) = (trace(2), trace(5));
+    let lua = ReusableLua::new(bindings(&small, 0..=2)).unwrap();
+    let eval = |code: &str| -> Vec<u32> {
+        let value = lua.eval(code, "test").unwrap();
+        mlua::FromLua::from_lua(value, lua.lua()).unwrap()
+    };
+    assert_eq!(eval("return { en_span_cnt() }"), [3]);
+    assert_eq!(eval("return { en_span_range() }"), [0, 2]);
+
+    lua.rebind(bindings(&large, 2..=5));
+    assert_eq!(eval("return { en_span_cnt() }"), [6]);
+    assert_eq!(eval("return { en_span_range() }"), [2, 5]);
+    assert_eq!(eval("return en_children(0)"), [1, 2, 3, 4, 5]);
+    let joined = "return en_join(en_foreach(function(id) return true end))";
+    assert_eq!(eval(joined), [2, 3, 4, 5]);
+    // a fresh join context each run
+    lua.rebind(bindings(&large, 0..=1));
+    assert_eq!(eval(joined), [0, 1]);
+}
+
+#[test]
+fn test_globals_dont_leak() {
+    let trace = trace(1);
+    let lua = ReusableLua::new(bindings(&trace, 0..=1)).unwrap();
+    lua.eval("leaked = 1; en_span_cnt = nil", "first").unwrap();
+    lua.rebind(bindings(&trace, 0..=1));
+    let value = lua.eval("return { leaked == nil, en_span_cnt() }", "second").unwrap();
+    let value: (bool, u32) = {
+        let t = value.as_table().unwrap();
+        (t.get(1).unwrap(), t.get(2).unwrap())
+    };
+    assert_eq!(value, (true, 2));
+}
+
+#[test]
+fn test_prelude() {
+    let trace = trace(3);
+    let mut lua = ReusableLua::new(bindings(&trace, 0..=3)).unwrap();
+    let prelude: Arc<str> = Arc::from("function big() return en_span_cnt() > 2 end");
+    lua.set_prelude(Some(prelude.clone())).unwrap();
+    let value = lua.eval("return big()", "query").unwrap();
+    assert_eq!(value.as_boolean(), Some(true));
+
+    // a broken prelude is reported, and the functions of the previous one stay usable
+    let broken: Arc<str> = Arc::from("function big( return");
+    assert!(lua.set_prelude(Some(broken.clone())).is_err());
+    assert!(lua.eval("return big()", "query").is_ok());
+    // not retried until it changes
+    assert!(lua.set_prelude(Some(broken)).is_ok());
+}
+
+#[test]
+fn test_unbind() {
+    let trace = trace(2);
+    let lua = ReusableLua::new(bindings(&trace, 0..=2)).unwrap();
+    lua.unbind();
+    // nothing else has the trace
+    assert_eq!(Arc::strong_count(&trace), 1);
+    let err = lua.eval("return en_span_cnt()", "unbound").unwrap_err().to_string();
+    assert!(err.contains("No trace to query"), "{err}");
+    assert!(lua.eval("return en_span_range()", "unbound").is_err());
+    lua.rebind(bindings(&trace, 0..=2));
+    let value = lua.eval("return en_span_cnt()", "bound").unwrap();
+    assert_eq!(value.as_u32(), Some(3));
+}
+
+#[test]
+fn test_materialize_is_cached() {
+    let (first, second) = (trace(10), trace(10));
+    let lua = ReusableLua::new(bindings(&first, 0..=10)).unwrap();
+    let materialize = || -> (Vec<u32>, u64) {
+        let query = "return en_filterset_materialize(en_filter('meta.name', 'EQ', 'span', \
+                     en_filterset_from_range(1, 10)))";
+        let value = lua.eval(query, "materialize").unwrap();
+        let ids = mlua::FromLua::from_lua(value, lua.lua()).unwrap();
+        (ids, lua.take_stats().spans_scanned)
+    };
+    let (ids, scanned) = materialize();
+    assert_eq!(ids, (1..=10).collect::<Vec<_>>());
+    assert_eq!(scanned, 10);
+    lua.rebind(bindings(&first, 0..=10));
+    assert_eq!(materialize(), (ids.clone(), 0));
+
+    // another trace of the same length is scanned again
+    lua.rebind(bindings(&second, 0..=10));
+    assert_eq!(materialize(), (ids, 10));
+}
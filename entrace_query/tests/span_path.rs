@@ -0,0 +1,24 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::lua_for;
+use entrace_core::test_util::LogProviderBuilder;
+
+#[test]
+fn test_span_path_in_lua() {
+    let mut builder = LogProviderBuilder::new();
+    let outer = builder.span(0, "outer").add();
+    builder.span(outer, "first").add();
+    let second = builder.span(outer, "second").add();
+    let trace = Arc::new(builder.build_impl());
+
+    let lua = lua_for(trace);
+
+    let path: String = lua.load(format!("return en_span_path({second})")).eval().unwrap();
+    assert_eq!(path, "0/0/1");
+    let id: u32 = lua.load("return en_resolve_path('0/0/1')").eval().unwrap();
+    assert_eq!(id, second);
+    let missing: Option<u32> = lua.load("return en_resolve_path('0/5')").eval().unwrap();
+    assert_eq!(missing, None);
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::cmp::Ordering;
+
+use common::lua_with;
+use croaring::Bitmap as Roaring;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+use entrace_query::{
+    filtersets::{Evaluator, EvaluatorError, Filterset, Matcher, Predicate},
+    lua_api::SpillConfig,
+};
+use std::sync::Arc;
+
+/// Matches the ids divisible by the constant of the predicate.
+struct DivisibleBy;
+impl Matcher<EnValue> for DivisibleBy {
+    fn subset_matching(&self, predicate: &Predicate<EnValue>, input: &Roaring) -> Roaring {
+        let EnValue::U64(n) = predicate.constant else { unreachable!() };
+        input.iter().filter(|x| (*x as u64).is_multiple_of(n)).collect()
+    }
+}
+
+const N: u32 = 100_000;
+
+/// `(multiples of 2 or multiples of 3) and not multiples of 5`, over a few ranges, with every
+/// intermediate result large.
+fn build(evaluator: &mut Evaluator<EnValue>) -> usize {
+    let divisible = |n| vec![vec![Predicate::new("i", Ordering::Equal, EnValue::U64(n))]];
+    let all = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(0..N)));
+    let low = evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(0..N / 2)));
+    let by_2 = evaluator.new_dnf(divisible(2), all);
+    let by_3 = evaluator.new_dnf(divisible(3), low);
+    let either = evaluator.new_filterset(Filterset::Or([by_2, by_3].into_iter().collect()));
+    let by_5 = evaluator.new_dnf(divisible(5), all);
+    let not_by_5 = evaluator.new_filterset(Filterset::Invert(by_5, all));
+    evaluator.new_filterset(Filterset::And([either, not_by_5].into_iter().collect()))
+}
+
+#[test]
+fn test_spilled_results_are_the_same() {
+    let mut in_memory = Evaluator::new(N);
+    let root = build(&mut in_memory);
+    in_memory.materialize(&DivisibleBy, root).unwrap();
+    let expected: Roaring = (0..N)
+        .filter(|x| {
+            (x.is_multiple_of(2) || (x.is_multiple_of(3) && *x < N / 2)) && !x.is_multiple_of(5)
+        })
+        .collect();
+    assert_eq!(in_memory.results[&root], expected);
+
+    let dir = std::env::temp_dir();
+    let mut spilling = Evaluator::new(N).with_spill(1024, dir.clone());
+    let root = build(&mut spilling);
+    spilling.materialize(&DivisibleBy, root).unwrap();
+    assert!(!spilling.spilled().is_empty());
+    assert!(!spilling.spilled().contains(&root));
+    assert_eq!(spilling.results[&root], expected);
+    assert_eq!(spilling.result_sizes(), in_memory.result_sizes());
+
+    // spilled results can still be read
+    let spilled = spilling.spilled();
+    for id in &spilled {
+        assert_eq!(spilling.result(*id).unwrap(), in_memory.results.get(id));
+    }
+    assert!(spilling.spilled().is_empty());
+}
+
+#[test]
+fn test_spill_files_are_removed() {
+    let dir = std::env::temp_dir().join(format!("entrace-spill-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    {
+        let mut spilling = Evaluator::new(N).with_spill(1024, dir.clone());
+        let root = build(&mut spilling);
+        spilling.materialize(&DivisibleBy, root).unwrap();
+        assert!(!spilling.spilled().is_empty());
+        assert_ne!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn test_missing_spill_file_is_an_error() {
+    let dir = std::env::temp_dir().join(format!("entrace-spill-missing-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut spilling = Evaluator::new(N).with_spill(1024, dir.clone());
+    let root = build(&mut spilling);
+    spilling.materialize(&DivisibleBy, root).unwrap();
+    let id = spilling.spilled()[0];
+    // like a temp dir cleaner would
+    std::fs::remove_dir_all(&dir).unwrap();
+    let err = spilling.result(id).unwrap_err();
+    assert!(matches!(err, EvaluatorError::SpillRead(x, _, _) if x == id), "{err}");
+}
+
+#[test]
+fn test_lua_queries_spill() {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..1000u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i)).add();
+    }
+    let trace = Arc::new(builder.build_impl());
+    let query = r#"
+        local all = en_filterset_from_range(1, 1000)
+        local low = en_filter("i", "LT", 700, all)
+        local high = en_filter("i", "GT", 200, all)
+        return en_filterset_materialize(en_filterset_intersect({low, high}))
+    "#;
+    let dir = std::env::temp_dir().join(format!("entrace-spill-lua-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let eval = |spill: Option<SpillConfig>| {
+        let lua = lua_with(trace.clone(), |state| match spill {
+            Some(spill) => state.with_spill(spill),
+            None => state,
+        });
+        lua.load(query).eval::<Vec<u32>>().unwrap()
+    };
+    let in_memory = eval(None);
+    assert_eq!(in_memory, (202..=700).collect::<Vec<_>>());
+    assert_eq!(eval(Some(SpillConfig { max_bytes: 16, dir: dir.clone() })), in_memory);
+    // the spill files are gone with the evaluator
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir(&dir).unwrap();
+}
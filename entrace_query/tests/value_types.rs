@@ -0,0 +1,75 @@
+mod common;
+
+use std::{cmp::Ordering, sync::Arc};
+
+use common::{lua_for, record_trace};
+use entrace_core::{EnValue, EnValueRef, LogProviderImpl};
+use entrace_query::lua_api::values_match;
+use tracing::info;
+
+fn get_trace() -> LogProviderImpl {
+    record_trace(|| {
+        info!(big = u64::MAX, "max");
+        info!(big = u64::MAX - 1, "max minus one");
+        info!(big = 1u64 << 63, "half");
+        info!(big = 0u64, "zero");
+    })
+}
+
+fn messages(lua: &mlua::Lua, query: &str) -> mlua::Result<Vec<String>> {
+    lua.load(query).eval()
+}
+
+#[test]
+fn test_values_match_u64_boundaries() {
+    let max = EnValueRef::U64(u64::MAX);
+    assert!(values_match(Ordering::Equal, &max, &EnValue::U64(u64::MAX)));
+    assert!(values_match(Ordering::Greater, &max, &EnValue::U64(u64::MAX - 1)));
+    assert!(values_match(Ordering::Less, &EnValueRef::U64(u64::MAX - 1), &EnValue::U64(u64::MAX)));
+    // u64::MAX used to be truncated to -1 when compared with an I64 constant
+    assert!(values_match(Ordering::Greater, &max, &EnValue::I64(-1)));
+    assert!(!values_match(Ordering::Equal, &max, &EnValue::I64(-1)));
+    assert!(values_match(Ordering::Greater, &EnValueRef::U128(u128::MAX), &EnValue::U64(u64::MAX)));
+    assert!(values_match(Ordering::Less, &EnValueRef::I64(-1), &EnValue::U64(0)));
+}
+
+#[test]
+fn test_filter_value_type() {
+    let trace = Arc::new(get_trace());
+    let lua = lua_for(trace);
+
+    let query = |filter: &str| {
+        format!(
+            r#"
+            local fs = en_filter({filter}, en_filterset_from_range(1, 4))
+            local out = {{}}
+            for _, id in ipairs(en_filterset_materialize(fs)) do
+                table.insert(out, en_attr_by_name(id, "message"))
+            end
+            return out
+            "#
+        )
+    };
+
+    let eq_max = query(
+        r#"{ target = "big", relation = "EQ", value = "18446744073709551615", value_type = "u64" }"#,
+    );
+    assert_eq!(messages(&lua, &eq_max).unwrap(), ["max"]);
+
+    let gt_half = query(
+        r#"{ target = "big", relation = "GT", value = "9223372036854775808", value_type = "u64" }"#,
+    );
+    assert_eq!(messages(&lua, &gt_half).unwrap(), ["max", "max minus one"]);
+
+    // a non-negative integer constant is inferred to be u64, so it compares correctly
+    // against values that don't fit in an i64
+    let gt_zero = query(r#"{ target = "big", relation = "GT", value = 0 }"#);
+    assert_eq!(messages(&lua, &gt_zero).unwrap(), ["max", "max minus one", "half"]);
+
+    let negative =
+        query(r#"{ target = "big", relation = "GT", value = "-1", value_type = "u64" }"#);
+    assert!(messages(&lua, &negative).is_err());
+
+    let bad_type = query(r#"{ target = "big", relation = "EQ", value = 1, value_type = "u32" }"#);
+    assert!(messages(&lua, &bad_type).is_err());
+}
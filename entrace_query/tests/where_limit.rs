@@ -0,0 +1,36 @@
+use std::cmp::Ordering;
+
+use croaring::Bitmap as Roaring;
+use entrace_core::{EnValue, test_util::LogProviderBuilder};
+use entrace_query::{filtersets::Predicate, lua_api::EnMatcher};
+
+#[test]
+fn test_first_matching_stops_early() {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..100u64 {
+        builder.span(0, "span").attr("even", EnValue::Bool(i % 2 == 0)).add();
+    }
+    let log = builder.build_impl();
+    let matcher = EnMatcher::new(&log);
+    let predicate = Predicate::new("even", Ordering::Equal, EnValue::Bool(true));
+
+    // span 1 has i = 0
+    let ids = matcher.first_matching(&predicate, &Roaring::from_range(1..101), 3).unwrap();
+    assert_eq!(ids, [1, 3, 5]);
+    assert_eq!(matcher.spans_scanned.get(), 5);
+}
+
+#[test]
+fn test_first_matching_fewer_than_limit() {
+    let mut builder = LogProviderBuilder::new();
+    for i in 0..10u64 {
+        builder.span(0, "span").attr("i", EnValue::U64(i)).add();
+    }
+    let log = builder.build_impl();
+    let matcher = EnMatcher::new(&log);
+    let predicate = Predicate::new("i", Ordering::Greater, EnValue::U64(7));
+    let ids = matcher.first_matching(&predicate, &Roaring::from_range(1..11), 100).unwrap();
+    assert_eq!(ids, [9, 10]);
+    assert_eq!(matcher.spans_scanned.get(), 10);
+    assert!(matcher.first_matching(&predicate, &Roaring::from_range(1..11), 0).unwrap().is_empty());
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::NoFilters;
+use croaring::Bitmap as Roaring;
+use entrace_core::EnValue;
+use entrace_query::filtersets::{Evaluator, Filterset, RewriteAction};
+
+fn range(evaluator: &mut Evaluator<EnValue>, r: std::ops::Range<u32>) -> usize {
+    evaluator.new_filterset(Filterset::Primitive(Roaring::from_range(r)))
+}
+
+/// `xor(xor(0..10, 5..15), 8..12, 8..12)`, where the last two cancel out.
+fn build(evaluator: &mut Evaluator<EnValue>) -> usize {
+    let a = range(evaluator, 0..10);
+    let b = range(evaluator, 5..15);
+    let c = range(evaluator, 8..12);
+    let d = range(evaluator, 8..12);
+    let inner = evaluator.new_filterset(Filterset::Xor(vec![a, b]));
+    evaluator.new_filterset(Filterset::Xor(vec![inner, c, d]))
+}
+
+#[test]
+fn test_xor() {
+    let expected: Roaring = (0..5).chain(10..15).collect();
+    let mut plain = Evaluator::new(20);
+    let root = build(&mut plain);
+    plain.materialize(&NoFilters, root).unwrap();
+    assert_eq!(plain.results[&root], expected);
+
+    let mut evaluator = Evaluator::new(20);
+    let root = build(&mut evaluator);
+    let actions = evaluator.explain(root);
+    assert_eq!(actions.len(), 1, "{actions:?}");
+    assert!(matches!(&actions[0], RewriteAction::CompressXor(x, y) if *x == root && *y == [4]));
+    assert_eq!(actions[0].to_string(), "flatten the nested symmetric differences #4 into #5");
+    assert_eq!(evaluator.describe(root), "symmetric difference of #0, #1, #2, #3");
+    evaluator.materialize(&NoFilters, root).unwrap();
+    assert_eq!(evaluator.results[&root], expected);
+}
+
+#[test]
+fn test_single_xor() {
+    let mut evaluator = Evaluator::new(20);
+    let a = range(&mut evaluator, 0..10);
+    let root = evaluator.new_filterset(Filterset::Xor(vec![a]));
+    let actions = evaluator.explain(root);
+    assert!(matches!(actions[..], [RewriteAction::EliminateSingleXor(x)] if x == root));
+    evaluator.normalize(root);
+    evaluator.materialize(&NoFilters, root).unwrap();
+    assert_eq!(evaluator.results[&root], Roaring::from_range(0..10));
+}
+
+#[test]
+fn test_subtract() {
+    // en_filterset_subtract(a, b) is an inversion of b with a as the universe
+    let mut evaluator = Evaluator::new(20);
+    let a = range(&mut evaluator, 0..10);
+    let b = range(&mut evaluator, 5..15);
+    let root = evaluator.new_filterset(Filterset::Invert(b, a));
+    evaluator.materialize(&NoFilters, root).unwrap();
+    assert_eq!(evaluator.results[&root], Roaring::from_range(0..5));
+}
@@ -0,0 +1,64 @@
+//! Compare `en_depth` with walking the parents from Lua, on the shape of the `paramtree` example.
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+
+use divan::{Bencher, black_box};
+use entrace_core::test_util::LogProviderBuilder;
+use entrace_query::lua_api::{JoinCtx, LuaEvalState, setup_lua_no_lock};
+use mlua::Lua;
+
+fn main() {
+    divan::main();
+}
+
+const DEPTH: u32 = 1000;
+const BREADTH: u32 = 100;
+
+/// `BREADTH` chains of `DEPTH` nested spans, like `paramtree(DEPTH, BREADTH)` in the example.
+fn setup() -> Lua {
+    let mut builder = LogProviderBuilder::new();
+    for b in 0..BREADTH {
+        let mut parent = 0;
+        for d in 0..DEPTH {
+            parent = builder.span(parent, &format!("d{d}b{b}")).add();
+        }
+    }
+    let trace = Arc::new(builder.build_impl());
+    let mut lua = Lua::new();
+    let join_ctx = Arc::new(JoinCtx::from_thread_count(1));
+    let state =
+        LuaEvalState::new(join_ctx, 0..=DEPTH * BREADTH, Rc::new(RefCell::new(HashMap::new())));
+    setup_lua_no_lock(&mut lua, trace, state).unwrap();
+    lua
+}
+
+/// The depth of the innermost span of every chain.
+fn leaves_query(depth_of: &str) -> String {
+    format!(
+        "local total = 0
+         for b = 1, {BREADTH} do total = total + {depth_of}(b * {DEPTH}) end
+         return total"
+    )
+}
+
+#[divan::bench]
+fn en_depth(bencher: Bencher) {
+    let lua = setup();
+    let query = leaves_query("en_depth");
+    bencher.bench_local(|| black_box(lua.load(&query).eval::<u64>().unwrap()));
+}
+
+#[divan::bench]
+fn parents_from_lua(bencher: Bencher) {
+    let lua = setup();
+    lua.load(
+        "function lua_depth(id)
+           local depth = 0
+           while id ~= 0 do id = en_parent(id); depth = depth + 1 end
+           return depth
+         end",
+    )
+    .exec()
+    .unwrap();
+    let query = leaves_query("lua_depth");
+    bencher.bench_local(|| black_box(lua.load(&query).eval::<u64>().unwrap()));
+}
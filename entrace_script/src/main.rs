@@ -1,38 +1,143 @@
 use entrace_core::LogProvider;
-use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::Instant,
+};
 
+use anyhow::Context;
 use clap::Parser;
-use entrace_core::{IETLoadConfig, IETPresentationConfig, remote::FileWatchConfig};
-use entrace_query::lua_api::{JoinCtx, LuaEvalState};
+use entrace_core::{
+    EN_DISK_VERSION, IETLoadConfig, IETPresentationConfig, LogProviderImpl, StorageFormat,
+    mmap::MmapLogProvider, parse_entrace_magic, remote::FileWatchConfig,
+};
+use entrace_query::lua_api::{JoinCtx, LuaEvalState, MaterializeCache};
 
 #[derive(Parser)]
 #[command(version, about, long_about = "Run a Lua script with access to the entrace Lua API")]
 struct Args {
-    #[arg(short, long, value_name = "FILE")]
-    lua_file: PathBuf,
+    #[arg(short, long, value_name = "FILE", required_unless_present_any = ["export", "import"])]
+    lua_file: Option<PathBuf>,
     #[arg(short, long, value_name = "FILE")]
     trace_file: PathBuf,
+    /// Write the trace to stdout in this format, instead of running a script
+    #[arg(long, conflicts_with = "lua_file")]
+    export: Option<ExportFormat>,
+    /// Read newline-delimited JSON, as written by `--export ndjson`, and write it to the trace file
+    /// as an IET trace, instead of running a script
+    #[arg(long, value_name = "NDJSON FILE", conflicts_with_all = ["lua_file", "export", "lazy"])]
+    import: Option<PathBuf>,
+    /// Read spans from the file only when the script asks for them, instead of loading the whole
+    /// trace into memory. IET files are converted to a temporary ET file for this
+    #[arg(long)]
+    lazy: bool,
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq)]
+enum ExportFormat {
+    /// Newline-delimited JSON, one span per line
+    Ndjson,
+}
+
+/// Deletes the file when dropped.
+struct TempFile(PathBuf);
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+/// Memory map the trace, so that entries are only decoded when they are read.
+///
+/// ET files are mapped as they are. IET files have no offset table, so they are converted into an
+/// ET file at `temp_path` first: this still reads the whole file, but only keeps the tree of spans
+/// in memory. Returns `None` for files which can't be mapped, like compressed ones.
+fn load_lazy(trace_file: &Path, temp_path: &Path) -> anyhow::Result<Option<LogProviderImpl>> {
+    let mut file = File::open(trace_file)?;
+    let mut magic = [0; 10];
+    if file.read_exact(&mut magic).is_err() {
+        return Ok(None);
+    }
+    let format = match parse_entrace_magic(&magic) {
+        Ok((EN_DISK_VERSION, format, _)) => format,
+        // let the full load report the error
+        _ => return Ok(None),
+    };
+    let et_file = match format {
+        StorageFormat::ET => file,
+        StorageFormat::IET | StorageFormat::IETPrefix => {
+            let out = File::options().read(true).write(true).create_new(true).open(temp_path)?;
+            let mut out = BufWriter::new(out);
+            let length_prefixed = format == StorageFormat::IETPrefix;
+            entrace_core::convert::iet_to_et(
+                &mut BufReader::new(file),
+                &mut out,
+                true,
+                length_prefixed,
+            )?;
+            out.into_inner()?
+        }
+    };
+    // SAFETY: Mmap is inherently unsafe, the trace file must not be changed while the script runs.
+    let provider = unsafe { MmapLogProvider::from_file(&et_file) }?;
+    Ok(Some(LogProviderImpl::Mmap(provider)))
 }
 
 fn main() -> anyhow::Result<()> {
-    let Args { lua_file, trace_file } = Args::parse();
-    let trace = unsafe {
-        entrace_core::load_trace(
-            &trace_file,
-            entrace_core::LoadConfig {
-                iht: IETLoadConfig {
-                    watch: FileWatchConfig::DontWatch,
-                    presentation: IETPresentationConfig::default(),
+    let Args { lua_file, trace_file, export, import, lazy } = Args::parse();
+    let start = Instant::now();
+    if let Some(import) = import {
+        let input = BufReader::new(File::open(&import)?);
+        let output = BufWriter::new(File::create(&trace_file)?);
+        let count = entrace_core::import::from_ndjson(input, output)
+            .with_context(|| format!("Failed to import {}", import.display()))?;
+        eprintln!("Imported {count} spans in {:.2?}", start.elapsed());
+        return Ok(());
+    }
+    let temp_file =
+        TempFile(std::env::temp_dir().join(format!("entrace-script-{}.et", std::process::id())));
+    let lazy_trace = if lazy { load_lazy(&trace_file, &temp_file.0)? } else { None };
+    let mode = match (lazy, &lazy_trace) {
+        (true, Some(_)) => "lazy",
+        (true, None) => "full load, lazy loading is not available for this file",
+        (false, _) => "full load",
+    };
+    let trace = match lazy_trace {
+        Some(trace) => trace,
+        None => unsafe {
+            entrace_core::load_trace(
+                &trace_file,
+                entrace_core::LoadConfig {
+                    iht: IETLoadConfig {
+                        watch: FileWatchConfig::DontWatch,
+                        presentation: IETPresentationConfig::default(),
+                        attr_transform: None,
+                    },
                 },
-            },
-        )
-    }?;
+            )
+        }?,
+    };
+    eprintln!("Loaded {} spans in {:.2?} ({mode})", trace.len(), start.elapsed());
+    if let Some(ExportFormat::Ndjson) = export {
+        let stdout = std::io::stdout().lock();
+        entrace_core::export::export_ndjson(&trace, BufWriter::new(stdout))?;
+        return Ok(());
+    }
+    let lua_file = lua_file.context("A Lua file is required without --export or --import")?;
     let trace_arc = Arc::new(trace);
     let mut lua = mlua::Lua::new();
     let finder_cache = Rc::new(RefCell::new(HashMap::new()));
     let join_ctx_arc = Arc::new(JoinCtx::from_thread_count(1));
-    let state =
-        LuaEvalState::new(join_ctx_arc, 0..=trace_arc.len().saturating_sub(1) as u32, finder_cache);
+    let last = trace_arc.len().saturating_sub(1) as u32;
+    // a script can materialize the same filterset more than once
+    let materialize_cache = Rc::new(RefCell::new(MaterializeCache::new()));
+    let state = LuaEvalState::new(join_ctx_arc, 0..=last, finder_cache)
+        .with_materialize_cache(materialize_cache);
     entrace_query::lua_api::setup_lua_no_lock(&mut lua, trace_arc, state)?;
 
     let lua_file_contents = std::fs::read_to_string(&lua_file)?;
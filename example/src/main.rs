@@ -1,8 +1,9 @@
 use clap::{Parser, ValueEnum};
 use entrace_core::{
+    format_span_oneline,
     mmap::ETStorage,
-    remote::{IETStorage, IETStorageConfig},
-    TreeLayer,
+    remote::{IETStorage, IETStorageConfig, PreviewStorage},
+    AttrEncoding, LogProvider, TreeLayer,
 };
 use petgraph::{
     graph::{DiGraph, NodeIndex},
@@ -205,6 +206,8 @@ pub enum LogMode {
     DiskET,
     DiskIET,
     StreamingET,
+    /// Like DiskIET, and print a live preview of the trace from the same process.
+    PreviewIET,
 }
 #[derive(Parser)]
 #[command(name = "entrace_graph_example")]
@@ -213,6 +216,9 @@ pub struct Args {
     pub log_file: Option<String>,
     #[arg(short = 'm', long)]
     pub log_mode: LogMode,
+    /// Write attributes in the compact encoding.
+    #[arg(long)]
+    pub compact_attrs: bool,
     pub work: Work,
 }
 
@@ -221,10 +227,12 @@ fn setup_tracing(args: &Args) -> Box<dyn FnOnce(&Args)> {
         Some(x) => x.as_str(),
         None => match args.log_mode {
             LogMode::DiskET => "log.et",
-            LogMode::DiskIET => "log.iet",
+            LogMode::DiskIET | LogMode::PreviewIET => "log.iet",
             LogMode::StreamingET => "localhost:8000",
         },
     };
+    let attr_encoding =
+        if args.compact_attrs { AttrEncoding::Compact } else { AttrEncoding::Standard };
     pub fn getf(filename: &str) -> std::io::Result<std::fs::File> {
         OpenOptions::new().truncate(true).create(true).write(true).read(true).open(filename)
     }
@@ -232,7 +240,7 @@ fn setup_tracing(args: &Args) -> Box<dyn FnOnce(&Args)> {
     match args.log_mode {
         LogMode::DiskET => {
             let file = getf(log_filename).unwrap();
-            let storage = Arc::new(ETStorage::init(file));
+            let storage = Arc::new(ETStorage::init_with_attr_encoding(file, attr_encoding));
             let tree_layer = TreeLayer::from_storage(storage.clone());
             Registry::default().with(LevelFilter::TRACE).with(tree_layer).init();
             let l_fn2 = log_filename.to_string();
@@ -251,7 +259,9 @@ fn setup_tracing(args: &Args) -> Box<dyn FnOnce(&Args)> {
         }
         LogMode::DiskIET => {
             let file = getf(log_filename).unwrap();
-            let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(file)));
+            let config =
+                IETStorageConfig::non_length_prefixed(file).with_attr_encoding(attr_encoding);
+            let storage = Arc::new(IETStorage::init(config));
             let tree_layer = TreeLayer::from_storage(storage.clone());
             Registry::default().with(LevelFilter::TRACE).with(tree_layer).init();
             Box::new(move |_args| {
@@ -260,13 +270,41 @@ fn setup_tracing(args: &Args) -> Box<dyn FnOnce(&Args)> {
         }
         LogMode::StreamingET => {
             let tcp_stream = TcpStream::connect(log_filename).unwrap();
-            let storage = Arc::new(IETStorage::init(IETStorageConfig::length_prefixed(tcp_stream)));
+            let config =
+                IETStorageConfig::length_prefixed(tcp_stream).with_attr_encoding(attr_encoding);
+            let storage = Arc::new(IETStorage::init(config));
             let tree_layer = TreeLayer::from_storage(storage.clone());
             Registry::default().with(LevelFilter::TRACE).with(tree_layer).init();
             Box::new(move |_args| {
                 storage.finish().unwrap();
             })
         }
+        LogMode::PreviewIET => {
+            let file = getf(log_filename).unwrap();
+            let config =
+                IETStorageConfig::non_length_prefixed(file).with_attr_encoding(attr_encoding);
+            let file_storage = Arc::new(IETStorage::init(config));
+            let (storage, mut preview) =
+                PreviewStorage::new(file_storage.clone(), Default::default());
+            let storage = Arc::new(storage);
+            let tree_layer = TreeLayer::from_storage(storage.clone());
+            Registry::default().with(LevelFilter::TRACE).with(tree_layer).init();
+            // stands in for the UI of a program, which would call frame_callback every frame
+            let preview_thread = thread::spawn(move || {
+                while preview.is_live() {
+                    preview.frame_callback();
+                    let last = preview.len().saturating_sub(1) as u32;
+                    let summary = format_span_oneline(&preview, last).unwrap_or_default();
+                    println!("preview: {} spans, last: {summary}", preview.len());
+                    sleep(Duration::from_millis(500));
+                }
+            });
+            Box::new(move |_args| {
+                storage.finish();
+                preview_thread.join().unwrap();
+                file_storage.finish().unwrap();
+            })
+        }
     }
 }
 pub fn time_print<T>(tag: &str, f: impl FnOnce() -> T) -> T {
@@ -0,0 +1,55 @@
+fn main() {
+    divan::main();
+}
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::{Arc, RwLock},
+};
+
+use divan::Bencher;
+use entrace_core::{LogProviderImpl, test_util::LogProviderBuilder};
+use entrace_query::lua_api::{
+    JoinCtx, LuaBindings, LuaEvalState, ReusableLua, setup_lua_on_arc_rwlock,
+};
+use mlua::{Lua, Value};
+
+const QUERY: &str = "return en_span_cnt()";
+
+fn trace() -> Arc<RwLock<LogProviderImpl>> {
+    let mut builder = LogProviderBuilder::new();
+    builder.span(0, "span").add();
+    Arc::new(RwLock::new(builder.build_impl()))
+}
+fn bindings(trace: &Arc<RwLock<LogProviderImpl>>) -> LuaBindings {
+    LuaBindings {
+        trace: trace.clone(),
+        join_ctx: Arc::new(JoinCtx::from_thread_count(1)),
+        range: 0..=1,
+    }
+}
+
+/// A tiny query the way it ran before workers kept their Lua state: set up from scratch.
+#[divan::bench]
+fn fresh_lua_per_query(bencher: Bencher) {
+    let trace = trace();
+    bencher.bench_local(|| {
+        let mut lua = Lua::new();
+        let join_ctx = Arc::new(JoinCtx::from_thread_count(1));
+        let state = LuaEvalState::new(join_ctx, 0..=1, Rc::new(RefCell::new(HashMap::new())));
+        setup_lua_on_arc_rwlock(&mut lua, trace.clone(), state).unwrap();
+        lua.load(QUERY).eval::<Value>().unwrap();
+    });
+}
+
+/// The same query on a [ReusableLua], only rebinding between runs.
+#[divan::bench]
+fn reused_lua_per_query(bencher: Bencher) {
+    let trace = trace();
+    let lua = ReusableLua::new(bindings(&trace)).unwrap();
+    bencher.bench_local(|| {
+        lua.rebind(bindings(&trace));
+        lua.eval(QUERY, "bench").unwrap();
+    });
+}
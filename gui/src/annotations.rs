@@ -0,0 +1,214 @@
+//! Notes left on spans of a trace, saved to a file in the settings directory.
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    hash::Hasher,
+    path::{Path, PathBuf},
+};
+
+use entrace_core::LogProvider;
+
+use crate::{
+    settings::{LoadSettingsError, get_settings_path},
+    tree::hash_span,
+};
+
+/// FNV-1a. Unlike [std::hash::DefaultHasher], this is the same in every build, so its hashes can
+/// be saved.
+struct Fnv(u64);
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// A hash of the metadata and message of span `id`, saved with its note.
+pub fn note_fingerprint(log: &dyn LogProvider, id: u32) -> Option<u64> {
+    hash_span(log, id, Fnv::default())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    /// See [note_fingerprint].
+    pub fingerprint: u64,
+    pub text: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AnnotationsError {
+    #[error("Cannot find the settings directory")]
+    Settings(#[from] LoadSettingsError),
+    #[error("Failed to read or write {0}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Bad line {0}, expected `<span id> <fingerprint> <note>`")]
+    BadLine(usize),
+}
+
+/// The notes of a trace, by span id.
+///
+/// Span ids don't change as a trace grows, but a file may be replaced by another trace with the
+/// same name, so each note also keeps the [note_fingerprint] of its span, and is only shown while
+/// that still matches.
+#[derive(Debug, Default)]
+pub struct Annotations {
+    notes: HashMap<u32, Note>,
+    /// Where the notes are saved, `None` for traces without a file, like remote ones.
+    path: Option<PathBuf>,
+}
+
+impl Annotations {
+    /// The notes of the trace at `trace_path`, or none if it has no saved notes yet.
+    pub fn load_for(trace_path: &Path) -> Result<Self, AnnotationsError> {
+        let path = annotations_path(trace_path)?;
+        let mut annotations = match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(AnnotationsError::Io(path, e)),
+        };
+        annotations.path = Some(path);
+        Ok(annotations)
+    }
+
+    /// Write the notes to their file, if they have one.
+    pub fn save(&self) -> Result<(), AnnotationsError> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let io_err = |e| AnnotationsError::Io(path.clone(), e);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_err)?;
+        }
+        std::fs::write(path, self.to_text()).map_err(io_err)
+    }
+
+    /// The note on span `id`, if it has one which was left on this same span.
+    pub fn get(&self, log: &dyn LogProvider, id: u32) -> Option<&str> {
+        let note = self.notes.get(&id)?;
+        (note_fingerprint(log, id) == Some(note.fingerprint)).then_some(note.text.as_str())
+    }
+
+    /// Set the note of span `id`. An empty note removes it.
+    pub fn set(&mut self, log: &dyn LogProvider, id: u32, text: &str) {
+        let text = text.trim();
+        match note_fingerprint(log, id) {
+            Some(fingerprint) if !text.is_empty() => {
+                self.notes.insert(id, Note { fingerprint, text: text.into() });
+            }
+            _ => {
+                self.notes.remove(&id);
+            }
+        }
+    }
+
+    /// One line per note, `<span id> <fingerprint> <note>`, with line breaks and backslashes in
+    /// the note escaped.
+    pub fn to_text(&self) -> String {
+        let mut ids: Vec<_> = self.notes.keys().copied().collect();
+        ids.sort_unstable();
+        let mut out = String::new();
+        for id in ids {
+            let Note { fingerprint, text } = &self.notes[&id];
+            let text = text.replace('\\', "\\\\").replace('\n', "\\n");
+            writeln!(out, "{id} {fingerprint:016x} {text}").ok();
+        }
+        out
+    }
+
+    pub fn parse(text: &str) -> Result<Self, AnnotationsError> {
+        let mut notes = HashMap::new();
+        for (idx, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let bad = || AnnotationsError::BadLine(idx + 1);
+            let mut parts = line.splitn(3, ' ');
+            let id = parts.next().and_then(|x| x.parse().ok()).ok_or_else(bad)?;
+            let fingerprint = parts.next().and_then(|x| u64::from_str_radix(x, 16).ok());
+            let fingerprint = fingerprint.ok_or_else(bad)?;
+            let text = unescape(parts.next().ok_or_else(bad)?);
+            notes.insert(id, Note { fingerprint, text });
+        }
+        Ok(Self { notes, path: None })
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            (c, _) => out.push(c),
+        }
+    }
+    out
+}
+
+/// The notes of each trace are saved in the `annotations` directory next to the settings, see
+/// [sidecar_path].
+pub fn annotations_path(trace_path: &Path) -> Result<PathBuf, AnnotationsError> {
+    Ok(sidecar_path("annotations", trace_path)?)
+}
+
+/// Where to save data kept for the trace at `trace_path`: in `dir` next to the settings, in a file
+/// named after the trace and a hash of its full path.
+pub fn sidecar_path(dir: &str, trace_path: &Path) -> Result<PathBuf, LoadSettingsError> {
+    let full_path = std::fs::canonicalize(trace_path).unwrap_or_else(|_| trace_path.into());
+    let mut hasher = Fnv::default();
+    hasher.write(full_path.as_os_str().as_encoded_bytes());
+    let name = trace_path.file_name().unwrap_or_default().to_string_lossy();
+    let file_name = format!("{name}-{:016x}.txt", hasher.finish());
+    Ok(get_settings_path()?.with_file_name(dir).join(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use entrace_core::test_util::LogProviderBuilder;
+
+    use super::Annotations;
+
+    #[test]
+    fn test_round_trip() {
+        let mut builder = LogProviderBuilder::new();
+        let slow = builder.span(0, "request").message("GET /").add();
+        let other = builder.span(0, "request").message("GET /health").add();
+        let log = builder.build_base();
+
+        let mut annotations = Annotations::default();
+        annotations.set(&log, slow, "  this is the slow one\nsee C:\\logs\\n.txt ");
+        annotations.set(&log, other, "");
+        assert_eq!(annotations.get(&log, other), None);
+
+        let path = std::env::temp_dir().join(format!("entrace-notes-{}.txt", std::process::id()));
+        annotations.path = Some(path.clone());
+        annotations.save().unwrap();
+        let loaded = Annotations::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.notes, annotations.notes);
+        assert_eq!(loaded.get(&log, slow), Some("this is the slow one\nsee C:\\logs\\n.txt"));
+
+        // another trace in the same file
+        let mut builder = LogProviderBuilder::new();
+        builder.span(0, "request").message("POST /login").add();
+        assert_eq!(loaded.get(&builder.build_base(), slow), None);
+
+        assert!(Annotations::parse("1 zz note").is_err());
+        assert!(Annotations::parse("1 00ff").is_err());
+    }
+}
@@ -2,6 +2,8 @@ use std::{
     cell::{LazyCell, RefCell},
     cmp::{Reverse, max},
     env,
+    fs::File,
+    io::Read,
     path::{Path, PathBuf},
     sync::{Arc, RwLock, atomic::Ordering},
 };
@@ -9,12 +11,14 @@ use std::{
 use anyhow::Context;
 use clap::Parser;
 use egui::{
-    Color32, Margin, Pos2, Rect, RichText, Stroke, TextEdit, Theme, Ui,
+    Align2, Color32, Id, LayerId, Margin, Order, Pos2, Rect, RichText, Stroke, TextEdit, TextStyle,
+    Theme, Ui,
     epaint::text::{FontInsert, InsertFontFamily},
 };
 use entrace_core::{
-    IETLoadConfig, IETPresentationConfig, LoadConfig, LogProvider,
-    remote::{FileWatchConfig, NotifyExt},
+    GZIP_MAGIC, IETLoadConfig, IETPresentationConfig, LoadConfig, LogProvider, LogProviderImpl,
+    display_error_context, parse_entrace_magic,
+    remote::{FileWatchConfig, NotifyExt, ThrottledRefresher},
 };
 use nucleo_matcher::{
     Matcher, Utf32Str,
@@ -25,21 +29,25 @@ use tracing::info;
 
 use crate::{
     LogState, LogStatus,
+    annotations::Annotations,
     benchmarkers::BenchmarkManager,
     cmdline::Cmdline,
+    command_palette::{Command, CommandPaletteState, command_palette},
     connection_dialog::{ConnectionDialog, connect_dialog},
     convert_dialog::{self, ConvertDialogState},
     enbitvec::EnBitVec,
     ephemeral_settings::EphemeralSettings,
     frame_time::{FrameTimeTracker, TrackFrameTime, us_to_human},
     homepage::center,
-    notifications::{self, NotificationHandle, RefreshToken},
+    notifications::{self, NotificationHandle, NotificationHistoryState, RefreshToken},
     row_height_from_ctx,
     search::{self, LocatingState, SearchState, query_window::query_windows},
     self_tracing::SelfTracingState,
     settings::{self, SettingsDialogState, SettingsState, apply_settings},
+    span_link::{self, GoToLinkState, link_source},
     time_print, time_trace,
     tree::TreeView,
+    tree_state::TreeState,
 };
 pub struct App {
     pub file_picker_state: FilePickerState,
@@ -56,6 +64,9 @@ pub struct App {
     pub benchmarks: BenchmarkManager,
     pub about_state: AboutState,
     pub api_docs_state: ApiDocsState,
+    pub notification_history: NotificationHistoryState,
+    pub command_palette: CommandPaletteState,
+    pub go_to_link: GoToLinkState,
 }
 impl Default for App {
     fn default() -> Self {
@@ -74,6 +85,9 @@ impl Default for App {
             benchmarks: BenchmarkManager::default(),
             about_state: AboutState::new(),
             api_docs_state: ApiDocsState::default(),
+            notification_history: NotificationHistoryState::default(),
+            command_palette: CommandPaletteState::default(),
+            go_to_link: GoToLinkState::default(),
         }
     }
 }
@@ -143,19 +157,136 @@ impl App {
         app
     }
 
+    /// Export the open trace in the background. This works on a [LogProvider::snapshot], so a
+    /// live trace keeps updating meanwhile.
+    fn export_ndjson(&mut self) {
+        let LogStatus::Ready(ref state) = self.log_status else { return };
+        let mut files = FileDialog::new().add_filter("NDJSON", &["ndjson", "jsonl"]);
+        if let Some(name) = state.file_path.file_stem() {
+            files = files.set_file_name(format!("{}.ndjson", name.to_string_lossy()));
+        }
+        let Some(out_path) = files.save_file() else { return };
+        let snapshot = state.trace_provider.read().unwrap().snapshot();
+        let notifier = self.notifier.clone();
+        spawn_task(move || {
+            let written = std::fs::File::create(&out_path)
+                .context("Failed to create the output file")
+                .and_then(|file| {
+                    let writer = std::io::BufWriter::new(file);
+                    Ok(entrace_core::export::export_ndjson(&*snapshot, writer)?)
+                });
+            match written {
+                Ok(()) => notifier.info(format!("Exported to {}", out_path.display())),
+                Err(e) => notifier.error(format!("Export failed: {e:#}")),
+            }
+        });
+    }
+
+    /// Run an action of the menus or the [command palette](crate::command_palette). Actions on the
+    /// open trace do nothing without one.
+    pub fn run_command(&mut self, command: Command, ctx: &egui::Context) {
+        match command {
+            Command::OpenFile => {
+                let mut files = FileDialog::new();
+                if let Ok(x) = env::current_dir() {
+                    files = files.set_directory(x)
+                }
+                if let Some(picked) = files.pick_file() {
+                    self.open_file(picked, ctx.clone());
+                }
+            }
+            Command::ConnectRemote => self.connect_dialog = ConnectionDialog::new_connection(),
+            Command::ExportNdjson => self.export_ndjson(),
+            Command::CloseTrace => self.close_trace(),
+            Command::Quit => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+            Command::Convert => self.convert_dialog = ConvertDialogState::Open(Default::default()),
+            Command::ClearResults => self.search_state.clear_results(),
+            Command::CombineResults => self.search_state.combine.open = true,
+            Command::NotificationHistory => self.notification_history.open = true,
+            Command::Settings => self.open_settings(),
+            Command::LuaApiDocs => self.api_docs_state.open = true,
+            Command::ThirdPartyLicenses => self.about_state.open = true,
+            Command::RunQuery => {
+                let LogStatus::Ready(ref state) = self.log_status else { return };
+                self.search_state.new_query(
+                    state.trace_provider.clone(),
+                    state.time_travel.unwrap_or(u32::MAX),
+                    ctx.clone(),
+                    self.notifier.clone(),
+                );
+            }
+            Command::CollapseAll => {
+                let LogStatus::Ready(ref mut state) = self.log_status else { return };
+                let len = state.is_open.len();
+                state.is_open = EnBitVec::repeat(false, len);
+                state.meta_open = EnBitVec::repeat(false, len);
+                state.dup_open = EnBitVec::repeat(false, len);
+                state.tree_view.invalidate();
+            }
+            Command::TogglePause => {
+                let LogStatus::Ready(ref mut state) = self.log_status else { return };
+                state.paused = !state.paused;
+            }
+            Command::FollowLive => {
+                let LogStatus::Ready(ref mut state) = self.log_status else { return };
+                state.time_travel = None;
+                state.tree_view.invalidate();
+            }
+            Command::GoToLink => {
+                self.go_to_link.open = true;
+                self.go_to_link.error = None;
+            }
+        }
+    }
+    fn open_settings(&mut self) {
+        match &self.settings {
+            SettingsState::None | SettingsState::Loading(..) => {
+                self.settings_dialog = SettingsDialogState::Loading;
+            }
+            SettingsState::Loaded(inner) => {
+                self.settings_dialog = SettingsDialogState::Some {
+                    settings_clone: inner.settings.clone(),
+                    settings_path: LazyCell::new(|| {
+                        settings::get_settings_path()
+                            .map(|x| x.to_string_lossy().into_owned())
+                            .unwrap_or("unknown".into())
+                    }),
+                };
+            }
+        }
+    }
+
+    /// Go back to the state before a trace was opened, stopping the worker of a live trace and
+    /// dropping query results.
+    pub fn close_trace(&mut self) {
+        let status = std::mem::replace(&mut self.log_status, LogStatus::NoFileOpened);
+        self.search_state.clear_results();
+        let LogStatus::Ready(mut state) = status else { return };
+        save_tree_state(&mut state, &self.notifier);
+        close_provider(state.trace_provider);
+    }
+
     pub fn open_file(&mut self, path: impl AsRef<Path> + Send + 'static, ctx: egui::Context) {
+        // saves the tree state of the trace open now
+        self.close_trace();
         let path_clone = path.as_ref().to_path_buf();
         let (tx, rx) = crossbeam::channel::bounded(1);
         self.log_status = LogStatus::Loading(rx);
         info!("set log status to loading");
+        let notifier = self.notifier.clone();
+        let expand_depth = self.settings.expand_depth();
+        let refresh_throttle = self.settings.refresh_throttle();
         spawn_task(move || {
             let (event_tx, event_rx) = crossbeam::channel::unbounded();
-            let presentation =
-                IETPresentationConfig { event_tx: Some(event_tx), refresher: RefreshToken(ctx) };
+            let presentation = IETPresentationConfig {
+                event_tx: Some(event_tx),
+                refresher: ThrottledRefresher::new(RefreshToken(ctx.clone()), refresh_throttle),
+            };
             let load_config = LoadConfig {
                 iht: IETLoadConfig {
                     watch: FileWatchConfig::Watch(path.as_ref().to_path_buf()),
                     presentation,
+                    attr_transform: None,
                 },
             };
             let trace = time_print("loading trace", || unsafe {
@@ -164,23 +295,85 @@ impl App {
             match trace {
                 Ok(x) => {
                     let cap = max(x.len(), 1);
-                    let has_open_children = EnBitVec::repeat(false, cap);
+                    let tree_state = match TreeState::load_for(&path_clone, cap) {
+                        Ok(tree_state) => tree_state,
+                        Err(e) => {
+                            notifier.error_chain(&e);
+                            None
+                        }
+                    };
+                    let tree_state = tree_state.unwrap_or_else(|| {
+                        let mut tree_state = TreeState::closed(cap);
+                        tree_state.open_to_depth(&x, expand_depth);
+                        tree_state
+                    });
+                    let is_live = x.is_live();
+                    let source_bytes = x.source_bytes();
+                    // a broken notes file is left alone, so nothing is saved over it
+                    let annotations = Annotations::load_for(&path_clone).unwrap_or_else(|e| {
+                        notifier
+                            .error(format!("Failed to load notes: {}", display_error_context(&e)));
+                        Annotations::default()
+                    });
                     tx.send(LogStatus::Ready(LogState {
+                        link_source: link_source(&path_clone, true),
                         file_path: path_clone,
+                        from_file: true,
                         trace_provider: Arc::new(RwLock::new(x)),
-                        is_open: has_open_children,
-                        meta_open: EnBitVec::repeat(false, cap),
+                        is_open: tree_state.is_open,
+                        meta_open: tree_state.meta_open,
+                        dup_open: tree_state.dup_open,
                         locating_state: RefCell::new(LocatingState::None),
                         tree_view: TreeView::default(),
                         event_rx: Some(event_rx),
+                        attr_universe: Default::default(),
+                        health: Default::default(),
+                        is_live,
+                        annotations,
+                        time_travel: None,
+                        source_bytes,
+                        paused: false,
                     }))
                     .unwrap();
                 }
                 Err(x) => tx.send(LogStatus::Error(x.into())).unwrap(),
             }
+            ctx.request_repaint();
         });
     }
 
+    /// Open a trace dropped on the window, and show a drop target while files are dragged over it.
+    /// Only one trace can be open, so of several dropped files only the first one is opened.
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let (hovering, dropped) =
+            ctx.input(|i| (!i.raw.hovered_files.is_empty(), i.raw.dropped_files.clone()));
+        if hovering {
+            let painter =
+                ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop_target")));
+            let rect = ctx.content_rect();
+            painter.rect_filled(rect, 0, Color32::from_black_alpha(192));
+            let font = TextStyle::Heading.resolve(&ctx.global_style());
+            painter.text(
+                rect.center(),
+                Align2::CENTER_CENTER,
+                "Drop a trace to open it",
+                font,
+                Color32::WHITE,
+            );
+        }
+        let mut paths = dropped.into_iter().filter_map(|x| x.path);
+        let Some(path) = paths.next() else { return };
+        let skipped = paths.count();
+        if skipped > 0 {
+            self.notifier
+                .info(format!("Opening {}, {skipped} other file(s) skipped", path.display()));
+        }
+        match check_trace_magic(&path) {
+            Ok(()) => self.open_file(path, ctx.clone()),
+            Err(e) => self.notifier.error(format!("Can't open {}: {e:#}", path.display())),
+        }
+    }
+
     pub fn update_inner(&mut self, ui: &mut Ui, _frame: &mut eframe::Frame) {
         self.frame_time_tracker.start_frame();
         #[cfg(debug_assertions)]
@@ -237,50 +430,53 @@ impl App {
             egui::MenuBar::new().ui(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open").clicked() {
-                        let mut files = FileDialog::new();
-                        if let Ok(x) = env::current_dir() {
-                            files = files.set_directory(x)
-                        }
-                        if let Some(picked) = files.pick_file() {
-                            self.open_file(picked, ui.ctx().clone());
-                        }
+                        self.run_command(Command::OpenFile, ui.ctx());
                     };
+                    let can_export = matches!(self.log_status, LogStatus::Ready(_));
+                    if ui.add_enabled(can_export, egui::Button::new("Export as NDJSON")).clicked() {
+                        self.run_command(Command::ExportNdjson, ui.ctx());
+                    }
+                    let can_close =
+                        matches!(self.log_status, LogStatus::Ready(_) | LogStatus::Error(_));
+                    if ui.add_enabled(can_close, egui::Button::new("Close trace")).clicked() {
+                        self.run_command(Command::CloseTrace, ui.ctx());
+                    }
                     if ui.button("Remote").clicked() {
-                        self.connect_dialog = ConnectionDialog::new_connection();
+                        self.run_command(Command::ConnectRemote, ui.ctx());
                     };
                     if ui.button("Quit").clicked() {
-                        ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+                        self.run_command(Command::Quit, ui.ctx());
                     }
                 });
                 ui.menu_button("Tools", |ui| {
                     if ui.button("Convert").clicked() {
-                        self.convert_dialog = ConvertDialogState::Open(Default::default());
+                        self.run_command(Command::Convert, ui.ctx());
+                    }
+                    if ui.button("Notification history").clicked() {
+                        self.run_command(Command::NotificationHistory, ui.ctx());
+                    }
+                    let has_trace = matches!(self.log_status, LogStatus::Ready(_));
+                    if ui.add_enabled(has_trace, egui::Button::new("Go to span link")).clicked() {
+                        self.run_command(Command::GoToLink, ui.ctx());
+                    }
+                    let hover = format!(
+                        "Search all actions ({})",
+                        ui.ctx().format_shortcut(&crate::command_palette::SHORTCUT)
+                    );
+                    if ui.button("Command palette").on_hover_text(hover).clicked() {
+                        self.command_palette.toggle();
                     }
                 });
                 if ui.button("Settings").clicked() {
-                    match &self.settings {
-                        SettingsState::None | SettingsState::Loading(..) => {
-                            self.settings_dialog = SettingsDialogState::Loading;
-                        }
-                        SettingsState::Loaded(inner) => {
-                            self.settings_dialog = SettingsDialogState::Some {
-                                settings_clone: inner.settings.clone(),
-                                settings_path: LazyCell::new(|| {
-                                    settings::get_settings_path()
-                                        .map(|x| x.to_string_lossy().into_owned())
-                                        .unwrap_or("unknown".into())
-                                }),
-                            };
-                        }
-                    }
+                    self.run_command(Command::Settings, ui.ctx());
                 };
                 ui.menu_button("About", |ui| {
                     ui.label(format!("ENTRACE GUI {}", env!("CARGO_PKG_VERSION")));
                     if ui.button("Lua API Docs").clicked() {
-                        self.api_docs_state.open = true;
+                        self.run_command(Command::LuaApiDocs, ui.ctx());
                     }
                     if ui.button("Third-party licenses").clicked() {
-                        self.about_state.open = true;
+                        self.run_command(Command::ThirdPartyLicenses, ui.ctx());
                     }
                 });
                 ui.add_space(16.0);
@@ -288,7 +484,7 @@ impl App {
                     && let Some(avg_time_us) = self.frame_time_tracker.get_average_us()
                 {
                     ui.horizontal(|ui| {
-                        ui.label(us_to_human(avg_time_us));
+                        ui.label(us_to_human(avg_time_us.into()));
                         let fps = (1000000.0 / avg_time_us as f64) as u64;
                         ui.label(format!("{fps} FPS"));
                     });
@@ -310,6 +506,7 @@ impl App {
                         &mut self.search_state,
                         &mut self.api_docs_state,
                         log_state,
+                        &self.notifier,
                         text_field_margin,
                     );
                 });
@@ -322,6 +519,12 @@ impl App {
             query_windows(ui, self);
             about_dialog(ui.ctx(), self);
             api_docs_dialog(ui.ctx(), &mut self.api_docs_state);
+            notifications::notification_history(ui.ctx(), self);
+            span_link::go_to_link_dialog(ui.ctx(), self);
+            let has_trace = matches!(self.log_status, LogStatus::Ready(_));
+            if let Some(command) = command_palette(ui.ctx(), &mut self.command_palette, has_trace) {
+                self.run_command(command, ui.ctx());
+            }
             let available_rect = ui.available_rect_before_wrap();
             let notification_area = Rect::from_min_max(
                 Pos2::new(available_rect.right() - 200.0, available_rect.top()),
@@ -332,16 +535,56 @@ impl App {
                 notifications::notifications(ui, self).response
             });
         });
+        self.handle_dropped_files(ui.ctx());
         self.frame_time_tracker.end_frame();
+        if self.ephemeral_settings.continuous_repaint {
+            ui.ctx().request_repaint();
+        }
+    }
+}
+/// Check that `path` starts like a trace, a compressed one included, so that dropping some other
+/// file doesn't replace the open trace with an error.
+fn check_trace_magic(path: &Path) -> anyhow::Result<()> {
+    let mut magic = [0; 10];
+    let mut file = File::open(path).context("Failed to open the file")?;
+    file.read_exact(&mut magic).context("The file is too short to be a trace")?;
+    if magic[..2] != GZIP_MAGIC {
+        parse_entrace_magic(&magic).context("Not an entrace trace")?;
     }
+    Ok(())
 }
+
 // simple right now, but might get replaced by a thread pool later.
+/// Save which spans of a trace opened from a file are expanded, to restore them when it is opened
+/// again. This takes them out of `state`, so only call it when closing the trace.
+fn save_tree_state(state: &mut LogState, notifier: &NotificationHandle) {
+    if !state.from_file {
+        return;
+    }
+    let tree_state = TreeState {
+        len: state.trace_provider.read().unwrap().len(),
+        is_open: std::mem::take(&mut state.is_open),
+        meta_open: std::mem::take(&mut state.meta_open),
+        dup_open: std::mem::take(&mut state.dup_open),
+    };
+    if let Err(e) = tree_state.save_for(&state.file_path) {
+        notifier.error_chain(&e);
+    }
+}
+
 pub fn spawn_task(f: impl FnOnce() + Send + 'static) {
     std::thread::spawn(f);
 }
 impl eframe::App for App {
     fn save(&mut self, _storage: &mut dyn eframe::Storage) {}
 
+    fn on_exit(&mut self) {
+        if let LogStatus::Ready(state) = &mut self.log_status {
+            save_tree_state(state, &self.notifier);
+        }
+        self.self_tracing_state.finish();
+    }
+
     fn ui(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
         #[cfg(feature = "dev")]
         {
@@ -492,3 +735,108 @@ fn api_docs_dialog(ctx: &egui::Context, state: &mut ApiDocsState) {
         });
     state.open = open;
 }
+
+/// Stop the worker of `trace` and free it. Returns whether it was closed right away.
+///
+/// A running query or export may still use the trace, then it is dropped when they finish, which
+/// stops the worker too.
+fn close_provider(trace: Arc<RwLock<LogProviderImpl>>) -> bool {
+    let Ok(provider) = Arc::try_unwrap(trace) else {
+        info!("Trace is still in use, closing it later");
+        return false;
+    };
+    let provider = provider.into_inner().unwrap_or_else(|x| x.into_inner());
+    if let Some(worker) = provider.close() {
+        spawn_task(move || {
+            worker.join().ok();
+            info!("Trace worker stopped");
+        });
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, RwLock},
+        time::{Duration, Instant},
+    };
+
+    use entrace_core::{StorageFormat, entrace_magic_for, test_util::LogProviderBuilder};
+
+    use super::{App, check_trace_magic, close_provider};
+    use crate::{
+        notifications::{NotificationHandle, NotificationState},
+        search::{Query, SearchState},
+    };
+
+    #[test]
+    fn test_check_trace_magic() {
+        let dir = std::env::temp_dir();
+        let check = |name: &str, contents: &[u8]| {
+            let path = dir.join(format!("entrace-drop-{}-{name}", std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            let result = check_trace_magic(&path);
+            std::fs::remove_file(&path).ok();
+            result
+        };
+        assert!(check("trace.iet", &entrace_magic_for(3, StorageFormat::IET)).is_ok());
+        assert!(check("trace.iet.gz", &[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3]).is_ok());
+        assert!(check("notes.txt", b"just some text").is_err());
+        assert!(check("short", &[0]).is_err());
+        assert!(check_trace_magic(&dir.join("entrace-drop-missing")).is_err());
+    }
+
+    #[test]
+    fn test_close_trace_after_query() {
+        let mut builder = LogProviderBuilder::new();
+        builder.span(0, "span").add();
+        let trace = Arc::new(RwLock::new(builder.build_impl()));
+        let mut search = SearchState::new();
+        let notifier = NotificationHandle(Arc::new(RwLock::new(NotificationState::new())));
+        let ctx = egui::Context::default();
+        search.new_query_with_text(
+            Arc::from("return { 1 }"),
+            trace.clone(),
+            u32::MAX,
+            ctx,
+            notifier,
+        );
+        let start = Instant::now();
+        while let Query::Loading { .. } = search.queries[0] {
+            assert!(start.elapsed() < Duration::from_secs(5), "query didn't finish");
+            search.queries[0].receive(&mut search.query_timing[0]);
+        }
+        let Query::Completed { result: Ok(result), .. } = &search.queries[0] else {
+            panic!("query failed: {:?}", search.queries[0]);
+        };
+        assert_eq!(result.ids, [1]);
+        // the idle query workers don't hold on to the trace
+        search.clear_results();
+        assert!(close_provider(trace));
+    }
+
+    /// The delay until the next frame egui asks for after `frames` frames without input.
+    fn idle_repaint_delay(app: &mut App, frames: usize) -> Duration {
+        let ctx = egui::Context::default();
+        let mut frame = eframe::Frame::_new_kittest();
+        let mut delay = Duration::ZERO;
+        for _ in 0..frames {
+            let output =
+                ctx.run_ui(egui::RawInput::default(), |ui| app.update_inner(ui, &mut frame));
+            delay = output.viewport_output[&egui::ViewportId::ROOT].repaint_delay;
+        }
+        delay
+    }
+
+    #[test]
+    fn test_idle_app_sleeps() {
+        // egui draws a few frames on its own at startup, for layout
+        let mut app = App::default();
+        assert_eq!(idle_repaint_delay(&mut app, 5), Duration::MAX);
+
+        let mut app = App::default();
+        app.ephemeral_settings.continuous_repaint = true;
+        assert_eq!(idle_repaint_delay(&mut app, 5), Duration::ZERO);
+    }
+}
@@ -0,0 +1,217 @@
+//! A searchable list of the actions of the GUI, opened with [SHORTCUT]. The actions are listed in
+//! [COMMANDS], and run by [crate::app::App::run_command].
+use std::cmp::Reverse;
+
+use egui::{Align2, Key, KeyboardShortcut, Modifiers, TextEdit};
+use nucleo_matcher::{
+    Matcher, Utf32Str,
+    pattern::{AtomKind, CaseMatching, Normalization, Pattern},
+};
+
+/// Opens and closes the palette.
+pub const SHORTCUT: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::P);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    OpenFile,
+    ConnectRemote,
+    ExportNdjson,
+    CloseTrace,
+    Quit,
+    Convert,
+    RunQuery,
+    ClearResults,
+    CombineResults,
+    CollapseAll,
+    TogglePause,
+    FollowLive,
+    GoToLink,
+    NotificationHistory,
+    Settings,
+    LuaApiDocs,
+    ThirdPartyLicenses,
+}
+pub struct CommandInfo {
+    pub command: Command,
+    /// What the palette shows and searches.
+    pub name: &'static str,
+    pub hover: &'static str,
+    /// Only available while a trace is open.
+    pub needs_trace: bool,
+}
+const fn info(
+    command: Command, name: &'static str, hover: &'static str, needs_trace: bool,
+) -> CommandInfo {
+    CommandInfo { command, name, hover, needs_trace }
+}
+/// Every command of the palette, in the order shown before anything is typed.
+pub const COMMANDS: &[CommandInfo] = &[
+    info(Command::OpenFile, "Open file", "Pick a trace file to open", false),
+    info(Command::ConnectRemote, "Connect to remote", "Receive a trace over the network", false),
+    info(Command::RunQuery, "Run query", "Run the query in the search box", true),
+    info(Command::ClearResults, "Clear query results", "Close every query window", false),
+    info(Command::CombineResults, "Combine results", "Union, intersect or diff two queries", true),
+    info(Command::CollapseAll, "Collapse all", "Collapse every span of the tree", true),
+    info(Command::TogglePause, "Pause / resume updates", "Stop or restart live updates", true),
+    info(Command::FollowLive, "Follow live", "Stop time travel, show the whole trace", true),
+    info(Command::GoToLink, "Go to span link", "Find the span of an entrace:// link", true),
+    info(Command::ExportNdjson, "Export as NDJSON", "Save the open trace as NDJSON", true),
+    info(Command::CloseTrace, "Close trace", "Close the open trace", true),
+    info(Command::Convert, "Convert", "Convert a trace between formats", false),
+    info(Command::NotificationHistory, "Notification history", "Show past notifications", false),
+    info(Command::Settings, "Settings", "Edit the settings", false),
+    info(Command::LuaApiDocs, "Lua API docs", "Functions available in queries", false),
+    info(Command::ThirdPartyLicenses, "Third-party licenses", "Licenses of dependencies", false),
+    info(Command::Quit, "Quit", "Close ENTRACE", false),
+];
+
+#[derive(Default)]
+pub struct CommandPaletteState {
+    pub open: bool,
+    pub search_buf: String,
+    matcher: Option<Matcher>,
+    /// Indices into [COMMANDS], best match first.
+    matches: Vec<usize>,
+    /// Index into `matches`.
+    selected: usize,
+    /// Focus the search box in the next frame.
+    focus: bool,
+}
+impl CommandPaletteState {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        if self.open {
+            self.search_buf.clear();
+            self.search();
+            self.focus = true;
+        }
+    }
+    fn search(&mut self) {
+        self.matches.clear();
+        self.selected = 0;
+        let pattern = Pattern::new(
+            &self.search_buf,
+            CaseMatching::Ignore,
+            Normalization::Smart,
+            AtomKind::Fuzzy,
+        );
+        if pattern.atoms.is_empty() {
+            self.matches.extend(0..COMMANDS.len());
+            return;
+        }
+        let matcher =
+            self.matcher.get_or_insert_with(|| Matcher::new(nucleo_matcher::Config::DEFAULT));
+        let mut buf = Vec::new();
+        let mut scored: Vec<(usize, u32)> = COMMANDS
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, x)| {
+                pattern.score(Utf32Str::new(x.name, &mut buf), matcher).map(|score| (idx, score))
+            })
+            .collect();
+        // stable, so equal scores keep the order of COMMANDS
+        scored.sort_by_key(|(_, score)| Reverse(*score));
+        self.matches.extend(scored.into_iter().map(|(idx, _)| idx));
+    }
+}
+
+/// Draw the palette if it is open, and toggle it on [SHORTCUT]. Returns the command picked with a
+/// click or Enter, then the palette closes.
+pub fn command_palette(
+    ctx: &egui::Context, state: &mut CommandPaletteState, has_trace: bool,
+) -> Option<Command> {
+    if ctx.input_mut(|i| i.consume_shortcut(&SHORTCUT)) {
+        state.toggle();
+    }
+    if !state.open {
+        return None;
+    }
+    let mut picked = None;
+    egui::Window::new("Command palette")
+        .title_bar(false)
+        .resizable(false)
+        .collapsible(false)
+        .anchor(Align2::CENTER_TOP, [0.0, 48.0])
+        .fixed_size([360.0, 0.0])
+        .show(ctx, |ui| {
+            // before the search box, which would use the arrows and Enter itself
+            let (up, down, enter, escape) = ui.input_mut(|i| {
+                (
+                    i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                    i.consume_key(Modifiers::NONE, Key::ArrowDown),
+                    i.consume_key(Modifiers::NONE, Key::Enter),
+                    i.key_pressed(Key::Escape),
+                )
+            });
+            let len = state.matches.len();
+            if len > 0 && down {
+                state.selected = (state.selected + 1) % len;
+            }
+            if len > 0 && up {
+                state.selected = (state.selected + len - 1) % len;
+            }
+            let search = TextEdit::singleline(&mut state.search_buf)
+                .hint_text("Type a command")
+                .desired_width(f32::INFINITY);
+            let search = ui.add(search);
+            if std::mem::take(&mut state.focus) {
+                search.request_focus();
+            }
+            if search.changed() {
+                state.search();
+            }
+            for (row, &idx) in state.matches.iter().enumerate() {
+                let info = &COMMANDS[idx];
+                let enabled = has_trace || !info.needs_trace;
+                let button = egui::Button::selectable(row == state.selected, info.name);
+                let button = ui.add_enabled(enabled, button).on_hover_text(info.hover);
+                if button.clicked() || (enter && enabled && row == state.selected) {
+                    picked = Some(info.command);
+                }
+            }
+            if escape {
+                state.open = false;
+            }
+        });
+    if picked.is_some() {
+        state.open = false;
+    }
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(state: &CommandPaletteState) -> Vec<&'static str> {
+        state.matches.iter().map(|&idx| COMMANDS[idx].name).collect()
+    }
+
+    #[test]
+    fn test_registry() {
+        assert!(COMMANDS.len() >= 12);
+        for (i, a) in COMMANDS.iter().enumerate() {
+            for b in &COMMANDS[i + 1..] {
+                assert_ne!(a.command, b.command, "{} is listed twice", a.name);
+                assert_ne!(a.name, b.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let mut state = CommandPaletteState::default();
+        state.toggle();
+        assert_eq!(names(&state).len(), COMMANDS.len());
+        state.search_buf = "colall".into();
+        state.search();
+        assert_eq!(names(&state), ["Collapse all"]);
+        state.search_buf = "query".into();
+        state.search();
+        assert_eq!(names(&state)[..2], ["Run query", "Clear query results"]);
+        state.search_buf = "zzz".into();
+        state.search();
+        assert!(names(&state).is_empty());
+    }
+}
@@ -1,20 +1,21 @@
 use std::{
     cell::RefCell,
-    net::TcpListener,
-    path::PathBuf,
+    net::{TcpListener, ToSocketAddrs},
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
 use egui::Context;
 use entrace_core::{
-    IETPresentationConfig, LogProviderImpl,
-    remote::{IETEvent, RemoteLogProvider},
+    IETPresentationConfig, LogProvider, LogProviderImpl,
+    remote::{IETEvent, RemoteLogProvider, ThrottledRefresher},
 };
 use tracing::info;
 
 use crate::{
     App, LogState, LogStatus, enbitvec::EnBitVec, notifications::RefreshToken,
-    search::LocatingState, tree::TreeView,
+    search::LocatingState, span_link::link_source, tree::TreeView,
 };
 
 pub enum ConnectionDialogState {
@@ -22,34 +23,68 @@ pub enum ConnectionDialogState {
     SetupConnection,
     SetupError(String),
 }
+/// Which side of the connection the viewer is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionMode {
+    /// Start a server, and wait for the traced process to connect, see [RemoteLogProvider::new].
+    Listen,
+    /// Connect to a traced process which is listening, see [RemoteLogProvider::connect].
+    Connect,
+}
 pub struct ConnectionDialog {
     pub connect_url: String,
+    pub mode: ConnectionMode,
     pub state: ConnectionDialogState,
 }
 impl ConnectionDialog {
     pub fn not_open() -> Self {
-        Self { connect_url: String::new(), state: ConnectionDialogState::NotOpen }
+        Self {
+            connect_url: String::new(),
+            mode: ConnectionMode::Listen,
+            state: ConnectionDialogState::NotOpen,
+        }
     }
     pub fn new_connection() -> Self {
-        Self { connect_url: "localhost:8000".into(), state: ConnectionDialogState::SetupConnection }
+        Self {
+            connect_url: "localhost:8000".into(),
+            mode: ConnectionMode::Listen,
+            state: ConnectionDialogState::SetupConnection,
+        }
     }
     pub fn is_some(&self) -> bool {
         !matches!(self.state, ConnectionDialogState::NotOpen)
     }
+    /// Start the connection, repainting for new spans at most once per `refresh_throttle`.
     pub fn connect(
         &mut self, context: &Context, event_tx: Option<crossbeam::channel::Sender<IETEvent>>,
+        refresh_throttle: Duration,
     ) -> Option<RemoteLogProvider> {
-        let tcp_listener = match TcpListener::bind(&self.connect_url) {
-            Ok(tcp_listener) => tcp_listener,
-            Err(x) => {
-                self.state = ConnectionDialogState::SetupError(x.to_string());
-                return None;
+        let refresher = ThrottledRefresher::new(RefreshToken(context.clone()), refresh_throttle);
+        let iht_config = IETPresentationConfig { refresher, event_tx };
+        match self.mode {
+            ConnectionMode::Listen => match TcpListener::bind(&self.connect_url) {
+                Ok(tcp_listener) => Some(RemoteLogProvider::new(tcp_listener, iht_config)),
+                Err(x) => {
+                    self.state = ConnectionDialogState::SetupError(x.to_string());
+                    None
+                }
+            },
+            ConnectionMode::Connect => {
+                let addr = self.connect_url.to_socket_addrs().map(|mut x| x.next());
+                match addr {
+                    Ok(Some(addr)) => Some(RemoteLogProvider::connect(addr, iht_config)),
+                    Ok(None) => {
+                        let error = format!("{} has no address", self.connect_url);
+                        self.state = ConnectionDialogState::SetupError(error);
+                        None
+                    }
+                    Err(x) => {
+                        self.state = ConnectionDialogState::SetupError(x.to_string());
+                        None
+                    }
+                }
             }
-        };
-        let ctx = context.clone();
-        let iht_config = IETPresentationConfig { refresher: RefreshToken(ctx), event_tx };
-        let provider = RemoteLogProvider::new(tcp_listener, iht_config);
-        Some(provider)
+        }
     }
 }
 
@@ -59,35 +94,66 @@ pub fn connect_dialog(ctx: &Context, app: &mut App) {
     match &app.connect_dialog.state {
         ConnectionDialogState::NotOpen => (),
         ConnectionDialogState::SetupError(x) => {
-            egui::Window::new("Server").open(&mut open).show(ctx, |ui| {
+            egui::Window::new("Remote").open(&mut open).show(ctx, |ui| {
                 ui.label(format!("Error while setting up connection: {x}"));
             });
         }
 
         ConnectionDialogState::SetupConnection => {
+            let refresh_throttle = app.settings.refresh_throttle();
             let dialog = &mut app.connect_dialog;
-            egui::Window::new("Server").open(&mut open).show(ctx, |ui| {
+            egui::Window::new("Remote").open(&mut open).show(ctx, |ui| {
+                ui.radio_value(
+                    &mut dialog.mode,
+                    ConnectionMode::Listen,
+                    "Listen for the traced process to connect",
+                );
+                ui.radio_value(
+                    &mut dialog.mode,
+                    ConnectionMode::Connect,
+                    "Connect to the traced process, which is listening",
+                );
                 ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
-                    ui.label("Server URL: ");
+                    ui.label(match dialog.mode {
+                        ConnectionMode::Listen => "Listen on: ",
+                        ConnectionMode::Connect => "Connect to: ",
+                    });
                     egui::TextEdit::singleline(&mut dialog.connect_url)
                         .desired_width(0.0)
                         .clip_text(false)
                         .show(ui);
-                    if ui.button("Start").clicked() {
+                    let start_text = match dialog.mode {
+                        ConnectionMode::Listen => "Start",
+                        ConnectionMode::Connect => "Connect",
+                    };
+                    if ui.button(start_text).clicked() {
                         let (event_tx, event_rx) = crossbeam::channel::unbounded();
-                        if let Some(provider) = dialog.connect(ui.ctx(), Some(event_tx)) {
+                        if let Some(provider) =
+                            dialog.connect(ui.ctx(), Some(event_tx), refresh_throttle)
+                        {
                             let is_open = EnBitVec::repeat(false, 1);
                             let meta_open = EnBitVec::repeat(false, 1);
+                            let source_bytes = provider.source_bytes();
                             app.log_status = LogStatus::Ready(LogState {
                                 file_path: PathBuf::from(&dialog.connect_url),
+                                from_file: false,
+                                link_source: link_source(Path::new(&dialog.connect_url), false),
                                 trace_provider: Arc::new(RwLock::new(LogProviderImpl::Remote(
                                     provider,
                                 ))),
                                 is_open,
                                 meta_open,
+                                dup_open: EnBitVec::repeat(false, 1),
                                 locating_state: RefCell::new(LocatingState::None),
                                 tree_view: TreeView::new(),
                                 event_rx: Some(event_rx),
+                                attr_universe: Default::default(),
+                                health: Default::default(),
+                                is_live: true,
+                                annotations: Default::default(),
+                                time_travel: None,
+                                source_bytes,
+                                paused: false,
                             });
                         }
                         info!("Connect clicked");
@@ -4,17 +4,18 @@ use std::{
     fs::{File, OpenOptions},
     io::{BufReader, BufWriter, Write},
     path::PathBuf,
+    sync::{Arc, RwLock},
     time::{Duration, Instant},
 };
 
 use anyhow::{Context, bail};
 use crossbeam::channel::Receiver;
 use egui::RichText;
-use entrace_core::{convert::ConvertError, display_error_context};
+use entrace_core::{LogProvider, LogProviderImpl, convert::ConvertError, display_error_context};
 use rfd::FileDialog;
 use tracing::{trace, warn};
 
-use crate::{App, settings::left_stroke_frame, spawn_task, time_print};
+use crate::{App, LogStatus, settings::left_stroke_frame, spawn_task, time_print};
 
 #[derive(Default)]
 pub enum ConvertDialogState {
@@ -43,6 +44,8 @@ pub struct ConvertDialogStateInner {
     error: Option<ConvertDialogError>,
     input: ConvertFilePath,
     output: ConvertFilePath,
+    /// Convert the open trace, instead of reading [ConvertDialogStateInner::input].
+    use_loaded: bool,
 }
 
 impl Default for ConvertDialogStateInner {
@@ -52,6 +55,7 @@ impl Default for ConvertDialogStateInner {
             input: ConvertFilePath::default(),
             output: ConvertFilePath { path: None, ty: ConvertFileType::ET },
             error: None,
+            use_loaded: false,
         }
     }
 }
@@ -77,10 +81,28 @@ pub fn convert_dialog(ui: &mut egui::Ui, app: &mut App) {
     let ConvertDialogState::Open(ref mut inner) = app.convert_dialog else {
         return;
     };
+    let loaded = match app.log_status {
+        LogStatus::Ready(ref state) => Some((&state.file_path, &state.trace_provider)),
+        _ => None,
+    };
+    inner.use_loaded &= loaded.is_some();
     let mut open = true;
     egui::Window::new("Convert").open(&mut open).show(ui.ctx(), |ui| {
         ui.label("Input");
         left_stroke_frame(ui, |ui| {
+            if let Some((path, _)) = loaded {
+                ui.checkbox(&mut inner.use_loaded, "Use the loaded trace").on_hover_text(
+                    "Write the trace which is open now, without reading its file again. A live \
+                     trace is written as it is when the conversion starts.",
+                );
+                if inner.use_loaded {
+                    ui.horizontal(|ui| {
+                        ui.label("Trace:");
+                        ui.code(path.display().to_string());
+                    });
+                    return;
+                }
+            }
             ui.horizontal(|ui| {
                 ui.label("File:");
                 if ui.button("Pick").clicked() {
@@ -189,7 +211,8 @@ pub fn convert_dialog(ui: &mut egui::Ui, app: &mut App) {
             if ui.button("Convert").clicked() {
                 trace!("Starting convert!");
                 inner.error = None;
-                match dispatch_convert(inner) {
+                let loaded = loaded.map(|(_, provider)| provider);
+                match dispatch_convert(inner, loaded) {
                     Err(y) => {
                         inner.error = Some(ConvertDialogError {
                             header: format!("{y:?}"),
@@ -205,9 +228,11 @@ pub fn convert_dialog(ui: &mut egui::Ui, app: &mut App) {
         app.convert_dialog = ConvertDialogState::NotOpen;
     }
 }
+/// Start the conversion configured in `inner` in the background. `loaded` is the open trace, which
+/// is converted instead of the input file if [ConvertDialogStateInner::use_loaded] is set.
 #[allow(clippy::type_complexity)]
 pub fn dispatch_convert(
-    inner: &mut ConvertDialogStateInner,
+    inner: &mut ConvertDialogStateInner, loaded: Option<&Arc<RwLock<LogProviderImpl>>>,
 ) -> Result<Receiver<(Duration, Result<(), ConvertError>)>, anyhow::Error> {
     use ConvertFileType::*;
     fn setup_io(
@@ -225,6 +250,32 @@ pub fn dispatch_convert(
         Ok((in_reader, out_writer))
     }
 
+    if inner.use_loaded {
+        let loaded = loaded.context("No trace is loaded")?;
+        let output_path = inner.output.path.clone().context("No output file")?;
+        // a live trace may change while it is written
+        let snapshot = loaded.read().unwrap().snapshot();
+        let is_et = inner.output.ty == ET;
+        let (tx, rx) = crossbeam::channel::bounded(1);
+        spawn_task(move || {
+            let start = Instant::now();
+            let r =
+                File::create(&output_path).map_err(ConvertError::OutWriteError).and_then(|file| {
+                    let mut out_writer = BufWriter::new(file);
+                    match is_et {
+                        true => time_print("provider_to_et", || {
+                            entrace_core::convert::provider_to_et(&*snapshot, &mut out_writer)
+                        }),
+                        false => time_print("provider_to_iet", || {
+                            entrace_core::convert::provider_to_iet(&*snapshot, &mut out_writer)
+                        }),
+                    }?;
+                    out_writer.flush().map_err(ConvertError::OutWriteError)
+                });
+            tx.send((start.elapsed(), r)).ok();
+        });
+        return Ok(rx);
+    }
     let input_path = inner.input.path.clone().context("No input file")?;
     let output_path = inner.output.path.clone().context("No output file")?;
 
@@ -270,3 +321,41 @@ pub fn dispatch_convert(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use entrace_core::{EnValue, LoadConfig, LogProvider, test_util::LogProviderBuilder};
+
+    use super::{ConvertDialogStateInner, ConvertFileType, dispatch_convert};
+
+    #[test]
+    fn test_convert_loaded_trace() {
+        let mut builder = LogProviderBuilder::new();
+        let span = builder.span(0, "request").attr("status", EnValue::U64(200)).add();
+        builder.span(span, "db").message("query").add();
+        let loaded = Arc::new(RwLock::new(builder.build_impl()));
+
+        for (ty, extension) in [(ConvertFileType::IET, "iet"), (ConvertFileType::ET, "et")] {
+            let path = std::env::temp_dir()
+                .join(format!("entrace-convert-loaded-{}.{extension}", std::process::id()));
+            let mut inner = ConvertDialogStateInner { use_loaded: true, ..Default::default() };
+            inner.output.path = Some(path.clone());
+            inner.output.ty = ty;
+            // no input file is needed
+            let rx = dispatch_convert(&mut inner, Some(&loaded)).unwrap();
+            rx.recv().unwrap().1.unwrap();
+            // SAFETY: nothing else touches this file while it is mapped.
+            let reloaded = unsafe { entrace_core::load_trace(&path, LoadConfig::default()) };
+            let reloaded = reloaded.unwrap();
+            std::fs::remove_file(&path).ok();
+            assert_eq!(reloaded.len(), 3);
+            assert_eq!(reloaded.children(span).unwrap(), [span + 1]);
+            assert_eq!(reloaded.message(span + 1).unwrap(), Some("query"));
+        }
+        let mut inner = ConvertDialogStateInner { use_loaded: true, ..Default::default() };
+        inner.output.path = Some("unused".into());
+        assert!(dispatch_convert(&mut inner, None).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Opening the source location of a span in an external editor.
+use std::process::Command;
+
+use anyhow::{Context, bail};
+
+/// Build the command line for opening `file` at `line`.
+///
+/// `template` is split on whitespace, then `{file}` and `{line}` are replaced in each argument, so
+/// `code -g {file}:{line}` runs `code` with the single argument `-g src/main.rs:12`. There is no
+/// shell, so quoting is not supported.
+///
+/// An empty template means `$EDITOR +{line} {file}`, which most terminal editors understand.
+pub fn editor_argv(template: &str, file: &str, line: u32) -> anyhow::Result<Vec<String>> {
+    let template = match template.trim() {
+        "" => {
+            let editor = std::env::var("EDITOR")
+                .context("No editor command is set, and $EDITOR is not set either")?;
+            format!("{editor} +{{line}} {{file}}")
+        }
+        x => x.to_string(),
+    };
+    let line = line.to_string();
+    let argv: Vec<String> = template
+        .split_whitespace()
+        .map(|arg| arg.replace("{file}", file).replace("{line}", &line))
+        .collect();
+    if argv.is_empty() {
+        bail!("The editor command is empty");
+    }
+    Ok(argv)
+}
+
+/// Spawn the editor with [editor_argv], without waiting for it to exit.
+pub fn open_in_editor(template: &str, file: &str, line: u32) -> anyhow::Result<()> {
+    let argv = editor_argv(template, file, line)?;
+    Command::new(&argv[0])
+        .args(&argv[1..])
+        .spawn()
+        .with_context(|| format!("Failed to start editor `{}`", argv[0]))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::editor_argv;
+    use crate::settings::{Settings, parse_settings};
+
+    #[test]
+    fn test_template_placeholders() {
+        let argv = editor_argv("code -g {file}:{line}", "src/main.rs", 12).unwrap();
+        assert_eq!(argv, ["code", "-g", "src/main.rs:12"]);
+        let argv = editor_argv("  hx   {file}:{line} ", "a.rs", 1).unwrap();
+        assert_eq!(argv, ["hx", "a.rs:1"]);
+    }
+
+    #[test]
+    fn test_literal_arguments_kept() {
+        let argv = editor_argv("emacsclient -n +{line} {file}", "lib.rs", 3).unwrap();
+        assert_eq!(argv, ["emacsclient", "-n", "+3", "lib.rs"]);
+    }
+
+    #[test]
+    fn test_setting_round_trip() {
+        let settings = Settings {
+            editor_command: "code -g \"{file}\":{line} --opt=x".into(),
+            ..Default::default()
+        };
+        let parsed = parse_settings(&settings.to_ini()).unwrap();
+        assert_eq!(parsed.editor_command, settings.editor_command);
+        assert_eq!(parse_settings("editor_command = \"\"").unwrap().editor_command, "");
+    }
+}
@@ -89,6 +89,24 @@ impl EnBitVec {
             EnBitVec::BitVec(bit_vec) => bit_vec.extend(iter),
         }
     }
+    /// The indices of the set bits, in order. With [EnBitVec::from_ones], this is how the bits are
+    /// saved, as usually only a few are set.
+    pub fn ones(&self) -> Vec<usize> {
+        match self {
+            EnBitVec::Vec(items) => {
+                items.iter().enumerate().filter(|x| *x.1).map(|x| x.0).collect()
+            }
+            EnBitVec::BitVec(bit_vec) => bit_vec.iter_ones().collect(),
+        }
+    }
+    /// `len` bits, which are set at the indices in `ones`. Indices out of bounds are ignored.
+    pub fn from_ones(ones: impl IntoIterator<Item = usize>, len: usize) -> Self {
+        let mut bits = Self::repeat(false, len);
+        for idx in ones.into_iter().filter(|&x| x < len) {
+            bits.set(idx, true);
+        }
+        bits
+    }
     pub fn with_capacity(cap: usize) -> Self {
         if Self::len_compressed(cap) {
             Self::BitVec(BitVec::with_capacity(cap))
@@ -3,11 +3,19 @@ pub struct EphemeralSettings {
     pub demo_mode: bool,
     /// egui only supports this on cfg(debug_assertions)
     pub debug_layout: bool,
+    /// Repaint every frame, even when nothing changed.
+    /// By default the app only repaints on input or when a background task wakes it up.
+    pub continuous_repaint: bool,
 }
 
 #[allow(clippy::derivable_impls)]
 impl Default for EphemeralSettings {
     fn default() -> Self {
-        Self { fps_in_menu: false, demo_mode: false, debug_layout: false }
+        Self {
+            fps_in_menu: false,
+            demo_mode: false,
+            debug_layout: false,
+            continuous_repaint: false,
+        }
     }
 }
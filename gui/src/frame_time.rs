@@ -105,33 +105,4 @@ impl TrackFrameTime for SamplingFrameTracker {
     }
 }
 
-pub fn us_to_human(mut us: u32) -> String {
-    let mut b = String::new();
-    fn component(b: &mut String, us: &mut u32, name: &str, in_us: u32) {
-        let in_new = *us / in_us;
-        if in_new != 0 {
-            *us %= in_us;
-            b.push_str(&format!("{in_new}{name} "));
-        }
-    }
-    component(&mut b, &mut us, "s", 1000000);
-    component(&mut b, &mut us, "ms", 1000);
-    component(&mut b, &mut us, "us", 1);
-    b.pop();
-    b
-}
-pub fn us_to_human_u64(mut us: u64) -> String {
-    let mut b = String::new();
-    fn component(b: &mut String, us: &mut u64, name: &str, in_us: u64) {
-        let in_new = *us / in_us;
-        if in_new != 0 {
-            *us %= in_us;
-            b.push_str(&format!("{in_new}{name} "));
-        }
-    }
-    component(&mut b, &mut us, "s", 1000000);
-    component(&mut b, &mut us, "ms", 1000);
-    component(&mut b, &mut us, "us", 1);
-    b.pop();
-    b
-}
+pub use entrace_core::human::us_to_human;
@@ -1,12 +1,19 @@
 use crate::{
-    App, LevelRepr, LogStatus, TraceReader, row_height,
+    App, LevelRepr, LogStatus, TraceReader,
+    log::LogState,
+    row_height,
     search::LocatingState,
-    tree::{TreeContextMut, tree_view},
+    tree::{BreadcrumbAction, TreeContextMut, breadcrumb_bar, tree_view},
 };
 use egui::{CollapsingHeader, Color32, Response, RichText, ScrollArea, Ui, vec2};
-use entrace_core::{LogProvider, LogProviderImpl, display_error_context};
+use entrace_core::{
+    GapKind, GapReport, LogProvider, LogProviderImpl, display_error_context, error_chain,
+    human::{bytes_to_human, format_si},
+    strip_ansi_escapes,
+};
 use std::{
     cell::RefCell,
+    fmt::Write,
     sync::{Arc, RwLock},
 };
 use tracing::info;
@@ -25,6 +32,10 @@ pub enum SpanContext<'a> {
     QueryResults {
         locating_state: &'a RefCell<LocatingState>,
         trace_provider: Arc<RwLock<LogProviderImpl>>,
+        /// See [crate::tree::TreeContextMut::strip_ansi].
+        strip_ansi: bool,
+        /// See [crate::tree::TreeContextMut::message_attr].
+        message_attr: &'a str,
     },
 }
 
@@ -33,7 +44,10 @@ pub fn span(
 ) -> SpanResponse {
     let row_height = row_height(ui);
 
-    let header = match trace_reader.header(id) {
+    let message_attr = match ctx {
+        SpanContext::QueryResults { message_attr, .. } => *message_attr,
+    };
+    let header = match trace_reader.header_with_message_attr(id, message_attr) {
         Ok(header) => header,
         Err(y) => {
             let ae = display_error_context(&y);
@@ -43,14 +57,10 @@ pub fn span(
     };
 
     let level_repr = header.level.repr(ui.ctx().theme());
-    let header_text: String;
-    if let Some(message) = header.message {
-        header_text = format!("{}: {}", level_repr.0, message);
-    } else if id == 0 {
-        header_text = "root".to_string();
-    } else {
-        header_text = header.name.into();
-    };
+    let strip_ansi = matches!(ctx, SpanContext::QueryResults { strip_ansi: true, .. });
+    let header_text = header.display_text(id);
+    let header_text =
+        if strip_ansi { strip_ansi_escapes(&header_text) } else { header_text.into() };
     let ui_header =
         CollapsingHeader::new(RichText::new(header_text).background_color(level_repr.1))
             .id_salt(id);
@@ -88,7 +98,39 @@ pub fn span(
                 }
             };
             for (x, y) in attr_names.into_iter().zip(attr_values) {
-                ui.label(format!("{x}: {y}",));
+                let attr = format!("{x}: {y}",);
+                ui.label(if strip_ansi { strip_ansi_escapes(&attr) } else { attr.into() });
+            }
+            let links = match trace_reader.follows_from(id) {
+                Ok(x) => x,
+                Err(y) => {
+                    ui.label(display_error_context(&y));
+                    return;
+                }
+            };
+            for target in links {
+                let target_text = match trace_reader.header_with_message_attr(target, message_attr)
+                {
+                    Ok(header) if strip_ansi => {
+                        strip_ansi_escapes(&header.display_text(target)).into_owned()
+                    }
+                    Ok(header) => header.display_text(target),
+                    Err(y) => display_error_context(&y),
+                };
+                #[allow(irrefutable_let_patterns)]
+                let SpanContext::QueryResults { locating_state, trace_provider, .. } = ctx else {
+                    continue;
+                };
+                ui.horizontal(|ui| {
+                    ui.label("follows from");
+                    let enabled = locating_state.borrow().can_start_new();
+                    let link = ui.add_enabled(enabled, egui::Link::new(target_text));
+                    if link.on_hover_text("Locate in main tree").clicked() {
+                        info!("Will locate {target}");
+                        *locating_state.borrow_mut() =
+                            LocatingState::start_locating(target, trace_provider, ui.ctx().clone());
+                    }
+                });
             }
         }
         let children = match trace_reader.children(id) {
@@ -134,12 +176,13 @@ pub fn span(
     }
     header_res.header_response.context_menu(|ui| {
         #[allow(irrefutable_let_patterns)]
-        if let SpanContext::QueryResults { locating_state, trace_provider } = ctx {
+        if let SpanContext::QueryResults { locating_state, trace_provider, .. } = ctx {
             let enabled = locating_state.borrow().can_start_new();
             let btn = egui::Button::new("Locate in main tree");
             if ui.add_enabled(enabled, btn).clicked() {
                 info!("Will locate {id}");
-                *locating_state.borrow_mut() = LocatingState::start_locating(id, trace_provider);
+                *locating_state.borrow_mut() =
+                    LocatingState::start_locating(id, trace_provider, ui.ctx().clone());
             };
         }
         if ui.button("Close this menu").clicked() {
@@ -156,6 +199,86 @@ pub fn span(
     }
 }
 
+/// How many gaps are listed in the hover text of the health indicator.
+const MAX_SHOWN_GAPS: usize = 10;
+fn describe_gap(gap: &GapReport) -> String {
+    let ids = if gap.ids.start() == gap.ids.end() {
+        format!("span {}", gap.ids.start())
+    } else {
+        format!("spans {}..={}", gap.ids.start(), gap.ids.end())
+    };
+    match gap.kind {
+        GapKind::MissingParent => format!("{ids}: parent is missing"),
+        GapKind::Unreferenced => format!("{ids}: not listed as a child of its parent"),
+    }
+}
+/// Like `1.2 GB, 3.4M spans, avg 350 B/span`, for a trace of `bytes` with `len` spans.
+fn describe_size(bytes: u64, len: usize) -> String {
+    let mut text = format!("{}, {} spans", bytes_to_human(bytes), format_si(len as f64, ""));
+    if len != 0 {
+        let avg = bytes / len as u64;
+        write!(text, ", avg {avg} B/span").ok();
+    }
+    text
+}
+/// Show whether every span is linked into the tree, see [LogProvider::find_gaps].
+fn trace_health(ui: &mut Ui, gaps: Option<&Vec<GapReport>>) {
+    match gaps {
+        None => (),
+        Some(gaps) if gaps.is_empty() => {
+            ui.weak("✔ healthy").on_hover_text("Every span is linked into the tree");
+        }
+        Some(gaps) => {
+            let count: u64 = gaps.iter().map(|x| (x.ids.end() - x.ids.start()) as u64 + 1).sum();
+            let badge =
+                RichText::new(format!("⚠ {count} unlinked")).color(ui.visuals().warn_fg_color);
+            ui.label(badge).on_hover_ui(|ui| {
+                ui.label("Some spans are not linked into the tree, they may have been lost:");
+                for gap in gaps.iter().take(MAX_SHOWN_GAPS) {
+                    ui.label(describe_gap(gap));
+                }
+                if gaps.len() > MAX_SHOWN_GAPS {
+                    ui.weak(format!("and {} more", gaps.len() - MAX_SHOWN_GAPS));
+                }
+            });
+        }
+    }
+}
+
+/// A slider to view a live trace as it was earlier, see [crate::log::LogState::time_travel].
+/// Returns whether the position changed.
+fn time_travel(ui: &mut Ui, time_travel: &mut Option<u32>, len: usize) -> bool {
+    let last = len.saturating_sub(1) as u32;
+    let mut pos = time_travel.map_or(last, |x| x.min(last));
+    let slider = egui::Slider::new(&mut pos, 0..=last).prefix("up to span ");
+    let hover = "Show the trace as it was when this span was recorded. The end follows the trace \
+                 as it grows";
+    if ui.add(slider).on_hover_text(hover).changed() {
+        *time_travel = (pos < last).then_some(pos);
+        return true;
+    }
+    if time_travel.is_some() && ui.button("⏭ Live").on_hover_text("Follow the trace").clicked() {
+        *time_travel = None;
+        return true;
+    }
+    false
+}
+
+/// A toggle for [crate::log::LogState::paused], and how much data waits while paused.
+fn pause_updates(ui: &mut Ui, state: &mut LogState) {
+    let text = if state.paused { "▶ Resume" } else { "⏸ Pause" };
+    let hover = "Stop adding new spans to the tree, so that it doesn't shift while you read it. \
+                 Nothing is lost, new data is buffered until you resume";
+    if ui.button(text).on_hover_text(hover).clicked() {
+        state.paused = !state.paused;
+    }
+    if state.paused {
+        let buffered = state.trace_provider.read().unwrap().pending_messages();
+        ui.weak(format!("paused, {buffered} buffered"))
+            .on_hover_text("Messages received while paused. A message can hold many spans");
+    }
+}
+
 pub fn center(ui: &mut Ui, app: &mut App) {
     match app.log_status {
         LogStatus::Ready(ref mut state) => {
@@ -166,22 +289,91 @@ pub fn center(ui: &mut Ui, app: &mut App) {
                 } else {
                     ui.label(state.file_path.display().to_string());
                 }
+                if state.is_live {
+                    let badge = RichText::new("● live").color(Color32::RED);
+                    ui.label(badge).on_hover_text("This trace is still growing");
+                    pause_updates(ui, state);
+                }
+                let shape = state.health.get(&state.trace_provider, ui.ctx());
+                trace_health(ui, shape.as_ref().map(|x| &x.gaps));
+                if let Some(shape) = shape {
+                    ui.weak(format!("depth {}", shape.max_depth))
+                        .on_hover_text("The most spans between the root and a span");
+                }
+                if let Some(bytes) = state.source_bytes {
+                    let len = state.trace_provider.read().unwrap().len();
+                    ui.weak(describe_size(bytes, len)).on_hover_text(
+                        "The size of the trace file, or the data received so far for a live one",
+                    );
+                }
+                let mut collapse = state.tree_view.collapse_duplicates;
+                ui.checkbox(&mut collapse, "Collapse duplicates").on_hover_text(
+                    "Group consecutive siblings with the same metadata and message into one row",
+                );
+                state.tree_view.set_collapse_duplicates(collapse);
+                let mut inline = state.tree_view.inline_events;
+                ui.checkbox(&mut inline, "Inline events").on_hover_text(
+                    "Show the events of an open span as single lines in its body, instead of rows \
+                     which can be opened",
+                );
+                state.tree_view.set_inline_events(inline);
+                if state.is_live || state.time_travel.is_some() {
+                    let len = state.trace_provider.read().unwrap().len();
+                    if time_travel(ui, &mut state.time_travel, len) {
+                        state.tree_view.invalidate();
+                    }
+                }
             });
 
             let delta = state.on_frame(&app.notifier);
+            // a backlog, like after resuming, takes more than one frame to work off
+            if !state.paused && state.trace_provider.read().unwrap().pending_messages() > 0 {
+                ui.ctx().request_repaint();
+            }
             if delta != 0 {
                 state.is_open.extend(std::iter::repeat_n(false, delta));
                 state.meta_open.extend(std::iter::repeat_n(false, delta));
+                state.dup_open.extend(std::iter::repeat_n(false, delta));
                 state.tree_view.invalidate();
+                state.attr_universe.get_mut().invalidate();
+                state.health.invalidate();
             }
             state.update_tree(&mut app.benchmarks.get_tree);
             let row_height = row_height(ui);
             let trace_reader = state.trace_provider.read().unwrap();
+            let path = state.tree_view.breadcrumb(&*trace_reader);
+            if let Some(action) = breadcrumb_bar(ui, &*trace_reader, &path) {
+                let target = match action {
+                    BreadcrumbAction::ScrollTo(id) => id,
+                    BreadcrumbAction::Collapse(id) => {
+                        state.is_open.set(id as usize, false);
+                        state.tree_view.invalidate();
+                        id
+                    }
+                };
+                // the ancestors are open already, and opening the path would undo a collapse
+                *state.locating_state.borrow_mut() = LocatingState::ScrollTo {
+                    target,
+                    target_row_offset: None,
+                    path: vec![],
+                    opened_path: true,
+                };
+            }
             let tree_ctx = TreeContextMut {
-                log_reader: &trace_reader,
+                log_reader: &*trace_reader,
                 open_writer: &mut state.is_open,
                 meta_open_writer: &mut state.meta_open,
+                dup_open_writer: &mut state.dup_open,
                 locating_state: Some(state.locating_state.borrow_mut()),
+                editor_command: app.settings.editor_command(),
+                compact: app.settings.compact_tree(),
+                strip_ansi: app.settings.strip_ansi(),
+                message_attr: app.settings.message_attr(),
+                color_rules: app.settings.color_rules(),
+                aggregates: app.settings.child_aggregates(),
+                annotations: &mut state.annotations,
+                link_source: &state.link_source,
+                notifier: &app.notifier,
             };
             ScrollArea::new([true; 2]).auto_shrink([false; 2]).show_rows(
                 ui,
@@ -202,7 +394,39 @@ pub fn center(ui: &mut Ui, app: &mut App) {
             ui.spinner();
         }
         LogStatus::Error(ref error) => {
-            ui.label(format!("Error:\n{error:?}"));
+            let formatted = display_error_context(error.as_ref());
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Failed to load the trace").strong());
+                if ui.button("Copy error").clicked() {
+                    ui.ctx().copy_text(formatted.clone());
+                }
+            });
+            let chain = error_chain(error.as_ref());
+            // vertical only, so that the width is bounded and even long tokens without spaces
+            // get broken up
+            ScrollArea::vertical().auto_shrink([false; 2]).show(ui, |ui| {
+                ui.add(egui::Label::new(RichText::new(&chain[0]).monospace()).wrap());
+                if chain.len() > 1 {
+                    CollapsingHeader::new("Caused by").default_open(true).show(ui, |ui| {
+                        for (idx, cause) in chain[1..].iter().enumerate() {
+                            let cause = RichText::new(format!("{idx}: {cause}")).monospace();
+                            ui.add(egui::Label::new(cause).wrap());
+                        }
+                    });
+                }
+            });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::describe_size;
+
+    #[test]
+    fn test_describe_size() {
+        assert_eq!(describe_size(1_200_000_000, 3_400_000), "1.2 GB, 3.4M spans, avg 352 B/span");
+        assert_eq!(describe_size(500, 0), "500 B, 0 spans");
+        assert_eq!(describe_size(2048, 2), "2.0 kB, 2 spans, avg 1024 B/span");
+    }
+}
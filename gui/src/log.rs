@@ -1,20 +1,25 @@
 use std::{
     cell::RefCell,
+    collections::{HashMap, HashSet},
     fmt::Display,
     path::PathBuf,
     sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
 
 use entrace_core::{
-    LogProvider, LogProviderImpl, display_error_context,
-    remote::{IETEvent, Notify, NotifyExt},
+    GapReport, LogProvider, LogProviderError, LogProviderImpl, display_error_context,
+    remote::{IETEvent, NotifyExt},
 };
-use tracing::{info, trace};
+use tracing::{info, trace, warn};
 
 use crate::{
+    annotations::Annotations,
     benchmarkers::SamplingBenchmark,
     enbitvec::EnBitVec,
+    notifications::NotificationHandle,
     search::LocatingState,
+    spawn_task, time_trace,
     tree::{TreeContext, TreeView},
 };
 
@@ -38,13 +43,34 @@ impl Display for LogStatus {
 }
 pub struct LogState {
     pub file_path: PathBuf,
+    /// Whether the trace was opened from [LogState::file_path], and not from a connection. Then
+    /// the expanded spans are saved when it is closed, see [crate::tree_state].
+    pub from_file: bool,
+    /// How links to its spans name this trace, see [crate::span_link::link_source].
+    pub link_source: String,
     pub trace_provider: Arc<RwLock<LogProviderImpl>>,
     /// Used for culling.
     pub is_open: EnBitVec,
     pub meta_open: EnBitVec,
+    /// Expanded runs of duplicates, see [crate::tree::Row::Duplicates].
+    pub dup_open: EnBitVec,
     pub locating_state: RefCell<LocatingState>,
     pub tree_view: TreeView,
     pub event_rx: Option<crossbeam::channel::Receiver<IETEvent>>,
+    pub attr_universe: RefCell<AttrUniverse>,
+    pub health: TraceHealth,
+    /// [LogProvider::is_live] as of the last [LogState::on_frame].
+    pub is_live: bool,
+    /// Notes on spans, saved for traces opened from a file.
+    pub annotations: Annotations,
+    /// The last span shown in the tree and searched by queries, to view a live trace as it was
+    /// earlier. `None` follows the trace as it grows.
+    pub time_travel: Option<u32>,
+    /// [LogProvider::source_bytes], updated when the trace grows.
+    pub source_bytes: Option<u64>,
+    /// Stop adding new data to a live trace, so that the tree doesn't shift while it is read.
+    /// New data waits in the channel of the provider until updates are resumed.
+    pub paused: bool,
 }
 impl LogState {
     pub fn update_tree<const N: u8>(&mut self, tree_benchmark: &mut SamplingBenchmark<N>) {
@@ -75,29 +101,41 @@ impl LogState {
         }
         let log_reader = self.trace_provider.read().unwrap();
         let ctx = TreeContext {
-            log_reader: &log_reader,
+            log_reader: &*log_reader,
             open_reader: &self.is_open,
             meta_open_reader: &self.meta_open,
+            dup_open_reader: &self.dup_open,
             locating_state: Some(locating_writer),
+            max_id: self.time_travel.unwrap_or(u32::MAX),
         };
         self.tree_view.update_tree(Some(tree_benchmark), std::iter::once(0), ctx);
     }
-    /// Returns the delta in the trace provider's item count
-    pub fn on_frame(&self, notifier: &impl Notify) -> usize {
+    /// Returns the delta in the trace provider's item count.
+    /// For traces which are not [live](LogProvider::is_live), or [paused](LogState::paused), this
+    /// is always 0.
+    pub fn on_frame(&mut self, notifier: &NotificationHandle) -> usize {
         let mut delta = 0;
-        if let Ok(mut q) = self.trace_provider.try_write() {
-            let len0 = q.len();
-            q.frame_callback();
-            delta = q.len().saturating_sub(len0);
-        } else {
-            trace!(
-                "Can't acquire write lock on trace provider, next frame_callback will be delayed"
-            )
+        // static traces don't grow, so there is nothing to poll
+        if self.is_live && !self.paused {
+            if let Ok(mut q) = self.trace_provider.try_write() {
+                let len0 = q.len();
+                q.frame_callback();
+                delta = q.len().saturating_sub(len0);
+                self.is_live = q.is_live();
+                if delta != 0 {
+                    self.source_bytes = q.source_bytes();
+                }
+            } else {
+                trace!(
+                    "Can't acquire write lock on trace provider, next frame_callback will be \
+                     delayed"
+                )
+            }
         }
         if let Some(ref rx) = self.event_rx {
             while let Ok(y) = rx.try_recv() {
                 match y {
-                    IETEvent::Error(err) => notifier.error(display_error_context(&err)),
+                    IETEvent::Error(err) => notifier.error_chain(&err),
                     IETEvent::Info(i) => notifier.info(i.to_string()),
                 }
             }
@@ -106,3 +144,262 @@ impl LogState {
         delta
     }
 }
+
+/// Cached attribute names, used to autocomplete filter targets, and the
+/// [LogProvider::attr_cardinality] of the ones which were shown.
+///
+/// Counting distinct values reads every value of every span, so it is only done for the names
+/// asked for with [AttrUniverse::request_cardinalities], again after the names were recomputed.
+#[derive(Default)]
+pub struct AttrUniverse {
+    names: SnapshotCache<Vec<String>>,
+    /// The names the counts were last refreshed for. Once [AttrUniverse::names] has newer ones,
+    /// the counts are stale.
+    counted_for: Option<Arc<Vec<String>>>,
+    cardinalities: HashMap<String, usize>,
+    /// Names whose count is up to date with `counted_for`.
+    fresh: HashSet<String>,
+    counting: Option<crossbeam::channel::Receiver<Vec<(String, usize)>>>,
+}
+impl AttrUniverse {
+    pub fn invalidate(&mut self) {
+        self.names.invalidate();
+    }
+    /// Returns the last known attribute names, and starts recomputing them if they are stale.
+    pub fn get(
+        &mut self, trace_provider: &Arc<RwLock<LogProviderImpl>>, ctx: &egui::Context,
+    ) -> Arc<Vec<String>> {
+        let names = self
+            .names
+            .get(trace_provider, ctx, "collecting attr names", |trace| trace.distinct_attr_names());
+        let Some(names) = names else { return Default::default() };
+        if !self.counted_for.as_ref().is_some_and(|x| Arc::ptr_eq(x, &names)) {
+            self.counted_for = Some(names.clone());
+            self.fresh.clear();
+        }
+        names
+    }
+    /// Starts counting the distinct values of those of `names` which weren't counted since the
+    /// names were last recomputed. The old counts are kept until the new ones are ready.
+    pub fn request_cardinalities<'a>(
+        &mut self, names: impl IntoIterator<Item = &'a str>,
+        trace_provider: &Arc<RwLock<LogProviderImpl>>, ctx: &egui::Context,
+    ) {
+        if let Some(ref rx) = self.counting
+            && let Ok(counted) = rx.try_recv()
+        {
+            for (name, cardinality) in counted {
+                self.fresh.insert(name.clone());
+                self.cardinalities.insert(name, cardinality);
+            }
+            self.counting = None;
+        }
+        if self.counting.is_some() {
+            return;
+        }
+        let stale: Vec<String> =
+            names.into_iter().filter(|x| !self.fresh.contains(*x)).map(String::from).collect();
+        if stale.is_empty() {
+            return;
+        }
+        let (tx, rx) = crossbeam::channel::bounded(1);
+        let tp = trace_provider.clone();
+        let ctx = ctx.clone();
+        spawn_task(move || {
+            let counted = time_trace("counting attr values", || {
+                let snapshot = tp.read().unwrap().snapshot();
+                stale
+                    .into_iter()
+                    .map(|name| Ok((name.clone(), snapshot.attr_cardinality(&name)?)))
+                    .collect::<Result<Vec<_>, LogProviderError>>()
+            });
+            match counted {
+                Ok(counted) => tx.send(counted).ok(),
+                Err(y) => {
+                    warn!(error = %display_error_context(&y), "Failed to count attr values");
+                    None
+                }
+            };
+            ctx.request_repaint();
+        });
+        self.counting = Some(rx);
+    }
+    /// The number of distinct values of attribute `name`, if it was counted.
+    pub fn cardinality(&self, name: &str) -> Option<usize> {
+        self.cardinalities.get(name).copied()
+    }
+}
+
+/// The least time between two recomputations of a [SnapshotCache]. A live trace grows every few
+/// frames, and the scans read all of it.
+const RECOMPUTE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A value computed in the background from a [LogProvider::snapshot] of the trace, so the scan
+/// doesn't block [LogState::on_frame] from taking in new data. When it is invalidated, it is
+/// recomputed at most once per [RECOMPUTE_INTERVAL], keeping the old value until the new one is
+/// ready.
+pub struct SnapshotCache<T> {
+    value: Option<Arc<T>>,
+    valid: bool,
+    last_start: Option<Instant>,
+    loading: Option<crossbeam::channel::Receiver<T>>,
+}
+impl<T> Default for SnapshotCache<T> {
+    fn default() -> Self {
+        Self { value: None, valid: false, last_start: None, loading: None }
+    }
+}
+impl<T: Send + 'static> SnapshotCache<T> {
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+    /// Returns the last value, or `None` if it was not computed yet. If it is stale, starts
+    /// `compute` as the task `tag`, or asks `ctx` for a repaint when the next one may start.
+    pub fn get(
+        &mut self, trace_provider: &Arc<RwLock<LogProviderImpl>>, ctx: &egui::Context,
+        tag: &'static str,
+        compute: impl FnOnce(&dyn LogProvider) -> Result<T, LogProviderError> + Send + 'static,
+    ) -> Option<Arc<T>> {
+        if let Some(ref rx) = self.loading
+            && let Ok(value) = rx.try_recv()
+        {
+            self.value = Some(Arc::new(value));
+            self.loading = None;
+        }
+        if self.valid || self.loading.is_some() {
+            return self.value.clone();
+        }
+        let since_start = self.last_start.map_or(RECOMPUTE_INTERVAL, |x| x.elapsed());
+        if since_start < RECOMPUTE_INTERVAL {
+            ctx.request_repaint_after(RECOMPUTE_INTERVAL - since_start);
+            return self.value.clone();
+        }
+        self.valid = true;
+        self.last_start = Some(Instant::now());
+        let (tx, rx) = crossbeam::channel::bounded(1);
+        let tp = trace_provider.clone();
+        let ctx = ctx.clone();
+        spawn_task(move || {
+            let value = time_trace(tag, || {
+                let snapshot = tp.read().unwrap().snapshot();
+                compute(&*snapshot)
+            });
+            match value {
+                Ok(value) => tx.send(value).ok(),
+                Err(y) => {
+                    warn!(task = tag, error = %display_error_context(&y), "Background scan failed");
+                    None
+                }
+            };
+            ctx.request_repaint();
+        });
+        self.loading = Some(rx);
+        self.value.clone()
+    }
+}
+
+/// What [TraceHealth] finds out about the structure of the trace.
+pub struct TraceShape {
+    /// See [LogProvider::find_gaps].
+    pub gaps: Vec<GapReport>,
+    /// See [LogProvider::max_depth].
+    pub max_depth: usize,
+}
+/// Cached result of [LogProvider::find_gaps] and [LogProvider::max_depth], shown as the trace
+/// health indicator.
+#[derive(Default)]
+pub struct TraceHealth(SnapshotCache<TraceShape>);
+impl TraceHealth {
+    pub fn invalidate(&mut self) {
+        self.0.invalidate();
+    }
+    /// Returns the last known shape, or `None` if it was not computed yet, and starts
+    /// recomputing it if it is stale.
+    pub fn get(
+        &mut self, trace_provider: &Arc<RwLock<LogProviderImpl>>, ctx: &egui::Context,
+    ) -> Option<Arc<TraceShape>> {
+        self.0.get(trace_provider, ctx, "finding gaps and depth", |trace| {
+            Ok(TraceShape { gaps: trace.find_gaps()?, max_depth: trace.max_depth()? })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, RwLock},
+        time::{Duration, Instant},
+    };
+
+    use entrace_core::{EnValue, LogProvider, test_util::LogProviderBuilder};
+
+    use super::{AttrUniverse, RECOMPUTE_INTERVAL, SnapshotCache};
+
+    /// Polls `f` until the background task it waits for is done.
+    fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> T {
+        let started = Instant::now();
+        loop {
+            if let Some(x) = f() {
+                return x;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_recompute_is_throttled() {
+        let mut builder = LogProviderBuilder::new();
+        builder.span(0, "request").add();
+        let trace = Arc::new(RwLock::new(builder.build_impl()));
+        let ctx = egui::Context::default();
+        let mut cache = SnapshotCache::default();
+        let get = |cache: &mut SnapshotCache<usize>| {
+            cache.get(&trace, &ctx, "len", |trace: &dyn LogProvider| Ok(trace.len()))
+        };
+        assert_eq!(*wait_for(|| get(&mut cache)), 2);
+
+        // right after the last scan, the old value is kept
+        cache.invalidate();
+        assert_eq!(get(&mut cache).as_deref(), Some(&2));
+        assert!(cache.loading.is_none());
+        cache.last_start = Some(Instant::now() - RECOMPUTE_INTERVAL);
+        get(&mut cache);
+        assert!(cache.loading.is_some());
+    }
+
+    #[test]
+    fn test_cardinalities_of_shown_names() {
+        let mut builder = LogProviderBuilder::new();
+        for i in 0..6u64 {
+            builder
+                .span(0, "request")
+                .attr("id", EnValue::U64(i))
+                .attr("status", EnValue::U64(i % 2))
+                .add();
+        }
+        let trace = Arc::new(RwLock::new(builder.build_impl()));
+        let ctx = egui::Context::default();
+        let mut universe = AttrUniverse::default();
+        let names = wait_for(|| Some(universe.get(&trace, &ctx)).filter(|x| !x.is_empty()));
+        assert_eq!(*names, ["id", "status"]);
+
+        let id = wait_for(|| {
+            universe.request_cardinalities(["id"], &trace, &ctx);
+            universe.cardinality("id")
+        });
+        assert_eq!(id, 6);
+        // only the names asked for are counted, and only once
+        assert_eq!(universe.cardinality("status"), None);
+        universe.request_cardinalities(["id"], &trace, &ctx);
+        assert!(universe.counting.is_none());
+
+        // new names make the counts stale, but they are kept until recounted
+        universe.invalidate();
+        universe.names.last_start = Some(Instant::now() - RECOMPUTE_INTERVAL);
+        wait_for(|| Some(universe.get(&trace, &ctx)).filter(|x| !Arc::ptr_eq(x, &names)));
+        universe.request_cardinalities(["id"], &trace, &ctx);
+        assert!(universe.counting.is_some());
+        assert_eq!(universe.cardinality("id"), Some(6));
+    }
+}
@@ -15,19 +15,25 @@ mod app;
 mod log;
 pub use app::*;
 pub use log::*;
+pub mod annotations;
 pub mod benchmarkers;
 pub mod cmdline;
+pub mod command_palette;
 pub mod connection_dialog;
 pub mod convert_dialog;
+pub mod editor;
 pub mod enbitvec;
 pub mod ephemeral_settings;
 pub mod frame_time;
 pub mod homepage;
 pub mod notifications;
+pub mod row_color;
 pub mod search;
 pub mod self_tracing;
 pub mod settings;
+pub mod span_link;
 pub mod tree;
+pub mod tree_state;
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result {
@@ -82,13 +88,7 @@ pub trait LevelRepr {
 }
 impl LevelRepr for LevelContainer {
     fn repr(&self, theme: egui::Theme) -> (&'static str, Color32) {
-        let symbol = match self {
-            LevelContainer::Trace => "[T]",
-            LevelContainer::Debug => "[D]",
-            LevelContainer::Info => "[I]",
-            LevelContainer::Warn => "[W]",
-            LevelContainer::Error => "[E]",
-        };
+        let symbol = self.symbol();
         // https://tailwindcolor.com/
         let color = match (self, theme) {
             (LevelContainer::Trace, Theme::Dark) => Color32::DARK_GRAY,
@@ -162,6 +162,7 @@ fn layout_text(ui: &mut Ui, text: String) -> Arc<egui::Galley> {
 }
 
 /// Debug-paint the available rect at ui
+#[allow(dead_code)]
 fn debug_available(ui: &mut Ui, text: &str) {
     ui.painter().debug_rect(ui.available_rect_before_wrap(), Color32::RED, text);
 }
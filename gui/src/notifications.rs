@@ -5,11 +5,11 @@ use std::{
 };
 
 use egui::{
-    Color32, FontId, Pos2, Rect, Sense, Stroke, TextStyle, UiBuilder, Vec2, epaint::RectShape,
-    pos2, vec2,
+    Color32, FontId, Pos2, Rect, RichText, Sense, Stroke, TextStyle, UiBuilder, Vec2,
+    epaint::RectShape, pos2, vec2,
 };
 use entrace_core::{
-    LevelContainer,
+    LevelContainer, error_chain,
     remote::{Notify, Refresh},
 };
 
@@ -32,13 +32,37 @@ impl Notify for NotificationHandle {
     }
 }
 impl NotificationHandle {
+    /// Show `err` as an error notification, with its sources listed as separate causes.
+    pub fn error_chain(&self, err: &dyn std::error::Error) {
+        let Ok(mut selfw) = self.0.write() else {
+            return;
+        };
+        let mut chain = error_chain(err);
+        let text = chain.remove(0);
+        selfw.push(LevelContainer::Error, text, chain, Duration::MAX);
+    }
     pub fn read(&'_ self) -> LockResult<RwLockReadGuard<'_, NotificationState>> {
         self.0.read()
     }
 }
+/// How many notifications [NotificationState::history] keeps. Older ones are dropped.
+pub const HISTORY_CAP: usize = 256;
+/// How many notifications are shown on screen at once. As errors don't expire, they would
+/// otherwise pile up; the ones pushed out are still in the history.
+pub const MAX_SHOWN: usize = 8;
 pub struct NotificationState {
     pub epoch: Instant,
     pub notis: VecDeque<Notification>,
+    /// Every notification, including expired and dismissed ones, up to [HISTORY_CAP].
+    pub history: VecDeque<HistoryEntry>,
+}
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub severity: LevelContainer,
+    pub time: Instant,
+    pub text: String,
+    /// The sources of an error, outermost first. See [entrace_core::error_chain].
+    pub causes: Vec<String>,
 }
 #[derive(Debug)]
 pub struct Notification {
@@ -46,6 +70,8 @@ pub struct Notification {
     pub start: Instant,
     pub duration: Duration,
     pub text: String,
+    /// See [HistoryEntry::causes].
+    pub causes: Vec<String>,
 }
 impl Notification {
     pub fn is_expired(&self, current_time: Instant) -> bool {
@@ -55,13 +81,28 @@ impl Notification {
 impl NotificationState {
     pub fn new() -> Self {
         let epoch = Instant::now();
-        Self { epoch, notis: VecDeque::new() }
+        Self { epoch, notis: VecDeque::new(), history: VecDeque::new() }
     }
     pub fn remove_notification(&mut self, idx: usize) {
         self.notis.remove(idx);
     }
     pub fn add_notification(&mut self, severity: LevelContainer, text: String, duration: Duration) {
-        self.notis.push_back(Notification { severity, start: Instant::now(), duration, text });
+        self.push(severity, text, vec![], duration);
+    }
+    pub fn push(
+        &mut self, severity: LevelContainer, text: String, causes: Vec<String>, duration: Duration,
+    ) {
+        let start = Instant::now();
+        if self.history.len() >= HISTORY_CAP {
+            self.history.pop_front();
+        }
+        let entry =
+            HistoryEntry { severity, time: start, text: text.clone(), causes: causes.clone() };
+        self.history.push_back(entry);
+        if self.notis.len() >= MAX_SHOWN {
+            self.notis.pop_front();
+        }
+        self.notis.push_back(Notification { severity, start, duration, text, causes });
     }
     pub fn recycle(&mut self) -> Option<Duration> {
         let now = Instant::now();
@@ -111,8 +152,12 @@ pub fn notifications(ui: &mut egui::Ui, app: &mut App) -> egui::InnerResponse<()
 
             let severity_galley =
                 ui.fonts_mut(|x| x.layout_no_wrap(repr.0.to_string(), font_id.clone(), text_color));
-            let text_galley = ui
-                .fonts_mut(|x| x.layout(notification.text.to_string(), font_id, text_color, 200.0));
+            let mut text = notification.text.clone();
+            for cause in &notification.causes {
+                text.push_str("\n↳ ");
+                text.push_str(cause);
+            }
+            let text_galley = ui.fonts_mut(|x| x.layout(text, font_id, text_color, 200.0));
             let severity_galley_size = severity_galley.size();
 
             let frame_width = (item_spacing.x + text_galley.size().x + item_spacing.x).max(100.0);
@@ -171,6 +216,97 @@ pub fn notifications(ui: &mut egui::Ui, app: &mut App) -> egui::InnerResponse<()
     }
     r
 }
+pub struct NotificationHistoryState {
+    pub open: bool,
+    /// Whether to show each severity, indexed by `LevelContainer as usize`.
+    pub shown: [bool; 5],
+    pub newest_first: bool,
+}
+impl Default for NotificationHistoryState {
+    fn default() -> Self {
+        Self { open: false, shown: [true; 5], newest_first: true }
+    }
+}
+impl NotificationHistoryState {
+    pub fn is_shown(&self, severity: LevelContainer) -> bool {
+        self.shown[severity as usize]
+    }
+}
+
+/// Ids into [NotificationState::history] of the entries `filter` shows, in display order.
+pub fn filtered_history(
+    history: &VecDeque<HistoryEntry>, filter: &NotificationHistoryState,
+) -> Vec<usize> {
+    let shown = history.iter().enumerate().filter(|(_, x)| filter.is_shown(x.severity));
+    let mut ids: Vec<usize> = shown.map(|(idx, _)| idx).collect();
+    if filter.newest_first {
+        ids.reverse();
+    }
+    ids
+}
+
+pub fn notification_history(ctx: &egui::Context, app: &mut App) {
+    let state = &mut app.notification_history;
+    let mut open = state.open;
+    egui::Window::new("Notifications").open(&mut open).show(ctx, |ui| {
+        let mut handle = app.notifier.0.write().unwrap();
+        ui.horizontal(|ui| {
+            use LevelContainer::*;
+            for level in [Trace, Debug, Info, Warn, Error] {
+                let repr = level.repr(ui.ctx().theme());
+                ui.checkbox(&mut state.shown[level as usize], repr.0);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut state.newest_first, true, "Newest first");
+            ui.selectable_value(&mut state.newest_first, false, "Oldest first");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Clear all").clicked() {
+                    handle.history.clear();
+                }
+            });
+        });
+        ui.separator();
+        let ids = filtered_history(&handle.history, state);
+        if ids.is_empty() {
+            ui.label("No notifications.");
+        }
+        let mut to_remove = None;
+        let now = Instant::now();
+        egui::ScrollArea::vertical().auto_shrink([false, true]).show(ui, |ui| {
+            for idx in ids {
+                let entry = &handle.history[idx];
+                let repr = entry.severity.repr(ui.ctx().theme());
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(repr.0).background_color(repr.1));
+                    let ago = now.saturating_duration_since(entry.time).as_secs();
+                    ui.weak(format!("{ago}s ago"));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                        if ui.small_button("Dismiss").clicked() {
+                            to_remove = Some(idx);
+                        }
+                        ui.add(egui::Label::new(&entry.text).wrap());
+                    });
+                });
+                if !entry.causes.is_empty() {
+                    egui::CollapsingHeader::new("Caused by").id_salt(("caused_by", idx)).show(
+                        ui,
+                        |ui| {
+                            for cause in &entry.causes {
+                                ui.add(egui::Label::new(cause).wrap());
+                            }
+                        },
+                    );
+                }
+            }
+        });
+        if let Some(idx) = to_remove {
+            handle.history.remove(idx);
+        }
+    });
+    state.open = open;
+}
+
 pub fn draw_x(ui: &mut egui::Ui, position: Pos2, size: f32, color: Color32, thickness: f32) {
     let half_size = size / 2.0;
 
@@ -183,8 +319,64 @@ pub fn draw_x(ui: &mut egui::Ui, position: Pos2, size: f32, color: Color32, thic
     ui.painter().line_segment([top_right, bottom_left], Stroke::new(thickness, color));
 }
 pub struct RefreshToken(pub egui::Context);
+/// Repaints for new data. Live traces wrap it in a [entrace_core::remote::ThrottledRefresher],
+/// see [crate::settings::Settings::refresh_throttle_ms].
 impl Refresh for RefreshToken {
     fn refresh(&self) {
-        self.0.request_repaint_after(Duration::from_millis(100));
+        self.0.request_repaint();
+    }
+    fn refresh_after(&self, delay: Duration) {
+        self.0.request_repaint_after(delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use entrace_core::{LevelContainer, convert::ConvertError};
+
+    use super::{
+        HISTORY_CAP, MAX_SHOWN, NotificationHandle, NotificationHistoryState, NotificationState,
+        filtered_history,
+    };
+
+    #[test]
+    fn test_history_is_capped() {
+        let mut state = NotificationState::new();
+        for i in 0..HISTORY_CAP + 10 {
+            state.add_notification(LevelContainer::Error, i.to_string(), Duration::MAX);
+        }
+        assert_eq!(state.history.len(), HISTORY_CAP);
+        assert_eq!(state.history[0].text, "10");
+        assert_eq!(state.notis.len(), MAX_SHOWN);
+        // removing a transient notification keeps it in the history
+        state.remove_notification(0);
+        assert_eq!(state.history.len(), HISTORY_CAP);
+    }
+
+    #[test]
+    fn test_filter_and_sort() {
+        let mut state = NotificationState::new();
+        let levels = [LevelContainer::Info, LevelContainer::Error, LevelContainer::Info];
+        for (i, level) in levels.into_iter().enumerate() {
+            state.add_notification(level, i.to_string(), Duration::from_secs(5));
+        }
+        let mut filter = NotificationHistoryState::default();
+        assert_eq!(filtered_history(&state.history, &filter), [2, 1, 0]);
+        filter.newest_first = false;
+        filter.shown[LevelContainer::Info as usize] = false;
+        assert_eq!(filtered_history(&state.history, &filter), [1]);
+    }
+
+    #[test]
+    fn test_error_chain() {
+        let handle = NotificationHandle::default();
+        let inner = ConvertError::ReadInputError(std::io::Error::other("disk on fire"));
+        let err = ConvertError::GatherError(Box::new(inner));
+        handle.error_chain(&err);
+        let state = handle.read().unwrap();
+        assert_eq!(state.notis[0].text, err.to_string());
+        assert_eq!(state.history[0].causes, ["Failed to read from input buffer", "disk on fire"]);
     }
 }
@@ -0,0 +1,269 @@
+//! Coloring tree rows by attribute values, configured with `color_rule` settings.
+use std::{
+    fmt::Display,
+    ops::{Bound, RangeBounds},
+    str::FromStr,
+};
+
+use egui::Color32;
+use entrace_core::EnValueRef;
+
+/// Colors which can be used by name in a rule. Mid-tone, so black and white text are both readable.
+// https://tailwindcolor.com/, 600
+const NAMED_COLORS: [(&str, Color32); 7] = [
+    ("red", Color32::from_rgb(220, 38, 38)),
+    ("orange", Color32::from_rgb(234, 88, 12)),
+    ("yellow", Color32::from_rgb(202, 138, 4)),
+    ("green", Color32::from_rgb(22, 163, 74)),
+    ("blue", Color32::from_rgb(37, 99, 235)),
+    ("purple", Color32::from_rgb(147, 51, 234)),
+    ("gray", Color32::from_rgb(75, 85, 99)),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueMatcher {
+    /// The value, as shown in the tree, is exactly this.
+    Equals(String),
+    /// The value is a number (or a string of one) in this range.
+    Range(Bound<f64>, Bound<f64>),
+}
+
+impl ValueMatcher {
+    pub fn matches(&self, value: &EnValueRef<'_>) -> bool {
+        match self {
+            ValueMatcher::Equals(expected) => match value {
+                EnValueRef::String(x) | EnValueRef::Debug(x) => x == expected,
+                x => x.to_string() == *expected,
+            },
+            ValueMatcher::Range(start, end) => {
+                let x = match *value {
+                    EnValueRef::U64(x) => x as f64,
+                    EnValueRef::I64(x) => x as f64,
+                    EnValueRef::U128(x) => x as f64,
+                    EnValueRef::I128(x) => x as f64,
+                    EnValueRef::Float(x) => x,
+                    EnValueRef::String(x) | EnValueRef::Debug(x) => match x.trim().parse() {
+                        Ok(x) => x,
+                        Err(_) => return false,
+                    },
+                    EnValueRef::Bool(_) | EnValueRef::Bytes(_) => return false,
+                };
+                (*start, *end).contains(&x)
+            }
+        }
+    }
+}
+
+/// A rule like `status_code 500..600 red`: rows of spans with a `status_code` attribute between 500
+/// and 600 get a red background instead of their level color.
+///
+/// The syntax is `<attr> <matcher> <color>`, where the matcher is
+/// - `=value`, matching the value as shown in the tree (spaces are allowed), or
+/// - a range of numbers, `a..b`, `a..=b`, `a..` or `..b`,
+///
+/// and the color is one of [NAMED_COLORS] or `#rrggbb`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRule {
+    pub attr: String,
+    pub matcher: ValueMatcher,
+    pub color: Color32,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ColorRuleError {
+    #[error("Expected `<attr> <matcher> <color>`")]
+    MissingPart,
+    #[error("Bad matcher `{0}`, expected `=value` or a range like `200..300`")]
+    BadMatcher(String),
+    #[error("Bad range bound `{0}`")]
+    BadBound(String, #[source] std::num::ParseFloatError),
+    #[error(
+        "Bad color `{0}`, expected #rrggbb or one of: red, orange, yellow, green, blue, purple, gray"
+    )]
+    BadColor(String),
+}
+
+fn parse_color(s: &str) -> Result<Color32, ColorRuleError> {
+    if let Some((_, color)) = NAMED_COLORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+        return Ok(*color);
+    }
+    let bad = || ColorRuleError::BadColor(s.into());
+    let is_hex = |x: &&str| x.len() == 6 && x.bytes().all(|b| b.is_ascii_hexdigit());
+    let hex = s.strip_prefix('#').filter(is_hex).ok_or_else(bad)?;
+    let rgb = u32::from_str_radix(hex, 16).map_err(|_| bad())?;
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Ok(Color32::from_rgb(r, g, b))
+}
+
+fn parse_range(s: &str) -> Result<ValueMatcher, ColorRuleError> {
+    let (start, end) = s.split_once("..").ok_or_else(|| ColorRuleError::BadMatcher(s.into()))?;
+    let parse = |x: &str| x.parse::<f64>().map_err(|e| ColorRuleError::BadBound(x.into(), e));
+    let start = match start {
+        "" => Bound::Unbounded,
+        x => Bound::Included(parse(x)?),
+    };
+    let end = match end.strip_prefix('=') {
+        Some(x) => Bound::Included(parse(x)?),
+        None if end.is_empty() => Bound::Unbounded,
+        None => Bound::Excluded(parse(end)?),
+    };
+    Ok(ValueMatcher::Range(start, end))
+}
+
+impl FromStr for ColorRule {
+    type Err = ColorRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (attr, rest) = s.split_once(char::is_whitespace).ok_or(ColorRuleError::MissingPart)?;
+        let (matcher, color) =
+            rest.trim().rsplit_once(char::is_whitespace).ok_or(ColorRuleError::MissingPart)?;
+        let matcher = matcher.trim();
+        let matcher = match matcher.strip_prefix('=') {
+            Some(value) => ValueMatcher::Equals(value.trim().into()),
+            None => parse_range(matcher)?,
+        };
+        Ok(ColorRule { attr: attr.into(), matcher, color: parse_color(color)? })
+    }
+}
+
+impl Display for ColorRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ", self.attr)?;
+        match &self.matcher {
+            ValueMatcher::Equals(value) => write!(f, "={value}")?,
+            ValueMatcher::Range(start, end) => {
+                if let Bound::Included(x) = start {
+                    write!(f, "{x}")?;
+                }
+                match end {
+                    Bound::Included(x) => write!(f, "..={x}")?,
+                    Bound::Excluded(x) => write!(f, "..{x}")?,
+                    Bound::Unbounded => write!(f, "..")?,
+                }
+            }
+        }
+        match NAMED_COLORS.iter().find(|(_, color)| *color == self.color) {
+            Some((name, _)) => write!(f, " {name}"),
+            None => {
+                let [r, g, b, _] = self.color.to_array();
+                write!(f, " #{r:02x}{g:02x}{b:02x}")
+            }
+        }
+    }
+}
+
+/// The color of the first rule matching one of the attributes, if any.
+pub fn rule_color(
+    rules: &[ColorRule], attr_names: &[&str], attr_values: &[EnValueRef<'_>],
+) -> Option<Color32> {
+    rules.iter().find_map(|rule| {
+        let mut attrs = attr_names.iter().zip(attr_values);
+        attrs
+            .any(|(name, value)| *name == rule.attr && rule.matcher.matches(value))
+            .then_some(rule.color)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use egui::Color32;
+    use entrace_core::EnValueRef;
+
+    use super::{ColorRule, ValueMatcher, rule_color};
+    use crate::settings::parse_settings;
+
+    fn rule(s: &str) -> ColorRule {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = rule("status_code 500..600 red");
+        assert_eq!(parsed.attr, "status_code");
+        assert_eq!(
+            parsed.matcher,
+            ValueMatcher::Range(Bound::Included(500.0), Bound::Excluded(600.0))
+        );
+        assert_eq!(parsed.color, Color32::from_rgb(220, 38, 38));
+        assert_eq!(
+            rule("x ..=1.5 #00ff80").matcher,
+            ValueMatcher::Range(Bound::Unbounded, Bound::Included(1.5))
+        );
+        assert_eq!(rule("x ..=1.5 #00ff80").color, Color32::from_rgb(0, 255, 128));
+        assert_eq!(
+            rule("x 3.. Blue").matcher,
+            ValueMatcher::Range(Bound::Included(3.0), Bound::Unbounded)
+        );
+        assert_eq!(rule("env =prod eu-1 green").matcher, ValueMatcher::Equals("prod eu-1".into()));
+        for bad in [
+            "",
+            "x",
+            "x red",
+            "x 1..2 ",
+            "x 1-2 red",
+            "x a..2 red",
+            "x 1..2 #12345",
+            "x 1..2 #+12345",
+            "x =1 pink",
+        ] {
+            assert!(bad.parse::<ColorRule>().is_err(), "{bad:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for s in
+            ["status_code 200..300 green", "x ..=1.5 #00ff80", "x 3.. blue", "env =prod eu-1 gray"]
+        {
+            assert_eq!(rule(s).to_string(), s);
+            assert_eq!(rule(&rule(s).to_string()), rule(s));
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let range = rule("n 200..300 green").matcher;
+        assert!(range.matches(&EnValueRef::U64(200)));
+        assert!(range.matches(&EnValueRef::I64(299)));
+        assert!(range.matches(&EnValueRef::Float(250.5)));
+        assert!(range.matches(&EnValueRef::String("204")));
+        assert!(!range.matches(&EnValueRef::U64(300)));
+        assert!(!range.matches(&EnValueRef::I128(-250)));
+        assert!(!range.matches(&EnValueRef::String("2xx")));
+        assert!(!range.matches(&EnValueRef::Bool(true)));
+
+        let equals = rule("n =true green").matcher;
+        assert!(equals.matches(&EnValueRef::Bool(true)));
+        assert!(equals.matches(&EnValueRef::String("true")));
+        assert!(!equals.matches(&EnValueRef::Bool(false)));
+        assert!(rule("n =42 green").matcher.matches(&EnValueRef::U64(42)));
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules =
+            [rule("status 500..600 red"), rule("status 200..300 green"), rule("slow =true orange")];
+        let names = ["slow", "status"];
+        let color = |values: &[EnValueRef]| rule_color(&rules, &names, values);
+        assert_eq!(color(&[EnValueRef::Bool(true), EnValueRef::U64(503)]), Some(rules[0].color));
+        assert_eq!(color(&[EnValueRef::Bool(true), EnValueRef::U64(200)]), Some(rules[1].color));
+        assert_eq!(color(&[EnValueRef::Bool(true), EnValueRef::U64(404)]), Some(rules[2].color));
+        assert_eq!(color(&[EnValueRef::Bool(false), EnValueRef::U64(404)]), None);
+        assert_eq!(rule_color(&rules, &[], &[]), None);
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let ini = "color_rule = \"status_code 500..600 red\"\ncolor_rule = \"env =a=b #102030\"";
+        let settings = parse_settings(ini).unwrap();
+        assert_eq!(
+            settings.color_rules,
+            [rule("status_code 500..600 red"), rule("env =a=b #102030")]
+        );
+        let reparsed = parse_settings(&settings.to_ini()).unwrap();
+        assert_eq!(reparsed.color_rules, settings.color_rules);
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use egui::{
     Color32, CornerRadius, Frame, Id, Key, Margin, Modifiers, Rect, Response, RichText, Sense,
     TextEdit, Ui,
@@ -6,6 +8,7 @@ use egui::{
     text::{CCursor, CCursorRange},
     vec2,
 };
+use entrace_query::lua_api::{COMPUTED_FIELDS, META_FIELDS};
 use nucleo_matcher::{
     Matcher, Utf32Str,
     pattern::{AtomKind, CaseMatching, Normalization, Pattern},
@@ -14,7 +17,7 @@ use tracing::info;
 
 use crate::{
     ApiDocsState, LogState, icon_colored,
-    notifications::draw_x,
+    notifications::{NotificationHandle, draw_x},
     rect,
     search::{QuerySettingsDialogData, SearchState, segmented_button::SegmentedIconButtons},
 };
@@ -37,10 +40,22 @@ impl Autocompleter {
         }
     }
 }
+/// Which candidates the autocomplete offers at the cursor.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompletionMode {
+    /// Lua function and attribute names.
+    #[default]
+    Code,
+    /// Filter targets: attribute names, `meta.*` and `en.*` fields.
+    /// Used inside the string after `target =` or in the first argument of `en_filter`.
+    FilterTarget,
+}
 #[derive(Default)]
 pub struct AutocompleteState {
     pub matcher: Option<nucleo_matcher::Matcher>,
-    pub autocomplete_results: Vec<(usize, &'static str, u32)>,
+    /// index of function in LUA_API_DOCS (None for attribute names), completion, score
+    pub autocomplete_results: Vec<(Option<usize>, String, u32)>,
+    pub mode: CompletionMode,
 
     pub nucleo_buf: Vec<char>,
     pub force_focus: bool,
@@ -49,7 +64,9 @@ pub struct AutocompleteState {
 }
 
 impl AutocompleteState {
-    pub fn recalculate_matches(&mut self, text: &str, cursor_range: Option<CCursorRange>) {
+    pub fn recalculate_matches(
+        &mut self, text: &str, cursor_range: Option<CCursorRange>, attr_names: &[String],
+    ) {
         if let Some(range) = cursor_range {
             self.cursor_range = Some(range);
         }
@@ -58,10 +75,16 @@ impl AutocompleteState {
 
         let byte_pos = text.char_indices().nth(cursor_index).map(|(i, _)| i).unwrap_or(text.len());
         let text_to_check = &text[..byte_pos];
-        let last_word = get_current_word(text_to_check);
+        let (mode, last_word) = completion_context(text_to_check);
 
         let old_is_empty = self.autocomplete_results.is_empty();
-        if last_word.is_empty() {
+        if mode != self.mode {
+            // the old selection points into the other candidate set
+            self.mode = mode;
+            self.selected_idx = None;
+        }
+        // an empty target string already narrows the candidates enough to be useful
+        if last_word.is_empty() && mode == CompletionMode::Code {
             self.autocomplete_results.clear();
             self.selected_idx = None;
             return;
@@ -74,14 +97,29 @@ impl AutocompleteState {
             Pattern::new(last_word, CaseMatching::Ignore, Normalization::Smart, AtomKind::Fuzzy);
         self.nucleo_buf.clear();
         self.autocomplete_results.clear();
-        let results = entrace_query::lua_api_docs::LUA_FN_NAMES.iter().enumerate().filter_map(
-            |(idx, item)| {
-                pattern
-                    .score(Utf32Str::new(item, &mut self.nucleo_buf), matcher)
-                    .map(|score| (idx, *item, score))
-            },
-        );
-        self.autocomplete_results.extend(results);
+        let mut score = |idx: Option<usize>, item: &str| {
+            pattern
+                .score(Utf32Str::new(item, &mut self.nucleo_buf), matcher)
+                .map(|score| (idx, item.to_string(), score))
+        };
+        match mode {
+            CompletionMode::Code => {
+                let fn_names = entrace_query::lua_api_docs::LUA_FN_NAMES.iter().enumerate();
+                let candidates = fn_names
+                    .map(|(idx, item)| (Some(idx), *item))
+                    .chain(attr_names.iter().map(|x| (None, x.as_str())));
+                let results = candidates.filter_map(|(idx, item)| score(idx, item));
+                self.autocomplete_results.extend(results);
+            }
+            CompletionMode::FilterTarget => {
+                let meta = META_FIELDS.iter().map(|x| format!("meta.{x}"));
+                let fields: Vec<String> =
+                    meta.chain(COMPUTED_FIELDS.iter().map(|x| format!("en.{x}"))).collect();
+                let candidates = attr_names.iter().chain(fields.iter());
+                let results = candidates.filter_map(|item| score(None, item));
+                self.autocomplete_results.extend(results);
+            }
+        }
         self.autocomplete_results.sort_by_key(|(_, _, score)| std::cmp::Reverse(*score));
         self.autocomplete_results.truncate(5);
 
@@ -97,29 +135,35 @@ impl AutocompleteState {
             None => 0,
         });
     }
-    pub fn accept_selection(&mut self, text: &mut String, selected: usize) {
+    pub fn accept_selection(&mut self, text: &mut String, selected: usize, attr_names: &[String]) {
         let cursor_index = self.cursor_range.map(|r| r.primary.index).unwrap_or(0);
         let byte_cursor_pos =
             text.char_indices().nth(cursor_index).map(|(i, _)| i).unwrap_or(text.len());
         let text_to_check = &text[..byte_cursor_pos];
-        let last_word_len = get_current_word(text_to_check).len();
+        let last_word_len = completion_context(text_to_check).1.len();
 
-        let result = self.autocomplete_results[selected].1;
+        let result = &self.autocomplete_results[selected].1;
         let start = byte_cursor_pos - last_word_len;
         text.replace_range(start..byte_cursor_pos, result);
         let new_cursor_pos = text[..start + result.len()].chars().count();
         self.cursor_range = Some(CCursorRange::one(CCursor::new(new_cursor_pos)));
         self.selected_idx = None;
         self.force_focus = true;
-        self.recalculate_matches(text, None);
+        self.recalculate_matches(text, None, attr_names);
     }
 }
 
 pub fn bottom_panel_ui(
     ui: &mut Ui, search_state: &mut SearchState, api_docs_state: &mut ApiDocsState,
-    log_state: &LogState, text_field_margin: Margin,
+    log_state: &LogState, notifier: &NotificationHandle, text_field_margin: Margin,
 ) {
     let text_edit_id = Id::new("bottom-search-text-edit");
+    let attr_names = match search_state.text.autocompleter {
+        Autocompleter::Enabled(_) => {
+            log_state.attr_universe.borrow_mut().get(&log_state.trace_provider, ui.ctx())
+        }
+        Autocompleter::Disabled => Default::default(),
+    };
     if let Autocompleter::Enabled(ref mut auto) = search_state.text.autocompleter
         && ui.memory(|m| m.has_focus(text_edit_id))
         && !auto.autocomplete_results.is_empty()
@@ -128,16 +172,17 @@ pub fn bottom_panel_ui(
             auto.cycle_or_start_selection();
         }
         if let Some(idx) = auto.selected_idx
+            && let Some(doc_idx) = auto.autocomplete_results[idx].0
             && ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Questionmark))
         {
             api_docs_state.clear_search();
-            api_docs_state.selected_idx = auto.autocomplete_results[idx].0;
+            api_docs_state.selected_idx = doc_idx;
             api_docs_state.open = true;
         }
         if let Some(idx) = auto.selected_idx
             && ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Enter))
         {
-            auto.accept_selection(&mut search_state.text.text, idx);
+            auto.accept_selection(&mut search_state.text.text, idx, &attr_names);
         }
     }
     // by displaying the autocomplete area, we steal the focus from the text field, breaking
@@ -182,9 +227,26 @@ pub fn bottom_panel_ui(
                         ui.add(egui::Label::new(
                             RichText::new("Select: TAB, Accept: Enter, Docs: ?").small(),
                         ));
+                        let mut universe = log_state.attr_universe.borrow_mut();
+                        let shown_names = auto
+                            .autocomplete_results
+                            .iter()
+                            .filter(|x| x.0.is_none() && attr_names.binary_search(&x.1).is_ok());
+                        universe.request_cardinalities(
+                            shown_names.map(|x| x.1.as_str()),
+                            &log_state.trace_provider,
+                            ui.ctx(),
+                        );
                         ui.horizontal(|ui| {
                             for (i, result) in auto.autocomplete_results.iter().enumerate() {
-                                let mut btn = egui::Button::new(result.1)
+                                // attribute names show how many distinct values they have
+                                let text = match universe.cardinality(&result.1) {
+                                    Some(n) if result.0.is_none() => {
+                                        format!("{} ({n})", result.1)
+                                    }
+                                    _ => result.1.clone(),
+                                };
+                                let mut btn = egui::Button::new(text)
                                     .sense(Sense::focusable_noninteractive());
                                 if auto.selected_idx == Some(i) {
                                     btn = btn.fill(ui.visuals().selection.bg_fill);
@@ -205,13 +267,23 @@ pub fn bottom_panel_ui(
         } else {
             egui::TextEdit::load_state(ui.ctx(), text_edit_id).and_then(|s| s.cursor.char_range())
         };
-        auto.recalculate_matches(&search_state.text.text, cursor_range);
+        auto.recalculate_matches(&search_state.text.text, cursor_range, &attr_names);
     }
 
     if search_response.has_focus()
         && ui.input(|i| i.key_pressed(egui::Key::Enter) && i.modifiers.ctrl)
     {
-        search_state.new_query(log_state.trace_provider.clone());
+        // run only the selection if there is one, like a REPL
+        let selection =
+            egui::TextEdit::load_state(ui.ctx(), text_edit_id).and_then(|s| s.cursor.char_range());
+        let text = query_text(&search_state.text.text, selection);
+        search_state.new_query_with_text(
+            Arc::from(text),
+            log_state.trace_provider.clone(),
+            log_state.time_travel.unwrap_or(u32::MAX),
+            ui.ctx().clone(),
+            notifier.clone(),
+        );
     }
 
     let avail = ui.ctx().content_rect();
@@ -258,7 +330,14 @@ pub fn bottom_panel_ui(
                 bg_corner_radius,
                 rects[0],
                 |ui, clr| ui.put(rects[0], icon_colored!("../../vendor/icons/play_arrow.svg", clr)),
-                |_| search_state.new_query(log_state.trace_provider.clone()),
+                |resp| {
+                    search_state.new_query(
+                        log_state.trace_provider.clone(),
+                        log_state.time_travel.unwrap_or(u32::MAX),
+                        resp.ctx,
+                        notifier.clone(),
+                    )
+                },
                 "Run (Ctrl+Enter)",
             );
             paint_label(
@@ -318,6 +397,19 @@ pub fn search_settings_dialog(ui: &mut Ui, search_state: &mut SearchState) {
                         .range(1..=255),
                 );
             });
+            ui.horizontal(|ui| {
+                ui.label("Spill results to disk above: ");
+                ui.add(
+                    egui::DragValue::new(&mut search_state.settings.spill_mib)
+                        .speed(1.0)
+                        .suffix(" MiB"),
+                )
+                .on_hover_text(
+                    "Write the largest intermediate results of a query thread to the temp \
+                     directory when they take more memory than this. Slower, use it for huge \
+                     traces. 0 keeps everything in memory.",
+                );
+            });
         });
         if let Some(rect) = ui.memory(|x| x.area_rect("Query settings"))
             && let QuerySettingsDialogData::Open { ref mut position, .. } =
@@ -328,6 +420,45 @@ pub fn search_settings_dialog(ui: &mut Ui, search_state: &mut SearchState) {
     }
 }
 
+/// The text to run for Ctrl+Enter: the selected part of `text`, or all of it if nothing (or only
+/// whitespace) is selected.
+fn query_text(text: &str, selection: Option<CCursorRange>) -> &str {
+    match selection {
+        Some(range) if !range.slice_str(text).trim().is_empty() => range.slice_str(text),
+        _ => text,
+    }
+}
+/// The completion mode at the end of `s`, and the partial word to complete.
+fn completion_context(s: &str) -> (CompletionMode, &str) {
+    if let Some(partial) = partial_filter_target(s) {
+        (CompletionMode::FilterTarget, partial)
+    } else {
+        (CompletionMode::Code, get_current_word(s))
+    }
+}
+/// If `s` ends inside a string literal used as a filter target, the contents of the literal so
+/// far. This is a heuristic: it only looks at what precedes the opening quote.
+fn partial_filter_target(s: &str) -> Option<&str> {
+    let quote = s.rfind(['"', '\''])?;
+    let partial = &s[quote + 1..];
+    if partial.contains('\n') {
+        return None;
+    }
+    let before = s[..quote].trim_end();
+    let is_target = if let Some(before) = before.strip_suffix('=') {
+        let before = before.trim_end();
+        before.strip_suffix("target").is_some_and(|x| !x.ends_with(is_ident_char))
+    } else if let Some(before) = before.strip_suffix('(') {
+        let before = before.trim_end();
+        before.strip_suffix("en_filter").is_some_and(|x| !x.ends_with(is_ident_char))
+    } else {
+        false
+    };
+    is_target.then_some(partial)
+}
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
 fn get_current_word(s: &str) -> &str {
     let start = s
         .char_indices()
@@ -337,3 +468,36 @@ fn get_current_word(s: &str) -> &str {
         .unwrap_or(0);
     &s[start..]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_target_context() {
+        let target = |s| completion_context(s);
+        assert_eq!(target(r#"{ target = "meta.fi"#), (CompletionMode::FilterTarget, "meta.fi"));
+        assert_eq!(target("{target='"), (CompletionMode::FilterTarget, ""));
+        assert_eq!(target(r#"en_filter( "us"#), (CompletionMode::FilterTarget, "us"));
+        assert_eq!(target(r#"en_filter("a", "EQ", "b"#), (CompletionMode::Code, "b"));
+        assert_eq!(target(r#"{ target = "a" }"#), (CompletionMode::Code, ""));
+        assert_eq!(target(r#"{ target = "a", value = "x"#), (CompletionMode::Code, "x"));
+        assert_eq!(target(r#"{ my_target = "x"#), (CompletionMode::Code, "x"));
+        assert_eq!(target("{ target = \"a\nen_fil"), (CompletionMode::Code, "en_fil"));
+        assert_eq!(target("return en_fil"), (CompletionMode::Code, "en_fil"));
+    }
+
+    #[test]
+    fn selected_query_text() {
+        let text = "local a = 1\nreturn ö";
+        let range = |a, b| Some(CCursorRange::two(CCursor::new(a), CCursor::new(b)));
+        assert_eq!(query_text(text, None), text);
+        assert_eq!(query_text(text, range(3, 3)), text);
+        assert_eq!(query_text(text, range(12, 20)), "return ö");
+        // selected backwards
+        assert_eq!(query_text(text, range(20, 12)), "return ö");
+        assert_eq!(query_text(text, range(0, 5)), "local");
+        // only a line break is selected
+        assert_eq!(query_text(text, range(11, 12)), text);
+    }
+}
@@ -1,47 +1,126 @@
 mod bottom_panel;
 pub mod query_window;
 pub mod segmented_button;
+mod workers;
 pub use bottom_panel::*;
 use std::{
-    cell::RefCell,
-    collections::HashMap,
     fmt::Debug,
-    ops::{Deref, RangeInclusive},
-    rc::Rc,
+    ops::{Range, RangeInclusive},
     sync::{Arc, RwLock},
     time::{Duration, Instant},
 };
+pub use workers::*;
 
-use crate::{search::query_window::PaginatedResults, spawn_task};
-use crossbeam::channel::Receiver;
+use crate::{
+    notifications::NotificationHandle,
+    search::query_window::{CombineState, PaginatedResults},
+    settings::get_prelude_path,
+    spawn_task,
+};
+use croaring::Bitmap as Roaring;
+use crossbeam::channel::{Receiver, Sender};
 use egui::{Pos2, Rect};
 
-use entrace_core::{LogProvider, LogProviderError, LogProviderImpl};
+use entrace_core::{LogProvider, LogProviderImpl, remote::NotifyExt};
 use entrace_query::{
-    QueryError,
-    lua_api::{self, EnMatcher, JoinCtx, LuaEvalState, setup_lua_on_arc_rwlock},
+    QueryError, QueryStats,
+    lua_api::{JoinCtx, LuaBindings, SpillConfig},
 };
-use mlua::{FromLua, Lua, Value};
 use tracing::{error, info};
 #[derive(Debug, Clone)]
 pub struct PartialQueryResult {
     pub ids: Vec<u32>,
+    pub stats: QueryStats,
 }
 #[derive(Debug)]
 pub struct QueryResult {
     pub ids: Vec<u32>,
     pub pages: PaginatedResults,
+    pub stats: QueryStats,
+    /// How the result was made from others, if it wasn't queried. See [SearchState::combine].
+    pub combined_from: Option<String>,
+}
+impl QueryResult {
+    fn new(ids: Vec<u32>, stats: QueryStats) -> Self {
+        Self { pages: PaginatedResults::new(ids.len()), ids, stats, combined_from: None }
+    }
+}
+/// How [SearchState::combine] combines the results of two queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SetOp {
+    #[default]
+    Union,
+    Intersection,
+    /// The ids of the first result which are not in the second.
+    Difference,
+}
+impl SetOp {
+    pub const ALL: [SetOp; 3] = [SetOp::Union, SetOp::Intersection, SetOp::Difference];
+    pub fn name(self) -> &'static str {
+        match self {
+            SetOp::Union => "union",
+            SetOp::Intersection => "intersection",
+            SetOp::Difference => "difference",
+        }
+    }
+    /// Combine the ids of `a` and `b`. The result is in id order, without duplicates.
+    pub fn apply(self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        let (a, b) = (Roaring::of(a), Roaring::of(b));
+        let combined = match self {
+            SetOp::Union => a.or(&b),
+            SetOp::Intersection => a.and(&b),
+            SetOp::Difference => a.andnot(&b),
+        };
+        combined.to_vec()
+    }
+}
+/// What the controller thread of a query sends to its window.
+#[derive(Debug)]
+pub enum QueryUpdate {
+    /// The matches of one partition, sent as soon as it finishes.
+    Batch { thread: u32, ids: Vec<u32> },
+    /// Every partition finished, with their merged stats.
+    Done(Result<QueryStats, QueryError>, Duration),
+}
+/// The matches of a running query, in the order its partitions finished.
+#[derive(Debug)]
+pub struct StreamingResult {
+    pub result: QueryResult,
+    /// Which partition each range of `result.ids` came from.
+    batches: Vec<(u32, Range<usize>)>,
+}
+impl StreamingResult {
+    pub fn new() -> Self {
+        Self { result: QueryResult::new(vec![], QueryStats::default()), batches: vec![] }
+    }
+    pub fn push(&mut self, thread: u32, ids: Vec<u32>) {
+        let start = self.result.ids.len();
+        self.result.ids.extend(ids);
+        self.batches.push((thread, start..self.result.ids.len()));
+        self.result.pages.set_len(self.result.ids.len());
+    }
+    /// Put the matches in partition order, which is id order within a partition.
+    pub fn finish(self, mut stats: QueryStats) -> QueryResult {
+        let reconcile_start = Instant::now();
+        let Self { result, mut batches } = self;
+        batches.sort_by_key(|(thread, _)| *thread);
+        let mut ids = Vec::with_capacity(result.ids.len());
+        for (_, range) in batches {
+            ids.extend_from_slice(&result.ids[range]);
+        }
+        stats.reconcile = reconcile_start.elapsed();
+        QueryResult::new(ids, stats)
+    }
+}
+impl Default for StreamingResult {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 #[derive(Debug)]
 pub enum Query {
-    Loading {
-        id: u16,
-        rx: crossbeam::channel::Receiver<(Result<QueryResult, QueryError>, Duration)>,
-    },
-    Completed {
-        id: u16,
-        result: Result<QueryResult, QueryError>,
-    },
+    Loading { id: u16, rx: Receiver<QueryUpdate>, partial: StreamingResult },
+    Completed { id: u16, result: Result<QueryResult, QueryError> },
 }
 impl Query {
     pub fn id(&self) -> u16 {
@@ -50,6 +129,30 @@ impl Query {
             Query::Completed { id, .. } => *id,
         }
     }
+    /// Apply the updates sent by the controller thread so far. Once the query completes (or its
+    /// controller dies), `timing` is set to how long it took.
+    pub fn receive(&mut self, timing: &mut QueryTiming) {
+        let Query::Loading { id, rx, partial } = self else { return };
+        let (result, elapsed) = loop {
+            match rx.try_recv() {
+                Ok(QueryUpdate::Batch { thread, ids }) => partial.push(thread, ids),
+                Ok(QueryUpdate::Done(stats, elapsed)) => {
+                    let partial = std::mem::take(partial);
+                    break (stats.map(|stats| partial.finish(stats)), elapsed);
+                }
+                Err(crossbeam::channel::TryRecvError::Empty) => return,
+                Err(crossbeam::channel::TryRecvError::Disconnected) => {
+                    let elapsed = match timing {
+                        QueryTiming::Loading(instant) => instant.elapsed(),
+                        QueryTiming::Finished(elapsed) => *elapsed,
+                    };
+                    break (Err(QueryError::QueryDied), elapsed);
+                }
+            }
+        };
+        *timing = QueryTiming::Finished(elapsed);
+        *self = Query::Completed { id: *id, result };
+    }
 }
 pub enum QueryTiming {
     Loading(Instant),
@@ -73,6 +176,9 @@ enum QuerySettingsDialogData {
 pub struct QuerySettings {
     data: QuerySettingsDialogData,
     num_threads: u8,
+    /// Spill the intermediate results of a query thread to the temp dir above this many MiB, see
+    /// [SpillConfig]. 0 keeps them in memory.
+    spill_mib: u32,
 }
 
 impl QuerySettings {
@@ -81,7 +187,14 @@ impl QuerySettings {
         let num_cpus = std::thread::available_parallelism()
             .unwrap_or_else(|_| 2.try_into().unwrap())
             .get() as u8;
-        QuerySettings { num_threads: num_cpus, data: QuerySettingsDialogData::Closed }
+        QuerySettings { num_threads: num_cpus, spill_mib: 0, data: QuerySettingsDialogData::Closed }
+    }
+    /// The [SpillConfig] of the query threads, or `None` if they keep results in memory.
+    pub fn spill(&self) -> Option<SpillConfig> {
+        (self.spill_mib > 0).then(|| SpillConfig {
+            max_bytes: self.spill_mib as usize * 1024 * 1024,
+            dir: std::env::temp_dir(),
+        })
     }
     pub fn is_open(&self) -> bool {
         match self.data {
@@ -98,118 +211,83 @@ pub struct SearchState {
     pub last_id: u16,
     pub query_window_open: Vec<bool>,
     pub query_timing: Vec<QueryTiming>,
+    pub workers: QueryWorkers,
+    pub combine: CombineState,
 }
 impl SearchState {
-    pub fn new_query(&mut self, trace_provider: Arc<RwLock<LogProviderImpl>>) {
-        let (tx, rx) = crossbeam::channel::bounded(1);
+    /// Run the query in the search box on background threads. The matches of each thread are
+    /// sent to the query window as soon as it finishes, and `ctx` is woken up for each of them.
+    ///
+    /// The prelude (see [get_prelude_path]) is read for every query, so edits to it apply to the
+    /// next one. If it fails to load, `notifier` gets an error, but the query still runs.
+    ///
+    /// Spans after `max_id` are not searched, see [crate::log::LogState::time_travel].
+    pub fn new_query(
+        &mut self, trace_provider: Arc<RwLock<LogProviderImpl>>, max_id: u32, ctx: egui::Context,
+        notifier: NotificationHandle,
+    ) {
+        let text_arc: Arc<str> = Arc::from(self.text.text.as_str());
+        self.new_query_with_text(text_arc, trace_provider, max_id, ctx, notifier);
+    }
+    /// Like [SearchState::new_query], but run `text` instead of the query editor contents.
+    pub fn new_query_with_text(
+        &mut self, text_arc: Arc<str>, trace_provider: Arc<RwLock<LogProviderImpl>>, max_id: u32,
+        ctx: egui::Context, notifier: NotificationHandle,
+    ) {
+        let (tx, rx) = crossbeam::channel::unbounded();
         let new_id = self.last_id + 1;
         self.last_id += 1;
-        self.queries.push(Query::Loading { id: new_id, rx });
+        self.queries.push(Query::Loading { id: new_id, rx, partial: StreamingResult::new() });
         self.query_window_open.push(true);
         self.query_timing.push(QueryTiming::Loading(Instant::now()));
-        let text_arc: Arc<str> = Arc::from(self.text.text.as_str());
         let tp = trace_provider.clone();
-        let mut threads = self.settings.num_threads as u32;
+        let threads = self.settings.num_threads as u32;
+        let spill = self.settings.spill();
+        self.workers.ensure(threads as usize);
+        let jobs = self.workers.sender();
         std::thread::spawn(move || {
             let query_start = Instant::now();
             // Controller thread
-            let spans_len = { trace_provider.read().unwrap().len() } as u32;
-            let mut items_per_thread = spans_len / threads;
-            info!(
-                "spans_len: {spans_len}, threads: {threads} -> items per thread: \
-                 {items_per_thread}"
-            );
-            if items_per_thread == 0 {
-                threads = 1;
-                items_per_thread = spans_len;
-                info!("Less items to query than threads, setting threads=1");
-            }
-            let mut ranges: Vec<RangeInclusive<u32>> = (0u32..threads)
-                .map(|x| (x * items_per_thread)..=(x + 1) * items_per_thread - 1)
-                .collect();
-            if let Some(last) = ranges.last_mut() {
-                *last = *last.start()..=spans_len.saturating_sub(1); // exclusive range
-            }
+            let spans_len = { trace_provider.read().unwrap().len() }.min(u32::MAX as usize) as u32;
+            let spans_len = spans_len.min(max_id.saturating_add(1));
+            let ranges = partition(spans_len, threads);
+            let threads = ranges.len() as u32;
             info!("Ranges for jobs: {ranges:?}");
 
-            let join_ctx = JoinCtx::from_thread_count(threads as usize);
-            let join_ctx_arc = Arc::new(join_ctx);
-
-            let rv = std::iter::repeat_with(|| None).take(threads as usize).collect();
-            #[allow(clippy::type_complexity)]
-            let results: Arc<
-                RwLock<Vec<Option<Result<PartialQueryResult, QueryError>>>>,
-            > = Arc::new(RwLock::new(rv));
-            std::thread::scope(|f| {
-                for i in 0..threads {
-                    let ta = text_arc.clone();
-                    let trace_provider = tp.clone();
-                    let range = ranges[i as usize].clone();
-                    let results2 = results.clone();
-                    let join_ctx_local = join_ctx_arc.clone();
-                    f.spawn(move || {
-                        let finder_cache = Rc::new(RefCell::new(HashMap::new()));
-                        let mut lua = Lua::new();
-                        let lua_state = LuaEvalState::new(join_ctx_local, range, finder_cache);
-                        if let Err(y) =
-                            setup_lua_on_arc_rwlock(&mut lua, trace_provider.clone(), lua_state)
-                        {
-                            let mut rw = results2.write().unwrap();
-                            rw[i as usize] = Some(Err(QueryError::LuaError(y)));
-                        }
-
-                        let start = Instant::now();
-                        let loaded: Result<Value, _> =
-                            lua.load(&*ta).set_name("search query").eval();
-                        info!(elapsed = ?start.elapsed(), "Thread {i} done");
-                        let lock = trace_provider.read().unwrap();
-                        match loaded {
-                            Ok(x) => {
-                                let ids: Result<_, _> = lua_result_to_ids(x, &lua, &*lock)
-                                    .map(|x| PartialQueryResult { ids: x });
-                                let mut rw = results2.write().unwrap();
-                                rw[i as usize] = Some(ids);
-                            }
-                            Err(y) => {
-                                let mut rw = results2.write().unwrap();
-                                if let mlua::Error::CallbackError { ref cause, .. } = y
-                                    && let mlua::Error::ExternalError(ext) = cause.deref()
-                                    && let Some(LogProviderError::JoinShutdown) = ext.downcast_ref()
-                                {
-                                    // this is not a true error; therefore ignored.
-                                    // see JoinShutdown docs.
-                                    rw[i as usize] = Some(Ok(PartialQueryResult { ids: vec![] }));
-                                } else {
-                                    rw[i as usize] = Some(Err(QueryError::LuaError(y)));
-                                }
-                            }
-                        }
-                    });
-                }
+            let prelude = read_prelude().unwrap_or_else(|e| {
+                notifier.error(format!("Failed to read the query prelude: {e:#}"));
+                None
             });
-            let elapsed = query_start.elapsed();
 
-            // reconcile partial results
-            let Ok(rr) = results.read() else {
-                tx.send((Err(QueryError::QueryDied), elapsed)).ok();
-                return;
-            };
-            let mut total_ids = vec![];
-            for partial in rr.iter() {
-                match partial {
-                    Some(Ok(y)) => {
-                        total_ids.extend(&y.ids);
-                    }
-                    Some(Err(x)) => {
-                        tx.send((Err(x.clone()), elapsed)).ok();
-                        return;
-                    }
-                    _ => unreachable!(),
-                }
+            let join_ctx = Arc::new(JoinCtx::from_thread_count(threads as usize));
+            let (done_tx, done_rx) = crossbeam::channel::unbounded();
+            for (i, range) in ranges.into_iter().enumerate() {
+                let bindings = LuaBindings { trace: tp.clone(), join_ctx: join_ctx.clone(), range };
+                let job = QueryJob {
+                    text: text_arc.clone(),
+                    prelude: prelude.clone(),
+                    spill: spill.clone(),
+                    bindings,
+                    thread: i as u32,
+                    done: done_tx.clone(),
+                };
+                jobs.send(job).ok();
             }
-            let ids_len = total_ids.len();
-            let qr = QueryResult { ids: total_ids, pages: PaginatedResults::new(ids_len) };
-            tx.send((Ok(qr), elapsed)).ok();
+            drop(done_tx);
+            // the workers have their own references while they run, and the trace should be free
+            // to close as soon as the query is done
+            drop((trace_provider, tp));
+            let mut prelude_error = None;
+            let result = forward_batches(done_rx, threads as usize, &tx, &ctx, &mut prelude_error);
+            if let Some(e) = prelude_error {
+                notifier.error(format!("Failed to load the query prelude: {e}"));
+            }
+            let elapsed = query_start.elapsed();
+            if let Ok(stats) = &result {
+                info!(%stats, "Query done");
+            }
+            tx.send(QueryUpdate::Done(result, elapsed)).ok();
+            ctx.request_repaint();
         });
     }
     pub fn new() -> Self {
@@ -220,35 +298,112 @@ impl SearchState {
             last_id: 0,
             query_window_open: vec![],
             query_timing: vec![],
+            workers: QueryWorkers::new(),
+            combine: CombineState::default(),
         }
     }
 }
-impl Default for SearchState {
-    fn default() -> Self {
-        Self::new()
+/// Split the spans `0..spans_len` into `threads` consecutive ranges of about the same size, the
+/// last one taking the remainder. Uses a single range if there are fewer spans than threads, and
+/// none if there are no spans at all.
+///
+/// The bounds are computed in u64, so this holds up to `spans_len == u32::MAX`.
+fn partition(spans_len: u32, threads: u32) -> Vec<RangeInclusive<u32>> {
+    if spans_len == 0 {
+        return vec![];
+    }
+    let (len, mut threads) = (spans_len as u64, threads.max(1) as u64);
+    let mut items_per_thread = len / threads;
+    info!("spans_len: {spans_len}, threads: {threads} -> items per thread: {items_per_thread}");
+    if items_per_thread == 0 {
+        threads = 1;
+        items_per_thread = len;
+        info!("Less items to query than threads, setting threads=1");
     }
+    (0..threads)
+        .map(|x| {
+            let start = x * items_per_thread;
+            let end = if x + 1 == threads { len } else { ((x + 1) * items_per_thread).min(len) };
+            // both fit, as start < end <= len <= u32::MAX
+            start as u32..=(end - 1) as u32
+        })
+        .collect()
 }
-/// materialize the filterset, if a filterset; or else just extract a Vec<u32>
-fn lua_result_to_ids(
-    result: mlua::Value, lua: &Lua, log: &impl LogProvider,
-) -> Result<Vec<u32>, QueryError> {
-    if let Value::Table(table) = result {
-        if let Ok(s) = table.get::<String>("type")
-            && s == "filterset"
-        {
-            let mut evaluator = lua_api::construct_evaluator(&table, log.len() as u32)
-                .map_err(QueryError::FiltersetEvalFail)?;
-            let root: usize = table.get("root").map_err(QueryError::FiltersetEvalFail)?;
-            evaluator.normalize(root);
-            let matcher = EnMatcher { log };
-            evaluator.materialize(&matcher, root);
-            return Ok(evaluator.results[&root].iter().collect());
+
+/// Send the matches of each of the `threads` partitions to the query window as it finishes, and
+/// merge their stats. Returns the first error, or [QueryError::QueryDied] if a partition never
+/// reported back.
+fn forward_batches(
+    done_rx: Receiver<JobDone>, threads: usize, tx: &Sender<QueryUpdate>, ctx: &egui::Context,
+    prelude_error: &mut Option<String>,
+) -> Result<QueryStats, QueryError> {
+    let mut reported = vec![false; threads];
+    let mut stats = QueryStats::default();
+    let mut first_error = None;
+    for JobDone { thread, result, prelude_error: error } in done_rx.iter() {
+        reported[thread as usize] = true;
+        // every thread loads the same prelude, so report it once
+        *prelude_error = prelude_error.take().or(error);
+        match result {
+            Ok(partial) => {
+                stats.merge(&partial.stats);
+                if first_error.is_none() {
+                    tx.send(QueryUpdate::Batch { thread, ids: partial.ids }).ok();
+                    ctx.request_repaint();
+                }
+            }
+            Err(e) => first_error = first_error.or(Some(e)),
         }
-        let ids: Vec<u32> = Vec::from_lua(mlua::Value::Table(table), lua)
-            .map_err(|_| QueryError::FailedToCoerce)?;
-        Ok(ids)
-    } else {
-        Err(QueryError::FailedToCoerce)
+    }
+    match first_error {
+        Some(e) => Err(e),
+        // the worker died without reporting back
+        None if reported.contains(&false) => Err(QueryError::QueryDied),
+        None => Ok(stats),
+    }
+}
+/// Read the query prelude, or `None` if there is none.
+fn read_prelude() -> anyhow::Result<Option<Arc<str>>> {
+    let path = get_prelude_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(x) => Ok(Some(Arc::from(x))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow::Error::new(e).context(format!("reading {}", path.display()))),
+    }
+}
+impl SearchState {
+    /// Add a query window with the results of queries `a` and `b` combined by `op`, as if they
+    /// were queried. Returns the id of the new query, or `None` if `a` or `b` hasn't completed
+    /// successfully.
+    pub fn combine(&mut self, a: u16, b: u16, op: SetOp) -> Option<u16> {
+        let start = Instant::now();
+        let ids_of = |id: u16| {
+            self.queries.iter().find(|x| x.id() == id).and_then(|x| match x {
+                Query::Completed { result: Ok(result), .. } => Some(&result.ids),
+                _ => None,
+            })
+        };
+        let ids = op.apply(ids_of(a)?, ids_of(b)?);
+        let new_id = self.last_id + 1;
+        self.last_id += 1;
+        let mut result = QueryResult::new(ids, QueryStats::default());
+        result.combined_from = Some(format!("The {} of query {a} and query {b}", op.name()));
+        self.queries.push(Query::Completed { id: new_id, result: Ok(result) });
+        self.query_window_open.push(true);
+        self.query_timing.push(QueryTiming::Finished(start.elapsed()));
+        Some(new_id)
+    }
+    /// Forget every query, for when their trace is closed. Running queries finish in the
+    /// background, and their results are dropped.
+    pub fn clear_results(&mut self) {
+        self.queries.clear();
+        self.query_window_open.clear();
+        self.query_timing.clear();
+    }
+}
+impl Default for SearchState {
+    fn default() -> Self {
+        Self::new()
     }
 }
 pub struct LocatingStarted {
@@ -277,28 +432,211 @@ impl LocatingState {
     }
 }
 impl LocatingState {
-    pub fn start_locating(target: u32, trace_provider: &Arc<RwLock<LogProviderImpl>>) -> Self {
+    pub fn start_locating(
+        target: u32, trace_provider: &Arc<RwLock<LogProviderImpl>>, ctx: egui::Context,
+    ) -> Self {
         let tc = trace_provider.clone();
         let (tx, path_rx) = crossbeam::channel::bounded(1);
         spawn_task(move || {
-            let tc = tc.read().unwrap();
-            let mut path = Vec::<u32>::with_capacity(4);
-            let mut cur_idx = target;
-            loop {
-                path.push(cur_idx);
-                if cur_idx == 0 {
-                    break;
-                }
-                let Ok(parent) = tc.parent(cur_idx) else {
-                    error!(id = cur_idx, "cannot resolve parent, breaking search at last known");
-                    break;
-                };
-                cur_idx = parent;
-            }
-            path.reverse();
+            let path = path_from_root(&*tc.read().unwrap(), target);
             info!("Path for {target}: {path:?}");
-            tx.send(path).unwrap();
+            // fails if the trace was closed meanwhile
+            tx.send(path).ok();
+            ctx.request_repaint();
         });
         LocatingState::Started(LocatingStarted { target, path_rx })
     }
+    /// Like [LocatingState::start_locating], but find the path right away, for callers which
+    /// already hold the trace.
+    pub fn scroll_to(target: u32, log: &dyn LogProvider) -> Self {
+        let path = path_from_root(log, target);
+        LocatingState::ScrollTo { target, target_row_offset: None, path, opened_path: false }
+    }
+}
+
+/// The ids from the root to `target`, both included.
+pub fn path_from_root(log: &dyn LogProvider, target: u32) -> Vec<u32> {
+    let mut path = Vec::<u32>::with_capacity(4);
+    let mut cur_idx = target;
+    loop {
+        path.push(cur_idx);
+        if cur_idx == 0 {
+            break;
+        }
+        let Ok(parent) = log.parent(cur_idx) else {
+            error!(id = cur_idx, "cannot resolve parent, breaking search at last known");
+            break;
+        };
+        cur_idx = parent;
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::{NotificationHandle, NotificationState};
+    use entrace_core::{EnValue, test_util::LogProviderBuilder};
+
+    /// The ranges are in order, don't overlap, and cover `0..spans_len`.
+    fn assert_covers(ranges: &[RangeInclusive<u32>], spans_len: u32) {
+        let mut next = 0u64;
+        for range in ranges {
+            assert_eq!(*range.start() as u64, next, "{ranges:?}");
+            assert!(range.start() <= range.end(), "{ranges:?}");
+            next = *range.end() as u64 + 1;
+        }
+        assert_eq!(next, spans_len as u64, "{ranges:?}");
+    }
+
+    #[test]
+    fn test_partition() {
+        let ranges = partition(10, 3);
+        assert_eq!(ranges, vec![0..=2, 3..=5, 6..=9]);
+        assert_eq!(partition(2, 4), vec![0..=1]);
+        assert_eq!(partition(5, 0), vec![0..=4]);
+        assert_eq!(partition(0, 4), vec![]);
+        for (spans_len, threads) in
+            [(u32::MAX, 1), (u32::MAX, 2), (u32::MAX, 7), (u32::MAX - 1, 255), (3, 255)]
+        {
+            let ranges = partition(spans_len, threads);
+            assert_eq!(ranges.len() as u32, if threads > spans_len { 1 } else { threads });
+            assert_covers(&ranges, spans_len);
+        }
+    }
+
+    fn done(thread: u32, ids: Vec<u32>) -> JobDone {
+        let result = Ok(PartialQueryResult { ids, stats: QueryStats::default() });
+        JobDone { thread, result, prelude_error: None }
+    }
+
+    #[test]
+    fn test_partial_batches_delivered() {
+        let (done_tx, done_rx) = crossbeam::channel::unbounded();
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let controller = std::thread::spawn(move || {
+            let ctx = egui::Context::default();
+            let result = forward_batches(done_rx, 2, &tx, &ctx, &mut None);
+            tx.send(QueryUpdate::Done(result, Duration::ZERO)).unwrap();
+        });
+        let mut query = Query::Loading { id: 1, rx, partial: StreamingResult::new() };
+        let mut timing = QueryTiming::Loading(Instant::now());
+
+        // the second partition finishes first, and shows up while the first still runs
+        done_tx.send(done(1, vec![7, 9])).unwrap();
+        let start = Instant::now();
+        while let Query::Loading { ref partial, .. } = query
+            && partial.result.ids.is_empty()
+        {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            query.receive(&mut timing);
+        }
+        let Query::Loading { ref partial, .. } = query else { panic!("query completed early") };
+        assert_eq!(partial.result.ids, [7, 9]);
+        assert_eq!(partial.result.pages.page_cnt(), 1);
+
+        done_tx.send(done(0, vec![2, 3])).unwrap();
+        drop(done_tx);
+        controller.join().unwrap();
+        query.receive(&mut timing);
+        let Query::Completed { result: Ok(result), .. } = query else { panic!("not completed") };
+        // reordered by partition
+        assert_eq!(result.ids, [2, 3, 7, 9]);
+        assert!(matches!(timing, QueryTiming::Finished(_)));
+    }
+
+    #[test]
+    fn test_set_ops() {
+        let (a, b) = ([9, 1, 5, 3, 3], [4, 5, 9, 100]);
+        assert_eq!(SetOp::Union.apply(&a, &b), [1, 3, 4, 5, 9, 100]);
+        assert_eq!(SetOp::Intersection.apply(&a, &b), [5, 9]);
+        assert_eq!(SetOp::Difference.apply(&a, &b), [1, 3]);
+        assert_eq!(SetOp::Difference.apply(&b, &a), [4, 100]);
+        assert_eq!(SetOp::Intersection.apply(&a, &[] as &[u32]), Vec::<u32>::new());
+        assert_eq!(SetOp::Union.apply(&[], &[u32::MAX]), [u32::MAX]);
+    }
+
+    #[test]
+    fn test_combine() {
+        let mut search = SearchState::new();
+        let completed = |id, ids: Vec<u32>| Query::Completed {
+            id,
+            result: Ok(QueryResult::new(ids, QueryStats::default())),
+        };
+        search.queries.push(completed(1, vec![1, 2, 3]));
+        search.queries.push(completed(2, vec![2, 3, 4]));
+        search.queries.push(Query::Completed { id: 3, result: Err(QueryError::QueryDied) });
+        search.query_window_open.extend([true; 3]);
+        search.query_timing.extend((0..3).map(|_| QueryTiming::Finished(Duration::ZERO)));
+        search.last_id = 3;
+
+        assert_eq!(search.combine(1, 2, SetOp::Difference), Some(4));
+        let Some(Query::Completed { id: 4, result: Ok(result) }) = search.queries.last() else {
+            panic!("no combined query");
+        };
+        assert_eq!(result.ids, [1]);
+        assert_eq!(result.combined_from.as_deref(), Some("The difference of query 1 and query 2"));
+        assert_eq!(search.query_window_open.len(), 4);
+        assert_eq!(search.query_timing.len(), 4);
+        // combined results can be combined again
+        assert_eq!(search.combine(4, 2, SetOp::Union), Some(5));
+        assert_eq!(search.queries.len(), 5);
+
+        // failed or missing queries
+        assert_eq!(search.combine(1, 3, SetOp::Union), None);
+        assert_eq!(search.combine(1, 42, SetOp::Union), None);
+        assert_eq!(search.queries.len(), 5);
+        assert_eq!(search.last_id, 5);
+    }
+
+    #[test]
+    fn test_missing_partition() {
+        let (done_tx, done_rx) = crossbeam::channel::unbounded();
+        let (tx, rx) = crossbeam::channel::unbounded();
+        done_tx.send(done(0, vec![1])).unwrap();
+        drop(done_tx);
+        let ctx = egui::Context::default();
+        let result = forward_batches(done_rx, 2, &tx, &ctx, &mut None);
+        assert!(matches!(result, Err(QueryError::QueryDied)));
+        assert!(matches!(rx.try_recv(), Ok(QueryUpdate::Batch { thread: 0, .. })));
+    }
+
+    #[test]
+    fn test_error_has_partition_range() {
+        let mut builder = LogProviderBuilder::new();
+        for _ in 0..5 {
+            builder.span(0, "good").add();
+        }
+        builder.span(0, "bad").attr("poison", EnValue::Bool(true)).add();
+        let trace = Arc::new(RwLock::new(builder.build_impl()));
+        let query = r#"
+            local start, stop = en_span_range()
+            for i = start, stop do
+                if en_attr_by_name(i, "poison") then error("found a poisoned span") end
+            end
+            return {}
+        "#;
+        let mut search = SearchState::new();
+        // 7 spans, so the poisoned one is in the second partition, 3..=6
+        search.settings.num_threads = 2;
+        let notifier = NotificationHandle(Arc::new(RwLock::new(NotificationState::new())));
+        let ctx = egui::Context::default();
+        search.new_query_with_text(Arc::from(query), trace, u32::MAX, ctx, notifier);
+        let start = Instant::now();
+        while let Query::Loading { .. } = search.queries[0] {
+            assert!(start.elapsed() < Duration::from_secs(5), "query didn't finish");
+            search.queries[0].receive(&mut search.query_timing[0]);
+        }
+
+        let Query::Completed { result: Err(ref error), .. } = search.queries[0] else {
+            panic!("expected the query to fail, got {:?}", search.queries[0]);
+        };
+        let QueryError::InRange { thread, ref range, ref source } = *error else {
+            panic!("expected an InRange error, got {error:?}");
+        };
+        assert_eq!((thread, range.clone()), (1, 3..=6));
+        assert!(matches!(**source, QueryError::LuaError(_)));
+        assert_eq!(error.to_string(), "Error on spans 3..=6 (query thread 1)");
+    }
 }
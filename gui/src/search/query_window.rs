@@ -2,7 +2,7 @@ use crate::{
     App, LogState, LogStatus,
     homepage::{SpanContext, span},
     layout_text,
-    search::{Query, QueryError, QueryResult, QueryTiming, search_settings_dialog},
+    search::{Query, QueryResult, SearchState, SetOp, search_settings_dialog},
 };
 use egui::{Layout, ScrollArea, Ui, Widget};
 use entrace_core::display_error_context;
@@ -11,48 +11,63 @@ use tracing::{error, info};
 
 pub fn query_windows(ui: &mut Ui, app: &mut App) {
     search_settings_dialog(ui, &mut app.search_state);
+    combine_dialog(ui.ctx(), &mut app.search_state);
     for i in 0..app.search_state.queries.len() {
         let id = app.search_state.queries[i].id();
         let s = format!("Query {id}");
         egui::Window::new(s).open(&mut app.search_state.query_window_open[i]).show(
             ui.ctx(),
             |ui| {
-                fn set_elapsed(i: usize, timing: &mut [QueryTiming]) {
-                    match timing[i] {
-                        QueryTiming::Loading(instant) => {
-                            timing[i] = QueryTiming::Finished(instant.elapsed())
-                        }
-                        QueryTiming::Finished(_) => unreachable!(),
-                    }
-                }
+                let timing = &mut app.search_state.query_timing[i];
+                app.search_state.queries[i].receive(timing);
                 match app.search_state.queries[i] {
-                    Query::Loading { ref id, ref rx } => {
-                        match rx.try_recv() {
-                            Ok((res, elapsed)) => {
-                                app.search_state.queries[i] =
-                                    Query::Completed { id: *id, result: res };
-                                app.search_state.query_timing[i] = QueryTiming::Finished(elapsed)
-                            }
-                            Err(x) => match x {
-                                crossbeam::channel::TryRecvError::Empty => (),
-                                crossbeam::channel::TryRecvError::Disconnected => {
-                                    app.search_state.queries[i] = Query::Completed {
-                                        id: *id,
-                                        result: Err(QueryError::QueryDied),
-                                    };
-                                    set_elapsed(i, &mut app.search_state.query_timing);
-                                }
-                            },
+                    Query::Loading { ref mut partial, .. } => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(format!("{} spans so far", partial.result.ids.len()));
+                        });
+                        if !partial.result.ids.is_empty()
+                            && let LogStatus::Ready(log_state) = &mut app.log_status
+                        {
+                            ui.separator();
+                            query_result_list(
+                                ui,
+                                &mut partial.result,
+                                log_state,
+                                app.settings.strip_ansi(),
+                                app.settings.message_attr(),
+                            );
                         }
-                        ui.spinner();
                     }
                     Query::Completed { ref mut result, .. } => {
                         let elapsed = &app.search_state.query_timing[i];
-                        ui.label(format!("Completed query in {:?}", elapsed.unwrap()));
+                        match result.as_ref().ok().and_then(|x| x.combined_from.as_ref()) {
+                            Some(from) => ui.label(from.as_str()),
+                            None => ui.label(format!("Completed query in {:?}", elapsed.unwrap())),
+                        };
+                        if let Ok(x) = result {
+                            ui.horizontal(|ui| {
+                                if x.combined_from.is_none() {
+                                    ui.weak(x.stats.to_string());
+                                }
+                                let hover = "Union, intersection or difference with another query";
+                                if ui.small_button("Combine…").on_hover_text(hover).clicked() {
+                                    let combine = &mut app.search_state.combine;
+                                    combine.open = true;
+                                    combine.a = Some(id);
+                                }
+                            });
+                        }
                         ui.separator();
                         match result {
                             Ok(x) => match &mut app.log_status {
-                                LogStatus::Ready(log_state) => query_result_list(ui, x, log_state),
+                                LogStatus::Ready(log_state) => query_result_list(
+                                    ui,
+                                    x,
+                                    log_state,
+                                    app.settings.strip_ansi(),
+                                    app.settings.message_attr(),
+                                ),
                                 _ => error!(
                                     "query_windows: want to show query result but it is already \
                                      destroyed"
@@ -83,6 +98,72 @@ pub fn query_windows(ui: &mut Ui, app: &mut App) {
         i += 1;
     }
 }
+/// The window to combine the results of two queries, see [SearchState::combine].
+#[derive(Default)]
+pub struct CombineState {
+    pub open: bool,
+    pub a: Option<u16>,
+    pub b: Option<u16>,
+    pub op: SetOp,
+}
+pub fn combine_dialog(ctx: &egui::Context, search: &mut SearchState) {
+    if !search.combine.open {
+        return;
+    }
+    let completed: Vec<u16> = search
+        .queries
+        .iter()
+        .filter_map(|x| match x {
+            Query::Completed { id, result: Ok(_) } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    let state = &mut search.combine;
+    // the picked queries may have been closed since
+    state.a = state.a.filter(|x| completed.contains(x));
+    state.b = state.b.filter(|x| completed.contains(x));
+    let mut open = true;
+    let mut go = None;
+    egui::Window::new("Combine query results")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            if completed.len() < 2 {
+                ui.weak("Run two queries to combine their results.");
+                return;
+            }
+            ui.horizontal(|ui| {
+                query_picker(ui, "combine_a", &mut state.a, &completed);
+                egui::ComboBox::from_id_salt("combine_op").selected_text(state.op.name()).show_ui(
+                    ui,
+                    |ui| {
+                        for op in SetOp::ALL {
+                            ui.selectable_value(&mut state.op, op, op.name());
+                        }
+                    },
+                );
+                query_picker(ui, "combine_b", &mut state.b, &completed);
+            });
+            let picked = state.a.zip(state.b);
+            if ui.add_enabled(picked.is_some(), egui::Button::new("Combine")).clicked() {
+                go = picked;
+            }
+        });
+    if let Some((a, b)) = go {
+        let op = search.combine.op;
+        search.combine(a, b, op);
+    }
+    search.combine.open = open;
+}
+fn query_picker(ui: &mut Ui, id_salt: &str, picked: &mut Option<u16>, ids: &[u16]) {
+    let text = picked.map_or("Pick a query".into(), |x| format!("Query {x}"));
+    egui::ComboBox::from_id_salt(id_salt).selected_text(text).show_ui(ui, |ui| {
+        for id in ids {
+            ui.selectable_value(picked, Some(*id), format!("Query {id}"));
+        }
+    });
+}
 #[derive(Debug)]
 pub struct PaginatedResults {
     cur_page: usize,
@@ -99,6 +180,10 @@ impl PaginatedResults {
             nr_entries,
         }
     }
+    /// Update the number of entries as more arrive, staying on the current page.
+    pub fn set_len(&mut self, nr_entries: usize) {
+        self.nr_entries = nr_entries;
+    }
     pub fn cur_range(&self) -> Range<usize> {
         let start = self.cur_page * self.page_size;
         start..min(start + self.page_size, self.nr_entries)
@@ -144,7 +229,9 @@ pub fn result_list_pagination(ui: &mut Ui, result: &mut QueryResult) {
         });
     });
 }
-pub fn query_result_list(ui: &mut Ui, result: &mut QueryResult, log: &mut LogState) {
+pub fn query_result_list(
+    ui: &mut Ui, result: &mut QueryResult, log: &mut LogState, strip_ansi: bool, message_attr: &str,
+) {
     result_list_pagination(ui, result);
     ScrollArea::new([false, true]).auto_shrink([false, false]).stick_to_bottom(false).show(
         ui,
@@ -154,6 +241,8 @@ pub fn query_result_list(ui: &mut Ui, result: &mut QueryResult, log: &mut LogSta
             let mut ctx = SpanContext::QueryResults {
                 locating_state: &log.locating_state,
                 trace_provider: log.trace_provider.clone(),
+                strip_ansi,
+                message_attr,
             };
             for id in result_range {
                 span(ui, &mut ctx, &log_reader, result.ids[id]);
@@ -0,0 +1,128 @@
+use std::{ops::Deref, sync::Arc, time::Instant};
+
+use crossbeam::channel::{Receiver, Sender};
+use entrace_core::LogProviderError;
+use entrace_query::{
+    QueryError,
+    lua_api::{LuaBindings, ReusableLua, SpillConfig, lua_result_to_ids},
+};
+use tracing::info;
+
+use crate::search::PartialQueryResult;
+
+/// One partition of a query, evaluated by a [QueryWorkers] thread.
+pub struct QueryJob {
+    pub text: Arc<str>,
+    /// Lua code run before the query, see [ReusableLua::set_prelude].
+    pub prelude: Option<Arc<str>>,
+    /// See [ReusableLua::set_spill].
+    pub spill: Option<SpillConfig>,
+    pub bindings: LuaBindings,
+    /// Index of the partition, sent back with the result.
+    pub thread: u32,
+    pub done: Sender<JobDone>,
+}
+/// What a [QueryWorkers] thread sends back for a [QueryJob].
+pub struct JobDone {
+    pub thread: u32,
+    pub result: Result<PartialQueryResult, QueryError>,
+    /// The error of the prelude, if it failed. The query still runs without it.
+    pub prelude_error: Option<String>,
+}
+
+/// Long-lived query threads. Each keeps a [ReusableLua] between queries, so the Lua API is only
+/// registered once per thread instead of once per query.
+pub struct QueryWorkers {
+    tx: Sender<QueryJob>,
+    rx: Receiver<QueryJob>,
+    count: usize,
+}
+impl QueryWorkers {
+    pub fn new() -> Self {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        Self { tx, rx, count: 0 }
+    }
+    /// Start workers until there are at least `count`, so that a query split into `count`
+    /// partitions can evaluate all of them at once.
+    pub fn ensure(&mut self, count: usize) {
+        while self.count < count {
+            let rx = self.rx.clone();
+            let name = format!("query-worker-{}", self.count);
+            std::thread::Builder::new()
+                .name(name)
+                .spawn(move || worker_loop(rx))
+                .expect("failed to spawn query worker");
+            self.count += 1;
+        }
+    }
+    pub fn sender(&self) -> Sender<QueryJob> {
+        self.tx.clone()
+    }
+}
+impl Default for QueryWorkers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs until every [QueryWorkers::sender] is dropped.
+fn worker_loop(rx: Receiver<QueryJob>) {
+    let mut lua: Option<ReusableLua> = None;
+    for QueryJob { text, prelude, spill, bindings, thread, done } in rx {
+        let range = bindings.range.clone();
+        let trace = bindings.trace.clone();
+        let reused = match lua {
+            Some(ref lua) => {
+                lua.rebind(bindings);
+                true
+            }
+            None => {
+                let start = Instant::now();
+                match ReusableLua::new(bindings) {
+                    Ok(x) => lua = Some(x),
+                    Err(y) => {
+                        let err = QueryError::LuaError(y).in_range(thread, range);
+                        done.send(JobDone { thread, result: Err(err), prelude_error: None }).ok();
+                        continue;
+                    }
+                }
+                info!(elapsed = ?start.elapsed(), "Thread {thread} set up Lua");
+                false
+            }
+        };
+        let lua = lua.as_mut().unwrap();
+        // a broken prelude shouldn't fail the query, it's reported separately
+        let prelude_error = lua.set_prelude(prelude).err().map(|x| x.to_string());
+        lua.set_spill(spill);
+        let lua = &*lua;
+
+        let start = Instant::now();
+        let loaded = lua.eval(&text, "search query");
+        info!(elapsed = ?start.elapsed(), reused, "Thread {thread} done");
+        let result = match loaded {
+            Ok(x) => {
+                let log = trace.read().unwrap();
+                lua_result_to_ids(x, lua.lua(), &*log)
+                    .map(|x| PartialQueryResult { ids: x, stats: lua.take_stats() })
+                    .map_err(|x| x.in_range(thread, range))
+            }
+            Err(y) => {
+                if let mlua::Error::CallbackError { ref cause, .. } = y
+                    && let mlua::Error::ExternalError(ext) = cause.deref()
+                    && let Some(LogProviderError::JoinShutdown) = ext.downcast_ref()
+                {
+                    // this is not a true error; therefore ignored.
+                    // see JoinShutdown docs.
+                    Ok(PartialQueryResult { ids: vec![], stats: lua.take_stats() })
+                } else {
+                    lua.take_stats();
+                    Err(QueryError::LuaError(y).in_range(thread, range))
+                }
+            }
+        };
+        // an idle worker shouldn't keep the trace alive, or closing it couldn't free it
+        lua.unbind();
+        drop(trace);
+        done.send(JobDone { thread, result, prelude_error }).ok();
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     fmt::Display,
-    fs::OpenOptions,
+    fs::{File, OpenOptions},
     path::PathBuf,
     process,
     sync::{Arc, RwLock},
@@ -11,10 +11,10 @@ use directories::ProjectDirs;
 use entrace_core::{
     TreeLayer,
     en_formatter::EnFormatter,
-    remote::{IETStorage, IETStorageConfig},
+    remote::{IETStorage, IETStorageConfig, IETStorageError},
 };
 use tracing::{info, level_filters::LevelFilter};
-use tracing_subscriber::{Registry, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{Registry, layer::SubscriberExt, reload, util::SubscriberInitExt};
 
 use crate::spawn_task;
 #[derive(Debug, Clone, PartialEq)]
@@ -65,6 +65,15 @@ impl Display for SelfTracingLevel {
         write!(f, "{}", self.repr_first_up())
     }
 }
+/// Changes the level of the running self-tracing subscriber.
+pub type LevelHandle = reload::Handle<LevelFilter, Registry>;
+/// The level filter of the self-tracing subscriber, which can be changed later through the
+/// handle, see [SelfTracingInner::set_level].
+pub fn reloadable_filter(
+    level: &SelfTracingLevel,
+) -> (reload::Layer<LevelFilter, Registry>, LevelHandle) {
+    reload::Layer::new(level.to_filter())
+}
 #[derive(Default)]
 pub enum SelfTracingState {
     #[default]
@@ -85,9 +94,11 @@ pub fn self_tracing_path() -> anyhow::Result<PathBuf> {
 
 impl SelfTracingState {
     pub fn start_tracing(level: SelfTracingLevel, save_trace: bool) -> SelfTracingState {
-        let filter_level = level.to_filter();
+        let (filter, level_handle) = reloadable_filter(&level);
         let st_path = Arc::new(RwLock::new(None));
         let path_c = st_path.clone();
+        let guard = Arc::new(RwLock::new(None));
+        let guard_c = guard.clone();
         spawn_task(move || {
             let printing_layer =
                 tracing_subscriber::fmt::layer().without_time().event_format(EnFormatter);
@@ -107,21 +118,141 @@ impl SelfTracingState {
                     .unwrap();
                 let storage =
                     Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(file)));
-                let tree_layer = TreeLayer::from_storage(storage);
-                Registry::default().with(filter_level).with(printing_layer).with(tree_layer).init();
+                let tree_layer = TreeLayer::from_storage(storage.clone());
+                Registry::default().with(filter).with(printing_layer).with(tree_layer).init();
                 info!(path = path.display().to_string(), "path for self_tracing");
                 *st_path.write().unwrap() = Some(path.display().to_string());
+                *guard.write().unwrap() = Some(SelfTraceGuard { storage });
             } else {
                 info!("Not saving self-trace file");
-                Registry::default().with(filter_level).with(printing_layer).init();
+                Registry::default().with(filter).with(printing_layer).init();
             }
             info!("Started self-tracing");
         });
-        SelfTracingState::Enabled(SelfTracingInner { level, path: path_c, saving: save_trace })
+        SelfTracingState::Enabled(SelfTracingInner {
+            level,
+            path: path_c,
+            saving: save_trace,
+            guard: guard_c,
+            level_handle,
+        })
+    }
+    /// Finish the self-trace file, if one is being saved. Called on exit.
+    pub fn finish(&self) {
+        if let SelfTracingState::Enabled(inner) = self
+            && let Some(guard) = inner.guard.read().unwrap().as_ref()
+        {
+            info!("Finishing self-trace");
+            if let Err(y) = guard.finish() {
+                eprintln!("Failed to finish self-trace: {y}");
+            }
+        }
     }
 }
 pub struct SelfTracingInner {
     pub level: SelfTracingLevel,
     pub saving: bool,
     pub path: Arc<RwLock<Option<String>>>,
+    /// Set once the self-trace file is opened, if `saving`.
+    pub guard: Arc<RwLock<Option<SelfTraceGuard>>>,
+    /// Only refers to the subscriber weakly. It is the global default, so it lives as long as the
+    /// process and the handle stays valid.
+    level_handle: LevelHandle,
+}
+impl SelfTracingInner {
+    /// Change the level of the running subscriber. [SelfTracingLevel::Disabled] turns self-tracing
+    /// off until a level is set again.
+    pub fn set_level(&mut self, level: SelfTracingLevel) -> Result<(), reload::Error> {
+        self.level_handle.reload(level.to_filter())?;
+        self.level = level;
+        Ok(())
+    }
+}
+
+/// Finishes the self-trace storage when dropped, so the file is complete even if
+/// [eframe::App::on_exit] doesn't run.
+///
+/// The storage is also owned by the global subscriber, which is never dropped, so without this the
+/// entries still buffered in the storage thread would be lost on exit.
+///
+/// Shutdown order: [eframe::App::on_exit] calls [SelfTracingState::finish], which writes out
+/// everything logged until then. Anything logged after that is dropped by the finished storage.
+/// Dropping the app then drops this guard, whose second finish is a no-op.
+pub struct SelfTraceGuard {
+    storage: Arc<IETStorage<File>>,
+}
+impl SelfTraceGuard {
+    pub fn finish(&self) -> Result<(), IETStorageError> {
+        match self.storage.finish() {
+            // the worker thread is gone once finished, so a second finish can't even reach it
+            Ok(_) | Err(IETStorageError::NoHandle | IETStorageError::Send(_)) => Ok(()),
+            Err(y) => Err(y),
+        }
+    }
+}
+impl Drop for SelfTraceGuard {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entrace_core::remote::load_iet_trace;
+    use tracing::{debug, info_span, trace};
+
+    #[test]
+    fn guard_finishes_on_drop() {
+        let path = std::env::temp_dir().join(format!("entrace-self-{}.iet", process::id()));
+        let file = File::create(&path).unwrap();
+        let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(file)));
+        let subscriber = Registry::default().with(TreeLayer::from_storage(storage.clone()));
+        let guard = SelfTraceGuard { storage };
+        {
+            let _default = subscriber.set_default();
+            info_span!("span").in_scope(|| info!("inside"));
+            guard.finish().unwrap();
+            info!("after finish, dropped");
+        }
+        // finishing again, as the drop does, is fine
+        guard.finish().unwrap();
+        drop(guard);
+
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let trace = load_iet_trace(&written[10..], false).unwrap();
+        assert_eq!(trace.data.len(), 3, "root, span and event");
+    }
+
+    #[test]
+    fn level_changes_while_running() {
+        let storage = Arc::new(IETStorage::init(IETStorageConfig::non_length_prefixed(vec![])));
+        let (filter, level_handle) = reloadable_filter(&SelfTracingLevel::Info);
+        let subscriber =
+            Registry::default().with(filter).with(TreeLayer::from_storage(storage.clone()));
+        let mut inner = SelfTracingInner {
+            level: SelfTracingLevel::Info,
+            saving: false,
+            path: Default::default(),
+            guard: Default::default(),
+            level_handle,
+        };
+        {
+            let _default = subscriber.set_default();
+            debug!("dropped at info");
+            inner.set_level(SelfTracingLevel::Debug).unwrap();
+            debug!("kept at debug");
+            inner.set_level(SelfTracingLevel::Disabled).unwrap();
+            info!("dropped when disabled");
+            inner.set_level(SelfTracingLevel::Trace).unwrap();
+            trace!("kept at trace");
+        }
+        assert_eq!(inner.level, SelfTracingLevel::Trace);
+        let written = storage.finish().unwrap();
+        let trace = load_iet_trace(&written[10..], false).unwrap();
+        let messages: Vec<String> =
+            trace.data.iter().filter_map(|x| Some(x.message.as_ref()?.to_string())).collect();
+        assert_eq!(messages, ["kept at debug", "kept at trace"]);
+    }
 }
@@ -5,11 +5,12 @@ use egui::{
     Color32, Context, DragValue, InnerResponse, Margin, RichText, TextStyle, ThemePreference, Ui,
     epaint::AlphaFromCoverage, pos2, vec2,
 };
-use entrace_core::remote::{NotifyExt, Refresh};
+use entrace_core::remote::{DEFAULT_REFRESH_WINDOW, NotifyExt, Refresh};
 use notify::{RecommendedWatcher, Watcher};
 use std::{
     cell::LazyCell,
     cmp::min,
+    fmt::Write as _,
     fs::{File, OpenOptions},
     io::{Read, Write},
     path::{Path, PathBuf},
@@ -18,18 +19,19 @@ use std::{
         Arc,
         atomic::{AtomicBool, Ordering},
     },
+    time::Duration,
 };
 use tracing::info;
 
 use crate::{
     App,
-    frame_time::{
-        FrameTimeTracker, SamplingFrameTracker, TrackFrameTime, us_to_human, us_to_human_u64,
-    },
+    frame_time::{FrameTimeTracker, SamplingFrameTracker, TrackFrameTime, us_to_human},
     rect,
+    row_color::ColorRule,
     search::Autocompleter,
     self_tracing::{SelfTracingLevel, SelfTracingState},
     time_print,
+    tree::ChildAggregate,
 };
 pub enum SettingsMessage {
     ReloadSettings,
@@ -48,6 +50,57 @@ impl SettingsState {
             SettingsState::Loaded(settings_state_inner) => settings_state_inner.settings.ui_scale,
         }
     }
+    /// The editor command template, see [crate::editor::editor_argv].
+    pub fn editor_command(&self) -> &str {
+        match self {
+            SettingsState::Loaded(inner) => &inner.settings.editor_command,
+            _ => "",
+        }
+    }
+    pub fn compact_tree(&self) -> bool {
+        match self {
+            SettingsState::Loaded(inner) => inner.settings.compact_tree,
+            _ => false,
+        }
+    }
+    pub fn color_rules(&self) -> &[ColorRule] {
+        match self {
+            SettingsState::Loaded(inner) => &inner.settings.color_rules,
+            _ => &[],
+        }
+    }
+    pub fn child_aggregates(&self) -> &[ChildAggregate] {
+        match self {
+            SettingsState::Loaded(inner) => &inner.settings.child_aggregates,
+            _ => &[],
+        }
+    }
+    pub fn expand_depth(&self) -> u32 {
+        match self {
+            SettingsState::Loaded(inner) => inner.settings.expand_depth,
+            _ => 0,
+        }
+    }
+    pub fn strip_ansi(&self) -> bool {
+        match self {
+            SettingsState::Loaded(inner) => inner.settings.strip_ansi,
+            _ => true,
+        }
+    }
+    pub fn message_attr(&self) -> &str {
+        match self {
+            SettingsState::Loaded(inner) => &inner.settings.message_attr,
+            _ => "message",
+        }
+    }
+    pub fn refresh_throttle(&self) -> Duration {
+        match self {
+            SettingsState::Loaded(inner) => {
+                Duration::from_millis(inner.settings.refresh_throttle_ms)
+            }
+            _ => DEFAULT_REFRESH_WINDOW,
+        }
+    }
 }
 
 impl SettingsState {
@@ -133,6 +186,26 @@ pub struct Settings {
     pub light_text_gamma: TextGamma,
     pub dark_text_gamma: TextGamma,
     pub query_autocomplete: bool,
+    /// Command for "Open in editor". Empty means `$EDITOR`, see [crate::editor::editor_argv].
+    pub editor_command: String,
+    /// Show spans in the tree as one-line summaries, see [entrace_core::format_span_oneline].
+    pub compact_tree: bool,
+    /// Hide ANSI escapes (like colors) in displayed text, see [entrace_core::strip_ansi_escapes].
+    pub strip_ansi: bool,
+    /// The attribute shown as the message of spans, see
+    /// [entrace_core::LogProvider::header_with_message_attr].
+    pub message_attr: String,
+    /// Rules for coloring tree rows by attribute, the first matching one wins. Each one is a
+    /// `color_rule` line.
+    pub color_rules: Vec<ColorRule>,
+    /// How many levels of the tree are expanded when a trace is opened, see
+    /// [crate::tree_state::TreeState::open_to_depth]. Saved tree states take precedence.
+    pub expand_depth: u32,
+    /// Aggregates of child attributes shown on span rows. Each one is a `child_aggregate` line.
+    pub child_aggregates: Vec<ChildAggregate>,
+    /// The least time between repaints for new spans of a live trace, see
+    /// [entrace_core::remote::ThrottledRefresher].
+    pub refresh_throttle_ms: u64,
 }
 
 impl Settings {
@@ -145,6 +218,14 @@ impl Settings {
             light_text_gamma,
             dark_text_gamma,
             query_autocomplete,
+            editor_command,
+            compact_tree,
+            strip_ansi,
+            message_attr,
+            color_rules,
+            expand_depth,
+            child_aggregates,
+            refresh_throttle_ms,
         } = self;
         let theme = match theme {
             ThemePreference::Dark => "dark",
@@ -154,15 +235,28 @@ impl Settings {
         let self_tracing = self_tracing.repr_first_low();
         let (light_text_gamma, dark_text_gamma) =
             (light_text_gamma.to_ini(), dark_text_gamma.to_ini());
-        format!(
+        let mut ini = format!(
             "ui_scale = {ui_scale:.1}
 self_tracing = \"{self_tracing}\"
 save_self_trace = {save_self_trace}
 theme = \"{theme}\"
 light_text_gamma = {light_text_gamma}
 dark_text_gamma = {dark_text_gamma}
-query_autocomplete = {query_autocomplete}"
-        )
+query_autocomplete = {query_autocomplete}
+editor_command = \"{editor_command}\"
+compact_tree = {compact_tree}
+strip_ansi = {strip_ansi}
+message_attr = \"{message_attr}\"
+expand_depth = {expand_depth}
+refresh_throttle_ms = {refresh_throttle_ms}"
+        );
+        for rule in color_rules {
+            write!(ini, "\ncolor_rule = \"{rule}\"").ok();
+        }
+        for aggregate in child_aggregates {
+            write!(ini, "\nchild_aggregate = \"{aggregate}\"").ok();
+        }
+        ini
     }
 }
 impl Default for Settings {
@@ -175,6 +269,14 @@ impl Default for Settings {
             light_text_gamma: TextGamma::Gamma(1.0),
             dark_text_gamma: TextGamma::DarkSpecial,
             query_autocomplete: true,
+            editor_command: String::new(),
+            compact_tree: false,
+            strip_ansi: true,
+            message_attr: "message".into(),
+            color_rules: vec![],
+            expand_depth: 0,
+            child_aggregates: vec![],
+            refresh_throttle_ms: DEFAULT_REFRESH_WINDOW.as_millis() as u64,
         }
     }
 }
@@ -239,6 +341,12 @@ pub fn get_settings_path() -> Result<PathBuf, LoadSettingsError> {
     Ok(pb)
 }
 
+/// Get the path of the query prelude, a Lua file next to the settings file which is run before
+/// every query. See [ReusableLua::set_prelude](entrace_query::lua_api::ReusableLua::set_prelude).
+pub fn get_prelude_path() -> Result<PathBuf, LoadSettingsError> {
+    Ok(get_settings_path()?.with_file_name("prelude.lua"))
+}
+
 /// Recursively create the settings directory and write the default settings to the config file
 pub fn ensure_settings_exist(path: impl AsRef<Path>) -> Result<(), LoadSettingsError> {
     let basedir = path.as_ref().parent().unwrap();
@@ -367,6 +475,63 @@ pub fn parse_line(line: &str, settings: &mut Settings) -> Result<(), LoadSetting
                 .map_err(|x| BadValue { value: value.into(), inner: Box::new(x) })?;
             settings.query_autocomplete = parsed;
         }
+        "editor_command" => {
+            // the command itself may contain `=`, so take everything after the first one
+            let value = line.split_once('=').ok_or(NoValue)?.1.trim();
+            let value = expect_tag("\"")(value)?;
+            let value = value.strip_suffix('"').ok_or_else(|| BadTag("\"".into(), "".into()))?;
+            settings.editor_command = value.into();
+        }
+        "compact_tree" => {
+            let value = splits.next().ok_or(NoValue)?.trim();
+            let parsed = str::parse::<bool>(value)
+                .map_err(|x| BadValue { value: value.into(), inner: Box::new(x) })?;
+            settings.compact_tree = parsed;
+        }
+        "strip_ansi" => {
+            let value = splits.next().ok_or(NoValue)?.trim();
+            let parsed = str::parse::<bool>(value)
+                .map_err(|x| BadValue { value: value.into(), inner: Box::new(x) })?;
+            settings.strip_ansi = parsed;
+        }
+        "message_attr" => {
+            let value = splits.next().ok_or(NoValue)?.trim();
+            let value = expect_tag("\"")(value)?;
+            let value = value.strip_suffix('"').ok_or_else(|| BadTag("\"".into(), "".into()))?;
+            settings.message_attr = value.into();
+        }
+        "expand_depth" => {
+            let value = splits.next().ok_or(NoValue)?.trim();
+            let parsed = str::parse::<u32>(value)
+                .map_err(|x| BadValue { value: value.into(), inner: Box::new(x) })?;
+            settings.expand_depth = parsed;
+        }
+        "refresh_throttle_ms" => {
+            let value = splits.next().ok_or(NoValue)?.trim();
+            let parsed = str::parse::<u64>(value)
+                .map_err(|x| BadValue { value: value.into(), inner: Box::new(x) })?;
+            settings.refresh_throttle_ms = parsed;
+        }
+        "color_rule" => {
+            // the rule itself may contain `=`, so take everything after the first one
+            let value = line.split_once('=').ok_or(NoValue)?.1.trim();
+            let value = expect_tag("\"")(value)?;
+            let value = value.strip_suffix('"').ok_or_else(|| BadTag("\"".into(), "".into()))?;
+            let rule = value
+                .parse::<ColorRule>()
+                .map_err(|x| BadValue { value: value.into(), inner: Box::new(x) })?;
+            settings.color_rules.push(rule);
+        }
+        "child_aggregate" => {
+            // like color rules, attribute names may contain `=`
+            let value = line.split_once('=').ok_or(NoValue)?.1.trim();
+            let value = expect_tag("\"")(value)?;
+            let value = value.strip_suffix('"').ok_or_else(|| BadTag("\"".into(), "".into()))?;
+            let aggregate = value
+                .parse::<ChildAggregate>()
+                .map_err(|x| BadValue { value: value.into(), inner: Box::new(x) })?;
+            settings.child_aggregates.push(aggregate);
+        }
 
         x => return Err(UnknownKey(x.into())),
     }
@@ -448,17 +613,13 @@ pub fn apply_settings(ctx: &Context, app: &mut App) {
                     );
                 }
             }
-            SelfTracingState::Enabled(ref tracing_inner) => {
-                info!(
-                    "Want to change tracing level from {} to {}",
-                    tracing_inner.level, inner.settings.self_tracing
-                );
-                if inner.settings.self_tracing != tracing_inner.level {
-                    app.notifier.info(
-                        "The self-tracing level changed.\nChanging to a different level while \
-                         running is not possible,\nso your change will only apply when you \
-                         restart entrace.",
-                    );
+            SelfTracingState::Enabled(ref mut tracing_inner) => {
+                let level = &inner.settings.self_tracing;
+                if *level != tracing_inner.level {
+                    info!("Changing self-tracing level from {} to {level}", tracing_inner.level);
+                    if let Err(x) = tracing_inner.set_level(level.clone()) {
+                        app.notifier.error_chain(&x);
+                    }
                 }
             }
         }
@@ -604,6 +765,90 @@ fn settings_dialog_inner(ui: &mut Ui, app: &mut App) {
         });
     });
     ui.checkbox(&mut settings_clone.query_autocomplete, "Autocomplete in query box");
+    ui.checkbox(&mut settings_clone.compact_tree, "Compact tree")
+        .on_hover_text("Show every span in the tree as a one-line summary with its attributes.");
+    ui.checkbox(&mut settings_clone.strip_ansi, "Hide ANSI escapes").on_hover_text(
+        "Hide the escape codes of pre-colored log messages. The trace itself is not changed.",
+    );
+    ui.horizontal(|ui| {
+        ui.label("Expand on open: ");
+        ui.add(DragValue::new(&mut settings_clone.expand_depth).range(0..=32).suffix(" levels"))
+            .on_hover_text(
+                "How many levels of the tree are expanded when a trace is opened for the first \
+                 time. Reopened traces keep their saved view.",
+            );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Live repaint interval: ");
+        ui.add(
+            DragValue::new(&mut settings_clone.refresh_throttle_ms).range(0..=1000).suffix(" ms"),
+        )
+        .on_hover_text(
+            "The least time between repaints when new spans arrive. Higher values use less CPU \
+                 on fast streams. Applies to traces opened afterwards.",
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Message attribute: ");
+        ui.add(egui::TextEdit::singleline(&mut settings_clone.message_attr).hint_text("message"))
+            .on_hover_text(
+                "The attribute shown as the text of a span, for traces which log it under another \
+                 name, like `msg`. Spans without it show their message as usual.",
+            );
+    });
+    ui.label("Row colors:").on_hover_text(
+        "Add rules with `color_rule` lines in the configuration file, like\n\
+         color_rule = \"status_code 500..600 red\"",
+    );
+    left_stroke_frame(ui, |ui| {
+        if settings_clone.color_rules.is_empty() {
+            ui.weak("No rules, rows are colored by level");
+        }
+        let mut remove = None;
+        for (idx, rule) in settings_clone.color_rules.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let swatch = RichText::new("    ").background_color(rule.color);
+                ui.label(swatch);
+                ui.code(rule.to_string());
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(idx);
+                }
+            });
+        }
+        if let Some(idx) = remove {
+            settings_clone.color_rules.remove(idx);
+        }
+    });
+    ui.label("Child aggregates:").on_hover_text(
+        "Add aggregates with `child_aggregate` lines in the configuration file, like\n\
+         child_aggregate = \"latency_ms avg\"\nThe operations are sum, avg, min, max and count.",
+    );
+    left_stroke_frame(ui, |ui| {
+        if settings_clone.child_aggregates.is_empty() {
+            ui.weak("None, span rows only show their text");
+        }
+        let mut remove = None;
+        for (idx, aggregate) in settings_clone.child_aggregates.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.code(aggregate.to_string());
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(idx);
+                }
+            });
+        }
+        if let Some(idx) = remove {
+            settings_clone.child_aggregates.remove(idx);
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Editor command: ");
+        ui.add(egui::TextEdit::singleline(&mut settings_clone.editor_command).hint_text("$EDITOR"))
+            .on_hover_text(
+                "Used by \"Open in editor\" in the span context menu. {file} and {line} are \
+                 replaced by the location of the span, for example `code -g {file}:{line}`.\nIf \
+                 empty, `$EDITOR +{line} {file}` is used.",
+            );
+    });
     ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
         if ui.button("Save").clicked()
             && let Err(x) = write_settings(settings_clone).context("Failed to write settings")
@@ -631,11 +876,15 @@ fn settings_dialog_inner(ui: &mut Ui, app: &mut App) {
     }
     if app.frame_time_tracker.is_some() {
         ui.checkbox(&mut app.ephemeral_settings.fps_in_menu, "FPS in menu");
+        ui.checkbox(&mut app.ephemeral_settings.continuous_repaint, "Repaint continuously")
+            .on_hover_text(
+                "Otherwise frames are only drawn on input or new data, so idle FPS is low",
+            );
     }
     if let Some(avg_time_us) = app.frame_time_tracker.get_average_us() {
         ui.horizontal(|ui| {
             ui.label("Average frame time:");
-            ui.label(us_to_human(avg_time_us));
+            ui.label(us_to_human(avg_time_us.into()));
         });
         ui.horizontal(|ui| {
             ui.label("Calculated FPS: ");
@@ -662,7 +911,7 @@ fn settings_dialog_inner(ui: &mut Ui, app: &mut App) {
             {
                 ui.horizontal(|ui| {
                     ui.label(format!("Average {} time: ", benchmark.name));
-                    ui.label(us_to_human_u64(avg_time));
+                    ui.label(us_to_human(avg_time));
                 });
             }
         }
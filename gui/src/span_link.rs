@@ -0,0 +1,247 @@
+//! Links to a span, like `entrace://localhost:8000/42#a1b2c3d4e5f60718`, to share a span with
+//! someone looking at the same trace.
+//!
+//! A link has the source of the trace (its file path or connection URL), the span id, and
+//! optionally the [note_fingerprint] of the span. Span ids don't change as a trace grows, so a
+//! link to a live stream stays valid. The fingerprint catches a file which was replaced by another
+//! trace.
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use egui::{Key, TextEdit};
+use entrace_core::LogProvider;
+
+use crate::{App, LogStatus, annotations::note_fingerprint, search::LocatingState};
+
+pub const SPAN_LINK_SCHEME: &str = "entrace://";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanLink {
+    /// See [link_source].
+    pub source: String,
+    pub id: u32,
+    pub fingerprint: Option<u64>,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SpanLinkError {
+    #[error("Not a span link, expected {SPAN_LINK_SCHEME}<source>/<span id>")]
+    Malformed,
+    #[error("The link is for {0}, not the open trace")]
+    OtherSource(String),
+    #[error("Span {0} is not in the trace (yet)")]
+    NoSpan(u32),
+    #[error("Span {0} is another span than the one linked, the trace has changed")]
+    Changed(u32),
+}
+
+/// The source of a trace, as written in links: the path of a file, made absolute so links work
+/// from any directory, or the address of a connection.
+pub fn link_source(path: &Path, from_file: bool) -> String {
+    match from_file.then(|| std::fs::canonicalize(path).ok()).flatten() {
+        Some(absolute) => absolute.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+impl SpanLink {
+    /// A link to span `id` of `log`, opened from `source`.
+    pub fn new(source: String, log: &dyn LogProvider, id: u32) -> Self {
+        Self { source, id, fingerprint: note_fingerprint(log, id) }
+    }
+    /// The span this link points to in `log`, opened from `source`.
+    pub fn resolve(&self, source: &str, log: &dyn LogProvider) -> Result<u32, SpanLinkError> {
+        if self.source != source {
+            return Err(SpanLinkError::OtherSource(self.source.clone()));
+        }
+        if self.id as usize >= log.len() {
+            return Err(SpanLinkError::NoSpan(self.id));
+        }
+        match self.fingerprint {
+            Some(x) if note_fingerprint(log, self.id) != Some(x) => {
+                Err(SpanLinkError::Changed(self.id))
+            }
+            _ => Ok(self.id),
+        }
+    }
+}
+
+impl Display for SpanLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(SPAN_LINK_SCHEME)?;
+        // `/` stays, as the id is after the last one
+        for c in self.source.chars() {
+            match c {
+                '%' | '#' | ' ' => write!(f, "%{:02X}", c as u32)?,
+                c if c.is_control() && c.is_ascii() => write!(f, "%{:02X}", c as u32)?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        write!(f, "/{}", self.id)?;
+        if let Some(x) = self.fingerprint {
+            write!(f, "#{x:016x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SpanLink {
+    type Err = SpanLinkError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s.trim().strip_prefix(SPAN_LINK_SCHEME).ok_or(SpanLinkError::Malformed)?;
+        let (rest, fingerprint) = match rest.rsplit_once('#') {
+            Some((rest, x)) => {
+                let x = u64::from_str_radix(x, 16).map_err(|_| SpanLinkError::Malformed)?;
+                (rest, Some(x))
+            }
+            None => (rest, None),
+        };
+        let (source, id) = rest.rsplit_once('/').ok_or(SpanLinkError::Malformed)?;
+        let id = id.parse().map_err(|_| SpanLinkError::Malformed)?;
+        let source = percent_decode(source).ok_or(SpanLinkError::Malformed)?;
+        if source.is_empty() {
+            return Err(SpanLinkError::Malformed);
+        }
+        Ok(Self { source, id, fingerprint })
+    }
+}
+
+#[derive(Default)]
+pub struct GoToLinkState {
+    pub open: bool,
+    pub text: String,
+    /// Why the last link didn't resolve.
+    pub error: Option<String>,
+}
+
+/// A window to paste a [SpanLink] into, which locates its span in the tree.
+pub fn go_to_link_dialog(ctx: &egui::Context, app: &mut App) {
+    let dialog = &mut app.go_to_link;
+    let LogStatus::Ready(ref mut state) = app.log_status else {
+        dialog.open = false;
+        return;
+    };
+    if !dialog.open {
+        return;
+    }
+    let mut open = true;
+    let mut go = false;
+    egui::Window::new("Go to span link").open(&mut open).collapsible(false).resizable(false).show(
+        ctx,
+        |ui| {
+            let hint = format!("{SPAN_LINK_SCHEME}<source>/<span id>");
+            let edit = TextEdit::singleline(&mut dialog.text).hint_text(hint).desired_width(360.0);
+            let edit = ui.add(edit);
+            let enter = edit.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            if let Some(error) = &dialog.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            go = ui.button("Go").clicked() || enter;
+        },
+    );
+    if go {
+        let resolved = dialog.text.parse::<SpanLink>().and_then(|link| {
+            link.resolve(&state.link_source, &*state.trace_provider.read().unwrap())
+        });
+        match resolved {
+            Ok(id) => {
+                // the span may be after the point in time shown
+                if state.time_travel.is_some_and(|x| id > x) {
+                    state.time_travel = None;
+                    state.tree_view.invalidate();
+                }
+                *state.locating_state.borrow_mut() =
+                    LocatingState::start_locating(id, &state.trace_provider, ctx.clone());
+                dialog.error = None;
+                open = false;
+            }
+            Err(e) => dialog.error = Some(e.to_string()),
+        }
+    }
+    dialog.open = open;
+}
+
+/// Undo the escapes of [SpanLink::fmt], which are only ever of ASCII characters.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut parts = s.split('%');
+    out.push_str(parts.next()?);
+    for part in parts {
+        let hex = part.get(..2).filter(|x| x.bytes().all(|b| b.is_ascii_hexdigit()))?;
+        let code = u8::from_str_radix(hex, 16).ok().filter(|x| x.is_ascii())?;
+        out.push(code as char);
+        out.push_str(&part[2..]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use entrace_core::test_util::LogProviderBuilder;
+
+    use super::{SpanLink, SpanLinkError};
+
+    #[test]
+    fn test_round_trip() {
+        let link =
+            |source: &str, fingerprint| SpanLink { source: source.into(), id: 42, fingerprint };
+        for link in [
+            link("localhost:8000", None),
+            link("/home/me/traces/run 1#2 (100%).iet", Some(0xa1b2)),
+            link("C:\\traces\\ünïcode.iet", Some(u64::MAX)),
+        ] {
+            let text = link.to_string();
+            assert!(!text.contains(' '), "{text}");
+            assert_eq!(text.parse::<SpanLink>(), Ok(link));
+        }
+        assert_eq!(link("localhost:8000", None).to_string(), "entrace://localhost:8000/42");
+        assert_eq!(
+            link("/a b.iet", Some(0xff)).to_string(),
+            "entrace:///a%20b.iet/42#00000000000000ff"
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        for text in [
+            "",
+            "localhost:8000/42",
+            "entrace://localhost:8000",
+            "entrace://localhost:8000/",
+            "entrace://localhost:8000/x",
+            "entrace:///42",
+            "entrace://a/42#nothex",
+            "entrace://a%2/42",
+            "entrace://a%C3%BC/42",
+        ] {
+            assert_eq!(text.parse::<SpanLink>(), Err(SpanLinkError::Malformed), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut builder = LogProviderBuilder::new();
+        builder.span(0, "request").message("GET /").add();
+        let target = builder.span(0, "request").message("GET /health").add();
+        let log = builder.build_base();
+
+        let link = SpanLink::new("localhost:8000".into(), &log, target);
+        let parsed: SpanLink = link.to_string().parse().unwrap();
+        assert_eq!(parsed.resolve("localhost:8000", &log), Ok(target));
+        assert_eq!(
+            parsed.resolve("localhost:9000", &log),
+            Err(SpanLinkError::OtherSource("localhost:8000".into()))
+        );
+        let later = SpanLink { id: 100, ..link.clone() };
+        assert_eq!(later.resolve("localhost:8000", &log), Err(SpanLinkError::NoSpan(100)));
+        // a link without a fingerprint only checks the id
+        let plain: SpanLink = "entrace://localhost:8000/1".parse().unwrap();
+        assert_eq!(plain.resolve("localhost:8000", &log), Ok(1));
+
+        // another trace from the same source
+        let mut builder = LogProviderBuilder::new();
+        builder.span(0, "request").message("POST /login").add();
+        builder.span(0, "request").message("POST /logout").add();
+        let other = builder.build_base();
+        assert_eq!(link.resolve("localhost:8000", &other), Err(SpanLinkError::Changed(target)));
+    }
+}
@@ -1,44 +1,131 @@
 use std::{
     cell::RefMut,
     f32::consts::PI,
+    fmt::Display,
+    hash::{DefaultHasher, Hash, Hasher},
     ops::{Deref, Range},
+    str::FromStr,
+    time::Duration,
 };
 
-use egui::{Color32, Rect, RichText, Sense, Shape, Stroke, StrokeKind, Ui, UiBuilder, pos2, vec2};
-use entrace_core::{LogProvider, MetadataRefContainer, display_error_context};
+use egui::{
+    Color32, Id, Rect, RichText, Sense, Shape, Stroke, StrokeKind, TextEdit, Ui, UiBuilder, pos2,
+    vec2,
+};
+use entrace_core::{
+    AggregateOp, LevelContainer, LogProvider, MetadataRefContainer, display_error_context,
+    export::format_span_json,
+    format_span_oneline,
+    remote::{Notify, NotifyExt},
+    span_path, strip_ansi_escapes,
+};
 use tracing::{debug, info, warn};
 
 use crate::{
-    LevelRepr, TraceReader, benchmarkers::SamplingBenchmark, enbitvec::EnBitVec, rect, row_height,
-    search::LocatingState,
+    LevelRepr,
+    annotations::Annotations,
+    benchmarkers::SamplingBenchmark,
+    editor::open_in_editor,
+    enbitvec::EnBitVec,
+    notifications::NotificationHandle,
+    rect,
+    row_color::{ColorRule, rule_color},
+    row_height,
+    search::{LocatingState, path_from_root},
+    span_link::SpanLink,
 };
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Row {
     SpanHeader(u32),
     MetaHeader(u32),
+    /// A run of `count` consecutive siblings with the same [span_fingerprint], starting at `first`.
+    /// Only used when [TreeView::collapse_duplicates] is set.
+    Duplicates {
+        first: u32,
+        count: u32,
+    },
+    /// A follows-from link of an open span to the span with this id, see
+    /// [LogProvider::follows_from].
+    FollowsFrom(u32),
+    /// A one-line event in the body of its parent, which can't be opened. Only used when
+    /// [TreeView::inline_events] is set.
+    InlineEvent(u32),
     Text(String),
     Attr(String),
     Err(String),
 }
+/// Everything needed to flatten the tree into rows. This doesn't depend on egui, so it can also
+/// be used headless.
 pub struct TreeContext<'t, 'o, 'l> {
-    pub log_reader: &'t TraceReader<'t>,
+    pub log_reader: &'t dyn LogProvider,
     pub open_reader: &'o EnBitVec,
     pub meta_open_reader: &'o EnBitVec,
+    /// Runs of duplicates that are expanded, by the id of their first span.
+    pub dup_open_reader: &'o EnBitVec,
     pub locating_state: Option<&'l mut LocatingState>,
+    /// Spans with a larger id are hidden, see [crate::log::LogState::time_travel].
+    pub max_id: u32,
 }
 pub struct TreeContextMut<'t, 'l, 'o> {
-    pub log_reader: &'t TraceReader<'t>,
+    pub log_reader: &'t dyn LogProvider,
     pub open_writer: &'o mut EnBitVec,
     pub meta_open_writer: &'o mut EnBitVec,
+    pub dup_open_writer: &'o mut EnBitVec,
     pub locating_state: Option<RefMut<'l, LocatingState>>,
+    /// See [crate::editor::editor_argv].
+    pub editor_command: &'o str,
+    /// Show [format_span_oneline] instead of the header text.
+    pub compact: bool,
+    /// Remove ANSI escapes from displayed text, see [strip_ansi_escapes].
+    pub strip_ansi: bool,
+    /// The attribute shown as the message, see [LogProvider::header_with_message_attr].
+    pub message_attr: &'o str,
+    /// Background colors by attribute, overriding the level color.
+    pub color_rules: &'o [ColorRule],
+    /// Summaries of the children shown after the text of spans which have any.
+    pub aggregates: &'o [ChildAggregate],
+    /// Notes on spans, edited in the context menu of a span.
+    pub annotations: &'o mut Annotations,
+    /// Names the trace in links to its spans, see [crate::span_link].
+    pub link_source: &'o str,
+    pub notifier: &'o NotificationHandle,
 }
 
+/// Hash of the metadata and message of a span. Consecutive siblings with the same fingerprint are
+/// grouped into one [Row::Duplicates] when [TreeView::collapse_duplicates] is set.
+pub fn span_fingerprint(log_reader: &dyn LogProvider, id: u32) -> Option<u64> {
+    hash_span(log_reader, id, DefaultHasher::new())
+}
+/// Hash the metadata and message of span `id` with `hasher`.
+pub fn hash_span(log_reader: &dyn LogProvider, id: u32, mut hasher: impl Hasher) -> Option<u64> {
+    let MetadataRefContainer { name, target, level, module_path, file, line } =
+        log_reader.meta(id).ok()?;
+    let message = log_reader.message(id).ok()?;
+    (name, target, level as u8, module_path, file, line, message).hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Pending {
+    Span(u32),
+    Event(u32),
+    Duplicates { first: u32, count: u32 },
+}
 #[derive(Debug)]
 pub struct TreeView {
     pub cache_valid: bool,
     pub rows: Vec<Row>,
     pub row_depths: Vec<u32>,
-    stack: Vec<(u32, u32)>,
+    /// Group runs of identical siblings, like repeated events of a loop, into one row each.
+    pub collapse_duplicates: bool,
+    /// Show the events of an open span as single lines in its body, instead of rows which can be
+    /// opened. See [LogProvider::is_event].
+    pub inline_events: bool,
+    /// The first row shown by the last [tree_view], see [TreeView::breadcrumb].
+    pub top_row: usize,
+    stack: Vec<(Pending, u32)>,
+    /// Reused when grouping children, see [TreeView::push_grouped_children].
+    children_buf: Vec<(Pending, u32)>,
 }
 impl Default for TreeView {
     fn default() -> Self {
@@ -47,25 +134,100 @@ impl Default for TreeView {
 }
 impl TreeView {
     pub fn new() -> Self {
-        Self { rows: vec![], row_depths: vec![], stack: vec![], cache_valid: false }
+        Self {
+            rows: vec![],
+            row_depths: vec![],
+            collapse_duplicates: false,
+            inline_events: false,
+            top_row: 0,
+            stack: vec![],
+            children_buf: vec![],
+            cache_valid: false,
+        }
     }
     pub fn invalidate(&mut self) {
         self.cache_valid = false;
     }
+    pub fn set_collapse_duplicates(&mut self, collapse: bool) {
+        if self.collapse_duplicates != collapse {
+            self.collapse_duplicates = collapse;
+            self.invalidate();
+        }
+    }
+    pub fn set_inline_events(&mut self, inline: bool) {
+        if self.inline_events != inline {
+            self.inline_events = inline;
+            self.invalidate();
+        }
+    }
+    /// How child `id` is shown: inline if it is an event and [TreeView::inline_events] is set.
+    fn child_pending(log_reader: &dyn LogProvider, inline_events: bool, id: u32) -> Pending {
+        if inline_events && log_reader.is_event(id).unwrap_or(false) {
+            Pending::Event(id)
+        } else {
+            Pending::Span(id)
+        }
+    }
+    /// The flattened rows from the last update, in display order, with their depths.
+    pub fn visible_rows(&self) -> impl Iterator<Item = (&Row, u32)> {
+        self.rows.iter().zip(self.row_depths.iter().copied())
+    }
+    /// The span row `row` belongs to: the span of a header, or the span whose body holds the row.
+    pub fn row_span(&self, row: usize) -> Option<u32> {
+        let depth = *self.row_depths.get(row)?;
+        match self.rows[row] {
+            Row::SpanHeader(id) | Row::MetaHeader(id) | Row::InlineEvent(id) => return Some(id),
+            Row::Duplicates { first, .. } => return Some(first),
+            _ => (),
+        }
+        // the body of a span is one level deeper than its header. Its metadata is shown another
+        // level deeper, under a MetaHeader at the depth of the body.
+        let earlier = self.rows[..row].iter().zip(&self.row_depths[..row]).rev();
+        earlier.filter(|(_, d)| **d < depth).find_map(|(row, _)| match row {
+            Row::SpanHeader(id) | Row::MetaHeader(id) | Row::InlineEvent(id) => Some(*id),
+            Row::Duplicates { first, .. } => Some(*first),
+            _ => None,
+        })
+    }
+    /// The ids from the root to the span of [TreeView::top_row], both included. Empty before the
+    /// first update.
+    pub fn breadcrumb(&self, log_reader: &dyn LogProvider) -> Vec<u32> {
+        let top_row = self.top_row.min(self.rows.len().saturating_sub(1));
+        match self.row_span(top_row) {
+            Some(id) => path_from_root(log_reader, id),
+            None => vec![],
+        }
+    }
     pub fn get_tree_non_cached<'t, 'o, 'l, Q: Iterator<Item = u32>>(
         &mut self, initial_spans: Q, ctx: TreeContext<'t, 'o, 'l>,
     ) {
         self.stack.clear();
         self.rows.clear();
         self.row_depths.clear();
-        self.stack.extend(initial_spans.map(|x| (x, 0)));
-        while let Some((this, depth)) = self.stack.pop() {
+        let max_id = ctx.max_id;
+        self.stack.extend(initial_spans.filter(|x| *x <= max_id).map(|x| (Pending::Span(x), 0)));
+        let locate_target = match ctx.locating_state {
+            Some(LocatingState::ScrollTo { target, .. }) => Some(*target),
+            _ => None,
+        };
+        while let Some((pending, depth)) = self.stack.pop() {
+            let this = match pending {
+                Pending::Span(this) | Pending::Event(this) => this,
+                Pending::Duplicates { first, count } => {
+                    self.add_row(Row::Duplicates { first, count }, depth);
+                    continue;
+                }
+            };
             if let Some(LocatingState::ScrollTo { target, target_row_offset, .. }) =
                 ctx.locating_state
                 && this == *target
             {
                 *target_row_offset = Some(self.rows.len());
             }
+            if let Pending::Event(_) = pending {
+                self.add_row(Row::InlineEvent(this), depth);
+                continue;
+            }
             self.add_span(ctx.log_reader, &ctx.open_reader, &ctx.meta_open_reader, this, depth);
             let open = ctx.open_reader.get(this as usize).unwrap_or(false);
             if open {
@@ -76,16 +238,67 @@ impl TreeView {
                         continue;
                     }
                 };
+                // ids are handed out in order, so children are sorted by id
+                let children = &children[..children.partition_point(|x| *x <= max_id)];
+                if self.collapse_duplicates {
+                    let (log_reader, dup_open) = (ctx.log_reader, ctx.dup_open_reader);
+                    self.push_grouped_children(
+                        log_reader,
+                        dup_open,
+                        children,
+                        depth,
+                        locate_target,
+                    );
+                    continue;
+                }
+                let (log_reader, inline) = (ctx.log_reader, self.inline_events);
                 let children_it = children
                     .iter()
                     .rev()
-                    .copied()
+                    .map(|x| Self::child_pending(log_reader, inline, *x))
                     .zip(std::iter::repeat_n(depth + 1, children.len()));
                 self.stack.extend(children_it);
             }
         }
     }
 
+    /// Push `children` to the stack, grouping runs of equal [span_fingerprint]s.
+    /// The spans of an expanded run are shown one level deeper, under the run's row. A run holding
+    /// `locate_target` is expanded, so it can be scrolled to.
+    fn push_grouped_children(
+        &mut self, log_reader: &dyn LogProvider, dup_open_reader: &EnBitVec, children: &[u32],
+        depth: u32, locate_target: Option<u32>,
+    ) {
+        self.children_buf.clear();
+        let inline_events = self.inline_events;
+        let mut start = 0;
+        while start < children.len() {
+            let fingerprint = span_fingerprint(log_reader, children[start]);
+            let mut end = start + 1;
+            if fingerprint.is_some() {
+                while end < children.len()
+                    && span_fingerprint(log_reader, children[end]) == fingerprint
+                {
+                    end += 1;
+                }
+            }
+            let run = &children[start..end];
+            let pending = |x| Self::child_pending(log_reader, inline_events, x);
+            if run.len() == 1 {
+                self.children_buf.push((pending(run[0]), depth + 1));
+            } else {
+                let (first, count) = (run[0], run.len() as u32);
+                self.children_buf.push((Pending::Duplicates { first, count }, depth + 1));
+                let has_target = locate_target.is_some_and(|x| run.contains(&x));
+                if has_target || dup_open_reader.get(first as usize).unwrap_or(false) {
+                    self.children_buf.extend(run.iter().map(|x| (pending(*x), depth + 2)));
+                }
+            }
+            start = end;
+        }
+        self.stack.extend(self.children_buf.drain(..).rev());
+    }
+
     pub fn update_tree<'t, 'o, 'l, Q: Iterator<Item = u32>, const N: u8>(
         &mut self, benchmark: Option<&mut SamplingBenchmark<N>>, initial_spans: Q,
         ctx: TreeContext<'t, 'o, 'l>,
@@ -136,7 +349,7 @@ impl TreeView {
     }
 
     pub fn add_span(
-        &mut self, log_reader: &TraceReader, open_reader: &impl Deref<Target = EnBitVec>,
+        &mut self, log_reader: &dyn LogProvider, open_reader: &impl Deref<Target = EnBitVec>,
         meta_open_reader: &impl Deref<Target = EnBitVec>, id: u32, span_depth: u32,
     ) {
         self.add_row(Row::SpanHeader(id), span_depth);
@@ -152,6 +365,14 @@ impl TreeView {
                 }
                 Err(y) => self.add_multiline(y.to_string(), span_depth + 1, Self::add_err),
             }
+            match log_reader.follows_from(id) {
+                Ok(links) => {
+                    for target in links {
+                        self.add_row(Row::FollowsFrom(target), span_depth + 1);
+                    }
+                }
+                Err(y) => self.add_multiline(y.to_string(), span_depth + 1, Self::add_err),
+            }
             self.add_row(Row::MetaHeader(id), span_depth + 1);
             if meta_open_reader.get(id as usize).unwrap_or(false) {
                 let m_depth = span_depth + 2;
@@ -181,6 +402,11 @@ pub fn tree_view<'t, 'o, 'l>(
     if tree.rows.is_empty() {
         return;
     }
+    if tree.top_row != row_range.start {
+        tree.top_row = row_range.start;
+        // the breadcrumb bar was drawn before this, with the old top row
+        ui.ctx().request_repaint();
+    }
     if let Some(LocatingState::ScrollTo { target_row_offset, .. }) = ctx.locating_state.as_deref() {
         let row_height = row_height(ui);
         if let Some(target_row_offset) = target_row_offset {
@@ -240,18 +466,8 @@ fn render_row<'t, 'o, 'l>(
             };
 
             let level_repr = header.level.repr(ui.ctx().theme());
-            let header_text_orig = if let Some(message) = header.message {
-                format!("{}: {}", level_repr.0, message)
-            } else if *id == 0 {
-                "root".to_string()
-            } else {
-                header.name.into()
-            };
-            let header_text = if let Some(nl) = memchr::memchr(b'\n', header_text_orig.as_bytes()) {
-                format!("{}...", &header_text_orig[..nl])
-            } else {
-                header_text_orig.clone()
-            };
+            let header_text = row_text(ctx, *id);
+            let background = row_background(ctx, *id).unwrap_or(level_repr.1);
 
             let is_open = ctx.open_writer.get(*id as usize).unwrap_or(false);
             let size = vec2(ui.spacing().icon_width, ui.spacing().icon_width);
@@ -259,13 +475,21 @@ fn render_row<'t, 'o, 'l>(
                 let available_rect = ui.available_rect_before_wrap();
                 let (_icon_id, icon_rect) = ui.allocate_space(size);
                 let ui_header =
-                    egui::Label::new(RichText::new(header_text).background_color(level_repr.1))
+                    egui::Label::new(RichText::new(header_text).background_color(background))
                         .sense(Sense::hover());
                 let label_resp = ui.add(ui_header);
+                let aggregates = aggregates_text(ctx, *id);
+                if !aggregates.is_empty() {
+                    ui.weak(aggregates);
+                }
                 let interact_id = ui.id().with(id);
                 let interact_rect =
                     label_resp.rect.with_min_x(0.0).with_max_x(available_rect.max.x);
                 let interact = ui.interact(interact_rect, interact_id, Sense::click());
+                // after the row, so that it gets the hover
+                if let Some(note) = ctx.annotations.get(ctx.log_reader, *id) {
+                    ui.label("📝").on_hover_text(note);
+                }
                 if interact.clicked() {
                     ctx.open_writer.toggle(*id as usize);
                     *invalidate = true;
@@ -273,47 +497,44 @@ fn render_row<'t, 'o, 'l>(
                 if interact.clicked_by(egui::PointerButton::Secondary) {
                     info!(span_id = id, interact_rect=%interact_rect, "Right clicked");
                 }
+                interact.context_menu(|ui| span_context_menu(ui, *id, ctx));
                 let visuals = ui.style().interact(&interact);
-                // adapted from `egui::containers::collapsing_header::paint_default_icon`
-                let rect = Rect::from_center_size(icon_rect.center(), size * 0.5);
-                let rect = rect.expand(visuals.expansion);
-                let mut points = vec![rect.left_top(), rect.right_top(), rect.center_bottom()];
-                if !is_open {
-                    let rotation = egui::emath::Rot2::from_angle(PI * 1.5);
-                    for p in &mut points {
-                        *p = rect.center() + rotation * (*p - rect.center());
-                    }
-                }
-                ui.painter().add(Shape::convex_polygon(
-                    points,
-                    visuals.fg_stroke.color,
-                    Stroke::NONE,
-                ));
-                if let Some(LocatingState::Highlight(target)) = ctx.locating_state.as_deref()
-                    && target == id
-                {
-                    let highlight_rect_min = icon_rect.min - ui.spacing().item_spacing;
-                    let highlight_rect_max = label_resp.rect.max + ui.spacing().item_spacing;
-                    let highlight_rect = rect!(highlight_rect_min, highlight_rect_max);
-                    let interact = ui.style().noninteractive();
-                    let (radius, bg_fill, bg_stroke) =
-                        (interact.corner_radius, interact.bg_fill, interact.bg_stroke);
-                    ui.painter().rect_filled(highlight_rect, radius, bg_fill.gamma_multiply_u8(30));
-                    ui.painter().rect_stroke(highlight_rect, radius, bg_stroke, StrokeKind::Middle);
-                }
+                paint_open_icon(ui, icon_rect, size, visuals, is_open);
+                mark_located(ui, ctx, *id, icon_rect.union(label_resp.rect));
 
                 // hover effect
                 if interact.hovered() {
                     ui.painter().rect_filled(interact_rect, 0, Color32::GRAY.gamma_multiply_u8(24));
                 }
-                if let Some(ref mut state) = ctx.locating_state
-                    && let LocatingState::ScrollTo { target, .. } = &**state
-                    && *target == *id
-                {
-                    ui.scroll_to_rect(label_resp.rect, None);
-                    info!(target, rect = %label_resp.rect, "Reached target");
-                    **state = LocatingState::Highlight(*id);
-                };
+            });
+        }
+        Row::InlineEvent(id) => {
+            let level_color = match ctx.log_reader.header(*id) {
+                Ok(header) => header.level.repr(ui.ctx().theme()).1,
+                Err(_) => Color32::TRANSPARENT,
+            };
+            let background = row_background(ctx, *id).unwrap_or(level_color);
+            let text = summary_text(ctx, *id, true);
+            ui.horizontal(|ui| {
+                let available_rect = ui.available_rect_before_wrap();
+                // no icon, since it can't be opened, but aligned with the headers of its siblings
+                let size = vec2(ui.spacing().icon_width, ui.spacing().icon_width);
+                let (_icon_id, icon_rect) = ui.allocate_space(size);
+                let label = egui::Label::new(RichText::new(text).background_color(background))
+                    .sense(Sense::hover());
+                let label_resp = ui.add(label);
+                let interact_id = ui.id().with("inline_event").with(id);
+                let interact_rect =
+                    label_resp.rect.with_min_x(0.0).with_max_x(available_rect.max.x);
+                let interact = ui.interact(interact_rect, interact_id, Sense::click());
+                if let Some(note) = ctx.annotations.get(ctx.log_reader, *id) {
+                    ui.label("📝").on_hover_text(note);
+                }
+                interact.context_menu(|ui| span_context_menu(ui, *id, ctx));
+                mark_located(ui, ctx, *id, icon_rect.union(label_resp.rect));
+                if interact.hovered() {
+                    ui.painter().rect_filled(interact_rect, 0, Color32::GRAY.gamma_multiply_u8(24));
+                }
             });
         }
         Row::MetaHeader(id) => {
@@ -334,28 +555,52 @@ fn render_row<'t, 'o, 'l>(
                     *invalidate = true;
                 }
                 let visuals = ui.style().interact(&interact);
-
-                // adapted from `egui::containers::collapsing_header::paint_default_icon`
-                let rect = Rect::from_center_size(icon_rect.center(), i_size * 0.5);
-                let rect = rect.expand(visuals.expansion);
-                let mut points = vec![rect.left_top(), rect.right_top(), rect.center_bottom()];
-                if !is_open {
-                    let rotation = egui::emath::Rot2::from_angle(PI * 1.5);
-                    for p in &mut points {
-                        *p = rect.center() + rotation * (*p - rect.center());
-                    }
+                paint_open_icon(ui, icon_rect, i_size, visuals, is_open);
+                if interact.hovered() {
+                    ui.painter().rect_filled(interact_rect, 0, Color32::GRAY.gamma_multiply_u8(24));
+                }
+            });
+        }
+        Row::Duplicates { first, count } => {
+            let is_open = ctx.dup_open_writer.get(*first as usize).unwrap_or(false);
+            let header_text = row_text(ctx, *first);
+            ui.horizontal(|ui| {
+                let i_size = vec2(ui.spacing().icon_width, ui.spacing().icon_width);
+                let available_rect = ui.available_rect_before_wrap();
+                let (_icon_id, icon_rect) = ui.allocate_space(i_size);
+                let label = RichText::new(format!("×{count} {header_text}")).italics();
+                let label_resp = ui.add(egui::Label::new(label).sense(Sense::hover()));
+                let interact_id = ui.id().with("dup_toggle").with(first);
+                let interact_rect =
+                    label_resp.rect.with_min_x(0.0).with_max_x(available_rect.max.x);
+                let interact = ui.interact(interact_rect, interact_id, Sense::click());
+                if interact.clicked() {
+                    ctx.dup_open_writer.toggle(*first as usize);
+                    *invalidate = true;
                 }
-                ui.painter().add(Shape::convex_polygon(
-                    points,
-                    visuals.fg_stroke.color,
-                    Stroke::NONE,
-                ));
+                let visuals = ui.style().interact(&interact);
+                paint_open_icon(ui, icon_rect, i_size, visuals, is_open);
                 if interact.hovered() {
                     ui.painter().rect_filled(interact_rect, 0, Color32::GRAY.gamma_multiply_u8(24));
                 }
             });
         }
+        Row::FollowsFrom(target) => {
+            let target_text = row_text(ctx, *target);
+            ui.horizontal(|ui| {
+                ui.label("follows from");
+                let can_locate = ctx.locating_state.as_deref().is_some_and(|x| x.can_start_new());
+                let link = ui.add_enabled(can_locate, egui::Link::new(target_text));
+                if link.clicked()
+                    && let Some(ref mut state) = ctx.locating_state
+                {
+                    info!(target, "Locating follows-from target");
+                    **state = LocatingState::scroll_to(*target, ctx.log_reader);
+                }
+            });
+        }
         Row::Text(x) | Row::Attr(x) => {
+            let x = if ctx.strip_ansi { strip_ansi_escapes(x) } else { x.into() };
             ui.add(egui::Label::new(x).wrap_mode(egui::TextWrapMode::Extend));
         }
         Row::Err(x) => {
@@ -363,3 +608,745 @@ fn render_row<'t, 'o, 'l>(
         }
     }
 }
+
+/// What was clicked in a [breadcrumb_bar].
+pub enum BreadcrumbAction {
+    ScrollTo(u32),
+    Collapse(u32),
+}
+/// The ancestors of the span at the top of the tree, from the root, as a row of buttons. Clicking
+/// one scrolls to it, and its context menu can also collapse it.
+pub fn breadcrumb_bar(
+    ui: &mut Ui, log_reader: &dyn LogProvider, path: &[u32],
+) -> Option<BreadcrumbAction> {
+    let mut action = None;
+    egui::ScrollArea::horizontal().id_salt("breadcrumb").show(ui, |ui| {
+        ui.horizontal(|ui| {
+            for (idx, &id) in path.iter().enumerate() {
+                if idx > 0 {
+                    ui.weak("›");
+                }
+                let name = match id {
+                    0 => "root",
+                    _ => log_reader.meta(id).map(|x| x.name).unwrap_or("?"),
+                };
+                let button = ui.small_button(name).on_hover_text(format!("Span {id}"));
+                if button.clicked() {
+                    action = Some(BreadcrumbAction::ScrollTo(id));
+                }
+                button.context_menu(|ui| {
+                    if ui.button("Scroll to").clicked() {
+                        action = Some(BreadcrumbAction::ScrollTo(id));
+                    }
+                    if ui.button("Collapse").clicked() {
+                        action = Some(BreadcrumbAction::Collapse(id));
+                    }
+                });
+            }
+        });
+    });
+    action
+}
+
+/// Highlight the row of span `id` at `rect` if it was located, and finish scrolling to it if it is
+/// being located.
+fn mark_located(ui: &Ui, ctx: &mut TreeContextMut<'_, '_, '_>, id: u32, rect: Rect) {
+    if let Some(LocatingState::Highlight(target)) = ctx.locating_state.as_deref()
+        && *target == id
+    {
+        let highlight_rect = rect.expand2(ui.spacing().item_spacing);
+        let interact = ui.style().noninteractive();
+        let (radius, bg_fill, bg_stroke) =
+            (interact.corner_radius, interact.bg_fill, interact.bg_stroke);
+        ui.painter().rect_filled(highlight_rect, radius, bg_fill.gamma_multiply_u8(30));
+        ui.painter().rect_stroke(highlight_rect, radius, bg_stroke, StrokeKind::Middle);
+    }
+    if let Some(ref mut state) = ctx.locating_state
+        && let LocatingState::ScrollTo { target, .. } = &**state
+        && *target == id
+    {
+        ui.scroll_to_rect(rect, None);
+        info!(target, rect = %rect, "Reached target");
+        **state = LocatingState::Highlight(id);
+    };
+}
+
+/// Paint the triangle of a collapsible row.
+// adapted from `egui::containers::collapsing_header::paint_default_icon`
+fn paint_open_icon(
+    ui: &Ui, icon_rect: Rect, size: egui::Vec2, visuals: &egui::style::WidgetVisuals, is_open: bool,
+) {
+    let rect = Rect::from_center_size(icon_rect.center(), size * 0.5);
+    let rect = rect.expand(visuals.expansion);
+    let mut points = vec![rect.left_top(), rect.right_top(), rect.center_bottom()];
+    if !is_open {
+        let rotation = egui::emath::Rot2::from_angle(PI * 1.5);
+        for p in &mut points {
+            *p = rect.center() + rotation * (*p - rect.center());
+        }
+    }
+    ui.painter().add(Shape::convex_polygon(points, visuals.fg_stroke.color, Stroke::NONE));
+}
+
+/// The text of the row of span `id`: its one-line summary in compact mode, or else the first line
+/// of its header text. ANSI escapes are removed if [TreeContextMut::strip_ansi] is set.
+fn row_text(ctx: &TreeContextMut<'_, '_, '_>, id: u32) -> String {
+    summary_text(ctx, id, ctx.compact)
+}
+/// Like [row_text], but with `oneline` deciding between the one-line summary and the header text.
+fn summary_text(ctx: &TreeContextMut<'_, '_, '_>, id: u32, oneline: bool) -> String {
+    let text = if oneline {
+        format_span_oneline(ctx.log_reader, id)
+    } else {
+        let header = ctx.log_reader.header_with_message_attr(id, ctx.message_attr);
+        header.map(|header| header.display_text(id))
+    };
+    let text = match text {
+        Ok(text) => text,
+        Err(y) => return display_error_context(&y),
+    };
+    let text = match ctx.strip_ansi {
+        true => strip_ansi_escapes(&text).into_owned(),
+        false => text,
+    };
+    match memchr::memchr(b'\n', text.as_bytes()) {
+        Some(nl) => format!("{}...", &text[..nl]),
+        None => text,
+    }
+}
+/// An aggregate over an attribute of the direct children of a span, like the average of their
+/// `latency_ms`, written `latency_ms avg` in the `child_aggregate` setting. See
+/// [LogProvider::aggregate_children].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChildAggregate {
+    pub attr: String,
+    pub op: AggregateOp,
+}
+#[derive(thiserror::Error, Debug)]
+pub enum ChildAggregateError {
+    #[error("Expected `<attr> <op>`")]
+    MissingPart,
+    #[error(transparent)]
+    BadOp(#[from] entrace_core::UnknownAggregateOp),
+}
+impl FromStr for ChildAggregate {
+    type Err = ChildAggregateError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (attr, op) = s.trim().rsplit_once(' ').ok_or(ChildAggregateError::MissingPart)?;
+        let attr = attr.trim();
+        if attr.is_empty() {
+            return Err(ChildAggregateError::MissingPart);
+        }
+        Ok(ChildAggregate { attr: attr.into(), op: op.parse()? })
+    }
+}
+impl Display for ChildAggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.attr, self.op)
+    }
+}
+/// The [TreeContextMut::aggregates] of span `id`, like `avg(latency_ms)=12.50 count(latency_ms)=4`.
+/// Empty for spans without children, and aggregates without a value are left out.
+fn aggregates_text(ctx: &TreeContextMut<'_, '_, '_>, id: u32) -> String {
+    if ctx.aggregates.is_empty() || ctx.log_reader.children(id).is_ok_and(|x| x.is_empty()) {
+        return String::new();
+    }
+    let mut text = String::new();
+    for ChildAggregate { attr, op } in ctx.aggregates {
+        let Ok(Some(value)) = ctx.log_reader.aggregate_children(id, attr, *op) else { continue };
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        // counts and sums of integers stay integers
+        match value.fract() == 0.0 && value.abs() < 1e15 {
+            true => text.push_str(&format!("{op}({attr})={value:.0}")),
+            false => text.push_str(&format!("{op}({attr})={value:.2}")),
+        }
+    }
+    text
+}
+/// The color of the first of [TreeContextMut::color_rules] matching span `id`.
+fn row_background(ctx: &TreeContextMut<'_, '_, '_>, id: u32) -> Option<Color32> {
+    if ctx.color_rules.is_empty() {
+        return None;
+    }
+    let names = ctx.log_reader.attr_names(id).ok()?;
+    let values = ctx.log_reader.attr_values(id).ok()?;
+    rule_color(ctx.color_rules, &names, &values)
+}
+const SPAN_PATH_HOVER: &str = "The child indices from the root, like 0/3/1. Unlike the id, this \
+    stays the same in other recordings of the same program. Find it again with en_resolve_path";
+fn span_context_menu(ui: &mut Ui, id: u32, ctx: &mut TreeContextMut<'_, '_, '_>) {
+    let location = match ctx.log_reader.meta(id) {
+        Ok(MetadataRefContainer { file: Some(file), line: Some(line), .. }) => Some((file, line)),
+        _ => None,
+    };
+    let btn = ui.add_enabled(location.is_some(), egui::Button::new("Open in editor"));
+    let btn = btn.on_disabled_hover_text("This span has no recorded file and line");
+    if btn.clicked()
+        && let Some((file, line)) = location
+    {
+        info!(span_id = id, file, line, "Opening in editor");
+        if let Err(x) = open_in_editor(ctx.editor_command, file, line) {
+            ctx.notifier.error(format!("{x:#}"));
+        }
+        ui.close();
+    }
+    if ui.button("Copy as one line").clicked() {
+        match format_span_oneline(ctx.log_reader, id) {
+            Ok(line) => ui.ctx().copy_text(line),
+            Err(x) => ctx.notifier.error_chain(&x),
+        }
+        ui.close();
+    }
+    for (label, include_subtree) in [("Copy as JSON", false), ("Copy subtree as JSON", true)] {
+        if ui.button(label).clicked() {
+            copy_span_json(ui, id, include_subtree, ctx);
+            ui.close();
+        }
+    }
+    if ui.button("Copy span path").on_hover_text(SPAN_PATH_HOVER).clicked() {
+        match span_path(ctx.log_reader, id) {
+            Ok(Some(path)) => ui.ctx().copy_text(path),
+            Ok(None) => ctx.notifier.error("This span is not reachable from the root"),
+            Err(x) => ctx.notifier.error_chain(&x),
+        }
+        ui.close();
+    }
+    let link_hover = "A link to this span, to open with Tools > Go to span link";
+    if ui.button("Copy link").on_hover_text(link_hover).clicked() {
+        let link = SpanLink::new(ctx.link_source.to_string(), ctx.log_reader, id);
+        ui.ctx().copy_text(link.to_string());
+        ui.close();
+    }
+    ui.separator();
+    note_editor(ui, id, ctx);
+}
+
+/// Copy span `id` as JSON for a bug report, see [format_span_json]. Warns if the subtree was too
+/// large to copy fully.
+fn copy_span_json(ui: &Ui, id: u32, include_subtree: bool, ctx: &TreeContextMut<'_, '_, '_>) {
+    let output = match format_span_json(ctx.log_reader, id, include_subtree) {
+        Ok(output) => output,
+        Err(x) => return ctx.notifier.error_chain(&x),
+    };
+    if output.omitted_spans > 0 {
+        let text = format!(
+            "The subtree is large, {} spans were left out of the copy",
+            output.omitted_spans
+        );
+        ctx.notifier.add_notification(LevelContainer::Warn, text, Duration::from_secs(5));
+    }
+    ui.ctx().copy_text(output.json);
+}
+
+/// Edit the note on span `id`. The draft is kept until it is saved, even if the menu is closed.
+fn note_editor(ui: &mut Ui, id: u32, ctx: &mut TreeContextMut<'_, '_, '_>) {
+    let draft_id = Id::new(("note_draft", id));
+    let saved = ctx.annotations.get(ctx.log_reader, id);
+    let has_note = saved.is_some();
+    let mut draft = ui.data_mut(|d| d.get_temp::<String>(draft_id));
+    let draft = draft.get_or_insert_with(|| saved.unwrap_or_default().to_string());
+    ui.add(TextEdit::multiline(draft).desired_rows(2).hint_text("Note"));
+    let mut save = None;
+    ui.horizontal(|ui| {
+        if ui.button("Save note").clicked() {
+            save = Some(draft.clone());
+        }
+        if has_note && ui.button("Remove note").clicked() {
+            save = Some(String::new());
+        }
+    });
+    match save {
+        Some(text) => {
+            ui.data_mut(|d| d.remove::<String>(draft_id));
+            ctx.annotations.set(ctx.log_reader, id, &text);
+            if let Err(x) = ctx.annotations.save() {
+                ctx.notifier.error_chain(&x);
+            }
+            ui.close();
+        }
+        None => {
+            ui.data_mut(|d| d.insert_temp(draft_id, draft.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use entrace_core::{EnValue, remote::BaseIETLogProvider, test_util::LogProviderBuilder};
+
+    use entrace_core::AggregateOp;
+
+    use super::{
+        ChildAggregate, Row, TreeContext, TreeContextMut, TreeView, aggregates_text, row_text,
+        span_fingerprint,
+    };
+    use crate::{enbitvec::EnBitVec, notifications::NotificationHandle, search::LocatingState};
+
+    /// root
+    /// |- 1 (x = 1)
+    /// |  |- 2
+    /// |- 3
+    fn small_tree() -> BaseIETLogProvider {
+        let mut builder = LogProviderBuilder::new();
+        let a = builder.span(0, "a").attr("x", EnValue::U64(1)).add();
+        builder.span(a, "b").add();
+        builder.span(0, "c").add();
+        builder.build_base()
+    }
+    fn flatten(open: &[usize], meta_open: &[usize]) -> Vec<(Row, u32)> {
+        let provider = small_tree();
+        let (mut open_v, mut meta_open_v) =
+            (EnBitVec::repeat(false, 4), EnBitVec::repeat(false, 4));
+        open.iter().for_each(|x| open_v.set(*x, true));
+        meta_open.iter().for_each(|x| meta_open_v.set(*x, true));
+        let ctx = TreeContext {
+            log_reader: &provider,
+            open_reader: &open_v,
+            meta_open_reader: &meta_open_v,
+            dup_open_reader: &EnBitVec::new(),
+            locating_state: None,
+            max_id: u32::MAX,
+        };
+        let mut tree = TreeView::new();
+        tree.get_tree_non_cached(std::iter::once(0), ctx);
+        let TreeView { rows, row_depths, .. } = tree;
+        rows.into_iter().zip(row_depths).collect()
+    }
+
+    #[test]
+    fn test_all_closed() {
+        assert_eq!(flatten(&[], &[]), [(Row::SpanHeader(0), 0)]);
+    }
+
+    #[test]
+    fn test_root_open() {
+        let expected = [
+            (Row::SpanHeader(0), 0),
+            (Row::MetaHeader(0), 1),
+            (Row::SpanHeader(1), 1),
+            (Row::SpanHeader(3), 1),
+        ];
+        assert_eq!(flatten(&[0], &[]), expected);
+    }
+
+    #[test]
+    fn test_nested_open_with_meta() {
+        let text = |x: &str| Row::Text(x.into());
+        let expected = [
+            (Row::SpanHeader(0), 0),
+            (Row::MetaHeader(0), 1),
+            (Row::SpanHeader(1), 1),
+            (Row::Attr("x: 1".into()), 2),
+            (Row::MetaHeader(1), 2),
+            (text("name: a"), 3),
+            (text("target: "), 3),
+            (text("module_path: None"), 3),
+            (text("file: None"), 3),
+            (text("line: None"), 3),
+            (text("level: Trace"), 3),
+            (Row::SpanHeader(2), 2),
+            (Row::SpanHeader(3), 1),
+        ];
+        assert_eq!(flatten(&[0, 1], &[1]), expected);
+    }
+
+    #[test]
+    fn test_closed_parent_hides_open_child() {
+        // 2 is open, but it is not visible since 1 is closed
+        let expected = [
+            (Row::SpanHeader(0), 0),
+            (Row::MetaHeader(0), 1),
+            (Row::SpanHeader(1), 1),
+            (Row::SpanHeader(3), 1),
+        ];
+        assert_eq!(flatten(&[0, 2], &[2]), expected);
+    }
+
+    #[test]
+    fn test_visible_rows_matches_rows() {
+        let provider = small_tree();
+        let open = EnBitVec::repeat(true, 4);
+        let meta_open = EnBitVec::repeat(false, 4);
+        let ctx = TreeContext {
+            log_reader: &provider,
+            open_reader: &open,
+            meta_open_reader: &meta_open,
+            dup_open_reader: &EnBitVec::new(),
+            locating_state: None,
+            max_id: u32::MAX,
+        };
+        let mut tree = TreeView::new();
+        tree.get_tree_non_cached(std::iter::once(0), ctx);
+        let visible: Vec<_> = tree.visible_rows().collect();
+        assert_eq!(visible.len(), tree.rows.len());
+        for (i, (row, depth)) in visible.into_iter().enumerate() {
+            assert_eq!(row, &tree.rows[i]);
+            assert_eq!(depth, tree.row_depths[i]);
+        }
+    }
+
+    /// The rows of [test_nested_open_with_meta], with 1 and its metadata open.
+    fn nested_tree(provider: &BaseIETLogProvider) -> TreeView {
+        let open = EnBitVec::from_ones([0, 1], 4);
+        let meta_open = EnBitVec::from_ones([1], 4);
+        let ctx = TreeContext {
+            log_reader: provider,
+            open_reader: &open,
+            meta_open_reader: &meta_open,
+            dup_open_reader: &EnBitVec::new(),
+            locating_state: None,
+            max_id: u32::MAX,
+        };
+        let mut tree = TreeView::new();
+        tree.get_tree_non_cached(std::iter::once(0), ctx);
+        tree
+    }
+
+    #[test]
+    fn test_row_span() {
+        let tree = nested_tree(&small_tree());
+        let spans: Vec<_> = (0..tree.rows.len()).map(|row| tree.row_span(row).unwrap()).collect();
+        // the attribute and the metadata of 1 belong to it, even though they are deeper
+        assert_eq!(spans, [0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 3]);
+        assert_eq!(tree.row_span(tree.rows.len()), None);
+    }
+
+    #[test]
+    fn test_breadcrumb() {
+        let provider = small_tree();
+        let mut tree = nested_tree(&provider);
+        assert_eq!(tree.breadcrumb(&provider), [0]);
+        // scrolled into the metadata of 1
+        tree.top_row = 6;
+        assert_eq!(tree.breadcrumb(&provider), [0, 1]);
+        tree.top_row = 11;
+        assert_eq!(tree.breadcrumb(&provider), [0, 1, 2]);
+        tree.top_row = 12;
+        assert_eq!(tree.breadcrumb(&provider), [0, 3]);
+        // the tree shrank since it was drawn
+        tree.top_row = 100;
+        assert_eq!(tree.breadcrumb(&provider), [0, 3]);
+        assert!(TreeView::new().breadcrumb(&provider).is_empty());
+    }
+
+    #[test]
+    fn test_max_id() {
+        let provider = small_tree();
+        let headers = |max_id| {
+            let ctx = TreeContext {
+                log_reader: &provider,
+                open_reader: &EnBitVec::repeat(true, 4),
+                meta_open_reader: &EnBitVec::repeat(false, 4),
+                dup_open_reader: &EnBitVec::new(),
+                locating_state: None,
+                max_id,
+            };
+            let mut tree = TreeView::new();
+            tree.get_tree_non_cached(std::iter::once(0), ctx);
+            let rows = tree.rows.into_iter();
+            rows.filter(|x| matches!(x, Row::SpanHeader(_))).collect::<Vec<_>>()
+        };
+        assert_eq!(headers(0), [Row::SpanHeader(0)]);
+        assert_eq!(headers(1), [Row::SpanHeader(0), Row::SpanHeader(1)]);
+        let all = [Row::SpanHeader(0), Row::SpanHeader(1), Row::SpanHeader(2), Row::SpanHeader(3)];
+        assert_eq!(headers(3), all);
+        assert_eq!(headers(u32::MAX), all);
+    }
+
+    /// root
+    /// |- 1..=4 tick
+    /// |- 5 tock
+    /// |- 6 tick
+    fn repetitive_tree() -> BaseIETLogProvider {
+        let mut builder = LogProviderBuilder::new();
+        for _ in 0..4 {
+            builder.span(0, "event").message("tick").add();
+        }
+        builder.span(0, "event").message("tock").add();
+        builder.span(0, "event").message("tick").add();
+        builder.build_base()
+    }
+    fn flatten_collapsed(dup_open: &[usize]) -> Vec<(Row, u32)> {
+        let provider = repetitive_tree();
+        let open = EnBitVec::repeat(true, 7);
+        let meta_open = EnBitVec::repeat(false, 7);
+        let mut dup_open_v = EnBitVec::repeat(false, 7);
+        dup_open.iter().for_each(|x| dup_open_v.set(*x, true));
+        let ctx = TreeContext {
+            log_reader: &provider,
+            open_reader: &open,
+            meta_open_reader: &meta_open,
+            dup_open_reader: &dup_open_v,
+            locating_state: None,
+            max_id: u32::MAX,
+        };
+        let mut tree = TreeView::new();
+        tree.set_collapse_duplicates(true);
+        tree.get_tree_non_cached(std::iter::once(0), ctx);
+        let TreeView { rows, row_depths, .. } = tree;
+        // only the structure matters here, not the attrs of the open spans
+        let rows = rows.into_iter().zip(row_depths);
+        rows.filter(|x| !matches!(x.0, Row::Attr(_) | Row::MetaHeader(_))).collect()
+    }
+
+    #[test]
+    fn test_duplicates_collapsed() {
+        let expected = [
+            (Row::SpanHeader(0), 0),
+            (Row::Duplicates { first: 1, count: 4 }, 1),
+            (Row::SpanHeader(5), 1),
+            (Row::SpanHeader(6), 1),
+        ];
+        assert_eq!(flatten_collapsed(&[]), expected);
+    }
+
+    #[test]
+    fn test_duplicates_expanded() {
+        let expected = [
+            (Row::SpanHeader(0), 0),
+            (Row::Duplicates { first: 1, count: 4 }, 1),
+            (Row::SpanHeader(1), 2),
+            (Row::SpanHeader(2), 2),
+            (Row::SpanHeader(3), 2),
+            (Row::SpanHeader(4), 2),
+            (Row::SpanHeader(5), 1),
+            (Row::SpanHeader(6), 1),
+        ];
+        assert_eq!(flatten_collapsed(&[1]), expected);
+    }
+
+    /// root
+    /// |- 1 request
+    /// |  |- 2 started (id = 7)
+    /// |  |- 3 query
+    /// |  |  |- 4 rows
+    /// |  |- 5 done
+    fn request_tree() -> BaseIETLogProvider {
+        let mut builder = LogProviderBuilder::new();
+        let request = builder.span(0, "request").add();
+        builder.span(request, "event").message("started").attr("id", EnValue::U64(7)).add();
+        let query = builder.span(request, "query").add();
+        builder.span(query, "event").message("rows").add();
+        builder.span(request, "event").message("done").add();
+        builder.build_base()
+    }
+    fn flatten_inline(
+        inline: bool, open: &[usize], locate: Option<u32>,
+    ) -> (Vec<(Row, u32)>, Option<usize>) {
+        let provider = request_tree();
+        let mut open_v = EnBitVec::repeat(false, 6);
+        open.iter().for_each(|x| open_v.set(*x, true));
+        let mut locating = locate.map(|target| LocatingState::ScrollTo {
+            target,
+            target_row_offset: None,
+            path: vec![],
+            opened_path: true,
+        });
+        let ctx = TreeContext {
+            log_reader: &provider,
+            open_reader: &open_v,
+            meta_open_reader: &EnBitVec::repeat(false, 6),
+            dup_open_reader: &EnBitVec::new(),
+            locating_state: locating.as_mut(),
+            max_id: u32::MAX,
+        };
+        let mut tree = TreeView::new();
+        tree.set_inline_events(inline);
+        tree.get_tree_non_cached(std::iter::once(0), ctx);
+        let TreeView { rows, row_depths, .. } = tree;
+        let rows = rows.into_iter().zip(row_depths).collect();
+        let offset = match locating {
+            Some(LocatingState::ScrollTo { target_row_offset, .. }) => target_row_offset,
+            _ => None,
+        };
+        (rows, offset)
+    }
+
+    #[test]
+    fn test_inline_events() {
+        let expected = [
+            (Row::SpanHeader(0), 0),
+            (Row::SpanHeader(1), 1),
+            (Row::InlineEvent(2), 2),
+            (Row::SpanHeader(3), 2),
+            (Row::InlineEvent(4), 3),
+            (Row::InlineEvent(5), 2),
+        ];
+        // events can't be opened, so their attrs aren't shown even if they were open before
+        let (rows, _) = flatten_inline(true, &[0, 1, 2, 3], None);
+        let rows: Vec<_> =
+            rows.into_iter().filter(|x| !matches!(x.0, Row::MetaHeader(_))).collect();
+        assert_eq!(rows, expected);
+        let (rows, _) = flatten_inline(false, &[0, 1, 2, 3], None);
+        assert!(rows.contains(&(Row::SpanHeader(2), 2)));
+        assert!(rows.contains(&(Row::Attr("id: 7".into()), 3)));
+        assert!(!rows.iter().any(|x| matches!(x.0, Row::InlineEvent(_))));
+    }
+
+    #[test]
+    fn test_locate_inline_event() {
+        let (rows, offset) = flatten_inline(true, &[0, 1, 3], Some(4));
+        let offset = offset.expect("the located event has no row");
+        assert_eq!(rows[offset], (Row::InlineEvent(4), 3));
+    }
+
+    #[test]
+    fn test_inline_duplicate_events() {
+        let provider = repetitive_tree();
+        let mut dup_open = EnBitVec::repeat(false, 7);
+        dup_open.set(1, true);
+        let ctx = TreeContext {
+            log_reader: &provider,
+            open_reader: &EnBitVec::repeat(true, 7),
+            meta_open_reader: &EnBitVec::repeat(false, 7),
+            dup_open_reader: &dup_open,
+            locating_state: None,
+            max_id: u32::MAX,
+        };
+        let mut tree = TreeView::new();
+        tree.set_collapse_duplicates(true);
+        tree.set_inline_events(true);
+        tree.get_tree_non_cached(std::iter::once(0), ctx);
+        let rows: Vec<_> =
+            tree.visible_rows().filter(|x| !matches!(x.0, Row::MetaHeader(_))).collect();
+        let expected = [
+            (&Row::SpanHeader(0), 0),
+            (&Row::Duplicates { first: 1, count: 4 }, 1),
+            (&Row::InlineEvent(1), 2),
+            (&Row::InlineEvent(2), 2),
+            (&Row::InlineEvent(3), 2),
+            (&Row::InlineEvent(4), 2),
+            (&Row::InlineEvent(5), 1),
+            (&Row::InlineEvent(6), 1),
+        ];
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let provider = repetitive_tree();
+        let fingerprint = |id| span_fingerprint(&provider, id).unwrap();
+        assert_eq!(fingerprint(1), fingerprint(4));
+        assert_eq!(fingerprint(1), fingerprint(6));
+        assert_ne!(fingerprint(1), fingerprint(5));
+    }
+
+    #[test]
+    fn test_follows_from_rows() {
+        let mut builder = LogProviderBuilder::new();
+        let producer = builder.span(0, "producer").add();
+        let consumer = builder.span(0, "consumer").follows_from(producer).add();
+        let provider = builder.build_base();
+        let ctx = TreeContext {
+            log_reader: &provider,
+            open_reader: &EnBitVec::repeat(true, 3),
+            meta_open_reader: &EnBitVec::repeat(false, 3),
+            dup_open_reader: &EnBitVec::new(),
+            locating_state: None,
+            max_id: u32::MAX,
+        };
+        let mut tree = TreeView::new();
+        tree.get_tree_non_cached(std::iter::once(0), ctx);
+        let expected = [
+            (&Row::SpanHeader(0), 0),
+            (&Row::MetaHeader(0), 1),
+            (&Row::SpanHeader(producer), 1),
+            (&Row::MetaHeader(producer), 2),
+            (&Row::SpanHeader(consumer), 1),
+            (&Row::FollowsFrom(producer), 2),
+            (&Row::MetaHeader(consumer), 2),
+        ];
+        assert_eq!(tree.visible_rows().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_strip_ansi_in_row_text() {
+        let mut builder = LogProviderBuilder::new();
+        let colored = builder
+            .span(0, "colored")
+            .message("\x1b[1;31mfailed\x1b[0m: \x1b[4mdisk\x1b[24m")
+            .add();
+        let provider = builder.build_base();
+        let (mut open, mut meta_open, mut dup_open) =
+            (EnBitVec::new(), EnBitVec::new(), EnBitVec::new());
+        let notifier = NotificationHandle::default();
+        let mut ctx = TreeContextMut {
+            log_reader: &provider,
+            open_writer: &mut open,
+            meta_open_writer: &mut meta_open,
+            dup_open_writer: &mut dup_open,
+            locating_state: None,
+            editor_command: "",
+            compact: false,
+            strip_ansi: true,
+            message_attr: "message",
+            color_rules: &[],
+            aggregates: &[],
+            annotations: &mut Default::default(),
+            link_source: "",
+            notifier: &notifier,
+        };
+        assert_eq!(row_text(&ctx, colored), "[T]: failed: disk");
+        ctx.compact = true;
+        assert!(!row_text(&ctx, colored).contains('\x1b'));
+        ctx.strip_ansi = false;
+        ctx.compact = false;
+        assert_eq!(row_text(&ctx, colored), "[T]: \x1b[1;31mfailed\x1b[0m: \x1b[4mdisk\x1b[24m");
+    }
+
+    #[test]
+    fn test_parse_child_aggregate() {
+        let aggregate: ChildAggregate = "latency ms avg".parse().unwrap();
+        assert_eq!(aggregate, ChildAggregate { attr: "latency ms".into(), op: AggregateOp::Avg });
+        assert_eq!(aggregate.to_string(), "latency ms avg");
+        assert!("latency".parse::<ChildAggregate>().is_err());
+        assert!(" sum".parse::<ChildAggregate>().is_err());
+        assert!("latency median".parse::<ChildAggregate>().is_err());
+
+        let ini = "child_aggregate = \"latency avg\"\nchild_aggregate = \"a=b count\"";
+        let settings = crate::settings::parse_settings(ini).unwrap();
+        assert_eq!(settings.child_aggregates[1].attr, "a=b");
+        let reparsed = crate::settings::parse_settings(&settings.to_ini()).unwrap();
+        assert_eq!(reparsed.child_aggregates, settings.child_aggregates);
+    }
+
+    #[test]
+    fn test_aggregates_text() {
+        let mut builder = LogProviderBuilder::new();
+        let server = builder.span(0, "server").add();
+        for latency in [1.5, 2.0] {
+            builder.span(server, "request").attr("latency", EnValue::Float(latency)).add();
+        }
+        let request = builder.span(server, "request").attr("bytes", EnValue::U64(3)).add();
+        let provider = builder.build_base();
+        let (mut open, mut meta_open, mut dup_open) =
+            (EnBitVec::new(), EnBitVec::new(), EnBitVec::new());
+        let notifier = NotificationHandle::default();
+        let aggregates = ["latency avg", "latency count", "bytes sum", "missing max"]
+            .map(|x| x.parse::<ChildAggregate>().unwrap());
+        let ctx = TreeContextMut {
+            log_reader: &provider,
+            open_writer: &mut open,
+            meta_open_writer: &mut meta_open,
+            dup_open_writer: &mut dup_open,
+            locating_state: None,
+            editor_command: "",
+            compact: false,
+            strip_ansi: true,
+            message_attr: "message",
+            color_rules: &[],
+            aggregates: &aggregates,
+            annotations: &mut Default::default(),
+            link_source: "",
+            notifier: &notifier,
+        };
+        assert_eq!(
+            aggregates_text(&ctx, server),
+            "avg(latency)=1.75 count(latency)=2 sum(bytes)=3"
+        );
+        // leaves have nothing to aggregate
+        assert_eq!(aggregates_text(&ctx, request), "");
+    }
+}
@@ -0,0 +1,222 @@
+//! Which spans of a trace are expanded in the tree, saved to a file in the settings directory, so
+//! that a reopened trace shows the same view.
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use entrace_core::LogProvider;
+
+use crate::{annotations::sidecar_path, enbitvec::EnBitVec, settings::LoadSettingsError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum TreeStateError {
+    #[error("Cannot find the settings directory")]
+    Settings(#[from] LoadSettingsError),
+    #[error("Failed to read or write {0}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Bad line {0}, expected a list of span ids")]
+    BadLine(usize),
+}
+
+/// The expansion state of the tree of a trace with `len` spans, see [crate::log::LogState].
+#[derive(Debug, Default)]
+pub struct TreeState {
+    pub len: usize,
+    pub is_open: EnBitVec,
+    pub meta_open: EnBitVec,
+    pub dup_open: EnBitVec,
+}
+
+/// At most this many spans are opened by [TreeState::open_to_depth], so that a wide trace doesn't
+/// start with a huge tree.
+pub const MAX_AUTO_OPEN: usize = 1000;
+
+impl TreeState {
+    /// Every span closed.
+    pub fn closed(len: usize) -> Self {
+        Self {
+            len,
+            is_open: EnBitVec::repeat(false, len),
+            meta_open: EnBitVec::repeat(false, len),
+            dup_open: EnBitVec::repeat(false, len),
+        }
+    }
+    /// Open the spans less than `depth` levels below the root, so that `depth` levels of children
+    /// are shown. Spans without children stay closed. Spans are opened level by level, and the
+    /// rest are left closed after [MAX_AUTO_OPEN].
+    pub fn open_to_depth(&mut self, log: &(impl LogProvider + ?Sized), depth: u32) {
+        let mut queue = VecDeque::from([(0u32, 0u32)]);
+        let mut opened = 0;
+        while let Some((id, level)) = queue.pop_front() {
+            if level >= depth || opened >= MAX_AUTO_OPEN || id as usize >= self.len {
+                continue;
+            }
+            let children = log.children(id).unwrap_or(&[]);
+            if children.is_empty() {
+                continue;
+            }
+            self.is_open.set(id as usize, true);
+            opened += 1;
+            queue.extend(children.iter().map(|&child| (child, level + 1)));
+        }
+    }
+    /// The saved state of the trace at `trace_path`, which now has `len` spans, extended to that.
+    ///
+    /// `None` if nothing was saved, or it was saved for a longer trace, which means the file was
+    /// replaced by another trace.
+    pub fn load_for(trace_path: &Path, len: usize) -> Result<Option<Self>, TreeStateError> {
+        let path = tree_state_path(trace_path)?;
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(TreeStateError::Io(path, e)),
+        };
+        let state = Self::parse(&text)?;
+        if state.len > len {
+            return Ok(None);
+        }
+        Ok(Some(state.extend_to(len)))
+    }
+
+    pub fn save_for(&self, trace_path: &Path) -> Result<(), TreeStateError> {
+        let path = tree_state_path(trace_path)?;
+        let io_err = |e| TreeStateError::Io(path.clone(), e);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_err)?;
+        }
+        std::fs::write(&path, self.to_text()).map_err(io_err)
+    }
+
+    /// Add closed spans up to `len`, for a trace which grew since it was saved.
+    pub fn extend_to(mut self, len: usize) -> Self {
+        for bits in [&mut self.is_open, &mut self.meta_open, &mut self.dup_open] {
+            let missing = len.saturating_sub(bits.len());
+            bits.extend(std::iter::repeat_n(false, missing));
+        }
+        self.len = self.len.max(len);
+        self
+    }
+
+    /// The length, then one line for each of `is_open`, `meta_open` and `dup_open`, with the ids
+    /// of the set bits, see [EnBitVec::ones].
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}\n", self.len);
+        for bits in [&self.is_open, &self.meta_open, &self.dup_open] {
+            let ids: Vec<String> = bits.ones().iter().map(|x| x.to_string()).collect();
+            writeln!(out, "{}", ids.join(" ")).ok();
+        }
+        out
+    }
+
+    pub fn parse(text: &str) -> Result<Self, TreeStateError> {
+        let mut lines = text.lines();
+        let len = lines.next().and_then(|x| x.trim().parse().ok());
+        let len: usize = len.ok_or(TreeStateError::BadLine(1))?;
+        let mut bits = [EnBitVec::new(), EnBitVec::new(), EnBitVec::new()];
+        for (idx, bits) in bits.iter_mut().enumerate() {
+            let line = lines.next().unwrap_or_default();
+            let ids: Result<Vec<usize>, _> = line.split_whitespace().map(str::parse).collect();
+            let ids = ids.map_err(|_| TreeStateError::BadLine(idx + 2))?;
+            *bits = EnBitVec::from_ones(ids, len);
+        }
+        let [is_open, meta_open, dup_open] = bits;
+        Ok(Self { len, is_open, meta_open, dup_open })
+    }
+}
+
+/// The tree state of each trace is saved in the `tree_state` directory next to the settings, see
+/// [sidecar_path].
+pub fn tree_state_path(trace_path: &Path) -> Result<PathBuf, TreeStateError> {
+    Ok(sidecar_path("tree_state", trace_path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use entrace_core::{remote::BaseIETLogProvider, test_util::LogProviderBuilder};
+
+    use crate::enbitvec::EnBitVec;
+
+    use super::{MAX_AUTO_OPEN, TreeState};
+
+    /// root
+    /// |- 1
+    /// |  |- 2
+    /// |     |- 3
+    /// |- 4
+    /// |  |- 5
+    /// |- 6
+    fn deep_tree() -> BaseIETLogProvider {
+        let mut builder = LogProviderBuilder::new();
+        let a = builder.span(0, "a").add();
+        let b = builder.span(a, "b").add();
+        builder.span(b, "c").add();
+        let d = builder.span(0, "d").add();
+        builder.span(d, "e").add();
+        builder.span(0, "f").add();
+        builder.build_base()
+    }
+    fn opened(depth: u32) -> Vec<usize> {
+        let mut state = TreeState::closed(7);
+        state.open_to_depth(&deep_tree(), depth);
+        state.is_open.ones()
+    }
+
+    #[test]
+    fn test_open_to_depth() {
+        assert!(opened(0).is_empty());
+        assert_eq!(opened(1), [0]);
+        // leaves (6, and 5 below 4) stay closed
+        assert_eq!(opened(2), [0, 1, 4]);
+        assert_eq!(opened(3), [0, 1, 2, 4]);
+        assert_eq!(opened(10), [0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn test_open_to_depth_capped() {
+        let mut builder = LogProviderBuilder::new();
+        for _ in 0..MAX_AUTO_OPEN + 10 {
+            let parent = builder.span(0, "parent").add();
+            builder.span(parent, "child").add();
+        }
+        let log = builder.build_base();
+        let mut state = TreeState::closed(log.data.len());
+        state.open_to_depth(&log, 2);
+        let opened = state.is_open.ones();
+        assert_eq!(opened.len(), MAX_AUTO_OPEN);
+        // the root is opened first
+        assert_eq!(opened[..2], [0, 1]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let state = TreeState {
+            len: 6,
+            is_open: EnBitVec::from_ones([0, 2, 5], 6),
+            meta_open: EnBitVec::from_ones([3], 6),
+            dup_open: EnBitVec::repeat(false, 6),
+        };
+        assert_eq!(state.to_text(), "6\n0 2 5\n3\n\n");
+        let loaded = TreeState::parse(&state.to_text()).unwrap();
+        assert_eq!(loaded.is_open.ones(), [0, 2, 5]);
+        assert_eq!(loaded.meta_open.ones(), [3]);
+        assert!(loaded.dup_open.ones().is_empty());
+
+        // the trace grew since
+        let grown = loaded.extend_to(10);
+        assert_eq!((grown.len, grown.is_open.len(), grown.dup_open.len()), (10, 10, 10));
+        assert_eq!(grown.is_open.ones(), [0, 2, 5]);
+
+        assert!(TreeState::parse("x\n").is_err());
+        assert!(TreeState::parse("3\n1 z\n").is_err());
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let len = 100 * 1024 * 1024 + 1;
+        let bits = EnBitVec::from_ones([1, len - 1], len);
+        assert!(bits.is_compressed());
+        assert_eq!(bits.ones(), [1, len - 1]);
+    }
+}